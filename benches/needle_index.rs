@@ -0,0 +1,82 @@
+//! How long a 200,000-needle list takes to load from CSV and from a compiled
+//! `.nidx` file, and to build the lookup automaton from either.
+//!
+//! Run with `cargo bench --bench needle_index`. Compiling only skips CSV
+//! parsing: the compiled file holds the parsed needles, their weights and
+//! their options, not the automaton, so the build step is the same for both.
+
+use std::time::{Duration, Instant};
+
+use docsearcher::{
+    matcher::Strategy,
+    needle_index::CompiledNeedles,
+    read_needles_from_mem,
+    search::BlockSearch,
+    types::{ExtractOptions, MatchOptions},
+};
+
+const NEEDLES: usize = 200_000;
+
+/// Rounds per measurement; the fastest is reported
+const ROUNDS: usize = 5;
+
+fn fastest<T>(mut run: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = None;
+    let mut last = None;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let value = run();
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+        last = Some(value);
+    }
+    (best.unwrap(), last.unwrap())
+}
+
+fn main() {
+    let csv: String = (0..NEEDLES)
+        .map(|i| {
+            format!(
+                "Contact {} Surname{},id={};team=\"north; {}\"\n",
+                i,
+                i % 977,
+                i,
+                i % 13
+            )
+        })
+        .collect();
+    let needles = read_needles_from_mem(csv.as_bytes(), None).unwrap();
+    let compiled = CompiledNeedles::new(needles, MatchOptions::default()).to_bytes();
+
+    let (from_csv, parsed) = fastest(|| read_needles_from_mem(csv.as_bytes(), None).unwrap());
+    let (from_nidx, loaded) = fastest(|| CompiledNeedles::from_bytes(&compiled).unwrap().needles);
+    assert_eq!(parsed, loaded);
+
+    let matcher = BlockSearch::matcher(ExtractOptions::default());
+    let (build, _) = fastest(|| {
+        matcher.needle_lookup(
+            loaded.iter().map(|(term, _)| term.as_str()),
+            Strategy::Automaton,
+        )
+    });
+
+    println!(
+        "{} needles, {} KB of CSV, {} KB compiled",
+        NEEDLES,
+        csv.len() / 1024,
+        compiled.len() / 1024
+    );
+    println!(
+        "load from CSV:        {:>8.1} ms",
+        from_csv.as_secs_f64() * 1000.0
+    );
+    println!(
+        "load from .nidx:      {:>8.1} ms ({:.1}x faster)",
+        from_nidx.as_secs_f64() * 1000.0,
+        from_csv.as_secs_f64() / from_nidx.as_secs_f64()
+    );
+    println!(
+        "build the automaton:  {:>8.1} ms",
+        build.as_secs_f64() * 1000.0
+    );
+}
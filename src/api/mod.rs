@@ -0,0 +1,117 @@
+//! The `--api` protocol, for programs that run docsearcher as a subprocess.
+//!
+//! Under `--api v1`, `search` and `batch` print one JSON document with the
+//! results on stdout and nothing else, and report progress as NDJSON events
+//! on stderr. They never prompt and never color their output, and exit with
+//! the usual codes: 0 when something matched, 1 when nothing did and 2 on
+//! an error, which is reported as an `error` event first.
+//!
+//! Every version has a module of its own holding the shapes it prints
+//! ([`v1`]). Those shapes are frozen: golden files under `src/api/golden`
+//! pin their field names and types, and a later version gets new types
+//! instead of changing old ones.
+
+use std::{
+    fmt,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::output::{self, Channel, DivertGuard};
+
+pub mod v1;
+
+/// A version of the protocol, as given to `--api`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ApiVersion {
+    V1,
+}
+
+/// An error already reported as an `error` event, which is not printed again
+#[derive(Debug)]
+pub struct Reported(pub anyhow::Error);
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Reported {}
+
+/// One run under the protocol, writing its events to stderr
+pub struct ApiSession {
+    pub version: ApiVersion,
+    pub command: v1::Command,
+    events: Mutex<Box<dyn Write + Send>>,
+    start: Instant,
+    /// Set once the results document is printed
+    reported: AtomicBool,
+}
+
+impl ApiSession {
+    /// Start a run. Until the guard is dropped, what this thread prints
+    /// for people is dropped and its warnings become `warning` events.
+    pub fn start(version: ApiVersion, command: v1::Command) -> (Arc<Self>, DivertGuard) {
+        let session = Arc::new(Self {
+            version,
+            command,
+            events: Mutex::new(output::stderr_writer()),
+            start: Instant::now(),
+            reported: AtomicBool::new(false),
+        });
+
+        let diverted = Arc::clone(&session);
+        let guard = output::divert(Arc::new(move |channel, line| {
+            if channel == Channel::Warning {
+                diverted.emit(&v1::Event::Warning {
+                    file: None,
+                    message: line.to_string(),
+                });
+            }
+        }));
+        (session, guard)
+    }
+
+    /// Write one event as a line of JSON; may be called from any thread
+    pub fn emit(&self, event: &v1::Event) {
+        let line = serde_json::to_string(event).expect("events serialize");
+        let mut events = self.events.lock().expect("event writer lock poisoned");
+        let _ = writeln!(events, "{}", line).and_then(|()| events.flush());
+    }
+
+    /// Print the results document on stdout
+    pub fn print_report<T: Serialize>(&self, report: &T) -> Result<()> {
+        output::print_raw(format_args!("{}", serde_json::to_string_pretty(report)?));
+        self.reported.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// End the run with `exit_code`, reporting `error` when it failed. A
+    /// run that succeeded without searching, such as one with no needles,
+    /// still prints an empty results document.
+    pub fn finish(&self, error: Option<&anyhow::Error>, exit_code: i32) -> Result<()> {
+        match error {
+            Some(error) => self.emit(&v1::Event::Error {
+                message: error.to_string(),
+            }),
+            None if !self.reported.load(Ordering::Relaxed) => {
+                self.print_report(&v1::Report::empty(self.command))?
+            }
+            None => {}
+        }
+        let elapsed_ms = self.start.elapsed().as_millis() as u64;
+        self.emit(&v1::Event::Finished {
+            exit_code,
+            elapsed_ms,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+//! Version 1 of the `--api` protocol.
+//!
+//! These types are what `--api v1` prints and nothing else. They are
+//! deliberately separate from the results file and the other output
+//! formats, which are free to change: a field here is only ever added in a
+//! new version. The tests compare their shapes with `golden/v1/schema.json`.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    triage::{Location, MatchRecord},
+    types::{ContextKind, MetadataFields},
+};
+
+/// The value of every document's `api` field
+pub const VERSION: &str = "v1";
+
+/// The command a document or event comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Command {
+    Search,
+    Batch,
+}
+
+/// The document printed on stdout when a run finishes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// Always [`VERSION`]
+    pub api: &'static str,
+    pub command: Command,
+    pub results: Vec<Match>,
+    /// Documents that could not be searched
+    pub failures: Vec<Failure>,
+    pub summary: Summary,
+}
+
+impl Report {
+    /// The document of a run that searched nothing
+    pub fn empty(command: Command) -> Self {
+        Report {
+            api: VERSION,
+            command,
+            results: Vec::new(),
+            failures: Vec::new(),
+            summary: Summary {
+                files: 0,
+                files_with_matches: 0,
+                matches: 0,
+                failed: 0,
+                suppressed: 0,
+            },
+        }
+    }
+}
+
+/// One needle found in one document
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Match {
+    pub match_id: String,
+    pub term: String,
+    pub metadata: String,
+    /// The file on disk
+    pub file: String,
+    /// The file, then each archive entry leading to the document
+    pub source: Vec<String>,
+    /// The contexts the needle was found in, such as `prose` or `email`
+    pub contexts: Vec<String>,
+    /// The metadata as key/value pairs with `--metadata-format kv`, else `null`
+    pub fields: Option<BTreeMap<String, String>>,
+    /// The local needles list the needle came from; `null` for the global list
+    pub needles_list: Option<String>,
+    /// The decision carried forward with `--dispositions`
+    pub disposition: Option<String>,
+    pub note: Option<String>,
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl Match {
+    pub fn new(
+        record: &MatchRecord,
+        contexts: &[ContextKind],
+        fields: Option<MetadataFields>,
+        needles_list: Option<&Path>,
+    ) -> Self {
+        Match {
+            match_id: record.match_id.clone(),
+            term: record.term.clone(),
+            metadata: record.metadata.clone(),
+            file: record.file.clone(),
+            source: record.source.segments().to_vec(),
+            contexts: contexts
+                .iter()
+                .map(|kind| kind.as_str().to_string())
+                .collect(),
+            fields: fields.map(|fields| fields.into_iter().collect()),
+            needles_list: needles_list.map(|path| path.to_string_lossy().into_owned()),
+            disposition: record
+                .disposition
+                .map(|disposition| disposition.as_str().to_string()),
+            note: record.note.clone(),
+            occurrences: record.occurrences.iter().map(Occurrence::from).collect(),
+        }
+    }
+}
+
+/// Where in a document a needle was found
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Occurrence {
+    /// Index of the line, counted from 0
+    pub line: usize,
+    /// 1-based page number, `null` for formats without pages
+    pub page: Option<usize>,
+    /// Byte range of the match within `text`
+    pub span: [usize; 2],
+    /// The whole line the needle was found in
+    pub text: String,
+}
+
+impl From<&Location> for Occurrence {
+    fn from(location: &Location) -> Self {
+        // Pages estimated for formats without fixed pages came after v1
+        let page = location.page.filter(|_| !location.page_estimated);
+        Occurrence {
+            line: location.line,
+            page,
+            span: location.span,
+            text: location.text.clone(),
+        }
+    }
+}
+
+/// A document that could not be searched
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Failure {
+    pub file: String,
+    pub error: String,
+}
+
+/// Counts over the whole run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub files: usize,
+    pub files_with_matches: usize,
+    /// Entries in `results`
+    pub matches: usize,
+    /// Entries in `failures`
+    pub failed: usize,
+    /// Occurrences dropped by `--ignore-context`
+    pub suppressed: usize,
+}
+
+/// A progress event, written to stderr as one line of JSON
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The documents to search are known
+    Started { command: Command, files: usize },
+    /// A document was searched
+    FileDone { file: String, occurrences: usize },
+    /// A document could not be searched; the run goes on
+    FileFailed { file: String, error: String },
+    /// Something worth knowing that did not stop anything, about one
+    /// document or, when `file` is `null`, the run
+    Warning {
+        file: Option<String>,
+        message: String,
+    },
+    /// The run failed; `finished` follows with exit code 2
+    Error { message: String },
+    /// Always the last event
+    Finished { exit_code: i32, elapsed_ms: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Field names and value types of `value`: objects keep their keys,
+    /// arrays are described by their first element
+    fn shape(value: &Value) -> Value {
+        match value {
+            Value::Null => json!("null"),
+            Value::Bool(_) => json!("boolean"),
+            Value::Number(number) if number.is_f64() => json!("number"),
+            Value::Number(_) => json!("integer"),
+            Value::String(_) => json!("string"),
+            Value::Array(items) => json!(items.first().map(shape).into_iter().collect::<Vec<_>>()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), shape(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Every field set, so every type shows
+    fn populated_report() -> Report {
+        Report {
+            api: VERSION,
+            command: Command::Batch,
+            results: vec![Match {
+                match_id: "m-1".to_string(),
+                term: "Alice".to_string(),
+                metadata: "dept=legal".to_string(),
+                file: "a.zip".to_string(),
+                source: vec!["a.zip".to_string(), "b.pdf".to_string()],
+                contexts: vec!["prose".to_string()],
+                fields: Some(BTreeMap::from([("dept".to_string(), "legal".to_string())])),
+                needles_list: Some("local.csv".to_string()),
+                disposition: Some("confirmed".to_string()),
+                note: Some("checked".to_string()),
+                occurrences: vec![Occurrence {
+                    line: 3,
+                    page: Some(1),
+                    span: [0, 5],
+                    text: "Alice".to_string(),
+                }],
+            }],
+            failures: vec![Failure {
+                file: "c.pdf".to_string(),
+                error: "broken".to_string(),
+            }],
+            summary: Summary {
+                files: 2,
+                files_with_matches: 1,
+                matches: 1,
+                failed: 1,
+                suppressed: 0,
+            },
+        }
+    }
+
+    fn every_event() -> Vec<Event> {
+        vec![
+            Event::Started {
+                command: Command::Search,
+                files: 1,
+            },
+            Event::FileDone {
+                file: "a.pdf".to_string(),
+                occurrences: 2,
+            },
+            Event::FileFailed {
+                file: "c.pdf".to_string(),
+                error: "broken".to_string(),
+            },
+            Event::Warning {
+                file: Some("d.docx".to_string()),
+                message: "extension .docx, parsed as PDF".to_string(),
+            },
+            Event::Error {
+                message: "Needles file not found".to_string(),
+            },
+            Event::Finished {
+                exit_code: 0,
+                elapsed_ms: 12,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_schema_matches_the_golden_file() {
+        // Event names are part of the contract, so they are kept as they are
+        let events: Vec<Value> = every_event()
+            .iter()
+            .map(|event| {
+                let value = serde_json::to_value(event).unwrap();
+                let mut shape = shape(&value);
+                shape["event"] = value["event"].clone();
+                shape
+            })
+            .collect();
+        let schema = json!({
+            "report": shape(&serde_json::to_value(populated_report()).unwrap()),
+            "events": events,
+        });
+
+        let golden: Value = serde_json::from_str(include_str!("golden/v1/schema.json")).unwrap();
+        assert_eq!(
+            schema,
+            golden,
+            "the v1 shapes changed; add a new version instead:\n{}",
+            serde_json::to_string_pretty(&schema).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value([Command::Search, Command::Batch]).unwrap(),
+            json!(["search", "batch"])
+        );
+    }
+
+    #[test]
+    fn test_values_that_may_be_null() {
+        let mut report = populated_report();
+        let result = &mut report.results[0];
+        (
+            result.fields,
+            result.needles_list,
+            result.disposition,
+            result.note,
+        ) = (None, None, None, None);
+        result.occurrences[0].page = None;
+
+        let value = serde_json::to_value(&report).unwrap();
+        for field in ["fields", "needles_list", "disposition", "note"] {
+            assert_eq!(value["results"][0][field], Value::Null, "{}", field);
+        }
+        assert_eq!(value["results"][0]["occurrences"][0]["page"], Value::Null);
+        let warning = serde_json::to_value(Event::Warning {
+            file: None,
+            message: String::new(),
+        })
+        .unwrap();
+        assert_eq!(
+            warning,
+            json!({ "event": "warning", "file": null, "message": "" })
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Searching from async code, with the `async` feature.
+//!
+//! Parsing is CPU-bound and blocking, so each search runs the synchronous
+//! parsers on tokio's blocking thread pool through
+//! [`tokio::task::spawn_blocking`] and the caller awaits the result. These
+//! functions must be called from within a tokio runtime.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use futures::stream::{self, Stream, StreamExt};
+
+use crate::error::DocSearchError;
+use crate::parsers::{self, docx, pdf, LoadedNeedles};
+use crate::types::{SearchConfig, SearchResults};
+
+/// Search the DOCX document at `doc_path` for the needles of the needles
+/// file at `needles_path`, searching as `config` says
+pub async fn search_docx(
+    config: &SearchConfig,
+    needles_path: &Path,
+    doc_path: &Path,
+) -> Result<SearchResults, DocSearchError> {
+    let extract = config.extract_options();
+    blocking(needles_path, doc_path, move |needles, doc| {
+        docx::parse_from_path(needles, doc, extract)
+    })
+    .await
+}
+
+/// Search the PDF at `doc_path` for the needles of the needles file at
+/// `needles_path`, searching as `config` says
+pub async fn search_pdf(
+    config: &SearchConfig,
+    needles_path: &Path,
+    doc_path: &Path,
+) -> Result<SearchResults, DocSearchError> {
+    let extract = config.extract_options();
+    blocking(needles_path, doc_path, move |needles, doc| {
+        pdf::parse_from_path(needles, doc, extract)
+    })
+    .await
+}
+
+/// Search the document at `doc_path` with the parser its extension names,
+/// as [`parsers::parse_from_path`] does
+pub async fn search(
+    config: &SearchConfig,
+    needles_path: &Path,
+    doc_path: &Path,
+) -> Result<SearchResults, DocSearchError> {
+    let extract = config.extract_options();
+    blocking(needles_path, doc_path, move |needles, doc| {
+        parsers::parse_from_path(needles, doc, extract)
+    })
+    .await
+}
+
+/// Search every document of `docs` as [`search`] does, as many at a time as
+/// there are cores, with the needles read from `needles_path` once. Each
+/// document's results arrive as soon as it is searched, so they come in the
+/// order the searches finish, not the order of `docs`; a document that fails
+/// to parse yields its error and the others go on.
+pub fn search_batch(
+    config: &SearchConfig,
+    needles_path: &Path,
+    docs: &[PathBuf],
+) -> Result<
+    impl Stream<Item = Result<(PathBuf, SearchResults), DocSearchError>> + Send + 'static,
+    DocSearchError,
+> {
+    let extract = config.extract_options();
+    let needles = Arc::new(LoadedNeedles::read(
+        &needles_path.to_string_lossy(),
+        extract,
+    )?);
+    let concurrency = thread::available_parallelism().map_or(1, |n| n.get());
+
+    Ok(stream::iter(docs.to_vec())
+        .map(move |doc| {
+            let needles = Arc::clone(&needles);
+            async move {
+                let path = doc.to_string_lossy().into_owned();
+                let results = tokio::task::spawn_blocking(move || needles.search(&path, extract))
+                    .await
+                    .map_err(|e| {
+                        DocSearchError::ParseError(format!(
+                            "Search of {} did not finish: {}",
+                            doc.display(),
+                            e
+                        ))
+                    })??;
+                Ok((doc, results))
+            }
+        })
+        .buffer_unordered(concurrency))
+}
+
+/// Run `search` on the blocking thread pool with both paths as strings
+async fn blocking<F>(
+    needles_path: &Path,
+    doc_path: &Path,
+    search: F,
+) -> Result<SearchResults, DocSearchError>
+where
+    F: FnOnce(&str, &str) -> Result<SearchResults, DocSearchError> + Send + 'static,
+{
+    let needles_path = needles_path.to_string_lossy().into_owned();
+    let doc_path = doc_path.to_string_lossy().into_owned();
+    let name = doc_path.clone();
+    tokio::task::spawn_blocking(move || search(&needles_path, &doc_path))
+        .await
+        .map_err(|e| {
+            DocSearchError::ParseError(format!("Search of {} did not finish: {}", name, e))
+        })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap()
+    }
+
+    fn terms(results: &SearchResults) -> Vec<&str> {
+        results.iter().map(|result| result.term.as_str()).collect()
+    }
+
+    #[test]
+    fn test_documents_are_searched_off_the_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        let (docx, pdf) = (dir.path().join("a.docx"), dir.path().join("b.pdf"));
+        std::fs::write(&docx, fixtures::docx(&["Signed by Alice Johnson"])).unwrap();
+        std::fs::write(&pdf, fixtures::pdf(&[&["Bob Smith, page one"]])).unwrap();
+
+        runtime().block_on(async {
+            let config = SearchConfig::default();
+            assert_eq!(
+                terms(&search_docx(&config, &needles, &docx).await.unwrap()),
+                ["Alice Johnson"]
+            );
+            assert_eq!(
+                terms(&search_pdf(&config, &needles, &pdf).await.unwrap()),
+                ["Bob Smith"]
+            );
+            assert!(matches!(
+                search_docx(&config, &dir.path().join("missing.csv"), &docx).await,
+                Err(DocSearchError::FileNotFound(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_batch_streams_each_document_and_its_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let mut docs = Vec::new();
+        for i in 0..6 {
+            let doc = dir.path().join(format!("{}.txt", i));
+            std::fs::write(
+                &doc,
+                if i % 2 == 0 {
+                    "Alice Johnson"
+                } else {
+                    "nobody"
+                },
+            )
+            .unwrap();
+            docs.push(doc);
+        }
+        let corrupt = dir.path().join("corrupt.docx");
+        std::fs::write(&corrupt, b"not a zip archive").unwrap();
+        docs.push(corrupt);
+
+        let found = runtime().block_on(async {
+            let mut stream =
+                Box::pin(search_batch(&SearchConfig::default(), &needles, &docs).unwrap());
+            let first = stream.next().await.unwrap();
+            // The needles were read before the first document was searched
+            std::fs::remove_file(&needles).unwrap();
+            let mut found = vec![first];
+            found.extend(stream.collect::<Vec<_>>().await);
+            found
+        });
+        assert_eq!(found.len(), 7);
+        for (doc, results) in found.iter().filter_map(|result| result.as_ref().ok()) {
+            assert!(
+                results
+                    .iter()
+                    .all(|result| result.file.as_ref() == Some(doc)),
+                "{}",
+                doc.display()
+            );
+        }
+        let mut matched: Vec<(String, usize)> = found
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .map(|(doc, results)| {
+                (
+                    doc.file_name().unwrap().to_string_lossy().into_owned(),
+                    results.len(),
+                )
+            })
+            .collect();
+        matched.sort();
+        assert_eq!(
+            matched,
+            [
+                ("0.txt", 1),
+                ("1.txt", 0),
+                ("2.txt", 1),
+                ("3.txt", 0),
+                ("4.txt", 1),
+                ("5.txt", 0)
+            ]
+            .map(|(doc, n)| (doc.to_string(), n))
+        );
+        assert_eq!(
+            found
+                .iter()
+                .filter(|result| matches!(result, Err(DocSearchError::CorruptDocument { .. })))
+                .count(),
+            1
+        );
+
+        assert!(matches!(
+            search_batch(&SearchConfig::default(), &needles, &docs),
+            Err(DocSearchError::FileNotFound(_))
+        ));
+    }
+}
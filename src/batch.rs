@@ -0,0 +1,1127 @@
+//! Batch search engine.
+//!
+//! Files are processed by a two-stage pipeline: a small pool of IO workers
+//! reads documents into memory and hands the bytes to a bounded channel, and
+//! a pool of CPU workers sized to the core count extracts text and matches
+//! needles. The channel bound provides backpressure, so at most
+//! `channel_bound` buffers (plus one per worker) are resident at a time.
+//! With [`PipelineOptions::spill`], finished files' occurrences move to
+//! disk once too many are held in memory; see [`crate::spill`].
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{
+    error::DocumentError,
+    interrupt::CancelToken,
+    local_needles::NeedleScopes,
+    parsers::{archive, parser_for},
+    search::{BlockBudget, Extraction, Occurrence, TextBlock},
+    spill::{Segment, Spill},
+    types::{ExtractOptions, FileType},
+    utils::{
+        detect_filetype_from_bytes, filetype_from_extension, parse_filetype, sniff_document_type,
+    },
+};
+
+/// Concurrency limits for the batch pipeline
+#[derive(Clone, Debug)]
+pub struct PipelineOptions {
+    /// Number of workers extracting text and matching needles
+    pub cpu_threads: usize,
+    /// Number of workers reading files from disk
+    pub io_threads: usize,
+    /// Maximum number of read buffers waiting for a CPU worker
+    pub channel_bound: usize,
+    /// Hidden document content to search as well
+    pub extract: ExtractOptions,
+    /// Where occurrences go once too many are in memory; `None` keeps them
+    /// all in memory
+    pub spill: Option<Arc<Spill>>,
+    /// Stops the search like a `false` from `on_outcome`: files not yet
+    /// started are skipped, those being searched finish
+    pub cancel: CancelToken,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        let cpu_threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+        Self {
+            cpu_threads,
+            io_threads: 2,
+            channel_bound: cpu_threads * 2,
+            extract: ExtractOptions::default(),
+            spill: None,
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+/// The outcome of searching a single file in a batch
+#[derive(Debug)]
+pub struct FileOutcome {
+    pub path: PathBuf,
+    pub occurrences: Result<Vec<Occurrence>>,
+    /// Size of the file as read, zero when it could not be read
+    pub bytes_read: u64,
+    /// Bytes of text extracted, zero when extraction failed
+    pub text_bytes: u64,
+    /// Problems that did not stop the search, as in [`Extraction::warnings`]
+    pub warnings: Vec<String>,
+    /// Where the occurrences went when they were spilled to disk, leaving
+    /// `occurrences` empty
+    pub spilled: Option<Segment>,
+}
+
+impl FileOutcome {
+    /// The occurrences found, read back from disk if they were spilled;
+    /// `None` when the file could not be searched
+    pub fn load_occurrences(&self) -> Option<Result<Vec<Occurrence>>> {
+        let occurrences = self.occurrences.as_ref().ok()?;
+        Some(match &self.spilled {
+            Some(segment) => segment.read(),
+            None => Ok(occurrences.clone()),
+        })
+    }
+
+    /// Move the occurrences to a segment of `spill` if there are too many
+    /// in memory already
+    fn offload(&mut self, index: usize, spill: &Spill) {
+        let Ok(occurrences) = &self.occurrences else {
+            return;
+        };
+        match spill.offload(index, occurrences) {
+            Ok(None) => {}
+            Ok(Some(segment)) => {
+                self.occurrences = Ok(Vec::new());
+                self.spilled = Some(segment);
+            }
+            Err(e) => self.occurrences = Err(e),
+        }
+    }
+}
+
+/// Search `files` for `needles`, reading them from disk.
+///
+/// Outcomes are returned in the same order as `files`, regardless of the
+/// order in which workers finish. `on_file_done` is called from worker
+/// threads once per file.
+pub fn run_pipeline(
+    needles: &[(String, String)],
+    files: &[PathBuf],
+    options: &PipelineOptions,
+    on_file_done: &(dyn Fn(&Path) + Sync),
+) -> Vec<FileOutcome> {
+    run_pipeline_with_reader(
+        needles,
+        files,
+        options,
+        |path| std::fs::read(path),
+        on_file_done,
+    )
+}
+
+/// Same as [`run_pipeline`], with a custom function for the IO stage
+pub fn run_pipeline_with_reader<F>(
+    needles: &[(String, String)],
+    files: &[PathBuf],
+    options: &PipelineOptions,
+    reader: F,
+    on_file_done: &(dyn Fn(&Path) + Sync),
+) -> Vec<FileOutcome>
+where
+    F: Fn(&Path) -> io::Result<Vec<u8>> + Sync,
+{
+    run_stages(
+        &|_| needles,
+        files,
+        options,
+        reader,
+        on_file_done,
+        &|_, _| true,
+    )
+}
+
+/// Same as [`run_pipeline`], searching each file for the needles `scopes`
+/// assigns to it
+pub fn run_pipeline_scoped(
+    scopes: &NeedleScopes,
+    files: &[PathBuf],
+    options: &PipelineOptions,
+    on_file_done: &(dyn Fn(&Path) + Sync),
+) -> Vec<FileOutcome> {
+    run_pipeline_scoped_streaming(scopes, files, options, on_file_done, &|_, _| true)
+}
+
+/// Same as [`run_pipeline_scoped`], handing each outcome to `on_outcome`
+/// as soon as its file is done, in completion order.
+///
+/// When `on_outcome` returns `false` the search stops: files not yet
+/// started are skipped and come back as errors.
+pub fn run_pipeline_scoped_streaming(
+    scopes: &NeedleScopes,
+    files: &[PathBuf],
+    options: &PipelineOptions,
+    on_file_done: &(dyn Fn(&Path) + Sync),
+    on_outcome: &(dyn Fn(usize, &FileOutcome) -> bool + Sync),
+) -> Vec<FileOutcome> {
+    run_stages(
+        &|index| scopes.needles_for(index),
+        files,
+        options,
+        |path| std::fs::read(path),
+        on_file_done,
+        on_outcome,
+    )
+}
+
+fn run_stages<'n, F>(
+    needles_for: &(dyn Fn(usize) -> &'n [(String, String)] + Sync),
+    files: &[PathBuf],
+    options: &PipelineOptions,
+    reader: F,
+    on_file_done: &(dyn Fn(&Path) + Sync),
+    on_outcome: &(dyn Fn(usize, &FileOutcome) -> bool + Sync),
+) -> Vec<FileOutcome>
+where
+    F: Fn(&Path) -> io::Result<Vec<u8>> + Sync,
+{
+    let io_threads = options.io_threads.clamp(1, files.len().max(1));
+    let cpu_threads = options.cpu_threads.clamp(1, files.len().max(1));
+
+    let next_file = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let stopping = || stopped.load(Ordering::Relaxed) || options.cancel.is_cancelled();
+    let slots: Mutex<Vec<Option<FileOutcome>>> =
+        Mutex::new((0..files.len()).map(|_| None).collect());
+
+    let (sender, receiver) =
+        mpsc::sync_channel::<(usize, io::Result<Vec<u8>>)>(options.channel_bound);
+    let receiver = Mutex::new(receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..io_threads {
+            let sender = sender.clone();
+            let (next_file, stopping, reader) = (&next_file, &stopping, &reader);
+            scope.spawn(move || loop {
+                if stopping() {
+                    break;
+                }
+                let index = next_file.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = files.get(index) else {
+                    break;
+                };
+                if sender.send((index, reader(path))).is_err() {
+                    break;
+                }
+            });
+        }
+        // Only the IO workers hold senders now, so the channel closes once
+        // the last file has been read.
+        drop(sender);
+
+        for _ in 0..cpu_threads {
+            let (receiver, slots, stopped, stopping) = (&receiver, &slots, &stopped, &stopping);
+            scope.spawn(move || loop {
+                let message = receiver.lock().expect("receiver lock poisoned").recv();
+                let Ok((index, bytes)) = message else {
+                    break;
+                };
+                // Keep draining the channel so the IO workers can finish
+                if stopping() {
+                    continue;
+                }
+
+                let path = &files[index];
+                let bytes_read = bytes.as_ref().map_or(0, |bytes| bytes.len() as u64);
+                let extraction = bytes
+                    .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))
+                    .and_then(|bytes| {
+                        search_bytes(needles_for(index), path, &bytes, options.extract)
+                    });
+                let text_bytes = extraction
+                    .as_ref()
+                    .map_or(0, |extraction| extraction.text_bytes);
+                let (occurrences, warnings) = match extraction {
+                    Ok(extraction) => (Ok(extraction.occurrences), extraction.warnings),
+                    Err(e) => (Err(e), Vec::new()),
+                };
+                let mut outcome = FileOutcome {
+                    path: path.clone(),
+                    occurrences,
+                    bytes_read,
+                    text_bytes,
+                    warnings,
+                    spilled: None,
+                };
+
+                if !on_outcome(index, &outcome) {
+                    stopped.store(true, Ordering::Relaxed);
+                }
+                if let Some(spill) = &options.spill {
+                    outcome.offload(index, spill);
+                }
+                slots.lock().expect("slots lock poisoned")[index] = Some(outcome);
+                on_file_done(path);
+            });
+        }
+    });
+
+    files
+        .iter()
+        .zip(slots.into_inner().expect("slots lock poisoned"))
+        .map(|(path, slot)| {
+            slot.unwrap_or_else(|| {
+                let error = if stopping() {
+                    DocumentError::SearchStopped { path: path.clone() }.into()
+                } else {
+                    anyhow!("File was never processed")
+                };
+                FileOutcome {
+                    path: path.clone(),
+                    occurrences: Err(error),
+                    bytes_read: 0,
+                    text_bytes: 0,
+                    warnings: Vec::new(),
+                    spilled: None,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Collapse paths naming the same file, keeping the first spelling seen.
+///
+/// Two paths are the same file when they resolve to the same device and
+/// inode (Unix) or the same canonical path (elsewhere), so relative and
+/// absolute spellings and symlinks to a file all collapse into one entry.
+/// Paths that cannot be resolved are compared as given. Returns the unique
+/// paths and the number of duplicates dropped.
+pub fn dedupe_files(files: Vec<PathBuf>) -> (Vec<PathBuf>, usize) {
+    let mut seen = HashSet::new();
+    let total = files.len();
+
+    let unique: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|path| seen.insert(file_identity(path)))
+        .collect();
+    let duplicates = total - unique.len();
+
+    (unique, duplicates)
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum FileIdentity {
+    #[cfg(unix)]
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+fn file_identity(path: &Path) -> FileIdentity {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            return FileIdentity::Inode(metadata.dev(), metadata.ino());
+        }
+    }
+
+    FileIdentity::Path(std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// A `--pattern` selecting the files of a directory scan.
+///
+/// A pattern without `/` or `**` is matched against the file name only, so
+/// `*.pdf` selects PDFs at any depth the scan reaches. Any other pattern is
+/// matched against the path relative to the scan root, with `/` as the
+/// separator: `*` and `?` stay within one directory and `**` matches any
+/// number of them, so `reports/**/*.docx` selects DOCX files anywhere under
+/// `reports` and `archive/*.pdf` only the PDFs directly in `archive`.
+#[derive(Clone, Debug)]
+pub struct FilePattern {
+    pattern: glob::Pattern,
+    /// Whether the pattern is matched against the relative path
+    path: bool,
+}
+
+impl FilePattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let path = pattern.contains('/') || pattern.contains("**");
+        let pattern = glob::Pattern::new(pattern.strip_prefix("./").unwrap_or(pattern))
+            .map_err(|e| anyhow!("Invalid pattern {}: {}", pattern, e))?;
+        Ok(Self { pattern, path })
+    }
+
+    /// Whether the pattern is matched against paths rather than file names
+    pub fn is_path_pattern(&self) -> bool {
+        self.path
+    }
+
+    /// Whether the pattern asks for plain text files by name, as `*.txt`
+    /// does, rather than only reaching them through a wildcard extension
+    pub fn names_plain_text(&self) -> bool {
+        self.pattern.as_str().to_ascii_lowercase().ends_with(".txt")
+    }
+
+    /// Whether the file at `relative` (relative to the scan root) is selected
+    pub fn matches(&self, relative: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+
+        if self.path {
+            let relative: Vec<_> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            self.pattern.matches_with(&relative.join("/"), options)
+        } else {
+            relative
+                .file_name()
+                .is_some_and(|name| self.pattern.matches_with(&name.to_string_lossy(), options))
+        }
+    }
+
+    /// How deep a scan has to go to find every match, when that is bounded
+    fn max_depth(&self) -> Option<usize> {
+        match self.path && !self.pattern.as_str().contains("**") {
+            true => Some(self.pattern.as_str().split('/').count()),
+            false => None,
+        }
+    }
+}
+
+/// List the files under `root` selected by `pattern` and none of `excludes`,
+/// in file name order.
+///
+/// Name patterns look only at `root` itself unless `recursive` is set. Path
+/// patterns spell out the directories they reach and are matched at every
+/// depth either way. Symlinks are skipped unless `follow_symlinks` is set.
+pub fn scan_directory(
+    root: &Path,
+    pattern: &FilePattern,
+    excludes: &[FilePattern],
+    recursive: bool,
+    follow_symlinks: bool,
+) -> Vec<PathBuf> {
+    let max_depth = match (pattern.is_path_pattern(), recursive) {
+        (true, _) => pattern.max_depth(),
+        (false, true) => None,
+        (false, false) => Some(1),
+    };
+
+    let mut walker = WalkDir::new(root)
+        .follow_links(follow_symlinks)
+        .sort_by_file_name();
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth);
+    }
+
+    walker
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry.path().strip_prefix(root).is_ok_and(|relative| {
+                pattern.matches(relative)
+                    && !excludes.iter().any(|exclude| exclude.matches(relative))
+            })
+        })
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// What a scan found: counts over every file, and the listing up to a limit
+#[derive(Debug, Serialize)]
+pub struct ScanSummary {
+    pub total: usize,
+    /// Paths dropped because they were another spelling of a listed file
+    pub duplicates: usize,
+    /// Files per lowercase extension, `""` for none
+    pub by_extension: BTreeMap<String, usize>,
+    /// Files per directory, relative to the scan root when under it
+    pub by_directory: BTreeMap<String, usize>,
+    pub files: Vec<PathBuf>,
+    /// Files left out of `files` by the limit
+    pub omitted: usize,
+}
+
+impl ScanSummary {
+    pub fn new(
+        files: &[PathBuf],
+        duplicates: usize,
+        root: Option<&Path>,
+        limit: Option<usize>,
+    ) -> Self {
+        let mut by_extension = BTreeMap::new();
+        let mut by_directory = BTreeMap::new();
+        for file in files {
+            let extension = file
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            *by_extension.entry(extension).or_insert(0) += 1;
+
+            let parent = file.parent().unwrap_or(Path::new(""));
+            let parent = root
+                .and_then(|root| parent.strip_prefix(root).ok())
+                .unwrap_or(parent);
+            let parent: Vec<_> = parent
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            let directory = if parent.is_empty() {
+                ".".to_string()
+            } else {
+                parent.join("/")
+            };
+            *by_directory.entry(directory).or_insert(0) += 1;
+        }
+
+        let shown = limit.unwrap_or(files.len()).min(files.len());
+        ScanSummary {
+            total: files.len(),
+            duplicates,
+            by_extension,
+            by_directory,
+            files: files[..shown].to_vec(),
+            omitted: files.len() - shown,
+        }
+    }
+}
+
+/// Read a list of document paths, one per line, as given to `--files-from`.
+///
+/// Blank lines and lines starting with `#` are skipped.
+pub fn read_file_list(path: &Path) -> Result<Vec<PathBuf>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read file list {}: {}", path.display(), e))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Whether batch search can search `path`: a supported document type, or a
+/// container of them. Plain text files are left out, so a needles list or
+/// notes lying next to the documents are not searched unless asked for; see
+/// [`is_plain_text`].
+///
+/// Only files without an extension are opened to read their magic bytes, so
+/// a scan does not pick up spreadsheets or presentations whose legacy
+/// format shares its magic number with `.doc` files.
+pub fn is_searchable(path: &Path) -> bool {
+    match path.extension() {
+        Some(_) => has_searchable_extension(path),
+        None => parse_filetype(path).is_ok_and(|file_type| file_type != FileType::Txt),
+    }
+}
+
+/// [`is_searchable`] by the extension alone, for names that are not files
+/// on disk, such as the entries of an archive
+pub fn has_searchable_extension(path: &Path) -> bool {
+    archive::is_container(path)
+        || filetype_from_extension(&path.to_string_lossy())
+            .is_ok_and(|file_type| file_type != FileType::Txt)
+}
+
+/// Whether `path` is a plain text file, which batch search only searches
+/// when given by name or by a pattern that [names](FilePattern::names_plain_text) them
+pub fn is_plain_text(path: &Path) -> bool {
+    filetype_from_extension(&path.to_string_lossy())
+        .is_ok_and(|file_type| file_type == FileType::Txt)
+}
+
+/// Search the contents of a single document, dispatching on its extension
+/// and, for Word documents, on whether it is a legacy binary file.
+/// Containers (`.zip`, `.gz`) are searched entry by entry.
+pub fn search_bytes(
+    needles: &[(String, String)],
+    path: &Path,
+    bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    if archive::is_container(path) {
+        return archive::search(path, bytes, &|entry, bytes| {
+            search_bytes(needles, entry, bytes, extract)
+        });
+    }
+    let file_type = file_type_of(path, bytes)?;
+
+    let (mut extraction, parsed_as) =
+        with_detected_retry(path, bytes, file_type, extract, |file_type| {
+            Ok(parser_for(file_type)
+                .extract_text(path, bytes, extract)?
+                .search(needles, extract))
+        })?;
+    if parsed_as != file_type {
+        let warning = format!(
+            "extension {}, parsed as {}",
+            file_type.extension(),
+            parsed_as.name()
+        );
+        tracing::warn!(file = %path.display(), "{}", warning);
+        extraction.warnings.push(warning);
+    }
+    Ok(extraction)
+}
+
+/// The blocks [`search_bytes`] would search in `bytes`, for `explain`
+pub fn extract_blocks(
+    path: &Path,
+    bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Vec<TextBlock>> {
+    let file_type = file_type_of(path, bytes)?;
+
+    let (blocks, _) = with_detected_retry(path, bytes, file_type, extract, |file_type| {
+        Ok(parser_for(file_type)
+            .extract_text(path, bytes, extract)?
+            .blocks)
+    })?;
+    Ok(blocks)
+}
+
+/// The first blocks [`extract_blocks`] would return, as many as `budget`
+/// allows, for previews. Containers are not opened.
+pub fn preview_blocks(
+    path: &Path,
+    bytes: &[u8],
+    extract: ExtractOptions,
+    budget: BlockBudget,
+) -> Result<Vec<TextBlock>> {
+    let file_type = file_type_of(path, bytes)?;
+
+    let (blocks, _) = with_detected_retry(path, bytes, file_type, extract, |file_type| {
+        parser_for(file_type).extract_within(path, bytes, extract, budget)
+    })?;
+    Ok(blocks)
+}
+
+/// The type the extension of `path` names or, failing that, the one the
+/// magic bytes of `bytes` name
+fn file_type_of(path: &Path, bytes: &[u8]) -> Result<FileType> {
+    let name = path.to_string_lossy();
+    Ok(filetype_from_extension(&name)
+        .or_else(|_| detect_filetype_from_bytes(bytes, Some(&name)))?)
+}
+
+/// Run `parse` for the type the extension names; when that fails and the
+/// magic bytes of `bytes` name a type with another parser, run it once more
+/// for that type. Returns the result and the type that produced it. When
+/// both fail, the first error is kept.
+///
+/// Text extraction from malformed documents can panic deep inside the PDF
+/// library; that is contained to the offending file and counts as failing.
+fn with_detected_retry<T>(
+    path: &Path,
+    bytes: &[u8],
+    file_type: FileType,
+    extract: ExtractOptions,
+    parse: impl Fn(FileType) -> Result<T>,
+) -> Result<(T, FileType)> {
+    let attempt = |file_type| {
+        panic::catch_unwind(AssertUnwindSafe(|| parse(file_type)))
+            .unwrap_or_else(|_| Err(anyhow!("Parser panicked on {}", path.display())))
+    };
+
+    let error = match attempt(file_type) {
+        Ok(parsed) => return Ok((parsed, file_type)),
+        Err(e) => e,
+    };
+    // Word documents of either kind share a parser, which already tells
+    // them apart by their contents
+    let detected = sniff_document_type(bytes).filter(|detected| {
+        extract.retry_detected_type && !parser_for(file_type).file_types().contains(detected)
+    });
+    match detected.map(|detected| (detected, attempt(detected))) {
+        Some((detected, Ok(parsed))) => Ok((parsed, detected)),
+        _ => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::time::Duration;
+
+    fn needles() -> Vec<(String, String)> {
+        vec![
+            ("Alice Johnson".to_string(), "alice@company.com".to_string()),
+            ("Bob Smith".to_string(), "bob@enterprise.org".to_string()),
+        ]
+    }
+
+    fn corpus(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for i in 0..6 {
+            let (name, bytes) = if i % 2 == 0 {
+                (
+                    format!("doc{}.docx", i),
+                    fixtures::docx(&["Intro", "Contact Alice Johnson"]),
+                )
+            } else {
+                (
+                    format!("doc{}.pdf", i),
+                    fixtures::pdf(&[&["Report", "Bob Smith signed"]]),
+                )
+            };
+            let path = dir.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            files.push(path);
+        }
+        files
+    }
+
+    /// Scan a small tree with `pattern`, returning `/`-separated relative paths
+    fn scan(pattern: &str, recursive: bool) -> Vec<String> {
+        scan_excluding(pattern, &[], recursive)
+    }
+
+    fn scan_excluding(pattern: &str, excludes: &[&str], recursive: bool) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "a.pdf",
+            "b.docx",
+            "reports/r.docx",
+            "reports/2024/deep.docx",
+            "reports/2024/deep.pdf",
+            "archive/old.pdf",
+            "archive/sub/older.pdf",
+            "other/reports/stray.docx",
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+
+        let pattern = FilePattern::new(pattern).unwrap();
+        let excludes: Vec<_> = excludes
+            .iter()
+            .map(|exclude| FilePattern::new(exclude).unwrap())
+            .collect();
+        scan_directory(dir.path(), &pattern, &excludes, recursive, false)
+            .iter()
+            .map(|path| {
+                let relative = path.strip_prefix(dir.path()).unwrap();
+                relative
+                    .components()
+                    .map(|part| part.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_name_patterns_match_file_names() {
+        assert_eq!(scan("*.pdf", false), ["a.pdf"]);
+        assert_eq!(
+            scan("*.pdf", true),
+            [
+                "a.pdf",
+                "archive/old.pdf",
+                "archive/sub/older.pdf",
+                "reports/2024/deep.pdf"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_path_patterns_match_relative_paths() {
+        for recursive in [false, true] {
+            assert_eq!(
+                scan("reports/**/*.docx", recursive),
+                ["reports/2024/deep.docx", "reports/r.docx"]
+            );
+            assert_eq!(scan("archive/*.pdf", recursive), ["archive/old.pdf"]);
+            assert_eq!(scan("./archive/*.pdf", recursive), ["archive/old.pdf"]);
+            assert_eq!(
+                scan("**/reports/*.docx", recursive),
+                ["other/reports/stray.docx", "reports/r.docx"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_excluded_files_are_left_out() {
+        assert_eq!(
+            scan_excluding("**/*.pdf", &["**/archive/**"], false),
+            ["a.pdf", "reports/2024/deep.pdf"]
+        );
+        assert_eq!(
+            scan_excluding("*.*", &["*.docx", "archive/*"], true),
+            ["a.pdf", "archive/sub/older.pdf", "reports/2024/deep.pdf"]
+        );
+    }
+
+    #[test]
+    fn test_scan_summary_counts_every_file_past_the_limit() {
+        let root = Path::new("corpus");
+        let files: Vec<PathBuf> = ["a.pdf", "b.PDF", "reports/r.docx", "reports/2024/deep.docx"]
+            .iter()
+            .map(|file| root.join(file))
+            .collect();
+        let summary = ScanSummary::new(&files, 1, Some(root), Some(2));
+
+        assert_eq!(summary.files, files[..2]);
+        assert_eq!(
+            (summary.total, summary.omitted, summary.duplicates),
+            (4, 2, 1)
+        );
+        assert_eq!(
+            summary.by_extension,
+            BTreeMap::from([("docx".to_string(), 2), ("pdf".to_string(), 2)])
+        );
+        let directories: Vec<_> = summary
+            .by_directory
+            .iter()
+            .map(|(dir, count)| (dir.as_str(), *count))
+            .collect();
+        assert_eq!(directories, [(".", 2), ("reports", 1), ("reports/2024", 1)]);
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_an_error() {
+        assert!(FilePattern::new("reports/***.pdf").is_err());
+    }
+
+    #[test]
+    fn test_pipeline_matches_sequential_search_in_input_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = corpus(dir.path());
+        let needles = needles();
+
+        let options = PipelineOptions {
+            cpu_threads: 3,
+            io_threads: 2,
+            channel_bound: 2,
+            ..PipelineOptions::default()
+        };
+        let outcomes = run_pipeline(&needles, &files, &options, &|_| {});
+
+        assert_eq!(outcomes.len(), files.len());
+        for (outcome, path) in outcomes.iter().zip(&files) {
+            assert_eq!(&outcome.path, path);
+            let expected = search_bytes(
+                &needles,
+                path,
+                &std::fs::read(path).unwrap(),
+                ExtractOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(outcome.occurrences.as_ref().unwrap(), &expected.occurrences);
+            assert_eq!(outcome.text_bytes, expected.text_bytes);
+            assert_eq!(expected.occurrences.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_slow_reader_does_not_deadlock_at_channel_bound() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = corpus(dir.path());
+        let needles = needles();
+
+        let (done_tx, done_rx) = mpsc::channel();
+        let worker_files = files.clone();
+        thread::spawn(move || {
+            let options = PipelineOptions {
+                cpu_threads: 2,
+                io_threads: 3,
+                channel_bound: 1,
+                ..PipelineOptions::default()
+            };
+            let slow_reader = |path: &Path| {
+                thread::sleep(Duration::from_millis(20));
+                std::fs::read(path)
+            };
+            let outcomes =
+                run_pipeline_with_reader(&needles, &worker_files, &options, slow_reader, &|_| {});
+            done_tx.send(outcomes).unwrap();
+        });
+
+        let outcomes = done_rx
+            .recv_timeout(Duration::from_secs(30))
+            .expect("pipeline deadlocked");
+        assert!(outcomes.iter().all(|outcome| outcome.occurrences.is_ok()));
+    }
+
+    #[test]
+    fn test_stopping_from_on_outcome_skips_the_remaining_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = corpus(dir.path());
+        let scopes = NeedleScopes::global(
+            &dir.path().join("needles.csv"),
+            needles(),
+            Default::default(),
+            files.len(),
+        );
+
+        let options = PipelineOptions {
+            cpu_threads: 1,
+            io_threads: 1,
+            channel_bound: 1,
+            ..PipelineOptions::default()
+        };
+        let seen = AtomicUsize::new(0);
+        let outcomes =
+            run_pipeline_scoped_streaming(&scopes, &files, &options, &|_| {}, &|index, outcome| {
+                assert_eq!(outcome.path, files[index]);
+                seen.fetch_add(1, Ordering::Relaxed);
+                false
+            });
+
+        let stopped_after_first = |outcomes: &[FileOutcome]| {
+            assert!(outcomes[0].occurrences.is_ok());
+            for outcome in &outcomes[1..] {
+                let error = outcome.occurrences.as_ref().unwrap_err();
+                assert!(matches!(
+                    error.downcast_ref(),
+                    Some(DocumentError::SearchStopped { .. })
+                ));
+            }
+        };
+        assert_eq!(seen.into_inner(), 1);
+        stopped_after_first(&outcomes);
+
+        // Cancelling the token, as Ctrl-C does, stops it the same way
+        let outcomes =
+            run_pipeline_scoped_streaming(&scopes, &files, &options, &|_| {}, &|_, _| {
+                options.cancel.cancel();
+                true
+            });
+        stopped_after_first(&outcomes);
+    }
+
+    #[test]
+    fn test_read_failures_are_reported_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = corpus(dir.path());
+        files.insert(1, dir.path().join("missing.pdf"));
+
+        let outcomes = run_pipeline(&needles(), &files, &PipelineOptions::default(), &|_| {});
+
+        assert!(outcomes[1].occurrences.is_err());
+        assert_eq!(
+            outcomes.iter().filter(|o| o.occurrences.is_ok()).count(),
+            files.len() - 1
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_same_file_via_three_spellings_is_processed_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let absolute = dir.path().join("report.docx");
+        std::fs::write(&absolute, fixtures::docx(&["Alice Johnson"])).unwrap();
+
+        let cwd = std::env::current_dir().unwrap();
+        let to_root = "../".repeat(cwd.components().count() - 1);
+        let relative = PathBuf::from(to_root).join(absolute.strip_prefix("/").unwrap());
+        assert!(relative.is_relative() && relative.exists());
+
+        let link = dir.path().join("link.docx");
+        std::os::unix::fs::symlink(&absolute, &link).unwrap();
+
+        let other = dir.path().join("other.docx");
+        std::fs::write(&other, fixtures::docx(&["Bob Smith"])).unwrap();
+
+        let (files, duplicates) = dedupe_files(vec![
+            relative.clone(),
+            other.clone(),
+            absolute.clone(),
+            link,
+            relative.clone(),
+        ]);
+        assert_eq!(files, vec![relative, other]);
+        assert_eq!(duplicates, 3);
+
+        let processed = AtomicUsize::new(0);
+        let outcomes = run_pipeline(&needles(), &files, &PipelineOptions::default(), &|_| {
+            processed.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(processed.into_inner(), 2);
+        assert_eq!(outcomes[0].occurrences.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_renamed_legacy_doc_reports_unsupported() {
+        let path = Path::new("archive/old-contract.docx");
+        let err = search_bytes(
+            &needles(),
+            path,
+            &fixtures::ole2_stub(),
+            ExtractOptions::default(),
+        )
+        .unwrap_err();
+
+        match err.downcast_ref::<DocumentError>() {
+            Some(DocumentError::LegacyDocUnsupported { path: reported, .. }) => {
+                assert_eq!(reported, path)
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        let message = err.to_string();
+        assert!(
+            message.contains("old-contract.docx") && message.contains("Convert it to .docx"),
+            "{}",
+            message
+        );
+    }
+
+    #[cfg(feature = "doc")]
+    #[test]
+    fn test_simple_legacy_doc_is_searched_with_doc_feature() {
+        let bytes = fixtures::legacy_doc(&["Contact Alice Johnson"]);
+
+        for name in ["old.doc", "renamed.docx"] {
+            let extraction = search_bytes(
+                &needles(),
+                Path::new(name),
+                &bytes,
+                ExtractOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(extraction.occurrences.len(), 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_misnamed_documents_are_parsed_as_their_detected_type() {
+        let pdf = fixtures::pdf(&[&["Signed by Alice Johnson"]]);
+        let docx = fixtures::docx(&["Alice Johnson"]);
+        let rtf = fixtures::rtf(&["Alice Johnson"]);
+
+        for (name, bytes, warning) in [
+            ("scan.docx", &pdf, "extension .docx, parsed as PDF"),
+            ("scan.doc", &pdf, "extension .doc, parsed as PDF"),
+            ("memo.pdf", &docx, "extension .pdf, parsed as DOCX"),
+            // Word saves RTF under a .doc name
+            ("letter.doc", &rtf, "extension .doc, parsed as RTF"),
+        ] {
+            let extraction = search_bytes(
+                &needles(),
+                Path::new(name),
+                bytes,
+                ExtractOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(extraction.occurrences.len(), 1, "{}", name);
+            assert_eq!(extraction.warnings, [warning]);
+
+            let no_retry = ExtractOptions {
+                retry_detected_type: false,
+                ..ExtractOptions::default()
+            };
+            assert!(
+                search_bytes(&needles(), Path::new(name), bytes, no_retry).is_err(),
+                "{}",
+                name
+            );
+        }
+
+        // A correctly named document has nothing to warn about
+        let extraction = search_bytes(
+            &needles(),
+            Path::new("memo.docx"),
+            &docx,
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        assert!(extraction.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_documents_keep_their_original_error() {
+        let expected = search_bytes(
+            &needles(),
+            Path::new("broken.docx"),
+            b"not a document",
+            ExtractOptions::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        // Looks like a PDF, but is not one either: the DOCX error stands
+        let err = search_bytes(
+            &needles(),
+            Path::new("broken.docx"),
+            b"%PDF-1.4 truncated",
+            ExtractOptions::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, expected);
+
+        let err = search_bytes(
+            &needles(),
+            Path::new("broken.pdf"),
+            b"PK\x03\x04 truncated",
+            ExtractOptions::default(),
+        )
+        .unwrap_err();
+        assert!(!err.to_string().to_lowercase().contains("zip"), "{}", err);
+    }
+
+    #[test]
+    fn test_retry_warnings_reach_the_file_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.docx");
+        std::fs::write(&path, fixtures::pdf(&[&["Alice Johnson"]])).unwrap();
+
+        let outcomes = run_pipeline(&needles(), &[path], &PipelineOptions::default(), &|_| {});
+        assert_eq!(outcomes[0].occurrences.as_ref().unwrap().len(), 1);
+        assert_eq!(outcomes[0].warnings, ["extension .docx, parsed as PDF"]);
+    }
+
+    #[test]
+    fn test_dedupe_keeps_unresolvable_paths_as_given() {
+        let (files, duplicates) = dedupe_files(vec![
+            PathBuf::from("missing/a.pdf"),
+            PathBuf::from("missing/b.pdf"),
+            PathBuf::from("missing/a.pdf"),
+        ]);
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("missing/a.pdf"),
+                PathBuf::from("missing/b.pdf")
+            ]
+        );
+        assert_eq!(duplicates, 1);
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("files.txt");
+        std::fs::write(&list, "# corpus\na.pdf\n\n  b.docx  \na.pdf\n").unwrap();
+
+        assert_eq!(
+            read_file_list(&list).unwrap(),
+            vec![
+                PathBuf::from("a.pdf"),
+                PathBuf::from("b.docx"),
+                PathBuf::from("a.pdf")
+            ]
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! Reproducible throughput benchmarks (`docsearcher bench`).
+//!
+//! [`generate_corpus`] writes a synthetic corpus of alternating DOCX and PDF
+//! documents, built with [`crate::fixtures`], into a temporary directory.
+//! Needles are pseudo-names between `Dr` and `Bench`, words the filler text
+//! never uses, so in any case the only matches are the needles planted by
+//! the generator. [`run`] searches the corpus
+//! with the batch pipeline and derives throughput figures, and [`compare`]
+//! checks them against a saved baseline.
+//!
+//! The same seed always produces the same corpus, so runs on different
+//! machines or builds search identical input.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch::{run_pipeline, PipelineOptions},
+    fixtures,
+    matcher::Strategy,
+    resources::peak_rss_bytes,
+    types::OwnedNeedle,
+};
+
+/// Text lines on every generated page
+pub const LINES_PER_PAGE: usize = 20;
+
+const WORDS_PER_LINE: usize = 10;
+
+/// One line in this many gets a planted needle
+const PLANT_EVERY: u64 = 8;
+
+const SYLLABLES: [&str; 16] = [
+    "ka", "lo", "mi", "ne", "ru", "sa", "to", "vi", "ba", "de", "fo", "gu", "ha", "jo", "pe", "zu",
+];
+
+const FILLER: [&str; 16] = [
+    "the",
+    "report",
+    "quarterly",
+    "meeting",
+    "budget",
+    "review",
+    "and",
+    "of",
+    "with",
+    "project",
+    "notes",
+    "team",
+    "draft",
+    "final",
+    "for",
+    "update",
+];
+
+/// Size and seed of a synthetic corpus
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusOptions {
+    pub needles: usize,
+    pub files: usize,
+    pub pages: usize,
+    pub seed: u64,
+}
+
+/// A generated corpus, deleted when dropped
+pub struct Corpus {
+    pub options: CorpusOptions,
+    pub needles: Vec<OwnedNeedle>,
+    pub files: Vec<PathBuf>,
+    /// Bytes of text written into the documents
+    pub text_bytes: u64,
+    /// Number of needle occurrences planted in the text
+    pub planted: usize,
+    dir: tempfile::TempDir,
+}
+
+impl Corpus {
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// Throughput figures for one benchmark run
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchMetrics {
+    pub corpus: CorpusOptions,
+    pub cpu_threads: usize,
+    pub io_threads: usize,
+    /// How needles were looked up; compare runs with `--matcher naive` and
+    /// `--matcher automaton` to see what the automaton saves
+    #[serde(default)]
+    pub strategy: Strategy,
+    pub failed_files: usize,
+    /// Bytes of document files read
+    pub input_bytes: u64,
+    /// Bytes of text extracted
+    pub text_bytes: u64,
+    pub matches: usize,
+    pub elapsed_secs: f64,
+    pub files_per_sec: f64,
+    /// Megabytes (10^6 bytes) of text extracted per second
+    pub mb_per_sec: f64,
+    pub matches_per_sec: f64,
+    /// Peak resident set size of the process, where the platform reports it
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// How one rate compares to the baseline
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricChange {
+    pub metric: String,
+    pub baseline: f64,
+    pub current: f64,
+    /// Relative change in percent; negative is slower
+    pub change_pct: f64,
+    pub regressed: bool,
+}
+
+/// What `bench --format json` prints and `--baseline` reads back
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub metrics: BenchMetrics,
+    #[serde(default)]
+    pub comparison: Vec<MetricChange>,
+}
+
+impl BenchReport {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read baseline {}: {}", path.display(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("{} is not a bench JSON report: {}", path.display(), e))
+    }
+
+    /// Whether any rate dropped by more than the allowed regression
+    pub fn regressed(&self) -> bool {
+        self.comparison.iter().any(|change| change.regressed)
+    }
+}
+
+/// Deterministic SplitMix64 generator, so corpora don't depend on a crate's RNG
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// A capitalized pseudo-name unique to `index`. The leading `Dr` keeps one
+/// name from matching inside another, as `lo` would inside `kalo`.
+fn needle_term(index: usize) -> String {
+    let mut word = String::new();
+    let mut rest = index;
+    loop {
+        word.push_str(SYLLABLES[rest % SYLLABLES.len()]);
+        rest /= SYLLABLES.len();
+        if rest == 0 {
+            break;
+        }
+    }
+
+    let mut chars = word.chars();
+    let first = chars
+        .next()
+        .map(|c| c.to_ascii_uppercase())
+        .unwrap_or_default();
+    format!("Dr {}{} Bench", first, chars.as_str())
+}
+
+/// Write a corpus for `options` into a new temporary directory
+pub fn generate_corpus(options: &CorpusOptions) -> Result<Corpus> {
+    let dir = tempfile::Builder::new()
+        .prefix("docsearcher-bench")
+        .tempdir()?;
+    let mut rng = SplitMix64(options.seed);
+
+    let needles: Vec<OwnedNeedle> = (0..options.needles)
+        .map(|i| (needle_term(i), format!("id={}", i)))
+        .collect();
+
+    let mut files = Vec::with_capacity(options.files);
+    let mut text_bytes = 0;
+    let mut planted = 0;
+
+    for index in 0..options.files {
+        let pages: Vec<Vec<String>> = (0..options.pages)
+            .map(|_| {
+                (0..LINES_PER_PAGE)
+                    .map(|_| {
+                        let mut words: Vec<&str> = (0..WORDS_PER_LINE)
+                            .map(|_| FILLER[rng.below(FILLER.len())])
+                            .collect();
+                        if !needles.is_empty() && rng.next().is_multiple_of(PLANT_EVERY) {
+                            let position = rng.below(words.len() + 1);
+                            words.insert(position, &needles[rng.below(needles.len())].0);
+                            planted += 1;
+                        }
+                        let line = words.join(" ");
+                        text_bytes += line.len() as u64 + 1;
+                        line
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (name, bytes) = if index % 2 == 0 {
+            let paragraphs: Vec<&str> = pages.iter().flatten().map(String::as_str).collect();
+            (format!("doc{:05}.docx", index), fixtures::docx(&paragraphs))
+        } else {
+            let lines: Vec<Vec<&str>> = pages
+                .iter()
+                .map(|page| page.iter().map(String::as_str).collect())
+                .collect();
+            let pages: Vec<&[&str]> = lines.iter().map(Vec::as_slice).collect();
+            (format!("doc{:05}.pdf", index), fixtures::pdf(&pages))
+        };
+
+        let path = dir.path().join(name);
+        std::fs::write(&path, bytes)?;
+        files.push(path);
+    }
+
+    Ok(Corpus {
+        options: *options,
+        needles,
+        files,
+        text_bytes,
+        planted,
+        dir,
+    })
+}
+
+/// Search `corpus` with the batch pipeline and measure throughput
+pub fn run(corpus: &Corpus, pipeline: &PipelineOptions) -> BenchMetrics {
+    let input_bytes = corpus
+        .files
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+
+    let start = Instant::now();
+    let outcomes = run_pipeline(&corpus.needles, &corpus.files, pipeline, &|_| {});
+    let elapsed_secs = start.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    let failed_files = outcomes
+        .iter()
+        .filter(|outcome| outcome.occurrences.is_err())
+        .count();
+    let matches = outcomes
+        .iter()
+        .filter_map(|outcome| outcome.occurrences.as_ref().ok())
+        .map(Vec::len)
+        .sum();
+
+    BenchMetrics {
+        corpus: corpus.options,
+        cpu_threads: pipeline.cpu_threads,
+        io_threads: pipeline.io_threads,
+        strategy: pipeline.extract.strategy,
+        failed_files,
+        input_bytes,
+        text_bytes: corpus.text_bytes,
+        matches,
+        elapsed_secs,
+        files_per_sec: corpus.files.len() as f64 / elapsed_secs,
+        mb_per_sec: corpus.text_bytes as f64 / 1e6 / elapsed_secs,
+        matches_per_sec: matches as f64 / elapsed_secs,
+        peak_rss_bytes: peak_rss_bytes(),
+    }
+}
+
+/// Compare the rates in `current` with `baseline`; a rate regressed when it
+/// dropped by more than `max_regression_pct` percent
+pub fn compare(
+    current: &BenchMetrics,
+    baseline: &BenchMetrics,
+    max_regression_pct: f64,
+) -> Vec<MetricChange> {
+    [
+        (
+            "files_per_sec",
+            current.files_per_sec,
+            baseline.files_per_sec,
+        ),
+        ("mb_per_sec", current.mb_per_sec, baseline.mb_per_sec),
+        (
+            "matches_per_sec",
+            current.matches_per_sec,
+            baseline.matches_per_sec,
+        ),
+    ]
+    .into_iter()
+    .map(|(metric, current, baseline)| {
+        let change_pct = if baseline > 0.0 {
+            (current - baseline) / baseline * 100.0
+        } else {
+            0.0
+        };
+        MetricChange {
+            metric: metric.to_string(),
+            baseline,
+            current,
+            change_pct,
+            regressed: change_pct < -max_regression_pct,
+        }
+    })
+    .collect()
+}
+
+/// Parse a regression threshold such as `10%` or `2.5`
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let number = value.trim().trim_end_matches('%').trim();
+    match number.parse::<f64>() {
+        Ok(pct) if (0.0..=100.0).contains(&pct) => Ok(pct),
+        _ => Err(format!(
+            "expected a percentage between 0% and 100%, got '{}'",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ExtractOptions;
+
+    const TINY: CorpusOptions = CorpusOptions {
+        needles: 30,
+        files: 4,
+        pages: 2,
+        seed: 7,
+    };
+
+    #[test]
+    fn test_tiny_bench_end_to_end() {
+        let corpus = generate_corpus(&TINY).unwrap();
+        assert_eq!(corpus.files.len(), 4);
+        assert!(corpus.planted > 0);
+
+        let pipeline = PipelineOptions {
+            cpu_threads: 2,
+            io_threads: 1,
+            channel_bound: 4,
+            ..PipelineOptions::default()
+        };
+        let metrics = run(&corpus, &pipeline);
+        assert_eq!(metrics.corpus, TINY);
+        assert_eq!(metrics.failed_files, 0);
+        assert_eq!(metrics.matches, corpus.planted);
+        assert_eq!(metrics.text_bytes, corpus.text_bytes);
+        assert!(metrics.input_bytes > metrics.text_bytes / 2);
+        assert!(metrics.elapsed_secs > 0.0);
+        assert!(
+            metrics.files_per_sec > 0.0
+                && metrics.mb_per_sec > 0.0
+                && metrics.matches_per_sec > 0.0
+        );
+        if cfg!(unix) {
+            assert!(metrics.peak_rss_bytes.is_some_and(|rss| rss > 0));
+        }
+
+        let report = BenchReport {
+            metrics,
+            comparison: Vec::new(),
+        };
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<BenchReport>(&json).unwrap(), report);
+
+        for strategy in [Strategy::Naive, Strategy::Automaton] {
+            let pipeline = PipelineOptions {
+                extract: ExtractOptions {
+                    strategy,
+                    ..ExtractOptions::default()
+                },
+                ..pipeline.clone()
+            };
+            let metrics = run(&corpus, &pipeline);
+            assert_eq!(
+                (metrics.strategy, metrics.matches),
+                (strategy, corpus.planted)
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_corpus() {
+        let first = generate_corpus(&TINY).unwrap();
+        let second = generate_corpus(&TINY).unwrap();
+        for (a, b) in first.files.iter().zip(&second.files) {
+            assert_eq!(std::fs::read(a).unwrap(), std::fs::read(b).unwrap());
+        }
+
+        let other = generate_corpus(&CorpusOptions { seed: 8, ..TINY }).unwrap();
+        assert_ne!(
+            std::fs::read(&first.files[0]).unwrap(),
+            std::fs::read(&other.files[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compare_flags_regressions_beyond_threshold() {
+        let corpus = generate_corpus(&CorpusOptions { files: 0, ..TINY }).unwrap();
+        let baseline = BenchMetrics {
+            files_per_sec: 100.0,
+            mb_per_sec: 10.0,
+            matches_per_sec: 50.0,
+            ..run(&corpus, &PipelineOptions::default())
+        };
+        let current = BenchMetrics {
+            files_per_sec: 85.0,
+            mb_per_sec: 9.5,
+            matches_per_sec: 60.0,
+            ..baseline.clone()
+        };
+
+        let changes = compare(&current, &baseline, 10.0);
+        let regressed: Vec<&str> = changes
+            .iter()
+            .filter(|c| c.regressed)
+            .map(|c| c.metric.as_str())
+            .collect();
+        assert_eq!(regressed, ["files_per_sec"]);
+        assert!((changes[0].change_pct + 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("10%"), Ok(10.0));
+        assert_eq!(parse_percent("2.5"), Ok(2.5));
+        assert!(parse_percent("-5%").is_err());
+        assert!(parse_percent("ten").is_err());
+    }
+}
@@ -1,17 +1,57 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
-use dialoguer::{Input, Confirm, Select};
-use indicatif::{ProgressBar, ProgressStyle, MultiProgress};
-use std::path::PathBuf;
-use walkdir::WalkDir;
-use glob::glob;
+use dialoguer::{Confirm, Input, Select};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+#[cfg(feature = "sqlite")]
+use crate::store::{CannedQuery, DocumentRecord, ResultStore};
 use crate::{
-    types::{FileType, SearchResult},
-    utils::{parse_filetype, read_needles_from_file},
-    parsers::{parse_docx_from_path, parse_pdf_from_path},
-    cmd::tui::TuiApp,
+    api::{v1, ApiSession, ApiVersion, Reported},
+    batch::{
+        self, dedupe_files, read_file_list, run_pipeline_scoped_streaming, FileOutcome,
+        FilePattern, PipelineOptions, ScanSummary,
+    },
+    bench::{self, BenchReport, CorpusOptions},
+    cmd::tui::{PreviewBudget, TuiApp},
+    error::DocumentError,
+    html_full::{self, ExcerptLimits, FullReport},
+    interrupt::{self, EXIT_INTERRUPTED},
+    local_needles::{ListOptions, NeedleScopes},
+    logging::{self, LogOptions},
+    matcher::{CaseFolding, MatcherChoice, Strategy},
+    needle_index::{self, CompiledNeedles},
+    needle_list::{
+        self, needle_line, Change, ListFormat, ListLine, Merged, NeedleList, OnDuplicate,
+    },
+    output::{outln, warnln, LineWriter, RecordWriter},
+    parsers::{self, archive, docx, epub, odt, pdf},
+    progress::{NullReporter, PrintReporter, ProgressReporter},
+    report::{self, ReportEntry},
+    resources::ResourceUsage,
+    search::{
+        collapse_repeats, explain_lines, extracted_text, search_matcher, BlockLocation, MatchSet,
+        Occurrence, RepeatGroup, TextBlock,
+    },
+    settings::{OutputFormat, SearchSettings},
+    spill::{self, Spill},
+    triage::{self, Annotation, Disposition, Dispositions, Location, MatchRecord, TriageSession},
+    types::{
+        ContextKind, ExtractOptions, FileType, MatchOptions, MetadataFormat, NeedleDelimiter,
+        NeedleWeights, OwnedNeedle, SearchResult, SearchResults, Source, StopwordMode,
+    },
+    utils::{
+        check_regex_needles_in_file, check_swapped_arguments, context_snippet, csv_row,
+        display_width, escape_html, filter_needles_by_date, filter_needles_by_metadata,
+        filter_stopwords, metadata_keys, parse_filetype, parse_metadata_kv, read_needle_weights,
+        read_needles_from_file, read_needles_with_stats, snippet_range, validity_problems, Date,
+        NeedleFileStats, Stopwords,
+    },
 };
 
 #[derive(Parser)]
@@ -43,80 +83,305 @@ pub struct EnhancedCli {
     #[arg(short, long)]
     quiet: bool,
 
-    /// Case sensitive search
-    #[arg(long)]
-    case_sensitive: bool,
+    #[command(flatten)]
+    settings: SearchSettings,
 
-    /// Whole word matching
-    #[arg(long)]
-    whole_word: bool,
+    /// Append a JSON line describing each run to this file
+    #[arg(long, global = true, env = "DOCSEARCHER_LOG_FILE", value_name = "PATH")]
+    log_file: Option<PathBuf>,
 
-    /// Output format (text, json, csv, html)
-    #[arg(short, long, default_value = "text")]
-    format: String,
+    /// Rotate the log file once it reaches this many bytes
+    #[arg(long, global = true, env = "DOCSEARCHER_LOG_MAX_SIZE", default_value_t = 10 * 1024 * 1024, value_name = "BYTES")]
+    log_max_size: u64,
+
+    /// Number of rotated log files to keep
+    #[arg(
+        long,
+        global = true,
+        env = "DOCSEARCHER_LOG_KEEP",
+        default_value_t = 5,
+        value_name = "N"
+    )]
+    log_keep: usize,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Interactive search mode
     Interactive,
-    
+
     /// TUI mode with modern interface
-    Tui,
-    
+    Tui {
+        /// Documents to select for searching
+        files: Vec<PathBuf>,
+
+        /// Blocks (paragraphs, lines) a preview reads from the start of each file
+        #[arg(long, default_value_t = 20, value_name = "BLOCKS")]
+        preview_blocks: usize,
+
+        /// Milliseconds a preview spends extracting each file
+        #[arg(long, default_value_t = 200, value_name = "MS")]
+        preview_ms: u64,
+
+        #[command(flatten)]
+        settings: SearchSettings,
+    },
+
     /// Search in a specific document
     Search {
         /// Path to file containing search terms
         needles: PathBuf,
-        
+
         /// Path to document file
         document: PathBuf,
-        
-        /// Output format (text, json, csv, html)
-        #[arg(short, long, default_value = "text")]
-        format: String,
-        
-        /// Case sensitive search
+
+        #[command(flatten)]
+        settings: SearchSettings,
+
+        /// Treat a needles file without needles as a search with no matches (exit 1) instead of an error
         #[arg(long)]
-        case_sensitive: bool,
-        
-        /// Whole word matching
+        allow_empty_needles: bool,
+
+        /// Use a compiled needles file even if it was built for other matching options
         #[arg(long)]
-        whole_word: bool,
+        force: bool,
+
+        /// Skip the check for needles and document arguments given in the wrong order
+        #[arg(long)]
+        swap_ok: bool,
+
+        /// Append this run's results to a SQLite database (requires the `sqlite` feature)
+        #[arg(long, value_name = "DB")]
+        store_results: Option<PathBuf>,
+
+        /// Also write the results as JSON to FILE, for `triage`
+        #[arg(long, value_name = "FILE")]
+        save_results: Option<PathBuf>,
+
+        /// Carry the decisions in an annotated results file forward to unchanged matches
+        #[arg(long, value_name = "FILE")]
+        dispositions: Option<PathBuf>,
+
+        /// Also write the text extracted from the document to FILE; the `offset` of each JSON occurrence counts characters in it
+        #[arg(long, value_name = "FILE")]
+        dump_text: Option<PathBuf>,
+
+        /// For programs: print only versioned JSON results on stdout and NDJSON progress events on stderr
+        #[arg(long, value_enum, value_name = "VERSION")]
+        api: Option<ApiVersion>,
+
+        #[command(flatten)]
+        excerpts: ExcerptLimits,
+    },
+
+    /// Check that required needles appear in a document; exits 1 when they do not
+    Assert {
+        /// Needles that have to be present
+        #[arg(short, long)]
+        needles: PathBuf,
+
+        /// Document to check
+        document: PathBuf,
+
+        /// Pass only when every needle is present (the default)
+        #[arg(long, conflicts_with = "any")]
+        all: bool,
+
+        /// Pass when at least one needle is present
+        #[arg(long)]
+        any: bool,
+
+        #[command(flatten)]
+        settings: SearchSettings,
     },
-    
+
     /// Batch process multiple files
     Batch {
-        /// Directory containing documents
-        #[arg(short, long)]
-        directory: String,
-        
+        #[command(flatten)]
+        inputs: BatchInputs,
+
         /// Path to needles file
         #[arg(short, long)]
         needles_file: String,
-        
-        /// File pattern (e.g., "*.pdf", "*.docx")
-        #[arg(short, long, default_value = "*.*")]
-        pattern: String,
-        
-        /// Recursive search
+
+        /// Ignore .docsearcher-needles.csv files found while scanning
+        #[arg(long)]
+        no_local_needles: bool,
+
+        #[command(flatten)]
+        settings: SearchSettings,
+
+        /// Number of threads extracting and matching documents (default: number of cores)
+        #[arg(short = 'j', long, visible_aliases = ["jobs", "workers"])]
+        threads: Option<usize>,
+
+        /// Number of threads reading documents from disk
+        #[arg(long, default_value_t = 2)]
+        io_threads: usize,
+
+        /// Treat a needles file without needles as a search with no matches (exit 1) instead of an error
+        #[arg(long)]
+        allow_empty_needles: bool,
+
+        /// Append this run's results to a SQLite database (requires the `sqlite` feature)
+        #[arg(long, value_name = "DB")]
+        store_results: Option<PathBuf>,
+
+        /// Use a compiled needles file even if it was built for other matching options
+        #[arg(long)]
+        force: bool,
+
+        /// Write an HTML report (index.html plus one page per document) into DIR
+        #[arg(long, value_name = "DIR")]
+        report_dir: Option<PathBuf>,
+
+        /// Also write the results as JSON to FILE, for `triage`
+        #[arg(long, value_name = "FILE")]
+        save_results: Option<PathBuf>,
+
+        /// Carry the decisions in an annotated results file forward to unchanged matches
+        #[arg(long, value_name = "FILE")]
+        dispositions: Option<PathBuf>,
+
+        /// For programs: print only versioned JSON results on stdout and NDJSON progress events on stderr
+        #[arg(long, value_enum, value_name = "VERSION")]
+        api: Option<ApiVersion>,
+
+        /// Occurrences to hold in memory before further files' occurrences are written to disk
+        #[arg(long, value_name = "N", default_value_t = spill::DEFAULT_THRESHOLD)]
+        spill_threshold: usize,
+
+        /// Directory for spilled occurrences (default: the system temporary directory)
+        #[arg(long, value_name = "DIR")]
+        spill_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        excerpts: ExcerptLimits,
+    },
+
+    /// List the documents a batch run would search, without searching them
+    Scan {
+        #[command(flatten)]
+        inputs: BatchInputs,
+
+        /// List at most this many files; the counts still cover every file
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
+    },
+
+    /// Show how one needle is matched, or missed, in a document, stage by stage
+    Explain {
+        /// Needles file the term comes from
         #[arg(short, long)]
-        recursive: bool,
-        
-        /// Output format
+        needles: PathBuf,
+
+        /// Document to search
+        #[arg(short, long)]
+        document: PathBuf,
+
+        /// The needle to explain, as written in the needles file
+        #[arg(long)]
+        term: String,
+
+        /// Only explain this line (1-based, as shown by explain and triage)
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        line: Option<u64>,
+
+        #[command(flatten)]
+        settings: SearchSettings,
+    },
+
+    /// Step through saved results and record a decision for each match
+    Triage {
+        /// Results written with --save-results
+        results: PathBuf,
+
+        /// Where to write the annotated results (default: RESULTS with a .triage.json extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Benchmark the batch pipeline on a generated corpus
+    Bench {
+        /// Number of needles to search for
+        #[arg(long, default_value_t = 1000)]
+        needles: usize,
+
+        /// Number of documents to generate (alternating DOCX and PDF)
+        #[arg(long, default_value_t = 50)]
+        files: usize,
+
+        /// Pages of text per document
+        #[arg(long, default_value_t = 10)]
+        pages: usize,
+
+        /// Seed for the corpus generator; the same seed gives the same corpus
+        #[arg(long, default_value_t = 7)]
+        seed: u64,
+
+        /// Number of threads extracting and matching documents (default: number of cores)
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Number of threads reading documents from disk
+        #[arg(long, default_value_t = 2)]
+        io_threads: usize,
+
+        /// How needles are looked up: each on its own, all at once with an automaton, or whichever suits the corpus
+        #[arg(long, value_enum, default_value_t = MatcherChoice::Auto)]
+        matcher: MatcherChoice,
+
+        /// Output format (text or json)
         #[arg(short, long, default_value = "text")]
         format: String,
+
+        /// Compare against a report saved with --format json
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+
+        /// Fail when a rate drops by more than this much below the baseline
+        #[arg(long, value_parser = bench::parse_percent, default_value = "10%")]
+        max_regression: f64,
+    },
+
+    /// Work with needles files
+    Needles {
+        #[command(subcommand)]
+        command: NeedlesCommands,
+    },
+
+    /// Query a results database written with --store-results
+    #[cfg(feature = "sqlite")]
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
     },
-    
+
     /// Validate files without searching
     Validate {
         /// Path to needles file
         needles: PathBuf,
-        
+
         /// Path to document file
         document: PathBuf,
+
+        /// List the needles this stop-word file would drop
+        #[arg(long, value_name = "FILE")]
+        stopwords: Option<PathBuf>,
+
+        /// Which needles --stopwords drops
+        #[arg(long, value_enum, default_value_t = StopwordMode::Exact)]
+        stopword_mode: StopwordMode,
+
+        /// Character separating term and metadata, `\t` for a tab (default: detected)
+        #[arg(long, value_name = "CHAR")]
+        needle_delimiter: Option<NeedleDelimiter>,
     },
-    
+
     /// Show file information
     Info {
         /// Path to document file
@@ -124,616 +389,6540 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum NeedlesCommands {
+    /// Save a needles file in the compiled `.nidx` format, so later searches skip CSV parsing
+    ///
+    /// The file holds the parsed needles, their weights and the matching
+    /// options; the lookup automaton is still built on every search.
+    Compile {
+        /// Needles file to compile
+        needles: PathBuf,
+
+        /// Where to write the compiled needles
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Compile for case sensitive search
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// Compile for whole word matching
+        #[arg(long)]
+        whole_word: bool,
+
+        /// Compile for matching that ignores accents
+        #[arg(long)]
+        ignore_accents: bool,
+
+        /// Compile for matching without Unicode normalization
+        #[arg(long)]
+        no_normalize: bool,
+
+        /// Compile for matching every needle as a regular expression
+        #[arg(long)]
+        regex_needles: bool,
+
+        /// Compile for ignoring case the way this says
+        #[arg(long, value_enum, default_value_t = CaseFolding::Unicode)]
+        case_fold: CaseFolding,
+
+        /// Character separating term and metadata, `\t` for a tab (default: detected)
+        #[arg(long, value_name = "CHAR")]
+        needle_delimiter: Option<NeedleDelimiter>,
+    },
+
+    /// Combine needle lists into one with a needle per term, noting where each came from
+    Merge {
+        /// Needles files to merge (delimited, .json or compiled), in order of precedence
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Which needle to keep when a term has different metadata in two places
+        #[arg(long, value_enum, default_value_t = OnDuplicate::First)]
+        on_duplicate: OnDuplicate,
+
+        /// Character separating term and metadata, `\t` for a tab (default: detected)
+        #[arg(long, value_name = "CHAR")]
+        needle_delimiter: Option<NeedleDelimiter>,
+
+        #[command(flatten)]
+        output: ListOutput,
+    },
+
+    /// Drop repeated terms from a needle list
+    Dedupe {
+        /// Needles file to clean up
+        needles: PathBuf,
+
+        /// Which needle to keep when a term has different metadata in two places
+        #[arg(long, value_enum, default_value_t = OnDuplicate::First)]
+        on_duplicate: OnDuplicate,
+
+        /// Character separating term and metadata, `\t` for a tab (default: detected)
+        #[arg(long, value_name = "CHAR")]
+        needle_delimiter: Option<NeedleDelimiter>,
+
+        #[command(flatten)]
+        output: ListOutput,
+    },
+
+    /// Show the needles added, removed or given other metadata between two lists
+    Diff {
+        /// The earlier list
+        old: PathBuf,
+
+        /// The later list
+        new: PathBuf,
+
+        /// Character separating term and metadata, `\t` for a tab (default: detected)
+        #[arg(long, value_name = "CHAR")]
+        needle_delimiter: Option<NeedleDelimiter>,
+    },
+
+    /// Rewrite a needle list in another format or with another delimiter
+    Convert {
+        /// Needles file to convert
+        needles: PathBuf,
+
+        /// Character separating term and metadata, `\t` for a tab (default: detected)
+        #[arg(long, value_name = "CHAR")]
+        needle_delimiter: Option<NeedleDelimiter>,
+
+        #[command(flatten)]
+        output: ListOutput,
+    },
+}
+
+/// Where and how the `needles` commands write a list
+#[derive(Clone, Debug, clap::Args)]
+struct ListOutput {
+    /// Write the list to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Format to write (default: json for a .json output file, else csv)
+    #[arg(long, value_enum)]
+    to: Option<ListFormat>,
+
+    /// Character separating term and metadata in the list written (default: the input's, else a comma)
+    #[arg(long, value_name = "CHAR")]
+    output_delimiter: Option<NeedleDelimiter>,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Run a canned query, or raw read-only SQL with --sql
+    Query {
+        /// Path to the results database
+        database: PathBuf,
+
+        /// Canned query to run
+        #[arg(value_enum, required_unless_present = "sql")]
+        query: Option<CannedQuery>,
+
+        /// File to report on (for file-history)
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Raw SQL to run; statements that modify the database are rejected
+        #[arg(long, conflicts_with = "query")]
+        sql: Option<String>,
+    },
+}
+
 pub struct CliApp {
     cli: EnhancedCli,
 }
 
-impl CliApp {
-    pub fn new() -> Self {
-        Self {
-            cli: EnhancedCli::parse(),
+/// Exit code for runs that failed with an error
+pub const EXIT_ERROR: i32 = 2;
+
+/// How a successful run ended; like grep, searches exit 0 when something
+/// matched and 1 when nothing did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    NoMatches,
+    /// `assert` found required needles missing
+    AssertionFailed,
+    /// Ctrl-C stopped a batch, which reported what it had found
+    Interrupted,
+}
+
+impl Outcome {
+    fn from_matches(found: bool) -> Self {
+        if found {
+            Outcome::Success
+        } else {
+            Outcome::NoMatches
         }
     }
 
-    pub fn run() -> Result<()> {
-        let app = Self::new();
-        
-        match app.cli.command.as_ref() {
-            Some(Commands::Interactive) => Self::run_interactive(),
-            Some(Commands::Tui) => Self::run_tui(),
-            Some(Commands::Search { needles, document, format: _format, case_sensitive: _case_sensitive, whole_word: _whole_word }) => {
-                Self::run_search(needles, document, *_case_sensitive, *_whole_word, _format)
-            }
-            Some(Commands::Batch { directory, needles_file, pattern: _pattern, recursive: _recursive, format }) => {
-                let directory_path = PathBuf::from(directory);
-                let needles_path = PathBuf::from(needles_file);
-                Self::run_batch(&needles_path, &directory_path, false, false, &format)
-            }
-            Some(Commands::Validate { needles, document }) => {
-                Self::run_validate(Some(&needles), Some(&document))
-            }
-            Some(Commands::Info { file: _file }) => {
-                Self::run_info()
-            }
-            None => {
-                if app.cli.tui {
-                    Self::run_tui()
-                } else if app.cli.interactive {
-                    Self::run_interactive()
-                } else if let (Some(needles), Some(document)) = (&app.cli.needles, &app.cli.document) {
-                    Self::run_search(&needles, &document, app.cli.case_sensitive, app.cli.whole_word, &app.cli.format)
-                } else {
-                    Self::show_help();
-                    Ok(())
-                }
-            }
+    /// The process exit code for this outcome
+    pub fn code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::NoMatches | Outcome::AssertionFailed => 1,
+            Outcome::Interrupted => EXIT_INTERRUPTED,
         }
     }
+}
 
-    fn run_interactive() -> Result<()> {
-        Self::show_startup_logo();
-        
-        println!("{}", "Interactive Mode".bold().blue());
-        println!("{}", "=================".blue());
-        
-        let search_terms = Self::get_search_terms_interactive()?;
-        let target_files = Self::get_target_files_interactive()?;
-        let (_case_sensitive, _whole_word) = Self::get_search_options_interactive()?;
-        
-        println!("\n{}", "Starting search...".green());
-        
-        for (term, metadata) in &search_terms {
-            println!("Searching for: {} ({})", term.cyan(), metadata.yellow());
-            
-            for file_path in &target_files {
-                if let Ok(file_type) = parse_filetype(&file_path.to_string_lossy()) {
-                    let results = match file_type {
-                        FileType::Docx => parse_docx_from_path("contacts.csv", &file_path.to_string_lossy())?,
-                        FileType::Pdf => parse_pdf_from_path("contacts.csv", &file_path.to_string_lossy())?,
-                    };
-                    
-                    if !results.is_empty() {
-                        println!("  Found {} matches in {}", results.len().to_string().green(), file_path.display());
-                        for (found_term, found_metadata) in results {
-                            println!("    {} -> {}", found_term.cyan(), found_metadata.yellow());
-                        }
-                    }
-                }
-            }
+/// Which needles `assert` requires
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum AssertMode {
+    All,
+    Any,
+}
+
+/// Records `search` queues for the NDJSON writer before waiting on it
+const RECORD_QUEUE_BOUND: usize = 64;
+
+/// Characters of a long line shown either side of a match in triage
+const SNIPPET_CONTEXT: usize = 80;
+
+/// Characters of the snippet shown around a match in results, unless
+/// `--context` says how many to show either side
+const CONTEXT_SNIPPET_CHARS: usize = 80;
+
+/// How much of its line the context snippet of a result shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SnippetWidth {
+    /// Up to this many characters in all, centred on the match
+    Centred(usize),
+    /// This many characters either side of the match, from `--context`
+    Around(usize),
+}
+
+impl SnippetWidth {
+    /// The width `settings` ask for; `None` with `--no-context`
+    fn of(settings: &SearchSettings) -> Option<Self> {
+        match (settings.no_context, settings.context) {
+            (true, _) => None,
+            (false, Some(chars)) => Some(SnippetWidth::Around(chars)),
+            (false, None) => Some(SnippetWidth::Centred(CONTEXT_SNIPPET_CHARS)),
         }
-        
-        Ok(())
     }
-    
-    fn run_tui() -> Result<()> {
-        let mut tui_app = TuiApp::default();
-        tui_app.run()
+}
+
+/// Options shared by the search and batch commands
+#[derive(Default)]
+struct CommandOptions {
+    settings: SearchSettings,
+    /// The parsed `--where` filters of `settings`
+    filters: Vec<(String, String)>,
+    /// The `--stopwords` list of `settings`
+    stopwords: Stopwords,
+    force: bool,
+    store_results: Option<PathBuf>,
+    report_dir: Option<PathBuf>,
+    allow_empty_needles: bool,
+    save_results: Option<PathBuf>,
+    /// Where `--dump-text` writes the extracted text of the document
+    dump_text: Option<PathBuf>,
+    /// Decisions read with `--dispositions`
+    dispositions: Dispositions,
+    /// The `--api` run this is part of
+    api: Option<Arc<ApiSession>>,
+    /// Caps on the excerpts of `--format html-full`
+    excerpts: ExcerptLimits,
+}
+
+/// A searched document for `--store-results`: the file, the archive entries
+/// leading to the document, and what was found in it
+type StoredDocument = (PathBuf, Vec<String>, Result<SearchResults, String>);
+
+/// One `--collapse-repeats` row: the file (batch mode) and a group of repeats
+type RepeatRow = (Option<PathBuf>, RepeatGroup);
+
+/// One result: its JSON record, carrying any decision from `--dispositions`,
+/// the contexts it was found in, and the local needles list it came from
+/// (`None` for the global list)
+struct ResultRow {
+    record: MatchRecord,
+    contexts: Vec<ContextKind>,
+    source: Option<PathBuf>,
+}
+
+/// Results handed to the formatters a row at a time, so a batch run's rows
+/// need not all be in memory at once
+trait Rows {
+    /// Call `f` with each row in order, stopping at the first error
+    fn try_for_each(&self, f: &mut dyn FnMut(&ResultRow) -> Result<()>) -> Result<()>;
+}
+
+impl Rows for [ResultRow] {
+    fn try_for_each(&self, f: &mut dyn FnMut(&ResultRow) -> Result<()>) -> Result<()> {
+        self.iter().try_for_each(f)
     }
-    
-    fn run_search(needles: &PathBuf, document: &PathBuf, _case_sensitive: bool, _whole_word: bool, format: &str) -> Result<()> {
-        println!("{}", "Search Mode".bold().blue());
-        println!("{}", "=============".blue());
-        
-        if !needles.exists() {
-            return Err(anyhow::anyhow!("Needles file not found: {}", needles.display()));
+}
+
+/// The rows of a batch run, made a file at a time from its outcomes and
+/// reading back occurrences that were spilled to disk
+struct BatchRows<'a> {
+    outcomes: &'a [FileOutcome],
+    scopes: &'a NeedleScopes,
+    options: &'a CommandOptions,
+}
+
+impl BatchRows<'_> {
+    /// What was found in a file, `None` when it could not be searched
+    fn matches(&self, outcome: &FileOutcome) -> Option<Result<MatchSet>> {
+        let occurrences = outcome.load_occurrences()?;
+        Some(
+            occurrences.map(|occurrences| {
+                MatchSet::new(occurrences, &self.options.settings.ignore_context)
+            }),
+        )
+    }
+
+    /// Whether `row` is reported: always, unless `--dedup` keeps only the
+    /// first row of each needle, noting those in `seen`
+    fn first_time(&self, seen: &mut HashSet<(String, String)>, row: &ResultRow) -> bool {
+        !self.options.settings.dedup
+            || seen.insert((row.record.term.clone(), row.record.metadata.clone()))
+    }
+
+    /// The rows for file `index`
+    fn file_rows(&self, index: usize, outcome: &FileOutcome, matches: &MatchSet) -> Vec<ResultRow> {
+        CliApp::result_rows(
+            &outcome.path,
+            matches,
+            self.options,
+            |result| self.scopes.source(index, result).map(Path::to_path_buf),
+            |result| self.scopes.weight(index, result),
+        )
+    }
+}
+
+impl Rows for BatchRows<'_> {
+    fn try_for_each(&self, f: &mut dyn FnMut(&ResultRow) -> Result<()>) -> Result<()> {
+        let mut seen = HashSet::new();
+        for (index, outcome) in self.outcomes.iter().enumerate() {
+            let Some(matches) = self.matches(outcome) else {
+                continue;
+            };
+            self.file_rows(index, outcome, &matches?)
+                .iter()
+                .filter(|row| self.first_time(&mut seen, row))
+                .try_for_each(&mut *f)?;
         }
-        
-        if !document.exists() {
-            return Err(anyhow::anyhow!("Document file not found: {}", document.display()));
-        }
-        
-        let search_terms = read_needles_from_file(&needles.to_string_lossy())?;
-        let file_type = parse_filetype(&document.to_string_lossy())?;
-        
-        println!("Searching for {} terms in {}", search_terms.len(), document.display());
-        
-        let results = match file_type {
-            FileType::Docx => parse_docx_from_path(&needles.to_string_lossy(), &document.to_string_lossy())?,
-            FileType::Pdf => parse_pdf_from_path(&needles.to_string_lossy(), &document.to_string_lossy())?,
-        };
-        
-        Self::display_results(&results, format, std::time::Duration::from_secs(0))
-    }
-    
-    fn run_batch(needles: &PathBuf, directory: &PathBuf, case_sensitive: bool, whole_word: bool, format: &str) -> Result<()> {
-        println!("{}", "Batch Mode".bold().blue());
-        println!("{}", "===========".blue());
-        
-        if !needles.exists() {
-            return Err(anyhow::anyhow!("Needles file not found: {}", needles.display()));
-        }
-        
-        if !directory.exists() || !directory.is_dir() {
-            return Err(anyhow::anyhow!("Directory not found: {}", directory.display()));
-        }
-        
-        let search_terms = read_needles_from_file(&needles.to_string_lossy())?;
-        let files = Self::scan_directory(directory, "*.*", false)?;
-        
-        println!("Found {} files to process", files.len());
-        
-        Self::run_batch_search(&search_terms, &files, case_sensitive, whole_word, format)
-    }
-    
-    fn run_validate(needles: Option<&PathBuf>, document: Option<&PathBuf>) -> Result<()> {
-        println!("{}", "Validation Mode".bold().blue());
-        println!("{}", "=================".blue());
-        
-        let needles_valid = Self::validate_needles_file(needles);
-        let document_valid = Self::validate_document_file(document);
-        
-        println!("{}", "Validation Results:".bold());
-        println!("Needles file: {}", if needles_valid { "✓ Valid".green() } else { "✗ Invalid".red() });
-        println!("Document file: {}", if document_valid { "✓ Valid".green() } else { "✗ Invalid".red() });
-        
         Ok(())
     }
-    
-    fn run_info() -> Result<()> {
-        println!("{}", "File Information".bold().blue());
-        println!("{}", "==================".blue());
-        
-        let file = Self::get_document_path_interactive()?;
-        if !file.exists() {
-            eprintln!("{}", format!("File not found: {}", file.display()).red());
-            return Ok(());
+}
+
+/// The JSON records of some rows, made as they are serialized
+struct Records<'a, R: ?Sized> {
+    rows: &'a R,
+    columns: &'a [String],
+    batch: bool,
+    /// Add the context snippet of each record, this wide
+    snippets: Option<SnippetWidth>,
+    /// Write each record's keys in sorted order, as a `serde_json::Value`
+    /// does, rather than in field order
+    sorted: bool,
+}
+
+impl<'a, R: Rows + ?Sized> Records<'a, R> {
+    fn sorted(
+        rows: &'a R,
+        columns: &'a [String],
+        batch: bool,
+        snippets: Option<SnippetWidth>,
+    ) -> Self {
+        Records {
+            rows,
+            columns,
+            batch,
+            snippets,
+            sorted: true,
         }
-        
-        if let Ok(file_type) = parse_filetype(&file.to_string_lossy()) {
-            println!("File: {}", file.display());
-            println!("Type: {}", match file_type {
-                FileType::Docx => "DOCX Document".blue(),
-                FileType::Pdf => "PDF Document".red(),
-            });
-            println!("Size: {} bytes", file.metadata()?.len());
-        } else {
-            eprintln!("{}", "Unsupported file type".red());
+    }
+}
+
+impl<R: Rows + ?Sized> serde::Serialize for Records<'_, R> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::{Error, SerializeSeq};
+
+        let mut seq = serializer.serialize_seq(None)?;
+        let mut failed = None;
+        let written = self.rows.try_for_each(&mut |row| {
+            let record = CliApp::match_record(row, self.columns, self.batch, self.snippets);
+            let element = match self.sorted {
+                true => serde_json::to_value(&record)
+                    .map_err(S::Error::custom)
+                    .and_then(|value| seq.serialize_element(&value)),
+                false => seq.serialize_element(&record),
+            };
+            element.map_err(|e| {
+                failed = Some(e);
+                anyhow::anyhow!("record not serialized")
+            })
+        });
+        if let Some(e) = failed {
+            return Err(e);
         }
-        
-        Ok(())
+        written.map_err(S::Error::custom)?;
+        seq.end()
     }
+}
 
-    fn get_search_terms_interactive() -> Result<Vec<(String, String)>> {
-        let options = &[
-            "Enter search terms manually",
-            "Import from file",
-            "Use sample terms",
-        ];
-        
-        let choice = Select::new()
-            .with_prompt("How would you like to input search terms?")
-            .default(0)
-            .items(options)
-            .interact()?;
-        
-        match choice {
-            0 => {
-                let terms_input: String = Input::new()
-                    .with_prompt("Enter search terms (separated by commas, e.g., term1,metadata1,term2,metadata2)")
-                    .interact_text()?;
-                
-                Ok(terms_input.split(',')
-                    .map(|s| {
-                        let parts: Vec<&str> = s.trim().splitn(2, ',').collect();
-                        if parts.len() == 2 {
-                            (parts[0].to_string(), parts[1].to_string())
-                        } else {
-                            (parts[0].to_string(), "".to_string())
-                        }
-                    })
-                    .collect())
+/// Counts shown at the top of batch results
+struct BatchSummary {
+    total_files: usize,
+    files_with_matches: usize,
+    /// Rows across every file
+    matched: usize,
+    /// Times a needle was found, across every file
+    occurrences: usize,
+    suppressed: usize,
+    /// Legacy `.doc` files that could not be searched
+    legacy_docs: usize,
+    /// Documents searched with a warning, such as one parsed as another type
+    warned: usize,
+    /// Files left unsearched because the run was stopped
+    stopped: usize,
+    run: RunInfo,
+}
+
+/// Report metadata: what a run searched with and the resources it used
+struct RunInfo {
+    needles: NeedleSelection,
+    matcher: MatcherSelection,
+    resources: ResourceUsage,
+    /// Ctrl-C stopped the run before every file was searched
+    interrupted: bool,
+}
+
+/// The needles a run searched
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+struct NeedleSelection {
+    /// The date needles had to be valid on (`--as-of`)
+    as_of: Date,
+    /// Needles left out because they were not valid on that date
+    inactive: usize,
+    /// Needles dropped by `--stopwords`
+    stopwords: usize,
+    /// The delimiter the needles file was read with; `None` for compiled needles
+    delimiter: Option<NeedleDelimiter>,
+}
+
+/// How a run looked its needles up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+struct MatcherSelection {
+    /// `--matcher`
+    requested: MatcherChoice,
+    /// The strategy it resolved to
+    strategy: Strategy,
+}
+
+impl MatcherSelection {
+    /// Resolve `requested` for `needles`, expecting documents the average
+    /// size of `files` on disk
+    fn resolve(requested: MatcherChoice, needles: &[OwnedNeedle], files: &[PathBuf]) -> Self {
+        let haystack_bytes = match requested {
+            MatcherChoice::Auto => {
+                let sizes: Vec<u64> = files
+                    .iter()
+                    .filter_map(|file| std::fs::metadata(file).ok())
+                    .map(|metadata| metadata.len())
+                    .collect();
+                (!sizes.is_empty()).then(|| sizes.iter().sum::<u64>() / sizes.len() as u64)
             }
-            1 => {
-                let file_path: String = Input::new()
-                    .with_prompt("Enter path to needles file")
-                    .default("contacts.csv".to_string())
-                    .interact_text()?;
-                
-                let needles = read_needles_from_file(&file_path)?;
-                Ok(needles)
+            _ => None,
+        };
+        MatcherSelection {
+            requested,
+            strategy: requested.resolve(needles, haystack_bytes),
+        }
+    }
+}
+
+/// Where the batch and scan commands find their documents
+#[derive(Clone, Debug, clap::Args)]
+struct BatchInputs {
+    /// Directory containing documents
+    #[arg(short, long)]
+    directory: Option<PathBuf>,
+
+    /// Documents to search in addition to the directory scan
+    files: Vec<PathBuf>,
+
+    /// Read further document paths from FILE, one per line
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// File pattern: "*.pdf" matches file names, "reports/**/*.docx" paths under the directory
+    ///
+    /// A pattern without `/` or `**` is matched against the file name only:
+    /// "*.pdf" selects the PDFs in the directory, or in every subdirectory
+    /// with --recursive.
+    ///
+    /// Any other pattern is matched against the path relative to the
+    /// directory, whether or not --recursive is given. `*` and `?` do not
+    /// cross `/`, and `**` matches any number of directories:
+    /// "archive/*.pdf" selects the PDFs directly in archive,
+    /// "reports/**/*.docx" every DOCX file below reports.
+    ///
+    /// Plain text files are only selected by a pattern ending in .txt, such
+    /// as "*.txt" or "logs/**/*.txt", so needles lists and notes next to the
+    /// documents are not searched.
+    #[arg(short, long, default_value = "*.*", verbatim_doc_comment)]
+    pattern: String,
+
+    /// Leave out files matching PATTERN, matched the same way as --pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Recursive search
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Include symlinked files and directories found while scanning
+    #[arg(long)]
+    follow_symlinks: bool,
+}
+
+impl CommandOptions {
+    /// Validate `settings` and start options from them
+    fn new(settings: &SearchSettings) -> Result<Self> {
+        settings.validate()?;
+        Ok(Self {
+            filters: settings.where_clauses()?,
+            stopwords: Self::read_stopwords(settings)?,
+            settings: settings.clone(),
+            ..Self::default()
+        })
+    }
+
+    /// The `--stopwords` list, empty when none is given
+    fn read_stopwords(settings: &SearchSettings) -> Result<Stopwords> {
+        settings
+            .stopwords
+            .as_deref()
+            .map(|path| Stopwords::read(path, settings.stopword_mode))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Add `--save-results` and the decisions from `--dispositions`
+    fn with_results_files(
+        self,
+        save_results: Option<&PathBuf>,
+        dispositions: Option<&PathBuf>,
+    ) -> Result<Self> {
+        Ok(Self {
+            save_results: save_results.cloned(),
+            dispositions: dispositions
+                .map(|path| Dispositions::load(path))
+                .transpose()?
+                .unwrap_or_default(),
+            ..self
+        })
+    }
+
+    /// Speak the `--api` protocol in `session`, which prints its own JSON:
+    /// other output formats and `--collapse-repeats` are refused
+    fn with_api(self, session: Option<&Arc<ApiSession>>) -> Result<Self> {
+        let Some(session) = session else {
+            return Ok(self);
+        };
+        if !matches!(
+            self.settings.format,
+            OutputFormat::Text | OutputFormat::Json
+        ) {
+            let name = self
+                .settings
+                .format
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "--api prints JSON; it cannot be combined with --format {}",
+                name
+            ));
+        }
+        if self.settings.collapse_repeats {
+            return Err(anyhow::anyhow!(
+                "--api cannot be combined with --collapse-repeats"
+            ));
+        }
+        Ok(Self {
+            api: Some(Arc::clone(session)),
+            ..self
+        })
+    }
+
+    /// Report `event` when running under `--api`
+    fn emit(&self, event: v1::Event) {
+        if let Some(api) = &self.api {
+            api.emit(&event);
+        }
+    }
+
+    /// Whether outputs get disposition and note columns
+    fn annotated(&self) -> bool {
+        !self.dispositions.is_empty()
+    }
+}
+
+impl Default for CliApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CliApp {
+    pub fn new() -> Self {
+        Self {
+            cli: EnhancedCli::parse(),
+        }
+    }
+
+    /// Run the command given on the command line
+    pub fn run() -> Result<Outcome> {
+        let app = Self::new();
+
+        if let Some(path) = &app.cli.log_file {
+            logging::init(&LogOptions {
+                path: path.clone(),
+                max_size: app.cli.log_max_size,
+                keep: app.cli.log_keep,
+            });
+        }
+
+        interrupt::install()?;
+        let invocation = std::env::args().collect::<Vec<_>>().join(" ");
+        let span = logging::run_span(&invocation);
+        let _guard = span.enter();
+        let start = std::time::Instant::now();
+
+        let result = Self::dispatch(&app);
+        logging::finish_run(
+            start.elapsed(),
+            result.as_ref().map_or(EXIT_ERROR, |outcome| outcome.code()),
+        );
+        result
+    }
+
+    /// Run the command, as an `--api` session when one is asked for
+    fn dispatch(app: &Self) -> Result<Outcome> {
+        let api = match app.cli.command.as_ref() {
+            Some(Commands::Search {
+                api: Some(version), ..
+            }) => Some((*version, v1::Command::Search)),
+            Some(Commands::Batch {
+                api: Some(version), ..
+            }) => Some((*version, v1::Command::Batch)),
+            _ => None,
+        };
+        let Some((version, command)) = api else {
+            return Self::run_command(app, None);
+        };
+
+        let (session, _diverted) = ApiSession::start(version, command);
+        let result = Self::run_command(app, Some(&session));
+        session.finish(
+            result.as_ref().err(),
+            result.as_ref().map_or(EXIT_ERROR, |outcome| outcome.code()),
+        )?;
+        result.map_err(|e| Reported(e).into())
+    }
+
+    fn run_command(app: &Self, api: Option<&Arc<ApiSession>>) -> Result<Outcome> {
+        match app.cli.command.as_ref() {
+            Some(Commands::Interactive) => {
+                Self::run_interactive(app.cli.quiet).map(|()| Outcome::Success)
             }
-            2 => {
-                Ok(vec![
-                    ("Alice Johnson".to_string(), "".to_string()),
-                    ("Bob Smith".to_string(), "".to_string()),
-                    ("Carol Davis".to_string(), "".to_string()),
-                ])
+            Some(Commands::Tui {
+                files,
+                preview_blocks,
+                preview_ms,
+                settings,
+            }) => {
+                let budget = PreviewBudget {
+                    blocks: *preview_blocks,
+                    time: std::time::Duration::from_millis(*preview_ms),
+                };
+                Self::run_tui(settings, files, budget).map(|()| Outcome::Success)
+            }
+            Some(Commands::Search {
+                needles,
+                document,
+                settings,
+                allow_empty_needles,
+                force,
+                swap_ok,
+                store_results,
+                save_results,
+                dispositions,
+                dump_text,
+                api: _,
+                excerpts,
+            }) => {
+                let options = CommandOptions {
+                    force: *force,
+                    dump_text: dump_text.clone(),
+                    store_results: store_results.clone(),
+                    allow_empty_needles: *allow_empty_needles,
+                    excerpts: *excerpts,
+                    ..CommandOptions::new(settings)?
+                }
+                .with_results_files(save_results.as_ref(), dispositions.as_ref())?
+                .with_api(api)?;
+                Self::run_search(needles, document, &options, *swap_ok)
+            }
+            Some(Commands::Assert {
+                needles,
+                document,
+                all: _,
+                any,
+                settings,
+            }) => {
+                let mode = if *any {
+                    AssertMode::Any
+                } else {
+                    AssertMode::All
+                };
+                // An empty needles file is refused by run_assert itself, with its own message
+                let options = CommandOptions {
+                    allow_empty_needles: true,
+                    ..CommandOptions::new(settings)?
+                };
+                Self::run_assert(needles, document, mode, &options)
+            }
+            Some(Commands::Batch {
+                inputs,
+                needles_file,
+                no_local_needles,
+                settings,
+                threads,
+                io_threads,
+                allow_empty_needles,
+                store_results,
+                force,
+                report_dir,
+                save_results,
+                dispositions,
+                api: _,
+                spill_threshold,
+                spill_dir,
+                excerpts,
+            }) => {
+                let needles_path = PathBuf::from(needles_file);
+                // Removed with its segments when the pipeline is dropped,
+                // however the run ends
+                let spill = Spill::new(spill_dir.as_deref(), *spill_threshold)?;
+                // The strategy is resolved once the needles are read
+                let pipeline = PipelineOptions {
+                    extract: settings.extract_options(Strategy::default())?,
+                    spill: Some(Arc::new(spill)),
+                    ..Self::pipeline_options(*threads, *io_threads)
+                };
+                let options = CommandOptions {
+                    force: *force,
+                    store_results: store_results.clone(),
+                    report_dir: report_dir.clone(),
+                    allow_empty_needles: *allow_empty_needles,
+                    excerpts: *excerpts,
+                    ..CommandOptions::new(settings)?
+                }
+                .with_results_files(save_results.as_ref(), dispositions.as_ref())?
+                .with_api(api)?;
+                Self::run_batch(
+                    &needles_path,
+                    inputs,
+                    !*no_local_needles,
+                    &options,
+                    &pipeline,
+                )
+            }
+            Some(Commands::Scan {
+                inputs,
+                limit,
+                format,
+            }) => Self::run_scan(inputs, *limit, format),
+            Some(Commands::Bench {
+                needles,
+                files,
+                pages,
+                seed,
+                threads,
+                io_threads,
+                matcher,
+                format,
+                baseline,
+                max_regression,
+            }) => {
+                let corpus = CorpusOptions {
+                    needles: *needles,
+                    files: *files,
+                    pages: *pages,
+                    seed: *seed,
+                };
+                let pipeline = Self::pipeline_options(*threads, *io_threads);
+                Self::run_bench(
+                    &corpus,
+                    pipeline,
+                    *matcher,
+                    format,
+                    baseline.as_deref(),
+                    *max_regression,
+                )
+                .map(|()| Outcome::Success)
+            }
+            Some(Commands::Explain {
+                needles,
+                document,
+                term,
+                line,
+                settings,
+            }) => Self::run_explain(
+                needles,
+                document,
+                term,
+                line.map(|line| line as usize),
+                &CommandOptions::new(settings)?,
+            ),
+            Some(Commands::Triage { results, output }) => {
+                let output = output
+                    .clone()
+                    .unwrap_or_else(|| triage::default_output(results));
+                Self::run_triage(results, &output).map(|()| Outcome::Success)
+            }
+            Some(Commands::Needles {
+                command:
+                    NeedlesCommands::Compile {
+                        needles,
+                        output,
+                        case_sensitive,
+                        whole_word,
+                        ignore_accents,
+                        no_normalize,
+                        regex_needles,
+                        case_fold,
+                        needle_delimiter,
+                    },
+            }) => {
+                let options = MatchOptions {
+                    case_sensitive: *case_sensitive,
+                    whole_word: *whole_word,
+                    ignore_accents: *ignore_accents,
+                    normalize: !*no_normalize,
+                    regex_needles: *regex_needles,
+                    case_fold: *case_fold,
+                };
+                Self::run_needles_compile(needles, output, options, *needle_delimiter)
+                    .map(|()| Outcome::Success)
+            }
+            Some(Commands::Needles {
+                command:
+                    NeedlesCommands::Merge {
+                        inputs,
+                        on_duplicate,
+                        needle_delimiter,
+                        output,
+                    },
+            }) => Self::run_needles_merge(inputs, *on_duplicate, *needle_delimiter, output)
+                .map(|()| Outcome::Success),
+            Some(Commands::Needles {
+                command:
+                    NeedlesCommands::Dedupe {
+                        needles,
+                        on_duplicate,
+                        needle_delimiter,
+                        output,
+                    },
+            }) => Self::run_needles_dedupe(needles, *on_duplicate, *needle_delimiter, output)
+                .map(|()| Outcome::Success),
+            Some(Commands::Needles {
+                command:
+                    NeedlesCommands::Diff {
+                        old,
+                        new,
+                        needle_delimiter,
+                    },
+            }) => Self::run_needles_diff(old, new, *needle_delimiter).map(|()| Outcome::Success),
+            Some(Commands::Needles {
+                command:
+                    NeedlesCommands::Convert {
+                        needles,
+                        needle_delimiter,
+                        output,
+                    },
+            }) => {
+                let list = NeedleList::read(needles, *needle_delimiter)?;
+                let lines: Vec<ListLine> =
+                    list.needles.iter().cloned().map(ListLine::Needle).collect();
+                Self::write_needle_list(&lines, output, list.delimiter())?;
+                Self::report_list_written(output, || {
+                    format!(
+                        "Converted {} needles from {}",
+                        list.needles.len(),
+                        needles.display()
+                    )
+                });
+                Ok(Outcome::Success)
+            }
+            #[cfg(feature = "sqlite")]
+            Some(Commands::Db {
+                command:
+                    DbCommands::Query {
+                        database,
+                        query,
+                        file,
+                        sql,
+                    },
+            }) => Self::run_db_query(database, *query, file.as_deref(), sql.as_deref())
+                .map(|()| Outcome::Success),
+            Some(Commands::Validate {
+                needles,
+                document,
+                stopwords,
+                stopword_mode,
+                needle_delimiter,
+            }) => {
+                let stopwords = stopwords
+                    .as_deref()
+                    .map(|path| Stopwords::read(path, *stopword_mode))
+                    .transpose()?;
+                Self::run_validate(
+                    Some(needles),
+                    Some(document),
+                    stopwords.as_ref(),
+                    *needle_delimiter,
+                )
+                .map(|()| Outcome::Success)
+            }
+            Some(Commands::Info { file }) => Self::run_info(file).map(|()| Outcome::Success),
+            None => {
+                if app.cli.tui {
+                    Self::run_tui(&app.cli.settings, &[], PreviewBudget::default())
+                        .map(|()| Outcome::Success)
+                } else if app.cli.interactive {
+                    Self::run_interactive(app.cli.quiet).map(|()| Outcome::Success)
+                } else if let (Some(needles), Some(document)) =
+                    (&app.cli.needles, &app.cli.document)
+                {
+                    Self::run_search(
+                        needles,
+                        document,
+                        &CommandOptions::new(&app.cli.settings)?,
+                        false,
+                    )
+                } else {
+                    Self::show_help();
+                    Ok(Outcome::Success)
+                }
             }
-            _ => unreachable!(),
         }
     }
 
-    fn get_target_files_interactive() -> Result<Vec<PathBuf>> {
-        let options = &[
-            "Select individual files",
-            "Select directory with pattern",
-            "Use current directory",
-        ];
-        
-        let choice = Select::new()
-            .with_prompt("How would you like to select target files?")
-            .default(0)
-            .items(options)
-            .interact()?;
-        
-        match choice {
-            0 => {
-                let files_input: String = Input::new()
-                    .with_prompt("Enter file paths (separated by spaces)")
-                    .interact_text()?;
-                
-                Ok(files_input.split_whitespace()
-                    .map(|s| PathBuf::from(s.trim()))
-                    .collect())
+    /// Prompt for terms, files and options and search; `quiet` leaves out
+    /// the progress of each file
+    fn run_interactive(quiet: bool) -> Result<()> {
+        Self::show_startup_logo();
+
+        outln!("{}", "Interactive Mode".bold().blue());
+        outln!("{}", "=================".blue());
+
+        let search_terms = Self::get_search_terms_interactive()?;
+        let target_files = Self::get_target_files_interactive()?;
+        let settings = Self::get_search_options_interactive()?;
+        let matcher = MatcherSelection::resolve(settings.matcher, &search_terms, &target_files);
+        let extract = settings.extract_options(matcher.strategy)?;
+
+        outln!("\n{}", "Starting search...".green());
+        for (term, metadata) in &search_terms {
+            outln!("Searching for: {} ({})", term.cyan(), metadata.yellow());
+        }
+
+        let mut reporter: Box<dyn ProgressReporter> = match quiet {
+            true => Box::new(NullReporter),
+            false => Box::new(PrintReporter::new()),
+        };
+        let (found, failures) =
+            Self::search_typed_terms(&search_terms, &target_files, extract, reporter.as_mut());
+        for (file_path, results) in found {
+            if !results.is_empty() {
+                outln!(
+                    "  Found {} matches in {}",
+                    results.len().to_string().green(),
+                    file_path.display()
+                );
+                for SearchResult { term, metadata, .. } in results {
+                    outln!("    {} -> {}", term.cyan(), metadata.yellow());
+                }
             }
-            1 => {
-                let dir_path: String = Input::new()
-                    .with_prompt("Enter directory path")
-                    .interact_text()?;
-                
-                let pattern: String = Input::new()
-                    .with_prompt("Enter file pattern (e.g., *.pdf)")
-                    .default("*.pdf".to_string())
-                    .interact_text()?;
-                
-                let files = Self::scan_directory(&PathBuf::from(dir_path.clone()), &pattern, false)?;
-                if files.is_empty() {
-                    return Err(anyhow::anyhow!("No files found in directory: {}", dir_path));
+        }
+        if !failures.is_empty() {
+            outln!(
+                "  {} files could not be searched",
+                failures.len().to_string().red()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Search each of `target_files` that has a supported extension for the
+    /// terms typed in interactive mode, telling `reporter` its progress; a
+    /// file that cannot be searched is skipped, as in batch mode, and
+    /// returned with the failures
+    fn search_typed_terms(
+        search_terms: &[(String, String)],
+        target_files: &[PathBuf],
+        extract: ExtractOptions,
+        reporter: &mut dyn ProgressReporter,
+    ) -> (Vec<(PathBuf, SearchResults)>, Vec<v1::Failure>) {
+        let mut found = Vec::new();
+        let mut failures = Vec::new();
+        for file_path in target_files {
+            if parse_filetype(file_path).is_ok() {
+                match parsers::search_with_progress(
+                    search_terms,
+                    &file_path.to_string_lossy(),
+                    extract,
+                    reporter,
+                ) {
+                    Ok(results) => found.push((file_path.clone(), results)),
+                    Err(e) => {
+                        tracing::debug!(file = %file_path.display(), error = %e, "file skipped");
+                        eprintln!(
+                            "{}",
+                            format!("Skipping {}: {}", file_path.display(), e).yellow()
+                        );
+                        failures.push(v1::Failure {
+                            file: file_path.to_string_lossy().into_owned(),
+                            error: e.to_string(),
+                        });
+                    }
                 }
-                let file = Select::new()
-                    .with_prompt("Select document file")
-                    .items(&files.iter().map(|f| f.to_string_lossy().to_string()).collect::<Vec<_>>())
-                    .interact()?;
-                Ok(vec![files[file].clone()])
             }
-            2 => {
-                let files = Self::scan_directory(&PathBuf::from("."), "*.*", false)?;
-                Ok(files)
+        }
+        (found, failures)
+    }
+
+    fn run_tui(
+        settings: &SearchSettings,
+        files: &[PathBuf],
+        preview_budget: PreviewBudget,
+    ) -> Result<()> {
+        settings.validate()?;
+        let mut tui_app = TuiApp {
+            selected_files: files
+                .iter()
+                .map(|file| file.to_string_lossy().into_owned())
+                .collect(),
+            preview_budget,
+            ..TuiApp::with_settings(settings.clone())
+        };
+        tui_app.run()
+    }
+
+    fn run_search(
+        needles: &Path,
+        document: &Path,
+        options: &CommandOptions,
+        swap_ok: bool,
+    ) -> Result<Outcome> {
+        let start = std::time::Instant::now();
+        let stream = Self::record_stream(options, RECORD_QUEUE_BOUND);
+        outln!("{}", "Search Mode".bold().blue());
+        outln!("{}", "=============".blue());
+
+        if !needles.exists() {
+            return Err(anyhow::anyhow!(
+                "Needles file not found: {}",
+                needles.display()
+            ));
+        }
+
+        if !document.exists() {
+            return Err(anyhow::anyhow!(
+                "Document file not found: {}",
+                document.display()
+            ));
+        }
+
+        if !swap_ok {
+            check_swapped_arguments(needles, document)?;
+        }
+
+        Self::check_store_supported(options)?;
+
+        let (search_terms, columns, selection) = Self::load_needles(needles, options)?;
+        if search_terms.is_empty() {
+            return Ok(Outcome::NoMatches);
+        }
+        parse_filetype(document)?;
+
+        outln!(
+            "Searching for {} terms in {}",
+            search_terms.len(),
+            document.display()
+        );
+        options.emit(v1::Event::Started {
+            command: v1::Command::Search,
+            files: 1,
+        });
+
+        let matcher = MatcherSelection::resolve(
+            options.settings.matcher,
+            &search_terms,
+            &[document.to_path_buf()],
+        );
+        let extract = options.settings.extract_options(matcher.strategy)?;
+        let extraction = parsers::parse_any(document, &search_terms, extract)?;
+        if let Some(path) = &options.dump_text {
+            let blocks = batch::extract_blocks(document, &std::fs::read(document)?, extract)?;
+            std::fs::write(path, extracted_text(&blocks))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        options.emit(v1::Event::FileDone {
+            file: document.to_string_lossy().into_owned(),
+            occurrences: extraction.occurrences.len(),
+        });
+        for warning in &extraction.warnings {
+            if options.api.is_none() {
+                eprintln!("{}", format!("Warning: {}", warning).yellow());
             }
-            _ => unreachable!(),
+            options.emit(v1::Event::Warning {
+                file: Some(document.to_string_lossy().into_owned()),
+                message: warning.clone(),
+            });
+        }
+        let bytes_read = std::fs::metadata(document).map_or(0, |metadata| metadata.len());
+        let run = RunInfo {
+            needles: selection,
+            matcher,
+            resources: ResourceUsage::collect(
+                1,
+                bytes_read,
+                extraction.text_bytes,
+                start.elapsed(),
+            ),
+            interrupted: false,
+        };
+        let matches = MatchSet::new(extraction.occurrences, &options.settings.ignore_context);
+
+        if let Some(db) = &options.store_results {
+            Self::store_results(
+                db,
+                needles,
+                search_terms.len(),
+                &[(document.to_path_buf(), Vec::new(), Ok(matches.results()))],
+            )?;
+        }
+
+        let weights = Self::load_weights(needles, &run.needles)?;
+        let rows = Self::result_rows(
+            document,
+            &matches,
+            options,
+            |_| None,
+            |result| {
+                weights
+                    .get(&(result.term.clone(), result.metadata.clone()))
+                    .copied()
+                    .unwrap_or_default()
+            },
+        );
+        let span = tracing::Span::current();
+        span.record("files", 1);
+        span.record("files_with_matches", usize::from(!rows.is_empty()));
+        span.record("matches", rows.len());
+
+        if let Some(stream) = &stream {
+            Self::stream_records(
+                stream,
+                &rows,
+                &columns,
+                false,
+                SnippetWidth::of(&options.settings),
+            );
+        }
+        match &options.api {
+            Some(api) => {
+                let summary = v1::Summary {
+                    files: 1,
+                    files_with_matches: usize::from(!rows.is_empty()),
+                    matches: rows.len(),
+                    failed: 0,
+                    suppressed: matches.suppressed,
+                };
+                api.print_report(&Self::api_report(
+                    api,
+                    &rows[..],
+                    &columns,
+                    Vec::new(),
+                    summary,
+                )?)?;
+            }
+            None => Self::display_results(&rows, &matches, options, &run, &columns)?,
+        }
+        Self::save_results(&rows[..], options, &run, &columns, false)?;
+        Self::finish_stream(stream)?;
+        Ok(Outcome::from_matches(!rows.is_empty()))
+    }
+
+    /// With `--format ndjson`, a writer that owns stdout until it is
+    /// finished; everything else printed meanwhile goes to stderr
+    fn record_stream(options: &CommandOptions, bound: usize) -> Option<RecordWriter> {
+        (options.settings.format == OutputFormat::Ndjson).then(|| RecordWriter::stdout(bound))
+    }
+
+    /// Queue one NDJSON line per row. Returns `false` once the reader of
+    /// stdout has gone away.
+    fn stream_records(
+        stream: &RecordWriter,
+        rows: &[ResultRow],
+        columns: &[String],
+        batch: bool,
+        snippets: Option<SnippetWidth>,
+    ) -> bool {
+        rows.iter().all(|row| {
+            let line = serde_json::to_string(&Self::match_record(row, columns, batch, snippets))
+                .expect("match records serialize");
+            stream.send(line)
+        })
+    }
+
+    /// Wait for streamed records to be written. A reader that exits early
+    /// (`| head`) is not an error.
+    fn finish_stream(stream: Option<RecordWriter>) -> Result<()> {
+        if let Some(stream) = stream {
+            let end = stream.finish()?;
+            if end.closed {
+                tracing::debug!(
+                    written = end.written,
+                    "stdout closed by its reader, search stopped"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the needles are present in `document`: misses are the
+    /// finding, so they are listed first and fail the run
+    fn run_assert(
+        needles: &Path,
+        document: &Path,
+        mode: AssertMode,
+        options: &CommandOptions,
+    ) -> Result<Outcome> {
+        let json = match options.settings.format {
+            OutputFormat::Text => false,
+            OutputFormat::Json => true,
+            format => {
+                return Err(anyhow::anyhow!(
+                    "assert prints text or json, not {:?}",
+                    format
+                ))
+            }
+        };
+        if !needles.exists() {
+            return Err(anyhow::anyhow!(
+                "Needles file not found: {}",
+                needles.display()
+            ));
+        }
+        if !document.exists() {
+            return Err(anyhow::anyhow!(
+                "Document file not found: {}",
+                document.display()
+            ));
+        }
+
+        let (mut search_terms, _, selection) = Self::load_needles(needles, options)?;
+        if search_terms.is_empty() {
+            // `--all` would pass vacuously
+            return Err(anyhow::anyhow!(
+                "Needles file {} contains no needles; there is nothing to assert",
+                needles.display()
+            ));
+        }
+        let mut seen = HashSet::new();
+        search_terms.retain(|needle| seen.insert(needle.clone()));
+
+        parse_filetype(document)?;
+        let matcher = MatcherSelection::resolve(
+            options.settings.matcher,
+            &search_terms,
+            &[document.to_path_buf()],
+        );
+        let extraction = parsers::parse_any(
+            document,
+            &search_terms,
+            options.settings.extract_options(matcher.strategy)?,
+        )?;
+        let matches = MatchSet::new(extraction.occurrences, &options.settings.ignore_context);
+
+        let mut counts: std::collections::HashMap<OwnedNeedle, usize> =
+            std::collections::HashMap::new();
+        for occurrence in &matches.occurrences {
+            *counts
+                .entry((occurrence.term.clone(), occurrence.metadata.clone()))
+                .or_insert(0) += 1;
+        }
+        let (present, missing): (Vec<&OwnedNeedle>, Vec<&OwnedNeedle>) = search_terms
+            .iter()
+            .partition(|needle| counts.contains_key(*needle));
+        let passed = match mode {
+            AssertMode::All => missing.is_empty(),
+            AssertMode::Any => !present.is_empty(),
+        };
+
+        if json {
+            let needle = |needle: &OwnedNeedle| serde_json::json!({ "term": needle.0, "metadata": needle.1 });
+            let present: Vec<serde_json::Value> = present
+                .iter()
+                .map(|found| {
+                    let mut value = needle(found);
+                    value["occurrences"] = counts[*found].into();
+                    value
+                })
+                .collect();
+            let report = serde_json::json!({
+                "document": document.to_string_lossy(),
+                "mode": mode,
+                "passed": passed,
+                "missing": missing.iter().map(|missed| needle(missed)).collect::<Vec<_>>(),
+                "present": present,
+                "needles": selection,
+            });
+            outln!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(if passed {
+                Outcome::Success
+            } else {
+                Outcome::AssertionFailed
+            });
+        }
+
+        outln!("{}", "Assert Mode".bold().blue());
+        outln!("{}", "===========".blue());
+        outln!("Document: {}", document.display());
+        Self::display_suppressed(matches.suppressed, &options.settings.ignore_context);
+        Self::display_selection(&selection);
+
+        outln!();
+        outln!("{}", format!("Missing ({}):", missing.len()).bold());
+        for (term, metadata) in &missing {
+            outln!("  {} {} → {}", "✗".red(), term.red().bold(), metadata);
+        }
+        outln!("{}", format!("Present ({}):", present.len()).bold());
+        for needle in &present {
+            outln!(
+                "  {} {} → {} ×{}",
+                "✓".green(),
+                needle.0,
+                needle.1,
+                counts[*needle]
+            );
+        }
+
+        outln!();
+        let total = search_terms.len();
+        match (mode, passed) {
+            (AssertMode::All, true) => outln!(
+                "{}",
+                format!("PASS: all {} needles present", total)
+                    .green()
+                    .bold()
+            ),
+            (AssertMode::All, false) => outln!(
+                "{}",
+                format!("FAIL: {} of {} needles missing", missing.len(), total)
+                    .red()
+                    .bold()
+            ),
+            (AssertMode::Any, true) => outln!(
+                "{}",
+                format!("PASS: {} of {} needles present", present.len(), total)
+                    .green()
+                    .bold()
+            ),
+            (AssertMode::Any, false) => outln!(
+                "{}",
+                format!("FAIL: none of {} needles present", total)
+                    .red()
+                    .bold()
+            ),
+        }
+
+        Ok(if passed {
+            Outcome::Success
+        } else {
+            Outcome::AssertionFailed
+        })
+    }
+
+    fn run_batch(
+        needles: &Path,
+        inputs: &BatchInputs,
+        local_needles: bool,
+        options: &CommandOptions,
+        pipeline: &PipelineOptions,
+    ) -> Result<Outcome> {
+        let stream = Self::record_stream(options, pipeline.channel_bound);
+        outln!("{}", "Batch Mode".bold().blue());
+        outln!("{}", "===========".blue());
+
+        if !needles.exists() {
+            return Err(anyhow::anyhow!(
+                "Needles file not found: {}",
+                needles.display()
+            ));
+        }
+
+        Self::check_store_supported(options)?;
+
+        let (search_terms, mut columns, mut selection) = Self::load_needles(needles, options)?;
+        if search_terms.is_empty() {
+            return Ok(Outcome::NoMatches);
+        }
+        let (files, duplicates) = Self::collect_batch_files(inputs)?;
+        if duplicates > 0 {
+            outln!("Collapsed {} duplicate paths", duplicates);
+        }
+
+        outln!("Found {} files to process", files.len());
+
+        let scopes = match &inputs.directory {
+            Some(root) if local_needles => {
+                let lists = ListOptions {
+                    delimiter: options.settings.needle_delimiter,
+                    stopwords: &options.stopwords,
+                    filters: &options.filters,
+                    as_of: selection.as_of,
+                };
+                NeedleScopes::discover(
+                    needles,
+                    search_terms,
+                    Self::load_weights(needles, &selection)?,
+                    root,
+                    &files,
+                    lists,
+                )?
+            }
+            _ => NeedleScopes::global(
+                needles,
+                search_terms,
+                Self::load_weights(needles, &selection)?,
+                files.len(),
+            ),
+        };
+        if !scopes.local_lists().is_empty() {
+            outln!(
+                "Using {} local needle lists ({} distinct needle sets)",
+                scopes.local_lists().len(),
+                scopes.set_count()
+            );
+            if options.settings.metadata_format == MetadataFormat::Kv {
+                let all: Vec<OwnedNeedle> = scopes
+                    .local_lists()
+                    .iter()
+                    .flat_map(|list| list.needles.iter().cloned())
+                    .chain(scopes.global_needles().iter().cloned())
+                    .collect();
+                columns = metadata_keys(&all);
+            }
+        }
+
+        // Fail before searching rather than leave a partial report behind
+        if let Some(dir) = &options.report_dir {
+            report::prepare_report_dir(dir, &files)?;
+        }
+
+        selection.inactive += scopes.inactive();
+        selection.stopwords += scopes.stopwords();
+
+        Self::run_batch_search(
+            &scopes, &files, options, pipeline, &columns, selection, stream,
+        )
+    }
+
+    /// List what `batch` would search with the same inputs
+    fn run_scan(inputs: &BatchInputs, limit: Option<usize>, format: &str) -> Result<Outcome> {
+        let (files, duplicates) = Self::collect_batch_files(inputs)?;
+        let summary = ScanSummary::new(&files, duplicates, inputs.directory.as_deref(), limit);
+
+        if format.eq_ignore_ascii_case("json") {
+            outln!("{}", serde_json::to_string_pretty(&summary)?);
+            return Ok(Outcome::from_matches(summary.total > 0));
+        }
+
+        outln!("{}", "Scan".bold().blue());
+        outln!("{}", "====".blue());
+        outln!("Found {} files", summary.total);
+        if summary.duplicates > 0 {
+            outln!("Collapsed {} duplicate paths", summary.duplicates);
+        }
+
+        for file in &summary.files {
+            outln!("  {}", file.display());
+        }
+        if summary.omitted > 0 {
+            outln!("{}", format!("  ... and {} more", summary.omitted).italic());
+        }
+
+        let print_counts = |title: &str, counts: &std::collections::BTreeMap<String, usize>| {
+            if counts.is_empty() {
+                return;
+            }
+            outln!();
+            outln!("{}", title.bold());
+            // Padded by display width, so CJK and emoji names line up too
+            let width = counts
+                .keys()
+                .map(|key| display_width(key))
+                .max()
+                .unwrap_or(0);
+            for (key, count) in counts {
+                outln!(
+                    "  {}{}  {}",
+                    key,
+                    " ".repeat(width - display_width(key)),
+                    count
+                );
+            }
+        };
+        let by_extension = summary
+            .by_extension
+            .iter()
+            .map(|(extension, count)| {
+                (
+                    if extension.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        format!(".{}", extension)
+                    },
+                    *count,
+                )
+            })
+            .collect();
+        print_counts("By extension:", &by_extension);
+        print_counts("By directory:", &summary.by_directory);
+
+        Ok(Outcome::from_matches(summary.total > 0))
+    }
+
+    fn pipeline_options(threads: Option<usize>, io_threads: usize) -> PipelineOptions {
+        let mut pipeline = PipelineOptions {
+            io_threads,
+            ..PipelineOptions::default()
+        };
+        if let Some(threads) = threads {
+            pipeline.cpu_threads = threads;
+            pipeline.channel_bound = threads * 2;
+        }
+        pipeline
+    }
+
+    fn run_bench(
+        corpus: &CorpusOptions,
+        mut pipeline: PipelineOptions,
+        matcher: MatcherChoice,
+        format: &str,
+        baseline: Option<&Path>,
+        max_regression: f64,
+    ) -> Result<()> {
+        let json = format.eq_ignore_ascii_case("json");
+        // Load the baseline first so a bad path fails before the long run
+        let baseline = baseline.map(BenchReport::load).transpose()?;
+
+        if !json {
+            outln!("{}", "Benchmark".bold().blue());
+            outln!("{}", "=========".blue());
+            outln!(
+                "Generating {} documents of {} pages with {} needles (seed {})...",
+                corpus.files,
+                corpus.pages,
+                corpus.needles,
+                corpus.seed
+            );
+        }
+
+        let generated = bench::generate_corpus(corpus)?;
+        let document_bytes = generated.text_bytes / generated.files.len().max(1) as u64;
+        pipeline.extract.strategy = matcher.resolve(&generated.needles, Some(document_bytes));
+        let metrics = bench::run(&generated, &pipeline);
+        let comparison = baseline
+            .map(|baseline| bench::compare(&metrics, &baseline.metrics, max_regression))
+            .unwrap_or_default();
+        let report = BenchReport {
+            metrics,
+            comparison,
+        };
+
+        if json {
+            outln!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            let metrics = &report.metrics;
+            outln!(
+                "Threads: {} CPU, {} IO",
+                metrics.cpu_threads,
+                metrics.io_threads
+            );
+            outln!("Needle lookup: {}", metrics.strategy);
+            outln!("Elapsed: {:.3} s", metrics.elapsed_secs);
+            outln!(
+                "Files: {} ({} failed), {:.1} files/s",
+                corpus.files,
+                metrics.failed_files,
+                metrics.files_per_sec
+            );
+            outln!(
+                "Text extracted: {:.2} MB, {:.2} MB/s",
+                metrics.text_bytes as f64 / 1e6,
+                metrics.mb_per_sec
+            );
+            outln!(
+                "Matches: {}, {:.1} matches/s",
+                metrics.matches,
+                metrics.matches_per_sec
+            );
+            match metrics.peak_rss_bytes {
+                Some(rss) => outln!("Peak RSS: {:.1} MB", rss as f64 / 1e6),
+                None => outln!("Peak RSS: not available on this platform"),
+            }
+
+            if !report.comparison.is_empty() {
+                outln!();
+                outln!("Against baseline (max regression {}%):", max_regression);
+                for change in &report.comparison {
+                    let line = format!(
+                        "  {}: {:.2} → {:.2} ({:+.1}%)",
+                        change.metric, change.baseline, change.current, change.change_pct
+                    );
+                    outln!(
+                        "{}",
+                        if change.regressed {
+                            line.red()
+                        } else {
+                            line.green()
+                        }
+                    );
+                }
+            }
+        }
+
+        if report.regressed() {
+            return Err(anyhow::anyhow!(
+                "Throughput regressed by more than {}% against the baseline",
+                max_regression
+            ));
+        }
+        Ok(())
+    }
+
+    /// Run the search matcher for one needle and print what every stage did
+    fn run_explain(
+        needles: &Path,
+        document: &Path,
+        term: &str,
+        line: Option<usize>,
+        options: &CommandOptions,
+    ) -> Result<Outcome> {
+        outln!("{}", "Explain Mode".bold().blue());
+        outln!("{}", "============".blue());
+
+        let search_terms = read_needles_from_file(
+            &needles.to_string_lossy(),
+            options.settings.needle_delimiter,
+        )?;
+        match search_terms.iter().find(|(needle, _)| needle == term) {
+            Some((_, metadata)) => outln!("Needle: {} → {}", term.blue().bold(), metadata.green()),
+            None => {
+                outln!("Needle: {}", term.blue().bold());
+                outln!(
+                    "{}",
+                    format!(
+                        "  Not in {}; a search with it would not look for this term",
+                        needles.display()
+                    )
+                    .yellow()
+                );
+            }
+        }
+
+        let matcher = search_matcher(options.settings.match_options());
+        let stages = matcher.stage_names();
+        outln!(
+            "Matcher: {}; matches allowed at {}",
+            if stages.is_empty() {
+                "no normalization, exact case-sensitive substrings".to_string()
+            } else {
+                stages.join(" → ")
+            },
+            matcher.boundary()
+        );
+
+        let bytes = std::fs::read(document)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", document.display(), e))?;
+        let lines = batch::extract_blocks(
+            document,
+            &bytes,
+            options.settings.extract_options(Strategy::default())?,
+        )?;
+        outln!(
+            "Document: {} ({} lines extracted)",
+            document.display(),
+            lines.len()
+        );
+
+        let index = line.map(|line| line - 1);
+        if let Some(index) = index.filter(|&index| index >= lines.len()) {
+            return Err(anyhow::anyhow!(
+                "Line {} is past the end of the document ({} lines)",
+                index + 1,
+                lines.len()
+            ));
+        }
+
+        let explained = explain_lines(term, &lines, index, options.settings.match_options());
+        let Some(first) = explained.first() else {
+            outln!();
+            outln!("{}", "Verdict: no match on any line".red().bold());
+            if options.settings.case_sensitive {
+                Self::explain_near_misses(term, &lines);
+            }
+            return Ok(Outcome::NoMatches);
+        };
+
+        outln!();
+        outln!("Needle stages:");
+        for (stage, text) in &first.explanation.needle {
+            outln!("  {:<12} {:?}", stage, text);
+        }
+
+        let mut matches = 0;
+        let mut matched_lines = 0;
+        for explained_line in &explained {
+            outln!();
+            match &explained_line.location {
+                BlockLocation::PlainLine { .. } => {
+                    outln!("{}", format!("Line {}", explained_line.index + 1).bold())
+                }
+                location => outln!(
+                    "{}",
+                    format!("Line {} ({})", explained_line.index + 1, location).bold()
+                ),
+            }
+            for (stage, text) in &explained_line.explanation.line {
+                outln!("  {:<12} {:?}", stage, text);
+            }
+            if explained_line.explanation.candidates.is_empty() {
+                outln!(
+                    "  {}",
+                    "no candidate: the normalized needle does not occur in the normalized line"
+                        .yellow()
+                );
+            }
+
+            let before = matches;
+            for (candidate, context) in explained_line
+                .explanation
+                .candidates
+                .iter()
+                .zip(&explained_line.contexts)
+            {
+                let found = &lines[explained_line.index].text[candidate.span.clone()];
+                let boundary = &candidate.boundary;
+                let verdict = if !boundary.passed {
+                    format!("rejected at the boundary ({})", matcher.boundary()).red()
+                } else if options.settings.ignore_context.contains(context) {
+                    "dropped by --ignore-context".yellow()
+                } else {
+                    matches += 1;
+                    "match".green().bold()
+                };
+                let neighbour = |c: Option<char>, edge: &str| {
+                    c.map_or_else(|| edge.to_string(), |c| format!("{:?}", c))
+                };
+                outln!(
+                    "  candidate bytes {}..{} {:?}: before {}, after {}, context {} → {}",
+                    candidate.span.start,
+                    candidate.span.end,
+                    found,
+                    neighbour(boundary.before, "line start"),
+                    neighbour(boundary.after, "line end"),
+                    context.as_str(),
+                    verdict
+                );
+            }
+            if matches > before {
+                matched_lines += 1;
+            }
+        }
+
+        outln!();
+        if matches == 0 {
+            outln!("{}", "Verdict: no match".red().bold());
+            if index.is_none() && options.settings.case_sensitive {
+                Self::explain_near_misses(term, &lines);
+            }
+            Ok(Outcome::NoMatches)
+        } else {
+            outln!(
+                "{}",
+                format!("Verdict: {} matches on {} lines", matches, matched_lines)
+                    .green()
+                    .bold()
+            );
+            Ok(Outcome::Success)
+        }
+    }
+
+    /// Lines the needle misses only because of case, with `--case-sensitive`
+    fn explain_near_misses(term: &str, lines: &[TextBlock]) {
+        let lowered = term.to_lowercase();
+        let near: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                !line.text.contains(term) && line.text.to_lowercase().contains(&lowered)
+            })
+            .map(|(index, _)| index + 1)
+            .collect();
+        if near.is_empty() {
+            return;
+        }
+
+        let shown: Vec<String> = near.iter().take(10).map(usize::to_string).collect();
+        outln!(
+            "{} {}{} {} the needle in different case; matching is case-sensitive",
+            if near.len() == 1 { "Line" } else { "Lines" },
+            shown.join(", "),
+            if near.len() > shown.len() {
+                ", …"
+            } else {
+                ""
+            },
+            if near.len() == 1 {
+                "contains"
+            } else {
+                "contain"
+            }
+        );
+    }
+
+    /// Show each undecided match and read a single-key decision; every
+    /// decision is saved before the next match is shown
+    fn run_triage(results: &Path, output: &Path) -> Result<()> {
+        outln!("{}", "Triage".bold().blue());
+        outln!("{}", "======".blue());
+
+        let mut session = TriageSession::open(results, output)?;
+        let total = session.results.results.len();
+        outln!(
+            "{} matches, {} already decided. Decisions are saved to {}",
+            total,
+            session.decided(),
+            output.display()
+        );
+
+        let terminal = console::Term::stdout();
+        let mut note: Option<String> = None;
+        while let Some(index) = session.next_pending() {
+            let record = &session.results.results[index];
+            outln!();
+            outln!(
+                "[{}/{}] {} → {}",
+                index + 1,
+                total,
+                record.term.blue().bold(),
+                record.metadata.green()
+            );
+            outln!("  {}", record.source_label());
+            for location in record.occurrences.iter().take(3) {
+                outln!("  {}", Self::location_snippet(location));
+            }
+            if record.occurrences.len() > 3 {
+                outln!("  … and {} more", record.occurrences.len() - 3);
+            }
+            if let Some(note) = &note {
+                outln!("  Note: {}", note.italic());
+            }
+            outln!(
+                "{}",
+                "  [a]ccept [r]eject [f]lag [s]kip [n]ote [q]uit".dimmed()
+            );
+
+            let Some(key) = Self::read_key(&terminal)? else {
+                break;
+            };
+            match key {
+                'q' | 'Q' => break,
+                'n' | 'N' => {
+                    let text = if terminal.is_term() {
+                        Input::new()
+                            .with_prompt("Note")
+                            .allow_empty(true)
+                            .interact_text()?
+                    } else {
+                        Self::read_line()?.unwrap_or_default()
+                    };
+                    note = Some(text).filter(|text| !text.trim().is_empty());
+                }
+                key => match Disposition::from_key(key) {
+                    Some(disposition) => {
+                        session.record(
+                            index,
+                            Annotation {
+                                disposition,
+                                note: note.take(),
+                            },
+                        )?;
+                        outln!("  → {}", disposition.as_str());
+                    }
+                    None => outln!("{}", format!("  Unknown key: {}", key).yellow()),
+                },
+            }
+        }
+
+        outln!();
+        let counts: Vec<String> = session
+            .counts()
+            .into_iter()
+            .map(|(disposition, count)| format!("{} {}", count, disposition.as_str()))
+            .collect();
+        outln!(
+            "Decided {} of {} matches ({})",
+            session.decided(),
+            total,
+            counts.join(", ")
+        );
+        if session.next_pending().is_some() {
+            outln!(
+                "{}",
+                "Run triage again to continue where you left off.".italic()
+            );
+        }
+        Ok(())
+    }
+
+    /// One key press, or the first character of a line when stdin is not a
+    /// terminal; `None` at the end of input
+    fn read_key(terminal: &console::Term) -> Result<Option<char>> {
+        if terminal.is_term() {
+            return Ok(Some(terminal.read_char()?));
+        }
+        Ok(Self::read_line()?.map(|line| line.trim().chars().next().unwrap_or(' ')))
+    }
+
+    fn read_line() -> Result<Option<String>> {
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// `page 3, line 12: …` with the match highlighted, and long lines cut
+    /// to `SNIPPET_CONTEXT` characters either side of it
+    fn location_snippet(location: &Location) -> String {
+        let [start, end] = location.span;
+        let text = &location.text;
+        let (shown, cut_before, cut_after) = snippet_range(text, &(start..end), SNIPPET_CONTEXT);
+        let line = match (
+            text.get(shown.start..start),
+            text.get(start..end),
+            text.get(end..shown.end),
+        ) {
+            (Some(before), Some(found), Some(after)) => format!(
+                "{}{}{}{}{}",
+                if cut_before { "…" } else { "" },
+                before,
+                found.yellow().bold(),
+                after,
+                if cut_after { "…" } else { "" }
+            ),
+            _ => text.clone(),
+        };
+        match location.page {
+            Some(page) => format!("page {}, line {}: {}", page, location.line + 1, line),
+            None => format!("line {}: {}", location.line + 1, line),
+        }
+    }
+
+    fn run_needles_compile(
+        needles: &Path,
+        output: &Path,
+        options: MatchOptions,
+        delimiter: Option<NeedleDelimiter>,
+    ) -> Result<()> {
+        if output.exists()
+            && output
+                .extension()
+                .is_none_or(|ext| ext != needle_index::EXTENSION)
+        {
+            return Err(anyhow::anyhow!(
+                "Refusing to overwrite {}, which is not a .{} file",
+                output.display(),
+                needle_index::EXTENSION
+            ));
+        }
+
+        let start = std::time::Instant::now();
+        let search_terms = read_needles_from_file(&needles.to_string_lossy(), delimiter)?;
+        check_regex_needles_in_file(
+            &needles.to_string_lossy(),
+            &search_terms,
+            delimiter,
+            options.regex(),
+        )?;
+        let weights = read_needle_weights(&needles.to_string_lossy(), delimiter)?;
+        let compiled = CompiledNeedles::new(search_terms, options).with_weights(weights);
+        compiled.save(output)?;
+
+        outln!(
+            "Compiled {} needles ({}) into {} in {} ms",
+            compiled.needles.len(),
+            options,
+            output.display(),
+            start.elapsed().as_millis()
+        );
+        Ok(())
+    }
+
+    fn run_needles_merge(
+        inputs: &[PathBuf],
+        policy: OnDuplicate,
+        delimiter: Option<NeedleDelimiter>,
+        output: &ListOutput,
+    ) -> Result<()> {
+        let lists = inputs
+            .iter()
+            .map(|input| NeedleList::read(input, delimiter))
+            .collect::<Result<Vec<_>>>()?;
+        let merged = needle_list::merge(&lists, policy)?;
+        Self::warn_conflicts(&merged);
+
+        Self::write_needle_list(&merged.lines(&lists), output, lists[0].delimiter())?;
+        Self::report_list_written(output, || {
+            format!(
+                "Merged {} lists into {} needles, dropping {} repeated needles and {} conflicting ones",
+                lists.len(),
+                merged.needles.len(),
+                merged.duplicates,
+                merged.conflicts.len()
+            )
+        });
+        Ok(())
+    }
+
+    fn run_needles_dedupe(
+        needles: &Path,
+        policy: OnDuplicate,
+        delimiter: Option<NeedleDelimiter>,
+        output: &ListOutput,
+    ) -> Result<()> {
+        let list = NeedleList::read(needles, delimiter)?;
+        let merged = needle_list::merge(std::slice::from_ref(&list), policy)?;
+        Self::warn_conflicts(&merged);
+
+        let lines: Vec<ListLine> = merged
+            .needles
+            .iter()
+            .map(|(needle, _)| ListLine::Needle(needle.clone()))
+            .collect();
+        Self::write_needle_list(&lines, output, list.delimiter())?;
+        Self::report_list_written(output, || {
+            format!(
+                "Kept {} of {} needles from {}",
+                merged.needles.len(),
+                list.needles.len(),
+                needles.display()
+            )
+        });
+        Ok(())
+    }
+
+    fn warn_conflicts(merged: &Merged) {
+        for conflict in &merged.conflicts {
+            warnln!(
+                "Warning: {:?} is {:?} in {} and {:?} in {}; kept {:?}",
+                conflict.term,
+                conflict.kept.0,
+                conflict.kept.1,
+                conflict.dropped.0,
+                conflict.dropped.1,
+                conflict.kept.0
+            );
+        }
+    }
+
+    fn run_needles_diff(old: &Path, new: &Path, delimiter: Option<NeedleDelimiter>) -> Result<()> {
+        let (old, new) = (
+            NeedleList::read(old, delimiter)?,
+            NeedleList::read(new, delimiter)?,
+        );
+        let line_delimiter = new.delimiter().or(old.delimiter()).unwrap_or_default();
+
+        let changes = needle_list::diff(&old.needles, &new.needles);
+        let (mut added, mut removed, mut changed) = (0, 0, 0);
+        for change in &changes {
+            match change {
+                Change::Removed((term, metadata)) => {
+                    removed += 1;
+                    outln!("- {}", needle_line(term, metadata, line_delimiter)?);
+                }
+                Change::Added((term, metadata)) => {
+                    added += 1;
+                    outln!("+ {}", needle_line(term, metadata, line_delimiter)?);
+                }
+                Change::Changed { term, old, new } => {
+                    changed += 1;
+                    outln!("~ {}: {:?} -> {:?}", term, old, new);
+                }
+            }
+        }
+        outln!(
+            "{} added, {} removed, {} with other metadata",
+            added,
+            removed,
+            changed
+        );
+        Ok(())
+    }
+
+    /// Write `lines` where `output` says, by default with `delimiter`
+    fn write_needle_list(
+        lines: &[ListLine],
+        output: &ListOutput,
+        delimiter: Option<NeedleDelimiter>,
+    ) -> Result<()> {
+        let format = output
+            .to
+            .or(output.output.as_deref().map(ListFormat::of))
+            .unwrap_or(ListFormat::Csv);
+        let delimiter = output.output_delimiter.or(delimiter).unwrap_or_default();
+        let text = needle_list::write_list(lines, format, delimiter)?;
+        match &output.output {
+            Some(path) => std::fs::write(path, text)
+                .with_context(|| format!("Failed to write {}", path.display()))?,
+            None => outln!("{}", text.trim_end_matches('\n')),
+        }
+        Ok(())
+    }
+
+    /// Say what was written, unless the list itself went to stdout
+    fn report_list_written(output: &ListOutput, summary: impl FnOnce() -> String) {
+        if let Some(path) = &output.output {
+            outln!("{}; wrote {}", summary(), path.display());
+        }
+    }
+
+    /// Read the needles, apply `--where` filters, and work out the metadata
+    /// columns to expand in structured output
+    fn load_needles(
+        needles: &Path,
+        options: &CommandOptions,
+    ) -> Result<(Vec<OwnedNeedle>, Vec<String>, NeedleSelection)> {
+        let as_of = options.settings.as_of_date()?;
+        let mut delimiter = None;
+        let search_terms = if needle_index::is_compiled(needles) {
+            let compiled = CompiledNeedles::load(needles)?;
+            compiled.check_options(options.settings.match_options(), options.force)?;
+            compiled.needles
+        } else {
+            let (search_terms, stats) = read_needles_with_stats(
+                &needles.to_string_lossy(),
+                options.settings.needle_delimiter,
+            )?;
+            delimiter = Some(stats.delimiter);
+            check_regex_needles_in_file(
+                &needles.to_string_lossy(),
+                &search_terms,
+                delimiter,
+                options.settings.match_options().regex(),
+            )?;
+            if search_terms.is_empty() {
+                Self::check_empty_needles(needles, &stats, options)?;
+                return Ok((
+                    search_terms,
+                    Vec::new(),
+                    NeedleSelection {
+                        as_of,
+                        inactive: 0,
+                        stopwords: 0,
+                        delimiter,
+                    },
+                ));
+            }
+            search_terms
+        };
+        let (search_terms, stopped) = filter_stopwords(search_terms, &options.stopwords);
+        if !stopped.is_empty() {
+            outln!("Dropped {} needles listed as stop words", stopped.len());
+            if search_terms.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Every needle in {} is a stop word",
+                    needles.display()
+                ));
+            }
+        }
+
+        let total = search_terms.len();
+        let search_terms = filter_needles_by_metadata(search_terms, &options.filters);
+
+        if !options.filters.is_empty() {
+            outln!(
+                "Kept {} of {} needles matching the --where filters",
+                search_terms.len(),
+                total
+            );
+            if search_terms.is_empty() {
+                return Err(anyhow::anyhow!("No needles match the --where filters"));
+            }
+        }
+
+        let (search_terms, inactive) = filter_needles_by_date(search_terms, as_of);
+        if inactive > 0 {
+            outln!("Skipped {} needles not valid on {}", inactive, as_of);
+            if search_terms.is_empty() {
+                return Err(anyhow::anyhow!("No needles are valid on {}", as_of));
+            }
+        }
+
+        let columns = match options.settings.metadata_format {
+            MetadataFormat::Kv => metadata_keys(&search_terms),
+            MetadataFormat::Plain => Vec::new(),
+        };
+
+        Ok((
+            search_terms,
+            columns,
+            NeedleSelection {
+                as_of,
+                inactive,
+                stopwords: stopped.len(),
+                delimiter,
+            },
+        ))
+    }
+
+    /// The weights of the needles file at `needles`, or those compiled into it
+    fn load_weights(needles: &Path, selection: &NeedleSelection) -> Result<NeedleWeights> {
+        match selection.delimiter {
+            Some(delimiter) => Ok(read_needle_weights(
+                &needles.to_string_lossy(),
+                Some(delimiter),
+            )?),
+            None => Ok(CompiledNeedles::load(needles)?.weights),
+        }
+    }
+
+    /// Fail on a needles file without needles unless `--allow-empty-needles`
+    fn check_empty_needles(
+        needles: &Path,
+        stats: &NeedleFileStats,
+        options: &CommandOptions,
+    ) -> Result<()> {
+        let message = format!(
+            "Needles file {} contains no needles ({})",
+            needles.display(),
+            stats
+        );
+        if !options.allow_empty_needles {
+            return Err(anyhow::anyhow!(
+                "{}; pass --allow-empty-needles to treat it as a search without matches",
+                message
+            ));
+        }
+
+        outln!("{}", format!("{}; nothing to search for", message).yellow());
+        Ok(())
+    }
+
+    fn run_validate(
+        needles: Option<&PathBuf>,
+        document: Option<&PathBuf>,
+        stopwords: Option<&Stopwords>,
+        delimiter: Option<NeedleDelimiter>,
+    ) -> Result<()> {
+        outln!("{}", "Validation Mode".bold().blue());
+        outln!("{}", "=================".blue());
+
+        let needles_status = Self::validate_needles_file(needles, delimiter);
+        let document_valid = Self::validate_document_file(document);
+
+        outln!("{}", "Validation Results:".bold());
+        outln!("Needles file: {}", needles_status);
+        outln!(
+            "Document file: {}",
+            if document_valid {
+                "✓ Valid".green()
+            } else {
+                "✗ Invalid".red()
+            }
+        );
+
+        if let Some(Ok((terms, stats))) = needles
+            .filter(|path| path.exists())
+            .map(|path| read_needles_with_stats(&path.to_string_lossy(), delimiter))
+        {
+            outln!(
+                "Needles delimiter: '{}' ({})",
+                stats.delimiter,
+                if delimiter.is_some() {
+                    "given"
+                } else {
+                    "detected"
+                }
+            );
+
+            let problems = validity_problems(&terms);
+            if !problems.is_empty() {
+                outln!(
+                    "{}",
+                    format!(
+                        "Needles with malformed or inverted validity dates ({}):",
+                        problems.len()
+                    )
+                    .yellow()
+                );
+                for (term, problem) in problems {
+                    outln!("  {}: {}", term, problem);
+                }
+            }
+
+            if let Some(stopwords) = stopwords {
+                let (_, dropped) = filter_stopwords(terms, stopwords);
+                if !dropped.is_empty() {
+                    outln!(
+                        "{}",
+                        format!("Needles dropped as stop words ({}):", dropped.len()).yellow()
+                    );
+                    for (term, metadata) in dropped {
+                        outln!("  {} → {}", term, metadata);
+                    }
+                }
+            }
+        }
+
+        if let (Some(needles), Some(document)) = (needles, document) {
+            if let Err(e) = check_swapped_arguments(needles, document) {
+                outln!("{}", e.to_string().yellow());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_info(file: &Path) -> Result<()> {
+        outln!("{}", "File Information".bold().blue());
+        outln!("{}", "==================".blue());
+
+        if !file.exists() {
+            eprintln!("{}", format!("File not found: {}", file.display()).red());
+            return Ok(());
+        }
+
+        if let Ok(file_type) = parse_filetype(file) {
+            outln!("File: {}", file.display());
+            outln!(
+                "Type: {}",
+                match file_type {
+                    FileType::Docx => "DOCX Document".blue(),
+                    FileType::Pdf => "PDF Document".red(),
+                    FileType::Doc => "Legacy Word Document".yellow(),
+                    FileType::Xlsx => "Excel Workbook".green(),
+                    FileType::Odt => "OpenDocument Text".cyan(),
+                    FileType::Txt => "Plain Text".normal(),
+                    FileType::Rtf => "Rich Text Document".magenta(),
+                    FileType::Epub => "EPUB Ebook".green(),
+                }
+            );
+            outln!("Size: {} bytes", file.metadata()?.len());
+            let fields = match file_type {
+                FileType::Docx | FileType::Xlsx => {
+                    docx::extract_docx_meta(file).map(|meta| Self::owned_fields(&meta.fields()))
+                }
+                FileType::Odt => {
+                    odt::extract_odt_meta(file).map(|meta| Self::owned_fields(&meta.fields()))
+                }
+                FileType::Epub => {
+                    epub::extract_epub_meta(file).map(|meta| Self::owned_fields(&meta.fields()))
+                }
+                FileType::Pdf => {
+                    pdf::extract_pdf_meta(file).map(|meta| Self::owned_fields(&meta.fields()))
+                }
+                FileType::Doc | FileType::Txt | FileType::Rtf => Ok(Vec::new()),
+            };
+            match fields {
+                Ok(fields) => {
+                    for (name, value) in fields {
+                        let mut label = name.to_string();
+                        label[..1].make_ascii_uppercase();
+                        outln!("{}: {}", label, value);
+                    }
+                }
+                Err(e) => eprintln!(
+                    "{}",
+                    format!("Could not read document properties: {}", e).yellow()
+                ),
+            }
+            if file_type == FileType::Pdf {
+                match pdf::extract_pages(file) {
+                    Ok(pages) => outln!("Pages: {}", pages.len()),
+                    Err(e) => eprintln!("{}", format!("Could not read pages: {}", e).yellow()),
+                }
+            }
+        } else {
+            eprintln!("{}", "Unsupported file type".red());
+        }
+
+        Ok(())
+    }
+
+    fn owned_fields(fields: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        fields
+            .iter()
+            .map(|&(name, value)| (name, value.to_string()))
+            .collect()
+    }
+
+    fn get_search_terms_interactive() -> Result<Vec<(String, String)>> {
+        let options = &[
+            "Enter search terms manually",
+            "Import from file",
+            "Use sample terms",
+        ];
+
+        let choice = Select::new()
+            .with_prompt("How would you like to input search terms?")
+            .default(0)
+            .items(options)
+            .interact()?;
+
+        match choice {
+            0 => {
+                let terms_input: String = Input::new()
+                    .with_prompt("Enter search terms (separated by commas, e.g., term1,metadata1,term2,metadata2)")
+                    .interact_text()?;
+
+                Ok(Self::parse_typed_terms(&terms_input))
+            }
+            1 => {
+                let file_path: String = Input::new()
+                    .with_prompt("Enter path to needles file")
+                    .default("contacts.csv".to_string())
+                    .interact_text()?;
+
+                let needles = read_needles_from_file(&file_path, None)?;
+                Ok(needles)
+            }
+            2 => Ok(vec![
+                ("Alice Johnson".to_string(), "".to_string()),
+                ("Bob Smith".to_string(), "".to_string()),
+                ("Carol Davis".to_string(), "".to_string()),
+            ]),
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_target_files_interactive() -> Result<Vec<PathBuf>> {
+        let options = &[
+            "Select individual files",
+            "Select directory with pattern",
+            "Use current directory",
+        ];
+
+        let choice = Select::new()
+            .with_prompt("How would you like to select target files?")
+            .default(0)
+            .items(options)
+            .interact()?;
+
+        match choice {
+            0 => {
+                let files_input: String = Input::new()
+                    .with_prompt("Enter file paths (separated by spaces)")
+                    .interact_text()?;
+
+                Ok(files_input
+                    .split_whitespace()
+                    .map(|s| PathBuf::from(s.trim()))
+                    .collect())
+            }
+            1 => {
+                let dir_path: String = Input::new()
+                    .with_prompt("Enter directory path")
+                    .interact_text()?;
+
+                let pattern: String = Input::new()
+                    .with_prompt("Enter file pattern (e.g., *.pdf)")
+                    .default("*.pdf".to_string())
+                    .interact_text()?;
+
+                let files =
+                    Self::scan_directory(&PathBuf::from(dir_path.clone()), &pattern, false)?;
+                if files.is_empty() {
+                    return Err(anyhow::anyhow!("No files found in directory: {}", dir_path));
+                }
+                let file = Select::new()
+                    .with_prompt("Select document file")
+                    .items(
+                        &files
+                            .iter()
+                            .map(|f| f.to_string_lossy().to_string())
+                            .collect::<Vec<_>>(),
+                    )
+                    .interact()?;
+                Ok(vec![files[file].clone()])
+            }
+            2 => {
+                let files = Self::scan_directory(&PathBuf::from("."), "*.*", false)?;
+                Ok(files)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// The needles in `input`, typed as `term1,metadata1,term2,metadata2`;
+    /// a term left without metadata at the end gets none, and blank terms
+    /// are dropped
+    fn parse_typed_terms(input: &str) -> Vec<(String, String)> {
+        let fields: Vec<&str> = input.split(',').map(str::trim).collect();
+        fields
+            .chunks(2)
+            .filter(|pair| !pair[0].is_empty())
+            .map(|pair| {
+                (
+                    pair[0].to_string(),
+                    pair.get(1).copied().unwrap_or_default().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// The matching options chosen in interactive mode, with the defaults
+    /// for the rest
+    fn get_search_options_interactive() -> Result<SearchSettings> {
+        let case_sensitive = Confirm::new()
+            .with_prompt("Enable case sensitive search?")
+            .default(false)
+            .interact()?;
+
+        let whole_word = Confirm::new()
+            .with_prompt("Enable whole word matching?")
+            .default(false)
+            .interact()?;
+
+        Ok(SearchSettings {
+            case_sensitive,
+            whole_word,
+            ..SearchSettings::default()
+        })
+    }
+
+    /// Gather the directory scan, explicit paths and `--files-from` entries
+    /// into one list, with duplicate spellings of a file collapsed; returns
+    /// the list and the number of duplicates dropped
+    fn collect_batch_files(inputs: &BatchInputs) -> Result<(Vec<PathBuf>, usize)> {
+        let mut files = Vec::new();
+
+        if let Some(directory) = &inputs.directory {
+            if !directory.is_dir() {
+                return Err(anyhow::anyhow!(
+                    "Directory not found: {}",
+                    directory.display()
+                ));
+            }
+            let excludes = inputs
+                .exclude
+                .iter()
+                .map(|exclude| FilePattern::new(exclude))
+                .collect::<Result<Vec<_>>>()?;
+            files.extend(Self::scan_directory_with_links(
+                directory,
+                &inputs.pattern,
+                &excludes,
+                inputs.recursive,
+                inputs.follow_symlinks,
+            )?);
+        }
+
+        files.extend(inputs.files.iter().cloned());
+        if let Some(list) = &inputs.files_from {
+            files.extend(read_file_list(list)?);
+        }
+
+        if inputs.directory.is_none() && files.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No documents given: pass --directory, --files-from or file paths"
+            ));
+        }
+
+        Ok(dedupe_files(files))
+    }
+
+    fn scan_directory(directory: &Path, pattern: &str, recursive: bool) -> Result<Vec<PathBuf>> {
+        Self::scan_directory_with_links(directory, pattern, &[], recursive, true)
+    }
+
+    fn scan_directory_with_links(
+        directory: &Path,
+        pattern: &str,
+        excludes: &[FilePattern],
+        recursive: bool,
+        follow_symlinks: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let pattern = FilePattern::new(pattern)?;
+        let mut files =
+            batch::scan_directory(directory, &pattern, excludes, recursive, follow_symlinks);
+
+        // Filter by supported file types
+        files.retain(|file| {
+            batch::is_searchable(file) || (pattern.names_plain_text() && batch::is_plain_text(file))
+        });
+
+        Ok(files)
+    }
+
+    /// Search `files`, streaming each file's records to `stream` as it
+    /// finishes when one is given
+    fn run_batch_search(
+        scopes: &NeedleScopes,
+        files: &[PathBuf],
+        options: &CommandOptions,
+        pipeline: &PipelineOptions,
+        columns: &[String],
+        selection: NeedleSelection,
+        stream: Option<RecordWriter>,
+    ) -> Result<Outcome> {
+        let start = std::time::Instant::now();
+        let total_files = files.len() as u64;
+        let _graceful = interrupt::graceful(&pipeline.cancel);
+
+        // Local needles files add to the global needles; the longest list
+        // decides
+        let longest = (0..files.len())
+            .map(|index| scopes.needles_for(index))
+            .max_by_key(|needles| needles.len())
+            .unwrap_or_default();
+        let matcher = MatcherSelection::resolve(options.settings.matcher, longest, files);
+        let pipeline = &PipelineOptions {
+            extract: ExtractOptions {
+                strategy: matcher.strategy,
+                ..pipeline.extract
+            },
+            ..pipeline.clone()
+        };
+
+        // Create multi-progress bar
+        let multi_progress = match options.api {
+            Some(_) => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            None => MultiProgress::new(),
+        };
+        options.emit(v1::Event::Started {
+            command: v1::Command::Batch,
+            files: files.len(),
+        });
+        let overall_progress = multi_progress.add(ProgressBar::new(total_files));
+        overall_progress.set_style(
+            ProgressStyle::default_bar()
+                .template("Overall: [{bar:40.cyan/blue}] {pos}/{len} files")
+                .unwrap()
+                .progress_chars("█▉▊▋▌▍▎▏ "),
+        );
+
+        let on_file_done = |file_path: &Path| {
+            overall_progress.set_message(format!("Processed: {}", file_path.display()));
+            overall_progress.inc(1);
+        };
+        // Stream each file's records as it finishes; a closed stdout stops
+        // the search
+        let on_outcome = |index: usize, outcome: &FileOutcome| {
+            Self::emit_outcome(options, outcome);
+            let (Some(stream), Ok(occurrences)) = (&stream, &outcome.occurrences) else {
+                return true;
+            };
+            let matches = MatchSet::new(occurrences.clone(), &options.settings.ignore_context);
+            let rows = Self::result_rows(
+                &outcome.path,
+                &matches,
+                options,
+                |result| scopes.source(index, result).map(Path::to_path_buf),
+                |result| scopes.weight(index, result),
+            );
+            Self::stream_records(
+                stream,
+                &rows,
+                columns,
+                true,
+                SnippetWidth::of(&options.settings),
+            )
+        };
+        let outcomes =
+            run_pipeline_scoped_streaming(scopes, files, pipeline, &on_file_done, &on_outcome);
+
+        let interrupted = pipeline.cancel.is_cancelled();
+        if interrupted {
+            overall_progress.abandon_with_message("Interrupted");
+        } else {
+            overall_progress.finish_with_message("Batch processing completed!");
+        }
+        let resources = ResourceUsage::from_outcomes(&outcomes, start.elapsed());
+
+        let mut warned = 0;
+        for outcome in outcomes
+            .iter()
+            .filter(|outcome| !outcome.warnings.is_empty())
+        {
+            warned += 1;
+            for warning in outcome.warnings.iter().filter(|_| options.api.is_none()) {
+                eprintln!(
+                    "{}",
+                    format!("Warning for {}: {}", outcome.path.display(), warning).yellow()
+                );
+            }
+        }
+
+        // Summaries are gathered here a file at a time; the rows themselves
+        // are made again, reading back spilled occurrences, as they are written
+        let rows = BatchRows {
+            outcomes: &outcomes,
+            scopes,
+            options,
+        };
+        let mut documents: Vec<StoredDocument> = Vec::new();
+        let mut entries: Vec<ReportEntry> = Vec::new();
+        let mut repeats: Vec<RepeatRow> = Vec::new();
+        let mut matched = 0;
+        let mut occurrences = 0;
+        let mut seen = HashSet::new();
+        let mut files_with_matches = 0;
+        let mut suppressed = 0;
+        let mut legacy_docs = 0;
+        let mut stopped = 0;
+        let mut failures = Vec::new();
+
+        for (index, outcome) in outcomes.iter().enumerate() {
+            let path = &outcome.path;
+            let matches = match rows.matches(outcome) {
+                Some(matches) => matches?,
+                None => {
+                    let Err(e) = &outcome.occurrences else {
+                        unreachable!("only failed files have no matches")
+                    };
+                    if options.store_results.is_some() {
+                        documents.push((path.clone(), Vec::new(), Err(e.to_string())));
+                    }
+                    if options.report_dir.is_some() {
+                        entries.push(ReportEntry {
+                            path: path.clone(),
+                            matches: Err(e.to_string()),
+                        });
+                    }
+                    match e.downcast_ref() {
+                        Some(DocumentError::LegacyDocUnsupported { .. }) => legacy_docs += 1,
+                        Some(DocumentError::SearchStopped { .. }) => {
+                            stopped += 1;
+                            continue;
+                        }
+                        None => {}
+                    }
+                    tracing::debug!(file = %path.display(), error = %e, "file skipped");
+                    if options.api.is_none() {
+                        eprintln!("{}", format!("Skipping {}: {}", path.display(), e).yellow());
+                    }
+                    failures.push(v1::Failure {
+                        file: path.to_string_lossy().into_owned(),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            // One record per document, so each one inside an archive is
+            // stored with its entries
+            if options.store_results.is_some() {
+                if matches.occurrences.is_empty() {
+                    documents.push((path.clone(), Vec::new(), Ok(SearchResults::new())));
+                } else {
+                    documents.extend(
+                        matches.by_document().into_iter().map(|(entries, matches)| {
+                            (path.clone(), entries, Ok(matches.results()))
+                        }),
+                    );
+                }
+            }
+            if options.report_dir.is_some() {
+                let results = matches
+                    .results()
+                    .into_iter()
+                    .map(|result| {
+                        let contexts = matches.context_kinds(&result);
+                        (result, contexts)
+                    })
+                    .collect();
+                entries.push(ReportEntry {
+                    path: path.clone(),
+                    matches: Ok(results),
+                });
+            }
+
+            suppressed += matches.suppressed;
+            if options.settings.collapse_repeats {
+                repeats.extend(
+                    collapse_repeats(&matches.occurrences)
+                        .into_iter()
+                        .map(|group| (Some(path.clone()), group)),
+                );
+            }
+            let file_rows = rows.file_rows(index, outcome, &matches);
+            if !file_rows.is_empty() {
+                files_with_matches += 1;
+                occurrences += file_rows
+                    .iter()
+                    .map(|row| row.record.count())
+                    .sum::<usize>();
+                matched += file_rows
+                    .iter()
+                    .filter(|row| rows.first_time(&mut seen, row))
+                    .count();
+            }
+        }
+
+        if let Some(db) = &options.store_results {
+            Self::store_results(
+                db,
+                scopes.global_path(),
+                scopes.global_needles().len(),
+                &documents,
+            )?;
+        }
+
+        if let Some(dir) = &options.report_dir {
+            report::write_report(dir, &entries)?;
+            outln!(
+                "Report written to {}",
+                dir.join(report::INDEX_NAME).display()
+            );
+        }
+
+        let duration = start.elapsed();
+
+        // Display batch results
+        let span = tracing::Span::current();
+        span.record("files", files.len());
+        span.record("files_with_matches", files_with_matches);
+        span.record("matches", matched);
+
+        if interrupted {
+            warnln!(
+                "Interrupted: reporting what was found in {} of {} files",
+                files.len() - stopped,
+                files.len()
+            );
+        }
+        let run = RunInfo {
+            needles: selection,
+            matcher,
+            resources,
+            interrupted,
+        };
+        let summary = BatchSummary {
+            total_files: files.len(),
+            files_with_matches,
+            matched,
+            occurrences,
+            suppressed,
+            legacy_docs,
+            warned,
+            stopped,
+            run,
+        };
+        match &options.api {
+            Some(api) => {
+                let counts = v1::Summary {
+                    files: files.len(),
+                    files_with_matches,
+                    matches: matched,
+                    failed: failures.len(),
+                    suppressed,
+                };
+                api.print_report(&Self::api_report(api, &rows, columns, failures, counts)?)?;
+            }
+            None => {
+                Self::display_batch_results(&rows, &repeats, options, duration, &summary, columns)?
+            }
+        }
+        Self::save_results(&rows, options, &summary.run, columns, true)?;
+        Self::finish_stream(stream)?;
+        Ok(if interrupted {
+            Outcome::Interrupted
+        } else {
+            Outcome::from_matches(matched > 0)
+        })
+    }
+
+    /// Report a searched file as `--api` events
+    fn emit_outcome(options: &CommandOptions, outcome: &FileOutcome) {
+        let file = outcome.path.to_string_lossy().into_owned();
+        match &outcome.occurrences {
+            Ok(occurrences) => options.emit(v1::Event::FileDone {
+                file: file.clone(),
+                occurrences: occurrences.len(),
+            }),
+            Err(e) if matches!(e.downcast_ref(), Some(DocumentError::SearchStopped { .. })) => {}
+            Err(e) => options.emit(v1::Event::FileFailed {
+                file: file.clone(),
+                error: e.to_string(),
+            }),
+        }
+        for warning in &outcome.warnings {
+            options.emit(v1::Event::Warning {
+                file: Some(file.clone()),
+                message: warning.clone(),
+            });
+        }
+    }
+
+    /// The `--api` results document
+    fn api_report(
+        api: &ApiSession,
+        rows: &(impl Rows + ?Sized),
+        columns: &[String],
+        failures: Vec<v1::Failure>,
+        summary: v1::Summary,
+    ) -> Result<v1::Report> {
+        let mut results = Vec::new();
+        rows.try_for_each(&mut |row| {
+            let fields = (!columns.is_empty())
+                .then(|| parse_metadata_kv(&row.record.metadata).ok())
+                .flatten();
+            results.push(v1::Match::new(
+                &row.record,
+                &row.contexts,
+                fields,
+                row.source.as_deref(),
+            ));
+            Ok(())
+        })?;
+        match api.version {
+            ApiVersion::V1 => Ok(v1::Report {
+                api: v1::VERSION,
+                command: api.command,
+                results,
+                failures,
+                summary,
+            }),
+        }
+    }
+
+    fn check_store_supported(options: &CommandOptions) -> Result<()> {
+        if options.store_results.is_some() && !cfg!(feature = "sqlite") {
+            return Err(anyhow::anyhow!(
+                "--store-results requires docsearcher to be built with the `sqlite` feature"
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn store_results(
+        db: &Path,
+        needles: &Path,
+        needle_count: usize,
+        documents: &[StoredDocument],
+    ) -> Result<()> {
+        let records: Vec<DocumentRecord> = documents
+            .iter()
+            .map(|(path, entries, matches)| DocumentRecord {
+                path: path.clone(),
+                entries: entries.clone(),
+                matches: matches.as_deref().map_err(Clone::clone),
+            })
+            .collect();
+        let invocation = std::env::args().collect::<Vec<_>>().join(" ");
+
+        let run_id =
+            ResultStore::open(db)?.record_run(&invocation, needles, needle_count, &records)?;
+        outln!(
+            "{}",
+            format!("Stored run {} in {}", run_id, db.display()).blue()
+        );
+        Ok(())
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn store_results(
+        _db: &Path,
+        _needles: &Path,
+        _needle_count: usize,
+        _documents: &[StoredDocument],
+    ) -> Result<()> {
+        unreachable!("rejected by check_store_supported")
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn run_db_query(
+        database: &Path,
+        query: Option<CannedQuery>,
+        file: Option<&Path>,
+        sql: Option<&str>,
+    ) -> Result<()> {
+        let store = ResultStore::open_read_only(database)?;
+        let rows = match (query, sql) {
+            (_, Some(sql)) => store.raw_query(sql)?,
+            (Some(query), None) => store.canned_query(query, file)?,
+            (None, None) => return Err(anyhow::anyhow!("Give a canned query or --sql")),
+        };
+
+        outln!("{}", rows.columns.join(" | ").bold());
+        for row in &rows.rows {
+            outln!("{}", row.join(" | "));
+        }
+        outln!("{}", format!("{} rows", rows.rows.len()).italic());
+        Ok(())
+    }
+
+    /// A needles file without needles is reported as a finding, not as invalid
+    fn validate_needles_file(
+        path: Option<&PathBuf>,
+        delimiter: Option<NeedleDelimiter>,
+    ) -> colored::ColoredString {
+        let invalid = "✗ Invalid".red();
+        let Some(path) = path else {
+            return invalid;
+        };
+        if !path.exists() {
+            return invalid;
+        }
+
+        match read_needles_with_stats(&path.to_string_lossy(), delimiter) {
+            Ok((needles, stats)) if needles.is_empty() => {
+                format!("⚠ No needles ({})", stats).yellow()
+            }
+            Ok(_) => "✓ Valid".green(),
+            Err(_) => invalid,
+        }
+    }
+
+    fn validate_document_file(path: Option<&PathBuf>) -> bool {
+        if let Some(path) = path {
+            if !path.exists() {
+                return false;
+            }
+
+            parse_filetype(path).is_ok()
+        } else {
+            false
+        }
+    }
+
+    /// One row per needle found in `file`, heaviest needle first and in term
+    /// order among equal `weight`s, or with `--no-dedup` one per line it was
+    /// found on; for a container, the rows of each document inside it, by
+    /// document
+    fn result_rows(
+        file: &Path,
+        matches: &MatchSet,
+        options: &CommandOptions,
+        source: impl Fn(&SearchResult) -> Option<PathBuf>,
+        weight: impl Fn(&SearchResult) -> i64,
+    ) -> Vec<ResultRow> {
+        let mut rows = Vec::new();
+        for (entries, matches) in matches.by_document() {
+            let document = Source::file(file).nested(entries);
+            let mut results = matches.results();
+            // A needle found in the body and in a header is one row, with
+            // the occurrences of both
+            results.dedup_by(|a, b| a.term == b.term && a.metadata == b.metadata);
+            results.sort_by_cached_key(|result| std::cmp::Reverse(weight(result)));
+            for result in results {
+                let occurrences: Vec<&Occurrence> = matches
+                    .occurrences
+                    .iter()
+                    .filter(|occurrence| {
+                        occurrence.term == result.term && occurrence.metadata == result.metadata
+                    })
+                    .collect();
+                let hits: Vec<&[&Occurrence]> = match options.settings.no_dedup {
+                    true => occurrences.chunk_by(|a, b| a.line == b.line).collect(),
+                    false => vec![&occurrences],
+                };
+                for hit in hits {
+                    let mut record =
+                        MatchRecord::new(&document, &result.term, &result.metadata, hit);
+                    record.annotate(options.dispositions.get(&record.match_id));
+                    if weight(&result) != 0 {
+                        record
+                            .extra
+                            .insert("weight".to_string(), serde_json::json!(weight(&result)));
+                    }
+                    let mut contexts: Vec<ContextKind> = hit
+                        .iter()
+                        .map(|occurrence| occurrence.context_kind)
+                        .collect();
+                    contexts.sort();
+                    contexts.dedup();
+                    rows.push(ResultRow {
+                        contexts,
+                        source: source(&result),
+                        record,
+                    });
+                }
+            }
+        }
+        rows
+    }
+
+    fn display_results(
+        rows: &[ResultRow],
+        matches: &MatchSet,
+        options: &CommandOptions,
+        run: &RunInfo,
+        columns: &[String],
+    ) -> Result<()> {
+        outln!("\n{}", "=".repeat(50).blue());
+        outln!("{}", "SEARCH RESULTS".blue().bold());
+        outln!("{}", "=".repeat(50).blue());
+
+        // Show search options
+        outln!("Search Options:");
+        let yes_no = |on: bool| if on { "yes" } else { "no" };
+        outln!(
+            "  Case sensitive: {}",
+            yes_no(options.settings.case_sensitive)
+        );
+        outln!("  Whole word: {}", yes_no(options.settings.whole_word));
+        Self::display_suppressed(matches.suppressed, &options.settings.ignore_context);
+        Self::display_selection(&run.needles);
+        Self::display_matcher(&run.matcher);
+        Self::display_resources(&run.resources);
+        outln!();
+
+        if options.settings.collapse_repeats {
+            let rows: Vec<RepeatRow> = collapse_repeats(&matches.occurrences)
+                .into_iter()
+                .map(|group| (None, group))
+                .collect();
+            Self::display_repeats(&rows, options, run)?;
+        } else {
+            match options.settings.format {
+                OutputFormat::Json => Self::print_json_report(
+                    Records::sorted(rows, columns, false, SnippetWidth::of(&options.settings)),
+                    run,
+                    None,
+                )?,
+                OutputFormat::Csv => Self::display_csv_results(rows, columns, options)?,
+                OutputFormat::Html => Self::display_html_results(rows, options)?,
+                OutputFormat::HtmlFull => {
+                    let mut report = FullReport::new(options.excerpts);
+                    Self::add_full_html_document(
+                        &mut report,
+                        &rows.iter().collect::<Vec<_>>(),
+                        options,
+                        run,
+                    )?;
+                    Self::print_full_html(report, "DocSearcher Results", 1, options, run);
+                }
+                OutputFormat::Text => {
+                    Self::display_text_results(rows, SnippetWidth::of(&options.settings))
+                }
+                // Already streamed to stdout
+                OutputFormat::Ndjson => {}
+            }
+        }
+
+        outln!("{}", "=".repeat(50).blue());
+        outln!(
+            "{}",
+            format!(
+                "Search completed in {:.0} ms",
+                run.resources.elapsed_secs * 1000.0
+            )
+            .italic()
+        );
+        outln!("{}", format!("Found {} matches", rows.len()).green().bold());
+
+        Ok(())
+    }
+
+    fn display_batch_results(
+        results: &BatchRows,
+        repeats: &[RepeatRow],
+        options: &CommandOptions,
+        duration: std::time::Duration,
+        summary: &BatchSummary,
+        columns: &[String],
+    ) -> Result<()> {
+        outln!("\n{}", "=".repeat(60).blue());
+        outln!("{}", "BATCH SEARCH RESULTS".blue().bold());
+        outln!("{}", "=".repeat(60).blue());
+
+        outln!("Summary:");
+        if summary.run.interrupted {
+            outln!(
+                "  {}",
+                format!(
+                    "Interrupted: partial results, {} files not searched",
+                    summary.stopped
+                )
+                .yellow()
+                .bold()
+            );
+        }
+        outln!("  Total files processed: {}", summary.total_files);
+        outln!("  Files with matches: {}", summary.files_with_matches);
+        outln!("  Total matches found: {}", summary.matched);
+        outln!("  Total occurrences: {}", summary.occurrences);
+        if summary.legacy_docs > 0 {
+            outln!("  Legacy .doc files not searched: {}", summary.legacy_docs);
+        }
+        if summary.warned > 0 {
+            outln!("  Files searched with warnings: {}", summary.warned);
+        }
+        Self::display_suppressed(summary.suppressed, &options.settings.ignore_context);
+        Self::display_selection(&summary.run.needles);
+        Self::display_matcher(&summary.run.matcher);
+        Self::display_resources(&summary.run.resources);
+        outln!();
+
+        if options.settings.collapse_repeats {
+            Self::display_repeats(repeats, options, &summary.run)?;
+        } else {
+            match options.settings.format {
+                OutputFormat::Json => {
+                    let counts = serde_json::json!({
+                        "files": summary.total_files,
+                        "files_with_matches": summary.files_with_matches,
+                        "matches": summary.matched,
+                        "occurrences": summary.occurrences,
+                    });
+                    Self::print_json_report(
+                        Records::sorted(
+                            results,
+                            columns,
+                            true,
+                            SnippetWidth::of(&options.settings),
+                        ),
+                        &summary.run,
+                        Some(counts),
+                    )?
+                }
+                OutputFormat::Csv => Self::display_batch_csv_results(results, columns, options)?,
+                OutputFormat::Html => Self::display_batch_html_results(results, options)?,
+                OutputFormat::HtmlFull => {
+                    let mut report = FullReport::new(options.excerpts);
+                    let mut seen = HashSet::new();
+                    for (index, outcome) in results.outcomes.iter().enumerate() {
+                        let Some(matches) = results.matches(outcome) else {
+                            continue;
+                        };
+                        let rows = results.file_rows(index, outcome, &matches?);
+                        let rows: Vec<&ResultRow> = rows
+                            .iter()
+                            .filter(|row| results.first_time(&mut seen, row))
+                            .collect();
+                        Self::add_full_html_document(&mut report, &rows, options, &summary.run)?;
+                    }
+                    Self::print_full_html(
+                        report,
+                        "DocSearcher Batch Results",
+                        summary.total_files,
+                        options,
+                        &summary.run,
+                    );
+                }
+                OutputFormat::Text => Self::display_batch_text_results(
+                    results,
+                    summary.matched,
+                    SnippetWidth::of(&options.settings),
+                )?,
+                OutputFormat::Ndjson => {}
+            }
+        }
+
+        outln!("{}", "=".repeat(60).blue());
+        outln!(
+            "{}",
+            format!("Batch processing completed in {} ms", duration.as_millis()).italic()
+        );
+
+        Ok(())
+    }
+
+    /// `--collapse-repeats` output: one row per group of occurrences on the
+    /// same normalized line, with its count and page range. JSON keeps every
+    /// occurrence of a group under `repeats`.
+    fn display_repeats(rows: &[RepeatRow], options: &CommandOptions, run: &RunInfo) -> Result<()> {
+        let pages = |group: &RepeatGroup| match group.page_range() {
+            Some((first, last)) if first == last => format!("page {}", first),
+            Some((first, last)) => format!("pages {}–{}", first, last),
+            None => String::new(),
+        };
+
+        match options.settings.format {
+            OutputFormat::Json => {
+                let groups: Vec<serde_json::Value> = rows
+                    .iter()
+                    .map(|(file, group)| {
+                        let (first_page, last_page) = group.page_range().unzip();
+                        let repeats: Vec<serde_json::Value> = group
+                            .occurrences
+                            .iter()
+                            .map(|occurrence| {
+                                serde_json::json!({
+                                    "line": occurrence.line,
+                                    "page": occurrence.page(),
+                                    "span": [occurrence.span.start, occurrence.span.end],
+                                    "text": occurrence.line_text,
+                                    "context_kind": occurrence.context_kind.as_str()
+                                })
+                            })
+                            .collect();
+                        let mut value = serde_json::json!({
+                            "term": group.term,
+                            "metadata": group.metadata,
+                            "line": group.line,
+                            "count": group.count(),
+                            "first_page": first_page,
+                            "last_page": last_page,
+                            "repeats": repeats
+                        });
+                        if let Some(file) = file {
+                            value["file"] =
+                                serde_json::Value::String(file.to_string_lossy().into_owned());
+                        }
+                        value
+                    })
+                    .collect();
+                Self::print_json_report(groups, run, None)?;
+            }
+            OutputFormat::Csv => {
+                outln!(
+                    "{}",
+                    csv_row(
+                        &[
+                            "term",
+                            "metadata",
+                            "file",
+                            "line",
+                            "count",
+                            "first_page",
+                            "last_page"
+                        ],
+                        options.settings.csv_unsafe
+                    )
+                );
+                for (file, group) in rows {
+                    let (first_page, last_page) = group.page_range().unzip();
+                    let row = [
+                        group.term.clone(),
+                        group.metadata.clone(),
+                        file.as_ref()
+                            .map(|file| file.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        group.line.clone(),
+                        group.count().to_string(),
+                        first_page.map(|page| page.to_string()).unwrap_or_default(),
+                        last_page.map(|page| page.to_string()).unwrap_or_default(),
+                    ];
+                    outln!("{}", csv_row(&row, options.settings.csv_unsafe));
+                }
+            }
+            _ => {
+                if rows.is_empty() {
+                    outln!("{}", "No matches found.".yellow());
+                }
+                for (i, (file, group)) in rows.iter().enumerate() {
+                    let mut line = format!(
+                        "  {}: {} → {}",
+                        i + 1,
+                        group.term.blue(),
+                        group.metadata.green()
+                    );
+                    if group.count() > 1 {
+                        line.push_str(&format!(" ×{}", group.count()));
+                    }
+                    let pages = pages(group);
+                    if !pages.is_empty() {
+                        line.push_str(&format!(" ({})", pages));
+                    }
+                    let kinds: Vec<ContextKind> = group
+                        .occurrences
+                        .iter()
+                        .map(|occurrence| occurrence.context_kind)
+                        .collect();
+                    line.push_str(&Self::concealed_note(&kinds).magenta().to_string());
+                    if let Some(file) = file {
+                        line.push_str(&format!(" [{}]", file.display()));
+                    }
+                    outln!("{}", line);
+                    outln!("      {}", group.line.italic());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Needles skipped for `--as-of`, so a run on a subset is obvious
+    fn display_selection(needles: &NeedleSelection) {
+        if needles.inactive > 0 {
+            outln!(
+                "Needles not valid on {}: {} (not searched)",
+                needles.as_of,
+                needles.inactive
+            );
+        }
+        if needles.stopwords > 0 {
+            outln!(
+                "Needles dropped as stop words: {} (not searched)",
+                needles.stopwords
+            );
+        }
+        if let Some(delimiter) = needles
+            .delimiter
+            .filter(|&delimiter| delimiter != NeedleDelimiter::COMMA)
+        {
+            outln!("Needles delimiter: '{}'", delimiter);
+        }
+    }
+
+    /// The lookup strategy, and whether `--matcher auto` chose it
+    fn display_matcher(matcher: &MatcherSelection) {
+        match matcher.requested {
+            MatcherChoice::Auto => {
+                outln!("Needle lookup: {} (chosen automatically)", matcher.strategy)
+            }
+            _ => outln!("Needle lookup: {}", matcher.strategy),
+        }
+    }
+
+    /// Memory, CPU time and throughput of the run
+    fn display_resources(resources: &ResourceUsage) {
+        let unavailable = "not available on this platform";
+        outln!("Resources:");
+        match resources.peak_rss_bytes {
+            Some(rss) => outln!("  Peak memory: {:.1} MB", rss as f64 / 1e6),
+            None => outln!("  Peak memory: {}", unavailable),
+        }
+        match (resources.user_cpu_secs, resources.system_cpu_secs) {
+            (Some(user), Some(system)) => {
+                outln!("  CPU time: {:.2} s user, {:.2} s system", user, system)
+            }
+            _ => outln!("  CPU time: {}", unavailable),
+        }
+        outln!(
+            "  Read: {:.2} MB from {} files, {:.1} files/s",
+            resources.bytes_read as f64 / 1e6,
+            resources.files,
+            resources.files_per_sec
+        );
+        outln!(
+            "  Text extracted: {:.2} MB",
+            resources.bytes_extracted as f64 / 1e6
+        );
+    }
+
+    /// JSON output: the results, the needles searched and the resources the
+    /// run used, written out as `results` is serialized
+    /// Print the JSON report of `results`, with the batch `summary` counts
+    fn print_json_report(
+        results: impl serde::Serialize,
+        run: &RunInfo,
+        summary: Option<serde_json::Value>,
+    ) -> Result<()> {
+        // The fields in the order `serde_json::Value` keeps its keys, as when
+        // the report was built as a value
+        #[derive(serde::Serialize)]
+        struct Report<R> {
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            interrupted: bool,
+            matcher: MatcherSelection,
+            needles: serde_json::Value,
+            resources: serde_json::Value,
+            results: R,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            summary: Option<serde_json::Value>,
+        }
+
+        let report = Report {
+            interrupted: run.interrupted,
+            matcher: run.matcher,
+            needles: serde_json::to_value(run.needles)?,
+            resources: serde_json::to_value(&run.resources)?,
+            results,
+            summary,
+        };
+        let mut output = LineWriter::default();
+        serde_json::to_writer_pretty(&mut output, &report)?;
+        output.finish();
+        Ok(())
+    }
+
+    /// Write `--save-results`: the JSON report, whatever the output format
+    fn save_results(
+        rows: &(impl Rows + ?Sized),
+        options: &CommandOptions,
+        run: &RunInfo,
+        columns: &[String],
+        batch: bool,
+    ) -> Result<()> {
+        let Some(path) = &options.save_results else {
+            return Ok(());
+        };
+
+        let mut extra = serde_json::Map::new();
+        extra.insert("needles".to_string(), serde_json::to_value(run.needles)?);
+        extra.insert("matcher".to_string(), serde_json::to_value(run.matcher)?);
+        extra.insert(
+            "resources".to_string(),
+            serde_json::to_value(&run.resources)?,
+        );
+        if run.interrupted {
+            extra.insert("interrupted".to_string(), serde_json::Value::Bool(true));
+        }
+        triage::save_results(
+            path,
+            &Records {
+                rows,
+                columns,
+                batch,
+                snippets: SnippetWidth::of(&options.settings),
+                sorted: false,
+            },
+            &extra,
+        )?;
+        outln!("{}", format!("Results saved to {}", path.display()).blue());
+        Ok(())
+    }
+
+    /// ` [flag: note]` for a match with a decision, empty otherwise
+    fn disposition_note(record: &MatchRecord) -> String {
+        match (&record.disposition, &record.note) {
+            (Some(disposition), Some(note)) => format!(" [{}: {}]", disposition.as_str(), note),
+            (Some(disposition), None) => format!(" [{}]", disposition.as_str()),
+            (None, _) => String::new(),
+        }
+    }
+
+    /// The disposition and note cells of an annotated CSV or HTML row
+    fn disposition_cells(record: &MatchRecord) -> [String; 2] {
+        [
+            record
+                .disposition
+                .map(|disposition| disposition.as_str().to_string())
+                .unwrap_or_default(),
+            record.note.clone().unwrap_or_default(),
+        ]
+    }
+
+    /// Report occurrences dropped by `--ignore-context`
+    fn display_suppressed(suppressed: usize, ignored: &[ContextKind]) {
+        if ignored.is_empty() {
+            return;
+        }
+
+        let kinds: Vec<&str> = ignored.iter().map(ContextKind::as_str).collect();
+        outln!(
+            "  Suppressed occurrences ({}): {}",
+            kinds.join(", "),
+            suppressed
+        );
+    }
+
+    fn context_kinds_label(kinds: &[ContextKind]) -> String {
+        kinds
+            .iter()
+            .map(ContextKind::as_str)
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// ` (link-target, hidden)` for matches found in text a reader does not
+    /// see, empty otherwise
+    fn concealed_note(kinds: &[ContextKind]) -> String {
+        let concealed: Vec<&str> = kinds
+            .iter()
+            .filter(|kind| kind.is_concealed())
+            .map(ContextKind::as_str)
+            .collect();
+        if concealed.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", concealed.join(", "))
+        }
+    }
+
+    /// ` (page 3)` or ` (pages 3, 7)` for a match in a paged document
+    fn pages_note(record: &MatchRecord) -> String {
+        match record.pages().as_slice() {
+            [] => String::new(),
+            [page] => format!(" (page {})", page),
+            pages => format!(
+                " (pages {})",
+                pages
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// ` (paragraph 87 under Contracts › Termination)` for a match in the
+    /// body of a DOCX document
+    fn section_note(record: &MatchRecord) -> String {
+        record
+            .section()
+            .map(|section| format!(" ({})", section))
+            .unwrap_or_default()
+    }
+
+    /// ` ×3` for a needle found more than once
+    fn count_note(record: &MatchRecord) -> String {
+        match record.count() {
+            0 | 1 => String::new(),
+            count => format!(" ×{}", count),
+        }
+    }
+
+    /// ` (weight 10)` for a needle the needles file gives a weight
+    fn weight_note(record: &MatchRecord) -> String {
+        match record.weight() {
+            0 => String::new(),
+            weight => format!(" (weight {})", weight),
+        }
+    }
+
+    /// ` (fuzzy: "Jonson", 1 edit)` for the texts `--fuzzy` matched in
+    /// place of the needle
+    fn fuzzy_note(record: &MatchRecord) -> String {
+        let approximations = record.approximations();
+        if approximations.is_empty() {
+            return String::new();
+        }
+        let matched: Vec<String> = approximations
+            .iter()
+            .map(|approximate| {
+                let edits = if approximate.distance == 1 {
+                    "edit"
+                } else {
+                    "edits"
+                };
+                format!(
+                    "\"{}\", {} {}",
+                    approximate.matched, approximate.distance, edits
+                )
+            })
+            .collect();
+        format!(" (fuzzy: {})", matched.join("; "))
+    }
+
+    /// The pages of a match for a CSV cell, separated by `;`
+    fn pages_cell(record: &MatchRecord) -> String {
+        record
+            .pages()
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    fn display_text_results(rows: &[ResultRow], snippets: Option<SnippetWidth>) {
+        if rows.is_empty() {
+            outln!("{}", "No matches found.".yellow());
+            return;
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            let note = Self::concealed_note(&row.contexts);
+            let decision = Self::disposition_note(&row.record);
+            let pages = Self::count_note(&row.record)
+                + &Self::pages_note(&row.record)
+                + &Self::section_note(&row.record)
+                + &Self::fuzzy_note(&row.record)
+                + &Self::weight_note(&row.record);
+            outln!(
+                "  {}: {} → {}{}{}{}",
+                i + 1,
+                row.record.term.blue(),
+                row.record.metadata.green(),
+                pages,
+                note.magenta(),
+                decision.cyan()
+            );
+            if let Some(width) = snippets {
+                Self::display_snippet(&row.record, width);
+            }
+        }
+    }
+
+    fn display_batch_text_results(
+        results: &BatchRows,
+        count: usize,
+        snippets: Option<SnippetWidth>,
+    ) -> Result<()> {
+        if count == 0 {
+            outln!("{}", "No matches found in any files.".yellow());
+            return Ok(());
+        }
+
+        let mut i = 0;
+        results.try_for_each(&mut |ResultRow {
+                                       record,
+                                       contexts,
+                                       source,
+                                   }| {
+            i += 1;
+            let note = Self::concealed_note(contexts).magenta();
+            let decision = Self::disposition_note(record).cyan();
+            let pages = Self::count_note(record)
+                + &Self::pages_note(record)
+                + &Self::section_note(record)
+                + &Self::fuzzy_note(record)
+                + &Self::weight_note(record);
+            match source {
+                Some(list) => outln!(
+                    "  {}: {} → {}{}{}{} [{}] (from {})",
+                    i,
+                    record.term.blue(),
+                    record.metadata.green(),
+                    pages,
+                    note,
+                    decision,
+                    record.source_label(),
+                    list.display()
+                ),
+                None => outln!(
+                    "  {}: {} → {}{}{}{} [{}]",
+                    i,
+                    record.term.blue(),
+                    record.metadata.green(),
+                    pages,
+                    note,
+                    decision,
+                    record.source_label()
+                ),
+            }
+            if let Some(width) = snippets {
+                Self::display_snippet(record, width);
+            }
+            Ok(())
+        })
+    }
+
+    /// The text around the first occurrence of a match, `width` wide, with
+    /// the span of the match within it
+    fn snippet(
+        record: &MatchRecord,
+        width: SnippetWidth,
+    ) -> Option<(String, std::ops::Range<usize>)> {
+        let location = record.occurrences.first()?;
+        let (text, span) = (&location.text, location.span[0]..location.span[1]);
+        Some(match width {
+            SnippetWidth::Centred(chars) => context_snippet(text, &span, chars),
+            SnippetWidth::Around(chars) => {
+                let (shown, ..) = snippet_range(text, &span, chars);
+                (
+                    text[shown.clone()].to_string(),
+                    span.start - shown.start..span.end - shown.start,
+                )
+            }
+        })
+    }
+
+    /// Print the context snippet of a match, muted, under its result line
+    fn display_snippet(record: &MatchRecord, width: SnippetWidth) {
+        let Some((snippet, span)) = Self::snippet(record, width) else {
+            return;
+        };
+        let snippet = snippet.replace(['\r', '\n', '\t'], " ");
+        outln!(
+            "       {}{}{}",
+            snippet[..span.start].dimmed(),
+            snippet[span.clone()].dimmed().bold(),
+            snippet[span.end..].dimmed()
+        );
+    }
+
+    /// The context snippet of a match for a CSV cell; empty with `--no-context`
+    fn snippet_cell(record: &MatchRecord, snippets: Option<SnippetWidth>) -> String {
+        snippets
+            .and_then(|width| Self::snippet(record, width))
+            .map(|(snippet, _)| snippet)
+            .unwrap_or_default()
+    }
+
+    /// The context snippet of a match for an HTML cell, escaped, with the
+    /// match marked
+    fn snippet_html(record: &MatchRecord, width: SnippetWidth) -> String {
+        let Some((snippet, span)) = Self::snippet(record, width) else {
+            return String::new();
+        };
+        format!(
+            "{}<mark>{}</mark>{}",
+            escape_html(&snippet[..span.start]),
+            escape_html(&snippet[span.clone()]),
+            escape_html(&snippet[span.end..])
+        )
+    }
+
+    /// A JSON result: the record with its count, contexts, context snippet,
+    /// fuzzy distance score, parsed metadata fields and, in batch mode, the
+    /// needles list it came from
+    fn match_record(
+        row: &ResultRow,
+        columns: &[String],
+        batch: bool,
+        snippets: Option<SnippetWidth>,
+    ) -> MatchRecord {
+        let mut record = row.record.clone();
+        record
+            .extra
+            .insert("count".to_string(), serde_json::json!(record.count()));
+        let contexts: Vec<&str> = row.contexts.iter().map(ContextKind::as_str).collect();
+        record
+            .extra
+            .insert("context_kind".to_string(), serde_json::json!(contexts));
+        if let Some((snippet, span)) = snippets.and_then(|width| Self::snippet(&record, width)) {
+            record
+                .extra
+                .insert("context_snippet".to_string(), serde_json::json!(snippet));
+            record.extra.insert(
+                "context_span".to_string(),
+                serde_json::json!([span.start, span.end]),
+            );
+        }
+        if let Some(score) = record.distance_score() {
+            record
+                .extra
+                .insert("distance_score".to_string(), serde_json::json!(score));
+        }
+        if batch {
+            let source = row.source.as_ref().map(|list| list.to_string_lossy());
+            record
+                .extra
+                .insert("needles_list".to_string(), serde_json::json!(source));
+        }
+        if let Some(fields) = Self::metadata_fields(&record.metadata, columns) {
+            record.extra.insert("fields".to_string(), fields);
+        }
+        record
+    }
+
+    /// CSV writers: every field goes through `csv_field`, which quotes as
+    /// needed and, unless `raw` (`--csv-unsafe`) is set, prefixes values
+    /// starting with `=`, `+`, `-` or `@` with `'` so spreadsheets don't
+    /// evaluate them as formulas.
+    fn display_csv_results(
+        rows: &[ResultRow],
+        columns: &[String],
+        options: &CommandOptions,
+    ) -> Result<()> {
+        let raw = options.settings.csv_unsafe;
+        let snippets = SnippetWidth::of(&options.settings);
+        outln!(
+            "{}",
+            Self::csv_header(
+                &[
+                    "term",
+                    "metadata",
+                    "context_kind",
+                    "pages",
+                    "count",
+                    "section",
+                    "context_snippet"
+                ],
+                columns,
+                options
+            )
+        );
+        for ResultRow {
+            record, contexts, ..
+        } in rows
+        {
+            let mut row = vec![
+                record.term.clone(),
+                record.metadata.clone(),
+                Self::context_kinds_label(contexts),
+                Self::pages_cell(record),
+                record.count().to_string(),
+                record.section().unwrap_or_default(),
+                Self::snippet_cell(record, snippets),
+            ];
+            row.extend(Self::csv_metadata_cells(&record.metadata, columns));
+            if options.annotated() {
+                row.extend(Self::disposition_cells(record));
+            }
+            outln!("{}", csv_row(&row, raw));
+        }
+        Ok(())
+    }
+
+    fn display_batch_csv_results(
+        results: &BatchRows,
+        columns: &[String],
+        options: &CommandOptions,
+    ) -> Result<()> {
+        let raw = options.settings.csv_unsafe;
+        let snippets = SnippetWidth::of(&options.settings);
+        outln!(
+            "{}",
+            Self::csv_header(
+                &[
+                    "term",
+                    "metadata",
+                    "file",
+                    "source",
+                    "context_kind",
+                    "pages",
+                    "count",
+                    "section",
+                    "context_snippet",
+                    "needles_list"
+                ],
+                columns,
+                options
+            )
+        );
+        results.try_for_each(&mut |ResultRow {
+                                       record,
+                                       contexts,
+                                       source,
+                                   }| {
+            let mut row = vec![
+                record.term.clone(),
+                record.metadata.clone(),
+                record.file.clone(),
+                record.source_label(),
+                Self::context_kinds_label(contexts),
+                Self::pages_cell(record),
+                record.count().to_string(),
+                record.section().unwrap_or_default(),
+                Self::snippet_cell(record, snippets),
+                source
+                    .as_ref()
+                    .map(|list| list.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ];
+            row.extend(Self::csv_metadata_cells(&record.metadata, columns));
+            if options.annotated() {
+                row.extend(Self::disposition_cells(record));
+            }
+            outln!("{}", csv_row(&row, raw));
+            Ok(())
+        })
+    }
+
+    /// Parsed `key=value` metadata for the `fields` of a JSON result
+    fn metadata_fields(metadata: &str, columns: &[String]) -> Option<serde_json::Value> {
+        if columns.is_empty() {
+            return None;
+        }
+
+        let fields: serde_json::Map<String, serde_json::Value> = parse_metadata_kv(metadata)
+            .ok()?
+            .into_iter()
+            .map(|(key, value)| (key, serde_json::Value::String(value)))
+            .collect();
+        Some(serde_json::Value::Object(fields))
+    }
+
+    /// The base columns, any metadata columns and, with `--dispositions`,
+    /// `disposition` and `note`
+    fn csv_header(base: &[&str], columns: &[String], options: &CommandOptions) -> String {
+        let annotations: &[&str] = if options.annotated() {
+            &["disposition", "note"]
+        } else {
+            &[]
+        };
+        let header: Vec<&str> = base
+            .iter()
+            .copied()
+            .chain(columns.iter().map(String::as_str))
+            .chain(annotations.iter().copied())
+            .collect();
+        csv_row(&header, options.settings.csv_unsafe)
+    }
+
+    /// One cell per metadata column, empty when the key is absent
+    fn csv_metadata_cells(metadata: &str, columns: &[String]) -> Vec<String> {
+        if columns.is_empty() {
+            return Vec::new();
+        }
+
+        let fields = parse_metadata_kv(metadata).unwrap_or_default();
+        columns
+            .iter()
+            .map(|column| {
+                fields
+                    .iter()
+                    .find(|(key, _)| key == column)
+                    .map_or_else(String::new, |(_, value)| value.clone())
+            })
+            .collect()
+    }
+
+    fn display_html_results(rows: &[ResultRow], options: &CommandOptions) -> Result<()> {
+        outln!("<!DOCTYPE html>");
+        outln!(
+            "<html><head><meta charset=\"utf-8\"><title>DocSearcher Results</title></head><body>"
+        );
+        outln!("<h1>Search Results</h1>");
+        let snippets = SnippetWidth::of(&options.settings);
+        outln!(
+            "<table border='1'><tr><th>Term</th><th>Metadata</th><th>Count</th><th>Section</th>{}{}</tr>",
+            Self::html_snippet_header(snippets),
+            Self::html_disposition_headers(options)
+        );
+
+        for ResultRow {
+            record, contexts, ..
+        } in rows
+        {
+            let note = Self::concealed_note(contexts);
+            outln!(
+                "<tr><td>{}<em>{}</em></td><td>{}</td><td>{}</td><td>{}</td>{}{}</tr>",
+                escape_html(&record.term),
+                note,
+                escape_html(&record.metadata),
+                record.count(),
+                escape_html(&record.section().unwrap_or_default()),
+                Self::html_snippet_cell(record, snippets),
+                Self::html_disposition_cells(record, options)
+            );
+        }
+
+        outln!("</table></body></html>");
+        Ok(())
+    }
+
+    fn display_batch_html_results(results: &BatchRows, options: &CommandOptions) -> Result<()> {
+        outln!("<!DOCTYPE html>");
+        outln!("<html><head><meta charset=\"utf-8\"><title>DocSearcher Batch Results</title></head><body>");
+        outln!("<h1>Batch Search Results</h1>");
+        let snippets = SnippetWidth::of(&options.settings);
+        outln!(
+            "<table border='1'><tr><th>Term</th><th>Metadata</th><th>Count</th><th>Section</th><th>File</th><th>Needles list</th>{}{}</tr>",
+            Self::html_snippet_header(snippets),
+            Self::html_disposition_headers(options)
+        );
+
+        results.try_for_each(&mut |ResultRow { record, contexts, source }| {
+            let source = source.as_ref().map(|list| list.to_string_lossy().into_owned()).unwrap_or_default();
+            let note = Self::concealed_note(contexts);
+            outln!(
+                "<tr><td>{}<em>{}</em></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}{}</tr>",
+                escape_html(&record.term),
+                note,
+                escape_html(&record.metadata),
+                record.count(),
+                escape_html(&record.section().unwrap_or_default()),
+                escape_html(&record.source_label()),
+                escape_html(&source),
+                Self::html_snippet_cell(record, snippets),
+                Self::html_disposition_cells(record, options)
+            );
+            Ok(())
+        })?;
+
+        outln!("</table></body></html>");
+        Ok(())
+    }
+
+    /// Add the document `rows` were found in to an html-full report,
+    /// extracting its text again for the excerpts
+    fn add_full_html_document(
+        report: &mut FullReport,
+        rows: &[&ResultRow],
+        options: &CommandOptions,
+        run: &RunInfo,
+    ) -> Result<()> {
+        let Some(first) = rows.first() else {
+            return Ok(());
+        };
+        let path = first.record.source.outermost();
+        let mut details = Vec::new();
+        if let Ok(metadata) = std::fs::metadata(path) {
+            details.push(("Size", format!("{} bytes", metadata.len())));
+        }
+
+        // Matches inside archive entries only show their line
+        let blocks = match parse_filetype(path) {
+            Ok(file_type) if !archive::is_container(path) => {
+                details.push(("Type", file_type.name().to_string()));
+                let extract = options.settings.extract_options(run.matcher.strategy)?;
+                match std::fs::read(path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|bytes| batch::extract_blocks(path, &bytes, extract))
+                {
+                    Ok(blocks) => Some(blocks),
+                    Err(e) => {
+                        details.push((
+                            "Excerpts",
+                            format!(
+                                "matching lines only; extracting the text again failed: {}",
+                                e
+                            ),
+                        ));
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        if let Some(blocks) = &blocks {
+            details.push(("Blocks extracted", blocks.len().to_string()));
+            if let Some(pages) = blocks
+                .iter()
+                .filter_map(|block| block.location.page())
+                .max()
+            {
+                details.push(("Pages", pages.to_string()));
+            }
+        }
+
+        let matches: Vec<MatchRecord> = rows.iter().map(|row| row.record.clone()).collect();
+        report.add_document(&html_full::Document {
+            path: &path.to_string_lossy(),
+            details,
+            blocks: blocks.as_deref(),
+            matches: &matches,
+        });
+        Ok(())
+    }
+
+    /// Print an html-full report, with how the run was made in its footer
+    fn print_full_html(
+        report: FullReport,
+        title: &str,
+        searched: usize,
+        options: &CommandOptions,
+        run: &RunInfo,
+    ) {
+        let invocation = [
+            (
+                "Generated by",
+                format!("docsearcher {}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("Command", std::env::args().collect::<Vec<_>>().join(" ")),
+            ("Documents searched", searched.to_string()),
+            ("Needles valid on", run.needles.as_of.to_string()),
+            ("Needle lookup", run.matcher.strategy.to_string()),
+            (
+                "Settings",
+                serde_json::to_string(&options.settings).unwrap_or_default(),
+            ),
+        ];
+        outln!("{}", report.finish(title, &invocation).trim_end());
+    }
+
+    fn html_snippet_header(snippets: Option<SnippetWidth>) -> &'static str {
+        match snippets {
+            Some(_) => "<th>Context</th>",
+            None => "",
+        }
+    }
+
+    fn html_snippet_cell(record: &MatchRecord, snippets: Option<SnippetWidth>) -> String {
+        match snippets {
+            Some(width) => format!("<td>{}</td>", Self::snippet_html(record, width)),
+            None => String::new(),
+        }
+    }
+
+    fn html_disposition_headers(options: &CommandOptions) -> &'static str {
+        if options.annotated() {
+            "<th>Disposition</th><th>Note</th>"
+        } else {
+            ""
+        }
+    }
+
+    fn html_disposition_cells(record: &MatchRecord, options: &CommandOptions) -> String {
+        if !options.annotated() {
+            return String::new();
+        }
+        let [disposition, note] = Self::disposition_cells(record);
+        format!(
+            "<td>{}</td><td>{}</td>",
+            escape_html(&disposition),
+            escape_html(&note)
+        )
+    }
+
+    fn show_help() {
+        outln!("{}", "DocSearcher - Document Search Tool".blue().bold());
+        outln!();
+        outln!("Usage:");
+        outln!("  docsearcher <needles_file> <document_file>");
+        outln!("  docsearcher --interactive");
+        outln!("  docsearcher --tui");
+        outln!("  docsearcher search <needles_file> <document_file>");
+        outln!("  docsearcher batch <directory> <needles_file>");
+        outln!("  docsearcher validate <needles_file> <document_file>");
+        outln!("  docsearcher info <file>");
+        outln!();
+        outln!("Examples:");
+        outln!("  docsearcher contacts.csv document.docx");
+        outln!("  docsearcher --interactive");
+        outln!("  docsearcher --tui");
+        outln!("  docsearcher search contacts.csv report.pdf --format json");
+        outln!("  docsearcher batch ./documents contacts.csv --pattern *.pdf");
+        outln!("  docsearcher validate contacts.csv document.docx");
+        outln!("  docsearcher info report.pdf");
+        outln!();
+        outln!("For more help, run: docsearcher --help");
+    }
+
+    fn show_startup_logo() {
+        let logo = r#"
+ ____             ____                      _               
+|  _ \  ___   ___/ ___|  ___  __ _ _ __ ___| |__   ___ _ __ 
+| | | |/ _ \ / __\___ \ / _ \/ _` | '__/ __| '_ \ / _ \ '__|
+| |_| | (_) | (__ ___) |  __/ (_| | | | (__| | | |  __/ |  
+|____/ \___/ \___|____/ \___|\__,_|_|  \___|_| |_|\___|_|  
+"#;
+        outln!("{}", logo);
+        outln!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, triage::ResultsFile};
+
+    fn dispatch(args: &[&str]) -> Result<Outcome> {
+        let cli =
+            EnhancedCli::parse_from(std::iter::once("docsearcher").chain(args.iter().copied()));
+        CliApp::dispatch(&CliApp { cli })
+    }
+
+    /// A needles file holding only comments, and a document to search
+    fn comments_only(dir: &Path) -> (String, String) {
+        let needles = dir.join("needles.csv");
+        std::fs::write(&needles, "# contacts for this run\n\n# none today\n").unwrap();
+        let document = dir.join("report.docx");
+        std::fs::write(&document, fixtures::docx(&["Alice Johnson"])).unwrap();
+
+        (
+            needles.to_string_lossy().into_owned(),
+            document.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[test]
+    fn test_search_with_comment_only_needles() {
+        let dir = tempfile::tempdir().unwrap();
+        let (needles, document) = comments_only(dir.path());
+
+        let err = dispatch(&["search", &needles, &document])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("no needles (2 comment, 1 blank and 0 malformed lines)"),
+            "{}",
+            err
+        );
+        assert!(err.contains("--allow-empty-needles"), "{}", err);
+
+        let outcome = dispatch(&["search", &needles, &document, "--allow-empty-needles"]).unwrap();
+        assert_eq!(outcome, Outcome::NoMatches);
+        assert_eq!(outcome.code(), 1);
+    }
+
+    #[test]
+    fn test_batch_checks_needles_before_collecting_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (needles, _) = comments_only(dir.path());
+        let missing = dir.path().join("missing").to_string_lossy().into_owned();
+
+        // The missing directory would fail too; the needles are checked first
+        let err = dispatch(&["batch", "-n", &needles, "-d", &missing])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("contains no needles"), "{}", err);
+
+        let outcome = dispatch(&[
+            "batch",
+            "-n",
+            &needles,
+            "-d",
+            &missing,
+            "--allow-empty-needles",
+        ])
+        .unwrap();
+        assert_eq!(outcome, Outcome::NoMatches);
+    }
+
+    #[test]
+    fn test_validate_reports_comment_only_needles_as_finding() {
+        let dir = tempfile::tempdir().unwrap();
+        let (needles, document) = comments_only(dir.path());
+
+        assert_eq!(
+            dispatch(&["validate", &needles, &document]).unwrap(),
+            Outcome::Success
+        );
+    }
+
+    #[test]
+    fn test_compile_refuses_comment_only_needles() {
+        let dir = tempfile::tempdir().unwrap();
+        let (needles, _) = comments_only(dir.path());
+        let output = dir.path().join("needles.nidx");
+
+        let err = dispatch(&[
+            "needles",
+            "compile",
+            &needles,
+            "-o",
+            &output.to_string_lossy(),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("2 comment"), "{}", err);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_needles_merge_diff_and_convert() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(
+            path("a.csv"),
+            "Alice Johnson;id=1\nBob Smith;id=2\nAlice Johnson;id=1\n",
+        )
+        .unwrap();
+        std::fs::write(path("b.json"), r#"[{"term": "Bob Smith", "metadata": "id=20"}, {"term": "Carol; Diaz", "metadata": ""}]"#).unwrap();
+
+        dispatch(&[
+            "needles",
+            "merge",
+            &path("a.csv"),
+            &path("b.json"),
+            "-o",
+            &path("merged.csv"),
+        ])
+        .unwrap();
+        let merged = std::fs::read_to_string(path("merged.csv")).unwrap();
+        assert_eq!(
+            merged,
+            format!(
+                "# from {}\nAlice Johnson;id=1\n# Bob Smith also in {} as \"id=20\"\nBob Smith;id=2\n# from {}\n\"Carol; Diaz\";\"\"\n",
+                path("a.csv"),
+                path("b.json"),
+                path("b.json")
+            )
+        );
+        let err = dispatch(&[
+            "needles",
+            "merge",
+            &path("a.csv"),
+            &path("b.json"),
+            "--on-duplicate",
+            "error",
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("--on-duplicate"), "{}", err);
+
+        let diff = crate::output::capture(|| {
+            dispatch(&["needles", "diff", &path("a.csv"), &path("merged.csv")]).unwrap();
+            dispatch(&["needles", "diff", &path("merged.csv"), &path("b.json")]).unwrap();
+        });
+        assert_eq!(
+            diff,
+            "+ \"Carol; Diaz\";\"\"\n1 added, 0 removed, 0 with other metadata\n\
+             - Alice Johnson;id=1\n~ Bob Smith: \"id=2\" -> \"id=20\"\n0 added, 1 removed, 1 with other metadata\n"
+        );
+
+        // Lossless both ways, with the delimiter read from the input
+        dispatch(&[
+            "needles",
+            "convert",
+            &path("merged.csv"),
+            "-o",
+            &path("merged.json"),
+        ])
+        .unwrap();
+        dispatch(&[
+            "needles",
+            "convert",
+            &path("merged.json"),
+            "--to",
+            "csv",
+            "--output-delimiter",
+            "\\t",
+            "-o",
+            &path("back.tsv"),
+        ])
+        .unwrap();
+        let expected = read_needles_from_file(&path("merged.csv"), None).unwrap();
+        assert_eq!(
+            NeedleList::read(Path::new(&path("merged.json")), None)
+                .unwrap()
+                .needles,
+            expected
+        );
+        assert_eq!(
+            read_needles_from_file(&path("back.tsv"), None).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_search_exit_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, document) = comments_only(dir.path());
+        let needles = dir.path().join("contacts.csv");
+
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        assert_eq!(
+            dispatch(&["search", &needles.to_string_lossy(), &document]).unwrap(),
+            Outcome::Success
+        );
+
+        std::fs::write(&needles, "Bob Smith,id=2\n").unwrap();
+        assert_eq!(
+            dispatch(&["search", &needles.to_string_lossy(), &document]).unwrap(),
+            Outcome::NoMatches
+        );
+    }
+
+    #[test]
+    fn test_text_and_csv_results_name_the_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("contacts.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        let report = dir.path().join("report.pdf");
+        std::fs::write(
+            &report,
+            fixtures::pdf(&[
+                &["Alice Johnson"],
+                &["Bob Smith"],
+                &["Alice Johnson again", "Alice Johnson"],
+            ]),
+        )
+        .unwrap();
+        let memo = dir.path().join("memo.docx");
+        let body = "<w:p><w:r><w:t>Cover</w:t></w:r></w:p><w:p><w:r><w:br w:type=\"page\"/><w:t>Alice Johnson</w:t></w:r></w:p>";
+        std::fs::write(&memo, fixtures::docx_from_body_xml(body)).unwrap();
+
+        let needles = needles.to_string_lossy();
+        let search = |document: &Path, format: &str| {
+            crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(&[
+                        "search",
+                        &needles,
+                        &document.to_string_lossy(),
+                        "-f",
+                        format
+                    ])
+                    .unwrap(),
+                    Outcome::Success
+                );
+            })
+        };
+        let text = console::strip_ansi_codes(&search(&report, "text")).into_owned();
+        assert!(
+            text.contains("Alice Johnson → id=1 ×3 (pages 1, 3)"),
+            "{}",
+            text
+        );
+        assert!(text.contains("Bob Smith → id=2 (page 2)"), "{}", text);
+        let csv = search(&report, "csv");
+        assert!(
+            csv.contains("term,metadata,context_kind,pages,count,section,context_snippet\n"),
+            "{}",
+            csv
+        );
+        assert!(
+            csv.contains("Alice Johnson,id=1,prose,1;3,3,,Alice Johnson\n"),
+            "{}",
+            csv
+        );
+
+        // DOCX pages are counted at page breaks
+        let text = console::strip_ansi_codes(&search(&memo, "text")).into_owned();
+        assert!(
+            text.contains("Alice Johnson → id=1 (page 2) (paragraph 2)\n"),
+            "{}",
+            text
+        );
+        assert!(search(&memo, "csv")
+            .contains("Alice Johnson,id=1,prose,2,1,paragraph 2,Alice Johnson\n"));
+    }
+
+    #[test]
+    fn test_results_show_the_text_around_each_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("contacts.csv");
+        std::fs::write(&needles, "Johnson,id=1\n").unwrap();
+        let line = format!(
+            "{} supplied by Johnson Controls Inc. under the 2019 framework agreement {}",
+            "x".repeat(60),
+            "y".repeat(60)
+        );
+        let document = dir.path().join("report.docx");
+        std::fs::write(&document, fixtures::docx(&[&line])).unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let search = |args: &[&str]| {
+            crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(&[&["search", &needles, &document], args].concat()).unwrap(),
+                    Outcome::Success
+                );
+            })
+        };
+        let snippet =
+            "xxxxxxxxxxxxxxxxxxxxxxx supplied by Johnson Controls Inc. under the 2019 framewo";
+
+        let text = console::strip_ansi_codes(&search(&[])).into_owned();
+        assert!(
+            text.contains(&format!(
+                "Johnson → id=1 (page 1) (paragraph 1)\n       {}\n",
+                snippet
+            )),
+            "{}",
+            text
+        );
+        assert!(!console::strip_ansi_codes(&search(&["--no-context"])).contains(snippet));
+
+        let report = json_report(&search(&["-f", "json"]));
+        let result = &report["results"][0];
+        assert_eq!(result["context_snippet"], snippet);
+        let [start, end] = [0, 1].map(|i| result["context_span"][i].as_u64().unwrap() as usize);
+        assert_eq!(&snippet[start..end], "Johnson");
+        assert!(
+            json_report(&search(&["-f", "json", "--no-context"]))["results"][0]
+                .get("context_snippet")
+                .is_none()
+        );
+
+        assert!(search(&["-f", "csv"])
+            .contains(&format!("Johnson,id=1,prose,1,1,paragraph 1,{}\n", snippet)));
+        assert!(search(&["-f", "csv", "--no-context"])
+            .contains("Johnson,id=1,prose,1,1,paragraph 1,\n"));
+    }
+
+    #[test]
+    fn test_json_offsets_point_into_the_dumped_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Johnson,id=1\n").unwrap();
+        let document = dir.path().join("letter.docx");
+        std::fs::write(
+            &document,
+            fixtures::docx(&["Dear Ms Johnson,", "Ms Johnson and Mr Johnson agree."]),
+        )
+        .unwrap();
+        let dump = dir.path().join("letter.txt");
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let output = crate::output::capture(|| {
+            let args = [
+                "search",
+                &needles,
+                &document,
+                "-f",
+                "json",
+                "--dump-text",
+                &dump.to_string_lossy(),
+            ];
+            assert_eq!(dispatch(&args).unwrap(), Outcome::Success);
+        });
+        let report = json_report(&output);
+        let offsets: Vec<[usize; 2]> = report["results"][0]["occurrences"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|occurrence| serde_json::from_value(occurrence["offset"].clone()).unwrap())
+            .collect();
+        assert_eq!(offsets, [[8, 15], [20, 27], [35, 42]]);
+
+        let text: Vec<char> = std::fs::read_to_string(&dump).unwrap().chars().collect();
+        for [start, end] in offsets {
+            assert_eq!(text[start..end].iter().collect::<String>(), "Johnson");
+        }
+    }
+
+    #[test]
+    fn test_xlsx_workbooks_are_searched_alone_and_in_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n5551234,phone\n").unwrap();
+        let workbook = dir.path().join("contacts.xlsx");
+        std::fs::write(
+            &workbook,
+            fixtures::xlsx(&[(
+                "People",
+                &[&["Name", "Phone"], &["Alice Johnson", "5551234"]],
+            )]),
+        )
+        .unwrap();
+
+        let (needles, workbook) = (needles.to_string_lossy(), workbook.to_string_lossy());
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &workbook, "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        let parts: Vec<serde_json::Value> = json_report(&output)["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["occurrences"][0]["part"].clone())
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                serde_json::json!({"cell": {"sheet": "People", "row": 2, "col": 2}}),
+                serde_json::json!({"cell": {"sheet": "People", "row": 2, "col": 1}}),
+            ]
+        );
+
+        let root = dir.path().to_string_lossy();
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["batch", "-n", &needles, "-d", &root, "-f", "csv"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        assert!(output.contains("Alice Johnson,id=1,"), "{}", output);
+        assert!(output.contains("5551234,phone,"), "{}", output);
+    }
+
+    #[test]
+    fn test_docx_results_name_the_paragraph_and_its_headings() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("contacts.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let paragraph = |style: &str, text: &str| {
+            let style = if style.is_empty() {
+                String::new()
+            } else {
+                format!("<w:pPr><w:pStyle w:val=\"{}\"/></w:pPr>", style)
+            };
+            format!("<w:p>{}<w:r><w:t>{}</w:t></w:r></w:p>", style, text)
+        };
+        let body = [
+            paragraph("Heading1", "Contracts"),
+            paragraph("", "Recitals."),
+            paragraph("Heading2", "Termination &amp; Notice"),
+            paragraph("", "Notice goes to Alice Johnson."),
+        ]
+        .concat();
+        let document = dir.path().join("contract.docx");
+        std::fs::write(&document, fixtures::docx_from_body_xml(&body)).unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let search = |format: &str| {
+            crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(&["search", &needles, &document, "-f", format, "--no-context"])
+                        .unwrap(),
+                    Outcome::Success
+                );
+            })
+        };
+
+        let text = console::strip_ansi_codes(&search("text")).into_owned();
+        assert!(text.contains("Alice Johnson → id=1 (page 1) (paragraph 4 under Contracts › Termination & Notice)\n"), "{}", text);
+        let occurrence = &json_report(&search("json"))["results"][0]["occurrences"][0];
+        assert_eq!(occurrence["paragraph"], 4);
+        assert_eq!(
+            occurrence["headings"],
+            serde_json::json!(["Contracts", "Termination & Notice"])
+        );
+        assert!(search("csv").contains(
+            "Alice Johnson,id=1,prose,1,1,paragraph 4 under Contracts › Termination & Notice,\n"
+        ));
+        assert!(search("html")
+            .contains("<td>paragraph 4 under Contracts › Termination &amp; Notice</td>"));
+    }
+
+    #[test]
+    fn test_context_sets_the_characters_shown_either_side_of_a_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("contacts.csv");
+        std::fs::write(&needles, "Johnson,id=1\n").unwrap();
+        let document = dir.path().join("report.docx");
+        std::fs::write(
+            &document,
+            fixtures::docx(&["Parts <supplied> by Johnson & Sons under the agreement"]),
+        )
+        .unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let search = |args: &[&str]| {
+            crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(&[&["search", &needles, &document, "--context", "12"], args].concat())
+                        .unwrap(),
+                    Outcome::Success
+                );
+            })
+        };
+
+        let snippet = "upplied> by Johnson & Sons unde";
+        assert_eq!(
+            json_report(&search(&["-f", "json"]))["results"][0]["context_snippet"],
+            snippet
+        );
+        assert!(search(&["-f", "csv"])
+            .contains(&format!("Johnson,id=1,prose,1,1,paragraph 1,{}\n", snippet)));
+
+        let html = search(&["-f", "html"]);
+        assert!(html.contains("<th>Context</th>"), "{}", html);
+        assert!(
+            html.contains("<td>upplied&gt; by <mark>Johnson</mark> &amp; Sons unde</td>"),
+            "{}",
+            html
+        );
+        assert!(!search(&["-f", "html", "--no-context"]).contains("<th>Context</th>"));
+    }
+
+    #[test]
+    fn test_collapse_repeats_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let footers: Vec<String> = (1..=3)
+            .map(|page| format!("Alice Johnson - Page {}", page))
+            .collect();
+        let pages: Vec<[&str; 1]> = footers.iter().map(|footer| [footer.as_str()]).collect();
+        let pages: Vec<&[&str]> = pages.iter().map(|page| &page[..]).collect();
+        let document = dir.path().join("footer.pdf");
+        std::fs::write(&document, fixtures::pdf(&pages)).unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        for format in ["text", "json", "csv"] {
+            let outcome = dispatch(&[
+                "search",
+                &needles,
+                &document,
+                "--collapse-repeats",
+                "-f",
+                format,
+            ])
+            .unwrap();
+            assert_eq!(outcome, Outcome::Success);
+        }
+    }
+
+    #[test]
+    fn test_ndjson_exit_code_follows_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        for i in 0..3 {
+            std::fs::write(
+                dir.path().join(format!("doc{}.docx", i)),
+                fixtures::docx(&["Contact Alice Johnson"]),
+            )
+            .unwrap();
+        }
+        let (needles, directory) = (needles.to_string_lossy(), dir.path().to_string_lossy());
+
+        let batch = [
+            "batch", "-d", &directory, "-p", "*.docx", "-n", &needles, "-f", "ndjson",
+        ];
+        assert_eq!(dispatch(&batch).unwrap(), Outcome::Success);
+        let document = dir.path().join("doc0.docx");
+        assert_eq!(
+            dispatch(&[
+                "search",
+                &needles,
+                &document.to_string_lossy(),
+                "-f",
+                "ndjson"
+            ])
+            .unwrap(),
+            Outcome::Success
+        );
+
+        std::fs::write(dir.path().join("needles.csv"), "Bob Smith,id=2\n").unwrap();
+        assert_eq!(dispatch(&batch).unwrap(), Outcome::NoMatches);
+        assert!(dispatch(&[
+            "search",
+            &needles,
+            &document.to_string_lossy(),
+            "-f",
+            "ndjson",
+            "--collapse-repeats"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_interactive_mode_searches_the_typed_terms() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("a.docx");
+        std::fs::write(
+            &document,
+            fixtures::docx(&["Signed for ACME CORP", "Acme Corporation"]),
+        )
+        .unwrap();
+        let skipped = dir.path().join("notes.bin");
+        std::fs::write(&skipped, "Acme Corp").unwrap();
+        let broken = dir.path().join("broken.docx");
+        std::fs::write(&broken, "not a zip").unwrap();
+        let terms = CliApp::parse_typed_terms("Acme Corp, typed");
+        assert_eq!(terms, [("Acme Corp".to_string(), "typed".to_string())]);
+
+        let found = |case_sensitive, whole_word| -> Vec<(String, u32)> {
+            let extract = SearchSettings {
+                case_sensitive,
+                whole_word,
+                ..SearchSettings::default()
+            }
+            .extract_options(Strategy::default())
+            .unwrap();
+            let files = [broken.clone(), document.clone(), skipped.clone()];
+            let (found, failures) =
+                CliApp::search_typed_terms(&terms, &files, extract, &mut NullReporter);
+            assert_eq!(
+                found.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+                [&document]
+            );
+            assert_eq!(
+                failures
+                    .iter()
+                    .map(|failure| failure.file.as_str())
+                    .collect::<Vec<_>>(),
+                [broken.to_string_lossy()]
+            );
+            found[0]
+                .1
+                .iter()
+                .map(|result| {
+                    (
+                        format!("{} -> {}", result.term, result.metadata),
+                        result.occurrences,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(found(false, false), [("Acme Corp -> typed".to_string(), 2)]);
+        assert_eq!(found(true, false), [("Acme Corp -> typed".to_string(), 1)]);
+        assert!(found(true, true).is_empty());
+    }
+
+    #[test]
+    fn test_typed_terms_are_read_in_term_and_metadata_pairs() {
+        let pairs = |input| {
+            CliApp::parse_typed_terms(input)
+                .into_iter()
+                .map(|(term, metadata)| format!("{}={}", term, metadata))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pairs("Alice Johnson,id=1,Bob Smith,id=2"),
+            ["Alice Johnson=id=1", "Bob Smith=id=2"]
+        );
+        assert_eq!(pairs(" Alice , id=1 , Bob "), ["Alice=id=1", "Bob="]);
+        assert_eq!(pairs("Alice,,Bob,id=2"), ["Alice=", "Bob=id=2"]);
+        assert!(pairs("").is_empty() && pairs(",x").is_empty());
+    }
+
+    #[test]
+    fn test_settings_round_trip_cli_tui() {
+        let args = [
+            "docsearcher",
+            "search",
+            "needles.csv",
+            "report.docx",
+            "--case-sensitive",
+            "--whole-word",
+            "--ignore-accents",
+            "--no-normalize",
+            "--case-fold",
+            "turkic",
+            "--fuzzy",
+            "2",
+            "--and-same-paragraph",
+            "-f",
+            "CSV",
+            "--csv-unsafe",
+            "--no-context",
+            "--context",
+            "40",
+            "--ignore-context",
+            "email,url",
+            "--collapse-repeats",
+            "--no-dedup",
+            "--metadata-format",
+            "kv",
+            "--where",
+            "dept=legal",
+            "--where",
+            "note=\"a, b\"",
+            "--include-link-targets",
+            "--include-hidden-text",
+            "--search-meta",
+            "--as-of",
+            "2024-06-01",
+            "--stopwords",
+            "stopwords.txt",
+            "--stopword-mode",
+            "contains",
+            "--needle-delimiter",
+            "\\t",
+            "--no-retry-detect",
+            "--matcher",
+            "automaton",
+        ];
+        let Some(Commands::Search { settings, .. }) = EnhancedCli::parse_from(args).command else {
+            panic!("expected the search command");
+        };
+
+        // A literal without `..`, so a new setting has to be added here too
+        let expected = SearchSettings {
+            case_sensitive: true,
+            whole_word: true,
+            ignore_accents: true,
+            no_normalize: true,
+            // Cannot be combined with `--fuzzy`
+            regex_needles: false,
+            case_fold: CaseFolding::Turkic,
+            fuzzy: 2,
+            and_same_paragraph: true,
+            format: OutputFormat::Csv,
+            csv_unsafe: true,
+            no_context: true,
+            context: Some(40),
+            ignore_context: vec![ContextKind::Email, ContextKind::Url],
+            collapse_repeats: true,
+            no_dedup: true,
+            dedup: false,
+            metadata_format: MetadataFormat::Kv,
+            filters: vec!["dept=legal".to_string(), "note=\"a, b\"".to_string()],
+            include_link_targets: true,
+            include_hidden_text: true,
+            search_meta: true,
+            as_of: Some("2024-06-01".to_string()),
+            stopwords: Some(PathBuf::from("stopwords.txt")),
+            stopword_mode: StopwordMode::Contains,
+            needle_delimiter: Some("\\t".parse().unwrap()),
+            no_retry_detect: true,
+            matcher: MatcherChoice::Automaton,
+        };
+        assert_eq!(settings, expected);
+
+        let tui = TuiApp::with_settings(settings);
+        assert_eq!(
+            SearchSettings::from_entries(tui.settings.entries()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            SearchSettings::from_entries(SearchSettings::default().entries()).unwrap(),
+            SearchSettings::default()
+        );
+    }
+
+    #[test]
+    fn test_invalid_settings_are_refused_before_searching() {
+        let dir = tempfile::tempdir().unwrap();
+        let (needles, document) = comments_only(dir.path());
+
+        let err = dispatch(&["search", &needles, &document, "--where", "dept"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("dept"), "{}", err);
+        let err = dispatch(&[
+            "search",
+            &needles,
+            &document,
+            "--collapse-repeats",
+            "-f",
+            "html",
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("html"), "{}", err);
+        let err = dispatch(&[
+            "search",
+            &needles,
+            &document,
+            "--regex-needles",
+            "--fuzzy",
+            "1",
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("--fuzzy"), "{}", err);
+    }
+
+    #[test]
+    fn test_batch_passes_extract_options_to_the_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Bob Smith,id=2\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        let body = "<w:p><w:r><w:rPr><w:vanish/></w:rPr><w:t>Bob Smith</w:t></w:r></w:p>";
+        std::fs::write(
+            documents.join("memo.docx"),
+            fixtures::docx_from_body_xml(body),
+        )
+        .unwrap();
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let args = ["batch", "-n", &needles, "-d", &documents];
+        assert_eq!(dispatch(&args).unwrap(), Outcome::NoMatches);
+        assert_eq!(
+            dispatch(&[&args[..], &["--include-hidden-text"]].concat()).unwrap(),
+            Outcome::Success
+        );
+    }
+
+    #[test]
+    fn test_search_and_batch_honor_whole_word() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Ann,id=1\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        let annual = documents.join("annual.docx");
+        std::fs::write(&annual, fixtures::docx(&["Annual report for Annabelle"])).unwrap();
+        std::fs::write(
+            documents.join("letter.pdf"),
+            fixtures::pdf(&[&["Dear Ann,"], &["Signed, Ann."]]),
+        )
+        .unwrap();
+
+        let (needles, annual, documents) = (
+            needles.to_string_lossy(),
+            annual.to_string_lossy(),
+            documents.to_string_lossy(),
+        );
+        assert_eq!(
+            dispatch(&["search", &needles, &annual]).unwrap(),
+            Outcome::Success
+        );
+        assert_eq!(
+            dispatch(&["search", &needles, &annual, "--whole-word"]).unwrap(),
+            Outcome::NoMatches
+        );
+
+        let output = crate::output::capture(|| {
+            let args = [
+                "batch",
+                "-n",
+                &needles,
+                "-d",
+                &documents,
+                "-p",
+                "*",
+                "--whole-word",
+                "-f",
+                "json",
+            ];
+            assert_eq!(dispatch(&args).unwrap(), Outcome::Success);
+        });
+        let report = json_report(&output);
+        let files: Vec<&str> = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("letter.pdf"), "{:?}", files);
+        assert_eq!(
+            report["results"][0]["occurrences"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_results_name_the_text_matched() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let document = dir.path().join("letter.docx");
+        std::fs::write(&document, fixtures::docx(&["Dear Alice Jonson,"])).unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        assert_eq!(
+            dispatch(&["search", &needles, &document]).unwrap(),
+            Outcome::NoMatches
+        );
+
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "--fuzzy", "1"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        assert!(
+            console::strip_ansi_codes(&output).contains(
+                r#"Alice Johnson → id=1 (page 1) (paragraph 1) (fuzzy: "Alice Jonson", 1 edit)"#
+            ),
+            "{}",
+            output
+        );
+
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "--fuzzy", "1", "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        let report = json_report(&output);
+        let approximate = &report["results"][0]["occurrences"][0]["approximate"];
+        assert_eq!(approximate["matched"], "Alice Jonson");
+        assert_eq!(approximate["distance"], 1);
+        assert_eq!(report["results"][0]["distance_score"], 1.0 / 13.0_f32);
+    }
+
+    #[test]
+    fn test_results_show_the_matching_options_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let document = dir.path().join("memo.docx");
+        std::fs::write(&document, fixtures::docx(&["Signed by Alice Johnson"])).unwrap();
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+
+        let banner = |flags: &[&str]| {
+            let output = crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(&[&["search", &needles, &document][..], flags].concat()).unwrap(),
+                    Outcome::Success
+                );
+            });
+            let output = console::strip_ansi_codes(&output).into_owned();
+            (
+                output.contains("Case sensitive: yes"),
+                output.contains("Whole word: yes"),
+                output.contains("N/A"),
+            )
+        };
+        assert_eq!(banner(&[]), (false, false, false));
+        assert_eq!(
+            banner(&["--case-sensitive", "--whole-word"]),
+            (true, true, false)
+        );
+    }
+
+    #[test]
+    fn test_weighted_needles_are_listed_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(
+            &needles,
+            "Alice Johnson,id=1\nBob Smith,id=2,10\nCarol Diaz,id=3,-1\nDave Lee,id=4\n",
+        )
+        .unwrap();
+        let document = dir.path().join("memo.docx");
+        std::fs::write(
+            &document,
+            fixtures::docx(&["Carol Diaz, Alice Johnson, Dave Lee and Bob Smith"]),
+        )
+        .unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        let report = json_report(&output);
+        let results = report["results"].as_array().unwrap();
+        let found: Vec<(&str, &str)> = results
+            .iter()
+            .map(|result| {
+                (
+                    result["term"].as_str().unwrap(),
+                    result["metadata"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Bob Smith", "id=2"),
+                ("Alice Johnson", "id=1"),
+                ("Dave Lee", "id=4"),
+                ("Carol Diaz", "id=3")
+            ]
+        );
+        assert_eq!(results[0]["weight"], 10);
+        assert!(results[1].get("weight").is_none());
+
+        // Compiled, the needles keep their weights and their results the order
+        let compiled = dir
+            .path()
+            .join("needles.nidx")
+            .to_string_lossy()
+            .into_owned();
+        crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["needles", "compile", &needles, "-o", &compiled]).unwrap(),
+                Outcome::Success
+            );
+        });
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &compiled, &document, "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        assert_eq!(json_report(&output)["results"], report["results"]);
+
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "--no-context"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        assert!(
+            console::strip_ansi_codes(&output)
+                .contains("1: Bob Smith → id=2 (page 1) (paragraph 1) (weight 10)"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_results_count_every_time_a_needle_was_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        let document = dir.path().join("minutes.docx");
+        let paragraphs = [
+            "Bob Smith opened the meeting.",
+            "Alice Johnson seconded.",
+            "Bob Smith asked Bob Smith's deputy to minute it.",
+        ];
+        std::fs::write(&document, fixtures::docx(&paragraphs)).unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let run = |format: &str| {
+            crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(&["search", &needles, &document, "--no-context", "-f", format])
+                        .unwrap(),
+                    Outcome::Success
+                );
+            })
+        };
+        let report = json_report(&run("json"));
+        let counts: Vec<(&str, u64)> = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                (
+                    result["term"].as_str().unwrap(),
+                    result["count"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(counts, [("Alice Johnson", 1), ("Bob Smith", 3)]);
+
+        let csv = run("csv");
+        assert!(
+            csv.contains("term,metadata,context_kind,pages,count,section,context_snippet\n"),
+            "{}",
+            csv
+        );
+        assert!(
+            csv.contains("Bob Smith,id=2,prose,1,3,\"paragraphs 1, 3\",\n"),
+            "{}",
+            csv
+        );
+        assert!(run("html").contains("<td>id=2</td><td>3</td>"));
+        let text = console::strip_ansi_codes(&run("text")).into_owned();
+        assert!(text.contains("Bob Smith → id=2 ×3 (page 1)"), "{}", text);
+        assert!(text.contains("Alice Johnson → id=1 (page 1)"), "{}", text);
+
+        // The library and the --report-dir pages count the same
+        let found =
+            crate::parsers::docx::parse_from_path(&needles, &document, ExtractOptions::default())
+                .unwrap();
+        let counts: Vec<(&str, u32)> = found
+            .iter()
+            .map(|result| (result.term.as_str(), result.occurrences))
+            .collect();
+        assert_eq!(counts, [("Alice Johnson", 1), ("Bob Smith", 3)]);
+        let report_dir = dir.path().join("report");
+        let documents = dir.path().to_string_lossy();
+        crate::output::capture(|| {
+            dispatch(&[
+                "batch",
+                "-n",
+                &needles,
+                "-d",
+                &documents,
+                "--report-dir",
+                &report_dir.to_string_lossy(),
+            ])
+            .unwrap();
+        });
+        let pages: String = std::fs::read_dir(&report_dir)
+            .unwrap()
+            .map(|page| std::fs::read_to_string(page.unwrap().path()).unwrap())
+            .collect();
+        assert!(
+            pages.contains("<td>Bob Smith</td><td>id=2</td><td>3</td>"),
+            "{}",
+            pages
+        );
+    }
+
+    #[test]
+    fn test_batch_reports_each_file_with_its_count_unless_deduplicated() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Bob Smith,id=2\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        std::fs::write(
+            documents.join("a.docx"),
+            fixtures::docx(&["Bob Smith", "Minutes", "Bob Smith", "Bob Smith"]),
+        )
+        .unwrap();
+        std::fs::write(
+            documents.join("b.docx"),
+            fixtures::docx(&["Signed, Bob Smith"]),
+        )
+        .unwrap();
+        std::fs::write(documents.join("c.docx"), fixtures::docx(&["Nobody"])).unwrap();
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let run = |extra: &[&str]| {
+            crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(
+                        &[
+                            &["batch", "-n", &needles, "-d", &documents, "--threads", "1"],
+                            extra
+                        ]
+                        .concat()
+                    )
+                    .unwrap(),
+                    Outcome::Success
+                );
+            })
+        };
+        let report = json_report(&run(&["-f", "json"]));
+        let rows: Vec<(String, u64)> = report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                (
+                    result["file"]
+                        .as_str()
+                        .unwrap()
+                        .rsplit(['/', '\\'])
+                        .next()
+                        .unwrap()
+                        .to_string(),
+                    result["count"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(rows, [("a.docx".to_string(), 3), ("b.docx".to_string(), 1)]);
+        assert_eq!(
+            report["summary"],
+            serde_json::json!({"files": 3, "files_with_matches": 2, "matches": 2, "occurrences": 4})
+        );
+
+        let text = run(&[]);
+        assert!(text.contains("Files with matches: 2\n"), "{}", text);
+        assert!(text.contains("Total occurrences: 4\n"), "{}", text);
+
+        let report = json_report(&run(&["-f", "json", "--dedup"]));
+        assert_eq!(report["results"].as_array().unwrap().len(), 1);
+        assert_eq!(report["summary"]["matches"], 1);
+        assert_eq!(report["summary"]["occurrences"], 4);
+    }
+
+    #[test]
+    fn test_parallel_batch_reports_what_a_sequential_one_does() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        for i in 0..24 {
+            let paragraphs = match i % 3 {
+                0 => vec!["Alice Johnson"],
+                1 => vec!["Bob Smith", "and Alice Johnson"],
+                _ => vec!["Nobody"],
+            };
+            std::fs::write(
+                documents.join(format!("{:02}.docx", i)),
+                fixtures::docx(&paragraphs),
+            )
+            .unwrap();
         }
+        // A file that fails to parse does not stop the others
+        std::fs::write(documents.join("10-corrupt.docx"), b"not a zip archive").unwrap();
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let results = |threads: &[&str]| {
+            let output = crate::output::capture(|| {
+                assert_eq!(
+                    dispatch(
+                        &[
+                            &["batch", "-n", &needles, "-d", &documents, "-f", "json"],
+                            threads
+                        ]
+                        .concat()
+                    )
+                    .unwrap(),
+                    Outcome::Success
+                );
+            });
+            json_report(&output)["results"].clone()
+        };
+        let sequential = results(&["--threads", "1"]);
+        assert_eq!(sequential.as_array().unwrap().len(), 24);
+        assert_eq!(results(&["--workers", "4"]), sequential);
+        assert_eq!(results(&["--jobs", "3"]), sequential);
+        assert_eq!(results(&["-j", "8"]), sequential);
     }
 
-    fn get_search_options_interactive() -> Result<(bool, bool)> {
-        let case_sensitive = Confirm::new()
-            .with_prompt("Enable case sensitive search?")
-            .default(false)
-            .interact()?;
-        
-        let whole_word = Confirm::new()
-            .with_prompt("Enable whole word matching?")
-            .default(false)
-            .interact()?;
-        
-        Ok((case_sensitive, whole_word))
+    #[test]
+    fn test_info_shows_and_search_meta_searches_docx_properties() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let document = dir.path().join("review.docx");
+        std::fs::write(
+            &document,
+            fixtures::docx_with_properties(
+                &["Quarterly figures"],
+                &[("dc:title", "Review for Alice Johnson")],
+            ),
+        )
+        .unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let output = crate::output::capture(|| {
+            assert_eq!(dispatch(&["info", &document]).unwrap(), Outcome::Success);
+        });
+        assert!(
+            output.contains("Title: Review for Alice Johnson\n"),
+            "{}",
+            output
+        );
+
+        assert_eq!(
+            dispatch(&["search", &needles, &document]).unwrap(),
+            Outcome::NoMatches
+        );
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "--search-meta", "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        assert_eq!(
+            json_report(&output)["results"][0]["context_kind"],
+            serde_json::json!(["metadata"])
+        );
     }
 
-    fn get_document_path_interactive() -> Result<PathBuf> {
-        let options = &[
-            "Enter document path manually",
-            "Select from current directory",
-        ];
-        
-        let choice = Select::new()
-            .with_prompt("How would you like to select the document file?")
-            .default(0)
-            .items(options)
-            .interact()?;
-        
-        match choice {
-            0 => {
-                let file_path: String = Input::new()
-                    .with_prompt("Enter document path")
-                    .interact_text()?;
-                Ok(PathBuf::from(file_path.trim()))
-            }
-            1 => {
-                let dir_path: String = Input::new()
-                    .with_prompt("Enter directory path")
-                    .interact_text()?;
-                let pattern: String = Input::new()
-                    .with_prompt("Enter file pattern (e.g., *.pdf)")
-                    .default("*.pdf".to_string())
-                    .interact_text()?;
-                let files = Self::scan_directory(&PathBuf::from(dir_path.clone()), &pattern, false)?;
-                if files.is_empty() {
-                    return Err(anyhow::anyhow!("No files found in directory: {}", dir_path));
-                }
-                let file = Select::new()
-                    .with_prompt("Select document file")
-                    .items(&files.iter().map(|f| f.to_string_lossy().to_string()).collect::<Vec<_>>())
-                    .interact()?;
-                Ok(files[file].clone())
-            }
-            _ => unreachable!(),
-        }
+    #[test]
+    fn test_odt_documents_are_searched_and_their_properties_shown() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let document = dir.path().join("minutes.odt");
+        let body = "<text:h text:outline-level=\"1\">Attendees</text:h><text:p>Alice <text:span>Johnson</text:span></text:p>";
+        std::fs::write(
+            &document,
+            fixtures::odt_from_xml(body, "", &[("dc:title", "Board minutes")]),
+        )
+        .unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let output = crate::output::capture(|| {
+            assert_eq!(dispatch(&["info", &document]).unwrap(), Outcome::Success);
+        });
+        assert!(output.contains("Title: Board minutes\n"), "{}", output);
+
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        let occurrence = &json_report(&output)["results"][0]["occurrences"][0];
+        assert_eq!(occurrence["paragraph"], 2);
+        assert_eq!(occurrence["headings"], serde_json::json!(["Attendees"]));
     }
 
-    fn scan_directory(directory: &PathBuf, pattern: &str, recursive: bool) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-        
-        if recursive {
-            for entry in WalkDir::new(directory)
-                .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.file_type().is_file())
-            {
-                let path = entry.path().to_string_lossy();
-                if glob::Pattern::new(pattern).unwrap().matches(&path) {
-                    files.push(PathBuf::from(path.as_ref()));
-                }
-            }
-        } else {
-            let search_pattern = format!("{}/{}", directory.display(), pattern);
-            for entry in glob(&search_pattern)? {
-                if let Ok(path) = entry {
-                    if path.is_file() {
-                        files.push(path.to_string_lossy().to_string().into());
-                    }
-                }
-            }
-        }
-        
-        // Filter by supported file types
-        files.retain(|file| {
-            file.ends_with(".pdf") || file.ends_with(".docx")
+    #[test]
+    fn test_info_shows_pdf_information_and_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("report.pdf");
+        let info = [
+            ("Title", "Quarterly review"),
+            ("Producer", "Acme PDF"),
+            ("CreationDate", "D:20240131120000Z"),
+        ];
+        std::fs::write(
+            &document,
+            fixtures::pdf_with_info(
+                &[&["Alice Johnson"], &["Bob Smith"], &["Carol White"]],
+                &info,
+            ),
+        )
+        .unwrap();
+
+        let document = document.to_string_lossy();
+        let output = crate::output::capture(|| {
+            assert_eq!(dispatch(&["info", &document]).unwrap(), Outcome::Success);
         });
-        
-        Ok(files)
+        assert!(
+            output.contains(
+                "Title: Quarterly review\nProducer: Acme PDF\nCreation date: D:20240131120000Z\n"
+            ),
+            "{}",
+            output
+        );
+        assert!(output.contains("Pages: 3\n"), "{}", output);
     }
 
-    fn run_batch_search(_search_terms: &[(String, String)], files: &[PathBuf], _case_sensitive: bool, _whole_word: bool, format: &str) -> Result<()> {
-        let start = std::time::Instant::now();
-        let total_files = files.len() as u64;
-        
-        // Create multi-progress bar
-        let multi_progress = MultiProgress::new();
-        let overall_progress = multi_progress.add(ProgressBar::new(total_files));
-        overall_progress.set_style(
-            ProgressStyle::default_bar()
-                .template("Overall: [{bar:40.cyan/blue}] {pos}/{len} files")
-                .unwrap()
-                .progress_chars("█▉▊▋▌▍▎▏ ")
+    #[test]
+    fn test_batch_retries_misnamed_documents_unless_told_not_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        std::fs::write(
+            documents.join("scan.docx"),
+            fixtures::pdf(&[&["Alice Johnson"]]),
+        )
+        .unwrap();
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let args = ["batch", "-n", &needles, "-d", &documents];
+        let mut outcome = None;
+        let output = crate::output::capture(|| outcome = Some(dispatch(&args).unwrap()));
+        assert_eq!(outcome, Some(Outcome::Success));
+        assert!(
+            output.contains("Files searched with warnings: 1"),
+            "{}",
+            output
         );
-        
-        let mut all_results = Vec::new();
-        let mut files_with_matches = 0;
-        
-        for (_i, file_path) in files.iter().enumerate() {
-            overall_progress.set_message(format!("Processing: {}", file_path.display()));
-            
-            // Process individual file
-            if let Ok(file_type) = parse_filetype(&file_path.to_string_lossy()) {
-                let results = match file_type {
-                    FileType::Docx => parse_docx_from_path("contacts.csv", &file_path.to_string_lossy())?,
-                    FileType::Pdf => parse_pdf_from_path("contacts.csv", &file_path.to_string_lossy())?,
-                };
-                
-                if !results.is_empty() {
-                    files_with_matches += 1;
-                    for (term, metadata) in results {
-                        all_results.push((term, metadata, file_path.clone()));
-                    }
+        assert_eq!(
+            dispatch(&[&args[..], &["--no-retry-detect"]].concat()).unwrap(),
+            Outcome::NoMatches
+        );
+    }
+
+    #[test]
+    fn test_scan_and_batch_discover_the_same_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "a.pdf",
+            "notes.txt",
+            "reports/r.docx",
+            "reports/archive/old.pdf",
+            "archive/older.docx",
+            "other/b.pdf",
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        let root = dir.path().to_string_lossy();
+        let extra = dir.path().join("other/b.pdf");
+        let extra = extra.to_string_lossy();
+
+        let discover = |command: &[&str], flags: &[&str]| {
+            let args = std::iter::once("docsearcher")
+                .chain(command.iter().copied())
+                .chain(flags.iter().copied());
+            match EnhancedCli::parse_from(args).command {
+                Some(Commands::Batch { inputs, .. } | Commands::Scan { inputs, .. }) => {
+                    CliApp::collect_batch_files(&inputs).unwrap()
                 }
+                _ => unreachable!(),
             }
-            
-            overall_progress.inc(1);
+        };
+        for flags in [
+            &[
+                "-d",
+                &root,
+                "--pattern",
+                "**/*.*",
+                "--exclude",
+                "**/archive/**",
+            ][..],
+            &["-d", &root, "-r", "--exclude", "*.docx", &extra],
+            &["-d", &root, "--pattern", "*.pdf"],
+        ] {
+            let scanned = discover(&["scan"], flags);
+            assert_eq!(scanned, discover(&["batch", "-n", "needles.csv"], flags));
+            assert!(!scanned.0.is_empty());
         }
-        
-        overall_progress.finish_with_message("Batch processing completed!");
-        
-        let duration = start.elapsed();
-        
-        // Display batch results
-        Self::display_batch_results(&all_results, format, duration, files.len(), files_with_matches)
+
+        let (files, duplicates) = discover(
+            &["scan"],
+            &["-d", &root, "-r", "--exclude", "*.docx", &extra],
+        );
+        assert_eq!(files.len(), 3);
+        assert_eq!(duplicates, 1);
+        assert_eq!(
+            dispatch(&["scan", "-d", &root, "--format", "json", "--limit", "1"]).unwrap(),
+            Outcome::Success
+        );
+        assert_eq!(
+            dispatch(&["scan", "-d", &root, "--pattern", "*.xlsx"]).unwrap(),
+            Outcome::NoMatches
+        );
+
+        // Plain text files only when the pattern names them
+        assert!(!discover(&["scan"], &["-d", &root])
+            .0
+            .iter()
+            .any(|file| file.ends_with("notes.txt")));
+        assert_eq!(
+            discover(&["scan"], &["-d", &root, "--pattern", "*.txt"]).0,
+            [dir.path().join("notes.txt")]
+        );
     }
 
-    fn validate_needles_file(path: Option<&PathBuf>) -> bool {
-        if let Some(path) = path {
-            if !path.exists() {
-                return false;
-            }
-            
-            match read_needles_from_file(&path.to_string_lossy()) {
-                Ok(needles) => !needles.is_empty(),
-                Err(_) => false,
-            }
-        } else {
-            false
+    #[test]
+    fn test_scan_directory_keeps_upper_case_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "REPORT.PDF",
+            "Minutes.Docx",
+            "BACKUP.ZIP",
+            "NOTES.TXT",
+            "SLIDES.PPTX",
+        ] {
+            std::fs::write(dir.path().join(file), b"").unwrap();
         }
+
+        let mut files = CliApp::scan_directory(dir.path(), "*", false).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            ["BACKUP.ZIP", "Minutes.Docx", "REPORT.PDF"].map(|file| dir.path().join(file))
+        );
     }
 
-    fn validate_document_file(path: Option<&PathBuf>) -> bool {
-        if let Some(path) = path {
-            if !path.exists() {
-                return false;
-            }
-            
-            parse_filetype(&path.to_string_lossy()).is_ok()
-        } else {
-            false
-        }
+    #[test]
+    fn test_text_files_are_searched_line_by_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("contacts.txt");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let document = dir.path().join("server.txt");
+        std::fs::write(
+            &document,
+            "\u{FEFF}boot\r\nlogin by Alice Johnson\nlogout\r\n",
+        )
+        .unwrap();
+
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        // Lines count from 0, as blocks do
+        assert_eq!(
+            json_report(&output)["results"][0]["occurrences"][0]["line"],
+            1
+        );
+
+        let root = dir.path().to_string_lossy();
+        assert_eq!(
+            dispatch(&["batch", "-n", &needles, "-d", &root]).unwrap(),
+            Outcome::NoMatches
+        );
+        assert_eq!(
+            dispatch(&[
+                "batch",
+                "-n",
+                &needles,
+                "-d",
+                &root,
+                "--pattern",
+                "server.txt"
+            ])
+            .unwrap(),
+            Outcome::Success
+        );
     }
 
-    fn display_results(matches: &std::collections::HashSet<SearchResult>, format: &str, duration: std::time::Duration) -> Result<()> {
-        println!("\n{}", "=".repeat(50).blue());
-        println!("{}", "SEARCH RESULTS".blue().bold());
-        println!("{}", "=".repeat(50).blue());
-        
-        // Show search options
-        println!("Search Options:");
-        println!("  Case sensitive: {}", "N/A".yellow());
-        println!("  Whole word: {}", "N/A".yellow());
-        println!();
-        
-        match format.to_lowercase().as_str() {
-            "json" => Self::display_json_results(matches)?,
-            "csv" => Self::display_csv_results(matches)?,
-            "html" => Self::display_html_results(matches)?,
-            _ => Self::display_text_results(matches),
-        }
-        
-        println!("{}", "=".repeat(50).blue());
-        println!("{}", format!("Search completed in {} ms", duration.as_millis()).italic());
-        println!("{}", format!("Found {} matches", matches.len()).green().bold());
-        
-        Ok(())
+    #[test]
+    fn test_explain_verdicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, document) = comments_only(dir.path());
+        let needles = dir.path().join("contacts.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+
+        let needles = needles.to_string_lossy();
+        let explain = |term: &str, line: &[&str]| {
+            dispatch(
+                &[
+                    &["explain", "-n", &needles, "-d", &document, "--term", term][..],
+                    line,
+                ]
+                .concat(),
+            )
+        };
+        assert_eq!(explain("Alice Johnson", &[]).unwrap(), Outcome::Success);
+        assert_eq!(
+            explain("Alice Johnson", &["--line", "1"]).unwrap(),
+            Outcome::Success
+        );
+        assert_eq!(explain("alice johnson", &[]).unwrap(), Outcome::Success);
+        assert_eq!(
+            explain("alice johnson", &["--case-sensitive"]).unwrap(),
+            Outcome::NoMatches
+        );
+        let err = explain("Alice Johnson", &["--line", "2"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("past the end"), "{}", err);
     }
 
-    fn display_batch_results(results: &[(String, String, PathBuf)], format: &str, duration: std::time::Duration, total_files: usize, files_with_matches: usize) -> Result<()> {
-        println!("\n{}", "=".repeat(60).blue());
-        println!("{}", "BATCH SEARCH RESULTS".blue().bold());
-        println!("{}", "=".repeat(60).blue());
-        
-        println!("Summary:");
-        println!("  Total files processed: {}", total_files);
-        println!("  Files with matches: {}", files_with_matches);
-        println!("  Total matches found: {}", results.len());
-        println!();
-        
-        match format.to_lowercase().as_str() {
-            "json" => Self::display_batch_json_results(results)?,
-            "csv" => Self::display_batch_csv_results(results)?,
-            "html" => Self::display_batch_html_results(results)?,
-            _ => Self::display_batch_text_results(results),
-        }
-        
-        println!("{}", "=".repeat(60).blue());
-        println!("{}", format!("Batch processing completed in {} ms", duration.as_millis()).italic());
-        
-        Ok(())
+    #[test]
+    fn test_assert_all_and_any() {
+        let dir = tempfile::tempdir().unwrap();
+        let (empty, document) = comments_only(dir.path());
+        let write = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let present = write("present.csv", "Alice,id=1\nJohnson,id=2\n");
+        let partial = write("partial.csv", "Alice,id=1\nDisclaimer,id=3\n");
+        let absent = write("absent.csv", "Disclaimer,id=3\n");
+
+        let assert = |needles: &str, flags: &[&str]| {
+            dispatch(&[&["assert", "-n", needles, &document][..], flags].concat())
+        };
+        assert_eq!(assert(&present, &[]).unwrap(), Outcome::Success);
+        assert_eq!(assert(&partial, &[]).unwrap(), Outcome::AssertionFailed);
+        assert_eq!(
+            assert(&partial, &["--all", "-f", "json"]).unwrap(),
+            Outcome::AssertionFailed
+        );
+        assert_eq!(assert(&partial, &["--any"]).unwrap(), Outcome::Success);
+        assert_eq!(
+            assert(&absent, &["--any", "-f", "json"]).unwrap(),
+            Outcome::AssertionFailed
+        );
+        assert_eq!(Outcome::AssertionFailed.code(), 1);
+
+        let err = assert(&empty, &["--any"]).unwrap_err().to_string();
+        assert!(err.contains("nothing to assert"), "{}", err);
+        assert!(assert(&present, &["-f", "csv"]).is_err());
+        assert!(EnhancedCli::try_parse_from([
+            "docsearcher",
+            "assert",
+            "-n",
+            &present,
+            &document,
+            "--all",
+            "--any"
+        ])
+        .is_err());
     }
 
-    fn display_text_results(matches: &std::collections::HashSet<SearchResult>) {
-        if matches.is_empty() {
-            println!("{}", "No matches found.".yellow());
-            return;
-        }
-        
-        for (i, (term, metadata)) in matches.iter().enumerate() {
-            println!("  {}: {} → {}", i + 1, term.blue(), metadata.green());
-        }
+    #[test]
+    fn test_stopwords_are_dropped_and_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, document) = comments_only(dir.path());
+        let needles = dir.path().join("generated.csv");
+        std::fs::write(
+            &needles,
+            "Alice,id=1\nthe board,id=2\nand company,id=3\nJohnson,id=4\n",
+        )
+        .unwrap();
+        let stopwords = dir.path().join("stopwords.txt");
+        std::fs::write(&stopwords, "# generator junk\nThe Board\nand\ncompany\n").unwrap();
+        let results = dir.path().join("results.json");
+
+        let (needles, stopwords) = (needles.to_string_lossy(), stopwords.to_string_lossy());
+        let search = |mode: &str| {
+            let args = [
+                "search",
+                &needles,
+                &document,
+                "--stopwords",
+                &stopwords,
+                "--stopword-mode",
+                mode,
+            ];
+            dispatch(&[&args[..], &["--save-results", &results.to_string_lossy()]].concat())
+                .unwrap();
+            let saved = ResultsFile::load(&results).unwrap();
+            let terms: Vec<String> = saved
+                .results
+                .iter()
+                .map(|record| record.term.clone())
+                .collect();
+            (terms, saved.extra["needles"]["stopwords"].as_u64().unwrap())
+        };
+        assert_eq!(
+            search("exact"),
+            (vec!["Alice".to_string(), "Johnson".to_string()], 1)
+        );
+        assert_eq!(
+            search("contains"),
+            (vec!["Alice".to_string(), "Johnson".to_string()], 2)
+        );
+
+        std::fs::write(
+            dir.path().join("stopwords.txt"),
+            "alice\njohnson\nthe board\nand company\n",
+        )
+        .unwrap();
+        let err = dispatch(&["search", &needles, &document, "--stopwords", &stopwords])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("is a stop word"), "{}", err);
+        assert!(dispatch(&["validate", &needles, &document, "--stopwords", &stopwords]).is_ok());
     }
 
-    fn display_batch_text_results(results: &[(String, String, PathBuf)]) {
-        if results.is_empty() {
-            println!("{}", "No matches found in any files.".yellow());
-            return;
-        }
-        
-        for (i, (term, metadata, file)) in results.iter().enumerate() {
-            println!("  {}: {} → {} [{}]", i + 1, term.blue(), metadata.green(), file.display());
-        }
+    #[test]
+    fn test_as_of_searches_only_needles_valid_that_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, document) = comments_only(dir.path());
+        let needles = dir.path().join("vendors.csv");
+        std::fs::write(
+            &needles,
+            "Alice,valid_from=2024-07-01\nJohnson,valid_from=2024-01-01;valid_until=2024-12-31\n",
+        )
+        .unwrap();
+        let results = dir.path().join("results.json");
+
+        let needles = needles.to_string_lossy();
+        let search = |as_of: &str| {
+            dispatch(&[
+                "search",
+                &needles,
+                &document,
+                "--as-of",
+                as_of,
+                "--save-results",
+                &results.to_string_lossy(),
+            ])
+            .unwrap();
+            let saved = ResultsFile::load(&results).unwrap();
+            let terms: Vec<String> = saved
+                .results
+                .iter()
+                .map(|record| record.term.clone())
+                .collect();
+            (terms, saved.extra["needles"]["inactive"].as_u64().unwrap())
+        };
+        assert_eq!(search("2024-06-01"), (vec!["Johnson".to_string()], 1));
+        assert_eq!(
+            search("2024-07-01"),
+            (vec!["Alice".to_string(), "Johnson".to_string()], 0)
+        );
+
+        let err = dispatch(&["search", &needles, &document, "--as-of", "2023-12-31"])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("No needles are valid on 2023-12-31"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_dispositions_carry_forward_to_a_rerun() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        std::fs::write(
+            documents.join("memo.docx"),
+            fixtures::docx(&["Alice Johnson", "Bob Smith"]),
+        )
+        .unwrap();
+        let (results, annotated, rerun) = (
+            dir.path().join("results.json"),
+            dir.path().join("annotated.json"),
+            dir.path().join("rerun.json"),
+        );
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let args = ["batch", "-n", &needles, "-d", &documents, "-f", "csv"];
+        dispatch(&[&args[..], &["--save-results", &results.to_string_lossy()]].concat()).unwrap();
+
+        let mut saved = ResultsFile::load(&results).unwrap();
+        assert_eq!(saved.results.len(), 2);
+        assert!(saved.extra.contains_key("resources"));
+        assert_eq!(
+            saved.results[0].extra["needles_list"],
+            serde_json::Value::Null
+        );
+        saved.results[0].annotate(Some(&Annotation {
+            disposition: Disposition::Reject,
+            note: Some("wrong Alice".to_string()),
+        }));
+        saved.save(&annotated).unwrap();
+
+        let rerun_args = [
+            "--dispositions",
+            &annotated.to_string_lossy(),
+            "--save-results",
+            &rerun.to_string_lossy(),
+        ];
+        dispatch(&[&args[..], &rerun_args].concat()).unwrap();
+        let rerun = ResultsFile::load(&rerun).unwrap();
+        assert_eq!(rerun.results[0].match_id, saved.results[0].match_id);
+        assert_eq!(rerun.results[0].disposition, Some(Disposition::Reject));
+        assert_eq!(rerun.results[0].note.as_deref(), Some("wrong Alice"));
+        assert_eq!(rerun.results[1].disposition, None);
     }
 
-    fn display_json_results(matches: &std::collections::HashSet<SearchResult>) -> Result<()> {
-        let results: Vec<serde_json::Value> = matches
+    #[test]
+    fn test_matches_inside_containers_name_their_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        let pdf = fixtures::pdf(&[&["Signed by Alice Johnson"]]);
+        let zip = fixtures::zip_of(&[("a/report.pdf", &pdf), ("b/report.pdf", &pdf)]);
+        let archive = documents.join("dump.zip.gz");
+        std::fs::write(&archive, fixtures::gzip(None, &zip)).unwrap();
+        let results = dir.path().join("results.json");
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let args = [
+            "batch",
+            "-n",
+            &needles,
+            "-d",
+            &documents,
+            "--save-results",
+            &results.to_string_lossy(),
+        ];
+        assert_eq!(dispatch(&args).unwrap(), Outcome::Success);
+
+        let saved = ResultsFile::load(&results).unwrap();
+        let archive = archive.to_string_lossy();
+        let sources: Vec<&[String]> = saved
+            .results
             .iter()
-            .map(|(term, metadata)| {
-                serde_json::json!({
-                    "term": term,
-                    "metadata": metadata
-                })
-            })
+            .map(|record| record.source.segments())
             .collect();
-        
-        println!("{}", serde_json::to_string_pretty(&results)?);
-        Ok(())
+        assert_eq!(
+            sources,
+            [
+                [&*archive, "dump.zip", "a/report.pdf"],
+                [&*archive, "dump.zip", "b/report.pdf"]
+            ]
+        );
+        assert!(saved.results.iter().all(|record| record.file == archive));
+        assert_eq!(
+            saved.results[0].source_label(),
+            format!("{}!dump.zip!a/report.pdf", archive)
+        );
+        assert_ne!(saved.results[0].match_id, saved.results[1].match_id);
     }
 
-    fn display_batch_json_results(results: &[(String, String, PathBuf)]) -> Result<()> {
-        let results_json: Vec<serde_json::Value> = results
+    /// `output` without the figures that change from run to run: resource
+    /// use and timings
+    fn without_timings(output: &str) -> String {
+        let mut kept = Vec::new();
+        let mut resources = false;
+        for line in output.lines() {
+            if line.trim_start().starts_with("\"resources\": {") {
+                resources = true;
+            } else if resources {
+                resources = !line.trim_start().starts_with('}');
+            } else if ![
+                "Peak memory",
+                "CPU time",
+                "Read:",
+                "Text extracted",
+                "completed in",
+            ]
             .iter()
-            .map(|(term, metadata, file)| {
-                serde_json::json!({
-                    "term": term,
-                    "metadata": metadata,
-                    "file": file.to_string_lossy()
-                })
-            })
-            .collect();
-        
-        println!("{}", serde_json::to_string_pretty(&results_json)?);
-        Ok(())
+            .any(|figure| line.contains(figure))
+            {
+                kept.push(line);
+            }
+        }
+        kept.join("\n")
     }
 
-    fn display_csv_results(matches: &std::collections::HashSet<SearchResult>) -> Result<()> {
-        println!("term,metadata");
-        for (term, metadata) in matches {
-            println!("{},{},", term, metadata);
+    #[test]
+    fn test_spilled_batches_report_exactly_what_in_memory_ones_do() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(
+            &needles,
+            "Alice Johnson,dept=legal\nBob Smith,dept=sales\nCarol Diaz,dept=hr\n",
+        )
+        .unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        for (name, lines) in [
+            (
+                "a.docx",
+                &[
+                    "Alice Johnson and Bob Smith",
+                    "Bob Smith again",
+                    "Carol Diaz",
+                ][..],
+            ),
+            ("b.docx", &["Nobody here"][..]),
+            (
+                "c.docx",
+                &["Alice Johnson", "Alice Johnson", "Alice Johnson"][..],
+            ),
+        ] {
+            std::fs::write(documents.join(name), fixtures::docx(lines)).unwrap();
         }
-        Ok(())
+        std::fs::write(
+            documents.join("d.pdf"),
+            fixtures::pdf(&[&["Carol Diaz"], &["Bob Smith"]]),
+        )
+        .unwrap();
+        std::fs::write(documents.join("broken.pdf"), b"not a pdf").unwrap();
+        let spill = dir.path().join("spill");
+        std::fs::create_dir(&spill).unwrap();
+
+        let (needles, documents, spill) = (
+            needles.to_string_lossy(),
+            documents.to_string_lossy(),
+            spill.to_string_lossy(),
+        );
+        let run = |format: &str, threshold: &str| {
+            let saved = dir.path().join("saved.json");
+            let saved_arg = saved.to_string_lossy();
+            let args = [
+                "batch",
+                "-n",
+                &needles,
+                "-d",
+                &documents,
+                "--format",
+                format,
+                "--save-results",
+                &saved_arg,
+                "--spill-threshold",
+                threshold,
+                "--spill-dir",
+                &spill,
+            ];
+            let output =
+                crate::output::capture(|| assert_eq!(dispatch(&args).unwrap(), Outcome::Success));
+            (
+                without_timings(&output),
+                without_timings(&std::fs::read_to_string(&saved).unwrap()),
+            )
+        };
+
+        for format in ["text", "json", "csv", "html"] {
+            let in_memory = run(format, "1000000");
+            assert!(
+                in_memory.0.contains("Total matches found: 6"),
+                "{}",
+                in_memory.0
+            );
+            // Every file spilled, and some files spilled
+            for threshold in ["0", "3"] {
+                assert_eq!(
+                    run(format, threshold),
+                    in_memory,
+                    "--format {} --spill-threshold {}",
+                    format,
+                    threshold
+                );
+            }
+        }
+
+        // Segments are removed when a run ends, and when it fails
+        let report_dir = dir.path().join("not-a-dir");
+        std::fs::write(&report_dir, b"").unwrap();
+        let args = [
+            "batch",
+            "-n",
+            &needles,
+            "-d",
+            &documents,
+            "--spill-threshold",
+            "0",
+            "--spill-dir",
+            &spill,
+            "--report-dir",
+            &report_dir.to_string_lossy(),
+        ];
+        assert!(dispatch(&args).is_err());
+        assert_eq!(std::fs::read_dir(&*spill).unwrap().count(), 0);
+    }
+
+    /// The JSON report in captured output, between the run's other lines
+    fn json_report(output: &str) -> serde_json::Value {
+        let start = output.find("\n{").unwrap();
+        let end = output.rfind("\n}").unwrap();
+        serde_json::from_str(&output[start..end + 2]).unwrap()
     }
 
-    fn display_batch_csv_results(results: &[(String, String, PathBuf)]) -> Result<()> {
-        println!("term,metadata,file");
-        for (term, metadata, file) in results {
-            println!("{},{},{}", term, metadata, file.to_string_lossy());
+    /// Both lookup strategies find the same matches in the fixtures, and
+    /// the report says which one `--matcher` asked for and which one ran
+    #[test]
+    fn test_matcher_strategies_agree_and_the_override_is_honored() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        let overlapping = "Alice,id=1\nAlice Johnson,id=2\nJohnson,id=3\nson,id=4\nAlice,id=5\n";
+        std::fs::write(
+            &needles,
+            format!("{}{}", overlapping, fixtures::NON_BMP_NEEDLES),
+        )
+        .unwrap();
+        let paragraphs: Vec<&str> = ["Alice Johnson and Johnsons' son, Alice", "sonsonson"]
+            .into_iter()
+            .chain(fixtures::NON_BMP_PARAGRAPHS.iter().copied())
+            .collect();
+        std::fs::write(dir.path().join("chat.docx"), fixtures::docx(&paragraphs)).unwrap();
+        std::fs::write(
+            dir.path().join("memo.pdf"),
+            fixtures::pdf(&[&["Alice Johnson signed"], &["Johnson, Alice's son"]]),
+        )
+        .unwrap();
+        let (needles, directory) = (needles.to_string_lossy(), dir.path().to_string_lossy());
+        let document = dir.path().join("chat.docx");
+        let document = document.to_string_lossy();
+
+        let search = ["search", &needles, &document, "-f", "json"];
+        let batch = [
+            "batch", "-d", &directory, "-p", "*", "-n", &needles, "-f", "json",
+        ];
+        for command in [&search[..], &batch[..]] {
+            let mut results = Vec::new();
+            for matcher in ["naive", "automaton", "auto"] {
+                let output = crate::output::capture(|| {
+                    assert_eq!(
+                        dispatch(&[command, &["--matcher", matcher]].concat()).unwrap(),
+                        Outcome::Success
+                    );
+                });
+                let report = json_report(&output);
+                assert_eq!(report["matcher"]["requested"], matcher);
+                if matcher != "auto" {
+                    assert_eq!(report["matcher"]["strategy"], matcher);
+                }
+                results.push(report["results"].clone());
+            }
+            assert!(results[0].as_array().unwrap().len() >= 9);
+            assert_eq!(results[0], results[1]);
+            assert_eq!(results[0], results[2]);
         }
-        Ok(())
+
+        // A handful of needles over small documents isn't worth an automaton
+        let output = crate::output::capture(|| {
+            dispatch(&search[..]).unwrap();
+        });
+        assert_eq!(json_report(&output)["matcher"]["strategy"], "naive");
+        assert!(crate::output::capture(|| {
+            dispatch(&search[..3]).unwrap();
+        })
+        .contains("Needle lookup: naive (chosen automatically)"));
     }
 
-    fn display_html_results(matches: &std::collections::HashSet<SearchResult>) -> Result<()> {
-        println!("<!DOCTYPE html>");
-        println!("<html><head><title>DocSearcher Results</title></head><body>");
-        println!("<h1>Search Results</h1>");
-        println!("<table border='1'><tr><th>Term</th><th>Metadata</th></tr>");
-        
-        for (term, metadata) in matches {
-            println!("<tr><td>{}</td><td>{}</td></tr>", term, metadata);
+    /// The html-full report's page with what varies between runs (the
+    /// temporary directory, match ids hashed from it, the command line)
+    /// replaced
+    fn full_html_page(output: &str, dir: &Path) -> String {
+        let start = output.find("<!DOCTYPE html>").unwrap();
+        let end = output.rfind("</html>").unwrap();
+        let page = output[start..end + "</html>".len()].replace(&*dir.to_string_lossy(), "$DIR");
+
+        let mut normalized = String::new();
+        for line in page.lines() {
+            if line.starts_with("<dt>Command</dt>") {
+                normalized.push_str("<dt>Command</dt><dd>$COMMAND</dd>\n");
+                continue;
+            }
+            let mut rest = line;
+            while let Some(at) = ["href=\"#", "id=\""]
+                .iter()
+                .filter_map(|marker| rest.find(marker).map(|at| at + marker.len()))
+                .min()
+            {
+                normalized.push_str(&rest[..at]);
+                rest = &rest[at..];
+                let anchor = &rest[..rest.find('"').unwrap()];
+                match anchor.rsplit_once('-') {
+                    Some((id, n)) if id != "doc" => {
+                        normalized.push_str(&format!("<match_id>-{}", n))
+                    }
+                    _ => normalized.push_str(anchor),
+                }
+                rest = &rest[anchor.len()..];
+            }
+            normalized.push_str(rest);
+            normalized.push('\n');
         }
-        
-        println!("</table></body></html>");
-        Ok(())
+        normalized
+    }
+
+    #[test]
+    fn test_html_full_report_matches_the_golden_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(
+            &needles,
+            "Alice Johnson,dept=legal\nBob <Smith>,dept=sales & ops\n",
+        )
+        .unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        let heading = |level: usize, text: &str| {
+            format!(
+                "<w:p><w:pPr><w:pStyle w:val=\"Heading{}\"/></w:pPr><w:r><w:t>{}</w:t></w:r></w:p>",
+                level, text
+            )
+        };
+        let paragraph = |text: &str| {
+            format!(
+                "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+                text
+            )
+        };
+        let body = [
+            heading(1, "Agreement"),
+            paragraph("This agreement is made today."),
+            heading(2, "Parties"),
+            paragraph("Between Alice Johnson of London"),
+            paragraph("and Bob &lt;Smith&gt; of Leeds."),
+            paragraph(&format!(
+                "{} Alice Johnson {}",
+                "x".repeat(40),
+                "y".repeat(40)
+            )),
+            heading(2, "Signatures"),
+            paragraph("Signed by Alice Johnson"),
+        ]
+        .concat();
+        std::fs::write(
+            documents.join("agreement.docx"),
+            fixtures::docx_from_body_xml(&body),
+        )
+        .unwrap();
+        std::fs::write(
+            documents.join("scan.pdf"),
+            fixtures::pdf(&[
+                &["Cover page"],
+                &["Memo to Alice Johnson", "about the audit"],
+            ]),
+        )
+        .unwrap();
+        std::fs::write(
+            documents.join("empty.docx"),
+            fixtures::docx(&["Nobody here"]),
+        )
+        .unwrap();
+        std::fs::write(
+            documents.join("bundle.zip"),
+            fixtures::zip_of(&[("inner.docx", &fixtures::docx(&["Alice Johnson inside"]))]),
+        )
+        .unwrap();
+
+        let (needles, documents) = (needles.to_string_lossy(), documents.to_string_lossy());
+        let output = crate::output::capture(|| {
+            let args = [
+                "batch",
+                "-n",
+                &needles,
+                "-d",
+                &documents,
+                "-p",
+                "*",
+                "-f",
+                "html-full",
+                "--threads",
+                "1",
+                "--max-excerpt-chars",
+                "60",
+                "--as-of",
+                "2024-06-01",
+            ];
+            assert_eq!(dispatch(&args).unwrap(), Outcome::Success);
+        });
+        let page = full_html_page(&output, dir.path());
+        let golden = include_str!("../golden/html_full/batch.html");
+        assert!(page == golden, "the html-full report changed:\n{}", page);
     }
 
-    fn display_batch_html_results(results: &[(String, String, PathBuf)]) -> Result<()> {
-        println!("<!DOCTYPE html>");
-        println!("<html><head><title>DocSearcher Batch Results</title></head><body>");
-        println!("<h1>Batch Search Results</h1>");
-        println!("<table border='1'><tr><th>Term</th><th>Metadata</th><th>File</th></tr>");
-        
-        for (term, metadata, file) in results {
-            println!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>", term, metadata, file.to_string_lossy());
+    /// Every output format, for one document and for a batch, gives back
+    /// the needles' non-BMP terms byte for byte
+    #[test]
+    fn test_non_bmp_terms_come_back_byte_identical_from_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, fixtures::NON_BMP_NEEDLES).unwrap();
+        let document = dir.path().join("chat.docx");
+        std::fs::write(&document, fixtures::docx(fixtures::NON_BMP_PARAGRAPHS)).unwrap();
+        let mut terms =
+            crate::utils::read_needles_from_mem(fixtures::NON_BMP_NEEDLES.as_bytes(), None)
+                .unwrap();
+        terms.sort();
+        let (needles, document, directory) = (
+            needles.to_string_lossy(),
+            document.to_string_lossy(),
+            dir.path().to_string_lossy(),
+        );
+
+        let search = ["search", &needles, &document];
+        let batch = ["batch", "-d", &directory, "-p", "*.docx", "-n", &needles];
+        for command in [&search[..], &batch[..]] {
+            for format in ["text", "csv", "json", "ndjson", "html"] {
+                let output = crate::output::capture(|| {
+                    assert_eq!(
+                        dispatch(&[command, &["-f", format]].concat()).unwrap(),
+                        Outcome::Success
+                    );
+                });
+
+                let mut found: Vec<(String, String)> = match format {
+                    "json" => {
+                        let report = json_report(&output);
+                        let results = report["results"].as_array().unwrap();
+                        results
+                            .iter()
+                            .map(|record| {
+                                (
+                                    record["term"].as_str().unwrap().to_string(),
+                                    record["metadata"].as_str().unwrap().to_string(),
+                                )
+                            })
+                            .collect()
+                    }
+                    "ndjson" => output
+                        .lines()
+                        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+                        .map(|record| {
+                            (
+                                record["term"].as_str().unwrap().to_string(),
+                                record["metadata"].as_str().unwrap().to_string(),
+                            )
+                        })
+                        .collect(),
+                    "csv" => terms
+                        .iter()
+                        .filter(|(term, metadata)| {
+                            output
+                                .lines()
+                                .any(|line| line.starts_with(&csv_row(&[term, metadata], false)))
+                        })
+                        .cloned()
+                        .collect(),
+                    "html" => terms
+                        .iter()
+                        .filter(|(term, metadata)| {
+                            output.contains(&escape_html(term))
+                                && output.contains(&escape_html(metadata))
+                        })
+                        .cloned()
+                        .collect(),
+                    _ => terms
+                        .iter()
+                        .filter(|(term, _)| output.contains(term.as_str()))
+                        .cloned()
+                        .collect(),
+                };
+                found.sort();
+                assert_eq!(found, terms, "{} -f {}:\n{}", command[0], format, output);
+            }
         }
-        
-        println!("</table></body></html>");
-        Ok(())
     }
 
-    fn show_help() {
-        println!("{}", "DocSearcher - Document Search Tool".blue().bold());
-        println!();
-        println!("Usage:");
-        println!("  docsearcher <needles_file> <document_file>");
-        println!("  docsearcher --interactive");
-        println!("  docsearcher --tui");
-        println!("  docsearcher search <needles_file> <document_file>");
-        println!("  docsearcher batch <directory> <needles_file>");
-        println!("  docsearcher validate <needles_file> <document_file>");
-        println!("  docsearcher info <file>");
-        println!();
-        println!("Examples:");
-        println!("  docsearcher contacts.csv document.docx");
-        println!("  docsearcher --interactive");
-        println!("  docsearcher --tui");
-        println!("  docsearcher search contacts.csv report.pdf --format json");
-        println!("  docsearcher batch ./documents contacts.csv --pattern *.pdf");
-        println!("  docsearcher validate contacts.csv document.docx");
-        println!("  docsearcher info report.pdf");
-        println!();
-        println!("For more help, run: docsearcher --help");
+    #[test]
+    fn test_needle_delimiter_is_detected_or_given() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("export.csv");
+        std::fs::write(
+            &needles,
+            "# Excel export\nMüller, Hans;id=1\nAlice Johnson;id=2;dept=legal\n",
+        )
+        .unwrap();
+        let document = dir.path().join("report.docx");
+        std::fs::write(&document, fixtures::docx(&["Signed by Alice Johnson"])).unwrap();
+        let (needles, document) = (needles.to_string_lossy(), document.to_string_lossy());
+
+        let output = crate::output::capture(|| {
+            assert_eq!(
+                dispatch(&["search", &needles, &document, "-f", "json"]).unwrap(),
+                Outcome::Success
+            );
+        });
+        let report = json_report(&output);
+        assert_eq!(report["results"][0]["metadata"], "id=2;dept=legal");
+        assert_eq!(report["needles"]["delimiter"], ";");
+
+        // Forced to commas, the lines hold no needle Alice Johnson would match
+        let outcome =
+            dispatch(&["search", &needles, &document, "--needle-delimiter", ","]).unwrap();
+        assert_eq!(outcome, Outcome::NoMatches);
+
+        let output = crate::output::capture(|| {
+            dispatch(&["validate", &needles, &document]).unwrap();
+        });
+        assert!(
+            output.contains("Needles delimiter: ';' (detected)"),
+            "{}",
+            output
+        );
     }
 
-    fn show_startup_logo() {
-        let logo = r#"
- ____             ____                      _               
-|  _ \  ___   ___/ ___|  ___  __ _ _ __ ___| |__   ___ _ __ 
-| | | |/ _ \ / __\___ \ / _ \/ _` | '__/ __| '_ \ / _ \ '__|
-| |_| | (_) | (__ ___) |  __/ (_| | | | (__| | | |  __/ |  
-|____/ \___/ \___|____/ \___|\__,_|_|  \___|_| |_|\___|_|  
-"#;
-        println!("{}", logo);
-        println!();
+    /// Run `args` with `--api v1`, returning the result, stdout and the
+    /// events written to stderr, with `dir` and timings masked
+    fn api_run(args: &[&str], dir: &Path) -> (Result<Outcome>, String, Vec<serde_json::Value>) {
+        let mut result = None;
+        let (stdout, stderr) = crate::output::capture_streams(|| {
+            result = Some(dispatch(&[args, &["--api", "v1"]].concat()))
+        });
+        let dir = dir.to_string_lossy();
+        let events = stderr
+            .lines()
+            .map(|line| {
+                let mut event: serde_json::Value =
+                    serde_json::from_str(&line.replace(&*dir, "$DIR"))
+                        .expect("every stderr line is an event");
+                if event["event"] == "finished" {
+                    event["elapsed_ms"] = serde_json::json!(0);
+                }
+                event
+            })
+            .collect();
+        (result.unwrap(), stdout.replace(&*dir, "$DIR"), events)
+    }
+
+    #[test]
+    fn test_api_v1_output_matches_the_golden_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = dir.path().join("needles.csv");
+        std::fs::write(&needles, "Alice Johnson,dept=legal\nBob Smith,dept=sales\n").unwrap();
+        let documents = dir.path().join("documents");
+        std::fs::create_dir(&documents).unwrap();
+        let memo = documents.join("memo.docx");
+        std::fs::write(
+            &memo,
+            fixtures::docx(&["Signed by Alice Johnson", "Copied to Bob Smith"]),
+        )
+        .unwrap();
+        std::fs::write(
+            documents.join("scan.docx"),
+            fixtures::pdf(&[&["Alice Johnson"]]),
+        )
+        .unwrap();
+        std::fs::write(documents.join("broken.pdf"), b"not a pdf").unwrap();
+
+        let (needles, memo, documents) = (
+            needles.to_string_lossy(),
+            memo.to_string_lossy(),
+            documents.to_string_lossy(),
+        );
+        let search = ["search", &needles, &memo];
+        let batch = [
+            "batch",
+            "-n",
+            &needles,
+            "-d",
+            &documents,
+            "--metadata-format",
+            "kv",
+        ];
+        let runs = [
+            (&search[..], include_str!("../api/golden/v1/search.json")),
+            (&batch[..], include_str!("../api/golden/v1/batch.json")),
+        ];
+        for (args, golden) in runs {
+            let (result, stdout, mut events) = api_run(args, dir.path());
+            assert_eq!(result.unwrap(), Outcome::Success);
+
+            // Nothing but the one document, without colors
+            assert!(!stdout.contains('\u{1b}'), "{}", stdout);
+            let mut report: serde_json::Value =
+                serde_json::from_str(&stdout).expect("stdout is one JSON document");
+            for result in report["results"].as_array_mut().unwrap() {
+                result["match_id"] = serde_json::json!("<match_id>");
+            }
+
+            // Workers finish documents in any order
+            let last = events.len() - 1;
+            events[1..last]
+                .sort_by_key(|event| (event["file"].to_string(), event["event"].to_string()));
+            let run = serde_json::json!({ "stdout": report, "stderr": events });
+            let golden: serde_json::Value = serde_json::from_str(golden).unwrap();
+            assert_eq!(
+                run,
+                golden,
+                "{}",
+                serde_json::to_string_pretty(&run).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_api_v1_errors_are_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("report.docx");
+        std::fs::write(&document, fixtures::docx(&["Alice Johnson"])).unwrap();
+        let missing = dir.path().join("missing.csv");
+
+        let args = [
+            "search",
+            &missing.to_string_lossy(),
+            &document.to_string_lossy(),
+        ];
+        let (result, stdout, events) = api_run(&args, dir.path());
+        let err = result.unwrap_err();
+        assert!(err.is::<Reported>(), "{}", err);
+        assert_eq!(stdout, "");
+        assert_eq!(
+            events,
+            [
+                serde_json::json!({ "event": "error", "message": "Needles file not found: $DIR/missing.csv" }),
+                serde_json::json!({ "event": "finished", "exit_code": EXIT_ERROR, "elapsed_ms": 0 }),
+            ]
+        );
+
+        // Formats other than the protocol's own are refused
+        let (needles, document) = comments_only(dir.path());
+        let (result, _, events) =
+            api_run(&["search", &needles, &document, "-f", "csv"], dir.path());
+        assert!(result.unwrap_err().to_string().contains("--format csv"));
+        assert_eq!(events[0]["event"], "error");
+
+        // A run that searched nothing still prints a document
+        let (result, stdout, _) = api_run(
+            &["search", &needles, &document, "--allow-empty-needles"],
+            dir.path(),
+        );
+        assert_eq!(result.unwrap(), Outcome::NoMatches);
+        let report: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(report["results"], serde_json::json!([]));
     }
 }
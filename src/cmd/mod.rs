@@ -1,5 +1,5 @@
 pub mod cli;
 pub mod tui;
 
-pub use cli::CliApp;
+pub use cli::{CliApp, Outcome, EXIT_ERROR};
 pub use tui::TuiApp;
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use crossterm::{
     cursor::{Hide, Show},
-    event::{self, Event, KeyCode, KeyModifiers},
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
 };
@@ -10,23 +10,191 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Line},
-    widgets::{
-        Block, Borders, List, ListItem, Paragraph, Row, Table, Tabs,
-    },
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, Tabs},
     Frame, Terminal,
 };
 use std::{
     io::stdout,
-    time::Duration,
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
 };
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    types::{FileType, SearchResult},
-    utils::{parse_filetype},
-    parsers::{parse_docx_from_path, parse_pdf_from_path},
+    batch::preview_blocks,
+    interrupt::{self, CancelToken},
+    parsers,
+    progress::NullReporter,
+    search::{find_occurrences, BlockBudget},
+    settings::SearchSettings,
+    types::{ExtractOptions, SearchResult, SearchResults},
+    utils::truncate_to_width,
 };
 
+/// How long edits to the needles must pause before a preview starts
+pub const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often the screen is redrawn while no key is pressed, so previews
+/// start and show up without one
+const TICK: Duration = Duration::from_millis(50);
+
+/// How much of each selected file a preview searches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreviewBudget {
+    /// Blocks (paragraphs, lines) read from the start of each file
+    pub blocks: usize,
+    /// Time spent extracting each file
+    pub time: Duration,
+}
+
+impl Default for PreviewBudget {
+    fn default() -> Self {
+        Self {
+            blocks: 20,
+            time: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A preview for the background worker to run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewRequest {
+    /// The needle edit this preview is for
+    pub generation: u64,
+    pub needles: Vec<(String, String)>,
+    pub files: Vec<String>,
+    pub budget: PreviewBudget,
+    pub extract: ExtractOptions,
+    /// The app's token; once cancelled, no further file is searched
+    pub cancel: CancelToken,
+}
+
+impl PreviewRequest {
+    /// Search the start of every file; files that cannot be read are skipped
+    pub fn run(&self) -> Preview {
+        let mut counts: Vec<(String, usize)> = self
+            .needles
+            .iter()
+            .map(|(term, _)| (term.clone(), 0))
+            .collect();
+        let mut files = 0;
+        for file in &self.files {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let budget = BlockBudget {
+                blocks: self.budget.blocks,
+                deadline: Instant::now() + self.budget.time,
+            };
+            let Ok(bytes) = std::fs::read(file) else {
+                continue;
+            };
+            let Ok(blocks) = preview_blocks(Path::new(file), &bytes, self.extract, budget) else {
+                continue;
+            };
+            files += 1;
+            for occurrence in find_occurrences(&self.needles, &blocks, self.extract) {
+                if let Some((_, count)) =
+                    counts.iter_mut().find(|(term, _)| *term == occurrence.term)
+                {
+                    *count += 1;
+                }
+            }
+        }
+        Preview {
+            generation: self.generation,
+            counts,
+            files,
+        }
+    }
+}
+
+/// Approximate counts per needle from the start of each file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preview {
+    pub generation: u64,
+    /// Occurrences of every needle, in the order of the needles
+    pub counts: Vec<(String, usize)>,
+    /// Files that could be read
+    pub files: usize,
+}
+
+/// A full search for the background worker to run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchRequest {
+    pub needles: Vec<(String, String)>,
+    pub files: Vec<String>,
+    pub extract: ExtractOptions,
+    /// The app's token; once cancelled, no further file is searched
+    pub cancel: CancelToken,
+}
+
+impl SearchRequest {
+    /// Search every file in turn, sending an event as each one starts and
+    /// finishes and [`SearchEvent::Finished`] at the end. Stops early once
+    /// the app is cancelled or nobody is listening any more.
+    pub fn run(&self, events: &mpsc::Sender<SearchEvent>) {
+        for (index, file) in self.files.iter().enumerate() {
+            if self.cancel.is_cancelled()
+                || events
+                    .send(SearchEvent::Started {
+                        index,
+                        file: file.clone(),
+                    })
+                    .is_err()
+            {
+                return;
+            }
+            let event = match parsers::search_with_progress(
+                &self.needles,
+                file,
+                self.extract,
+                &mut NullReporter,
+            ) {
+                Ok(results) => SearchEvent::Searched {
+                    file: file.clone(),
+                    results,
+                },
+                Err(err) => SearchEvent::Failed {
+                    file: file.clone(),
+                    error: err.to_string(),
+                },
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+        let _ = events.send(SearchEvent::Finished);
+    }
+}
+
+/// What the search worker tells the UI loop
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchEvent {
+    /// The worker opened the file at `index` of the request's files
+    Started { index: usize, file: String },
+    /// The file was searched
+    Searched {
+        file: String,
+        results: SearchResults,
+    },
+    /// The file could not be read or parsed
+    Failed { file: String, error: String },
+    /// Every file was searched
+    Finished,
+}
+
+/// What the UI loop does after a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    Continue,
+    StartSearch,
+    Quit,
+}
+
 pub struct TuiApp {
     pub current_tab: usize,
     pub search_terms: Vec<String>,
@@ -37,6 +205,28 @@ pub struct TuiApp {
     pub current_file: String,
     pub files_processed: usize,
     pub total_files: usize,
+    /// Files the last search could not read or parse, with why
+    pub search_failures: Vec<(String, String)>,
+    pub settings: SearchSettings,
+    /// Row highlighted in the settings tab
+    pub selected_setting: usize,
+    /// Why the last settings change was refused
+    pub settings_error: Option<String>,
+    /// Whether keys edit the needles in the search tab
+    pub editing: bool,
+    /// The term being typed, added to `search_terms` by Enter
+    pub input: String,
+    /// Row highlighted in the search terms, for `d` to remove
+    pub selected_term: usize,
+    pub preview_budget: PreviewBudget,
+    /// Counts every needle edit; a preview for an older one is stale
+    pub preview_generation: u64,
+    /// When the pending preview starts, once edits have paused
+    pub preview_due: Option<Instant>,
+    /// The latest preview, kept apart from `search_results`
+    pub preview: Option<Preview>,
+    /// Cancelled by Ctrl-C, to stop the app and any search or preview
+    pub cancel: CancelToken,
 }
 
 impl Default for TuiApp {
@@ -51,6 +241,18 @@ impl Default for TuiApp {
             current_file: String::new(),
             files_processed: 0,
             total_files: 0,
+            search_failures: Vec::new(),
+            settings: SearchSettings::default(),
+            selected_setting: 0,
+            settings_error: None,
+            editing: false,
+            input: String::new(),
+            selected_term: 0,
+            preview_budget: PreviewBudget::default(),
+            preview_generation: 0,
+            preview_due: None,
+            preview: None,
+            cancel: CancelToken::new(),
         }
     }
 }
@@ -60,13 +262,21 @@ impl TuiApp {
         Self::default()
     }
 
+    /// Start with the settings given on the command line
+    pub fn with_settings(settings: SearchSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
     pub fn run(&mut self) -> Result<()> {
         // Show startup logo
         self.show_startup_logo()?;
-        
+
         // Clear screen before starting TUI
         execute!(stdout(), Clear(ClearType::All))?;
-        
+
         // Setup terminal
         enable_raw_mode()?;
         let mut stdout = stdout();
@@ -75,7 +285,11 @@ impl TuiApp {
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
 
+        // Raw mode turns Ctrl-C into a key, but a SIGINT sent from
+        // elsewhere stops the app the same way
+        let graceful = interrupt::graceful(&self.cancel);
         let res = self.run_app(&mut terminal);
+        drop(graceful);
 
         // Restore terminal
         disable_raw_mode()?;
@@ -88,56 +302,234 @@ impl TuiApp {
 
         Ok(())
     }
-    
+
     fn show_startup_logo(&self) -> Result<()> {
         let logo = r#"
 DocSearcher
 ===========
 "#;
         println!("{}", logo);
-        
+
         // Give user a moment to see the logo
         std::thread::sleep(Duration::from_millis(500));
-        
+
         Ok(())
     }
 
-    fn run_app(&mut self, terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>) -> Result<()> {
+    fn run_app(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    ) -> Result<()> {
+        let (previews, finished) = mpsc::channel();
+        let (searches, search_events) = mpsc::channel();
         loop {
+            if self.cancel.is_cancelled() {
+                return Ok(());
+            }
             terminal.draw(|f| self.ui(f))?;
 
+            for preview in finished.try_iter() {
+                self.receive_preview(preview);
+            }
+            for event in search_events.try_iter() {
+                self.receive_search_event(event);
+            }
+            if let Some(request) = self.poll_preview(Instant::now()) {
+                let previews = previews.clone();
+                thread::spawn(move || previews.send(request.run()));
+            }
+            if !event::poll(TICK)? {
+                continue;
+            }
+
             if let Event::Key(key) = event::read()? {
-                if let KeyCode::Char('q') = key.code {
-                    return Ok(());
+                match self.handle_key(key, Instant::now()) {
+                    KeyAction::Continue => {}
+                    KeyAction::StartSearch => {
+                        if let Some(request) = self.start_search() {
+                            let searches = searches.clone();
+                            thread::spawn(move || request.run(&searches));
+                        }
+                    }
+                    KeyAction::Quit => return Ok(()),
                 }
-                if let KeyCode::Char('h') = key.code {
-                    self.current_tab = (self.current_tab + 1) % 4;
+            }
+        }
+    }
+
+    /// Apply a key pressed at `now` to the app's state
+    pub fn handle_key(&mut self, key: KeyEvent, now: Instant) -> KeyAction {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.cancel.cancel();
+            return KeyAction::Quit;
+        }
+        if key.code == KeyCode::Char('s') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return KeyAction::StartSearch;
+        }
+        if self.editing {
+            self.edit_needles(key.code, now);
+            return KeyAction::Continue;
+        }
+        match key.code {
+            KeyCode::Char('q') => {
+                // Stops a search under way after the file it is on
+                self.cancel.cancel();
+                return KeyAction::Quit;
+            }
+            KeyCode::Char('h') => self.current_tab = (self.current_tab + 1) % 4,
+            KeyCode::Char('l') => {
+                self.current_tab = if self.current_tab == 0 {
+                    3
+                } else {
+                    self.current_tab - 1
                 }
-                if let KeyCode::Char('l') = key.code {
-                    self.current_tab = if self.current_tab == 0 { 3 } else { self.current_tab - 1 };
+            }
+            code if self.current_tab == 0 => self.handle_terms_key(code, now),
+            code if self.current_tab == 3 => self.handle_settings_key(code),
+            _ => {}
+        }
+        KeyAction::Continue
+    }
+
+    /// Start editing with i or Enter, move through the terms with j/k or the
+    /// arrow keys and remove the highlighted one with d
+    fn handle_terms_key(&mut self, code: KeyCode, now: Instant) {
+        let count = self.search_terms.len();
+        match code {
+            KeyCode::Char('i') | KeyCode::Enter => self.set_editing(true, now),
+            KeyCode::Down | KeyCode::Char('j') if count > 0 => {
+                self.selected_term = (self.selected_term + 1) % count
+            }
+            KeyCode::Up | KeyCode::Char('k') if count > 0 => {
+                self.selected_term = (self.selected_term + count - 1) % count
+            }
+            KeyCode::Char('d') if self.selected_term < count => {
+                self.search_terms.remove(self.selected_term);
+                self.selected_term = self.selected_term.min(count.saturating_sub(2));
+                self.needles_changed(now);
+            }
+            _ => {}
+        }
+    }
+
+    /// Type a term into the input, adding it to the needles with Enter;
+    /// Esc stops editing and keeps what is typed for later
+    pub fn edit_needles(&mut self, code: KeyCode, now: Instant) {
+        match code {
+            KeyCode::Esc => {
+                self.set_editing(false, now);
+                return;
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Enter => {
+                let term = self.input.trim().to_string();
+                self.input.clear();
+                if term.is_empty() {
+                    return;
                 }
-                if let KeyCode::Char('s') = key.code {
-                    if key.modifiers.contains(KeyModifiers::CONTROL) {
-                        self.start_search()?;
-                    }
+                self.search_terms.push(term);
+                self.selected_term = self.search_terms.len() - 1;
+            }
+            KeyCode::Backspace => {
+                if self.input.pop().is_none() {
+                    return;
                 }
             }
+            _ => return,
+        }
+        self.needles_changed(now);
+    }
+
+    /// Start or stop editing; the term being typed is only searched while
+    /// it is shown, so the needles change with it
+    fn set_editing(&mut self, editing: bool, now: Instant) {
+        self.editing = editing;
+        if !self.input.trim().is_empty() {
+            self.needles_changed(now);
+        }
+    }
+
+    /// Schedule a preview for after the edits pause; one still running for
+    /// the needles before this edit is ignored when it finishes
+    pub fn needles_changed(&mut self, now: Instant) {
+        self.preview_generation += 1;
+        self.preview_due = Some(now + PREVIEW_DEBOUNCE);
+    }
+
+    /// The preview to start at `now`, once the pending one is due
+    pub fn poll_preview(&mut self, now: Instant) -> Option<PreviewRequest> {
+        if self.preview_due.is_none_or(|due| now < due) {
+            return None;
+        }
+        self.preview_due = None;
+
+        let needles = self.needles();
+        if needles.is_empty() || self.selected_files.is_empty() {
+            self.preview = None;
+            return None;
+        }
+        // Previews only read the start of each file, whatever its size
+        let extract = self.extract_options(&needles)?;
+        Some(PreviewRequest {
+            generation: self.preview_generation,
+            files: self.selected_files.clone(),
+            budget: self.preview_budget,
+            extract,
+            needles,
+            cancel: self.cancel.clone(),
+        })
+    }
+
+    /// The search terms as needles, with the one being typed while editing
+    fn needles(&self) -> Vec<(String, String)> {
+        self.search_terms
+            .iter()
+            .chain(self.editing.then_some(&self.input))
+            .map(|term| term.trim())
+            .filter(|term| !term.is_empty())
+            .map(|term| (term.to_string(), String::new()))
+            .collect()
+    }
+
+    /// Show a finished preview, unless the needles changed since it started
+    pub fn receive_preview(&mut self, preview: Preview) {
+        if preview.generation == self.preview_generation {
+            self.preview = Some(preview);
+        }
+    }
+
+    /// Move through the settings with j/k or the arrow keys and change the
+    /// highlighted one with Enter or Space
+    fn handle_settings_key(&mut self, code: KeyCode) {
+        let count = self.settings.entries().len();
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected_setting = (self.selected_setting + 1) % count
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_setting = (self.selected_setting + count - 1) % count
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let (name, _) = self.settings.entries()[self.selected_setting];
+                self.settings_error = self.settings.cycle(name).err().map(|e| e.to_string());
+            }
+            _ => {}
         }
     }
 
     fn ui(&self, f: &mut Frame) {
         let size = f.size();
-        
+
         // Create main layout
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(2)
             .constraints(
                 [
-                    Constraint::Length(3),  // Header
-                    Constraint::Length(3),  // Tabs
-                    Constraint::Min(0),     // Content
-                    Constraint::Length(3),  // Status bar
+                    Constraint::Length(3), // Header
+                    Constraint::Length(3), // Tabs
+                    Constraint::Min(0),    // Content
+                    Constraint::Length(3), // Status bar
                 ]
                 .as_ref(),
             )
@@ -145,10 +537,10 @@ DocSearcher
 
         // Header
         self.draw_header(f, chunks[0]);
-        
+
         // Tabs
         self.draw_tabs(f, chunks[1]);
-        
+
         // Content based on current tab
         match self.current_tab {
             0 => self.draw_search_tab(f, chunks[2]),
@@ -157,30 +549,39 @@ DocSearcher
             3 => self.draw_settings_tab(f, chunks[2]),
             _ => unreachable!(),
         }
-        
+
         // Status bar
         self.draw_status_bar(f, chunks[3]);
     }
 
     fn draw_header(&self, f: &mut Frame, area: Rect) {
         let title = Line::from(vec![
-            Span::styled("DocSearcher", Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                "DocSearcher",
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            ),
             Span::raw(" v1.0"),
         ]);
-        
-        let paragraph = Paragraph::new(title)
-            .block(Block::default().borders(Borders::NONE));
+
+        let paragraph = Paragraph::new(title).block(Block::default().borders(Borders::NONE));
         f.render_widget(paragraph, area);
     }
 
     fn draw_tabs(&self, f: &mut Frame, area: Rect) {
-        let titles = vec!["Search", "Files", "Results", "Settings"];
+        let titles = ["Search", "Files", "Results", "Settings"];
         let tabs = titles
             .iter()
             .map(|t| {
                 let (first, rest) = t.split_at(1);
                 Line::from(vec![
-                    Span::styled(first, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        first,
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD),
+                    ),
                     Span::styled(rest, Style::default().fg(Color::Gray)),
                 ])
             })
@@ -190,31 +591,32 @@ DocSearcher
             .select(self.current_tab)
             .block(Block::default().borders(Borders::NONE))
             .style(Style::default().fg(Color::White))
-            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+            .highlight_style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            );
 
         f.render_widget(tabs, area);
     }
 
     fn draw_search_tab(&self, f: &mut Frame, area: Rect) {
+        let lines = self.search_terms.len() + usize::from(self.editing);
+        let terms_height = lines.clamp(1, 8) as u16 + 2;
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(3),
-                Constraint::Length(3),
-                Constraint::Min(0),
-            ].as_ref())
+            .constraints(
+                [
+                    Constraint::Length(terms_height),
+                    Constraint::Length(3),
+                    Constraint::Min(0),
+                    Constraint::Length(3),
+                ]
+                .as_ref(),
+            )
             .split(area);
 
-        // Search terms input
-        let search_terms = if self.search_terms.is_empty() {
-            "Enter search terms (one per line)...".to_string()
-        } else {
-            self.search_terms.join("\n")
-        };
-        
-        let search_input = Paragraph::new(search_terms)
-            .block(Block::default().title("Search Terms").borders(Borders::ALL));
-        f.render_widget(search_input, chunks[0]);
+        self.draw_terms(f, chunks[0]);
 
         // File selection
         let files_display = if self.selected_files.is_empty() {
@@ -222,28 +624,125 @@ DocSearcher
         } else {
             format!("{} files selected", self.selected_files.len())
         };
-        
+
         let files_input = Paragraph::new(files_display)
             .block(Block::default().title("Target Files").borders(Borders::ALL));
         f.render_widget(files_input, chunks[1]);
 
+        self.draw_preview(f, chunks[2]);
+
         // Search button
         let search_button = Paragraph::new("Press Ctrl+S to start search")
             .block(Block::default().title("Actions").borders(Borders::ALL));
-        f.render_widget(search_button, chunks[2]);
+        f.render_widget(search_button, chunks[3]);
+    }
+
+    /// The search terms, one per line, with the highlighted one marked and,
+    /// while editing, the term being typed and the cursor after it
+    fn draw_terms(&self, f: &mut Frame, area: Rect) {
+        let title = if self.editing {
+            "Search Terms (editing, Enter adds, Esc stops)"
+        } else {
+            "Search Terms"
+        };
+        let block = Block::default().title(title).borders(Borders::ALL);
+        if self.search_terms.is_empty() && !self.editing {
+            let hint = "Press i or Enter to add search terms, one per line...";
+            f.render_widget(Paragraph::new(hint).block(block), area);
+            return;
+        }
+
+        let width = area.width.saturating_sub(4) as usize;
+        let mut lines: Vec<Line> = self
+            .search_terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| {
+                let term = truncate_to_width(term, width).into_owned();
+                match i == self.selected_term && !self.editing {
+                    true => Line::from(Span::styled(
+                        format!("> {}", term),
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD),
+                    )),
+                    false => Line::from(format!("  {}", term)),
+                }
+            })
+            .collect();
+        if self.editing {
+            lines.push(Line::from(format!("+ {}", self.input)));
+        }
+
+        // Keep the input, or else the highlighted term, in view
+        let rows = area.height.saturating_sub(2) as usize;
+        let last = if self.editing {
+            lines.len()
+        } else {
+            self.selected_term + 1
+        };
+        let skip = last.saturating_sub(rows);
+        if self.editing {
+            let column = 2 + self.input.width();
+            let x = area.x + 1 + column.min(area.width.saturating_sub(3) as usize) as u16;
+            let y = area.y + 1 + (lines.len() - 1 - skip) as u16;
+            f.set_cursor(x, y);
+        }
+        let lines: Vec<Line> = lines.into_iter().skip(skip).collect();
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// Approximate counts from the latest preview, labelled as such
+    fn draw_preview(&self, f: &mut Frame, area: Rect) {
+        let budget = self.preview_budget;
+        let mut title = format!(
+            "Preview - approximate, first {} blocks or {} ms of each file",
+            budget.blocks,
+            budget.time.as_millis()
+        );
+        if self.preview_due.is_some() {
+            title.push_str(" (updating)");
+        }
+        let block = Block::default().title(title).borders(Borders::ALL);
+
+        let Some(preview) = &self.preview else {
+            let hint = "Counts appear here when you pause while editing the search terms";
+            f.render_widget(Paragraph::new(hint).block(block), area);
+            return;
+        };
+        let column = (area.width.saturating_sub(2) as usize * 70 / 100).saturating_sub(1);
+        let rows: Vec<Row> = preview
+            .counts
+            .iter()
+            .map(|(term, count)| {
+                Row::new(vec![
+                    truncate_to_width(term, column).into_owned(),
+                    format!("~{}", count),
+                ])
+            })
+            .collect();
+        let table = Table::new(rows)
+            .header(Row::new(vec![
+                "Term".to_string(),
+                format!("Across {} files", preview.files),
+            ]))
+            .block(block)
+            .widths(&[Constraint::Percentage(70), Constraint::Percentage(30)]);
+        f.render_widget(table, area);
     }
 
     fn draw_files_tab(&self, f: &mut Frame, area: Rect) {
-        let files: Vec<ListItem> = self.selected_files
+        let files: Vec<ListItem> = self
+            .selected_files
             .iter()
             .map(|file| {
-                let extension = file.split('.').last().unwrap_or("");
+                let extension = file.split('.').next_back().unwrap_or("");
                 let indicator = match extension.to_lowercase().as_str() {
                     "pdf" => "[PDF]",
                     "docx" => "[DOCX]",
                     _ => "[UNK]",
                 };
-                
+
                 ListItem::new(vec![Line::from(vec![
                     Span::styled(indicator, Style::default().fg(Color::Blue)),
                     Span::raw(" "),
@@ -252,33 +751,52 @@ DocSearcher
             })
             .collect();
 
-        let files_list = List::new(files)
-            .block(Block::default().title("Selected Files").borders(Borders::ALL));
+        let files_list = List::new(files).block(
+            Block::default()
+                .title("Selected Files")
+                .borders(Borders::ALL),
+        );
         f.render_widget(files_list, area);
     }
 
     fn draw_results_tab(&self, f: &mut Frame, area: Rect) {
         if self.search_results.is_empty() {
-            let no_results = Paragraph::new("No search results yet. Run a search to see results here.")
-                .block(Block::default().title("Search Results").borders(Borders::ALL));
+            let no_results =
+                Paragraph::new("No search results yet. Run a search to see results here.").block(
+                    Block::default()
+                        .title("Search Results")
+                        .borders(Borders::ALL),
+                );
             f.render_widget(no_results, area);
             return;
         }
 
-        let results: Vec<Row> = self.search_results
+        // Cut cells to their column by display width, so wide CJK and emoji
+        // characters end in `…` instead of being clipped mid-column
+        let inner = area.width.saturating_sub(2) as usize;
+        let column = |percent: usize| (inner * percent / 100).saturating_sub(1);
+        let results: Vec<Row> = self
+            .search_results
             .iter()
             .map(|result| {
                 Row::new(vec![
-                    result.0.clone(),
-                    result.1.clone(),
-                    "Match".to_string(),
+                    truncate_to_width(&result.term, column(30)).into_owned(),
+                    truncate_to_width(&result.metadata, column(50)).into_owned(),
+                    match result.location.is_body() {
+                        true => "Match".to_string(),
+                        false => format!("Match ({})", result.location),
+                    },
                 ])
             })
             .collect();
 
         let table = Table::new(results)
             .header(Row::new(vec!["Term", "Metadata", "Status"]))
-            .block(Block::default().title("Search Results").borders(Borders::ALL))
+            .block(
+                Block::default()
+                    .title("Search Results")
+                    .borders(Borders::ALL),
+            )
             .widths(&[
                 Constraint::Percentage(30),
                 Constraint::Percentage(50),
@@ -289,25 +807,60 @@ DocSearcher
     }
 
     fn draw_settings_tab(&self, f: &mut Frame, area: Rect) {
-        let settings_text = vec![
-            "Keyboard Shortcuts:",
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(9), Constraint::Min(0)].as_ref())
+            .split(area);
+
+        let shortcuts = [
             "  h/l - Navigate tabs",
+            "  i/Enter - Edit search terms (Search tab), Esc to stop",
+            "  j/k - Select search term or setting",
+            "  d - Remove selected search term",
+            "  Enter/Space - Change setting",
             "  Ctrl+S - Start search",
-            "  q - Quit",
-            "",
-            "Search Options:",
-            "  Case sensitive: false",
-            "  Whole word: false",
-            "  Pattern matching: false",
+            "  q, Ctrl+C - Quit",
         ];
+        let help = Paragraph::new(shortcuts.join("\n")).block(
+            Block::default()
+                .title("Keyboard Shortcuts")
+                .borders(Borders::ALL),
+        );
+        f.render_widget(help, chunks[0]);
 
-        let settings = Paragraph::new(settings_text.join("\n"))
-            .block(Block::default().title("Settings").borders(Borders::ALL));
-        f.render_widget(settings, area);
+        let rows: Vec<Row> = self
+            .settings
+            .entries()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, value))| {
+                let style = if i == self.selected_setting {
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                Row::new(vec![name.to_string(), value.to_string()]).style(style)
+            })
+            .collect();
+
+        let title = match &self.settings_error {
+            Some(error) => format!("Search Options - {}", error),
+            None => "Search Options".to_string(),
+        };
+        let table = Table::new(rows)
+            .header(Row::new(vec!["Setting", "Value"]))
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .widths(&[Constraint::Percentage(40), Constraint::Percentage(60)]);
+        f.render_widget(table, chunks[1]);
     }
 
     fn draw_status_bar(&self, f: &mut Frame, area: Rect) {
-        let status_text = if self.is_searching {
+        let status_text = if self.editing && !self.is_searching {
+            "Editing search terms - type a term, Enter adds it, Backspace deletes, Esc stops"
+                .to_string()
+        } else if self.is_searching {
             format!(
                 "Searching: {} ({:.1}%) - {} of {} files processed",
                 self.current_file,
@@ -315,53 +868,86 @@ DocSearcher
                 self.files_processed,
                 self.total_files
             )
-        } else {
+        } else if self.search_failures.is_empty() {
             "Ready - Press 'h' for help, 'q' to quit".to_string()
+        } else {
+            format!(
+                "Ready - {} of {} files could not be searched (first: {}) - 'q' to quit",
+                self.search_failures.len(),
+                self.total_files,
+                self.search_failures[0].0
+            )
         };
 
-        let status = Paragraph::new(status_text)
-            .block(Block::default().borders(Borders::TOP));
+        let status = Paragraph::new(status_text).block(Block::default().borders(Borders::TOP));
         f.render_widget(status, area);
     }
 
-    fn start_search(&mut self) -> Result<()> {
-        if self.search_terms.is_empty() || self.selected_files.is_empty() {
-            return Ok(());
+    /// Reset the progress for a search of the typed terms in the selected
+    /// files, returning the request for the worker; `None` while a search
+    /// is under way or there is nothing to search
+    pub fn start_search(&mut self) -> Option<SearchRequest> {
+        let needles = self.needles();
+        if self.is_searching || needles.is_empty() || self.selected_files.is_empty() {
+            return None;
         }
+        let extract = self.extract_options(&needles)?;
 
         self.is_searching = true;
+        self.search_progress = 0.0;
+        self.current_file.clear();
         self.files_processed = 0;
         self.total_files = self.selected_files.len();
         self.search_results.clear();
+        self.search_failures.clear();
 
-        for (i, file_path) in self.selected_files.iter().enumerate() {
-            self.current_file = file_path.clone();
-            self.files_processed = i;
-            self.search_progress = i as f32 / self.total_files as f32;
-
-            // Process the file
-            if let Ok(file_type) = parse_filetype(file_path) {
-                let result = match file_type {
-                    FileType::Docx => parse_docx_from_path("contacts.csv", file_path),
-                    FileType::Pdf => parse_pdf_from_path("contacts.csv", file_path),
-                };
+        Some(SearchRequest {
+            files: self.selected_files.clone(),
+            extract,
+            needles,
+            cancel: self.cancel.clone(),
+        })
+    }
 
-                if let Ok(matches) = result {
-                    for (term, metadata) in matches {
-                        self.search_results.push((term, metadata));
-                    }
-                }
+    /// The options to search `needles` with, from the settings built into a
+    /// [`SearchConfig`](crate::types::SearchConfig); `None` when they cannot
+    /// be combined, with the reason shown on the settings tab
+    fn extract_options(&mut self, needles: &[(String, String)]) -> Option<ExtractOptions> {
+        let strategy = self.settings.matcher.resolve(needles, None);
+        match self.settings.extract_options(strategy) {
+            Ok(extract) => Some(extract),
+            Err(e) => {
+                self.settings_error = Some(e.to_string());
+                None
             }
-
-            // Small delay to show progress
-            std::thread::sleep(Duration::from_millis(100));
         }
+    }
 
-        self.is_searching = false;
-        self.search_progress = 1.0;
-        self.files_processed = self.total_files;
+    /// Update the progress and results with an event from the worker
+    pub fn receive_search_event(&mut self, event: SearchEvent) {
+        match event {
+            SearchEvent::Started { index, file } => {
+                self.current_file = file;
+                self.search_progress = index as f32 / self.total_files as f32;
+            }
+            SearchEvent::Searched { results, .. } => {
+                self.search_results.extend(results);
+                self.file_done();
+            }
+            SearchEvent::Failed { file, error } => {
+                self.search_failures.push((file, error));
+                self.file_done();
+            }
+            SearchEvent::Finished => {
+                self.is_searching = false;
+                self.current_file.clear();
+            }
+        }
+    }
 
-        Ok(())
+    fn file_done(&mut self) {
+        self.files_processed += 1;
+        self.search_progress = self.files_processed as f32 / self.total_files as f32;
     }
 }
 
@@ -371,8 +957,374 @@ pub fn show_progress_bar(total: u64, message: &str) -> ProgressBar {
         ProgressStyle::default_bar()
             .template("{spinner:.green} {msg}: [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
             .unwrap()
-            .progress_chars("█▉▊▋▌▍▎▏ ")
+            .progress_chars("█▉▊▋▌▍▎▏ "),
     );
     pb.set_message(message.to_string());
     pb
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// An app with a file selected, still editing after typing `text`
+    /// starting at `start`, one key every 50 ms; the last line is left in
+    /// the input
+    fn typed(text: &str, start: Instant) -> (TuiApp, Instant) {
+        let mut app = TuiApp {
+            selected_files: vec!["report.docx".to_string()],
+            editing: true,
+            ..TuiApp::new()
+        };
+        let mut now = start;
+        for c in text.chars() {
+            let key = if c == '\n' {
+                KeyCode::Enter
+            } else {
+                KeyCode::Char(c)
+            };
+            app.edit_needles(key, now);
+            now += Duration::from_millis(50);
+        }
+        (app, now - Duration::from_millis(50))
+    }
+
+    #[test]
+    fn test_an_edit_schedules_one_preview_after_the_debounce_window() {
+        let start = Instant::now();
+        let (mut app, last_key) = typed("Alice\nBob", start);
+        assert_eq!(app.search_terms, ["Alice"]);
+        assert_eq!(app.input, "Bob");
+
+        // Nothing while keys keep coming or before the window has passed
+        assert_eq!(app.poll_preview(start + PREVIEW_DEBOUNCE), None);
+        assert_eq!(
+            app.poll_preview(last_key + PREVIEW_DEBOUNCE - Duration::from_millis(1)),
+            None
+        );
+
+        let request = app
+            .poll_preview(last_key + PREVIEW_DEBOUNCE)
+            .expect("a preview once the edits paused");
+        assert_eq!(request.generation, app.preview_generation);
+        assert_eq!(
+            request.needles,
+            [
+                ("Alice".to_string(), String::new()),
+                ("Bob".to_string(), String::new())
+            ]
+        );
+        assert_eq!(request.files, ["report.docx"]);
+        assert_eq!(app.poll_preview(last_key + PREVIEW_DEBOUNCE * 10), None);
+    }
+
+    #[test]
+    fn test_preview_results_never_overwrite_full_results() {
+        let start = Instant::now();
+        let (mut app, last_key) = typed("Alice", start);
+        app.search_results = vec![SearchResult::new("Alice", "id=1")];
+        let request = app.poll_preview(last_key + PREVIEW_DEBOUNCE).unwrap();
+
+        let preview = Preview {
+            generation: request.generation,
+            counts: vec![("Alice".to_string(), 3)],
+            files: 1,
+        };
+        app.receive_preview(preview.clone());
+        assert_eq!(app.preview, Some(preview));
+        assert_eq!(app.search_results, [SearchResult::new("Alice", "id=1")]);
+
+        // A preview for needles edited since is dropped
+        app.edit_needles(KeyCode::Backspace, last_key + PREVIEW_DEBOUNCE * 2);
+        app.receive_preview(Preview {
+            generation: request.generation,
+            counts: vec![("Alice".to_string(), 9)],
+            files: 1,
+        });
+        assert_eq!(
+            app.preview.as_ref().unwrap().counts,
+            [("Alice".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_preview_reads_only_the_start_of_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let paragraphs = [
+            "Alice Johnson",
+            "Filler",
+            "Filler",
+            "Bob Smith",
+            "Alice Johnson",
+        ];
+        let document = dir.path().join("report.docx");
+        std::fs::write(&document, fixtures::docx(&paragraphs)).unwrap();
+
+        let request = PreviewRequest {
+            generation: 1,
+            needles: vec![
+                ("Alice Johnson".to_string(), String::new()),
+                ("Bob Smith".to_string(), String::new()),
+            ],
+            files: vec![
+                document.to_string_lossy().into_owned(),
+                dir.path()
+                    .join("missing.pdf")
+                    .to_string_lossy()
+                    .into_owned(),
+            ],
+            budget: PreviewBudget {
+                blocks: 3,
+                time: Duration::from_secs(60),
+            },
+            extract: ExtractOptions::default(),
+            cancel: CancelToken::new(),
+        };
+        let preview = request.run();
+        assert_eq!(
+            preview.counts,
+            [
+                ("Alice Johnson".to_string(), 1),
+                ("Bob Smith".to_string(), 0)
+            ]
+        );
+        assert_eq!(preview.files, 1);
+
+        let whole = PreviewRequest {
+            budget: PreviewBudget {
+                blocks: 100,
+                ..request.budget
+            },
+            ..request
+        };
+        assert_eq!(
+            whole.run().counts,
+            [
+                ("Alice Johnson".to_string(), 2),
+                ("Bob Smith".to_string(), 1)
+            ]
+        );
+
+        // A cancelled app's preview searches nothing more
+        whole.cancel.cancel();
+        assert_eq!(whole.run().files, 0);
+    }
+
+    #[test]
+    fn test_settings_that_cannot_be_combined_start_no_search() {
+        let (mut app, now) = typed("Alice Johnson", Instant::now());
+        app.settings = SearchSettings {
+            fuzzy: 1,
+            regex_needles: true,
+            ..SearchSettings::default()
+        };
+
+        assert_eq!(app.start_search(), None);
+        assert!(!app.is_searching);
+        assert_eq!(
+            app.settings_error.as_deref(),
+            Some("fuzzy matching cannot be combined with regular-expression needles")
+        );
+        assert_eq!(app.poll_preview(now + PREVIEW_DEBOUNCE), None);
+    }
+
+    #[test]
+    fn test_search_worker_reports_each_file_and_uses_the_typed_terms() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("report.docx");
+        std::fs::write(
+            &document,
+            fixtures::docx(&["Alice Johnson signed", "Bob Smith did not"]),
+        )
+        .unwrap();
+        let document = document.to_string_lossy().into_owned();
+        let missing = dir
+            .path()
+            .join("missing.pdf")
+            .to_string_lossy()
+            .into_owned();
+
+        let (mut app, _) = typed("Alice Johnson\n\nCarol", Instant::now());
+        app.selected_files = vec![document.clone(), missing.clone()];
+        let request = app.start_search().expect("a search of the typed terms");
+        assert_eq!(
+            request.needles,
+            [
+                ("Alice Johnson".to_string(), String::new()),
+                ("Carol".to_string(), String::new())
+            ]
+        );
+        assert!(app.is_searching);
+        assert_eq!(app.start_search(), None, "one search at a time");
+
+        let (events, received) = mpsc::channel();
+        request.run(&events);
+        let events: Vec<SearchEvent> = received.try_iter().collect();
+        assert_eq!(
+            events[0],
+            SearchEvent::Started {
+                index: 0,
+                file: document.clone()
+            }
+        );
+        let found = SearchResult {
+            file: Some(document.clone().into()),
+            page: Some(1),
+            context: Some("Alice Johnson signed".to_string()),
+            ..SearchResult::new("Alice Johnson", "")
+        };
+        assert_eq!(
+            events[1],
+            SearchEvent::Searched {
+                file: document.clone(),
+                results: vec![found.clone()]
+            }
+        );
+        assert_eq!(
+            events[2],
+            SearchEvent::Started {
+                index: 1,
+                file: missing.clone()
+            }
+        );
+        assert!(matches!(&events[3], SearchEvent::Failed { file, .. } if *file == missing));
+        assert_eq!(events[4], SearchEvent::Finished);
+
+        for (i, event) in events.into_iter().enumerate() {
+            app.receive_search_event(event);
+            if i == 2 {
+                assert_eq!(app.current_file, missing);
+                assert_eq!((app.files_processed, app.search_progress), (1, 0.5));
+            }
+        }
+        assert!(!app.is_searching);
+        assert_eq!(app.search_results, [found]);
+        assert_eq!(app.search_failures.len(), 1);
+
+        // A cancelled app's search sends nothing more
+        request.cancel.cancel();
+        let (events, received) = mpsc::channel();
+        request.run(&events);
+        assert_eq!(received.try_iter().count(), 0);
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    /// Press `keys` in turn, returning the action of the last one
+    fn press(app: &mut TuiApp, keys: &[KeyCode]) -> KeyAction {
+        let now = Instant::now();
+        keys.iter().fold(KeyAction::Continue, |_, code| {
+            app.handle_key(key(*code), now)
+        })
+    }
+
+    fn chars(text: &str) -> Vec<KeyCode> {
+        text.chars().map(KeyCode::Char).collect()
+    }
+
+    #[test]
+    fn test_terms_are_typed_into_the_input_and_added_with_enter() {
+        let mut app = TuiApp::new();
+
+        // Typing outside edit mode does not edit; i starts it
+        press(&mut app, &chars("Ab"));
+        assert!(!app.editing && app.input.is_empty());
+        press(&mut app, &[KeyCode::Char('i')]);
+        assert!(app.editing);
+
+        press(&mut app, &chars("Alicx"));
+        press(
+            &mut app,
+            &[KeyCode::Backspace, KeyCode::Char('e'), KeyCode::Enter],
+        );
+        assert_eq!(app.search_terms, ["Alice"]);
+        assert!(app.input.is_empty());
+
+        // q and h are text while editing; a blank term is not added
+        press(&mut app, &chars("  "));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(press(&mut app, &chars("qhBob")), KeyAction::Continue);
+        assert_eq!(app.current_tab, 0);
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.search_terms, ["Alice", "qhBob"]);
+
+        // Esc leaves edit mode and keeps what is typed; Enter resumes
+        press(&mut app, &chars("Car"));
+        press(&mut app, &[KeyCode::Esc]);
+        assert!(!app.editing);
+        assert_eq!(app.input, "Car");
+        press(&mut app, &[KeyCode::Enter]);
+        assert!(app.editing);
+        press(&mut app, &chars("ol"));
+        press(&mut app, &[KeyCode::Enter, KeyCode::Esc]);
+        assert_eq!(app.search_terms, ["Alice", "qhBob", "Carol"]);
+        assert_eq!(press(&mut app, &[KeyCode::Char('q')]), KeyAction::Quit);
+    }
+
+    #[test]
+    fn test_a_term_left_in_the_input_after_esc_is_not_searched() {
+        let (mut app, last_key) = typed("Alice\nBo", Instant::now());
+        app.edit_needles(KeyCode::Esc, last_key);
+        assert_eq!(app.input, "Bo");
+
+        let alice = [("Alice".to_string(), String::new())];
+        assert_eq!(
+            app.start_search()
+                .expect("a search of the added terms")
+                .needles,
+            alice
+        );
+        app.is_searching = false;
+        let request = app
+            .poll_preview(last_key + PREVIEW_DEBOUNCE)
+            .expect("a preview without the hidden term");
+        assert_eq!(request.needles, alice);
+
+        // Editing again shows the term and searches it too
+        press(&mut app, &[KeyCode::Char('i')]);
+        assert_eq!(app.start_search().unwrap().needles.len(), 2);
+    }
+
+    #[test]
+    fn test_the_selected_term_is_removed_with_d() {
+        let mut app = TuiApp {
+            search_terms: vec!["Alice".to_string(), "Bob".to_string(), "Carol".to_string()],
+            ..TuiApp::new()
+        };
+
+        press(&mut app, &[KeyCode::Char('j'), KeyCode::Char('d')]);
+        assert_eq!(app.search_terms, ["Alice", "Carol"]);
+        assert_eq!(app.selected_term, 1);
+        assert!(
+            app.preview_due.is_some(),
+            "removing a term refreshes the preview"
+        );
+
+        // Removing the last term moves the selection up; k wraps around
+        press(&mut app, &[KeyCode::Char('d')]);
+        assert_eq!(
+            (app.search_terms.as_slice(), app.selected_term),
+            (&["Alice".to_string()][..], 0)
+        );
+        press(
+            &mut app,
+            &[KeyCode::Char('k'), KeyCode::Char('d'), KeyCode::Char('d')],
+        );
+        assert!(app.search_terms.is_empty());
+
+        // Only the Search tab edits terms
+        app.search_terms = vec!["Alice".to_string()];
+        press(&mut app, &[KeyCode::Char('h'), KeyCode::Char('d')]);
+        assert_eq!(app.search_terms, ["Alice"]);
+        assert_eq!(
+            app.handle_key(
+                KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL),
+                Instant::now()
+            ),
+            KeyAction::StartSearch
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Errors that callers may need to tell apart from other failures.
+//!
+//! The public parser and needles-reading functions return a
+//! [`DocSearchError`], so a library caller can tell a missing needles file
+//! from a corrupt document or an unsupported file type. Inside the crate most
+//! failures are plain [`anyhow::Error`] messages; [`DocumentError`] variants
+//! are returned where batch mode or the CLI treat a failure specially, and can
+//! be recovered with `error.downcast_ref::<DocumentError>()`.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::utils::NeedleFileStats;
+
+/// Why a search through the library API failed
+#[derive(Debug, thiserror::Error)]
+pub enum DocSearchError {
+    /// The needles file or the document does not exist
+    #[error("File not found: {}", .0.display())]
+    FileNotFound(PathBuf),
+    /// Neither the document's extension nor its magic bytes name a supported type
+    #[error("Unsupported file type. Only .docx, .pdf, .doc, .xlsx, .odt, .txt, .rtf and .epub files are supported. Got: {0}")]
+    UnsupportedFormat(String),
+    /// A document of a supported type whose text could not be extracted,
+    /// such as a DOCX that is not a valid archive or a damaged PDF; `path`
+    /// is empty for a document searched in memory
+    #[error("Corrupt document{}: {source}", located(path))]
+    CorruptDocument {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The needle on `line` of the needles file, counted from 1, cannot be
+    /// searched for, such as a regular expression that does not compile;
+    /// `content` is the needle as written
+    #[error("Invalid needle on line {line}: {content}")]
+    InvalidNeedles { line: usize, content: String },
+    /// The needles file holds no needle, only comments, blank or malformed
+    /// lines
+    #[error("No valid search terms found in input ({0})")]
+    NeedlesEmpty(NeedleFileStats),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    /// A document that was recognised but cannot be searched
+    #[error(transparent)]
+    Document(#[from] DocumentError),
+    /// The search of the document at `path` took longer than the
+    /// [`timeout`](crate::types::SearchLimits::timeout) it was given
+    #[error("Search of {} timed out after {after:?}", path.display())]
+    TimedOut { path: PathBuf, after: Duration },
+    /// Any other failure, such as needles that are not UTF-8 or a search
+    /// that did not finish
+    #[error("{0}")]
+    ParseError(String),
+}
+
+/// Why [`SearchConfigBuilder::build`](crate::types::SearchConfigBuilder::build)
+/// refused a combination of options
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ConfigError {
+    /// A fuzzy distance and regular-expression needles, which are never
+    /// matched approximately
+    #[error("fuzzy matching cannot be combined with regular-expression needles")]
+    FuzzyRegexNeedles,
+}
+
+/// ` <path>` after the error's name, or nothing for an empty path
+fn located(path: &Path) -> String {
+    match path.as_os_str().is_empty() {
+        true => String::new(),
+        false => format!(" {}", path.display()),
+    }
+}
+
+impl DocSearchError {
+    /// Classify a failure to extract the text of the document at `path`:
+    /// a typed error is kept, anything else means the document is corrupt
+    pub(crate) fn extraction(path: &Path, error: anyhow::Error) -> Self {
+        match recover(error) {
+            Ok(error) => error,
+            Err(error) => DocSearchError::CorruptDocument {
+                path: path.to_path_buf(),
+                source: error.into(),
+            },
+        }
+    }
+}
+
+/// Recovers the typed error an [`anyhow::Error`] was made from, if any,
+/// else a [`ParseError`](DocSearchError::ParseError) with its whole chain of
+/// causes. I/O errors are not recovered: the context naming the file would
+/// be lost.
+impl From<anyhow::Error> for DocSearchError {
+    fn from(error: anyhow::Error) -> Self {
+        recover(error).unwrap_or_else(|error| DocSearchError::ParseError(format!("{:#}", error)))
+    }
+}
+
+/// The typed error `error` was made from, or `error` itself
+fn recover(error: anyhow::Error) -> Result<DocSearchError, anyhow::Error> {
+    let error = match error.downcast::<DocSearchError>() {
+        Ok(error) => return Ok(error),
+        Err(error) => error,
+    };
+    error
+        .downcast::<DocumentError>()
+        .map(DocSearchError::Document)
+}
+
+/// A document that was recognised but cannot be searched
+#[derive(Debug)]
+pub enum DocumentError {
+    /// A legacy Word 97-2003 binary document (OLE2 compound file), which
+    /// is only searchable in simple cases with the `doc` feature
+    LegacyDocUnsupported { path: PathBuf, reason: String },
+    /// A batch file left unsearched because the search was stopped early,
+    /// e.g. when the reader of streamed output went away
+    SearchStopped { path: PathBuf },
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentError::LegacyDocUnsupported { path, reason } => write!(
+                f,
+                "{} is a legacy Word 97-2003 (.doc) document and could not be searched ({}). \
+                 Convert it to .docx, e.g. `soffice --headless --convert-to docx {}`",
+                path.display(),
+                reason,
+                path.display()
+            ),
+            DocumentError::SearchStopped { path } => {
+                write!(f, "Search stopped before {} was searched", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DocumentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, parsers, types::ExtractOptions};
+
+    #[test]
+    fn test_library_errors_are_typed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(path("contacts.csv"), "Alice Johnson,id=1\n").unwrap();
+        std::fs::write(path("empty.csv"), "# no needles yet\n\n").unwrap();
+        std::fs::write(
+            path("regex.csv"),
+            "Alice Johnson,id=1\n# a comment\n/(unclosed/,id=2\n",
+        )
+        .unwrap();
+        std::fs::create_dir(path("folder.csv")).unwrap();
+        std::fs::write(path("report.docx"), fixtures::docx(&["Alice Johnson"])).unwrap();
+        std::fs::write(path("broken.docx"), b"not a zip archive").unwrap();
+        std::fs::write(path("broken.pdf"), b"not a pdf").unwrap();
+        std::fs::write(
+            path("broken.xlsx"),
+            fixtures::zip_archive(&[("xl/workbook.xml", "<workbook")]),
+        )
+        .unwrap();
+        std::fs::write(path("slides.pptx"), b"Alice Johnson").unwrap();
+        let search = |needles: &str, document: &str| {
+            parsers::parse_from_path(&path(needles), &path(document), ExtractOptions::default())
+        };
+
+        // A missing needles file or document is named
+        match search("missing.csv", "report.docx") {
+            Err(DocSearchError::FileNotFound(reported)) => {
+                assert_eq!(reported, dir.path().join("missing.csv"))
+            }
+            other => panic!("expected FileNotFound, got {:?}", other),
+        }
+        match search("contacts.csv", "absent.docx") {
+            Err(DocSearchError::FileNotFound(reported)) => {
+                assert_eq!(reported, dir.path().join("absent.docx"))
+            }
+            other => panic!("expected FileNotFound, got {:?}", other),
+        }
+        match search("empty.csv", "report.docx") {
+            Err(DocSearchError::NeedlesEmpty(stats)) => {
+                assert_eq!((stats.needles, stats.comments, stats.blank), (0, 1, 1))
+            }
+            other => panic!("expected NeedlesEmpty, got {:?}", other),
+        }
+        match search("regex.csv", "report.docx") {
+            Err(DocSearchError::InvalidNeedles { line, content }) => {
+                assert_eq!((line, content.as_str()), (3, "/(unclosed/"))
+            }
+            other => panic!("expected InvalidNeedles, got {:?}", other),
+        }
+        match search("folder.csv", "report.docx") {
+            Err(DocSearchError::IoError(e)) => {
+                assert!(e.to_string().contains("folder.csv"), "{}", e)
+            }
+            other => panic!("expected IoError, got {:?}", other),
+        }
+        // Plain .txt documents are searched now, so take another extension
+        match search("contacts.csv", "slides.pptx") {
+            Err(DocSearchError::UnsupportedFormat(reported)) => {
+                assert_eq!(reported, path("slides.pptx"))
+            }
+            other => panic!("expected UnsupportedFormat, got {:?}", other),
+        }
+        for document in ["broken.docx", "broken.pdf", "broken.xlsx"] {
+            match search("contacts.csv", document) {
+                Err(error @ DocSearchError::CorruptDocument { .. }) => {
+                    let DocSearchError::CorruptDocument { path: reported, .. } = &error else {
+                        unreachable!()
+                    };
+                    assert_eq!(*reported, dir.path().join(document));
+                    assert!(std::error::Error::source(&error).is_some(), "{}", document);
+                    assert!(
+                        error
+                            .to_string()
+                            .starts_with(&format!("Corrupt document {}: ", path(document))),
+                        "{}",
+                        error
+                    );
+                }
+                other => panic!("expected CorruptDocument for {}, got {:?}", document, other),
+            }
+        }
+        assert_eq!(search("contacts.csv", "report.docx").unwrap().len(), 1);
+
+        // In memory there is no path to name
+        match parsers::docx::parse_from_mem(
+            b"Alice Johnson,id=1\n",
+            b"not a zip archive",
+            ExtractOptions::default(),
+        ) {
+            Err(error @ DocSearchError::CorruptDocument { .. }) => assert!(
+                error.to_string().starts_with("Corrupt document: "),
+                "{}",
+                error
+            ),
+            other => panic!("expected CorruptDocument, got {:?}", other),
+        }
+        match crate::utils::read_needles_from_mem(b"Alice \xFF,id=1\n", None) {
+            Err(DocSearchError::ParseError(message)) => {
+                assert!(message.contains("UTF-8"), "{}", message)
+            }
+            other => panic!("expected ParseError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_typed_errors_survive_anyhow() {
+        let error = anyhow::Error::from(DocSearchError::UnsupportedFormat("a.pptx".to_string()));
+        assert!(matches!(
+            DocSearchError::from(error),
+            DocSearchError::UnsupportedFormat(_)
+        ));
+
+        let legacy = DocumentError::LegacyDocUnsupported {
+            path: PathBuf::from("old.doc"),
+            reason: "encrypted".to_string(),
+        };
+        assert!(matches!(
+            DocSearchError::extraction(Path::new("old.doc"), legacy.into()),
+            DocSearchError::Document(_)
+        ));
+        match DocSearchError::from(anyhow::anyhow!("bad workbook").context("Failed to read sheet"))
+        {
+            DocSearchError::ParseError(message) => {
+                assert_eq!(message, "Failed to read sheet: bad workbook")
+            }
+            other => panic!("expected ParseError, got {:?}", other),
+        }
+
+        // The CLI keeps the regular expression's error; the library gets the line
+        let error = crate::utils::check_regex_needles(
+            "Alice,id=1\n/(x/,id=2\n",
+            None,
+            ExtractOptions::default().matching.regex(),
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid regular expression on line 2 ('/(x/'):"),
+            "{}",
+            error
+        );
+        assert!(matches!(
+            DocSearchError::from(error),
+            DocSearchError::InvalidNeedles { line: 2, .. }
+        ));
+    }
+}
@@ -0,0 +1,799 @@
+//! Builders for small synthetic documents.
+//!
+//! These produce just enough structure for the parsers in this crate to
+//! handle them, and are used by the test suite and by benchmark corpora.
+
+use std::io::{Cursor, Write};
+
+use flate2::{Compression, GzBuilder};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const W_NAMESPACE: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+const R_NAMESPACE: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const SPREADSHEET_NAMESPACE: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+
+/// Needles, as lines of a needles file, holding emoji, a ZWJ sequence, a
+/// musical symbol and CJK Extension B ideographs: all outside the Basic
+/// Multilingual Plane. Each is found in [`NON_BMP_PARAGRAPHS`].
+pub const NON_BMP_NEEDLES: &str = "\
+Chat 😀 export,who=emoji
+𠀀𠀁𪚥 ledger,who=cjk-ext-b
+👩‍👩‍👧 family,who=<zwj & co>
+𝄞 \"Score\" 🎵,who=music
+";
+
+/// Paragraphs of a chat-log export containing every [`NON_BMP_NEEDLES`] term
+pub const NON_BMP_PARAGRAPHS: &[&str] = &[
+    "Exported: Chat 😀 export, 2026-03-01",
+    "Row 3 of the 𠀀𠀁𪚥 ledger total",
+    "Photo of the 👩‍👩‍👧 family 🎉",
+    "Title 𝄞 \"Score\" 🎵 notes",
+];
+
+/// Build a DOCX archive with one paragraph per entry in `paragraphs`
+pub fn docx(paragraphs: &[&str]) -> Vec<u8> {
+    let body: String = paragraphs
+        .iter()
+        .map(|text| {
+            format!(
+                "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+                escape_xml(text)
+            )
+        })
+        .collect();
+
+    docx_from_body_xml(&body)
+}
+
+/// Like [`docx`], with a header part and a footer part holding one paragraph
+/// each
+pub fn docx_with_header_footer(paragraphs: &[&str], header: &str, footer: &str) -> Vec<u8> {
+    let paragraph = |text: &str| {
+        format!(
+            "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+            escape_xml(text)
+        )
+    };
+    let body: String = paragraphs.iter().map(|text| paragraph(text)).collect();
+    let document = format!(
+        "<w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}</w:body></w:document>",
+        W_NAMESPACE, R_NAMESPACE, body
+    );
+    let document_rels = format!(
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+         <Relationship Id=\"rId1\" Type=\"{0}/header\" Target=\"header1.xml\"/>\
+         <Relationship Id=\"rId2\" Type=\"{0}/footer\" Target=\"/word/footer1.xml\"/>\
+         </Relationships>",
+        R_NAMESPACE
+    );
+    let header = format!(
+        "<w:hdr xmlns:w=\"{}\">{}</w:hdr>",
+        W_NAMESPACE,
+        paragraph(header)
+    );
+    let footer = format!(
+        "<w:ftr xmlns:w=\"{}\">{}</w:ftr>",
+        W_NAMESPACE,
+        paragraph(footer)
+    );
+
+    zip_archive(&[
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("word/document.xml", &document),
+        ("word/_rels/document.xml.rels", &document_rels),
+        ("word/header1.xml", &header),
+        ("word/footer1.xml", &footer),
+    ])
+}
+
+/// Like [`docx`], with a footnotes part and an endnotes part holding one
+/// note per entry, numbered from 1 and referenced from a final paragraph,
+/// after the separator notes Word writes
+pub fn docx_with_notes(paragraphs: &[&str], footnotes: &[&str], endnotes: &[&str]) -> Vec<u8> {
+    let paragraph = |text: &str| {
+        format!(
+            "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+            escape_xml(text)
+        )
+    };
+    let mut body: String = paragraphs.iter().map(|text| paragraph(text)).collect();
+    body.push_str("<w:p>");
+    for (kind, notes) in [("footnote", footnotes), ("endnote", endnotes)] {
+        for id in 1..=notes.len() {
+            body.push_str(&format!(
+                "<w:r><w:{0}Reference w:id=\"{1}\"/></w:r>",
+                kind, id
+            ));
+        }
+    }
+    body.push_str("</w:p>");
+    let document = format!(
+        "<w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}</w:body></w:document>",
+        W_NAMESPACE, R_NAMESPACE, body
+    );
+    let document_rels = format!(
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+         <Relationship Id=\"rId1\" Type=\"{0}/footnotes\" Target=\"footnotes.xml\"/>\
+         <Relationship Id=\"rId2\" Type=\"{0}/endnotes\" Target=\"endnotes.xml\"/>\
+         </Relationships>",
+        R_NAMESPACE
+    );
+    let notes = |kind: &str, notes: &[&str]| {
+        let mut xml = format!(
+            "<w:{0}s xmlns:w=\"{1}\">\
+             <w:{0} w:type=\"separator\" w:id=\"-1\"><w:p><w:r><w:separator/></w:r></w:p></w:{0}>\
+             <w:{0} w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:{0}>",
+            kind, W_NAMESPACE
+        );
+        for (id, text) in notes.iter().enumerate() {
+            xml.push_str(&format!(
+                "<w:{0} w:id=\"{1}\"><w:p><w:r><w:{0}Ref/></w:r><w:r><w:t xml:space=\"preserve\"> {2}</w:t></w:r></w:p></w:{0}>",
+                kind,
+                id + 1,
+                escape_xml(text)
+            ));
+        }
+        xml.push_str(&format!("</w:{}s>", kind));
+        xml
+    };
+
+    zip_archive(&[
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("word/document.xml", &document),
+        ("word/_rels/document.xml.rels", &document_rels),
+        ("word/footnotes.xml", &notes("footnote", footnotes)),
+        ("word/endnotes.xml", &notes("endnote", endnotes)),
+    ])
+}
+
+/// Like [`docx`], with a comments part holding one comment per
+/// `(author, text)` entry, numbered from 0 and anchored on the first
+/// paragraph
+pub fn docx_with_comments(paragraphs: &[&str], comments: &[(&str, &str)]) -> Vec<u8> {
+    let paragraph = |text: &str| {
+        format!(
+            "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+            escape_xml(text)
+        )
+    };
+    let mut body: String = paragraphs.iter().map(|text| paragraph(text)).collect();
+    let anchors: String = (0..comments.len())
+        .map(|id| format!("<w:commentRangeStart w:id=\"{0}\"/><w:commentRangeEnd w:id=\"{0}\"/><w:r><w:commentReference w:id=\"{0}\"/></w:r>", id))
+        .collect();
+    body.insert_str(body.find("</w:p>").unwrap_or(body.len()), &anchors);
+    let document = format!(
+        "<w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}</w:body></w:document>",
+        W_NAMESPACE, R_NAMESPACE, body
+    );
+    let document_rels = format!(
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+         <Relationship Id=\"rId1\" Type=\"{}/comments\" Target=\"comments.xml\"/>\
+         </Relationships>",
+        R_NAMESPACE
+    );
+    let mut xml = format!("<w:comments xmlns:w=\"{}\">", W_NAMESPACE);
+    for (id, (author, text)) in comments.iter().enumerate() {
+        xml.push_str(&format!(
+            "<w:comment w:id=\"{}\" w:author=\"{}\" w:date=\"2024-05-12T09:30:00Z\" w:initials=\"\">{}</w:comment>",
+            id,
+            escape_xml(author),
+            paragraph(text)
+        ));
+    }
+    xml.push_str("</w:comments>");
+
+    zip_archive(&[
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("word/document.xml", &document),
+        ("word/_rels/document.xml.rels", &document_rels),
+        ("word/comments.xml", &xml),
+    ])
+}
+
+/// Like [`docx`], with a `docProps/core.xml` part setting each
+/// `(element, value)` property, such as `("dc:title", "Q3 review")`
+pub fn docx_with_properties(paragraphs: &[&str], properties: &[(&str, &str)]) -> Vec<u8> {
+    let body: String = paragraphs
+        .iter()
+        .map(|text| {
+            format!(
+                "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+                escape_xml(text)
+            )
+        })
+        .collect();
+    let document = format!(
+        "<w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}</w:body></w:document>",
+        W_NAMESPACE, R_NAMESPACE, body
+    );
+    let root_rels = ROOT_RELS.replace(
+        "</Relationships>",
+        "<Relationship Id=\"rId2\" \
+         Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" \
+         Target=\"docProps/core.xml\"/></Relationships>",
+    );
+    let fields: String = properties
+        .iter()
+        .map(|(element, value)| format!("<{0}>{1}</{0}>", element, escape_xml(value)))
+        .collect();
+    let core = format!(
+        "<cp:coreProperties \
+         xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">{}</cp:coreProperties>",
+        fields
+    );
+
+    zip_archive(&[
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", &root_rels),
+        ("word/document.xml", &document),
+        ("docProps/core.xml", &core),
+    ])
+}
+
+/// Like [`docx`], followed by a table with one row per entry in `rows` and
+/// one paragraph per cell, laid out with the table, row and cell properties
+/// Word writes
+pub fn docx_with_table(paragraphs: &[&str], rows: &[&[&str]]) -> Vec<u8> {
+    let paragraph = |text: &str| {
+        format!(
+            "<w:p><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+            escape_xml(text)
+        )
+    };
+    let mut body: String = paragraphs.iter().map(|text| paragraph(text)).collect();
+    body.push_str("<w:tbl><w:tblPr><w:tblStyle w:val=\"TableGrid\"/></w:tblPr><w:tblGrid/>");
+    for row in rows {
+        body.push_str("<w:tr><w:trPr/>");
+        for cell in row.iter() {
+            body.push_str(&format!(
+                "<w:tc><w:tcPr><w:tcW w:w=\"0\" w:type=\"auto\"/></w:tcPr>{}</w:tc>",
+                paragraph(cell)
+            ));
+        }
+        body.push_str("</w:tr>");
+    }
+    body.push_str("</w:tbl>");
+
+    docx_from_body_xml(&body)
+}
+
+/// Build a DOCX archive whose `<w:body>` contains the given raw XML
+pub fn docx_from_body_xml(body: &str) -> Vec<u8> {
+    docx_with_hyperlinks(body, &[])
+}
+
+/// Like [`docx_from_body_xml`], with external hyperlink relationships given
+/// as `(id, target)` pairs for `<w:hyperlink r:id="…">` elements in `body`
+pub fn docx_with_hyperlinks(body: &str, links: &[(&str, &str)]) -> Vec<u8> {
+    let document = format!(
+        "<w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}</w:body></w:document>",
+        W_NAMESPACE, R_NAMESPACE, body
+    );
+    let relationships: String = links
+        .iter()
+        .map(|(id, target)| {
+            format!(
+                "<Relationship Id=\"{}\" Type=\"{}/hyperlink\" Target=\"{}\" TargetMode=\"External\"/>",
+                id,
+                R_NAMESPACE,
+                escape_xml(target)
+            )
+        })
+        .collect();
+    let document_rels = format!(
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">{}</Relationships>",
+        relationships
+    );
+
+    zip_archive(&[
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("word/document.xml", &document),
+        ("word/_rels/document.xml.rels", &document_rels),
+    ])
+}
+
+/// Build an XLSX workbook with one worksheet per `(name, rows)` entry,
+/// one cell per string from column A. Cells that parse as numbers are
+/// written as numbers, other non-empty cells as shared strings.
+pub fn xlsx(sheets: &[(&str, &[&[&str]])]) -> Vec<u8> {
+    let mut shared: Vec<&str> = Vec::new();
+    let mut parts: Vec<(String, String)> = Vec::new();
+    let mut workbook_sheets = String::new();
+    let mut workbook_rels = String::new();
+    for (i, (name, rows)) in sheets.iter().enumerate() {
+        let mut data = String::new();
+        for (r, row) in rows.iter().enumerate() {
+            data.push_str(&format!("<row r=\"{}\">", r + 1));
+            for (c, value) in row
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| !value.is_empty())
+            {
+                let reference = format!("{}{}", (b'A' + c as u8) as char, r + 1);
+                if value.parse::<f64>().is_ok() {
+                    data.push_str(&format!("<c r=\"{}\"><v>{}</v></c>", reference, value));
+                } else {
+                    shared.push(value);
+                    data.push_str(&format!(
+                        "<c r=\"{}\" t=\"s\"><v>{}</v></c>",
+                        reference,
+                        shared.len() - 1
+                    ));
+                }
+            }
+            data.push_str("</row>");
+        }
+        parts.push((
+            format!("xl/worksheets/sheet{}.xml", i + 1),
+            format!(
+                "<worksheet xmlns=\"{}\"><sheetData>{}</sheetData></worksheet>",
+                SPREADSHEET_NAMESPACE, data
+            ),
+        ));
+        workbook_sheets.push_str(&format!(
+            "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+            escape_xml(name),
+            i + 1,
+            i + 1
+        ));
+        workbook_rels.push_str(&format!(
+            "<Relationship Id=\"rId{}\" Type=\"{}/worksheet\" Target=\"worksheets/sheet{}.xml\"/>",
+            i + 1,
+            R_NAMESPACE,
+            i + 1
+        ));
+    }
+    workbook_rels.push_str(&format!(
+        "<Relationship Id=\"rIdStrings\" Type=\"{}/sharedStrings\" Target=\"sharedStrings.xml\"/>",
+        R_NAMESPACE
+    ));
+
+    let workbook = format!(
+        "<workbook xmlns=\"{}\" xmlns:r=\"{}\"><sheets>{}</sheets></workbook>",
+        SPREADSHEET_NAMESPACE, R_NAMESPACE, workbook_sheets
+    );
+    let workbook_rels = format!(
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">{}</Relationships>",
+        workbook_rels
+    );
+    let strings: String = shared
+        .iter()
+        .map(|text| {
+            format!(
+                "<si><t xml:space=\"preserve\">{}</t></si>",
+                escape_xml(text)
+            )
+        })
+        .collect();
+    let strings = format!(
+        "<sst xmlns=\"{}\" count=\"{}\">{}</sst>",
+        SPREADSHEET_NAMESPACE,
+        shared.len(),
+        strings
+    );
+    let root_rels = ROOT_RELS.replace("word/document.xml", "xl/workbook.xml");
+
+    let mut entries: Vec<(&str, &str)> = vec![
+        ("[Content_Types].xml", XLSX_CONTENT_TYPES),
+        ("_rels/.rels", &root_rels),
+        ("xl/workbook.xml", &workbook),
+        ("xl/_rels/workbook.xml.rels", &workbook_rels),
+        ("xl/sharedStrings.xml", &strings),
+    ];
+    entries.extend(
+        parts
+            .iter()
+            .map(|(name, xml)| (name.as_str(), xml.as_str())),
+    );
+    zip_archive(&entries)
+}
+
+/// Build an ODT document with one `text:p` paragraph per string
+pub fn odt(paragraphs: &[&str]) -> Vec<u8> {
+    let body: String = paragraphs
+        .iter()
+        .map(|text| format!("<text:p>{}</text:p>", escape_xml(text)))
+        .collect();
+    odt_from_xml(&body, "", &[])
+}
+
+/// Build an ODT document from the XML inside its `office:text` element and
+/// inside the `style:master-page` of its styles, with a `meta.xml` part
+/// setting each `(element, value)` property, such as `("dc:title", "Q3
+/// review")`
+pub fn odt_from_xml(body: &str, master_page: &str, properties: &[(&str, &str)]) -> Vec<u8> {
+    let content = format!(
+        "<office:document-content {}><office:body><office:text>{}</office:text></office:body></office:document-content>",
+        ODF_NAMESPACES, body
+    );
+    let styles = format!(
+        "<office:document-styles {}><office:master-styles>\
+         <style:master-page style:name=\"Standard\">{}</style:master-page>\
+         </office:master-styles></office:document-styles>",
+        ODF_NAMESPACES, master_page
+    );
+    let fields: String = properties
+        .iter()
+        .map(|(element, value)| format!("<{0}>{1}</{0}>", element, escape_xml(value)))
+        .collect();
+    let meta = format!(
+        "<office:document-meta {}><office:meta>{}</office:meta></office:document-meta>",
+        ODF_NAMESPACES, fields
+    );
+
+    zip_archive(&[
+        ("mimetype", "application/vnd.oasis.opendocument.text"),
+        ("META-INF/manifest.xml", ODT_MANIFEST),
+        ("content.xml", &content),
+        ("styles.xml", &styles),
+        ("meta.xml", &meta),
+    ])
+}
+
+/// Build an EPUB ebook with one XHTML chapter per entry in `chapters`, one
+/// `<p>` per string, named `text/chapter1.xhtml` on and in that order in its
+/// spine
+pub fn epub(chapters: &[&[&str]]) -> Vec<u8> {
+    let names: Vec<String> = (1..=chapters.len())
+        .map(|n| format!("text/chapter{}.xhtml", n))
+        .collect();
+    let manifest: String = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            format!(
+                "<item id=\"c{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>",
+                i + 1,
+                name
+            )
+        })
+        .collect();
+    let spine: String = (1..=chapters.len())
+        .map(|n| format!("<itemref idref=\"c{}\"/>", n))
+        .collect();
+    let contents: Vec<String> = chapters
+        .iter()
+        .map(|paragraphs| {
+            let body: String = paragraphs.iter().map(|text| format!("<p>{}</p>", escape_xml(text))).collect();
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                 <html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>Chapter</title></head><body>{}</body></html>",
+                body
+            )
+        })
+        .collect();
+    let files: Vec<(&str, &str)> = names
+        .iter()
+        .map(String::as_str)
+        .zip(contents.iter().map(String::as_str))
+        .collect();
+    epub_from_parts("<dc:title>Untitled</dc:title>", &manifest, &spine, &files)
+}
+
+/// Build an EPUB ebook from the XML inside the `<metadata>`, `<manifest>`
+/// and `<spine>` of its package document `OEBPS/content.opf`, with each
+/// `(path, contents)` file under `OEBPS/`
+pub fn epub_from_parts(
+    metadata: &str,
+    manifest: &str,
+    spine: &str,
+    files: &[(&str, &str)],
+) -> Vec<u8> {
+    let container = "<?xml version=\"1.0\"?>\
+        <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\"><rootfiles>\
+        <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\
+        </rootfiles></container>";
+    let package = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">{}</metadata>\
+         <manifest>{}</manifest><spine>{}</spine></package>",
+        metadata, manifest, spine
+    );
+    let paths: Vec<String> = files
+        .iter()
+        .map(|(path, _)| format!("OEBPS/{}", path))
+        .collect();
+
+    let mut entries = vec![
+        ("mimetype", "application/epub+zip"),
+        ("META-INF/container.xml", container),
+        ("OEBPS/content.opf", package.as_str()),
+    ];
+    entries.extend(
+        paths
+            .iter()
+            .map(String::as_str)
+            .zip(files.iter().map(|(_, contents)| *contents)),
+    );
+    zip_archive(&entries)
+}
+
+/// Build an RTF document with one paragraph per string, as WordPad writes
+/// it: characters outside ASCII become `\uN?` escapes
+pub fn rtf(paragraphs: &[&str]) -> Vec<u8> {
+    let mut rtf =
+        String::from("{\\rtf1\\ansi\\ansicpg1252\\deff0{\\fonttbl{\\f0\\fnil Calibri;}}\\uc1\n");
+    for paragraph in paragraphs {
+        rtf.push_str("\\pard\\f0 ");
+        for c in paragraph.chars() {
+            match c {
+                '\\' | '{' | '}' => rtf.extend(['\\', c]),
+                ' '..='~' => rtf.push(c),
+                _ => {
+                    let mut units = [0; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        rtf.push_str(&format!("\\u{}?", *unit as i16));
+                    }
+                }
+            }
+        }
+        rtf.push_str("\\par\n");
+    }
+    rtf.push('}');
+    rtf.into_bytes()
+}
+
+/// Build a PDF with one page per entry in `pages`, one text line per string
+pub fn pdf(pages: &[&[&str]]) -> Vec<u8> {
+    pdf_with_info(pages, &[])
+}
+
+/// Like [`pdf`], with a document information dictionary of `(key, value)`
+/// pairs, such as `("Title", "Quarterly review")`
+pub fn pdf_with_info(pages: &[&[&str]], info: &[(&str, &str)]) -> Vec<u8> {
+    // Objects 1 and 2 are the catalog and page tree, 3 is the shared font,
+    // and every page contributes a page object followed by its content stream.
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 4 + i * 2).collect();
+    let kids = page_ids
+        .iter()
+        .map(|id| format!("{} 0 R", id))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, pages.len()),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+
+    for (page, id) in pages.iter().zip(&page_ids) {
+        let mut content = String::from("BT /F1 12 Tf 72 720 Td");
+        for (i, line) in page.iter().enumerate() {
+            if i > 0 {
+                content.push_str(" 0 -24 Td");
+            }
+            content.push_str(&format!(" ({}) Tj", escape_pdf_string(line)));
+        }
+        content.push_str(" ET");
+
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+             /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            id + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}\nendstream",
+            content.len(),
+            content
+        ));
+    }
+
+    let info_ref = if info.is_empty() {
+        String::new()
+    } else {
+        let entries: Vec<String> = info
+            .iter()
+            .map(|(key, value)| format!("/{} ({})", key, escape_pdf_string(value)))
+            .collect();
+        objects.push(format!("<< {} >>", entries.join(" ")));
+        format!(" /Info {} 0 R", objects.len())
+    };
+
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+
+    let xref_offset = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n", objects.len() + 1).as_bytes());
+    out.extend_from_slice(b"0000000000 65535 f \n");
+    for offset in offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R{} >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            info_ref,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+
+    out
+}
+
+/// Build a ZIP archive from `(name, contents)` pairs
+pub fn zip_archive(entries: &[(&str, &str)]) -> Vec<u8> {
+    let entries: Vec<(&str, &[u8])> = entries
+        .iter()
+        .map(|(name, contents)| (*name, contents.as_bytes()))
+        .collect();
+    zip_of(&entries)
+}
+
+/// Build a ZIP archive from `(name, bytes)` pairs, such as documents
+pub fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    // A fixed timestamp, so the same entries always give the same bytes
+    let options = FileOptions::default().last_modified_time(zip::DateTime::default());
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        // ODF and EPUB readers expect their `mimetype` entry uncompressed
+        let options = match *name {
+            "mimetype" => options.compression_method(zip::CompressionMethod::Stored),
+            _ => options,
+        };
+        writer
+            .start_file(*name, options)
+            .expect("in-memory zip entry");
+        writer.write_all(contents).expect("in-memory zip write");
+    }
+
+    writer.finish().expect("in-memory zip finish").into_inner()
+}
+
+/// Gzip `bytes`, recording `name` as the original file name when given
+pub fn gzip(name: Option<&str>, bytes: &[u8]) -> Vec<u8> {
+    let mut builder = GzBuilder::new();
+    if let Some(name) = name {
+        builder = builder.filename(name);
+    }
+    let mut encoder = builder.write(Vec::new(), Compression::default());
+    encoder.write_all(bytes).expect("in-memory gzip write");
+    encoder.finish().expect("in-memory gzip finish")
+}
+
+/// Bytes that look like a legacy `.doc` (OLE2 compound file) but hold
+/// nothing else, for testing detection without the `doc` feature
+pub fn ole2_stub() -> Vec<u8> {
+    let mut bytes = crate::parsers::doc::OLE2_MAGIC.to_vec();
+    bytes.resize(512, 0);
+    bytes
+}
+
+/// Build a minimal Word 97-2003 `.doc` with one piece per paragraph.
+///
+/// Paragraphs that fit in Latin-1 are stored as 8-bit text and the rest as
+/// UTF-16, so both piece encodings are exercised.
+#[cfg(feature = "doc")]
+pub fn legacy_doc(paragraphs: &[&str]) -> Vec<u8> {
+    legacy_doc_with_flags(paragraphs, 0)
+}
+
+/// Like [`legacy_doc`], with extra FIB flags set (e.g. `0x0004` for fast-saved)
+#[cfg(feature = "doc")]
+pub fn legacy_doc_with_flags(paragraphs: &[&str], extra_flags: u16) -> Vec<u8> {
+    const TEXT_START: usize = 0x400;
+
+    let mut word = vec![0u8; TEXT_START];
+    let mut cps = vec![0u32];
+    let mut pcds = Vec::new();
+
+    for paragraph in paragraphs {
+        let text = format!("{}\r", paragraph);
+        let offset = word.len() as u32;
+        if text
+            .chars()
+            .all(|c| (c as u32) < 0x80 || (0xA0..=0xFF).contains(&(c as u32)))
+        {
+            word.extend(text.chars().map(|c| c as u8));
+            pcds.push((offset * 2) | 0x4000_0000);
+        } else {
+            word.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            pcds.push(offset);
+        }
+        cps.push(cps.last().unwrap() + text.encode_utf16().count() as u32);
+    }
+
+    let mut plc: Vec<u8> = cps.iter().flat_map(|cp| cp.to_le_bytes()).collect();
+    for fc in &pcds {
+        plc.extend([0, 0]);
+        plc.extend(fc.to_le_bytes());
+        plc.extend([0, 0]);
+    }
+    let mut table = vec![0x02];
+    table.extend((plc.len() as u32).to_le_bytes());
+    table.extend(plc);
+
+    let mut put = |pos: usize, bytes: &[u8]| word[pos..pos + bytes.len()].copy_from_slice(bytes);
+    put(0x00, &0xA5ECu16.to_le_bytes());
+    put(0x02, &0x00C1u16.to_le_bytes());
+    put(0x0A, &(0x0200 | extra_flags).to_le_bytes());
+    put(0x20, &14u16.to_le_bytes());
+    put(0x3E, &22u16.to_le_bytes());
+    put(0x4C, cps.last().unwrap().to_le_bytes().as_slice());
+    put(0x98, &0x5Du16.to_le_bytes());
+    put(0x1A2, &0u32.to_le_bytes());
+    put(0x1A6, &(table.len() as u32).to_le_bytes());
+
+    let mut file = cfb::CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    file.create_stream("/WordDocument")
+        .unwrap()
+        .write_all(&word)
+        .unwrap();
+    file.create_stream("/1Table")
+        .unwrap()
+        .write_all(&table)
+        .unwrap();
+    file.flush().unwrap();
+    file.into_inner().into_inner()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escape a PDF literal string, encoding non-ASCII characters as WinAnsi octal
+fn escape_pdf_string(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{}", c),
+            c if c.is_ascii() => c.to_string(),
+            c if (c as u32) <= 0xFF => format!("\\{:03o}", c as u32),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+const CONTENT_TYPES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+<Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+<Override PartName=\"/word/document.xml\" \
+ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>\
+</Types>";
+
+const XLSX_CONTENT_TYPES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+<Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+<Override PartName=\"/xl/workbook.xml\" \
+ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+</Types>";
+
+const ODF_NAMESPACES: &str = "\
+xmlns:office=\"urn:oasis:names:tc:opendocument:xmlns:office:1.0\" \
+xmlns:style=\"urn:oasis:names:tc:opendocument:xmlns:style:1.0\" \
+xmlns:text=\"urn:oasis:names:tc:opendocument:xmlns:text:1.0\" \
+xmlns:table=\"urn:oasis:names:tc:opendocument:xmlns:table:1.0\" \
+xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+xmlns:meta=\"urn:oasis:names:tc:opendocument:xmlns:meta:1.0\" \
+office:version=\"1.3\"";
+
+const ODT_MANIFEST: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+<manifest:manifest xmlns:manifest=\"urn:oasis:names:tc:opendocument:xmlns:manifest:1.0\" manifest:version=\"1.3\">\
+<manifest:file-entry manifest:full-path=\"/\" manifest:media-type=\"application/vnd.oasis.opendocument.text\"/>\
+<manifest:file-entry manifest:full-path=\"content.xml\" manifest:media-type=\"text/xml\"/>\
+<manifest:file-entry manifest:full-path=\"styles.xml\" manifest:media-type=\"text/xml\"/>\
+<manifest:file-entry manifest:full-path=\"meta.xml\" manifest:media-type=\"text/xml\"/>\
+</manifest:manifest>";
+
+const ROOT_RELS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+<Relationship Id=\"rId1\" \
+Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" \
+Target=\"word/document.xml\"/>\
+</Relationships>";
@@ -0,0 +1,579 @@
+//! The self-contained HTML report printed by `--format html-full`.
+//!
+//! One page covers the whole run, for reading offline: an index of every
+//! document with matches, then a section per document with its details,
+//! an outline of its headings or pages, and every occurrence shown in the
+//! blocks of extracted text around it. Index and outline entries link to
+//! the excerpts. The CSS is inlined and a footer records how the report was
+//! made.
+//!
+//! Excerpts are bounded by [`ExcerptLimits`]: each excerpt, the excerpts of
+//! one document, and all the excerpts in the report have a character cap.
+//! Occurrences past a cap are counted in a notice instead of shown.
+
+use std::fmt::Write;
+
+use crate::{
+    search::{BlockLocation, TextBlock},
+    triage::{Location, MatchRecord},
+    utils::escape_html,
+};
+
+/// Blocks shown on each side of the block an occurrence was found in
+pub const CONTEXT_BLOCKS: usize = 2;
+
+/// Caps on the excerpt text in a report, in characters
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::Args)]
+pub struct ExcerptLimits {
+    /// With --format html-full: characters of text shown around each occurrence
+    #[arg(long, value_name = "N", default_value_t = 600)]
+    pub max_excerpt_chars: usize,
+
+    /// With --format html-full: characters of excerpts shown per document
+    #[arg(long, value_name = "N", default_value_t = 60_000)]
+    pub max_document_chars: usize,
+
+    /// With --format html-full: characters of excerpts in the whole report
+    #[arg(long, value_name = "N", default_value_t = 2_000_000)]
+    pub max_report_chars: usize,
+}
+
+impl Default for ExcerptLimits {
+    fn default() -> Self {
+        Self {
+            max_excerpt_chars: 600,
+            max_document_chars: 60_000,
+            max_report_chars: 2_000_000,
+        }
+    }
+}
+
+/// A document as the report describes it
+pub struct Document<'a> {
+    /// The file on disk
+    pub path: &'a str,
+    /// Name and value of each detail shown about the document
+    pub details: Vec<(&'static str, String)>,
+    /// The blocks the document's matches were found in, when they could be
+    /// extracted again; matches inside archive entries only show their line
+    pub blocks: Option<&'a [TextBlock]>,
+    pub matches: &'a [MatchRecord],
+}
+
+/// A report being built a document at a time
+pub struct FullReport {
+    limits: ExcerptLimits,
+    index: String,
+    sections: String,
+    documents: usize,
+    /// Excerpt characters written so far
+    spent: usize,
+    /// Occurrences left out once `max_report_chars` was spent
+    omitted: usize,
+}
+
+/// One line of an outline: a heading or a page, and where its first excerpt is
+struct OutlineEntry {
+    label: String,
+    depth: usize,
+    occurrences: usize,
+    anchor: Option<String>,
+}
+
+impl FullReport {
+    pub fn new(limits: ExcerptLimits) -> Self {
+        FullReport {
+            limits,
+            index: String::new(),
+            sections: String::new(),
+            documents: 0,
+            spent: 0,
+            omitted: 0,
+        }
+    }
+
+    /// Add a document with matches
+    pub fn add_document(&mut self, document: &Document) {
+        let id = format!("doc-{}", self.documents);
+        self.documents += 1;
+        let occurrences: usize = document
+            .matches
+            .iter()
+            .map(|record| record.occurrences.len())
+            .sum();
+
+        // Excerpts first: the index and the outline link to the ones shown
+        let mut excerpts = String::new();
+        let mut anchors: Vec<Vec<Option<String>>> = Vec::new();
+        let mut document_spent = 0;
+        let mut capped = 0;
+        for record in document.matches {
+            let blocks = document
+                .blocks
+                .filter(|_| record.source.entries().is_empty());
+            let mut record_anchors = Vec::new();
+            for (n, location) in record.occurrences.iter().enumerate() {
+                let (html, chars) = excerpt(location, blocks, self.limits.max_excerpt_chars);
+                if self.spent + chars > self.limits.max_report_chars {
+                    self.omitted += 1;
+                    record_anchors.push(None);
+                    continue;
+                }
+                if document_spent + chars > self.limits.max_document_chars {
+                    capped += 1;
+                    record_anchors.push(None);
+                    continue;
+                }
+                self.spent += chars;
+                document_spent += chars;
+
+                let anchor = format!("{}-{}", record.match_id, n);
+                let _ = write!(
+                    excerpts,
+                    "<div class=\"excerpt\" id=\"{}\"><p class=\"where\"><b>{}</b> ({}) · {}{}</p>\n{}</div>\n",
+                    anchor,
+                    escape_html(&record.term),
+                    escape_html(&record.metadata),
+                    escape_html(&location_label(location, blocks)),
+                    entries_label(record),
+                    html
+                );
+                record_anchors.push(Some(anchor));
+            }
+            anchors.push(record_anchors);
+        }
+        if capped > 0 {
+            let _ = writeln!(
+                excerpts,
+                "<p class=\"notice\">{} more occurrences in this document are not shown (--max-document-chars {}).</p>",
+                capped, self.limits.max_document_chars
+            );
+        }
+
+        let _ = write!(
+            self.index,
+            "<li><a href=\"#{}\">{}</a> — {} matches, {} occurrences\n<ul>\n",
+            id,
+            escape_html(document.path),
+            document.matches.len(),
+            occurrences
+        );
+        for (record, record_anchors) in document.matches.iter().zip(&anchors) {
+            let label = format!(
+                "{} ({})",
+                escape_html(&record.term),
+                escape_html(&record.metadata)
+            );
+            let label = match record_anchors.iter().flatten().next() {
+                Some(anchor) => format!("<a href=\"#{}\">{}</a>", anchor, label),
+                None => format!("{} <i>(not shown)</i>", label),
+            };
+            let _ = writeln!(
+                self.index,
+                "<li>{}{} ×{}</li>",
+                label,
+                entries_label(record),
+                record.occurrences.len()
+            );
+        }
+        self.index.push_str("</ul></li>\n");
+
+        let _ = write!(
+            self.sections,
+            "<section id=\"{}\">\n<h2>{}</h2>\n<table class=\"details\">\n",
+            id,
+            escape_html(document.path)
+        );
+        for (name, value) in &document.details {
+            let _ = writeln!(
+                self.sections,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                name,
+                escape_html(value)
+            );
+        }
+        self.sections.push_str("</table>\n");
+        if let Some(blocks) = document.blocks {
+            let outline = outline(blocks, document.matches, &anchors);
+            if !outline.is_empty() {
+                self.sections
+                    .push_str("<h3>Outline</h3>\n<ul class=\"outline\">\n");
+                for entry in outline {
+                    let label = escape_html(&entry.label);
+                    let label = match &entry.anchor {
+                        Some(anchor) => format!("<a href=\"#{}\">{}</a>", anchor, label),
+                        None => label,
+                    };
+                    let _ = writeln!(
+                        self.sections,
+                        "<li class=\"depth-{}\">{} ({})</li>",
+                        entry.depth.min(6),
+                        label,
+                        entry.occurrences
+                    );
+                }
+                self.sections.push_str("</ul>\n");
+            }
+        }
+        let _ = write!(self.sections, "<h3>Matches</h3>\n{}</section>\n", excerpts);
+    }
+
+    /// The finished page; `invocation` names and values how the run was made
+    pub fn finish(self, title: &str, invocation: &[(&str, String)]) -> String {
+        let truncated = match self.omitted {
+            0 => String::new(),
+            omitted => format!(
+                "<p class=\"notice\">Report truncated: {} occurrences are not shown after {} characters of excerpts (--max-report-chars {}).</p>\n",
+                omitted, self.spent, self.limits.max_report_chars
+            ),
+        };
+        let footer: String = invocation
+            .iter()
+            .map(|(name, value)| format!("<dt>{}</dt><dd>{}</dd>\n", name, escape_html(value)))
+            .collect();
+
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\n<style>\n{css}</style></head><body>\n\
+             <h1>{title}</h1>\n{truncated}<nav>\n<h2>Documents</h2>\n<ol>\n{index}</ol>\n</nav>\n{sections}{truncated}\
+             <footer>\n<dl>\n{footer}</dl>\n</footer>\n</body></html>\n",
+            title = escape_html(title),
+            css = CSS,
+            truncated = truncated,
+            index = self.index,
+            sections = self.sections,
+            footer = footer,
+        )
+    }
+}
+
+const CSS: &str = "\
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; margin-top: 2em; }
+table.details th { text-align: left; padding-right: 1em; }
+ul.outline li { list-style: none; }
+ul.outline .depth-2 { margin-left: 1.5em; }
+ul.outline .depth-3 { margin-left: 3em; }
+ul.outline .depth-4, ul.outline .depth-5, ul.outline .depth-6 { margin-left: 4.5em; }
+.excerpt { border-left: 3px solid #8ab; margin: 1em 0; padding-left: 1em; }
+.excerpt pre { white-space: pre-wrap; margin: 0; }
+.excerpt .hit { background: #f4f4f4; }
+.excerpt .concealed { color: #777; font-style: italic; }
+mark { background: #ffe066; }
+.notice { color: #a33; font-weight: bold; }
+footer { border-top: 1px solid #ccc; margin-top: 3em; font-size: smaller; color: #555; }
+footer dt { font-weight: bold; }
+";
+
+/// ` in a › b` for a match inside archive entries, empty otherwise
+fn entries_label(record: &MatchRecord) -> String {
+    match record.source.entries() {
+        [] => String::new(),
+        entries => format!(" in {}", escape_html(&entries.join(" › "))),
+    }
+}
+
+/// Where `location` is, in words
+fn location_label(location: &Location, blocks: Option<&[TextBlock]>) -> String {
+    match matching_block(location, blocks) {
+        Some(block) => block.location.to_string(),
+        None => match location.page {
+            Some(page) => format!("page {}, line {}", page, location.line + 1),
+            None => format!("line {}", location.line + 1),
+        },
+    }
+}
+
+/// The block `location` was found in, when the blocks extracted again are
+/// the ones searched
+fn matching_block<'b>(
+    location: &Location,
+    blocks: Option<&'b [TextBlock]>,
+) -> Option<&'b TextBlock> {
+    blocks?
+        .get(location.line)
+        .filter(|block| block.text == location.text)
+}
+
+/// The excerpt around `location` as HTML, and the characters of text in it:
+/// the matching line cut around the match, then the nearest blocks on
+/// either side while `max_chars` lasts
+fn excerpt(location: &Location, blocks: Option<&[TextBlock]>, max_chars: usize) -> (String, usize) {
+    let [start, end] = location.span;
+    let text = &location.text;
+    let (before, hit, after) = (&text[..start], &text[start..end], &text[end..]);
+
+    // The match is always shown whole; the rest of its line shares what is left
+    let mut budget = max_chars.saturating_sub(hit.chars().count());
+    let keep_after = (budget / 2).max(budget.saturating_sub(before.chars().count()));
+    let after = head(after, keep_after.min(budget));
+    budget -= after.chars().count().min(budget);
+    let before = tail(before, budget);
+    budget -= before.chars().count().min(budget);
+    let mut chars = max_chars - budget;
+
+    let mut lines = vec![format!(
+        "<span class=\"hit\">{}{}<mark>{}</mark>{}{}</span>",
+        if before.len() < start { "…" } else { "" },
+        escape_html(before),
+        escape_html(hit),
+        escape_html(after),
+        if after.len() < text.len() - end {
+            "…"
+        } else {
+            ""
+        },
+    )];
+
+    if let (Some(blocks), true) = (blocks, matching_block(location, blocks).is_some()) {
+        let line = location.line;
+        let mut shown_before = Vec::new();
+        let mut shown_after = Vec::new();
+        for distance in 1..=CONTEXT_BLOCKS {
+            let nearest = [line.checked_sub(distance), Some(line + distance)];
+            for (index, is_before) in nearest.into_iter().zip([true, false]) {
+                let Some(block) = index.and_then(|index| blocks.get(index)) else {
+                    continue;
+                };
+                if budget == 0 || block.text.trim().is_empty() {
+                    continue;
+                }
+                let shown = if is_before {
+                    tail(&block.text, budget)
+                } else {
+                    head(&block.text, budget)
+                };
+                budget -= shown.chars().count();
+                chars += shown.chars().count();
+                let cut = shown.len() < block.text.len();
+                let html = match (is_before, cut) {
+                    (true, true) => format!("…{}", escape_html(shown)),
+                    (false, true) => format!("{}…", escape_html(shown)),
+                    _ => escape_html(shown),
+                };
+                let html = match block.concealed {
+                    Some(kind) => format!(
+                        "<span class=\"concealed\">[{}] {}</span>",
+                        kind.as_str(),
+                        html
+                    ),
+                    None => html,
+                };
+                if is_before {
+                    shown_before.push(html);
+                } else {
+                    shown_after.push(html);
+                }
+            }
+        }
+        shown_before.reverse();
+        lines = shown_before
+            .into_iter()
+            .chain(lines)
+            .chain(shown_after)
+            .collect();
+    }
+
+    (format!("<pre>{}</pre>", lines.join("\n")), chars)
+}
+
+/// The first `chars` characters of `text`
+fn head(text: &str, chars: usize) -> &str {
+    match text.char_indices().nth(chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// The last `chars` characters of `text`
+fn tail(text: &str, chars: usize) -> &str {
+    if chars == 0 {
+        return &text[text.len()..];
+    }
+    match text.char_indices().nth_back(chars - 1) {
+        Some((start, _)) => &text[start..],
+        None => text,
+    }
+}
+
+/// The headings of a DOCX document or the pages of a PDF, in order, each
+/// with the occurrences found under it and its first excerpt shown
+fn outline(
+    blocks: &[TextBlock],
+    matches: &[MatchRecord],
+    anchors: &[Vec<Option<String>>],
+) -> Vec<OutlineEntry> {
+    // The section each block belongs to, and the entries of the headings
+    // enclosing the current block
+    let mut entries: Vec<OutlineEntry> = Vec::new();
+    let mut sections = Vec::with_capacity(blocks.len());
+    let mut path: Vec<String> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    for block in blocks {
+        let key = match &block.location {
+            BlockLocation::PdfPage { page } => vec![format!("Page {}", page)],
+            BlockLocation::DocxParagraph { heading_path, .. } => heading_path.clone(),
+            BlockLocation::DocxPart { part, .. } => vec![part.name().to_string()],
+            BlockLocation::DocxProperty { .. } | BlockLocation::PdfProperty { .. } => {
+                vec!["Properties".to_string()]
+            }
+            BlockLocation::SheetCell { sheet, .. } => vec![sheet.clone()],
+            BlockLocation::PlainLine { .. } => Vec::new(),
+            BlockLocation::EpubChapter { href, .. } => vec![href.clone()],
+        };
+        let common = path
+            .iter()
+            .zip(&key)
+            .take_while(|(open, new)| open == new)
+            .count();
+        open.truncate(common);
+        for (depth, label) in key.iter().enumerate().skip(common) {
+            open.push(entries.len());
+            entries.push(OutlineEntry {
+                label: label.clone(),
+                depth: depth + 1,
+                occurrences: 0,
+                anchor: None,
+            });
+        }
+        path = key;
+        sections.push(open.last().copied());
+    }
+
+    for (record, record_anchors) in matches.iter().zip(anchors) {
+        if !record.source.entries().is_empty() {
+            continue;
+        }
+        for (location, anchor) in record.occurrences.iter().zip(record_anchors) {
+            let Some(Some(section)) = sections.get(location.line) else {
+                continue;
+            };
+            let entry = &mut entries[*section];
+            entry.occurrences += 1;
+            if entry.anchor.is_none() {
+                entry.anchor = anchor.clone();
+            }
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Source;
+
+    fn location(line: usize, text: &str, needle: &str) -> Location {
+        let start = text.find(needle).unwrap();
+        Location {
+            line,
+            page: None,
+            page_estimated: false,
+            part: Default::default(),
+            paragraph: None,
+            headings: Vec::new(),
+            span: [start, start + needle.len()],
+            offset: [0, 0],
+            text: text.to_string(),
+            approximate: None,
+        }
+    }
+
+    fn paragraph(index: usize, heading_path: &[&str], text: &str) -> TextBlock {
+        let heading_path = heading_path
+            .iter()
+            .map(|heading| heading.to_string())
+            .collect();
+        TextBlock::new(
+            text,
+            BlockLocation::DocxParagraph {
+                index,
+                heading_path,
+                page: 1,
+                cell: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_excerpts_show_surrounding_blocks_within_the_cap() {
+        let blocks: Vec<TextBlock> = [
+            "first",
+            "",
+            "before <b>",
+            "Signed by Alice Johnson today",
+            "after",
+            "second after",
+            "too far",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(index, text)| paragraph(index, &[], text))
+        .collect();
+        let found = location(3, &blocks[3].text, "Alice Johnson");
+
+        let (html, chars) = excerpt(&found, Some(&blocks), 1000);
+        assert_eq!(
+            html,
+            "<pre>before &lt;b&gt;\n<span class=\"hit\">Signed by <mark>Alice Johnson</mark> today</span>\nafter\nsecond after</pre>"
+        );
+        assert_eq!(chars, 10 + 29 + 5 + 12);
+
+        // The match stays whole; its line is cut around it
+        let (html, chars) = excerpt(&found, Some(&blocks), 19);
+        assert_eq!(
+            html,
+            "<pre><span class=\"hit\">…by <mark>Alice Johnson</mark> to…</span></pre>"
+        );
+        assert_eq!(chars, 19);
+
+        // Blocks that aren't the ones searched only give the matching line
+        let (html, _) = excerpt(&found, Some(&blocks[..3]), 1000);
+        assert!(html.starts_with("<pre><span class=\"hit\">Signed") && !html.contains("after"));
+    }
+
+    #[test]
+    fn test_caps_leave_a_notice() {
+        let blocks: Vec<TextBlock> = (0..4)
+            .map(|index| paragraph(index, &["Terms"], "Alice Johnson signed"))
+            .collect();
+        let source = Source::file(std::path::Path::new("memo.docx"));
+        let mut record = MatchRecord::new(&source, "Alice", "id=1", &[]);
+        record.occurrences = (0..4)
+            .map(|line| location(line, "Alice Johnson signed", "Alice"))
+            .collect();
+        let matches = [record];
+        let document = Document {
+            path: "memo.docx",
+            details: vec![("Type", "DOCX".to_string())],
+            blocks: Some(&blocks),
+            matches: &matches,
+        };
+
+        let limits = ExcerptLimits {
+            max_excerpt_chars: 20,
+            max_document_chars: 50,
+            max_report_chars: 1_000,
+        };
+        let mut report = FullReport::new(limits);
+        report.add_document(&document);
+        let html = report.finish("Report", &[]);
+        assert_eq!(html.matches("<div class=\"excerpt\"").count(), 2);
+        assert!(html.contains(
+            "2 more occurrences in this document are not shown (--max-document-chars 50)"
+        ));
+        assert!(html.contains("<li class=\"depth-1\"><a href=\"#"));
+        assert!(!html.contains("Report truncated"));
+
+        let mut report = FullReport::new(ExcerptLimits {
+            max_report_chars: 30,
+            ..limits
+        });
+        report.add_document(&document);
+        report.add_document(&document);
+        let html = report.finish("Report", &[("Command", "docsearcher <batch>".to_string())]);
+        assert_eq!(html.matches("<div class=\"excerpt\"").count(), 1);
+        assert!(html.contains("Report truncated: 7 occurrences are not shown after 20 characters of excerpts (--max-report-chars 30)"));
+        assert!(html.contains("Alice (id=1) <i>(not shown)</i>"));
+        assert!(html.contains("<dd>docsearcher &lt;batch&gt;</dd>"));
+    }
+}
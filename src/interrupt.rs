@@ -0,0 +1,98 @@
+//! Ctrl-C.
+//!
+//! While a long command runs inside [`graceful`], the first Ctrl-C only
+//! cancels its [`CancelToken`]: the batch pipeline stops handing out files,
+//! documents already being searched finish, and the command reports what it
+//! has as a partial result before exiting with [`EXIT_INTERRUPTED`]. A
+//! second Ctrl-C, or the first one outside [`graceful`], restores the
+//! terminal and exits at once.
+
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use crossterm::{cursor::Show, execute, terminal::disable_raw_mode};
+
+/// Exit code for runs stopped by Ctrl-C, as shells report SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Set to ask work in progress to stop at its next checkpoint
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens are equal when they are the same token
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// The token the next Ctrl-C cancels
+static CURRENT: Mutex<Option<CancelToken>> = Mutex::new(None);
+
+/// Handle Ctrl-C for the rest of the process
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(on_interrupt)
+        .map_err(|e| anyhow!("Failed to install the Ctrl-C handler: {}", e))
+}
+
+fn on_interrupt() {
+    let current = CURRENT.lock().map_or(None, |current| current.clone());
+    match current {
+        Some(token) if !token.is_cancelled() => token.cancel(),
+        _ => {
+            restore_terminal();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    }
+}
+
+/// Stops routing Ctrl-C to a token when dropped
+pub struct GracefulGuard(());
+
+impl Drop for GracefulGuard {
+    fn drop(&mut self) {
+        if let Ok(mut current) = CURRENT.lock() {
+            *current = None;
+        }
+    }
+}
+
+/// Cancel `token` on the first Ctrl-C, until the guard is dropped
+pub fn graceful(token: &CancelToken) -> GracefulGuard {
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(token.clone());
+    }
+    GracefulGuard(())
+}
+
+/// Leave raw mode and show the cursor, as progress bars and the TUI found
+/// the terminal
+pub fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), Show);
+    let mut stderr = io::stderr();
+    let _ = execute!(stderr, Show);
+    let _ = writeln!(stderr);
+}
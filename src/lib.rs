@@ -1,8 +1,35 @@
+pub mod api;
+#[cfg(feature = "async")]
+pub mod async_search;
+pub mod batch;
+pub mod bench;
+pub mod cmd;
+pub mod error;
+pub mod fixtures;
+pub mod html_full;
+pub mod interrupt;
+pub mod local_needles;
+pub mod logging;
+pub mod matcher;
+pub mod needle_index;
+pub mod needle_list;
+pub mod output;
 pub mod parsers;
+pub mod progress;
+pub mod report;
+pub mod resources;
+pub mod search;
+pub mod settings;
+pub mod spill;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod triage;
 pub mod types;
 pub mod utils;
-pub mod cmd;
 
+pub use error::{ConfigError, DocSearchError};
 pub use parsers::{parse_docx_from_path, parse_pdf_from_path};
-pub use types::{FileType, SearchResult};
-pub use utils::{parse_filetype, read_needles_from_file, read_needles_from_mem};
+pub use types::{FileType, SearchConfig, SearchResult};
+pub use utils::{
+    detect_filetype_from_bytes, parse_filetype, read_needles_from_file, read_needles_from_mem,
+};
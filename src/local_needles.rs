@@ -0,0 +1,438 @@
+//! Per-directory needle lists for batch runs.
+//!
+//! A `.docsearcher-needles.csv` file in a scanned directory adds its needles
+//! to the ones searched for in that directory and everything below it. A
+//! list whose first line is `!override` replaces the inherited needles
+//! instead of adding to them. Each document is searched with the stack of
+//! lists active in its directory; documents with the same stack share one
+//! needle set, and every needle in a set remembers the list it came from so
+//! results can name it.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    types::{NeedleDelimiter, NeedleWeights, OwnedNeedle, SearchResult},
+    utils::{
+        filter_needles_by_date, filter_needles_by_metadata, filter_stopwords, needle_weights,
+        read_needles_from_mem, Date, Stopwords,
+    },
+};
+
+/// Name of the per-directory needles file
+pub const FILE_NAME: &str = ".docsearcher-needles.csv";
+
+/// First line that makes a list replace the inherited needles
+pub const OVERRIDE_MARKER: &str = "!override";
+
+/// One needles file and the needles it contributes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NeedleList {
+    pub path: PathBuf,
+    pub needles: Vec<OwnedNeedle>,
+    /// The weights the list gives its needles
+    pub weights: NeedleWeights,
+    /// Whether the list starts with `!override`
+    pub replaces: bool,
+}
+
+impl NeedleList {
+    /// Parse a needles file, honouring a leading `!override` line; lines
+    /// are split at `delimiter`, or at the detected one when `None`
+    pub fn parse(path: &Path, bytes: &[u8], delimiter: Option<NeedleDelimiter>) -> Result<Self> {
+        let (first, rest) = match bytes.iter().position(|&b| b == b'\n') {
+            Some(end) => bytes.split_at(end),
+            None => (bytes, &[][..]),
+        };
+        let replaces = std::str::from_utf8(first).is_ok_and(|line| line.trim() == OVERRIDE_MARKER);
+
+        // Parse the newline left after the marker so warnings keep their
+        // line numbers.
+        let content = if replaces { rest } else { bytes };
+        let needles = read_needles_from_mem(content, delimiter)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let weights = needle_weights(&String::from_utf8_lossy(content), delimiter);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            needles,
+            weights,
+            replaces,
+        })
+    }
+}
+
+/// How local lists are read and narrowed down, the same way as the global
+/// needles file
+#[derive(Clone, Copy)]
+pub struct ListOptions<'a> {
+    /// `--needle-delimiter`; `None` detects it per list
+    pub delimiter: Option<NeedleDelimiter>,
+    pub stopwords: &'a Stopwords,
+    /// The parsed `--where` filters
+    pub filters: &'a [(String, String)],
+    pub as_of: Date,
+}
+
+/// The needles searched for in a group of documents
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NeedleSet {
+    pub needles: Vec<OwnedNeedle>,
+    /// Index of the list each needle came from
+    sources: HashMap<OwnedNeedle, usize>,
+    /// The weight each needle has in that list, when not 0
+    weights: NeedleWeights,
+}
+
+impl NeedleSet {
+    /// Merge `stack` (indexes into `lists`, outermost first); a needle
+    /// listed twice is attributed to the outermost list
+    fn build(lists: &[NeedleList], stack: &[usize]) -> Self {
+        let mut set = Self::default();
+        for &list in stack {
+            for needle in &lists[list].needles {
+                let (term, metadata) = needle;
+                if let Entry::Vacant(entry) = set.sources.entry((term.clone(), metadata.clone())) {
+                    entry.insert(list);
+                    set.needles.push(needle.clone());
+                    if let Some(&weight) = lists[list].weights.get(needle) {
+                        set.weights.insert(needle.clone(), weight);
+                    }
+                }
+            }
+        }
+        set
+    }
+}
+
+/// Which needles each document of a batch is searched for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NeedleScopes {
+    /// The global needles file first, then local lists in discovery order
+    lists: Vec<NeedleList>,
+    sets: Vec<NeedleSet>,
+    /// Index into `sets` for each file
+    file_sets: Vec<usize>,
+    /// Local needles left out because they are not valid on the `as_of` date
+    inactive: usize,
+    /// Local needles dropped as stop words
+    stopwords: usize,
+}
+
+impl NeedleScopes {
+    /// Search all `file_count` files for the global needles only, which
+    /// weigh as `weights` says
+    pub fn global(
+        path: &Path,
+        needles: Vec<OwnedNeedle>,
+        weights: NeedleWeights,
+        file_count: usize,
+    ) -> Self {
+        let lists = vec![NeedleList {
+            path: path.to_path_buf(),
+            needles,
+            weights,
+            replaces: false,
+        }];
+        let sets = vec![NeedleSet::build(&lists, &[0])];
+
+        Self {
+            lists,
+            sets,
+            file_sets: vec![0; file_count],
+            inactive: 0,
+            stopwords: 0,
+        }
+    }
+
+    /// Look for local lists in `root` and the directories between it and
+    /// each of `files`. Files outside `root` get the global needles.
+    ///
+    /// Local needles are read with the same `--needle-delimiter` and go
+    /// through the same `--stopwords`, `--where` filters and `--as-of` date
+    /// as the global ones.
+    pub fn discover(
+        path: &Path,
+        needles: Vec<OwnedNeedle>,
+        weights: NeedleWeights,
+        root: &Path,
+        files: &[PathBuf],
+        options: ListOptions,
+    ) -> Result<Self> {
+        let mut scopes = Self::global(path, needles, weights, 0);
+        scopes.sets.clear();
+
+        let mut stacks = HashMap::new();
+        let mut set_ids: HashMap<Vec<usize>, usize> = HashMap::new();
+
+        for file in files {
+            let stack = match file.parent().filter(|dir| dir.starts_with(root)) {
+                Some(dir) => scopes.stack_for(dir, root, &mut stacks, options)?,
+                None => vec![0],
+            };
+
+            let id = match set_ids.get(&stack) {
+                Some(&id) => id,
+                None => {
+                    scopes.sets.push(NeedleSet::build(&scopes.lists, &stack));
+                    set_ids.insert(stack, scopes.sets.len() - 1);
+                    scopes.sets.len() - 1
+                }
+            };
+            scopes.file_sets.push(id);
+        }
+
+        Ok(scopes)
+    }
+
+    /// The lists active in `dir`, outermost first, memoized per directory
+    fn stack_for(
+        &mut self,
+        dir: &Path,
+        root: &Path,
+        stacks: &mut HashMap<PathBuf, Vec<usize>>,
+        options: ListOptions,
+    ) -> Result<Vec<usize>> {
+        if let Some(stack) = stacks.get(dir) {
+            return Ok(stack.clone());
+        }
+
+        let mut stack = match dir.parent() {
+            Some(parent) if dir != root && parent.starts_with(root) => {
+                self.stack_for(parent, root, stacks, options)?
+            }
+            _ => vec![0],
+        };
+
+        let candidate = dir.join(FILE_NAME);
+        if candidate.is_file() {
+            let bytes = std::fs::read(&candidate)
+                .map_err(|e| anyhow!("Failed to read {}: {}", candidate.display(), e))?;
+            let mut list = NeedleList::parse(&candidate, &bytes, options.delimiter)?;
+            let (needles, stopped) = filter_stopwords(list.needles, options.stopwords);
+            let (needles, inactive) = filter_needles_by_date(
+                filter_needles_by_metadata(needles, options.filters),
+                options.as_of,
+            );
+            list.needles = needles;
+            self.inactive += inactive;
+            self.stopwords += stopped.len();
+
+            if list.replaces {
+                stack.clear();
+            }
+            stack.push(self.lists.len());
+            self.lists.push(list);
+        }
+
+        stacks.insert(dir.to_path_buf(), stack.clone());
+        Ok(stack)
+    }
+
+    /// The needles to search the file at `index` for
+    pub fn needles_for(&self, index: usize) -> &[OwnedNeedle] {
+        &self.sets[self.file_sets[index]].needles
+    }
+
+    /// The local list that contributed `result` to the file at `index`, or
+    /// `None` when it came from the global needles file
+    pub fn source(&self, index: usize, result: &SearchResult) -> Option<&Path> {
+        match self.sets[self.file_sets[index]]
+            .sources
+            .get(&(result.term.clone(), result.metadata.clone()))
+        {
+            Some(&list) if list > 0 => Some(&self.lists[list].path),
+            _ => None,
+        }
+    }
+
+    /// The weight of the needle of `result` in the file at `index`, from
+    /// the list that contributed it
+    pub fn weight(&self, index: usize, result: &SearchResult) -> i64 {
+        let weights = &self.sets[self.file_sets[index]].weights;
+        weights
+            .get(&(result.term.clone(), result.metadata.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The global needles
+    pub fn global_needles(&self) -> &[OwnedNeedle] {
+        &self.lists[0].needles
+    }
+
+    /// The global needles file
+    pub fn global_path(&self) -> &Path {
+        &self.lists[0].path
+    }
+
+    /// Number of local needles not searched because they are not valid on
+    /// the `as_of` date
+    pub fn inactive(&self) -> usize {
+        self.inactive
+    }
+
+    /// Number of local needles dropped as stop words
+    pub fn stopwords(&self) -> usize {
+        self.stopwords
+    }
+
+    /// The local lists that were found
+    pub fn local_lists(&self) -> &[NeedleList] {
+        &self.lists[1..]
+    }
+
+    /// Number of distinct needle sets the files are searched with
+    pub fn set_count(&self) -> usize {
+        self.sets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch::{run_pipeline_scoped, PipelineOptions},
+        fixtures,
+        search::results_from_occurrences,
+    };
+
+    fn needle(term: &str, metadata: &str) -> OwnedNeedle {
+        (term.to_string(), metadata.to_string())
+    }
+
+    fn list_options(stopwords: &Stopwords) -> ListOptions<'_> {
+        ListOptions {
+            delimiter: None,
+            stopwords,
+            filters: &[],
+            as_of: Date::today(),
+        }
+    }
+
+    #[test]
+    fn test_parse_override_marker() {
+        let list = NeedleList::parse(Path::new("x.csv"), b"!override\nDave,id=4\n", None).unwrap();
+        assert!(list.replaces);
+        assert_eq!(list.needles, vec![needle("Dave", "id=4")]);
+
+        let list = NeedleList::parse(Path::new("x.csv"), b"Dave,id=4", None).unwrap();
+        assert!(!list.replaces);
+        assert!(NeedleList::parse(Path::new("x.csv"), b"!override\n", None).is_err());
+
+        // The marker line is not sampled when detecting the delimiter
+        let list = NeedleList::parse(Path::new("x.csv"), b"!override\nDave;id=4\n", None).unwrap();
+        assert_eq!(list.needles, vec![needle("Dave", "id=4")]);
+    }
+
+    #[test]
+    fn test_subtree_needles_only_match_within_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let text = ["Alice, Carol, Dave, Erin and Frank"];
+
+        let write = |relative: &str, bytes: &[u8]| {
+            let path = root.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, bytes).unwrap();
+            path
+        };
+        let root_list = write(FILE_NAME, b"Carol,id=root\n");
+        let client_list = write(
+            &format!("client/{}", FILE_NAME),
+            b"Dave,id=client,5\nFrank,id=client;valid_until=2000-01-01\n",
+        );
+        write(
+            &format!("vendor/{}", FILE_NAME),
+            b"!override\nErin,id=vendor\n",
+        );
+        let files = vec![
+            write("top.docx", &fixtures::docx(&text)),
+            write("client/a.docx", &fixtures::docx(&text)),
+            write("client/deep/b.docx", &fixtures::docx(&text)),
+            write("other/c.docx", &fixtures::docx(&text)),
+            write("vendor/d.docx", &fixtures::docx(&text)),
+        ];
+
+        let global = vec![needle("Alice", "id=global")];
+        let weights = NeedleWeights::from([(needle("Alice", "id=global"), 2)]);
+        let scopes = NeedleScopes::discover(
+            Path::new("needles.csv"),
+            global,
+            weights,
+            root,
+            &files,
+            list_options(&Stopwords::default()),
+        )
+        .unwrap();
+        assert_eq!(scopes.local_lists().len(), 3);
+        assert_eq!(scopes.inactive(), 1);
+        // top and other share the root stack, client and client/deep share theirs
+        assert_eq!(scopes.set_count(), 3);
+
+        let outcomes = run_pipeline_scoped(&scopes, &files, &PipelineOptions::default(), &|_| {});
+        let found: Vec<Vec<String>> = outcomes
+            .iter()
+            .map(|outcome| {
+                let mut terms: Vec<String> =
+                    results_from_occurrences(outcome.occurrences.as_ref().unwrap())
+                        .into_iter()
+                        .map(|result| result.term)
+                        .collect();
+                terms.sort();
+                terms
+            })
+            .collect();
+
+        assert_eq!(found[0], ["Alice", "Carol"]);
+        assert_eq!(found[1], ["Alice", "Carol", "Dave"]);
+        assert_eq!(found[2], ["Alice", "Carol", "Dave"]);
+        assert_eq!(found[3], ["Alice", "Carol"]);
+        assert_eq!(found[4], ["Erin"]);
+
+        // Each list weighs its own needles
+        assert_eq!(scopes.weight(1, &SearchResult::new("Dave", "id=client")), 5);
+        assert_eq!(
+            scopes.weight(1, &SearchResult::new("Alice", "id=global")),
+            2
+        );
+        assert_eq!(scopes.weight(4, &SearchResult::new("Erin", "id=vendor")), 0);
+
+        assert_eq!(
+            scopes.source(1, &SearchResult::new("Dave", "id=client")),
+            Some(client_list.as_path())
+        );
+        assert_eq!(
+            scopes.source(1, &SearchResult::new("Carol", "id=root")),
+            Some(root_list.as_path())
+        );
+        assert_eq!(
+            scopes.source(1, &SearchResult::new("Alice", "id=global")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_files_outside_root_get_global_needles() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(FILE_NAME), "Carol,id=root\n").unwrap();
+
+        let files = vec![PathBuf::from("elsewhere/a.pdf")];
+        let global = vec![needle("Alice", "id=global")];
+        let scopes = NeedleScopes::discover(
+            Path::new("needles.csv"),
+            global.clone(),
+            NeedleWeights::new(),
+            dir.path(),
+            &files,
+            list_options(&Stopwords::default()),
+        )
+        .unwrap();
+        assert_eq!(scopes.needles_for(0), global.as_slice());
+        assert!(scopes.local_lists().is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+//! Audit log of docsearcher runs (`--log-file`).
+//!
+//! Each invocation runs inside a `run` span carrying the user, the command
+//! line and the file and match counts; when it finishes, one JSON line with
+//! the span, the duration and the exit code is appended to the log file.
+//! Per-file failures are logged at debug level inside the same span.
+//!
+//! The log file is rotated by size: when a line would push it past the
+//! limit, `run.log` becomes `run.log.1`, `run.log.1` becomes `run.log.2`
+//! and so on, keeping a fixed number of old files. Logging never writes to
+//! stdout, and an unwritable log only produces a warning on stderr.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tracing::{field, Span, Subscriber};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::MakeWriter,
+    layer::SubscriberExt,
+    Layer,
+};
+
+/// Where and how much to log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogOptions {
+    pub path: PathBuf,
+    /// Size in bytes after which the log is rotated
+    pub max_size: u64,
+    /// Number of rotated files to keep
+    pub keep: usize,
+}
+
+/// Install the file logger as the global subscriber.
+///
+/// Returns `false`, after printing a warning, when the log file cannot be
+/// opened; the run then continues without logging.
+pub fn init(options: &LogOptions) -> bool {
+    let installed = subscriber(options).and_then(|subscriber| {
+        tracing::subscriber::set_global_default(subscriber).map_err(io::Error::other)
+    });
+
+    match installed {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Warning: not logging to {}: {}", options.path.display(), e);
+            false
+        }
+    }
+}
+
+/// Build a subscriber that writes JSON lines to the rotating log file
+pub fn subscriber(options: &LogOptions) -> io::Result<impl Subscriber + Send + Sync> {
+    let file = RotatingFile::open(&options.path, options.max_size, options.keep)?;
+    let layer = tracing_subscriber::fmt::layer()
+        .json()
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_ansi(false)
+        .with_writer(file)
+        .with_filter(Targets::new().with_target("docsearcher", LevelFilter::DEBUG));
+
+    Ok(tracing_subscriber::registry().with(layer))
+}
+
+/// Open the span a whole invocation runs in.
+///
+/// Commands fill in `files`, `files_with_matches` and `matches` with
+/// `Span::current().record(..)` once they know them.
+pub fn run_span(invocation: &str) -> Span {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    tracing::info_span!(
+        target: "docsearcher::run",
+        "run",
+        user = %user,
+        invocation = %invocation,
+        files = field::Empty,
+        files_with_matches = field::Empty,
+        matches = field::Empty,
+    )
+}
+
+/// Log the end of the run; call from inside the span returned by [`run_span`]
+pub fn finish_run(duration: Duration, exit_code: i32) {
+    tracing::info!(
+        target: "docsearcher::run",
+        duration_ms = duration.as_millis() as u64,
+        exit_code,
+        "run finished"
+    );
+}
+
+/// An append-only log file rotated by size
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    state: Mutex<(File, u64)>,
+    warned: AtomicBool,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            keep,
+            state: Mutex::new((file, size)),
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    /// Append one complete line, rotating first if it would not fit
+    fn write_line(&self, line: &[u8]) {
+        let mut state = self.state.lock().expect("log file lock poisoned");
+
+        let result = (|| {
+            if state.1 > 0 && state.1 + line.len() as u64 > self.max_size {
+                *state = (self.rotate()?, 0);
+            }
+            state.0.write_all(line)?;
+            state.1 += line.len() as u64;
+            Ok::<_, io::Error>(())
+        })();
+
+        if let Err(e) = result {
+            if !self.warned.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "Warning: failed to write log file {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Shift `path.N` to `path.N+1`, dropping the oldest, and start a new file
+    fn rotate(&self) -> io::Result<File> {
+        if self.keep == 0 {
+            return File::create(&self.path);
+        }
+
+        let _ = fs::remove_file(self.rotated_path(self.keep));
+        for index in (1..self.keep).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+    type Writer = LineWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LineWriter {
+            file: self,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// Buffers one formatted event so it is written, and rotated, as a whole line
+pub struct LineWriter<'a> {
+    file: &'a RotatingFile,
+    buffer: Vec<u8>,
+}
+
+impl Write for LineWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LineWriter<'_> {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            self.file.write_line(&self.buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_lines(path: &Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("log line is not JSON"))
+            .collect()
+    }
+
+    #[test]
+    fn test_two_runs_rotate_into_numbered_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = LogOptions {
+            path: dir.path().join("runs.log"),
+            max_size: 256,
+            keep: 2,
+        };
+
+        let subscriber = subscriber(&options).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            for run in 0..2 {
+                let span = run_span(&format!("docsearcher batch -d docs{} -n needles.csv", run));
+                let _guard = span.enter();
+                tracing::debug!(
+                    file = "docs/broken.pdf",
+                    error = "Failed to extract text",
+                    "file skipped"
+                );
+                span.record("files", 3);
+                span.record("files_with_matches", 1);
+                span.record("matches", 2);
+                finish_run(Duration::from_millis(40), 0);
+            }
+        });
+
+        // Every line exceeds the limit on its own, so each line after the
+        // first rotated the file; only two old files are kept.
+        let rotated: Vec<PathBuf> = (1..=3)
+            .map(|i| dir.path().join(format!("runs.log.{}", i)))
+            .collect();
+        assert!(rotated[0].exists() && rotated[1].exists());
+        assert!(!rotated[2].exists());
+
+        let current = read_lines(&options.path);
+        assert_eq!(current.len(), 1);
+        let run = &current[0];
+        assert_eq!(run["fields"]["message"], "run finished");
+        assert_eq!(run["fields"]["exit_code"], 0);
+        assert_eq!(
+            run["span"]["invocation"],
+            "docsearcher batch -d docs1 -n needles.csv"
+        );
+        assert_eq!(run["span"]["files"], 3);
+        assert_eq!(run["span"]["matches"], 2);
+        assert!(run["timestamp"].is_string());
+
+        let previous = read_lines(&rotated[0]);
+        assert_eq!(previous[0]["level"], "DEBUG");
+        assert_eq!(previous[0]["fields"]["file"], "docs/broken.pdf");
+        assert_eq!(read_lines(&rotated[1]).len(), 1);
+    }
+
+    #[test]
+    fn test_small_lines_share_a_file_until_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runs.log");
+        let file = RotatingFile::open(&path, 10, 1).unwrap();
+
+        file.write_line(b"1234\n");
+        file.write_line(b"123\n");
+        file.write_line(b"abc\n");
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("runs.log.1")).unwrap(),
+            "1234\n123\n"
+        );
+    }
+
+    #[test]
+    fn test_unwritable_log_path_is_reported_not_fatal() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = LogOptions {
+            path: dir.path().join("missing").join("runs.log"),
+            max_size: 1024,
+            keep: 1,
+        };
+
+        assert!(subscriber(&options).is_err());
+    }
+}
@@ -1,8 +1,16 @@
-use docsearcher::cmd::CliApp;
+use docsearcher::{
+    api::Reported,
+    cmd::{CliApp, EXIT_ERROR},
+};
 
 fn main() {
-    if let Err(e) = CliApp::run() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    match CliApp::run() {
+        Ok(outcome) => std::process::exit(outcome.code()),
+        Err(e) => {
+            if !e.is::<Reported>() {
+                eprintln!("Error: {}", e);
+            }
+            std::process::exit(EXIT_ERROR);
+        }
     }
 }
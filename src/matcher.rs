@@ -0,0 +1,1436 @@
+//! Matching a needle against a line, as a sequence of stages.
+//!
+//! Every search goes through a [`Matcher`]: each [`Normalizer`] rewrites the
+//! needle and the line, the normalized needle is looked up in the normalized
+//! line, the candidate spans are mapped back to the original line, and each
+//! candidate has to pass the [`Boundary`] check. The stages can be run one at
+//! a time, and [`Matcher::explain`] records what each of them produced for
+//! `docsearcher explain`.
+//!
+//! Searches match needles as substrings. A [`CollapseWhitespace`] stage
+//! first turns every run of whitespace into one space, and an [`Nfc`] stage
+//! composes needles and lines alike, unless turned off. Unless the search is
+//! case-sensitive, a [`CaseFold`] stage makes them ignore case, a
+//! [`StripAccents`] stage makes them ignore accents when asked to, and
+//! whole-word searches only accept matches at a [`Boundary::Word`]. With
+//! [`Matcher::with_max_edits`], [`NeedleLookup::find_approximate`] also finds
+//! runs of words a few edits away from a needle. [`near_windows`] pairs up
+//! the matches of two needles that are a few words apart.
+//!
+//! A search looks many needles up in every line at once through a
+//! [`NeedleLookup`], using one of two [`Strategy`]s for the lookup stage: each
+//! needle on its own, or every needle in one pass of an Aho-Corasick
+//! automaton. Both find exactly the same spans; [`select`] picks the one
+//! expected to be faster for the needle list and the text to search.
+
+use std::{borrow::Cow, collections::HashMap, fmt, ops::Range};
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{
+    char::{canonical_combining_class, compose, decompose_canonical, is_combining_mark},
+    is_nfc, UnicodeNormalization,
+};
+
+/// A stage that rewrites needles and lines before they are compared
+pub trait Normalizer: Send + Sync {
+    /// Name shown by `explain`
+    fn name(&self) -> &'static str;
+
+    fn normalize<'a>(&self, text: &'a str) -> Normalized<'a>;
+}
+
+/// Text after one or more normalization stages, with the offset in the
+/// original text that each byte came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Normalized<'a> {
+    pub text: Cow<'a, str>,
+    /// Original offset of every byte of `text`, plus one for its end;
+    /// `None` when the text is unchanged
+    origins: Option<Vec<usize>>,
+}
+
+impl<'a> Normalized<'a> {
+    /// `text`, unchanged
+    pub fn identity(text: &'a str) -> Self {
+        Normalized {
+            text: Cow::Borrowed(text),
+            origins: None,
+        }
+    }
+
+    /// Build normalized text from pieces, each replacing the original text
+    /// starting at the given offset; `end` is the length of the original
+    pub fn from_pieces<'p>(pieces: impl IntoIterator<Item = (usize, &'p str)>, end: usize) -> Self {
+        let mut text = String::new();
+        let mut origins = Vec::new();
+        for (origin, piece) in pieces {
+            text.push_str(piece);
+            origins.extend(std::iter::repeat_n(origin, piece.len()));
+        }
+        origins.push(end);
+        Normalized {
+            text: Cow::Owned(text),
+            origins: Some(origins),
+        }
+    }
+
+    /// The original offset of byte `offset` of the normalized text
+    pub fn origin(&self, offset: usize) -> usize {
+        match &self.origins {
+            Some(origins) => origins[offset.min(origins.len() - 1)],
+            None => offset,
+        }
+    }
+
+    /// Map a span of the normalized text back to the original text
+    pub fn original_span(&self, span: &Range<usize>) -> Range<usize> {
+        let start = self.origin(span.start);
+        let Some(origins) = &self.origins else {
+            return span.clone();
+        };
+        if span.is_empty() {
+            return start..start;
+        }
+
+        // The span ends where the first byte from a later original position starts
+        let last = self.origin(span.end - 1);
+        let end = origins[span.end..]
+            .iter()
+            .copied()
+            .find(|&origin| origin > last)
+            .unwrap_or(last + 1);
+        start..end
+    }
+
+    /// Apply `next` on top of this normalization
+    fn then(self, next: &dyn Normalizer) -> Normalized<'a> {
+        let output = next.normalize(&self.text);
+        let origins = match (&self.origins, output.origins) {
+            (None, None) => {
+                return Normalized {
+                    text: Cow::Owned(output.text.into_owned()),
+                    origins: None,
+                }
+            }
+            (_, Some(inner)) => inner.iter().map(|&offset| self.origin(offset)).collect(),
+            (Some(_), None) => (0..=output.text.len())
+                .map(|offset| self.origin(offset))
+                .collect(),
+        };
+        Normalized {
+            text: Cow::Owned(output.text.into_owned()),
+            origins: Some(origins),
+        }
+    }
+}
+
+/// Collapses every run of whitespace, including tabs and non-breaking
+/// spaces, to a single space, so that `Acme  Corporation` and
+/// `Acme\u{a0}Corporation` compare alike
+pub struct CollapseWhitespace;
+
+impl Normalizer for CollapseWhitespace {
+    fn name(&self) -> &'static str {
+        "collapse-whitespace"
+    }
+
+    fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+        let mut previous_space = false;
+        let unchanged = text.chars().all(|c| {
+            let plain = c == ' ' && !previous_space;
+            previous_space = c.is_whitespace();
+            plain || !c.is_whitespace()
+        });
+        if unchanged {
+            return Normalized::identity(text);
+        }
+        let mut pieces = Vec::new();
+        let mut in_run = false;
+        for (i, c) in text.char_indices() {
+            if !c.is_whitespace() {
+                pieces.push((i, &text[i..i + c.len_utf8()]));
+            } else if !in_run {
+                pieces.push((i, " "));
+            }
+            in_run = c.is_whitespace();
+        }
+        Normalized::from_pieces(pieces, text.len())
+    }
+}
+
+/// How [`CaseFold`] makes needles and lines ignore case
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseFolding {
+    /// Unicode case folding, so `STRASSE` matches `straße` and `İstanbul`
+    /// matches `istanbul`
+    #[default]
+    Unicode,
+    /// Unicode case folding with Turkish and Azerbaijani dotted and
+    /// dotless I: `I` folds to `ı` and `İ` to `i`
+    Turkic,
+    /// ASCII letters only, the fastest; other letters match only in the
+    /// case they are written in
+    Simple,
+}
+
+/// Folds the case of needles and lines, so that matching ignores case.
+///
+/// Besides lowercasing, [`CaseFolding::Unicode`] folds `ß` and `ẞ` to `ss`,
+/// final `ς` to `σ` and ligatures such as `ﬁ` to their letters. `İ` folds
+/// to a plain `i` rather than to `i` with a combining dot, as Unicode's own
+/// folding has it, so that Turkish names match when written without the
+/// dot.
+pub struct CaseFold(pub CaseFolding);
+
+impl CaseFold {
+    /// Append the folded form of `c` to `folded`
+    fn fold(&self, c: char, folded: &mut String) {
+        match (self.0, c) {
+            (CaseFolding::Simple, c) => folded.push(c.to_ascii_lowercase()),
+            (CaseFolding::Turkic, 'I') => folded.push('ı'),
+            (_, 'İ') => folded.push('i'),
+            (_, 'ß' | 'ẞ') => folded.push_str("ss"),
+            (_, 'ς') => folded.push('σ'),
+            (_, 'ﬀ') => folded.push_str("ff"),
+            (_, 'ﬁ') => folded.push_str("fi"),
+            (_, 'ﬂ') => folded.push_str("fl"),
+            (_, 'ﬃ') => folded.push_str("ffi"),
+            (_, 'ﬄ') => folded.push_str("ffl"),
+            (_, 'ﬅ' | 'ﬆ') => folded.push_str("st"),
+            (_, c) => folded.extend(c.to_lowercase()),
+        }
+    }
+}
+
+impl Normalizer for CaseFold {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            CaseFolding::Unicode => "casefold",
+            CaseFolding::Turkic => "casefold-turkic",
+            CaseFolding::Simple => "ascii-lowercase",
+        }
+    }
+
+    fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+        if self.0 == CaseFolding::Simple {
+            return match text.bytes().any(|b| b.is_ascii_uppercase()) {
+                true => Normalized {
+                    text: Cow::Owned(text.to_ascii_lowercase()),
+                    origins: None,
+                },
+                false => Normalized::identity(text),
+            };
+        }
+        let mut unchanged = true;
+        let mut folded = String::new();
+        let pieces: Vec<(usize, String)> = text
+            .char_indices()
+            .map(|(i, c)| {
+                folded.clear();
+                self.fold(c, &mut folded);
+                unchanged &= folded.chars().eq([c]);
+                (i, folded.clone())
+            })
+            .collect();
+        if unchanged {
+            return Normalized::identity(text);
+        }
+        // Offsets only need recording when a character changes length
+        if pieces.iter().all(|(i, piece)| {
+            text[*i..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.len_utf8() == piece.len())
+        }) {
+            return Normalized {
+                text: Cow::Owned(pieces.into_iter().map(|(_, piece)| piece).collect()),
+                origins: None,
+            };
+        }
+        Normalized::from_pieces(
+            pieces.iter().map(|(i, piece)| (*i, piece.as_str())),
+            text.len(),
+        )
+    }
+}
+
+/// Composes text to Unicode NFC, so that a letter stored as one character
+/// (`é`) and as a base letter with a combining accent (`e\u{301}`) compare
+/// alike
+pub struct Nfc;
+
+impl Normalizer for Nfc {
+    fn name(&self) -> &'static str {
+        "nfc"
+    }
+
+    fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+        if is_nfc(text) {
+            return Normalized::identity(text);
+        }
+        // Characters only compose with the ones after them up to the next
+        // starter they don't combine with, so each run up to there is
+        // composed on its own, keeping its offset. Hangul vowel and final
+        // jamo always join the syllable before them.
+        let mut starts = Vec::new();
+        let mut previous = None;
+        for (i, c) in text.char_indices() {
+            let joins = previous.is_some_and(|previous| {
+                canonical_combining_class(c) != 0
+                    || compose(previous, c).is_some()
+                    || ('\u{1160}'..='\u{11ff}').contains(&c)
+            });
+            if !joins {
+                starts.push(i);
+            }
+            previous = Some(c);
+        }
+        starts.push(text.len());
+        let pieces: Vec<(usize, String)> = starts
+            .windows(2)
+            .map(|run| (run[0], text[run[0]..run[1]].nfc().collect()))
+            .collect();
+        Normalized::from_pieces(
+            pieces.iter().map(|(i, piece)| (*i, piece.as_str())),
+            text.len(),
+        )
+    }
+}
+
+/// Decomposes letters and drops their accents (NFD without combining
+/// marks), so that matching ignores accents: `José García` and `Jose
+/// Garcia` normalize alike. Letters without a decomposition, such as `ø`
+/// and `ł`, stay as they are.
+pub struct StripAccents;
+
+impl Normalizer for StripAccents {
+    fn name(&self) -> &'static str {
+        "strip-accents"
+    }
+
+    fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+        if text.is_ascii() {
+            return Normalized::identity(text);
+        }
+        let pieces: Vec<(usize, String)> = text
+            .char_indices()
+            .map(|(i, c)| {
+                let mut piece = String::new();
+                decompose_canonical(c, |part| {
+                    if !is_combining_mark(part) {
+                        piece.push(part);
+                    }
+                });
+                (i, piece)
+            })
+            .collect();
+        Normalized::from_pieces(
+            pieces.iter().map(|(i, piece)| (*i, piece.as_str())),
+            text.len(),
+        )
+    }
+}
+
+/// Where a match may start and end
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Anywhere, including inside words
+    #[default]
+    Any,
+    /// Only at word boundaries: not next to a letter, digit or combining
+    /// mark, in any script
+    Word,
+}
+
+/// The characters around a candidate match and whether the boundary allowed it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundaryCheck {
+    pub before: Option<char>,
+    pub after: Option<char>,
+    pub passed: bool,
+}
+
+impl Boundary {
+    /// Check the match at `span` of the original `line`
+    pub fn check(&self, line: &str, span: &Range<usize>) -> BoundaryCheck {
+        let before = line[..span.start].chars().next_back();
+        let after = line[span.end..].chars().next();
+        let passed = match self {
+            Boundary::Any => true,
+            Boundary::Word => !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char),
+        };
+        BoundaryCheck {
+            before,
+            after,
+            passed,
+        }
+    }
+}
+
+/// Whether `c` belongs to a word. Combining marks do, so that a needle
+/// does not end in the middle of a decomposed letter such as `n\u{301}`.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || matches!(c, '\u{300}'..='\u{36f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}' | '\u{fe20}'..='\u{fe2f}')
+}
+
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Boundary::Any => write!(f, "any position"),
+            Boundary::Word => write!(f, "word boundaries"),
+        }
+    }
+}
+
+/// The spans of the words in `text`, in order
+fn words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (is_word_char(c), start) {
+            (true, None) => start = Some(i),
+            (false, Some(word)) => {
+                words.push(word..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words.extend(start.map(|word| word..text.len()));
+    words
+}
+
+/// Windows of `line` where a span of `first` and a span of `second` are at
+/// most `within` words apart, in either order, as the indices of the two
+/// spans and the window from the start of the earlier to the end of the
+/// later. Adjacent words are 1 apart. A window that holds a smaller one is
+/// dropped, and so is one that overlaps a window before it.
+pub fn near_windows(
+    line: &str,
+    first: &[Range<usize>],
+    second: &[Range<usize>],
+    within: usize,
+) -> Vec<(usize, usize, Range<usize>)> {
+    let words = words(line);
+    // The first and last word a span touches
+    let word_range = |span: &Range<usize>| {
+        let first = words.partition_point(|word| word.end <= span.start);
+        let last = words.partition_point(|word| word.start < span.end);
+        (first, last.saturating_sub(1).max(first))
+    };
+
+    let mut windows = Vec::new();
+    for (i, a) in first.iter().enumerate() {
+        for (j, b) in second.iter().enumerate() {
+            let (earlier, later) = if a.start <= b.start { (a, b) } else { (b, a) };
+            if earlier.end > later.start {
+                continue;
+            }
+            let distance = word_range(later).0.saturating_sub(word_range(earlier).1);
+            if distance <= within {
+                windows.push((i, j, earlier.start..later.end));
+            }
+        }
+    }
+
+    let mut smallest: Vec<(usize, usize, Range<usize>)> = windows
+        .iter()
+        .filter(|(_, _, window)| {
+            !windows.iter().any(|(_, _, other)| {
+                other != window && window.start <= other.start && other.end <= window.end
+            })
+        })
+        .cloned()
+        .collect();
+    smallest.sort_by_key(|(_, _, window)| (window.start, window.end));
+    let mut kept: Vec<(usize, usize, Range<usize>)> = Vec::new();
+    for window in smallest {
+        if kept
+            .last()
+            .is_none_or(|(_, _, last)| last.end <= window.2.start)
+        {
+            kept.push(window);
+        }
+    }
+    kept
+}
+
+/// Levenshtein distance between `needle` and `text`, or `None` when it is
+/// more than `limit`
+fn edit_distance(needle: &[char], text: &str, limit: usize) -> Option<usize> {
+    let mut previous: Vec<usize> = (0..=needle.len()).collect();
+    let mut current = vec![0; needle.len() + 1];
+    for (i, c) in text.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &n) in needle.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(c != n))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        // The distance never drops below the best of a row
+        if current.iter().all(|&distance| distance > limit) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    let distance = previous[needle.len()];
+    (distance <= limit).then_some(distance)
+}
+
+/// Which needles are regular expressions, and how they are compiled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegexNeedles {
+    /// Every needle, rather than only those wrapped in `/…/`
+    pub all: bool,
+    pub case_insensitive: bool,
+}
+
+impl RegexNeedles {
+    /// The pattern of `needle`, when it is a regular expression
+    pub fn pattern<'n>(&self, needle: &'n str) -> Option<&'n str> {
+        match needle
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            Some(pattern) if !pattern.is_empty() => Some(pattern),
+            _ => self.all.then_some(needle),
+        }
+    }
+
+    pub fn compile(&self, pattern: &str) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(self.case_insensitive)
+            .build()
+    }
+}
+
+/// Normalization stages, a boundary rule, the edits an approximate match
+/// may need, and which needles are regular expressions
+#[derive(Default)]
+pub struct Matcher {
+    normalizers: Vec<Box<dyn Normalizer>>,
+    boundary: Boundary,
+    max_edits: usize,
+    regex: RegexNeedles,
+}
+
+/// One candidate found by the lookup stage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// Span in the normalized line
+    pub normalized: Range<usize>,
+    /// The same span in the original line
+    pub span: Range<usize>,
+    pub boundary: BoundaryCheck,
+}
+
+/// What every stage produced for one needle and one line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    /// The needle after each stage, starting with `original`
+    pub needle: Vec<(&'static str, String)>,
+    /// The line after each stage, starting with `original`
+    pub line: Vec<(&'static str, String)>,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Explanation {
+    /// The original spans that passed every stage
+    pub fn matches(&self) -> Vec<Range<usize>> {
+        self.candidates
+            .iter()
+            .filter(|candidate| candidate.boundary.passed)
+            .map(|candidate| candidate.span.clone())
+            .collect()
+    }
+}
+
+impl Matcher {
+    /// Add a normalization stage, run after the ones already added
+    pub fn with_normalizer(mut self, normalizer: impl Normalizer + 'static) -> Self {
+        self.normalizers.push(Box::new(normalizer));
+        self
+    }
+
+    pub fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    /// Also match runs of words that differ from a needle by up to
+    /// `max_edits` inserted, deleted or replaced characters; see
+    /// [`NeedleLookup::find_approximate`]
+    pub fn with_max_edits(mut self, max_edits: usize) -> Self {
+        self.max_edits = max_edits;
+        self
+    }
+
+    pub fn max_edits(&self) -> usize {
+        self.max_edits
+    }
+
+    /// Look the needles `regex` picks up as regular expressions, in the
+    /// original line: the normalization stages don't apply to them
+    pub fn with_regex_needles(mut self, regex: RegexNeedles) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    /// Names of the normalization stages, in order
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.normalizers
+            .iter()
+            .map(|normalizer| normalizer.name())
+            .collect()
+    }
+
+    /// Stage 1: run every normalization stage over `text`
+    pub fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+        self.normalizers
+            .iter()
+            .fold(Normalized::identity(text), |normalized, normalizer| {
+                normalized.then(normalizer.as_ref())
+            })
+    }
+
+    /// Stage 2: the spans of `needle` in `line`, both already normalized
+    pub fn lookup(&self, needle: &str, line: &str) -> Vec<Range<usize>> {
+        if needle.is_empty() {
+            return Vec::new();
+        }
+        line.match_indices(needle)
+            .map(|(start, _)| start..start + needle.len())
+            .collect()
+    }
+
+    /// Stages 3 and 4: map a normalized span back to `line` and check its boundary
+    pub fn candidate(
+        &self,
+        line: &str,
+        normalized_line: &Normalized,
+        span: Range<usize>,
+    ) -> Candidate {
+        let original = normalized_line.original_span(&span);
+        Candidate {
+            boundary: self.boundary.check(line, &original),
+            span: original,
+            normalized: span,
+        }
+    }
+
+    /// Spans of the original `line` where a needle, normalized with
+    /// [`normalize`](Self::normalize), matches
+    pub fn find(
+        &self,
+        normalized_needle: &str,
+        line: &str,
+        normalized_line: &Normalized,
+    ) -> Vec<Range<usize>> {
+        self.lookup(normalized_needle, &normalized_line.text)
+            .into_iter()
+            .map(|span| self.candidate(line, normalized_line, span))
+            .filter(|candidate| candidate.boundary.passed)
+            .map(|candidate| candidate.span)
+            .collect()
+    }
+
+    /// Run every stage for one needle and one line, keeping what each produced
+    pub fn explain(&self, needle: &str, line: &str) -> Explanation {
+        let trace = |text: &str| {
+            let mut stages = vec![("original", text.to_string())];
+            let mut current = Normalized::identity(text);
+            for normalizer in &self.normalizers {
+                current = current.then(normalizer.as_ref());
+                stages.push((normalizer.name(), current.text.to_string()));
+            }
+            stages
+        };
+
+        let normalized_needle = self.normalize(needle);
+        let normalized_line = self.normalize(line);
+        let candidates = self
+            .lookup(&normalized_needle.text, &normalized_line.text)
+            .into_iter()
+            .map(|span| self.candidate(line, &normalized_line, span))
+            .collect();
+
+        Explanation {
+            needle: trace(needle),
+            line: trace(line),
+            candidates,
+        }
+    }
+}
+
+/// How the lookup stage finds a list of needles in a line
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Scan the line once for each needle
+    #[default]
+    Naive,
+    /// Scan the line once for every needle, with an Aho-Corasick automaton
+    Automaton,
+}
+
+impl Strategy {
+    /// Name used in reports
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strategy::Naive => "naive",
+            Strategy::Automaton => "automaton",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The `--matcher` option: a [`Strategy`], or `auto` to [`select`] one
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MatcherChoice {
+    #[default]
+    Auto,
+    Naive,
+    Automaton,
+}
+
+impl MatcherChoice {
+    /// The strategy to search `needles` with; `haystack_bytes` is the
+    /// expected size of each document searched, when known
+    pub fn resolve(self, needles: &[(String, String)], haystack_bytes: Option<u64>) -> Strategy {
+        match self {
+            MatcherChoice::Auto => {
+                let needle_bytes = needles.iter().map(|(term, _)| term.len() as u64).sum();
+                select(needles.len() as u64, needle_bytes, haystack_bytes)
+            }
+            MatcherChoice::Naive => Strategy::Naive,
+            MatcherChoice::Automaton => Strategy::Automaton,
+        }
+    }
+}
+
+// Costs per lookup, measured in a release build over a megabyte of
+// generated English-like text in short lines, with 1 to 50,000 needles of 4
+// to 24 bytes. The naive lookup cost 850 to 1,700 ps per needle per byte of
+// text. The automaton cost 1,800 ps per byte of text up to a few hundred
+// needles and 6,000 ps above that, whatever the number of needles, plus
+// about 40 µs and 110 to 145 ns per byte of needle to build, once per
+// document. The constants take the figures for large needle lists, where
+// the choice matters most.
+
+/// Picoseconds the naive lookup spends per needle per byte of text
+pub const NAIVE_PICOS_PER_NEEDLE_BYTE: u64 = 1_000;
+/// Picoseconds the automaton spends per byte of text
+pub const AUTOMATON_PICOS_PER_BYTE: u64 = 6_000;
+/// Picoseconds spent building an automaton, however small
+pub const BUILD_PICOS: u64 = 40_000_000;
+/// Picoseconds spent building the automaton per byte of needle
+pub const BUILD_PICOS_PER_NEEDLE_BYTE: u64 = 120_000;
+/// Text assumed per document when its size is not known up front
+pub const TYPICAL_HAYSTACK_BYTES: u64 = 64 * 1024;
+
+/// The strategy expected to search a document of `haystack_bytes` (or
+/// [`TYPICAL_HAYSTACK_BYTES`]) fastest for `needle_count` needles totalling
+/// `needle_bytes`
+pub fn select(needle_count: u64, needle_bytes: u64, haystack_bytes: Option<u64>) -> Strategy {
+    let haystack = haystack_bytes.unwrap_or(TYPICAL_HAYSTACK_BYTES);
+    let naive = needle_count
+        .saturating_mul(haystack)
+        .saturating_mul(NAIVE_PICOS_PER_NEEDLE_BYTE);
+    let automaton = haystack
+        .saturating_mul(AUTOMATON_PICOS_PER_BYTE)
+        .saturating_add(BUILD_PICOS)
+        .saturating_add(needle_bytes.saturating_mul(BUILD_PICOS_PER_NEEDLE_BYTE));
+    if automaton < naive {
+        Strategy::Automaton
+    } else {
+        Strategy::Naive
+    }
+}
+
+/// Needles normalized once and looked up together with one [`Strategy`]
+pub struct NeedleLookup<'m> {
+    matcher: &'m Matcher,
+    /// Distinct normalized needles, leaving out empty ones
+    patterns: Vec<String>,
+    /// Indices of the needles that normalize to each pattern
+    needles: Vec<Vec<usize>>,
+    /// Set for [`Strategy::Automaton`]
+    automaton: Option<AhoCorasick>,
+    /// The needles that are regular expressions, by index, leaving out
+    /// ones that don't compile
+    regexes: Vec<(usize, Regex)>,
+}
+
+impl Matcher {
+    /// Normalize `needles` once, to look them up in many lines with `strategy`
+    pub fn needle_lookup<'n>(
+        &self,
+        needles: impl IntoIterator<Item = &'n str>,
+        strategy: Strategy,
+    ) -> NeedleLookup<'_> {
+        let mut patterns = Vec::new();
+        let mut pattern_needles: Vec<Vec<usize>> = Vec::new();
+        let mut indices = HashMap::new();
+        let mut regexes = Vec::new();
+        for (index, needle) in needles.into_iter().enumerate() {
+            if let Some(pattern) = self.regex.pattern(needle) {
+                regexes.extend(self.regex.compile(pattern).ok().map(|regex| (index, regex)));
+                continue;
+            }
+            let normalized = self.normalize(needle).text.into_owned();
+            if normalized.is_empty() {
+                continue;
+            }
+            let pattern = *indices.entry(normalized.clone()).or_insert_with(|| {
+                patterns.push(normalized);
+                pattern_needles.push(Vec::new());
+                patterns.len() - 1
+            });
+            pattern_needles[pattern].push(index);
+        }
+
+        // An automaton too large to build leaves the naive lookup, which
+        // finds the same spans
+        let automaton = match strategy {
+            Strategy::Naive => None,
+            Strategy::Automaton => AhoCorasick::builder()
+                .match_kind(MatchKind::Standard)
+                .build(&patterns)
+                .ok(),
+        };
+
+        NeedleLookup {
+            matcher: self,
+            patterns,
+            needles: pattern_needles,
+            automaton,
+            regexes,
+        }
+    }
+}
+
+impl NeedleLookup<'_> {
+    /// The strategy the set looks needles up with
+    pub fn strategy(&self) -> Strategy {
+        match self.automaton {
+            Some(_) => Strategy::Automaton,
+            None => Strategy::Naive,
+        }
+    }
+
+    /// Whether needle `index` is a regular expression
+    pub fn is_regex(&self, index: usize) -> bool {
+        self.regexes.iter().any(|(needle, _)| *needle == index)
+    }
+
+    /// The index of each needle that matches the original `line`, with the
+    /// span, ordered by needle and then position: for every needle, what
+    /// [`Matcher::find`] gives, or for a regular expression, its
+    /// non-empty matches
+    pub fn find(&self, line: &str, normalized_line: &Normalized) -> Vec<(usize, Range<usize>)> {
+        let mut found = Vec::new();
+        for (pattern, spans) in self.lookup(&normalized_line.text) {
+            let spans: Vec<Range<usize>> = spans
+                .into_iter()
+                .map(|span| self.matcher.candidate(line, normalized_line, span))
+                .filter(|candidate| candidate.boundary.passed)
+                .map(|candidate| candidate.span)
+                .collect();
+            for &needle in &self.needles[pattern] {
+                found.extend(spans.iter().map(|span| (needle, span.clone())));
+            }
+        }
+        for (needle, regex) in &self.regexes {
+            let spans = regex
+                .find_iter(line)
+                .map(|found| found.range())
+                .filter(|span| !span.is_empty());
+            found.extend(
+                spans
+                    .filter(|span| self.matcher.boundary.check(line, span).passed)
+                    .map(|span| (*needle, span)),
+            );
+        }
+        // Stable, so each needle's spans stay in order
+        found.sort_by_key(|(needle, _)| *needle);
+        found
+    }
+
+    /// The index of each needle that differs from a run of whole words in
+    /// the original `line` by 1 to [`Matcher::max_edits`] edits, with the
+    /// span and the number of edits, ordered like [`find`](Self::find).
+    /// Each needle is compared with runs of as many words as it has, and
+    /// only those within `max_edits` characters of its length. Needles of
+    /// no more than twice `max_edits` characters only match exactly, since
+    /// that many edits would turn them into most short words.
+    pub fn find_approximate(
+        &self,
+        line: &str,
+        normalized_line: &Normalized,
+    ) -> Vec<(usize, Range<usize>, usize)> {
+        let max_edits = self.matcher.max_edits;
+        if max_edits == 0 {
+            return Vec::new();
+        }
+        let text = &normalized_line.text;
+        let line_words = words(text);
+        let mut found = Vec::new();
+        for (pattern, needle) in self.patterns.iter().enumerate() {
+            let needle_chars: Vec<char> = needle.chars().collect();
+            let width = words(needle).len().max(1);
+            if needle_chars.len() <= 2 * max_edits || line_words.len() < width {
+                continue;
+            }
+            // End of the last match, so that matches don't overlap
+            let mut end = 0;
+            for window in line_words.windows(width) {
+                let span = window[0].start..window[width - 1].end;
+                let candidate = &text[span.clone()];
+                if span.start < end
+                    || candidate.chars().count().abs_diff(needle_chars.len()) > max_edits
+                {
+                    continue;
+                }
+                let Some(distance @ 1..) = edit_distance(&needle_chars, candidate, max_edits)
+                else {
+                    continue;
+                };
+                let candidate = self.matcher.candidate(line, normalized_line, span);
+                if !candidate.boundary.passed {
+                    continue;
+                }
+                end = candidate.normalized.end;
+                for &index in &self.needles[pattern] {
+                    found.push((index, candidate.span.clone(), distance));
+                }
+            }
+        }
+        // Stable, so each needle's spans stay in order
+        found.sort_by_key(|(needle, _, _)| *needle);
+        found
+    }
+
+    /// Stage 2 for every pattern: the ones found in the normalized `line`,
+    /// with their spans
+    fn lookup(&self, line: &str) -> Vec<(usize, Vec<Range<usize>>)> {
+        let Some(automaton) = &self.automaton else {
+            return (0..self.patterns.len())
+                .map(|pattern| (pattern, self.matcher.lookup(&self.patterns[pattern], line)))
+                .filter(|(_, spans)| !spans.is_empty())
+                .collect();
+        };
+
+        // Every match of every pattern, overlapping ones included; keeping
+        // each pattern's leftmost matches that don't overlap the one before
+        // gives what `str::match_indices` does
+        let mut spans: HashMap<usize, Vec<Range<usize>>> = HashMap::new();
+        for found in automaton.find_overlapping_iter(line) {
+            spans
+                .entry(found.pattern().as_usize())
+                .or_default()
+                .push(found.range());
+        }
+        spans
+            .into_iter()
+            .map(|(pattern, mut spans)| {
+                spans.sort_by_key(|span| span.start);
+                let mut end = 0;
+                spans.retain(|span| {
+                    let keep = span.start >= end;
+                    if keep {
+                        end = span.end;
+                    }
+                    keep
+                });
+                (pattern, spans)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lowercases needles and lines, the simplest normalizer that changes
+    /// text; searches fold case with [`CaseFold`]
+    struct Lowercase;
+
+    impl Normalizer for Lowercase {
+        fn name(&self) -> &'static str {
+            "lowercase"
+        }
+
+        fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+            if text.chars().all(|c| c.to_lowercase().eq([c])) {
+                return Normalized::identity(text);
+            }
+            let pieces: Vec<(usize, String)> = text
+                .char_indices()
+                .map(|(i, c)| (i, c.to_lowercase().collect()))
+                .collect();
+            // Offsets only need recording when a character changes length, as
+            // `İ` and `ẞ` do
+            if pieces.iter().all(|(i, piece)| {
+                text[*i..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.len_utf8() == piece.len())
+            }) {
+                return Normalized {
+                    text: Cow::Owned(pieces.into_iter().map(|(_, piece)| piece).collect()),
+                    origins: None,
+                };
+            }
+            Normalized::from_pieces(
+                pieces.iter().map(|(i, piece)| (*i, piece.as_str())),
+                text.len(),
+            )
+        }
+    }
+
+    /// Drops hyphens
+    struct NoHyphens;
+
+    impl Normalizer for NoHyphens {
+        fn name(&self) -> &'static str {
+            "no-hyphens"
+        }
+
+        fn normalize<'a>(&self, text: &'a str) -> Normalized<'a> {
+            let kept = text
+                .char_indices()
+                .filter(|(_, c)| *c != '-')
+                .map(|(i, c)| (i, &text[i..i + c.len_utf8()]));
+            Normalized::from_pieces(kept, text.len())
+        }
+    }
+
+    fn find(matcher: &Matcher, needle: &str, line: &str) -> Vec<Range<usize>> {
+        let needle = matcher.normalize(needle);
+        matcher.find(&needle.text, line, &matcher.normalize(line))
+    }
+
+    #[test]
+    fn test_default_matcher_is_exact_substring_search() {
+        let matcher = Matcher::default();
+        assert_eq!(
+            find(&matcher, "Alice", "Alice and Alice"),
+            vec![0..5, 10..15]
+        );
+        assert!(find(&matcher, "alice", "Alice").is_empty());
+        assert_eq!(find(&matcher, "Al", "Alice"), vec![0..2]);
+        assert!(find(&matcher, "", "Alice").is_empty());
+        assert!(matches!(matcher.normalize("Alice").text, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_lowercase_ignores_case() {
+        let matcher = Matcher::default().with_normalizer(Lowercase);
+        assert_eq!(
+            find(&matcher, "alice", "ALICE and Alice"),
+            vec![0..5, 10..15]
+        );
+        assert_eq!(find(&matcher, "ZOË", "zoë"), vec![0..4]);
+        assert!(matches!(
+            Lowercase.normalize("already lower").text,
+            Cow::Borrowed(_)
+        ));
+        // Lowercasing `ẞ` takes a byte fewer, so the span maps back
+        assert_eq!(find(&matcher, "ßa", "GROẞARTIG"), vec![3..7]);
+    }
+
+    #[test]
+    fn test_case_fold_handles_dotted_i_and_sharp_s() {
+        let matcher = Matcher::default().with_normalizer(CaseFold(CaseFolding::Unicode));
+        assert_eq!(
+            find(&matcher, "istanbul", "İSTANBUL, İstanbul"),
+            vec![0..9, 11..20]
+        );
+        assert_eq!(find(&matcher, "İstanbul", "istanbul"), vec![0..8]);
+        assert_eq!(find(&matcher, "straße", "HAUPTSTRASSE 5"), vec![5..12]);
+        assert_eq!(
+            find(&matcher, "STRASSE", "Hauptstraße, HAUPTSTRAẞE"),
+            vec![5..12, 19..27]
+        );
+        assert_eq!(find(&matcher, "office", "the oﬃce"), vec![4..10]);
+        assert_eq!(find(&matcher, "ΟΔΥΣΣΕΥΣ", "Οδυσσευς"), vec![0..16]);
+        assert!(matches!(
+            CaseFold(CaseFolding::Unicode)
+                .normalize("already folded")
+                .text,
+            Cow::Borrowed(_)
+        ));
+
+        // Turkish: I and ı are one letter, İ and i another
+        let turkic = Matcher::default().with_normalizer(CaseFold(CaseFolding::Turkic));
+        assert_eq!(find(&turkic, "ılgaz", "ILGAZ"), vec![0..5]);
+        assert_eq!(find(&turkic, "istanbul", "İSTANBUL"), vec![0..9]);
+        assert!(find(&turkic, "istanbul", "ISTANBUL").is_empty());
+        assert_eq!(find(&matcher, "istanbul", "ISTANBUL"), vec![0..8]);
+
+        // ASCII only
+        let simple = Matcher::default().with_normalizer(CaseFold(CaseFolding::Simple));
+        assert_eq!(find(&simple, "strasse", "STRASSE"), vec![0..7]);
+        assert!(find(&simple, "straße", "STRASSE").is_empty());
+        assert!(find(&simple, "émile", "ÉMILE").is_empty());
+    }
+
+    #[test]
+    fn test_nfc_matches_composed_and_decomposed_text() {
+        let matcher = Matcher::default().with_normalizer(Nfc);
+        // Needle decomposed, line composed, and the other way round
+        assert_eq!(
+            find(&matcher, "Jose\u{301} Garci\u{301}a", "Dear José García,"),
+            vec![5..18]
+        );
+        assert_eq!(
+            find(&matcher, "Zoë", "Zoe\u{308} and Zoë"),
+            vec![0..5, 10..14]
+        );
+        // Hangul jamo compose into syllables
+        assert_eq!(
+            find(&matcher, "한", "\u{1112}\u{1161}\u{11ab}국"),
+            vec![0..9]
+        );
+        assert!(find(&Matcher::default(), "José", "Jose\u{301}").is_empty());
+        assert!(matches!(Nfc.normalize("José").text, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_collapse_whitespace_matches_any_spacing() {
+        let matcher = Matcher::default().with_normalizer(CollapseWhitespace);
+        assert_eq!(
+            find(&matcher, "Acme Corporation", "Acme\u{a0}Corporation"),
+            vec![0..17]
+        );
+        assert_eq!(
+            find(&matcher, "Acme Corporation", "To Acme   Corporation."),
+            vec![3..21]
+        );
+        assert_eq!(
+            find(
+                &matcher,
+                "Acme Corporation",
+                "Acme\tCorporation, Acme \t Corporation"
+            ),
+            vec![0..16, 18..36]
+        );
+        // Needles pasted with odd spacing match as well
+        assert_eq!(
+            find(&matcher, "Acme  Corporation", "Acme Corporation"),
+            vec![0..16]
+        );
+        assert_eq!(
+            find(
+                &matcher,
+                "Acme\u{202f}\u{a0}Corporation",
+                "Acme Corporation"
+            ),
+            vec![0..16]
+        );
+        assert!(find(&matcher, "Acme Corporation", "AcmeCorporation").is_empty());
+        assert!(matches!(
+            CollapseWhitespace.normalize("one space each").text,
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_strip_accents_matches_either_way() {
+        let matcher = Matcher::default()
+            .with_normalizer(Lowercase)
+            .with_normalizer(StripAccents)
+            .with_boundary(Boundary::Word);
+        assert_eq!(
+            find(&matcher, "José García", "Signed: Jose Garcia"),
+            vec![8..19]
+        );
+        assert_eq!(
+            find(&matcher, "Francois Lefevre", "FRANÇOIS LEFÈVRE"),
+            vec![0..18]
+        );
+        assert_eq!(
+            find(&matcher, "Zoë Müller", "Zoe Muller, Zoë Müller"),
+            vec![0..10, 12..24]
+        );
+        // Decomposed accents belong to the match they follow
+        assert_eq!(
+            find(&matcher, "Dvorak", "Dvor\u{30c}a\u{301}k"),
+            vec![0..10]
+        );
+        assert_eq!(find(&matcher, "Søren", "Soren"), Vec::<Range<usize>>::new());
+        assert!(matches!(
+            StripAccents.normalize("plain ascii").text,
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let matcher = Matcher::default().with_boundary(Boundary::Word);
+        assert_eq!(find(&matcher, "Al", "Al, Alice"), vec![0..2]);
+
+        // Letters of any script, and marks combining with the last letter
+        assert_eq!(find(&matcher, "Ann", "Annë, ΣAnn Ann"), vec![13..16]);
+        assert!(find(&matcher, "Ann", "Ann\u{301}").is_empty());
+        assert_eq!(find(&matcher, "Ann", "(Ann.) Ann_"), vec![1..4, 7..10]);
+
+        let check = Boundary::Word.check("xJosé!", &(1..6));
+        assert_eq!(
+            check,
+            BoundaryCheck {
+                before: Some('x'),
+                after: Some('!'),
+                passed: false
+            }
+        );
+        assert!(Boundary::Any.check("xJosé!", &(1..6)).passed);
+    }
+
+    #[test]
+    fn test_spans_map_back_through_stages_that_change_length() {
+        let matcher = Matcher::default()
+            .with_normalizer(NoHyphens)
+            .with_normalizer(Lowercase);
+
+        // "İ" lowercases to two characters, the hyphen disappears
+        let line = "Dear İ JEAN-PAUL!";
+        let spans = find(&matcher, "jeanpaul", line);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(&line[spans[0].clone()], "JEAN-PAUL");
+
+        let spans = find(&matcher, "i̇", line);
+        assert_eq!(&line[spans[0].clone()], "İ");
+    }
+
+    #[test]
+    fn test_strategies_find_what_find_does() {
+        let needles = ["ana", "Ana", "anana", "", "-", "ana", "nan", "b"];
+        let lines = ["Banana ananas", "ANA-NA, bananA", "", "ß-ana-"];
+        for matcher in [
+            Matcher::default(),
+            Matcher::default()
+                .with_normalizer(NoHyphens)
+                .with_normalizer(Lowercase),
+            Matcher::default()
+                .with_normalizer(Lowercase)
+                .with_boundary(Boundary::Word),
+        ] {
+            for line in lines {
+                let expected: Vec<(usize, Range<usize>)> = needles
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, needle)| {
+                        find(&matcher, needle, line)
+                            .into_iter()
+                            .map(move |span| (index, span))
+                    })
+                    .collect();
+                for strategy in [Strategy::Naive, Strategy::Automaton] {
+                    let lookup = matcher.needle_lookup(needles, strategy);
+                    assert_eq!(lookup.strategy(), strategy);
+                    assert_eq!(
+                        lookup.find(line, &matcher.normalize(line)),
+                        expected,
+                        "{:?} in {:?}",
+                        strategy,
+                        line
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_regex_needles() {
+        let regex = RegexNeedles {
+            all: false,
+            case_insensitive: true,
+        };
+        let matcher = Matcher::default()
+            .with_normalizer(Lowercase)
+            .with_boundary(Boundary::Word)
+            .with_regex_needles(regex);
+        let found = |needles: &[&str], line: &str| {
+            let lookup = matcher.needle_lookup(needles.iter().copied(), Strategy::Automaton);
+            lookup
+                .find(line, &matcher.normalize(line))
+                .into_iter()
+                .map(|(needle, span)| (needle, line[span].to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        // Alternation and an optional letter, in any case
+        let names = ["/Jon?athan/", "/Sm(i|y)th/", "Smith"];
+        assert_eq!(
+            found(&names, "JONATHAN Smyth met Joathan Smith"),
+            [
+                (0, "JONATHAN"),
+                (0, "Joathan"),
+                (1, "Smyth"),
+                (1, "Smith"),
+                (2, "Smith")
+            ]
+            .map(|(needle, text)| (needle, text.to_string()))
+        );
+        // Anchors hold at the ends of the line
+        assert_eq!(found(&["/^Ann/"], "Ann and Ann"), [(0, "Ann".to_string())]);
+        assert!(found(&["/Ann$/"], "Ann and Anne").is_empty());
+        // Unicode classes, with word boundaries checked around the match
+        assert_eq!(
+            found(&[r"/\p{Lu}\p{Ll}+ Müller/"], "Zoë Müller, 3Zoë Müller"),
+            [(0, "Zoë Müller".to_string())]
+        );
+        // Invalid patterns are left out; without `/…/`, needles are literal
+        assert!(found(&["/Jon(athan/", "Jon?athan"], "Jonathan").is_empty());
+        assert!(RegexNeedles::default().compile("Jon(athan").is_err());
+        assert_eq!(
+            RegexNeedles {
+                all: true,
+                ..RegexNeedles::default()
+            }
+            .pattern("a|b"),
+            Some("a|b")
+        );
+        assert_eq!(RegexNeedles::default().pattern("/"), None);
+        assert!(matcher
+            .needle_lookup(["/x/", "y"], Strategy::Naive)
+            .is_regex(0));
+    }
+
+    #[test]
+    fn test_select_weighs_needles_against_text() {
+        assert_eq!(select(1, 10, Some(1 << 20)), Strategy::Naive);
+        assert_eq!(select(64, 1_000, Some(1 << 20)), Strategy::Automaton);
+        // A small document doesn't pay for building an automaton
+        assert_eq!(select(10, 120, Some(1_000)), Strategy::Naive);
+        assert_eq!(select(50_000, 900_000, None), Strategy::Automaton);
+
+        let needles: Vec<(String, String)> = (0..100)
+            .map(|i| (format!("name {}", i), String::new()))
+            .collect();
+        assert_eq!(
+            MatcherChoice::Auto.resolve(&needles, None),
+            Strategy::Automaton
+        );
+        assert_eq!(
+            MatcherChoice::Naive.resolve(&needles, None),
+            Strategy::Naive
+        );
+        assert_eq!(
+            MatcherChoice::Automaton.resolve(&needles[..1], Some(10)),
+            Strategy::Automaton
+        );
+    }
+
+    #[test]
+    fn test_explain_records_every_stage() {
+        let matcher = Matcher::default()
+            .with_normalizer(Lowercase)
+            .with_boundary(Boundary::Word);
+        let explanation = matcher.explain("José", "JOSÉ and Josése");
+
+        assert_eq!(
+            explanation.needle,
+            vec![
+                ("original", "José".to_string()),
+                ("lowercase", "josé".to_string())
+            ]
+        );
+        assert_eq!(explanation.line[1].1, "josé and josése");
+        assert_eq!(explanation.candidates.len(), 2);
+        assert!(explanation.candidates[0].boundary.passed);
+        assert_eq!(explanation.candidates[1].boundary.after, Some('s'));
+        assert_eq!(explanation.matches(), vec![0..5]);
+    }
+
+    #[test]
+    fn test_near_windows_count_words_between_parts() {
+        let spans = |line: &str, word: &str| {
+            line.match_indices(word)
+                .map(|(i, _)| i..i + word.len())
+                .collect::<Vec<_>>()
+        };
+        let near = |line: &str, within| {
+            near_windows(line, &spans(line, "Smith"), &spans(line, "invoice"), within)
+                .into_iter()
+                .map(|(_, _, window)| line[window].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Five words apart: four words in between
+        let line = "Smith, who sent us the invoice.";
+        assert_eq!(near(line, 5), ["Smith, who sent us the invoice"]);
+        assert!(near(line, 4).is_empty());
+        let line = "Smith, who then sent us the invoice.";
+        assert!(near(line, 5).is_empty());
+        assert_eq!(near(line, 6), ["Smith, who then sent us the invoice"]);
+
+        // Either order, adjacent words, and the closest pairs only
+        assert_eq!(near("invoice Smith", 1), ["invoice Smith"]);
+        assert_eq!(
+            near("Smith and Smith invoice and invoice Smith", 1),
+            ["Smith invoice", "invoice Smith"]
+        );
+        assert!(near("Smith", 5).is_empty());
+    }
+
+    #[test]
+    fn test_approximate_matches_within_max_edits() {
+        let approximate = |max_edits: usize, needle: &str, line: &str| {
+            let matcher = Matcher::default()
+                .with_normalizer(Lowercase)
+                .with_max_edits(max_edits);
+            let normalized = matcher.normalize(line);
+            matcher
+                .needle_lookup([needle], Strategy::Naive)
+                .find_approximate(line, &normalized)
+                .into_iter()
+                .map(|(_, span, distance)| (line[span].to_string(), distance))
+                .collect::<Vec<_>>()
+        };
+
+        let line = "Mr Jonhson met Dr Jhonson, and Johnson too";
+        assert!(approximate(0, "Johnson", line).is_empty());
+        // A swapped pair of letters takes two edits; exact matches are left
+        // to `find`
+        assert!(approximate(1, "Johnson", line).is_empty());
+        assert_eq!(
+            approximate(1, "Johnson", "Jonson and Johnsen"),
+            vec![("Jonson".to_string(), 1), ("Johnsen".to_string(), 1)]
+        );
+        assert_eq!(
+            approximate(2, "Johnson", line),
+            vec![("Jonhson".to_string(), 2), ("Jhonson".to_string(), 2)]
+        );
+        assert_eq!(
+            approximate(1, "Mary Smith", "MARY SMYTH, Mary-Smith"),
+            vec![("MARY SMYTH".to_string(), 1), ("Mary-Smith".to_string(), 1)]
+        );
+        // Needles too short for the edits allowed
+        assert!(approximate(2, "Lee", "Leo and Lea").is_empty());
+        assert_eq!(edit_distance(&['a', 'b', 'c'], "xyz", 2), None);
+        assert_eq!(edit_distance(&['k', 'i', 't'], "kite", 2), Some(1));
+    }
+}
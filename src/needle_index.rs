@@ -0,0 +1,420 @@
+//! Pre-compiled needle sets (`.nidx` files).
+//!
+//! `docsearcher needles compile` parses and validates a needles file once and
+//! writes the result, together with the matching options it was compiled
+//! for, in a compact binary format that loads without re-parsing CSV. That
+//! is all it saves: the lookup automaton cannot be stored, so it is built
+//! from the loaded needles on every search as it is for a CSV file.
+//!
+//! Layout, all integers little-endian:
+//!
+//! ```text
+//! magic      b"NIDX"
+//! version    u16
+//! options    u16   bit 0 = case sensitive, bit 1 = whole word,
+//!                  bit 2 = ignore accents, bit 3 = no normalize,
+//!                  bit 4 = regex needles, bit 5 = Turkic case folding,
+//!                  bit 6 = simple case folding
+//! count      u64
+//! count × (term, metadata, weight): term and metadata each a u32 byte
+//!            length followed by UTF-8, weight an i64, 0 for none
+//! checksum   u64   FNV-1a of every preceding byte
+//! ```
+//!
+//! Files with another version, a bad checksum or trailing/missing bytes are
+//! rejected rather than partially loaded. Version 1 files only had bits 0
+//! and 1, and set no others for options added since, and version 2 files
+//! had no weights, so both are refused rather than read as if those options
+//! were off and every needle weighed 0.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    matcher::CaseFolding,
+    types::{MatchOptions, NeedleWeights, OwnedNeedle},
+    utils::fnv1a,
+};
+
+/// Leading bytes of every compiled needle file
+pub const MAGIC: &[u8; 4] = b"NIDX";
+
+/// Version written by this build; other versions are refused
+pub const FORMAT_VERSION: u16 = 3;
+
+/// Conventional extension of compiled needle files
+pub const EXTENSION: &str = "nidx";
+
+const CASE_SENSITIVE: u16 = 1 << 0;
+const WHOLE_WORD: u16 = 1 << 1;
+const IGNORE_ACCENTS: u16 = 1 << 2;
+/// Set when compiled for `--no-normalize`
+const NO_NORMALIZE: u16 = 1 << 3;
+const REGEX_NEEDLES: u16 = 1 << 4;
+/// At most one of the two is set; neither is `--case-fold unicode`
+const CASE_FOLD_TURKIC: u16 = 1 << 5;
+const CASE_FOLD_SIMPLE: u16 = 1 << 6;
+
+/// A needle set together with the options it was compiled for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledNeedles {
+    pub options: MatchOptions,
+    pub needles: Vec<OwnedNeedle>,
+    /// The weights of the needles file's third column; a needle without one
+    /// is left out
+    pub weights: NeedleWeights,
+}
+
+impl CompiledNeedles {
+    pub fn new(needles: Vec<OwnedNeedle>, options: MatchOptions) -> Self {
+        Self {
+            options,
+            needles,
+            weights: NeedleWeights::new(),
+        }
+    }
+
+    /// These needles, weighed as `weights` says
+    pub fn with_weights(self, weights: NeedleWeights) -> Self {
+        Self { weights, ..self }
+    }
+
+    /// Encode into the `.nidx` format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&encode_options(self.options).to_le_bytes());
+        bytes.extend_from_slice(&(self.needles.len() as u64).to_le_bytes());
+
+        for needle in &self.needles {
+            for field in [&needle.0, &needle.1] {
+                bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(field.as_bytes());
+            }
+            let weight = self.weights.get(needle).copied().unwrap_or_default();
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a `.nidx` file, failing on any version mismatch or corruption
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(anyhow!("Not a compiled needle file"));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+        let version = u16::from_le_bytes(reader.take()?);
+        if version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "Compiled needle file has format version {}, this build reads version {}; recompile it",
+                version,
+                FORMAT_VERSION
+            ));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len().saturating_sub(8));
+        if body.len() < reader.pos || u64::from_le_bytes(checksum.try_into()?) != fnv1a(body) {
+            return Err(anyhow!("Compiled needle file is truncated or corrupt"));
+        }
+        let mut reader = Reader {
+            bytes: body,
+            pos: reader.pos,
+        };
+
+        let options = decode_options(u16::from_le_bytes(reader.take()?))?;
+        let count = u64::from_le_bytes(reader.take()?);
+
+        // Every needle takes at least sixteen bytes, which bounds the
+        // allocation for a corrupt count.
+        let mut needles = Vec::with_capacity((count as usize).min(body.len() / 16));
+        let mut weights = NeedleWeights::new();
+        for _ in 0..count {
+            let needle = (reader.string()?, reader.string()?);
+            let weight = i64::from_le_bytes(reader.take()?);
+            if weight != 0 {
+                weights.entry(needle.clone()).or_insert(weight);
+            }
+            needles.push(needle);
+        }
+
+        if reader.pos != body.len() {
+            return Err(anyhow!("Compiled needle file is truncated or corrupt"));
+        }
+
+        Ok(Self {
+            options,
+            needles,
+            weights,
+        })
+    }
+
+    /// Write to `path`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Read from `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// Refuse a set compiled for different options than `requested`, unless `force`
+    pub fn check_options(&self, requested: MatchOptions, force: bool) -> Result<()> {
+        if self.options == requested || force {
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "Needles were compiled with {} but this search uses {}; recompile them or pass --force",
+            self.options,
+            requested
+        ))
+    }
+}
+
+/// Whether `path` holds a compiled needle set, judged by its magic bytes
+pub fn is_compiled(path: &Path) -> bool {
+    crate::utils::read_file_header(path, MAGIC.len()).is_ok_and(|header| header == MAGIC)
+}
+
+fn encode_options(options: MatchOptions) -> u16 {
+    let mut flags = 0;
+    if options.case_sensitive {
+        flags |= CASE_SENSITIVE;
+    }
+    if options.whole_word {
+        flags |= WHOLE_WORD;
+    }
+    if options.ignore_accents {
+        flags |= IGNORE_ACCENTS;
+    }
+    if !options.normalize {
+        flags |= NO_NORMALIZE;
+    }
+    if options.regex_needles {
+        flags |= REGEX_NEEDLES;
+    }
+    flags |= match options.case_fold {
+        CaseFolding::Unicode => 0,
+        CaseFolding::Turkic => CASE_FOLD_TURKIC,
+        CaseFolding::Simple => CASE_FOLD_SIMPLE,
+    };
+    flags
+}
+
+fn decode_options(flags: u16) -> Result<MatchOptions> {
+    let known = CASE_SENSITIVE
+        | WHOLE_WORD
+        | IGNORE_ACCENTS
+        | NO_NORMALIZE
+        | REGEX_NEEDLES
+        | CASE_FOLD_TURKIC
+        | CASE_FOLD_SIMPLE;
+    if flags & !known != 0
+        || flags & (CASE_FOLD_TURKIC | CASE_FOLD_SIMPLE) == CASE_FOLD_TURKIC | CASE_FOLD_SIMPLE
+    {
+        return Err(anyhow!(
+            "Compiled needle file uses unknown options {:#06x}",
+            flags
+        ));
+    }
+
+    Ok(MatchOptions {
+        case_sensitive: flags & CASE_SENSITIVE != 0,
+        whole_word: flags & WHOLE_WORD != 0,
+        ignore_accents: flags & IGNORE_ACCENTS != 0,
+        normalize: flags & NO_NORMALIZE == 0,
+        regex_needles: flags & REGEX_NEEDLES != 0,
+        case_fold: match (flags & CASE_FOLD_TURKIC != 0, flags & CASE_FOLD_SIMPLE != 0) {
+            (true, _) => CaseFolding::Turkic,
+            (_, true) => CaseFolding::Simple,
+            _ => CaseFolding::Unicode,
+        },
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.slice(N)?;
+        Ok(bytes.try_into()?)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = u32::from_le_bytes(self.take()?) as usize;
+        let bytes = self.slice(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| anyhow!("Compiled needle file contains invalid UTF-8"))
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("Compiled needle file is truncated or corrupt"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures,
+        parsers::search_docx_mem,
+        search::{results_from_occurrences, search_text, weigh_results},
+        types::ExtractOptions,
+        utils::{needle_weights, read_needles_from_mem},
+    };
+
+    const NEEDLES: &str =
+        "Alice Johnson,alice@company.com\nBob Smith,id=7;note=\"ext; 4410\"\nZoë Ångström,id=9\n";
+
+    fn compiled() -> CompiledNeedles {
+        let needles = read_needles_from_mem(NEEDLES.as_bytes(), None).unwrap();
+        CompiledNeedles::new(
+            needles,
+            MatchOptions {
+                case_sensitive: true,
+                ..MatchOptions::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = compiled();
+        assert_eq!(
+            CompiledNeedles::from_bytes(&original.to_bytes()).unwrap(),
+            original
+        );
+
+        for case_fold in [CaseFolding::Turkic, CaseFolding::Simple] {
+            let folded = CompiledNeedles::new(
+                original.needles.clone(),
+                MatchOptions {
+                    case_fold,
+                    ..MatchOptions::default()
+                },
+            );
+            assert_eq!(
+                CompiledNeedles::from_bytes(&folded.to_bytes()).unwrap(),
+                folded
+            );
+        }
+    }
+
+    #[test]
+    fn test_weights_survive_the_round_trip_and_order_results() {
+        let content = "Alice Johnson,id=1\nBob Smith,id=2,10\nCarol Diaz,id=3,-1\n";
+        let needles = read_needles_from_mem(content.as_bytes(), None).unwrap();
+        let weights = needle_weights(content, None);
+        let compiled = CompiledNeedles::new(needles.clone(), MatchOptions::default())
+            .with_weights(weights.clone());
+        let loaded = CompiledNeedles::from_bytes(&compiled.to_bytes()).unwrap();
+        assert_eq!(loaded, compiled);
+
+        let occurrences = search_text(
+            &needles,
+            "Carol Diaz, Alice Johnson and Bob Smith",
+            ExtractOptions::default(),
+        );
+        let terms = |weights: &NeedleWeights| -> Vec<String> {
+            weigh_results(results_from_occurrences(&occurrences), weights)
+                .into_iter()
+                .map(|result| result.term)
+                .collect()
+        };
+        assert_eq!(
+            terms(&loaded.weights),
+            ["Bob Smith", "Alice Johnson", "Carol Diaz"]
+        );
+        assert_eq!(terms(&loaded.weights), terms(&weights));
+    }
+
+    #[test]
+    fn test_loaded_needles_match_like_fresh_ones() {
+        let fresh = read_needles_from_mem(NEEDLES.as_bytes(), None).unwrap();
+        let loaded = CompiledNeedles::from_bytes(&compiled().to_bytes())
+            .unwrap()
+            .needles;
+        let document = fixtures::docx(&["Meeting with Alice Johnson", "Zoë Ångström, Bob Smi th"]);
+
+        let expected = search_docx_mem(&fresh, &document).unwrap();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(search_docx_mem(&loaded, &document).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let mut bytes = compiled().to_bytes();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let err = CompiledNeedles::from_bytes(&bytes).unwrap_err().to_string();
+        assert!(err.contains("format version"), "{}", err);
+
+        // Files from before the accent, normalization, regex and case
+        // folding options, and from before weights
+        for version in [1u16, 2] {
+            bytes[4..6].copy_from_slice(&version.to_le_bytes());
+            let err = CompiledNeedles::from_bytes(&bytes).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("format version {}", version)),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_every_truncation_and_corruption() {
+        let bytes = compiled().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(
+                CompiledNeedles::from_bytes(&bytes[..len]).is_err(),
+                "truncated to {}",
+                len
+            );
+        }
+
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 0x01;
+        assert!(CompiledNeedles::from_bytes(&flipped).is_err());
+
+        let mut extended = bytes;
+        extended.push(0);
+        assert!(CompiledNeedles::from_bytes(&extended).is_err());
+    }
+
+    #[test]
+    fn test_check_options() {
+        let needles = compiled();
+        let requested = MatchOptions::default();
+        assert!(needles.check_options(needles.options, false).is_ok());
+        assert!(needles.check_options(requested, true).is_ok());
+
+        let err = needles
+            .check_options(requested, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("--force"), "{}", err);
+    }
+}
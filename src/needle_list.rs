@@ -0,0 +1,498 @@
+//! Whole needle lists, for the `docsearcher needles` commands.
+//!
+//! A list is read from a delimited needles file, a JSON array of
+//! `{"term", "metadata"}` objects, or a compiled `.nidx` file, and written
+//! back as delimited text or JSON. Written files read back through
+//! [`read_needles_from_file`](crate::utils::read_needles_from_file) to the
+//! same needles: fields are quoted when the delimiter, a quote, a leading
+//! `#` or surrounding spaces would otherwise change them, and empty
+//! metadata is written `""`.
+//!
+//! [`merge`] combines lists, keeping one needle per term as the
+//! [`OnDuplicate`] policy says and recording which list each came from;
+//! [`diff`] compares two lists by term.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    needle_index::{self, CompiledNeedles},
+    types::{NeedleDelimiter, OwnedNeedle},
+    utils::{read_needles_with_stats, NeedleFileStats},
+};
+
+/// How a needle list is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// One `term<delimiter>metadata` line per needle
+    Csv,
+    /// An array of `{"term", "metadata"}` objects
+    Json,
+}
+
+impl ListFormat {
+    /// The format a file name asks for: JSON for `.json`, else delimited text
+    pub fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ListFormat::Json,
+            _ => ListFormat::Csv,
+        }
+    }
+}
+
+/// Which needle is kept when a term appears more than once
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnDuplicate {
+    /// The first, in the order the lists were given
+    #[default]
+    First,
+    /// The last, in the place of the first
+    Last,
+    /// Fail when the metadata differs
+    Error,
+}
+
+/// A needle as it appears in JSON lists
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonNeedle {
+    term: String,
+    #[serde(default)]
+    metadata: String,
+}
+
+/// The needles read from one file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NeedleList {
+    /// The file, as given
+    pub source: String,
+    pub needles: Vec<OwnedNeedle>,
+    /// Lines without needles, and the delimiter; `None` for JSON and
+    /// compiled lists
+    pub stats: Option<NeedleFileStats>,
+}
+
+impl NeedleList {
+    /// Read `path` as a compiled list, a JSON list (`.json`), or a delimited
+    /// file split at `delimiter` or the detected one. A list without
+    /// needles is not an error.
+    pub fn read(path: &Path, delimiter: Option<NeedleDelimiter>) -> Result<Self> {
+        let source = path.to_string_lossy().into_owned();
+        if needle_index::is_compiled(path) {
+            let needles = CompiledNeedles::load(path)?.needles;
+            return Ok(NeedleList {
+                source,
+                needles,
+                stats: None,
+            });
+        }
+        if ListFormat::of(path) == ListFormat::Json {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read needles file: {}", source))?;
+            let needles: Vec<JsonNeedle> = serde_json::from_str(&content)
+                .with_context(|| format!("{} is not a JSON array of needles", source))?;
+            let needles = needles
+                .into_iter()
+                .map(|needle| (needle.term, needle.metadata))
+                .collect();
+            return Ok(NeedleList {
+                source,
+                needles,
+                stats: None,
+            });
+        }
+        let (needles, stats) = read_needles_with_stats(&source, delimiter)?;
+        Ok(NeedleList {
+            source,
+            needles,
+            stats: Some(stats),
+        })
+    }
+
+    /// The delimiter the list was read with, if it had one
+    pub fn delimiter(&self) -> Option<NeedleDelimiter> {
+        self.stats.map(|stats| stats.delimiter)
+    }
+}
+
+/// A line of a list being written
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListLine {
+    /// A `#` comment; JSON lists leave these out
+    Comment(String),
+    Needle(OwnedNeedle),
+}
+
+/// Write `lines` in `format`, splitting delimited lines at `delimiter`
+pub fn write_list(
+    lines: &[ListLine],
+    format: ListFormat,
+    delimiter: NeedleDelimiter,
+) -> Result<String> {
+    match format {
+        ListFormat::Csv => lines
+            .iter()
+            .map(|line| match line {
+                ListLine::Comment(comment) => Ok(format!("# {}\n", comment)),
+                ListLine::Needle((term, metadata)) => {
+                    Ok(format!("{}\n", needle_line(term, metadata, delimiter)?))
+                }
+            })
+            .collect(),
+        ListFormat::Json => {
+            let needles: Vec<JsonNeedle> = lines
+                .iter()
+                .filter_map(|line| match line {
+                    ListLine::Needle((term, metadata)) => Some(JsonNeedle {
+                        term: term.clone(),
+                        metadata: metadata.clone(),
+                    }),
+                    ListLine::Comment(_) => None,
+                })
+                .collect();
+            Ok(format!("{}\n", serde_json::to_string_pretty(&needles)?))
+        }
+    }
+}
+
+/// A needle as a line of a delimited needles file
+pub fn needle_line(term: &str, metadata: &str, delimiter: NeedleDelimiter) -> Result<String> {
+    if term.contains(['\n', '\r']) || metadata.contains(['\n', '\r']) {
+        return Err(anyhow!(
+            "Needle {:?} contains a line break, which a needles file cannot hold",
+            term
+        ));
+    }
+    let delimiter = delimiter.char();
+    let padded = |field: &str| field.trim() != field;
+    let term = if term.contains([delimiter, '"']) || term.starts_with('#') || padded(term) {
+        quote(term)
+    } else {
+        term.to_string()
+    };
+    let metadata = if metadata.is_empty() || metadata.contains([delimiter, '"']) || padded(metadata)
+    {
+        quote(metadata)
+    } else {
+        metadata.to_string()
+    };
+    Ok(format!("{}{}{}", term, delimiter, metadata))
+}
+
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+/// A term found with different metadata in two places
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub term: String,
+    /// The metadata kept, and the list it came from
+    pub kept: (String, String),
+    /// The metadata dropped, and the list it came from
+    pub dropped: (String, String),
+}
+
+/// Lists combined by [`merge`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Merged {
+    /// One needle per term, in the order terms first appear, with the index
+    /// of the list the kept needle came from
+    pub needles: Vec<(OwnedNeedle, usize)>,
+    /// Needles dropped because the same term and metadata came before
+    pub duplicates: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merged {
+    /// The merged list, each run of needles from one list introduced by a
+    /// comment naming it, and each conflict noted above the needle kept
+    pub fn lines(&self, lists: &[NeedleList]) -> Vec<ListLine> {
+        let mut lines = Vec::new();
+        let mut current = None;
+        for ((term, metadata), list) in &self.needles {
+            if current != Some(*list) {
+                lines.push(ListLine::Comment(format!("from {}", lists[*list].source)));
+                current = Some(*list);
+            }
+            for conflict in self
+                .conflicts
+                .iter()
+                .filter(|conflict| conflict.term == *term)
+            {
+                lines.push(ListLine::Comment(format!(
+                    "{} also in {} as {:?}",
+                    term, conflict.dropped.1, conflict.dropped.0
+                )));
+            }
+            lines.push(ListLine::Needle((term.clone(), metadata.clone())));
+        }
+        lines
+    }
+}
+
+/// Combine `lists` into one with a needle per term. A term repeated with
+/// the same metadata is dropped silently; with other metadata, `policy`
+/// decides which is kept and the other is recorded as a [`Conflict`].
+pub fn merge(lists: &[NeedleList], policy: OnDuplicate) -> Result<Merged> {
+    let mut merged = Merged::default();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (index, list) in lists.iter().enumerate() {
+        for (term, metadata) in &list.needles {
+            let Some(&position) = positions.get(term) else {
+                positions.insert(term.clone(), merged.needles.len());
+                merged
+                    .needles
+                    .push(((term.clone(), metadata.clone()), index));
+                continue;
+            };
+            let ((_, kept), kept_from) = &mut merged.needles[position];
+            if kept == metadata {
+                merged.duplicates += 1;
+                continue;
+            }
+            let earlier = (kept.clone(), lists[*kept_from].source.clone());
+            let later = (metadata.clone(), list.source.clone());
+            let (kept_entry, dropped) = match policy {
+                OnDuplicate::First => (earlier, later),
+                OnDuplicate::Last => {
+                    (*kept, *kept_from) = (metadata.clone(), index);
+                    (later, earlier)
+                }
+                OnDuplicate::Error => {
+                    return Err(anyhow!(
+                        "{:?} has metadata {:?} in {} and {:?} in {}; pass --on-duplicate first or last to pick one",
+                        term,
+                        earlier.0,
+                        earlier.1,
+                        later.0,
+                        later.1
+                    ))
+                }
+            };
+            merged.conflicts.push(Conflict {
+                term: term.clone(),
+                kept: kept_entry,
+                dropped,
+            });
+        }
+    }
+    Ok(merged)
+}
+
+/// How a needle differs between two lists
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(OwnedNeedle),
+    Removed(OwnedNeedle),
+    /// The term is in both lists with other metadata
+    Changed {
+        term: String,
+        old: String,
+        new: String,
+    },
+}
+
+/// What changed from `old` to `new`, by term: removed needles in the order
+/// of `old`, then added and changed ones in the order of `new`. Only the
+/// first needle of a repeated term is compared.
+pub fn diff(old: &[OwnedNeedle], new: &[OwnedNeedle]) -> Vec<Change> {
+    let first = |needles: &[OwnedNeedle]| {
+        let mut seen = HashMap::new();
+        let unique: Vec<OwnedNeedle> = needles
+            .iter()
+            .filter(|(term, metadata)| seen.insert(term.clone(), metadata.clone()).is_none())
+            .cloned()
+            .collect();
+        let by_term: HashMap<String, String> = unique.iter().cloned().collect();
+        (unique, by_term)
+    };
+    let (old, old_terms) = first(old);
+    let (new, new_terms) = first(new);
+
+    let removed = old
+        .iter()
+        .filter(|(term, _)| !new_terms.contains_key(term))
+        .map(|needle| Change::Removed(needle.clone()));
+    let added_or_changed = new
+        .iter()
+        .filter_map(|(term, metadata)| match old_terms.get(term) {
+            None => Some(Change::Added((term.clone(), metadata.clone()))),
+            Some(old) if old != metadata => Some(Change::Changed {
+                term: term.clone(),
+                old: old.clone(),
+                new: metadata.clone(),
+            }),
+            Some(_) => None,
+        });
+    removed.chain(added_or_changed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::read_needles_from_file;
+
+    fn needles(pairs: &[(&str, &str)]) -> Vec<OwnedNeedle> {
+        pairs
+            .iter()
+            .map(|(term, metadata)| (term.to_string(), metadata.to_string()))
+            .collect()
+    }
+
+    fn list(source: &str, pairs: &[(&str, &str)]) -> NeedleList {
+        NeedleList {
+            source: source.to_string(),
+            needles: needles(pairs),
+            stats: None,
+        }
+    }
+
+    /// Needles whose fields need quoting with one delimiter or another
+    fn awkward() -> Vec<OwnedNeedle> {
+        needles(&[
+            ("Alice Johnson", "dept=legal;region=eu"),
+            ("Smith, Bob", "id=2"),
+            ("Carol \"CJ\" Diaz", ""),
+            ("#hashtag", " padded "),
+            ("\"quoted\"", "\"meta\""),
+            ("tab\tand|pipe", "a,b;c"),
+            ("👩‍👩‍👧 family", "emoji"),
+        ])
+    }
+
+    #[test]
+    fn test_conversion_round_trips_through_the_needles_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines: Vec<ListLine> = awkward().into_iter().map(ListLine::Needle).collect();
+
+        for delimiter in NeedleDelimiter::CANDIDATES {
+            let csv = dir.path().join("list.csv");
+            std::fs::write(
+                &csv,
+                write_list(&lines, ListFormat::Csv, delimiter).unwrap(),
+            )
+            .unwrap();
+            let read = read_needles_from_file(&csv.to_string_lossy(), Some(delimiter)).unwrap();
+            assert_eq!(read, awkward(), "delimiter {}", delimiter);
+            assert_eq!(
+                NeedleList::read(&csv, None).unwrap().delimiter(),
+                Some(delimiter)
+            );
+
+            // To JSON and back to delimited text
+            let list = NeedleList::read(&csv, Some(delimiter)).unwrap();
+            let json = dir.path().join("list.json");
+            let lines: Vec<ListLine> = list.needles.into_iter().map(ListLine::Needle).collect();
+            std::fs::write(
+                &json,
+                write_list(&lines, ListFormat::Json, delimiter).unwrap(),
+            )
+            .unwrap();
+            let from_json = NeedleList::read(&json, None).unwrap();
+            assert_eq!(from_json.needles, awkward());
+            let lines: Vec<ListLine> = from_json
+                .needles
+                .into_iter()
+                .map(ListLine::Needle)
+                .collect();
+            std::fs::write(
+                &csv,
+                write_list(&lines, ListFormat::Csv, delimiter).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(
+                read_needles_from_file(&csv.to_string_lossy(), Some(delimiter)).unwrap(),
+                awkward()
+            );
+        }
+
+        assert!(needle_line("two\nlines", "", NeedleDelimiter::COMMA).is_err());
+    }
+
+    #[test]
+    fn test_merge_conflicts_follow_the_policy() {
+        let lists = [
+            list(
+                "a.csv",
+                &[("Alice", "id=1"), ("Bob", "id=2"), ("Alice", "id=1")],
+            ),
+            list("b.json", &[("Bob", "id=20"), ("Carol", "id=3")]),
+        ];
+
+        let first = merge(&lists, OnDuplicate::First).unwrap();
+        assert_eq!(
+            first.needles,
+            [
+                (("Alice".to_string(), "id=1".to_string()), 0),
+                (("Bob".to_string(), "id=2".to_string()), 0),
+                (("Carol".to_string(), "id=3".to_string()), 1)
+            ]
+        );
+        assert_eq!(first.duplicates, 1);
+        assert_eq!(
+            first.conflicts,
+            [Conflict {
+                term: "Bob".to_string(),
+                kept: ("id=2".to_string(), "a.csv".to_string()),
+                dropped: ("id=20".to_string(), "b.json".to_string()),
+            }]
+        );
+        assert_eq!(
+            first.lines(&lists),
+            [
+                ListLine::Comment("from a.csv".to_string()),
+                ListLine::Needle(("Alice".to_string(), "id=1".to_string())),
+                ListLine::Comment("Bob also in b.json as \"id=20\"".to_string()),
+                ListLine::Needle(("Bob".to_string(), "id=2".to_string())),
+                ListLine::Comment("from b.json".to_string()),
+                ListLine::Needle(("Carol".to_string(), "id=3".to_string())),
+            ]
+        );
+
+        // The last metadata wins, in the place of the first
+        let last = merge(&lists, OnDuplicate::Last).unwrap();
+        assert_eq!(
+            last.needles[1],
+            (("Bob".to_string(), "id=20".to_string()), 1)
+        );
+        assert_eq!(
+            last.conflicts[0].dropped,
+            ("id=2".to_string(), "a.csv".to_string())
+        );
+
+        let err = merge(&lists, OnDuplicate::Error).unwrap_err().to_string();
+        assert!(
+            err.contains("\"id=2\" in a.csv and \"id=20\" in b.json"),
+            "{}",
+            err
+        );
+        // Exact repeats are not conflicts
+        assert!(merge(&lists[..1], OnDuplicate::Error).is_ok());
+    }
+
+    #[test]
+    fn test_diff_by_term() {
+        let old = needles(&[("Alice", "id=1"), ("Bob", "id=2"), ("Dave", "id=4")]);
+        let new = needles(&[
+            ("Bob", "id=20"),
+            ("Carol", "id=3"),
+            ("Alice", "id=1"),
+            ("Carol", "id=30"),
+        ]);
+        assert_eq!(
+            diff(&old, &new),
+            [
+                Change::Removed(("Dave".to_string(), "id=4".to_string())),
+                Change::Changed {
+                    term: "Bob".to_string(),
+                    old: "id=2".to_string(),
+                    new: "id=20".to_string()
+                },
+                Change::Added(("Carol".to_string(), "id=3".to_string())),
+            ]
+        );
+        assert_eq!(diff(&new, &new), []);
+    }
+}
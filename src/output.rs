@@ -0,0 +1,486 @@
+//! Writing results to stdout when the reader may be slow or may go away.
+//!
+//! [`RecordWriter`] streams NDJSON records from a thread of its own: workers
+//! hand it lines through a bounded queue, so a slow consumer holds the
+//! workers back instead of letting output pile up in memory, and progress
+//! bars on stderr keep drawing. When the consumer exits, the broken pipe
+//! ends the stream: [`RecordWriter::send`] starts returning `false` and the
+//! search stops cleanly.
+//!
+//! Everything else the commands print goes through [`outln!`], which never
+//! panics on a closed stdout the way `println!` does, and moves to stderr
+//! while a record stream owns stdout. Warnings go through [`warnln!`]. A
+//! command that speaks a machine protocol instead (`--api`) [`divert`]s both
+//! and writes its own output with [`print_raw`].
+
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, BufWriter, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Set while a [`RecordWriter`] owns stdout
+static STREAMING: AtomicBool = AtomicBool::new(false);
+
+/// Set once a write to stdout failed because the reader went away
+static STDOUT_CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// Which kind of line a [`Diversion`] is handed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Command output, from [`outln!`]
+    Output,
+    /// A warning, from [`warnln!`]
+    Warning,
+}
+
+/// Takes the lines printed while it is installed with [`divert`]
+pub type Diversion = Arc<dyn Fn(Channel, &str) + Send + Sync>;
+
+thread_local! {
+    /// Installed by [`divert`] on the thread running a command
+    static DIVERTED: RefCell<Option<Diversion>> = const { RefCell::new(None) };
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Where [`capture_streams`] collects what this thread prints to stdout
+    /// and through [`stderr_writer`]
+    static CAPTURE: RefCell<Option<(Captured, Captured)>> = const { RefCell::new(None) };
+}
+
+/// `println!` for command output; see [`print_line`]
+macro_rules! outln {
+    () => {
+        $crate::output::print_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::print_line(format_args!($($arg)*))
+    };
+}
+pub(crate) use outln;
+
+/// `eprintln!` for warnings; see [`print_warning`]
+macro_rules! warnln {
+    ($($arg:tt)*) => {
+        $crate::output::print_warning(format_args!($($arg)*))
+    };
+}
+pub(crate) use warnln;
+
+/// Print a line of command output.
+///
+/// Goes to stderr while a record stream owns stdout. Once stdout is closed
+/// by its reader, further output is dropped instead of panicking.
+pub fn print_line(args: fmt::Arguments) {
+    if let Some(diversion) = diverted() {
+        diversion(Channel::Output, &args.to_string());
+        return;
+    }
+    if STREAMING.load(Ordering::Relaxed) {
+        let _ = writeln!(io::stderr(), "{}", args);
+        return;
+    }
+    print_raw(args);
+}
+
+/// Print a warning to stderr
+pub fn print_warning(args: fmt::Arguments) {
+    match diverted() {
+        Some(diversion) => diversion(Channel::Warning, &args.to_string()),
+        None => {
+            let _ = writeln!(io::stderr(), "{}", args);
+        }
+    }
+}
+
+/// Print a line to stdout whether or not output is diverted, dropping it
+/// once the reader of stdout has gone away
+pub fn print_raw(args: fmt::Arguments) {
+    #[cfg(test)]
+    if let Some((mut captured, _)) = captured() {
+        let _ = writeln!(captured, "{}", args);
+        return;
+    }
+    if STDOUT_CLOSED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = writeln!(io::stdout().lock(), "{}", args) {
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            STDOUT_CLOSED.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Command output as a writer: each complete line is printed with
+/// [`print_line`], so a long document such as a JSON report can be
+/// serialized straight to the output instead of into a string first
+#[derive(Default)]
+pub struct LineWriter {
+    pending: Vec<u8>,
+}
+
+impl LineWriter {
+    /// Print what is left after the last line break as a line of its own
+    pub fn finish(mut self) {
+        if !self.pending.is_empty() {
+            print_line(format_args!("{}", String::from_utf8_lossy(&self.pending)));
+            self.pending.clear();
+        }
+    }
+}
+
+impl Write for LineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(end) = self.pending.iter().rposition(|&byte| byte == b'\n') {
+            let rest = self.pending.split_off(end + 1);
+            let lines = std::mem::replace(&mut self.pending, rest);
+            for line in String::from_utf8_lossy(&lines[..end]).split('\n') {
+                print_line(format_args!("{}", line));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Removes a [`divert`]ed diversion when dropped
+pub struct DivertGuard(());
+
+impl Drop for DivertGuard {
+    fn drop(&mut self) {
+        DIVERTED.with(|diverted| *diverted.borrow_mut() = None);
+    }
+}
+
+/// Hand what [`outln!`] and [`warnln!`] print on this thread to `diversion`
+/// instead, until the guard is dropped
+pub fn divert(diversion: Diversion) -> DivertGuard {
+    DIVERTED.with(|diverted| *diverted.borrow_mut() = Some(diversion));
+    DivertGuard(())
+}
+
+fn diverted() -> Option<Diversion> {
+    DIVERTED.with(|diverted| diverted.borrow().clone())
+}
+
+/// Stderr, as a writer that can be moved to another thread
+pub fn stderr_writer() -> Box<dyn Write + Send> {
+    #[cfg(test)]
+    if let Some((_, captured)) = captured() {
+        return Box::new(captured);
+    }
+    Box::new(io::stderr())
+}
+
+/// Whether the reader of stdout has gone away
+pub fn stdout_closed() -> bool {
+    STDOUT_CLOSED.load(Ordering::Relaxed)
+}
+
+/// How a record stream ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamEnd {
+    /// Lines accepted by the output; with buffering, the last of them may
+    /// not have reached a reader that went away
+    pub written: usize,
+    /// The reader went away before every line was written
+    pub closed: bool,
+}
+
+/// Writes lines from any thread to one output, in the order they are sent,
+/// through a queue of at most `bound` lines
+pub struct RecordWriter {
+    sender: Option<SyncSender<String>>,
+    writer: Option<JoinHandle<io::Result<StreamEnd>>>,
+    closed: Arc<AtomicBool>,
+    /// Whether this writer owns stdout
+    stdout: bool,
+}
+
+impl RecordWriter {
+    /// Stream to stdout; other output moves to stderr until the stream ends
+    pub fn stdout(bound: usize) -> Self {
+        #[cfg(test)]
+        if let Some((captured, _)) = captured() {
+            return Self::spawn(move || captured, bound);
+        }
+        STREAMING.store(true, Ordering::Relaxed);
+        let mut writer = Self::spawn(|| io::stdout().lock(), bound);
+        writer.stdout = true;
+        writer
+    }
+
+    /// Stream to the output `open` returns, called on the writer thread
+    pub fn spawn<W, F>(open: F, bound: usize) -> Self
+    where
+        W: Write,
+        F: FnOnce() -> W + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel::<String>(bound.max(1));
+        let closed = Arc::new(AtomicBool::new(false));
+        let writer_closed = Arc::clone(&closed);
+
+        let writer = thread::spawn(move || {
+            let mut output = BufWriter::new(open());
+            let mut written = 0;
+            // Flush whenever the queue runs dry, so a slow trickle of
+            // records still reaches the reader promptly
+            while let Ok(line) = receiver.recv() {
+                let result = std::iter::once(line)
+                    .chain(receiver.try_iter())
+                    .try_for_each(|line| {
+                        writeln!(output, "{}", line)?;
+                        written += 1;
+                        Ok(())
+                    })
+                    .and_then(|()| output.flush());
+
+                match result {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                        writer_closed.store(true, Ordering::Relaxed);
+                        // Dropping the receiver makes further sends fail
+                        return Ok(StreamEnd {
+                            written,
+                            closed: true,
+                        });
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(StreamEnd {
+                written,
+                closed: false,
+            })
+        });
+
+        Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            closed,
+            stdout: false,
+        }
+    }
+
+    /// Queue `line`, waiting while the queue is full. Returns `false` once
+    /// the output is gone, when the caller should stop producing.
+    pub fn send(&self, line: String) -> bool {
+        !self.closed.load(Ordering::Relaxed)
+            && self
+                .sender
+                .as_ref()
+                .is_some_and(|sender| sender.send(line).is_ok())
+    }
+
+    /// Write out what is queued and wait for the writer to finish
+    pub fn finish(mut self) -> io::Result<StreamEnd> {
+        self.close()
+    }
+
+    fn close(&mut self) -> io::Result<StreamEnd> {
+        self.sender = None;
+        let end = match self.writer.take() {
+            Some(writer) => writer
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("record writer panicked"))),
+            None => Ok(StreamEnd {
+                written: 0,
+                closed: false,
+            }),
+        };
+
+        if self.stdout {
+            if matches!(end, Ok(StreamEnd { closed: true, .. })) {
+                STDOUT_CLOSED.store(true, Ordering::Relaxed);
+            }
+            STREAMING.store(false, Ordering::Relaxed);
+        }
+        end
+    }
+}
+
+impl Drop for RecordWriter {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Output collected by [`capture`]
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn captured() -> Option<(Captured, Captured)> {
+    CAPTURE.with(|capture| capture.borrow().clone())
+}
+
+/// Run `f` and return what it printed to stdout on this thread, streamed
+/// records included
+#[cfg(test)]
+pub fn capture(f: impl FnOnce()) -> String {
+    capture_streams(f).0
+}
+
+/// Like [`capture`], also returning what was written through
+/// [`stderr_writer`]s opened on this thread
+#[cfg(test)]
+pub fn capture_streams(f: impl FnOnce()) -> (String, String) {
+    let captured = (Captured::default(), Captured::default());
+    CAPTURE.with(|capture| *capture.borrow_mut() = Some(captured.clone()));
+    f();
+    CAPTURE.with(|capture| *capture.borrow_mut() = None);
+    let text = |captured: Captured| {
+        String::from_utf8(captured.0.lock().unwrap().clone()).expect("command output is UTF-8")
+    };
+    (text(captured.0), text(captured.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Accepts `capacity` bytes, then fails like a pipe whose reader exited
+    struct ClosingPipe {
+        received: Arc<Mutex<Vec<u8>>>,
+        capacity: usize,
+    }
+
+    impl Write for ClosingPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut received = self.received.lock().unwrap();
+            if received.len() + buf.len() > self.capacity {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            received.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_line_writer_prints_whole_lines() {
+        let printed = capture(|| {
+            let mut writer = LineWriter::default();
+            write!(writer, "{{\n  \"a\"").unwrap();
+            write!(writer, ": 1\n}}").unwrap();
+            writer.finish();
+        });
+        assert_eq!(printed, "{\n  \"a\": 1\n}\n");
+    }
+
+    #[test]
+    fn test_lines_arrive_in_order() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let pipe = ClosingPipe {
+            received: Arc::clone(&received),
+            capacity: usize::MAX,
+        };
+        let writer = RecordWriter::spawn(move || pipe, 2);
+
+        for i in 0..100 {
+            assert!(writer.send(format!("{{\"n\":{}}}", i)));
+        }
+        assert_eq!(
+            writer.finish().unwrap(),
+            StreamEnd {
+                written: 100,
+                closed: false
+            }
+        );
+
+        let text = String::from_utf8(received.lock().unwrap().clone()).unwrap();
+        assert_eq!(text.lines().count(), 100);
+        assert_eq!(text.lines().last(), Some("{\"n\":99}"));
+    }
+
+    #[test]
+    fn test_closed_output_stops_the_stream() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        // Small enough that the buffered writer hits the error mid-stream
+        let pipe = ClosingPipe {
+            received: Arc::clone(&received),
+            capacity: 64 * 1024,
+        };
+        let writer = RecordWriter::spawn(move || pipe, 4);
+
+        let line = "x".repeat(1000);
+        let sent = (0..10_000)
+            .take_while(|_| writer.send(line.clone()))
+            .count();
+        assert!(
+            sent < 10_000,
+            "sending should stop once the output is closed"
+        );
+
+        let end = writer.finish().unwrap();
+        assert!(end.closed);
+        assert!(end.written <= sent);
+    }
+
+    /// A real pipe whose reader takes a few lines and exits
+    #[cfg(unix)]
+    #[test]
+    fn test_reader_exiting_early_ends_the_stream_cleanly() {
+        use std::{
+            fs::File,
+            io::{BufRead, BufReader},
+            os::fd::FromRawFd,
+        };
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (read_end, write_end) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        let consumer = thread::spawn(move || {
+            let lines: Vec<String> = BufReader::new(read_end)
+                .lines()
+                .take(3)
+                .map(Result::unwrap)
+                .collect();
+            lines
+        });
+
+        let writer = RecordWriter::spawn(move || write_end, 8);
+        let mut sent = 0;
+        while sent < 1_000_000 && writer.send(format!("{{\"match\":{}}}", sent)) {
+            sent += 1;
+        }
+        let end = writer.finish().unwrap();
+
+        assert_eq!(
+            consumer.join().unwrap(),
+            ["{\"match\":0}", "{\"match\":1}", "{\"match\":2}"]
+        );
+        assert!(end.closed);
+        assert!(sent < 1_000_000);
+    }
+}
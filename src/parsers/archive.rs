@@ -0,0 +1,207 @@
+//! Documents inside `.zip` archives and gzip (`.gz`) files.
+//!
+//! A container is opened in memory and every entry that is a supported
+//! document, or another container, is searched in turn, so a PDF inside a
+//! zip inside a gzip file is found like any other. Each occurrence records
+//! the entry names leading from the searched file to its document in
+//! [`Occurrence::entries`](crate::search::Occurrence::entries).
+//!
+//! Entries that fail to parse are skipped with a warning rather than
+//! failing the whole archive; entries of other types are ignored.
+
+use std::{
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::{batch::has_searchable_extension, search::Extraction};
+
+/// How many containers deep documents are looked for
+pub const MAX_DEPTH: usize = 8;
+
+/// Largest entry read from a container, against decompression bombs
+pub const MAX_ENTRY_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Whether `path` names a container searched by this module
+pub fn is_container(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip") || ext.eq_ignore_ascii_case("gz"))
+}
+
+/// Search the documents in the container `bytes`, read from `path`, with
+/// `search_document`, tagging each occurrence with the entries leading to it
+pub fn search(
+    path: &Path,
+    bytes: &[u8],
+    search_document: &dyn Fn(&Path, &[u8]) -> Result<Extraction>,
+) -> Result<Extraction> {
+    search_at(path, bytes, search_document, 1)
+}
+
+fn search_at(
+    path: &Path,
+    bytes: &[u8],
+    search_document: &dyn Fn(&Path, &[u8]) -> Result<Extraction>,
+    depth: usize,
+) -> Result<Extraction> {
+    if depth > MAX_DEPTH {
+        return Err(anyhow!(
+            "{} is nested more than {} containers deep",
+            path.display(),
+            MAX_DEPTH
+        ));
+    }
+
+    let mut extraction = Extraction::default();
+    for (entry, contents) in entries(path, bytes)? {
+        let entry_path = Path::new(&entry);
+        let found = if is_container(entry_path) {
+            search_at(entry_path, &contents, search_document, depth + 1)
+        } else {
+            search_document(entry_path, &contents)
+        };
+
+        match found {
+            Ok(found) => {
+                extraction.text_bytes += found.text_bytes;
+                extraction.warnings.extend(
+                    found
+                        .warnings
+                        .into_iter()
+                        .map(|warning| format!("{}: {}", entry, warning)),
+                );
+                extraction
+                    .occurrences
+                    .extend(found.occurrences.into_iter().map(|mut occurrence| {
+                        occurrence.entries.insert(0, entry.clone());
+                        occurrence
+                    }));
+            }
+            Err(e) => {
+                tracing::warn!(container = %path.display(), entry, error = %e, "archive entry skipped")
+            }
+        }
+    }
+    Ok(extraction)
+}
+
+/// The searchable entries of a container, by name
+fn entries(path: &Path, bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+    {
+        let mut decoder = GzDecoder::new(bytes);
+        let contents = read_entry(&mut decoder, path)?;
+        // The name stored in the header, else the file name without `.gz`
+        let name = decoder
+            .header()
+            .and_then(|header| header.filename())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            });
+        return Ok(if has_searchable_extension(Path::new(&name)) {
+            vec![(name, contents)]
+        } else {
+            Vec::new()
+        });
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let name = file.name().to_string();
+        if file.is_dir() || !has_searchable_extension(Path::new(&name)) {
+            continue;
+        }
+        let contents = read_entry(&mut file, Path::new(&name))?;
+        entries.push((name, contents));
+    }
+    Ok(entries)
+}
+
+fn read_entry(reader: &mut dyn Read, name: &Path) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    reader
+        .take(MAX_ENTRY_BYTES + 1)
+        .read_to_end(&mut contents)
+        .map_err(|e| anyhow!("Failed to decompress {}: {}", name.display(), e))?;
+    if contents.len() as u64 > MAX_ENTRY_BYTES {
+        return Err(anyhow!(
+            "{} is larger than {} bytes uncompressed",
+            name.display(),
+            MAX_ENTRY_BYTES
+        ));
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{batch::search_bytes, fixtures, types::ExtractOptions};
+
+    fn needles() -> Vec<(String, String)> {
+        vec![("Alice Johnson".to_string(), "id=1".to_string())]
+    }
+
+    fn search_document(path: &Path, bytes: &[u8]) -> Result<Extraction> {
+        search_bytes(&needles(), path, bytes, ExtractOptions::default())
+    }
+
+    #[test]
+    fn test_pdf_in_zip_in_gz_records_every_entry() {
+        let pdf = fixtures::pdf(&[&["Signed by Alice Johnson"]]);
+        let docx = fixtures::docx(&["Alice Johnson"]);
+        let zip = fixtures::zip_of(&[
+            ("a/b/report.pdf", &pdf),
+            ("notes.txt", b"Alice Johnson"),
+            ("c/memo.docx", &docx),
+        ]);
+        let gz = fixtures::gzip(None, &zip);
+
+        let extraction = search(Path::new("dump.zip.gz"), &gz, &search_document).unwrap();
+        let entries: Vec<&[String]> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| &occurrence.entries[..])
+            .collect();
+        assert_eq!(
+            entries,
+            [["dump.zip", "a/b/report.pdf"], ["dump.zip", "c/memo.docx"]]
+        );
+        assert_eq!(extraction.occurrences[0].page(), Some(1));
+    }
+
+    #[test]
+    fn test_gzip_header_name_is_preferred() {
+        let gz = fixtures::gzip(Some("original.pdf"), &fixtures::pdf(&[&["Alice Johnson"]]));
+        let extraction = search(Path::new("renamed.gz"), &gz, &search_document).unwrap();
+        assert_eq!(extraction.occurrences[0].entries, ["original.pdf"]);
+    }
+
+    #[test]
+    fn test_broken_entries_are_skipped() {
+        let zip = fixtures::zip_of(&[
+            ("broken.pdf", b"not a pdf"),
+            ("good.docx", &fixtures::docx(&["Alice Johnson"])),
+        ]);
+        let extraction = search(Path::new("mixed.zip"), &zip, &search_document).unwrap();
+        assert_eq!(extraction.occurrences.len(), 1);
+        assert_eq!(extraction.occurrences[0].entries, ["good.docx"]);
+
+        assert!(search(Path::new("corrupt.zip"), b"not a zip", &search_document).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Legacy Word 97-2003 binary documents (`.doc`).
+//!
+//! These are OLE2 compound files, not zip archives, and are often renamed to
+//! `.docx`. They are always detected by their magic bytes so callers get a
+//! [`DocumentError::LegacyDocUnsupported`] instead of a zip error. With the
+//! `doc` feature, text is read through the piece table of the WordDocument
+//! stream; fast-saved and encrypted files are still refused.
+
+use anyhow::Result;
+use std::path::Path;
+
+use super::ExtractedDocument;
+use crate::{
+    error::DocumentError,
+    search::{Extraction, TextBlock},
+    types::ExtractOptions,
+};
+
+/// Leading bytes of every OLE2 compound file
+pub const OLE2_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Whether `bytes` start like a legacy binary Office document
+pub fn is_legacy_doc(bytes: &[u8]) -> bool {
+    bytes.starts_with(&OLE2_MAGIC)
+}
+
+/// Search an in-memory legacy document; `path` is only used in errors
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    bytes: &[u8],
+    path: &Path,
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    Ok(document(bytes, path)?.search(needles, extract))
+}
+
+/// The blocks [`occurrences_mem`] searches: every line of the text
+pub fn blocks_mem(bytes: &[u8], path: &Path) -> Result<Vec<TextBlock>> {
+    Ok(document(bytes, path)?.blocks)
+}
+
+/// The text of an in-memory legacy document, line by line; `path` is only
+/// used in errors
+pub fn document(bytes: &[u8], path: &Path) -> Result<ExtractedDocument> {
+    let text = extract_text(bytes).map_err(|reason| DocumentError::LegacyDocUnsupported {
+        path: path.to_path_buf(),
+        reason,
+    })?;
+
+    Ok(ExtractedDocument {
+        text_bytes: text.len() as u64,
+        ..ExtractedDocument::new(TextBlock::plain_lines(&text))
+    })
+}
+
+/// Search a legacy document on disk with needles that were already loaded
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    let bytes = std::fs::read(file_path)?;
+    occurrences_mem(needles, &bytes, Path::new(file_path), extract)
+}
+
+#[cfg(not(feature = "doc"))]
+fn extract_text(_bytes: &[u8]) -> Result<String, String> {
+    Err("docsearcher was built without the `doc` feature".to_string())
+}
+
+#[cfg(feature = "doc")]
+fn extract_text(bytes: &[u8]) -> Result<String, String> {
+    use std::io::{Cursor, Read};
+
+    const FIB_IDENT: u16 = 0xA5EC;
+    const F_COMPLEX: u16 = 0x0004;
+    const F_ENCRYPTED: u16 = 0x0100;
+    const F_WHICH_TBL_STM: u16 = 0x0200;
+    const CCP_TEXT_OFFSET: usize = 0x4C;
+    const FC_CLX_OFFSET: usize = 0x1A2;
+
+    let mut file = cfb::CompoundFile::open(Cursor::new(bytes))
+        .map_err(|e| format!("unreadable compound file: {}", e))?;
+    let mut read_stream = |name: &str| {
+        let mut buffer = Vec::new();
+        file.open_stream(name)
+            .and_then(|mut stream| stream.read_to_end(&mut buffer))
+            .map_err(|_| format!("missing {} stream", name.trim_start_matches('/')))?;
+        Ok::<_, String>(buffer)
+    };
+
+    let word = read_stream("/WordDocument")?;
+    if read_u16(&word, 0)? != FIB_IDENT {
+        return Err("not a Word document".to_string());
+    }
+
+    let flags = read_u16(&word, 0x0A)?;
+    if flags & F_ENCRYPTED != 0 {
+        return Err("the document is encrypted".to_string());
+    }
+    if flags & F_COMPLEX != 0 {
+        return Err("the document was fast-saved".to_string());
+    }
+
+    let table = read_stream(if flags & F_WHICH_TBL_STM != 0 {
+        "/1Table"
+    } else {
+        "/0Table"
+    })?;
+    let ccp_text = read_u32(&word, CCP_TEXT_OFFSET)? as usize;
+    let fc_clx = read_u32(&word, FC_CLX_OFFSET)? as usize;
+    let lcb_clx = read_u32(&word, FC_CLX_OFFSET + 4)? as usize;
+    let clx = table
+        .get(fc_clx..fc_clx.saturating_add(lcb_clx))
+        .ok_or("piece table out of range")?;
+
+    let mut text = String::new();
+    for piece in read_pieces(clx)? {
+        if piece.cp_start >= ccp_text {
+            break;
+        }
+        let len = piece.cp_end.min(ccp_text).saturating_sub(piece.cp_start);
+
+        if piece.compressed {
+            let bytes = word
+                .get(piece.offset..piece.offset + len)
+                .ok_or("text out of range")?;
+            text.extend(bytes.iter().map(|&b| cp1252_char(b)));
+        } else {
+            let bytes = word
+                .get(piece.offset..piece.offset + len * 2)
+                .ok_or("text out of range")?;
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            text.extend(
+                char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+            );
+        }
+    }
+
+    Ok(clean_text(&text))
+}
+
+/// A run of document text described by the piece table
+#[cfg(feature = "doc")]
+struct Piece {
+    cp_start: usize,
+    cp_end: usize,
+    /// Byte offset of the text in the WordDocument stream
+    offset: usize,
+    /// Whether the text is stored as 8-bit cp1252 rather than UTF-16
+    compressed: bool,
+}
+
+/// Read the piece descriptors from a `Clx` structure, skipping any
+/// formatting (`Prc`) entries in front of the piece table
+#[cfg(feature = "doc")]
+fn read_pieces(clx: &[u8]) -> Result<Vec<Piece>, String> {
+    let mut pos = 0;
+    while clx.get(pos) == Some(&0x01) {
+        pos += 3 + read_u16(clx, pos + 1)? as usize;
+    }
+    if clx.get(pos) != Some(&0x02) {
+        return Err("malformed piece table".to_string());
+    }
+
+    let lcb = read_u32(clx, pos + 1)? as usize;
+    let plc = clx
+        .get(pos + 5..pos + 5 + lcb)
+        .ok_or("malformed piece table")?;
+    let count = lcb.saturating_sub(4) / 12;
+
+    (0..count)
+        .map(|i| {
+            let fc = read_u32(plc, 4 * (count + 1) + 8 * i + 2)?;
+            let compressed = fc & 0x4000_0000 != 0;
+            let fc = (fc & !0x4000_0000) as usize;
+            Ok(Piece {
+                cp_start: read_u32(plc, 4 * i)? as usize,
+                cp_end: read_u32(plc, 4 * (i + 1))? as usize,
+                offset: if compressed { fc / 2 } else { fc },
+                compressed,
+            })
+        })
+        .collect()
+}
+
+/// Turn Word's control characters into line breaks and drop field codes,
+/// keeping field results
+#[cfg(feature = "doc")]
+fn clean_text(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut in_field_code = false;
+
+    for c in text.chars() {
+        match c {
+            '\u{13}' => in_field_code = true,
+            '\u{14}' | '\u{15}' => in_field_code = false,
+            _ if in_field_code => {}
+            '\r' | '\u{07}' | '\u{0B}' | '\u{0C}' => cleaned.push('\n'),
+            '\t' => cleaned.push('\t'),
+            c if c.is_control() => {}
+            c => cleaned.push(c),
+        }
+    }
+
+    cleaned
+}
+
+/// The character a Windows-1252 byte stands for
+pub(super) fn cp1252_char(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž',
+        '\u{8F}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}',
+        'ž', 'Ÿ',
+    ];
+
+    match byte {
+        0x80..=0x9F => HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+#[cfg(feature = "doc")]
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16, String> {
+    bytes
+        .get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "truncated document".to_string())
+}
+
+#[cfg(feature = "doc")]
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32, String> {
+    bytes
+        .get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated document".to_string())
+}
+
+#[cfg(all(test, feature = "doc"))]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_extracts_text_from_simple_doc() {
+        let bytes =
+            fixtures::legacy_doc(&["Meeting with Alice Johnson", "Zoë → Bob Smith", "Café"]);
+        assert!(is_legacy_doc(&bytes));
+
+        let text = extract_text(&bytes).unwrap();
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            ["Meeting with Alice Johnson", "Zoë → Bob Smith", "Café"]
+        );
+
+        let needles = vec![("Bob Smith".to_string(), "id=7".to_string())];
+        let extraction = occurrences_mem(
+            &needles,
+            &bytes,
+            Path::new("old.doc"),
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(extraction.text_bytes, text.len() as u64);
+        let occurrences = extraction.occurrences;
+        assert_eq!(occurrences.len(), 1);
+        assert_eq!(occurrences[0].line, 1);
+        assert_eq!(
+            occurrences[0].location,
+            crate::search::BlockLocation::PlainLine { line: 1 }
+        );
+    }
+
+    #[test]
+    fn test_fast_saved_doc_is_refused() {
+        let bytes = fixtures::legacy_doc_with_flags(&["text"], 0x0004);
+
+        let err = extract_text(&bytes).unwrap_err();
+        assert!(err.contains("fast-saved"), "{}", err);
+    }
+}
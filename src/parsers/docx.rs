@@ -1,18 +1,30 @@
 use anyhow::Result;
-use colored::Colorize;
 use std::{
-    collections::HashSet,
+    collections::HashMap,
     fs::File,
     io::{Cursor, Error, ErrorKind, Read},
-    time::Instant,
+    ops::ControlFlow,
+    path::Path,
 };
 use zip::ZipArchive;
 
-use crate::utils::read_needles_from_file;
-use crate::types::SearchResult;
+use super::doc::{self, is_legacy_doc};
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{
+    merge_results, results_from_occurrences, search_blocks, BlockBudget, BlockLocation, Extraction,
+    TextBlock,
+};
+use crate::types::{ContextKind, ExtractOptions, FileType, Location, SearchResult, SearchResults};
+
+/// Namespace of `r:id` attributes that point into a part's relationships
+const R_NAMESPACE: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
 
 enum AttributeType {
     OfficeDocument,
+    CoreProperties,
 }
 
 impl AttributeType {
@@ -21,23 +33,111 @@ impl AttributeType {
             AttributeType::OfficeDocument => {
                 "http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument"
             }
+            AttributeType::CoreProperties => {
+                "http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties"
+            }
         }
     }
 }
 
+/// The core properties of a DOCX document, from its `docProps/core.xml`
+/// part; a property the document does not set is `None`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocxMeta {
+    pub title: Option<String>,
+    /// `dc:creator`
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    /// As written, usually separated by commas or semicolons
+    pub keywords: Option<String>,
+    /// When the document was created, as the W3C date and time it records
+    pub created: Option<String>,
+    /// When the document was last saved, as the W3C date and time it records
+    pub modified: Option<String>,
+}
+
+impl DocxMeta {
+    /// The properties that are set, by name, in the order declared
+    pub fn fields(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("title", &self.title),
+            ("author", &self.author),
+            ("subject", &self.subject),
+            ("keywords", &self.keywords),
+            ("created", &self.created),
+            ("modified", &self.modified),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+        .collect()
+    }
+
+    /// The properties searched with `--search-meta`: the dates are left out
+    pub(super) fn searchable(&self) -> Vec<(&'static str, &str)> {
+        self.fields()
+            .into_iter()
+            .filter(|(name, _)| !matches!(*name, "created" | "modified"))
+            .collect()
+    }
+}
+
+/// Read the core properties of the DOCX file at `path`. A document without
+/// a core properties part has none set.
+pub fn extract_docx_meta(path: &Path) -> Result<DocxMeta> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    Ok(core_properties(&mut archive))
+}
+
+/// The core properties of a document, from the part the package
+/// relationships name, or `docProps/core.xml` when they name none
+fn core_properties<R>(archive: &mut ZipArchive<R>) -> DocxMeta
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
+    let name = read_relationships(archive, "")
+        .into_iter()
+        .find(|(_, kind, _)| kind == AttributeType::CoreProperties.as_str())
+        .map_or_else(
+            || "docProps/core.xml".to_string(),
+            |(_, _, target)| target.trim_start_matches('/').to_string(),
+        );
+    let Some(buffer) = read_part(archive, &name) else {
+        return DocxMeta::default();
+    };
+    let Ok(xml) = roxmltree::Document::parse(&buffer) else {
+        return DocxMeta::default();
+    };
+
+    let property = |name: &str| {
+        let value = xml
+            .descendants()
+            .find(|elem| elem.has_tag_name(name))?
+            .text()?
+            .trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+    DocxMeta {
+        title: property("title"),
+        author: property("creator"),
+        subject: property("subject"),
+        keywords: property("keywords"),
+        created: property("created"),
+        modified: property("modified"),
+    }
+}
+
 fn get_doc_name<R>(archive: &mut ZipArchive<R>) -> Option<String>
 where
     R: std::io::Seek,
     R: std::io::Read,
 {
     let mut doc_name = None;
-    let names: Vec<_> = archive.file_names().collect();
-    println!("Found {} files in archive, {:?}", names.len(), names);
-    let mut rels = archive.by_name("_rels/.rels").unwrap();
+    let mut rels = archive.by_name("_rels/.rels").ok()?;
     let mut rels_buffer = String::new();
-    rels.read_to_string(&mut rels_buffer).unwrap();
+    rels.read_to_string(&mut rels_buffer).ok()?;
 
-    let rel_xml = roxmltree::Document::parse(&rels_buffer).unwrap();
+    let rel_xml = roxmltree::Document::parse(&rels_buffer).ok()?;
 
     for elem in rel_xml.descendants() {
         'outer: for attr in elem.attributes() {
@@ -53,56 +153,559 @@ where
     doc_name
 }
 
+/// [`DocumentParser`] for Word documents, DOCX or legacy DOC: they are told
+/// apart by their contents, as a `.doc` is often renamed to `.docx`
+pub struct DocxParser;
+
+impl DocumentParser for DocxParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Docx, FileType::Doc]
+    }
+
+    fn extract_text(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        if is_legacy_doc(bytes) {
+            return doc::document(bytes, path);
+        }
+        Ok(ExtractedDocument::new(blocks_mem(bytes, extract)?))
+    }
+
+    fn extract_within(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        budget: BlockBudget,
+    ) -> Result<Vec<TextBlock>> {
+        if is_legacy_doc(bytes) {
+            return Ok(doc::blocks_mem(bytes, path)?
+                .into_iter()
+                .take(budget.blocks)
+                .collect());
+        }
+        blocks_within(bytes, extract, budget)
+    }
+
+    fn each_block(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        on_block: &mut dyn FnMut(TextBlock) -> ControlFlow<()>,
+    ) -> Result<()> {
+        if is_legacy_doc(bytes) {
+            let _ = doc::blocks_mem(bytes, path)?
+                .into_iter()
+                .try_for_each(on_block);
+            return Ok(());
+        }
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        each_haystack_block(&mut archive, extract, on_block)
+    }
+}
+
+/// Search an in-memory DOCX document for the needles of an in-memory
+/// needles file, searching as `extract` says
 pub fn parse_from_mem(
     needle_bytes: &[u8],
     haystack_bytes: &[u8],
-) -> Result<HashSet<SearchResult>> {
-    let needles = crate::utils::read_needles_from_mem(needle_bytes)?;
-    println!("Searching across {} contacts", needles.len());
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_mem_for_needles(&DocxParser, needle_bytes, haystack_bytes, extract)
+}
+
+/// Search an in-memory DOCX document with needles that were already loaded.
+///
+/// Unlike [`parse_from_mem`] this does not re-read the needles for every
+/// document, which makes it suitable for worker threads in batch mode.
+pub fn search_mem(needles: &[(String, String)], haystack_bytes: &[u8]) -> Result<SearchResults> {
+    Ok(results_from_occurrences(
+        &occurrences_mem(needles, haystack_bytes, ExtractOptions::default())?.occurrences,
+    ))
+}
+
+/// Like [`search_mem`], but report every occurrence rather than the distinct
+/// needles, also searching the content `extract` opts into
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    let haystack = extract_haystack(&mut archive, extract, None)?;
 
-    let haystack_reader = Cursor::new(haystack_bytes);
-    let mut archive = ZipArchive::new(haystack_reader)?;
+    Ok(search_blocks(needles, &haystack, extract))
+}
 
-    parse(&needles, &mut archive)
+/// The blocks [`occurrences_mem`] searches
+pub fn blocks_mem(haystack_bytes: &[u8], extract: ExtractOptions) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, extract, None)
 }
 
-pub fn parse_from_path(needle_path: &str, file_path: &str) -> Result<HashSet<SearchResult>> {
-    let start = Instant::now();
-    let needles = read_needles_from_file(needle_path)?;
-    println!(
-        "{}",
-        format!(
-            "Read {} contacts in {} ms",
-            needles.len(),
-            start.elapsed().as_millis()
-        )
-        .blue()
-    );
+/// The first blocks of [`blocks_mem`], as many as `budget` allows
+pub fn blocks_within(
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+    budget: BlockBudget,
+) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, extract, Some(budget))
+}
 
-    let start = Instant::now();
-    let file: File = File::open(file_path)?;
-    let mut archive = ZipArchive::new(file)?;
-    println!(
-        "{}",
-        format!("Opened archive in {} ms", start.elapsed().as_millis()).blue()
-    );
-    parse(&needles, &mut archive)
+/// Search a DOCX file on disk for the needles of a needles file, searching
+/// as `extract` says: [`parse_iter`], with the results of each needle
+/// combined
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    Ok(merge_results(
+        parse_iter(needle_path, file_path, extract).collect::<Result<_, _>>()?,
+    ))
 }
 
-fn parse<R>(
+/// Search a DOCX file on disk for the needles of a needles file, yielding
+/// each needle once for every paragraph it is found in, as that paragraph
+/// is read. The document's XML is parsed whole, but its paragraphs are
+/// searched one at a time and no more than a few results are held.
+pub fn parse_iter(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> impl Iterator<Item = Result<SearchResult, DocSearchError>> + Send {
+    super::search_path_iter(&DocxParser, needle_path, file_path, extract)
+}
+
+/// Search a DOCX file on disk with needles that were already loaded,
+/// reporting every occurrence.
+///
+/// Legacy `.doc` files (including ones renamed to `.docx`) are handed to
+/// the [`doc`](super::doc) parser.
+pub fn parse_with_needles(
     needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(&DocxParser, needles, file_path, extract, &mut NullReporter)
+}
+
+/// Read a part from the archive as a string
+pub(super) fn read_part<R>(archive: &mut ZipArchive<R>, name: &str) -> Option<String>
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
+    let mut part = archive.by_name(name).ok()?;
+    let mut buffer = String::new();
+    part.read_to_string(&mut buffer).ok()?;
+    Some(buffer)
+}
+
+/// The relationships of the part `doc_name` as `(id, type, target)`, from
+/// its `_rels/<name>.rels` part. A missing or unreadable part has none.
+pub(super) fn read_relationships<R>(
+    archive: &mut ZipArchive<R>,
+    doc_name: &str,
+) -> Vec<(String, String, String)>
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
+    let (dir, file) = doc_name.rsplit_once('/').unwrap_or(("", doc_name));
+    let rels_name = if dir.is_empty() {
+        format!("_rels/{}.rels", file)
+    } else {
+        format!("{}/_rels/{}.rels", dir, file)
+    };
+
+    let Some(buffer) = read_part(archive, &rels_name) else {
+        return Vec::new();
+    };
+    let Ok(rel_xml) = roxmltree::Document::parse(&buffer) else {
+        return Vec::new();
+    };
+
+    rel_xml
+        .descendants()
+        .filter(|elem| elem.has_tag_name("Relationship"))
+        .filter_map(|elem| {
+            Some((
+                elem.attribute("Id")?.to_owned(),
+                elem.attribute("Type")?.to_owned(),
+                elem.attribute("Target")?.to_owned(),
+            ))
+        })
+        .collect()
+}
+
+/// Relationship ids of the part `doc_name` and their targets
+fn get_relationships<R>(archive: &mut ZipArchive<R>, doc_name: &str) -> HashMap<String, String>
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
+    read_relationships(archive, doc_name)
+        .into_iter()
+        .map(|(id, _, target)| (id, target))
+        .collect()
+}
+
+/// The header, footer, footnotes, endnotes and comments parts of the part
+/// `doc_name`, as the kind of part and its archive path, in the order its
+/// relationships list them
+fn get_story_parts<R>(archive: &mut ZipArchive<R>, doc_name: &str) -> Vec<(StoryPart, String)>
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
+    let dir = doc_name.rsplit_once('/').map_or("", |(dir, _)| dir);
+    read_relationships(archive, doc_name)
+        .into_iter()
+        .filter_map(|(_, kind, target)| {
+            let part = match kind.rsplit('/').next()? {
+                "header" => StoryPart::Header,
+                "footer" => StoryPart::Footer,
+                "footnotes" => StoryPart::Footnotes,
+                "endnotes" => StoryPart::Endnotes,
+                "comments" => StoryPart::Comments,
+                _ => return None,
+            };
+            // Targets are relative to the part's directory, or absolute from
+            // the archive root
+            let name = match target.strip_prefix('/') {
+                Some(absolute) => absolute.to_string(),
+                None if dir.is_empty() => target,
+                None => format!("{}/{}", dir, target),
+            };
+            Some((part, name))
+        })
+        .collect()
+}
+
+/// A part of a DOCX document searched after its body
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StoryPart {
+    Header,
+    Footer,
+    Footnotes,
+    Endnotes,
+    Comments,
+}
+
+impl StoryPart {
+    /// Where `paragraph` of this part is: the part itself for headers and
+    /// footers, or the note or comment holding it. Separator notes, which
+    /// hold no text of the author's, are not anywhere.
+    fn locate(self, paragraph: roxmltree::Node) -> Option<Location> {
+        let container = |tag: &str| paragraph.ancestors().find(|elem| elem.has_tag_name(tag));
+        let note = |tag: &str| {
+            let note = container(tag)?;
+            if attribute(note, "type").is_some_and(|kind| kind != "normal") {
+                return None;
+            }
+            attribute(note, "id")?.parse().ok()
+        };
+        match self {
+            StoryPart::Header => Some(Location::Header),
+            StoryPart::Footer => Some(Location::Footer),
+            StoryPart::Footnotes => note("footnote").map(Location::Footnote),
+            StoryPart::Endnotes => note("endnote").map(Location::Endnote),
+            StoryPart::Comments => {
+                let comment = container("comment")?;
+                let author = attribute(comment, "author").unwrap_or_default().to_string();
+                Some(Location::Comment {
+                    author,
+                    id: attribute(comment, "id")?.parse().ok()?,
+                })
+            }
+        }
+    }
+}
+
+/// The value of the attribute `name` of `elem`, in whichever namespace
+pub(super) fn attribute<'a>(elem: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    elem.attributes()
+        .find(|attr| attr.name() == name)
+        .map(|attr| attr.value())
+}
+
+/// Whether a run is formatted as hidden text (`<w:rPr><w:vanish/>`)
+fn is_hidden_run(run: roxmltree::Node) -> bool {
+    run.children()
+        .filter(|elem| elem.has_tag_name("rPr"))
+        .flat_map(|properties| properties.children())
+        .filter(|elem| elem.has_tag_name("vanish"))
+        .any(|vanish| {
+            let value = vanish
+                .attributes()
+                .find(|attr| attr.name() == "val")
+                .map(|attr| attr.value());
+            !matches!(value, Some("0" | "false" | "off"))
+        })
+}
+
+/// The outline level of a heading paragraph, 1 for the outermost: from a
+/// `HeadingN` paragraph style, or an explicit `w:outlineLvl`
+fn heading_level(paragraph: roxmltree::Node) -> Option<usize> {
+    let properties = paragraph.children().find(|elem| elem.has_tag_name("pPr"))?;
+    properties.children().find_map(|elem| {
+        let value = elem.attributes().find(|attr| attr.name() == "val")?.value();
+        let level = match elem.tag_name().name() {
+            "outlineLvl" => value.parse::<usize>().ok()? + 1,
+            "pStyle" => value.strip_prefix("Heading")?.parse().ok()?,
+            _ => return None,
+        };
+        (1..=9).contains(&level).then_some(level)
+    })
+}
+
+/// The row and column, counted from 1, of the table cell `paragraph` is in,
+/// the innermost one for nested tables
+fn table_cell(paragraph: roxmltree::Node) -> Option<(u32, u32)> {
+    let cell = paragraph.ancestors().find(|elem| elem.has_tag_name("tc"))?;
+    let row = cell.parent().filter(|elem| elem.has_tag_name("tr"))?;
+    let position = |node: roxmltree::Node, tag: &str| {
+        node.prev_siblings()
+            .filter(|elem| elem.has_tag_name(tag))
+            .count() as u32
+    };
+    Some((position(row, "tr"), position(cell, "tc")))
+}
+
+/// The page breaks among the `own` elements of `paragraph`: those before
+/// its first text, which start the paragraph on a new page, and the rest.
+///
+/// With `rendered`, the breaks are the `w:lastRenderedPageBreak`s Word
+/// wrote where each page began when it last laid the document out; these
+/// include explicit breaks, so explicit ones are not counted again. Without
+/// them, the breaks are `w:br w:type="page"` and a `w:pageBreakBefore`
+/// paragraph property.
+fn page_breaks<'a>(
+    paragraph: roxmltree::Node<'a, '_>,
+    own: impl Fn(&roxmltree::Node<'a, '_>) -> bool,
+    rendered: bool,
+) -> (usize, usize) {
+    let mut before = 0;
+    let mut after = 0;
+    let mut text_seen = false;
+    for elem in paragraph.descendants().filter(|elem| own(elem)) {
+        let is_break = match elem.tag_name().name() {
+            "t" => {
+                text_seen |= !elem.text().unwrap_or_default().is_empty();
+                false
+            }
+            "lastRenderedPageBreak" => rendered,
+            "br" => {
+                !rendered
+                    && elem
+                        .attributes()
+                        .any(|attr| attr.name() == "type" && attr.value() == "page")
+            }
+            "pageBreakBefore" => {
+                let value = elem
+                    .attributes()
+                    .find(|attr| attr.name() == "val")
+                    .map(|attr| attr.value());
+                !rendered && !matches!(value, Some("0" | "false" | "off"))
+            }
+            _ => false,
+        };
+        match (is_break, text_seen) {
+            (true, false) => before += 1,
+            (true, true) => after += 1,
+            _ => {}
+        }
+    }
+    (before, after)
+}
+
+/// Whether the whitespace at the edges of text element `t` is kept, as
+/// `xml:space="preserve"` on it or an enclosing element asks
+fn preserves_space(t: roxmltree::Node) -> bool {
+    t.ancestors()
+        .find_map(|elem| elem.attribute((XML_NAMESPACE, "space")))
+        == Some("preserve")
+}
+
+/// Append the text of `run` to `text`.
+///
+/// Like Word, whitespace at the start and end of a `w:t` is dropped unless
+/// it has `xml:space="preserve"`. Line breaks (`w:br`, `w:cr`) and tabs
+/// become a single space, so a phrase needle matches across them as it
+/// would across a space. A non-breaking hyphen becomes `-`, and an optional
+/// hyphen, shown only where Word breaks the word, is dropped.
+fn push_run_text(run: roxmltree::Node, text: &mut String) {
+    for elem in run.children().filter(|elem| elem.is_element()) {
+        match elem.tag_name().name() {
+            "t" if preserves_space(elem) => text.push_str(elem.text().unwrap_or_default()),
+            "t" => text.push_str(
+                elem.text()
+                    .unwrap_or_default()
+                    .trim_matches([' ', '\t', '\r', '\n']),
+            ),
+            "br" | "cr" | "tab" => text.push(' '),
+            "noBreakHyphen" => text.push('-'),
+            _ => {}
+        }
+    }
+}
+
+/// The visible text of `paragraph`, whose runs are those `own` accepts, and
+/// the link targets and hidden runs `extract` asks for as blocks of their
+/// own at `location`. Link targets are looked up in `relationships`.
+fn paragraph_text<'a>(
+    paragraph: roxmltree::Node<'a, '_>,
+    own: impl Fn(&roxmltree::Node<'a, '_>) -> bool,
+    location: &BlockLocation,
+    extract: ExtractOptions,
+    relationships: &HashMap<String, String>,
+) -> (String, Vec<TextBlock>) {
+    let concealed = |text: String, kind: ContextKind| TextBlock {
+        concealed: Some(kind),
+        ..TextBlock::new(text, location.clone())
+    };
+
+    let mut text = String::new();
+    let mut concealed_lines = Vec::new();
+    for elem in paragraph.descendants().filter(|elem| own(elem)) {
+        if elem.has_tag_name("hyperlink") && extract.include_link_targets {
+            if let Some(target) = elem
+                .attribute((R_NAMESPACE, "id"))
+                .and_then(|id| relationships.get(id))
+            {
+                concealed_lines.push(concealed(target.clone(), ContextKind::LinkTarget));
+            }
+        } else if elem.has_tag_name("r") {
+            if !is_hidden_run(elem) {
+                push_run_text(elem, &mut text);
+            } else if extract.include_hidden_text {
+                let mut hidden = String::new();
+                push_run_text(elem, &mut hidden);
+                concealed_lines.push(concealed(hidden, ContextKind::Hidden));
+            }
+        }
+    }
+    concealed_lines.retain(|line| !line.text.is_empty());
+    (text, concealed_lines)
+}
+
+/// The paragraphs of the header, footer, notes or comments part `name`,
+/// read like those of the body, with the author of each comment as a block
+/// of its own. A missing or unreadable part has none.
+fn story_blocks<R>(
     archive: &mut ZipArchive<R>,
-) -> Result<HashSet<SearchResult>>
+    name: &str,
+    story: StoryPart,
+    extract: ExtractOptions,
+) -> Vec<TextBlock>
 where
     R: std::io::Seek,
     R: std::io::Read,
 {
-    let start = Instant::now();
-    println!("{}", format!("Creating haystack from document...",).blue());
+    let relationships = if extract.include_link_targets {
+        get_relationships(archive, name)
+    } else {
+        HashMap::new()
+    };
+    let Some(buffer) = read_part(archive, name) else {
+        return Vec::new();
+    };
+    let Ok(xml) = roxmltree::Document::parse(&buffer) else {
+        return Vec::new();
+    };
 
+    let mut blocks = Vec::new();
+    // Paragraphs are counted within each note or comment, and within the
+    // whole of a header or footer
+    let mut counted: Option<(Location, usize)> = None;
+    for paragraph in xml.descendants().filter(|elem| elem.has_tag_name("p")) {
+        let Some(part) = story.locate(paragraph) else {
+            continue;
+        };
+        let index = match &counted {
+            Some((counted_part, count)) if *counted_part == part => *count,
+            _ => 0,
+        };
+        counted = Some((part.clone(), index + 1));
+        let location = BlockLocation::DocxPart { part, index };
+        if let BlockLocation::DocxPart {
+            part: Location::Comment { author, .. },
+            index: 0,
+        } = &location
+        {
+            if !author.is_empty() {
+                blocks.push(TextBlock {
+                    concealed: Some(ContextKind::CommentAuthor),
+                    ..TextBlock::new(author.clone(), location.clone())
+                });
+            }
+        }
+        let own = |elem: &roxmltree::Node| {
+            elem.ancestors().skip(1).find(|node| node.has_tag_name("p")) == Some(paragraph)
+        };
+        let (text, concealed_lines) =
+            paragraph_text(paragraph, own, &location, extract, &relationships);
+        if !text.is_empty() {
+            blocks.push(TextBlock::new(text, location));
+        }
+        blocks.extend(concealed_lines);
+    }
+    blocks
+}
+
+/// Collect the blocks of [`each_haystack_block`]. With a `budget`, stop once
+/// it is spent.
+fn extract_haystack<R>(
+    archive: &mut ZipArchive<R>,
+    extract: ExtractOptions,
+    budget: Option<BlockBudget>,
+) -> Result<Vec<TextBlock>>
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
+    let mut haystack = Vec::new();
+    each_haystack_block(archive, extract, &mut |block| {
+        haystack.push(block);
+        match budget.is_some_and(|budget| budget.spent(haystack.len())) {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        }
+    })?;
+    if let Some(budget) = budget {
+        haystack.truncate(budget.blocks);
+    }
+    Ok(haystack)
+}
+
+/// Hand `on_block` the text of every paragraph in the main document part,
+/// plus hyperlink targets and hidden runs when `extract` asks for them, each
+/// located at its paragraph under the headings before it, then the
+/// paragraphs of its headers, footers, footnotes, endnotes and comments,
+/// then its properties when `extract` asks for them. Stop when it breaks.
+fn each_haystack_block<R>(
+    archive: &mut ZipArchive<R>,
+    extract: ExtractOptions,
+    on_block: &mut dyn FnMut(TextBlock) -> ControlFlow<()>,
+) -> Result<()>
+where
+    R: std::io::Seek,
+    R: std::io::Read,
+{
     let doc_name = get_doc_name(archive)
         .ok_or_else(|| Error::new(ErrorKind::NotFound, "Could not find document name"))?;
-    println!("Found document name: {}", doc_name);
+
+    let relationships = if extract.include_link_targets {
+        get_relationships(archive, &doc_name)
+    } else {
+        HashMap::new()
+    };
 
     let mut document = archive
         .by_name(&doc_name)
@@ -115,6 +718,7 @@ where
             "Failed to write document to buffer",
         )
     })?;
+    drop(document);
 
     let doc = roxmltree::Document::parse(&buffer)
         .map_err(|_| Error::new(ErrorKind::InvalidInput, "Could not parse XML tree"))?;
@@ -128,56 +732,708 @@ where
         .first_element_child()
         .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Root node is empty"))?;
 
-    let haystack = body
+    // The enclosing headings, with their levels, outermost first
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let rendered_breaks = body
+        .descendants()
+        .any(|elem| elem.has_tag_name("lastRenderedPageBreak"));
+    let mut page = 1;
+    for (index, paragraph) in body
         .descendants()
         .filter(|elem| elem.has_tag_name("p"))
-        .fold(Vec::new(), |mut acc, elem| {
-            elem.descendants()
-                .filter(|elem| elem.has_tag_name("r"))
-                .for_each(|elem| {
-                    elem.descendants()
-                        .filter(|elem| elem.has_tag_name("t"))
-                        .for_each(|elem| {
-                            elem.text().and_then(|text| {
-                                return Some(acc.push(text));
-                            });
-                        });
-                });
+        .enumerate()
+    {
+        // Paragraphs nested in this one, such as text boxes, are lines of their own
+        let own = |elem: &roxmltree::Node| {
+            elem.ancestors().skip(1).find(|node| node.has_tag_name("p")) == Some(paragraph)
+        };
+
+        let level = heading_level(paragraph);
+        if let Some(level) = level {
+            headings.retain(|(enclosing, _)| *enclosing < level);
+        }
+        let (breaks_before, breaks_after) = page_breaks(paragraph, own, rendered_breaks);
+        page += breaks_before;
+        let location = BlockLocation::DocxParagraph {
+            index,
+            heading_path: headings
+                .iter()
+                .map(|(_, heading)| heading.clone())
+                .collect(),
+            page,
+            cell: table_cell(paragraph),
+        };
+        page += breaks_after;
 
-            acc
-        });
-    println!(
-        "{}",
-        format!(
-            "Haystack created. Extracted {} lines from document in {} ms",
-            haystack.len(),
-            start.elapsed().as_millis()
+        // The visible runs of a paragraph form one block, so phrases match
+        // across run boundaries; link targets and hidden runs follow it as
+        // blocks of their own
+        let (text, concealed_lines) =
+            paragraph_text(paragraph, own, &location, extract, &relationships);
+
+        if let Some(level) = level.filter(|_| !text.trim().is_empty()) {
+            headings.push((level, text.trim().to_string()));
+        }
+        let blocks = (!text.is_empty()).then(|| TextBlock::new(text, location));
+        if blocks
+            .into_iter()
+            .chain(concealed_lines)
+            .try_for_each(&mut *on_block)
+            .is_break()
+        {
+            return Ok(());
+        }
+    }
+
+    // After the body, so blocks keep their index whether or not a document
+    // has headers, footers, notes or comments
+    for (story, name) in get_story_parts(archive, &doc_name) {
+        if story_blocks(archive, &name, story, extract)
+            .into_iter()
+            .try_for_each(&mut *on_block)
+            .is_break()
+        {
+            return Ok(());
+        }
+    }
+    if extract.include_metadata {
+        let meta = core_properties(archive);
+        let _ = meta
+            .searchable()
+            .into_iter()
+            .map(|(name, value)| TextBlock {
+                concealed: Some(ContextKind::Metadata),
+                ..TextBlock::new(
+                    value,
+                    BlockLocation::DocxProperty {
+                        name: name.to_string(),
+                    },
+                )
+            })
+            .try_for_each(on_block);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures,
+        types::{MatchOptions, SearchResult},
+    };
+
+    /// A mailto link whose target holds a needle, and a hidden run holding another
+    fn concealed_docx() -> Vec<u8> {
+        fixtures::docx_with_hyperlinks(
+            "<w:p><w:hyperlink r:id=\"rId7\"><w:r><w:t>Email our counsel</w:t></w:r></w:hyperlink></w:p>\
+             <w:p><w:r><w:t xml:space=\"preserve\">Reviewed by </w:t></w:r>\
+             <w:r><w:rPr><w:vanish/></w:rPr><w:t>Bob Smith</w:t></w:r></w:p>\
+             <w:p><w:r><w:rPr><w:vanish w:val=\"false\"/></w:rPr><w:t>Carol Diaz</w:t></w:r></w:p>",
+            &[("rId7", "mailto:alice.johnson@company.com")],
         )
-        .blue()
-    );
+    }
 
-    println!("{}", "Starting search...".blue());
-    let start = Instant::now();
-    let matches = haystack.iter().fold(HashSet::new(), |mut acc, substack| {
-        needles
+    fn needles() -> Vec<(String, String)> {
+        ["alice.johnson", "Bob Smith", "Carol Diaz"]
             .iter()
-            .filter(|needle| substack.contains(&needle.0))
-            .for_each(|needle| {
-                acc.insert((needle.0.clone(), needle.1.clone()));
-            });
-
-        acc
-    });
-    println!(
-        "{}",
-        format!("Search completed in {} ms", start.elapsed().as_millis()).blue()
-    );
-
-    println!("{}", format!("Found {} matches:", matches.len(),).green());
-    matches
-        .iter()
-        .enumerate()
-        .for_each(|(i, match_)| println!("{}", format!("{}: {:?}", i + 1, match_).green()));
+            .map(|term| (term.to_string(), String::new()))
+            .collect()
+    }
+
+    fn found(extract: ExtractOptions) -> Vec<(String, ContextKind)> {
+        occurrences_mem(&needles(), &concealed_docx(), extract)
+            .unwrap()
+            .occurrences
+            .into_iter()
+            .map(|occurrence| (occurrence.term, occurrence.context_kind))
+            .collect()
+    }
+
+    /// The lines extracted from a document body
+    fn lines(body: &str) -> Vec<String> {
+        blocks_mem(
+            &fixtures::docx_from_body_xml(body),
+            ExtractOptions::default(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|line| line.text)
+        .collect()
+    }
+
+    fn matches(body: &str, needle: &str) -> bool {
+        let needles = [(needle.to_string(), String::new())];
+        !occurrences_mem(
+            &needles,
+            &fixtures::docx_from_body_xml(body),
+            ExtractOptions::default(),
+        )
+        .unwrap()
+        .occurrences
+        .is_empty()
+    }
+
+    #[test]
+    fn test_runs_of_a_paragraph_form_one_line() {
+        let body = "<w:p><w:r><w:t xml:space=\"preserve\">Alice </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>John</w:t></w:r><w:r><w:t>son</w:t></w:r></w:p>\
+                    <w:p><w:r><w:t>Bob Smith</w:t></w:r></w:p>";
+        assert_eq!(lines(body), ["Alice Johnson", "Bob Smith"]);
+        assert!(matches(body, "Alice Johnson"));
+        assert!(!matches(body, "Johnson Bob"));
+    }
+
+    #[test]
+    fn test_edge_whitespace_is_kept_only_where_preserved() {
+        let body = "<w:p><w:r><w:t>Ali</w:t></w:r><w:r><w:t>ce</w:t></w:r>\
+                    <w:r><w:t xml:space=\"preserve\"> </w:t></w:r><w:r><w:t xml:space=\"preserve\">Johnson, </w:t></w:r>\
+                    <w:r><w:t> Bob </w:t></w:r><w:r><w:t>Smith</w:t></w:r></w:p>";
+        assert_eq!(lines(body), ["Alice Johnson, BobSmith"]);
+        assert!(matches(body, "Alice Johnson"));
+        assert!(!matches(body, "Bob Smith"));
+
+        // Inherited from an enclosing element
+        let body = "<w:p xml:space=\"preserve\"><w:r><w:t>Bob </w:t></w:r><w:r><w:t>Smith</w:t></w:r></w:p>";
+        assert!(matches(body, "Bob Smith"));
+    }
+
+    #[test]
+    fn test_breaks_and_tabs_match_as_spaces() {
+        for separator in ["<w:br/>", "<w:br w:type=\"page\"/>", "<w:cr/>", "<w:tab/>"] {
+            // Inside one run and between two runs
+            for body in [
+                format!(
+                    "<w:p><w:r><w:t>Alice</w:t>{}<w:t>Johnson</w:t></w:r></w:p>",
+                    separator
+                ),
+                format!(
+                    "<w:p><w:r><w:t>Alice</w:t></w:r><w:r>{}<w:t>Johnson</w:t></w:r></w:p>",
+                    separator
+                ),
+            ] {
+                assert_eq!(lines(&body), ["Alice Johnson"], "{}", body);
+                assert!(matches(&body, "Alice Johnson"), "{}", body);
+                assert!(
+                    matches(&body, "Alice") && matches(&body, "Johnson"),
+                    "{}",
+                    body
+                );
+                assert!(!matches(&body, "AliceJohnson"), "{}", body);
+            }
+        }
+
+        // Tab stop definitions in paragraph properties are not text
+        let body = "<w:p><w:pPr><w:tabs><w:tab w:val=\"left\" w:pos=\"720\"/></w:tabs></w:pPr><w:r><w:t>Alice</w:t></w:r></w:p>";
+        assert_eq!(lines(body), ["Alice"]);
+    }
+
+    #[test]
+    fn test_hyphen_elements() {
+        let body = "<w:p><w:r><w:t>Jean</w:t><w:noBreakHyphen/><w:t>Paul Sartre</w:t></w:r></w:p>";
+        assert_eq!(lines(body), ["Jean-Paul Sartre"]);
+        assert!(matches(body, "Jean-Paul"));
+
+        let body = "<w:p><w:r><w:t>John</w:t><w:softHyphen/><w:t>son</w:t></w:r></w:p>";
+        assert_eq!(lines(body), ["Johnson"]);
+        assert!(matches(body, "Johnson"));
+    }
+
+    #[test]
+    fn test_case_sensitivity() {
+        let document = fixtures::docx(&["Signed by alice JOHNSON", "Alice Johnson, counsel"]);
+        let needles = [
+            ("Alice Johnson".to_string(), "id=1".to_string()),
+            ("ALICE johnson".to_string(), "id=2".to_string()),
+        ];
+        let found = |case_sensitive| {
+            let extract = ExtractOptions {
+                matching: MatchOptions {
+                    case_sensitive,
+                    ..MatchOptions::default()
+                },
+                ..ExtractOptions::default()
+            };
+            let occurrences = occurrences_mem(&needles, &document, extract)
+                .unwrap()
+                .occurrences;
+            occurrences
+                .into_iter()
+                .map(|occurrence| (occurrence.term, occurrence.line, occurrence.span))
+                .collect::<Vec<_>>()
+        };
+
+        // Every needle is reported as written, with the span of the text it matched
+        assert_eq!(
+            found(false),
+            [
+                ("Alice Johnson".to_string(), 0, 10..23),
+                ("ALICE johnson".to_string(), 0, 10..23),
+                ("Alice Johnson".to_string(), 1, 0..13),
+                ("ALICE johnson".to_string(), 1, 0..13),
+            ]
+        );
+        assert_eq!(found(true), [("Alice Johnson".to_string(), 1, 0..13)]);
+    }
+
+    #[test]
+    fn test_needles_files_match_in_any_case_unless_asked() {
+        let document = fixtures::docx(&["Counsel: BOB smith", "cc: carol DIAZ"]);
+        let needles = b"bob Smith,id=2\nCarol Diaz,id=3\nDave,id=4\n";
+        let found = |case_sensitive| {
+            let mut found: Vec<SearchResult> = parse_from_mem(
+                needles,
+                &document,
+                ExtractOptions {
+                    matching: MatchOptions {
+                        case_sensitive,
+                        ..MatchOptions::default()
+                    },
+                    ..ExtractOptions::default()
+                },
+            )
+            .unwrap()
+            .into_iter()
+            .collect();
+            found.sort_by(|a, b| a.term.cmp(&b.term));
+            found
+        };
+
+        let on_line = |term, metadata, line: &str| SearchResult {
+            page: Some(1),
+            context: Some(line.to_string()),
+            ..SearchResult::new(term, metadata)
+        };
+        assert_eq!(
+            found(false),
+            [
+                on_line("Carol Diaz", "id=3", "cc: carol DIAZ"),
+                on_line("bob Smith", "id=2", "Counsel: BOB smith")
+            ]
+        );
+        assert!(found(true).is_empty());
+    }
+
+    #[test]
+    fn test_composed_and_decomposed_forms_match_unless_asked() {
+        // José García composed in the document and decomposed in the needles
+        // file, and Zoë Müller the other way round
+        let document = fixtures::docx(&["Signed: José García", "cc: Zoe\u{308} Mu\u{308}ller"]);
+        let needles = "Jose\u{301} Garci\u{301}a,id=1\nZoë Müller,id=2\n";
+        let found = |normalize| {
+            let mut found: Vec<SearchResult> = parse_from_mem(
+                needles.as_bytes(),
+                &document,
+                ExtractOptions {
+                    matching: MatchOptions {
+                        normalize,
+                        ..MatchOptions::default()
+                    },
+                    ..ExtractOptions::default()
+                },
+            )
+            .unwrap()
+            .into_iter()
+            .collect();
+            found.sort_by(|a, b| a.term.cmp(&b.term));
+            found
+        };
+
+        let on_line = |term, metadata, line: &str| SearchResult {
+            page: Some(1),
+            context: Some(line.to_string()),
+            ..SearchResult::new(term, metadata)
+        };
+        assert_eq!(
+            found(true),
+            [
+                on_line("Jose\u{301} Garci\u{301}a", "id=1", "Signed: José García"),
+                on_line("Zoë Müller", "id=2", "cc: Zoe\u{308} Mu\u{308}ller")
+            ]
+        );
+        assert!(found(false).is_empty());
+    }
+
+    #[test]
+    fn test_headers_and_footers_are_searched() {
+        let document = fixtures::docx_with_header_footer(
+            &["Minutes", "Present: Bob Smith"],
+            "Confidential – Alice Johnson",
+            "Bob Smith, page 1",
+        );
+        let needles = b"Alice Johnson,id=1\nBob Smith,id=2\nCarol Diaz,id=3\n";
+
+        let results = parse_from_mem(needles, &document, ExtractOptions::default()).unwrap();
+        let found: Vec<(&str, Location)> = results
+            .iter()
+            .map(|result| (result.term.as_str(), result.location.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Alice Johnson", Location::Header),
+                ("Bob Smith", Location::Body),
+                ("Bob Smith", Location::Footer)
+            ]
+        );
+
+        // After the body, each numbered within its part
+        let locations: Vec<String> = blocks_mem(&document, ExtractOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|block| block.location.to_string())
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                "paragraph 1",
+                "paragraph 2",
+                "header paragraph 1",
+                "footer paragraph 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notes_are_searched_apart_from_separators() {
+        let document = fixtures::docx_with_notes(
+            &["Judgment"],
+            &["Ibid.", "Alice Johnson, at 4."],
+            &["Bob Smith"],
+        );
+        let blocks = blocks_mem(&document, ExtractOptions::default()).unwrap();
+        let located: Vec<(String, String)> = blocks
+            .iter()
+            .map(|block| (block.location.to_string(), block.text.trim().to_string()))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                ("paragraph 1".to_string(), "Judgment".to_string()),
+                ("footnote 1 paragraph 1".to_string(), "Ibid.".to_string()),
+                (
+                    "footnote 2 paragraph 1".to_string(),
+                    "Alice Johnson, at 4.".to_string()
+                ),
+                ("endnote 1 paragraph 1".to_string(), "Bob Smith".to_string()),
+            ]
+        );
+
+        let results = parse_from_mem(
+            b"Alice Johnson,id=1\nBob Smith,id=2\n",
+            &document,
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        let found: Vec<Location> = results
+            .iter()
+            .map(|result| result.location.clone())
+            .collect();
+        assert_eq!(found, [Location::Footnote(2), Location::Endnote(1)]);
+    }
+
+    #[test]
+    fn test_comments_and_their_authors_are_searched() {
+        let document = fixtures::docx_with_comments(
+            &["Draft agreement"],
+            &[
+                ("Bob Smith", "Check with Alice Johnson before signing."),
+                ("", "Agreed."),
+            ],
+        );
+        let needles = b"Alice Johnson,id=1\nBob Smith,id=2\n";
+        let results = parse_from_mem(needles, &document, ExtractOptions::default()).unwrap();
+        let author = Location::Comment {
+            author: "Bob Smith".to_string(),
+            id: 0,
+        };
+        let found: Vec<(&str, Location)> = results
+            .iter()
+            .map(|result| (result.term.as_str(), result.location.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [("Alice Johnson", author.clone()), ("Bob Smith", author)]
+        );
+
+        let needles = [
+            ("Alice Johnson".to_string(), String::new()),
+            ("Bob Smith".to_string(), String::new()),
+        ];
+        let extraction = occurrences_mem(&needles, &document, ExtractOptions::default()).unwrap();
+        let found: Vec<(String, &str, ContextKind)> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| {
+                (
+                    occurrence.location.to_string(),
+                    occurrence.term.as_str(),
+                    occurrence.context_kind,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "comment 0 by Bob Smith paragraph 1".to_string(),
+                    "Bob Smith",
+                    ContextKind::CommentAuthor
+                ),
+                (
+                    "comment 0 by Bob Smith paragraph 1".to_string(),
+                    "Alice Johnson",
+                    ContextKind::Prose
+                ),
+            ]
+        );
+        let blocks = blocks_mem(&document, ExtractOptions::default()).unwrap();
+        assert_eq!(
+            blocks.last().unwrap().location.to_string(),
+            "comment 1 paragraph 1"
+        );
+    }
+
+    #[test]
+    fn test_core_properties_are_read_and_searched_when_asked() {
+        let document = fixtures::docx_with_properties(
+            &["Quarterly figures"],
+            &[
+                ("dc:title", "Review for Alice Johnson"),
+                ("dc:creator", "Bob Smith"),
+                ("cp:keywords", "finance; review"),
+                ("dcterms:created", "2024-05-12T09:30:00Z"),
+            ],
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("review.docx");
+        std::fs::write(&path, &document).unwrap();
+        let meta = extract_docx_meta(&path).unwrap();
+        assert_eq!(
+            meta,
+            DocxMeta {
+                title: Some("Review for Alice Johnson".to_string()),
+                author: Some("Bob Smith".to_string()),
+                keywords: Some("finance; review".to_string()),
+                created: Some("2024-05-12T09:30:00Z".to_string()),
+                ..DocxMeta::default()
+            }
+        );
+        let path = dir.path().join("plain.docx");
+        std::fs::write(&path, fixtures::docx(&["No properties"])).unwrap();
+        assert_eq!(extract_docx_meta(&path).unwrap(), DocxMeta::default());
+
+        let needles = [
+            ("Alice Johnson".to_string(), String::new()),
+            ("Bob Smith".to_string(), String::new()),
+        ];
+        assert!(
+            occurrences_mem(&needles, &document, ExtractOptions::default())
+                .unwrap()
+                .occurrences
+                .is_empty()
+        );
+        let extract = ExtractOptions {
+            include_metadata: true,
+            ..ExtractOptions::default()
+        };
+        let extraction = occurrences_mem(&needles, &document, extract).unwrap();
+        let found: Vec<(&str, String, ContextKind)> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| {
+                (
+                    occurrence.term.as_str(),
+                    occurrence.location.to_string(),
+                    occurrence.context_kind,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Alice Johnson",
+                    "title property".to_string(),
+                    ContextKind::Metadata
+                ),
+                (
+                    "Bob Smith",
+                    "author property".to_string(),
+                    ContextKind::Metadata
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_cells_are_searched_and_located() {
+        let docx = fixtures::docx_with_table(
+            &["Contacts"],
+            &[
+                &["Name", "Email"],
+                &["Alice Johnson", "alice.johnson@example.com"],
+                &["Bob Smith", "bob@example.com"],
+            ],
+        );
+        let results = parse_from_mem(
+            b"Alice Johnson,id=1\nbob@example.com,id=2\n",
+            &docx,
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        let found: Vec<(&str, Location)> = results
+            .iter()
+            .map(|result| (result.term.as_str(), result.location.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Alice Johnson", Location::Table { row: 2, col: 1 }),
+                ("bob@example.com", Location::Table { row: 3, col: 2 })
+            ]
+        );
+
+        let blocks = blocks_mem(&docx, ExtractOptions::default()).unwrap();
+        assert_eq!(
+            blocks[3].location.to_string(),
+            "paragraph 4 in table row 2, column 1"
+        );
+    }
 
-    Ok(matches)
+    #[test]
+    fn test_text_box_paragraphs_are_separate_lines() {
+        let body = "<w:p><w:r><w:t xml:space=\"preserve\">Before </w:t></w:r><w:r><w:pict><w:txbxContent>\
+                    <w:p><w:r><w:t>Boxed</w:t></w:r></w:p></w:txbxContent></w:pict></w:r><w:r><w:t>after</w:t></w:r></w:p>";
+        assert_eq!(lines(body), ["Before after", "Boxed"]);
+    }
+
+    #[test]
+    fn test_concealed_content_is_opt_in() {
+        assert_eq!(
+            found(ExtractOptions::default()),
+            [("Carol Diaz".to_string(), ContextKind::Prose)]
+        );
+
+        let links = ExtractOptions {
+            include_link_targets: true,
+            ..ExtractOptions::default()
+        };
+        assert_eq!(
+            found(links),
+            [
+                ("alice.johnson".to_string(), ContextKind::LinkTarget),
+                ("Carol Diaz".to_string(), ContextKind::Prose),
+            ]
+        );
+
+        let hidden = ExtractOptions {
+            include_hidden_text: true,
+            ..ExtractOptions::default()
+        };
+        assert_eq!(
+            found(hidden),
+            [
+                ("Bob Smith".to_string(), ContextKind::Hidden),
+                ("Carol Diaz".to_string(), ContextKind::Prose),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks_are_located_at_their_paragraph_and_headings() {
+        let body = "<w:p><w:pPr><w:pStyle w:val=\"Heading1\"/></w:pPr><w:r><w:t>Contracts</w:t></w:r></w:p>\
+                    <w:p><w:pPr><w:pStyle w:val=\"Heading2\"/></w:pPr><w:r><w:t>Signatories</w:t></w:r></w:p>\
+                    <w:p/>\
+                    <w:p><w:r><w:t>Alice Johnson</w:t></w:r><w:r><w:rPr><w:vanish/></w:rPr><w:t>Bob Smith</w:t></w:r></w:p>\
+                    <w:p><w:pPr><w:outlineLvl w:val=\"0\"/></w:pPr><w:r><w:t>Annex</w:t></w:r></w:p>\
+                    <w:p><w:r><w:t>Alice Johnson again</w:t></w:r></w:p>";
+        let needles = [
+            ("Alice Johnson".to_string(), String::new()),
+            ("Bob Smith".to_string(), String::new()),
+        ];
+        let hidden = ExtractOptions {
+            include_hidden_text: true,
+            ..ExtractOptions::default()
+        };
+        let extraction =
+            occurrences_mem(&needles, &fixtures::docx_from_body_xml(body), hidden).unwrap();
+
+        let paragraph = |index, headings: &[&str]| BlockLocation::DocxParagraph {
+            index,
+            heading_path: headings.iter().map(|heading| heading.to_string()).collect(),
+            page: 1,
+            cell: None,
+        };
+        let found: Vec<(&str, BlockLocation, ContextKind)> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| {
+                (
+                    occurrence.term.as_str(),
+                    occurrence.location.clone(),
+                    occurrence.context_kind,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Alice Johnson",
+                    paragraph(3, &["Contracts", "Signatories"]),
+                    ContextKind::Prose
+                ),
+                (
+                    "Bob Smith",
+                    paragraph(3, &["Contracts", "Signatories"]),
+                    ContextKind::Hidden
+                ),
+                (
+                    "Alice Johnson",
+                    paragraph(5, &["Annex"]),
+                    ContextKind::Prose
+                ),
+            ]
+        );
+        assert_eq!(
+            found[0].1.to_string(),
+            "paragraph 4 under Contracts › Signatories"
+        );
+        assert_eq!(extraction.occurrences[0].page(), Some(1));
+    }
+
+    #[test]
+    fn test_paragraphs_are_numbered_with_the_page_they_start_on() {
+        let needles = [("Alice Johnson".to_string(), String::new())];
+        let pages = |body: &str| -> Vec<Option<usize>> {
+            occurrences_mem(
+                &needles,
+                &fixtures::docx_from_body_xml(body),
+                ExtractOptions::default(),
+            )
+            .unwrap()
+            .occurrences
+            .iter()
+            .map(crate::search::Occurrence::page)
+            .collect()
+        };
+
+        // Explicit breaks: one in a paragraph of its own, one after a
+        // paragraph's text, and a paragraph property
+        let explicit = "<w:p><w:r><w:t>Alice Johnson</w:t></w:r></w:p>\
+                        <w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>\
+                        <w:p><w:r><w:t>Alice Johnson</w:t><w:br w:type=\"page\"/></w:r></w:p>\
+                        <w:p><w:r><w:t>Alice Johnson</w:t><w:br/></w:r></w:p>\
+                        <w:p><w:pPr><w:pageBreakBefore/></w:pPr><w:r><w:t>Alice Johnson</w:t></w:r></w:p>";
+        assert_eq!(pages(explicit), vec![Some(1), Some(2), Some(3), Some(4)]);
+
+        // Word's rendered breaks follow the explicit one, which is not
+        // counted twice, and mark where text overflowed onto a new page
+        let rendered = "<w:p><w:r><w:t>Alice Johnson</w:t></w:r></w:p>\
+                        <w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>\
+                        <w:p><w:r><w:lastRenderedPageBreak/><w:t>Alice Johnson</w:t></w:r></w:p>\
+                        <w:p><w:r><w:t>Long text </w:t><w:lastRenderedPageBreak/><w:t>flowing over</w:t></w:r></w:p>\
+                        <w:p><w:r><w:t>Alice Johnson</w:t></w:r></w:p>";
+        assert_eq!(pages(rendered), vec![Some(1), Some(2), Some(3)]);
+    }
 }
@@ -0,0 +1,575 @@
+//! EPUB ebooks (`.epub`): the XHTML content documents of the spine in
+//! reading order, where every paragraph, heading, list item and table cell
+//! is a block located at its chapter.
+//!
+//! An ebook is a ZIP archive. `META-INF/container.xml` names its OPF package
+//! document, whose `<manifest>` lists the files of the book, whose `<spine>`
+//! orders the content documents, and whose `<metadata>` holds the title,
+//! creators and other Dublin Core properties.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+use zip::ZipArchive;
+
+use super::docx::{read_part, DocxMeta};
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{search_blocks, BlockBudget, BlockLocation, Extraction, TextBlock};
+use crate::types::{ContextKind, ExtractOptions, FileType, SearchResults};
+
+/// Elements whose text is a block of its own
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "dt",
+    "dd",
+    "div",
+    "blockquote",
+    "pre",
+    "td",
+    "th",
+    "caption",
+    "figcaption",
+    "section",
+    "article",
+    "aside",
+    "header",
+    "footer",
+    "nav",
+];
+
+/// Elements whose text is not read: scripts, styles and ruby annotations
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "rt", "rp"];
+
+/// Named HTML entities that content documents use without declaring them,
+/// as the character references XML understands
+const HTML_ENTITIES: &[(&str, &str)] = &[
+    ("&nbsp;", "&#160;"),
+    ("&shy;", "&#173;"),
+    ("&ndash;", "&#8211;"),
+    ("&mdash;", "&#8212;"),
+    ("&lsquo;", "&#8216;"),
+    ("&rsquo;", "&#8217;"),
+    ("&ldquo;", "&#8220;"),
+    ("&rdquo;", "&#8221;"),
+    ("&hellip;", "&#8230;"),
+    ("&copy;", "&#169;"),
+];
+
+/// [`DocumentParser`] for EPUB ebooks
+pub struct EpubParser;
+
+impl DocumentParser for EpubParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Epub]
+    }
+
+    fn extract_text(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        Ok(ExtractedDocument::new(blocks_mem(bytes, extract)?))
+    }
+
+    fn extract_within(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        budget: BlockBudget,
+    ) -> Result<Vec<TextBlock>> {
+        blocks_within(bytes, extract, budget)
+    }
+}
+
+/// Search an in-memory EPUB ebook for the needles of an in-memory needles
+/// file, searching as `extract` says
+pub fn parse_from_mem(
+    needle_bytes: &[u8],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_mem_for_needles(&EpubParser, needle_bytes, haystack_bytes, extract)
+}
+
+/// Report every occurrence of `needles` in an in-memory EPUB ebook, also
+/// searching the metadata when `extract` asks for it
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    Ok(search_blocks(
+        needles,
+        &blocks_mem(haystack_bytes, extract)?,
+        extract,
+    ))
+}
+
+/// The blocks [`occurrences_mem`] searches
+pub fn blocks_mem(haystack_bytes: &[u8], extract: ExtractOptions) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, extract, None)
+}
+
+/// The first blocks of [`blocks_mem`], as many as `budget` allows
+pub fn blocks_within(
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+    budget: BlockBudget,
+) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, extract, Some(budget))
+}
+
+/// Search an EPUB file on disk for the needles of a needles file, searching
+/// as `extract` says
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_path(
+        &EpubParser,
+        needle_path,
+        file_path,
+        extract,
+        &mut NullReporter,
+    )
+}
+
+/// Search an EPUB file on disk with needles that were already loaded,
+/// reporting every occurrence
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(&EpubParser, needles, file_path, extract, &mut NullReporter)
+}
+
+/// Read the metadata of the EPUB file at `path` from its package document:
+/// the `dc:title`, the `dc:creator`s joined with commas, the `dc:subject`s,
+/// the `dc:date` as created and the `dcterms:modified` date
+pub fn extract_epub_meta(path: &Path) -> Result<DocxMeta> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    Ok(read_package(&mut archive)?.meta)
+}
+
+/// What the package document says about the book
+struct Package {
+    /// The hrefs of the content documents, in reading order
+    chapters: Vec<String>,
+    /// The folder the package document is in, which hrefs are relative to
+    folder: String,
+    meta: DocxMeta,
+}
+
+/// Read the package document that `META-INF/container.xml` names
+fn read_package<R>(archive: &mut ZipArchive<R>) -> Result<Package>
+where
+    R: Seek,
+    R: Read,
+{
+    let container =
+        read_part(archive, "META-INF/container.xml").context("EPUB container not found")?;
+    let container =
+        roxmltree::Document::parse(&container).context("Could not parse the EPUB container")?;
+    let package_path = container
+        .descendants()
+        .find(|elem| elem.has_tag_name("rootfile"))
+        .and_then(|rootfile| rootfile.attribute("full-path"))
+        .context("EPUB container names no package document")?;
+
+    let opf = read_part(archive, package_path)
+        .with_context(|| format!("Package document {} not found", package_path))?;
+    let opf = roxmltree::Document::parse(&opf).context("Could not parse the package document")?;
+
+    // Content documents by manifest id, with their hrefs
+    let items: HashMap<&str, &str> = opf
+        .descendants()
+        .filter(|elem| elem.has_tag_name("item"))
+        .filter(|item| {
+            matches!(
+                item.attribute("media-type"),
+                Some("application/xhtml+xml" | "text/html")
+            )
+        })
+        .filter_map(|item| Some((item.attribute("id")?, item.attribute("href")?)))
+        .collect();
+    let chapters = opf
+        .descendants()
+        .filter(|elem| elem.has_tag_name("itemref"))
+        .filter_map(|itemref| items.get(itemref.attribute("idref")?))
+        .map(|href| href.to_string())
+        .collect();
+
+    let values = |name: &str| -> Vec<&str> {
+        opf.descendants()
+            .filter(|elem| {
+                elem.has_tag_name(name)
+                    && elem
+                        .ancestors()
+                        .any(|ancestor| ancestor.has_tag_name("metadata"))
+            })
+            .filter_map(|elem| elem.text())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    let joined = |name: &str| Some(values(name).join(", ")).filter(|value| !value.is_empty());
+    let modified = opf
+        .descendants()
+        .find(|elem| {
+            elem.has_tag_name("meta") && elem.attribute("property") == Some("dcterms:modified")
+        })
+        .and_then(|elem| elem.text())
+        .map(|value| value.trim().to_string());
+    let meta = DocxMeta {
+        title: values("title").first().map(|title| title.to_string()),
+        author: joined("creator"),
+        subject: joined("subject"),
+        keywords: None,
+        created: values("date").first().map(|date| date.to_string()),
+        modified,
+    };
+
+    let folder = package_path
+        .rsplit_once('/')
+        .map_or("", |(folder, _)| folder)
+        .to_string();
+    Ok(Package {
+        chapters,
+        folder,
+        meta,
+    })
+}
+
+/// Collect the text of every block of every chapter, in reading order, then
+/// the metadata when `extract` asks for it. With a `budget`, stop once it is
+/// spent.
+fn extract_haystack<R>(
+    archive: &mut ZipArchive<R>,
+    extract: ExtractOptions,
+    budget: Option<BlockBudget>,
+) -> Result<Vec<TextBlock>>
+where
+    R: Seek,
+    R: Read,
+{
+    let package = read_package(archive)?;
+
+    let mut haystack = Vec::new();
+    for href in &package.chapters {
+        if budget.is_some_and(|budget| budget.spent(haystack.len())) {
+            break;
+        }
+        let path = resolve(&package.folder, href);
+        let Some(xhtml) = read_part(archive, &path) else {
+            tracing::warn!(chapter = %href, "chapter listed in the spine but missing from the EPUB");
+            continue;
+        };
+        let paragraphs =
+            chapter_text(&xhtml).with_context(|| format!("Could not parse chapter {}", href))?;
+        haystack.extend(paragraphs.into_iter().enumerate().map(|(index, text)| {
+            TextBlock::new(
+                text,
+                BlockLocation::EpubChapter {
+                    href: href.clone(),
+                    index,
+                },
+            )
+        }));
+    }
+    if extract.include_metadata {
+        haystack.extend(
+            package
+                .meta
+                .searchable()
+                .into_iter()
+                .map(|(name, value)| TextBlock {
+                    concealed: Some(ContextKind::Metadata),
+                    ..TextBlock::new(
+                        value,
+                        BlockLocation::DocxProperty {
+                            name: name.to_string(),
+                        },
+                    )
+                }),
+        );
+    }
+
+    if let Some(budget) = budget {
+        haystack.truncate(budget.blocks);
+    }
+    Ok(haystack)
+}
+
+/// The archive path of `href`, relative to `folder`, with `%XX` escapes
+/// decoded and `..` steps taken
+fn resolve(folder: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut path: Vec<String> = folder
+        .split('/')
+        .filter(|step| !step.is_empty())
+        .map(str::to_string)
+        .collect();
+    for step in href.split('/') {
+        match step {
+            "" | "." => {}
+            ".." => {
+                path.pop();
+            }
+            step => path.push(percent_decode(step)),
+        }
+    }
+    path.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The non-empty blocks of the `<body>` of a content document, tags
+/// stripped and whitespace collapsed
+fn chapter_text(xhtml: &str) -> Result<Vec<String>> {
+    let xhtml = HTML_ENTITIES
+        .iter()
+        .fold(xhtml.to_string(), |xhtml, (entity, reference)| {
+            xhtml.replace(entity, reference)
+        });
+    // EPUB 2 content documents declare the XHTML doctype
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    let xml = roxmltree::Document::parse_with_options(&xhtml, options)?;
+
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    if let Some(body) = xml.descendants().find(|elem| elem.has_tag_name("body")) {
+        collect_blocks(body, &mut current, &mut blocks);
+    }
+    end_block(&mut current, &mut blocks);
+    Ok(blocks)
+}
+
+/// Append the text under `node` to `current`, ending a block before and
+/// after each block element
+fn collect_blocks(node: roxmltree::Node, current: &mut String, blocks: &mut Vec<String>) {
+    for child in node.children() {
+        if child.is_text() {
+            current.push_str(child.text().unwrap_or_default());
+            continue;
+        }
+        let name = child.tag_name().name();
+        if !child.is_element() || SKIPPED_ELEMENTS.contains(&name) {
+            continue;
+        }
+        if name == "br" {
+            current.push(' ');
+            continue;
+        }
+
+        let is_block = BLOCK_ELEMENTS.contains(&name);
+        if is_block {
+            end_block(current, blocks);
+        }
+        collect_blocks(child, current, blocks);
+        if is_block {
+            end_block(current, blocks);
+        }
+    }
+}
+
+/// Move the text of `current`, with its whitespace collapsed, to `blocks`
+/// unless it is blank
+fn end_block(current: &mut String, blocks: &mut Vec<String>) {
+    let text = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        blocks.push(text);
+    }
+    current.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::Location;
+
+    fn located(blocks: &[TextBlock]) -> Vec<(String, &str)> {
+        blocks
+            .iter()
+            .map(|block| (block.location.to_string(), block.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_chapters_are_read_in_spine_order() {
+        let chapter = |body: &str| {
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.1//EN\" \"http://www.w3.org/TR/xhtml11/DTD/xhtml11.dtd\">\n\
+                 <html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>x</title><style>p {{ }}</style></head><body>{}</body></html>",
+                body
+            )
+        };
+        let bytes = fixtures::epub_from_parts(
+            "<dc:title>Memoirs</dc:title><dc:creator>Carol Diaz</dc:creator><dc:creator>Dan Wu</dc:creator>",
+            "<item id=\"c1\" href=\"text/one.xhtml\" media-type=\"application/xhtml+xml\"/>\
+             <item id=\"c2\" href=\"text/chapter%20two.xhtml\" media-type=\"application/xhtml+xml\"/>\
+             <item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>",
+            "<itemref idref=\"c2\"/><itemref idref=\"c1\"/>",
+            &[
+                ("text/one.xhtml", &chapter("<h1>One</h1><p>Met <em>Alice</em>\n   Johnson&nbsp;today.</p><script>var x = 'Bob Smith';</script>")),
+                ("text/chapter two.xhtml", &chapter("<div><p>Bob Smith<br/>wrote</p>Signed</div><ul><li>Item</li></ul>")),
+                ("style.css", "p { }"),
+            ],
+        );
+
+        let blocks = blocks_mem(&bytes, ExtractOptions::default()).unwrap();
+        assert_eq!(
+            located(&blocks),
+            [
+                (
+                    "paragraph 1 of chapter text/chapter%20two.xhtml".to_string(),
+                    "Bob Smith wrote"
+                ),
+                (
+                    "paragraph 2 of chapter text/chapter%20two.xhtml".to_string(),
+                    "Signed"
+                ),
+                (
+                    "paragraph 3 of chapter text/chapter%20two.xhtml".to_string(),
+                    "Item"
+                ),
+                ("paragraph 1 of chapter text/one.xhtml".to_string(), "One"),
+                (
+                    "paragraph 2 of chapter text/one.xhtml".to_string(),
+                    "Met Alice Johnson today."
+                ),
+            ]
+        );
+
+        let needles = vec![
+            ("Alice Johnson".to_string(), "id=1".to_string()),
+            ("Dan Wu".to_string(), "id=2".to_string()),
+        ];
+        let extraction = occurrences_mem(&needles, &bytes, ExtractOptions::default()).unwrap();
+        let found: Vec<(&str, Location)> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| (occurrence.term.as_str(), occurrence.location.part()))
+            .collect();
+        assert_eq!(
+            found,
+            [(
+                "Alice Johnson",
+                Location::Chapter {
+                    href: "text/one.xhtml".to_string()
+                }
+            )]
+        );
+
+        // The metadata is searched when asked for
+        let extract = ExtractOptions {
+            include_metadata: true,
+            ..ExtractOptions::default()
+        };
+        let extraction = occurrences_mem(&needles, &bytes, extract).unwrap();
+        assert_eq!(
+            extraction
+                .occurrences
+                .iter()
+                .map(|occurrence| occurrence.location.to_string())
+                .collect::<Vec<_>>(),
+            ["paragraph 2 of chapter text/one.xhtml", "author property",]
+        );
+    }
+
+    #[test]
+    fn test_metadata_is_read_from_the_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.epub");
+        std::fs::write(
+            &path,
+            fixtures::epub_from_parts(
+                "<dc:title> Memoirs </dc:title><dc:creator>Carol Diaz</dc:creator><dc:creator>Dan Wu</dc:creator>\
+                 <dc:date>2019-04-01</dc:date><meta property=\"dcterms:modified\">2024-01-02T03:04:05Z</meta>",
+                "",
+                "",
+                &[],
+            ),
+        )
+        .unwrap();
+
+        let meta = extract_epub_meta(&path).unwrap();
+        assert_eq!(
+            meta.fields(),
+            [
+                ("title", "Memoirs"),
+                ("author", "Carol Diaz, Dan Wu"),
+                ("created", "2019-04-01"),
+                ("modified", "2024-01-02T03:04:05Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simple_ebook_is_searched() {
+        let bytes = fixtures::epub(&[&["Prologue"], &["Dear Ms Johnson,", "Regards, Bob Smith"]]);
+        let results =
+            parse_from_mem(b"Bob Smith,id=3\n", &bytes, ExtractOptions::default()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].location,
+            Location::Chapter {
+                href: "text/chapter2.xhtml".to_string()
+            }
+        );
+
+        assert_eq!(
+            resolve("OEBPS", "../images/../text/a%C3%A9.xhtml#note"),
+            "text/aé.xhtml"
+        );
+        assert!(blocks_mem(
+            &fixtures::zip_archive(&[("mimetype", "application/epub+zip")]),
+            ExtractOptions::default()
+        )
+        .is_err());
+    }
+}
@@ -1,5 +1,1022 @@
+//! Document parsers, one module per format, and the registry that picks
+//! one for a file.
+//!
+//! A parser only extracts text: it turns a document into positioned
+//! [`TextBlock`]s through [`DocumentParser`], and matching is shared. A new
+//! format is a module with a parser, its [`FileType`] and an entry in
+//! [`PARSERS`].
+//!
+//! Parsing prints nothing: callers that want to show progress pass a
+//! [`ProgressReporter`] to [`parse_with_progress`], which tells it about
+//! each step of the search.
+
+use anyhow::Result;
+use std::{
+    collections::HashSet, io, ops::ControlFlow, path::Path, sync::mpsc, thread, time::Instant,
+};
+
+use crate::error::DocSearchError;
+use crate::progress::{NullReporter, ProgressReporter};
+use crate::search::{
+    results_from_occurrences, search_blocks, search_blocks_until, weigh_results, BlockBudget,
+    BlockSearch, Extraction, Occurrence, TextBlock,
+};
+use crate::types::{ExtractOptions, FileType, NeedleWeights, SearchResult, SearchResults};
+use crate::utils::{
+    check_regex_needles, check_regex_needles_in_file, needle_weights, parse_filetype,
+    read_needle_weights, read_needles_from_file, read_needles_from_mem,
+};
+
+pub mod archive;
+pub mod doc;
 pub mod docx;
+pub mod epub;
+pub mod odt;
 pub mod pdf;
+pub mod rtf;
+pub mod txt;
+pub mod xlsx;
 
+pub use docx::occurrences_mem as docx_occurrences_mem;
 pub use docx::parse_from_path as parse_docx_from_path;
+pub use docx::parse_iter as parse_docx_iter;
+pub use docx::parse_with_needles as parse_docx_with_needles;
+pub use docx::search_mem as search_docx_mem;
+pub use epub::parse_from_path as parse_epub_from_path;
+pub use epub::parse_with_needles as parse_epub_with_needles;
+pub use odt::parse_from_path as parse_odt_from_path;
+pub use odt::parse_with_needles as parse_odt_with_needles;
+pub use pdf::occurrences_mem as pdf_occurrences_mem;
 pub use pdf::parse_from_path as parse_pdf_from_path;
+pub use pdf::parse_iter as parse_pdf_iter;
+pub use pdf::parse_with_needles as parse_pdf_with_needles;
+pub use pdf::search_mem as search_pdf_mem;
+pub use rtf::parse_from_path as parse_rtf_from_path;
+pub use rtf::parse_with_needles as parse_rtf_with_needles;
+pub use txt::parse_from_path as parse_txt_from_path;
+pub use txt::parse_with_needles as parse_txt_with_needles;
+pub use xlsx::parse_from_path as parse_xlsx_from_path;
+pub use xlsx::parse_with_needles as parse_xlsx_with_needles;
+
+/// The text a parser extracted from a document, ready to search
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtractedDocument {
+    pub blocks: Vec<TextBlock>,
+    /// Bytes of text extracted, which can be more than the blocks hold
+    pub text_bytes: u64,
+    /// Content that could not be read, such as PDF pages that failed to
+    /// extract
+    pub warnings: Vec<String>,
+}
+
+impl ExtractedDocument {
+    /// A document of `blocks`, with nothing left unread
+    pub fn new(blocks: Vec<TextBlock>) -> Self {
+        Self {
+            text_bytes: blocks.iter().map(|block| block.text.len() as u64).sum(),
+            blocks,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Every occurrence of `needles` in the document, with its warnings
+    pub fn search(&self, needles: &[(String, String)], extract: ExtractOptions) -> Extraction {
+        Extraction {
+            text_bytes: self.text_bytes,
+            warnings: self.warnings.clone(),
+            ..search_blocks(needles, &self.blocks, extract)
+        }
+    }
+
+    /// [`search`](Self::search), giving up with `None` when `deadline`
+    /// passes first
+    pub fn search_until(
+        &self,
+        needles: &[(String, String)],
+        extract: ExtractOptions,
+        deadline: Option<Instant>,
+    ) -> Option<Extraction> {
+        Some(Extraction {
+            text_bytes: self.text_bytes,
+            warnings: self.warnings.clone(),
+            ..search_blocks_until(needles, &self.blocks, extract, deadline)?
+        })
+    }
+}
+
+/// Text extraction for one document format
+pub trait DocumentParser: Sync {
+    /// The types this parser reads
+    fn file_types(&self) -> &'static [FileType];
+
+    /// Extract the blocks of the document in `bytes`, including the content
+    /// `extract` opts into; `path` names the document in errors
+    fn extract_text(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+    ) -> Result<ExtractedDocument>;
+
+    /// The first blocks of [`extract_text`](Self::extract_text), as many as
+    /// `budget` allows. Parsers that can stop reading early override this.
+    fn extract_within(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        budget: BlockBudget,
+    ) -> Result<Vec<TextBlock>> {
+        let mut blocks = self.extract_text(path, bytes, extract)?.blocks;
+        blocks.truncate(budget.blocks);
+        Ok(blocks)
+    }
+
+    /// Hand `on_block` each block of [`extract_text`](Self::extract_text) in
+    /// turn, stopping when it breaks. Parsers that can read a document a
+    /// part at a time override this, so its blocks are never all held at
+    /// once.
+    fn each_block(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        on_block: &mut dyn FnMut(TextBlock) -> ControlFlow<()>,
+    ) -> Result<()> {
+        for block in self.extract_text(path, bytes, extract)?.blocks {
+            if on_block(block).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether this parser reads the file at `path`, judging by its
+    /// extension
+    fn supports(&self, path: &Path) -> bool {
+        parse_filetype(path).is_ok_and(|file_type| self.file_types().contains(&file_type))
+    }
+}
+
+/// Every parser; each file type has exactly one
+pub const PARSERS: &[&dyn DocumentParser] = &[
+    &docx::DocxParser,
+    &pdf::PdfParser,
+    &xlsx::XlsxParser,
+    &odt::OdtParser,
+    &txt::TxtParser,
+    &rtf::RtfParser,
+    &epub::EpubParser,
+];
+
+/// The parser for documents of `file_type`
+pub fn parser_for(file_type: FileType) -> &'static dyn DocumentParser {
+    PARSERS
+        .iter()
+        .copied()
+        .find(|parser| parser.file_types().contains(&file_type))
+        .expect("every file type has a parser")
+}
+
+/// Search the document at `path` for `needles`, reporting every occurrence.
+///
+/// This is [`batch::search_bytes`](crate::batch::search_bytes) on the
+/// contents of the file: its extension picks the parser, containers are
+/// searched entry by entry, and a document that fails to parse is retried
+/// as the type its magic bytes name.
+pub fn parse_any(
+    path: &Path,
+    needles: &[(String, String)],
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    let bytes = read_document(path)?;
+    crate::batch::search_bytes(needles, path, &bytes, extract)
+        .map_err(|e| DocSearchError::extraction(path, e))
+}
+
+/// Search the document at `file_path` for the needles of the needles file
+/// at `needle_path`, searching as `extract` says, with the parser its
+/// extension names
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    parse_with_progress(needle_path, file_path, extract, &mut NullReporter)
+}
+
+/// [`parse_from_path`], telling `reporter` about each step as it finishes
+pub fn parse_with_progress(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+    reporter: &mut dyn ProgressReporter,
+) -> Result<SearchResults, DocSearchError> {
+    let results = parse_filetype(file_path).and_then(|file_type| {
+        search_path(
+            parser_for(file_type),
+            needle_path,
+            file_path,
+            extract,
+            reporter,
+        )
+    });
+    reporter.on_done();
+    results
+}
+
+/// Search the document at `file_path` for `needles` that were already
+/// loaded, such as terms typed in, with the parser its extension names,
+/// telling `reporter` about each step as it finishes
+pub fn search_with_progress(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+    reporter: &mut dyn ProgressReporter,
+) -> Result<SearchResults, DocSearchError> {
+    reporter.on_start(needles.len());
+    let extraction = parse_filetype(file_path).and_then(|file_type| {
+        search_path_with_needles(parser_for(file_type), needles, file_path, extract, reporter)
+    });
+    reporter.on_done();
+    Ok(limited(
+        results_from_occurrences(&extraction?.occurrences),
+        Some(file_path),
+        extract,
+    ))
+}
+
+/// The needles of a needles file and their weights, read once to search
+/// any number of documents
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadedNeedles {
+    pub needles: Vec<(String, String)>,
+    pub weights: NeedleWeights,
+}
+
+impl LoadedNeedles {
+    /// Read the needles file at `needle_path`, checking its regular
+    /// expressions as `extract` says
+    pub fn read(needle_path: &str, extract: ExtractOptions) -> Result<Self, DocSearchError> {
+        let needles = read_needles_from_file(needle_path, None)?;
+        check_regex_needles_in_file(needle_path, &needles, None, extract.matching.regex())?;
+        Ok(Self {
+            needles,
+            weights: read_needle_weights(needle_path, None)?,
+        })
+    }
+
+    /// Search the document at `file_path` with the parser its extension
+    /// names, as [`parse_from_path`] does without reading the needles again
+    pub fn search(
+        &self,
+        file_path: &str,
+        extract: ExtractOptions,
+    ) -> Result<SearchResults, DocSearchError> {
+        let extraction = search_path_with_needles(
+            parser_for(parse_filetype(file_path)?),
+            &self.needles,
+            file_path,
+            extract,
+            &mut NullReporter,
+        )?;
+        Ok(self.results(&extraction, file_path, extract))
+    }
+
+    /// The weighed results of `extraction`, which was found in the document
+    /// at `file_path`, as many as `extract` allows
+    fn results(
+        &self,
+        extraction: &Extraction,
+        file_path: &str,
+        extract: ExtractOptions,
+    ) -> SearchResults {
+        limited(
+            weigh_results(
+                results_from_occurrences(&extraction.occurrences),
+                &self.weights,
+            ),
+            Some(file_path),
+            extract,
+        )
+    }
+}
+
+/// [`parse_with_progress`] with a given parser
+fn search_path(
+    parser: &dyn DocumentParser,
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+    reporter: &mut dyn ProgressReporter,
+) -> Result<SearchResults, DocSearchError> {
+    let needles = LoadedNeedles::read(needle_path, extract)?;
+    reporter.on_start(needles.needles.len());
+
+    let extraction =
+        search_path_with_needles(parser, &needles.needles, file_path, extract, reporter)?;
+    Ok(needles.results(&extraction, file_path, extract))
+}
+
+/// Results a [`search_path_iter`] may find before they are taken
+const RESULTS_AHEAD: usize = 64;
+
+/// [`search_path`] with `parser`, yielding each needle once for every block
+/// it is found in, as that block is searched. `&&` needles are only
+/// yielded once the whole document is. Reading stops once as many needles
+/// as the [`limits`](ExtractOptions::limits) allow have been found.
+///
+/// The document is read and searched on a thread of its own, which stays
+/// at most [`RESULTS_AHEAD`] results ahead of the caller and stops when the
+/// iterator is dropped. A failure is yielded after the results found
+/// before it.
+fn search_path_iter(
+    parser: &'static dyn DocumentParser,
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> impl Iterator<Item = Result<SearchResult, DocSearchError>> + Send {
+    let (sender, receiver) = mpsc::sync_channel(RESULTS_AHEAD);
+    let (needle_path, file_path) = (needle_path.to_string(), file_path.to_string());
+    thread::spawn(move || {
+        let searched = stream_results(parser, &needle_path, &file_path, extract, &mut |result| {
+            sender.send(Ok(result)).is_ok()
+        });
+        if let Err(e) = searched {
+            // Nobody is left to tell when the iterator was dropped
+            let _ = sender.send(Err(e));
+        }
+    });
+    receiver.into_iter()
+}
+
+/// Search the document at `file_path` a block at a time, handing
+/// `on_result` each block's weighed results until it returns false or the
+/// [`limits`](ExtractOptions::limits) are reached
+fn stream_results(
+    parser: &dyn DocumentParser,
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+    on_result: &mut dyn FnMut(SearchResult) -> bool,
+) -> Result<(), DocSearchError> {
+    let deadline = extract.limits.deadline();
+    let needles = LoadedNeedles::read(needle_path, extract)?;
+    let bytes = read_document(Path::new(file_path))?;
+    let matcher = BlockSearch::matcher(extract);
+    let mut search = BlockSearch::new(&matcher, &needles.needles, extract);
+    // The needles found, each in every part of the document it was found in
+    let mut found = HashSet::new();
+    let mut hand_over = |occurrences: Vec<Occurrence>| {
+        let results = weigh_results(results_from_occurrences(&occurrences), &needles.weights);
+        results.into_iter().all(|result| {
+            let needle = (
+                result.term.clone(),
+                result.metadata.clone(),
+                result.location.clone(),
+            );
+            if !found.contains(&needle) && extract.limits.reached(found.len()) {
+                return false;
+            }
+            found.insert(needle);
+            on_result(found_in(result, Some(file_path), extract))
+                && !extract.limits.reached(found.len())
+        })
+    };
+
+    let (mut stopped, mut timed_out) = (false, false);
+    parser
+        .each_block(Path::new(file_path), &bytes, extract, &mut |block| {
+            timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            stopped = timed_out || !hand_over(search.search(&block));
+            match stopped {
+                false => ControlFlow::Continue(()),
+                true => ControlFlow::Break(()),
+            }
+        })
+        .map_err(|e| DocSearchError::extraction(Path::new(file_path), e))?;
+    if timed_out {
+        return Err(timed_out_error(Path::new(file_path), extract));
+    }
+    if !stopped {
+        hand_over(search.finish());
+    }
+    Ok(())
+}
+
+/// Search the document at `file_path` with `parser` and needles that were
+/// already loaded, reporting every occurrence and telling `reporter` about
+/// the reading, extraction and search steps
+fn search_path_with_needles(
+    parser: &dyn DocumentParser,
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+    reporter: &mut dyn ProgressReporter,
+) -> Result<Extraction, DocSearchError> {
+    let deadline = extract.limits.deadline();
+    let bytes = read_document(Path::new(file_path))?;
+    reporter.on_file_open();
+    let document = extract_text(parser, Path::new(file_path), &bytes, extract)?;
+    reporter.on_haystack_built(document.blocks.len());
+
+    let extraction = document
+        .search_until(needles, extract, deadline)
+        .ok_or_else(|| timed_out_error(Path::new(file_path), extract))?;
+    reporter.on_search_complete(extraction.occurrences.len());
+
+    Ok(extraction)
+}
+
+/// Search an in-memory document with `parser` for the needles of an
+/// in-memory needles file, searching as `extract` says
+fn search_mem_for_needles(
+    parser: &dyn DocumentParser,
+    needle_bytes: &[u8],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    let deadline = extract.limits.deadline();
+    let needles = read_needles_from_mem(needle_bytes, None)?;
+    let content = String::from_utf8_lossy(needle_bytes);
+    check_regex_needles(&content, None, extract.matching.regex())?;
+
+    let extraction = extract_text(parser, Path::new(""), haystack_bytes, extract)?
+        .search_until(&needles, extract, deadline)
+        .ok_or_else(|| timed_out_error(Path::new(""), extract))?;
+    Ok(limited(
+        weigh_results(
+            results_from_occurrences(&extraction.occurrences),
+            &needle_weights(&content, None),
+        ),
+        None,
+        extract,
+    ))
+}
+
+/// `result`, found in the document at `file_path` when it was read from
+/// disk, without the context `extract` leaves out
+fn found_in(
+    result: SearchResult,
+    file_path: Option<&str>,
+    extract: ExtractOptions,
+) -> SearchResult {
+    let result = match file_path {
+        Some(file_path) => result.in_file(file_path),
+        None => result,
+    };
+    match extract.include_context {
+        true => result,
+        false => SearchResult {
+            context: None,
+            ..result
+        },
+    }
+}
+
+/// The first of `results` the [`limits`](ExtractOptions::limits) of
+/// `extract` allow, each as [`found_in`] gives it
+fn limited(
+    mut results: SearchResults,
+    file_path: Option<&str>,
+    extract: ExtractOptions,
+) -> SearchResults {
+    if let Some(max_results) = extract.limits.max_results {
+        results.truncate(max_results);
+    }
+    results
+        .into_iter()
+        .map(|result| found_in(result, file_path, extract))
+        .collect()
+}
+
+/// The error of a search of the document at `path` that ran past the
+/// timeout of `extract`
+fn timed_out_error(path: &Path, extract: ExtractOptions) -> DocSearchError {
+    DocSearchError::TimedOut {
+        path: path.to_path_buf(),
+        after: extract.limits.timeout.unwrap_or_default(),
+    }
+}
+
+/// [`DocumentParser::extract_text`], with its failure classified
+fn extract_text(
+    parser: &dyn DocumentParser,
+    path: &Path,
+    bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<ExtractedDocument, DocSearchError> {
+    parser
+        .extract_text(path, bytes, extract)
+        .map_err(|e| DocSearchError::extraction(path, e))
+}
+
+/// The contents of the document at `path`; the error names it
+fn read_document(path: &Path) -> Result<Vec<u8>, DocSearchError> {
+    std::fs::read(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => DocSearchError::FileNotFound(path.to_path_buf()),
+        kind => io::Error::new(kind, format!("Failed to open {}: {}", path.display(), e)).into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::search::merge_results;
+    use crate::types::SearchConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn test_needles_file_searches_take_every_option() {
+        let needles = b"Alice Johnson,id=1\nBob Smith,id=2\n";
+        let document = fixtures::docx_with_properties(
+            &["Signed by Bob Smlth"],
+            &[("dc:creator", "Alice Johnson")],
+        );
+        let found = |extract: ExtractOptions| -> Vec<String> {
+            docx::parse_from_mem(needles, &document, extract)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.term)
+                .collect()
+        };
+
+        assert!(found(ExtractOptions::default()).is_empty());
+        assert_eq!(
+            found(ExtractOptions {
+                max_edits: 1,
+                ..ExtractOptions::default()
+            }),
+            ["Bob Smith"]
+        );
+        assert_eq!(
+            found(ExtractOptions {
+                include_metadata: true,
+                ..ExtractOptions::default()
+            }),
+            ["Alice Johnson"]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_results_carry_the_distance_and_text_of_the_closest_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(path("contacts.csv"), "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        std::fs::write(
+            path("a.docx"),
+            fixtures::docx(&[
+                "Signed by Alise Jonson",
+                "Alice Jonson",
+                "cc Bob Smlth",
+                "Bob Smith",
+            ]),
+        )
+        .unwrap();
+
+        let fuzzy = ExtractOptions {
+            max_edits: 2,
+            ..ExtractOptions::default()
+        };
+        let whole = docx::parse_from_path(&path("contacts.csv"), &path("a.docx"), fuzzy).unwrap();
+        let scored: Vec<(&str, Option<f32>, Option<&str>)> = whole
+            .iter()
+            .map(|result| {
+                (
+                    result.term.as_str(),
+                    result.distance_score,
+                    result.matched.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            scored,
+            [
+                ("Alice Johnson", Some(1.0 / 13.0), Some("Alice Jonson")),
+                ("Bob Smith", None, None)
+            ]
+        );
+
+        // Found a line at a time, the closest line wins once merged
+        let yielded: Vec<SearchResult> =
+            parse_docx_iter(&path("contacts.csv"), &path("a.docx"), fuzzy)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(yielded[0].distance_score, Some(2.0 / 13.0));
+        assert_eq!(merge_results(yielded), whole);
+        let exact = docx::parse_from_path(
+            &path("contacts.csv"),
+            &path("a.docx"),
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(exact[0].term, "Bob Smith");
+        assert_eq!(
+            (exact[0].distance_score, exact[0].matched.as_deref()),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_loaded_needles_search_every_document_without_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(
+            path("contacts.csv"),
+            "Alice Johnson,id=1,5\nBob Smith,id=2\n",
+        )
+        .unwrap();
+        std::fs::write(
+            path("a.docx"),
+            fixtures::docx(&["Bob Smith and Alice Johnson"]),
+        )
+        .unwrap();
+        std::fs::write(path("b.txt"), "Bob Smith").unwrap();
+
+        let needles =
+            LoadedNeedles::read(&path("contacts.csv"), ExtractOptions::default()).unwrap();
+        std::fs::remove_file(path("contacts.csv")).unwrap();
+
+        let found = |name: &str| -> Vec<(String, i64)> {
+            needles
+                .search(&path(name), ExtractOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|result| (result.term, result.weight))
+                .collect()
+        };
+        assert_eq!(
+            found("a.docx"),
+            [
+                ("Alice Johnson".to_string(), 5),
+                ("Bob Smith".to_string(), 0)
+            ]
+        );
+        assert_eq!(found("b.txt"), [("Bob Smith".to_string(), 0)]);
+    }
+
+    #[test]
+    fn test_iterators_yield_each_line_of_what_a_whole_search_finds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(
+            path("contacts.csv"),
+            "Alice Johnson,id=1\nBob Smith,id=2,5\nFalcon && overrun,id=3\n",
+        )
+        .unwrap();
+        let lines = [
+            "Alice Johnson signed",
+            "Project Falcon",
+            "Bob Smith and Alice Johnson",
+            "budget overrun",
+        ];
+        std::fs::write(path("a.docx"), fixtures::docx(&lines)).unwrap();
+        std::fs::write(path("a.pdf"), fixtures::pdf(&[&lines[..2], &lines[2..]])).unwrap();
+
+        let needles =
+            LoadedNeedles::read(&path("contacts.csv"), ExtractOptions::default()).unwrap();
+        let terms = |results: &[SearchResult]| -> Vec<(String, u32)> {
+            results
+                .iter()
+                .map(|result| (result.term.clone(), result.occurrences))
+                .collect()
+        };
+        for (name, iter) in [
+            (
+                "a.docx",
+                Box::new(parse_docx_iter(
+                    &path("contacts.csv"),
+                    &path("a.docx"),
+                    ExtractOptions::default(),
+                )) as Box<dyn Iterator<Item = _> + Send>,
+            ),
+            (
+                "a.pdf",
+                Box::new(parse_pdf_iter(
+                    &path("contacts.csv"),
+                    &path("a.pdf"),
+                    ExtractOptions::default(),
+                )),
+            ),
+        ] {
+            // Taken on another thread, a line at a time, the conjunction last
+            let yielded: Vec<SearchResult> = thread::spawn(move || iter.collect::<Result<_, _>>())
+                .join()
+                .unwrap()
+                .unwrap();
+            let expected = [
+                ("Alice Johnson", 1),
+                ("Bob Smith", 1),
+                ("Alice Johnson", 1),
+                ("Falcon && overrun", 2),
+            ];
+            assert_eq!(
+                terms(&yielded),
+                expected.map(|(term, n)| (term.to_string(), n)),
+                "{}",
+                name
+            );
+
+            let whole = needles
+                .search(&path(name), ExtractOptions::default())
+                .unwrap();
+            assert_eq!(merge_results(yielded), whole, "{}", name);
+            assert_eq!(terms(&whole)[0], ("Bob Smith".to_string(), 1));
+        }
+        assert_eq!(
+            docx::parse_from_path(
+                &path("contacts.csv"),
+                &path("a.docx"),
+                ExtractOptions::default()
+            )
+            .unwrap(),
+            needles
+                .search(&path("a.docx"), ExtractOptions::default())
+                .unwrap()
+        );
+
+        // A failure ends the results; one that stops the search comes first
+        let mut missing = parse_pdf_iter(
+            &path("contacts.csv"),
+            &path("missing.pdf"),
+            ExtractOptions::default(),
+        );
+        assert!(matches!(
+            missing.next(),
+            Some(Err(DocSearchError::FileNotFound(_)))
+        ));
+        assert!(missing.next().is_none());
+        let mut taken = parse_docx_iter(
+            &path("contacts.csv"),
+            &path("a.docx"),
+            ExtractOptions::default(),
+        );
+        assert_eq!(taken.next().unwrap().unwrap().term, "Alice Johnson");
+        drop(taken);
+    }
+
+    #[test]
+    fn test_searches_stop_at_their_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(
+            path("contacts.csv"),
+            "Alice Johnson,id=1\nBob Smith,id=2,5\nCarol Diaz,id=3\n",
+        )
+        .unwrap();
+        let lines = [
+            "Alice Johnson signed",
+            "Bob Smith approved",
+            "cc: Carol Diaz",
+        ];
+        std::fs::write(path("a.docx"), fixtures::docx(&lines)).unwrap();
+        std::fs::write(path("a.txt"), lines.join("\n")).unwrap();
+        let search = |name: &str, config: SearchConfig| {
+            parse_from_path(&path("contacts.csv"), &path(name), config.extract_options())
+        };
+        let terms = |results: SearchResults| -> Vec<String> {
+            results.into_iter().map(|result| result.term).collect()
+        };
+
+        // Read whole, the heaviest are kept; streamed, reading stops at the
+        // first found
+        let one = SearchConfig {
+            max_results: Some(1),
+            ..SearchConfig::default()
+        };
+        assert_eq!(terms(search("a.txt", one.clone()).unwrap()), ["Bob Smith"]);
+        assert_eq!(terms(search("a.docx", one.clone()).unwrap()), ["Bob Smith"]);
+        let streamed = |config: &SearchConfig| {
+            docx::parse_from_path(
+                &path("contacts.csv"),
+                &path("a.docx"),
+                config.extract_options(),
+            )
+        };
+        assert_eq!(terms(streamed(&one).unwrap()), ["Alice Johnson"]);
+        assert_eq!(
+            parse_docx_iter(
+                &path("contacts.csv"),
+                &path("a.docx"),
+                one.extract_options()
+            )
+            .count(),
+            1
+        );
+        let two = SearchConfig {
+            max_results: Some(2),
+            ..SearchConfig::default()
+        };
+        assert_eq!(
+            terms(streamed(&two).unwrap()),
+            ["Bob Smith", "Alice Johnson"]
+        );
+        let none = SearchConfig {
+            max_results: Some(0),
+            ..SearchConfig::default()
+        };
+        assert!(streamed(&none).unwrap().is_empty());
+        assert!(search("a.docx", none.clone()).unwrap().is_empty());
+        assert!(txt::parse_from_mem(
+            b"Carol Diaz,id=3\n",
+            lines.join("\n").as_bytes(),
+            none.extract_options()
+        )
+        .unwrap()
+        .is_empty());
+
+        // Past the timeout, every search gives up on the document
+        let at_once = SearchConfig {
+            timeout: Some(Duration::ZERO),
+            ..SearchConfig::default()
+        };
+        for searched in [
+            search("a.txt", at_once.clone()),
+            search("a.docx", at_once.clone()),
+            streamed(&at_once),
+        ] {
+            match searched {
+                Err(DocSearchError::TimedOut { path, after }) => {
+                    assert_eq!(after, Duration::ZERO, "{}", path.display())
+                }
+                other => panic!("the search did not time out: {:?}", other),
+            }
+        }
+        assert!(matches!(
+            txt::parse_from_mem(
+                b"Carol Diaz,id=3\n",
+                b"Carol Diaz",
+                at_once.extract_options()
+            ),
+            Err(DocSearchError::TimedOut { .. })
+        ));
+        let in_time = SearchConfig {
+            timeout: Some(Duration::from_secs(60)),
+            ..SearchConfig::default()
+        };
+        assert_eq!(streamed(&in_time).unwrap().len(), 3);
+
+        let without_context = SearchConfig {
+            include_context: false,
+            ..SearchConfig::default()
+        };
+        for results in [
+            search("a.txt", without_context.clone()),
+            streamed(&without_context),
+        ] {
+            assert!(results
+                .unwrap()
+                .iter()
+                .all(|result| result.context.is_none()));
+        }
+    }
+
+    #[test]
+    fn test_parsing_prints_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(path("contacts.csv"), "Alice Johnson,id=1\nBob Smith,id=2\n").unwrap();
+        std::fs::write(path("a.docx"), fixtures::docx(&["Alice Johnson signed"])).unwrap();
+        std::fs::write(path("a.pdf"), fixtures::pdf(&[&["Alice Johnson signed"]])).unwrap();
+        std::fs::write(path("a.txt"), "Alice Johnson\nsigned\n").unwrap();
+
+        /// The steps it was told about, in order
+        #[derive(Default)]
+        struct Steps(Vec<(&'static str, usize)>);
+        impl ProgressReporter for Steps {
+            fn on_start(&mut self, total_needles: usize) {
+                self.0.push(("needles", total_needles));
+            }
+            fn on_file_open(&mut self) {
+                self.0.push(("opened", 0));
+            }
+            fn on_haystack_built(&mut self, lines: usize) {
+                self.0.push(("extracted", lines));
+            }
+            fn on_search_complete(&mut self, matches: usize) {
+                self.0.push(("searched", matches));
+            }
+            fn on_done(&mut self) {
+                self.0.push(("done", 0));
+            }
+        }
+
+        let mut steps = Steps::default();
+        let mut failed = Steps::default();
+        let printed = crate::output::capture(|| {
+            assert_eq!(
+                parse_from_path(
+                    &path("contacts.csv"),
+                    &path("a.docx"),
+                    ExtractOptions::default()
+                )
+                .unwrap()
+                .len(),
+                1
+            );
+            assert_eq!(
+                pdf::parse_from_path(
+                    &path("contacts.csv"),
+                    &path("a.pdf"),
+                    ExtractOptions::default()
+                )
+                .unwrap()
+                .len(),
+                1
+            );
+            assert_eq!(
+                txt::parse_from_mem(b"Bob Smith,id=2\n", b"Bob Smith", ExtractOptions::default())
+                    .unwrap()
+                    .len(),
+                1
+            );
+            parse_with_progress(
+                &path("contacts.csv"),
+                &path("a.txt"),
+                ExtractOptions::default(),
+                &mut steps,
+            )
+            .unwrap();
+            parse_with_progress(
+                &path("contacts.csv"),
+                &path("missing.txt"),
+                ExtractOptions::default(),
+                &mut failed,
+            )
+            .unwrap_err();
+        });
+        assert_eq!(printed, "");
+
+        assert_eq!(
+            steps.0,
+            [
+                ("needles", 2),
+                ("opened", 0),
+                ("extracted", 2),
+                ("searched", 1),
+                ("done", 0)
+            ]
+        );
+        assert_eq!(failed.0, [("needles", 2), ("done", 0)]);
+    }
+
+    #[test]
+    fn test_every_file_type_has_one_parser() {
+        for file_type in [
+            FileType::Docx,
+            FileType::Pdf,
+            FileType::Doc,
+            FileType::Xlsx,
+            FileType::Odt,
+            FileType::Txt,
+            FileType::Rtf,
+            FileType::Epub,
+        ] {
+            let parsers = PARSERS
+                .iter()
+                .filter(|parser| parser.file_types().contains(&file_type))
+                .count();
+            assert_eq!(parsers, 1, "{:?}", file_type);
+            let path = format!("document{}", file_type.extension());
+            assert!(parser_for(file_type).supports(Path::new(&path)), "{}", path);
+        }
+        assert!(!PARSERS
+            .iter()
+            .any(|parser| parser.supports(Path::new("archive.zip"))));
+    }
+
+    #[test]
+    fn test_parse_any_dispatches_on_the_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = vec![("Alice Johnson".to_string(), "id=1".to_string())];
+        let documents: [(&str, Vec<u8>); 7] = [
+            ("a.docx", fixtures::docx(&["Alice Johnson"])),
+            ("a.pdf", fixtures::pdf(&[&["Alice Johnson"]])),
+            (
+                "a.xlsx",
+                fixtures::xlsx(&[("Sheet1", &[&["Alice Johnson"]])]),
+            ),
+            ("a.odt", fixtures::odt(&["Alice Johnson"])),
+            ("a.txt", b"Alice Johnson".to_vec()),
+            ("a.rtf", fixtures::rtf(&["Alice Johnson"])),
+            ("a.epub", fixtures::epub(&[&["Alice Johnson"]])),
+        ];
+        for (name, bytes) in documents {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            assert_eq!(
+                parse_any(&path, &needles, ExtractOptions::default())
+                    .unwrap()
+                    .occurrences
+                    .len(),
+                1,
+                "{}",
+                name
+            );
+        }
+        let unsupported = dir.path().join("a.pptx");
+        std::fs::write(&unsupported, b"Alice Johnson").unwrap();
+        assert!(parse_any(&unsupported, &needles, ExtractOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,550 @@
+//! OpenDocument text documents (`.odt`), as written by LibreOffice and
+//! OpenOffice: every `text:p` paragraph and `text:h` heading is a block,
+//! located like a DOCX paragraph.
+//!
+//! A document is a ZIP archive whose `content.xml` part holds the body,
+//! `styles.xml` the headers and footers of its master pages, and `meta.xml`
+//! its title, author and other properties.
+
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+use zip::ZipArchive;
+
+use super::docx::{read_part, DocxMeta};
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{search_blocks, BlockBudget, BlockLocation, Extraction, TextBlock};
+use crate::types::{ContextKind, ExtractOptions, FileType, Location, SearchResults};
+
+/// [`DocumentParser`] for OpenDocument text documents
+pub struct OdtParser;
+
+impl DocumentParser for OdtParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Odt]
+    }
+
+    fn extract_text(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        Ok(ExtractedDocument::new(blocks_mem(bytes, extract)?))
+    }
+
+    fn extract_within(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        budget: BlockBudget,
+    ) -> Result<Vec<TextBlock>> {
+        blocks_within(bytes, extract, budget)
+    }
+}
+
+/// Search an in-memory ODT document for the needles of an in-memory needles
+/// file, searching as `extract` says
+pub fn parse_from_mem(
+    needle_bytes: &[u8],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_mem_for_needles(&OdtParser, needle_bytes, haystack_bytes, extract)
+}
+
+/// Report every occurrence of `needles` in an in-memory ODT document, also
+/// searching the content `extract` opts into
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    let haystack = extract_haystack(&mut archive, extract, None)?;
+
+    Ok(search_blocks(needles, &haystack, extract))
+}
+
+/// The blocks [`occurrences_mem`] searches
+pub fn blocks_mem(haystack_bytes: &[u8], extract: ExtractOptions) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, extract, None)
+}
+
+/// The first blocks of [`blocks_mem`], as many as `budget` allows
+pub fn blocks_within(
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+    budget: BlockBudget,
+) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, extract, Some(budget))
+}
+
+/// Search an ODT file on disk for the needles of a needles file, searching
+/// as `extract` says
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_path(
+        &OdtParser,
+        needle_path,
+        file_path,
+        extract,
+        &mut NullReporter,
+    )
+}
+
+/// Search an ODT file on disk with needles that were already loaded,
+/// reporting every occurrence
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(&OdtParser, needles, file_path, extract, &mut NullReporter)
+}
+
+/// Read the properties of the ODT file at `path` from its `meta.xml` part.
+/// A document without one has none set.
+pub fn extract_odt_meta(path: &Path) -> Result<DocxMeta> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    Ok(properties(&mut archive))
+}
+
+/// The properties of a document. The author is the `dc:creator` who last
+/// saved it, or the `meta:initial-creator` when it names none, and the
+/// `meta:keyword`s are joined with commas.
+fn properties<R>(archive: &mut ZipArchive<R>) -> DocxMeta
+where
+    R: Seek,
+    R: Read,
+{
+    let Some(buffer) = read_part(archive, "meta.xml") else {
+        return DocxMeta::default();
+    };
+    let Ok(xml) = roxmltree::Document::parse(&buffer) else {
+        return DocxMeta::default();
+    };
+
+    let values = |name: &str| -> Vec<&str> {
+        xml.descendants()
+            .filter(|elem| elem.has_tag_name(name))
+            .filter_map(|elem| elem.text())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    let property = |name: &str| values(name).first().map(|value| value.to_string());
+    let keywords = values("keyword");
+    DocxMeta {
+        title: property("title"),
+        author: property("creator").or_else(|| property("initial-creator")),
+        subject: property("subject"),
+        keywords: (!keywords.is_empty()).then(|| keywords.join(", ")),
+        created: property("creation-date"),
+        modified: property("date"),
+    }
+}
+
+/// Collect the text of every paragraph and heading of the body, located at
+/// its paragraph under the headings before it, then the paragraphs of the
+/// headers and footers, of the footnotes and of the endnotes, then the
+/// properties when `extract` asks for them. With a `budget`, stop once it
+/// is spent.
+fn extract_haystack<R>(
+    archive: &mut ZipArchive<R>,
+    extract: ExtractOptions,
+    budget: Option<BlockBudget>,
+) -> Result<Vec<TextBlock>>
+where
+    R: Seek,
+    R: Read,
+{
+    let content = read_part(archive, "content.xml").context("Document content not found")?;
+    let content =
+        roxmltree::Document::parse(&content).context("Could not parse the document content")?;
+    let body = content
+        .descendants()
+        .find(|elem| {
+            elem.has_tag_name("text")
+                && elem
+                    .parent()
+                    .is_some_and(|parent| parent.has_tag_name("body"))
+        })
+        .context("Document has no text body")?;
+
+    let mut haystack = Vec::new();
+    // The enclosing headings, with their levels, outermost first
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut page = 1;
+    let mut index = 0;
+    for elem in body.descendants() {
+        if budget.is_some_and(|budget| budget.spent(haystack.len())) {
+            break;
+        }
+        // LibreOffice records where each page began when it last laid the
+        // document out
+        if elem.has_tag_name("soft-page-break") {
+            page += 1;
+            continue;
+        }
+        if !is_paragraph(elem)
+            || elem
+                .ancestors()
+                .any(|ancestor| is_set_aside(ancestor) || ancestor.has_tag_name("note"))
+        {
+            continue;
+        }
+
+        let level = elem.has_tag_name("h").then(|| {
+            attribute(elem, "outline-level")
+                .and_then(|level| level.parse().ok())
+                .unwrap_or(1)
+        });
+        if let Some(level) = level {
+            headings.retain(|(enclosing, _)| *enclosing < level);
+        }
+        let location = BlockLocation::DocxParagraph {
+            index,
+            heading_path: headings
+                .iter()
+                .map(|(_, heading)| heading.clone())
+                .collect(),
+            page,
+            cell: table_cell(elem),
+        };
+        index += 1;
+
+        let text = paragraph_text(elem);
+        if let Some(level) = level.filter(|_| !text.trim().is_empty()) {
+            headings.push((level, text.trim().to_string()));
+        }
+        if !text.is_empty() {
+            haystack.push(TextBlock::new(text, location));
+        }
+    }
+
+    // After the body, so blocks keep their index whether or not a document
+    // has headers, footers or notes
+    if let Some(styles) = read_part(archive, "styles.xml") {
+        let styles =
+            roxmltree::Document::parse(&styles).context("Could not parse the document styles")?;
+        for (tags, part) in [(HEADERS, Location::Header), (FOOTERS, Location::Footer)] {
+            let paragraphs = styles.descendants().filter(|elem| {
+                is_paragraph(*elem)
+                    && elem
+                        .ancestors()
+                        .any(|ancestor| tags.iter().any(|tag| ancestor.has_tag_name(*tag)))
+            });
+            haystack.extend(part_blocks(paragraphs, &part));
+        }
+    }
+    for class in ["footnote", "endnote"] {
+        for (number, note) in body
+            .descendants()
+            .filter(|elem| {
+                elem.has_tag_name("note") && attribute(*elem, "note-class") == Some(class)
+            })
+            .enumerate()
+        {
+            let citation = note
+                .children()
+                .find(|elem| elem.has_tag_name("note-citation"))
+                .and_then(|citation| citation.text());
+            let number = citation
+                .and_then(|citation| citation.trim().parse().ok())
+                .unwrap_or(number as u32 + 1);
+            let part = if class == "footnote" {
+                Location::Footnote(number)
+            } else {
+                Location::Endnote(number)
+            };
+            let paragraphs = note.descendants().filter(|elem| {
+                is_paragraph(*elem)
+                    && elem
+                        .ancestors()
+                        .find(|ancestor| ancestor.has_tag_name("note"))
+                        == Some(note)
+            });
+            haystack.extend(part_blocks(paragraphs, &part));
+        }
+    }
+    if extract.include_metadata {
+        let meta = properties(archive);
+        haystack.extend(
+            meta.searchable()
+                .into_iter()
+                .map(|(name, value)| TextBlock {
+                    concealed: Some(ContextKind::Metadata),
+                    ..TextBlock::new(
+                        value,
+                        BlockLocation::DocxProperty {
+                            name: name.to_string(),
+                        },
+                    )
+                }),
+        );
+    }
+
+    if let Some(budget) = budget {
+        haystack.truncate(budget.blocks);
+    }
+    Ok(haystack)
+}
+
+/// The contents of a master page that are headers
+const HEADERS: &[&str] = &["header", "header-left", "header-first"];
+/// The contents of a master page that are footers
+const FOOTERS: &[&str] = &["footer", "footer-left", "footer-first"];
+
+/// The non-empty paragraphs of a header, footer or note, counted from 0
+/// within it
+fn part_blocks<'a, 'input: 'a>(
+    paragraphs: impl Iterator<Item = roxmltree::Node<'a, 'input>>,
+    part: &Location,
+) -> Vec<TextBlock> {
+    paragraphs
+        .enumerate()
+        .map(|(index, paragraph)| (index, paragraph_text(paragraph)))
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(index, text)| {
+            TextBlock::new(
+                text,
+                BlockLocation::DocxPart {
+                    part: part.clone(),
+                    index,
+                },
+            )
+        })
+        .collect()
+}
+
+fn is_paragraph(elem: roxmltree::Node) -> bool {
+    elem.has_tag_name("p") || elem.has_tag_name("h")
+}
+
+/// Content that is not part of the text as it reads: comments, and the
+/// deleted text that tracked changes keep
+fn is_set_aside(elem: roxmltree::Node) -> bool {
+    elem.has_tag_name("annotation") || elem.has_tag_name("tracked-changes")
+}
+
+/// The value of an attribute by local name, whatever its namespace
+fn attribute<'a>(elem: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    elem.attributes()
+        .find(|attr| attr.name() == name)
+        .map(|attr| attr.value())
+}
+
+/// The text of a paragraph as it reads: `text:s` spaces expanded, tabs and
+/// line breaks as spaces, and leaving out notes, comments and the paragraphs
+/// of frames nested in it, which are blocks of their own
+fn paragraph_text(paragraph: roxmltree::Node) -> String {
+    fn push_text(node: roxmltree::Node, text: &mut String) {
+        for child in node.children() {
+            if child.is_text() {
+                text.push_str(child.text().unwrap_or_default());
+                continue;
+            }
+            match child.tag_name().name() {
+                "s" => text.extend(std::iter::repeat_n(
+                    ' ',
+                    attribute(child, "c")
+                        .and_then(|c| c.parse().ok())
+                        .unwrap_or(1),
+                )),
+                "tab" | "line-break" => text.push(' '),
+                "note" | "annotation" | "p" | "h" => {}
+                _ => push_text(child, text),
+            }
+        }
+    }
+
+    let mut text = String::new();
+    push_text(paragraph, &mut text);
+    text
+}
+
+/// The row and column, counted from 1, of the table cell a paragraph is
+/// in, the innermost one for nested tables. Columns count the cells a
+/// repeated or spanned cell stands for.
+fn table_cell(paragraph: roxmltree::Node) -> Option<(u32, u32)> {
+    let cell = paragraph
+        .ancestors()
+        .take_while(|elem| !elem.has_tag_name("table"))
+        .find(|elem| elem.has_tag_name("table-cell"))?;
+    let row = cell
+        .parent()
+        .filter(|elem| elem.has_tag_name("table-row"))?;
+    let table = row.ancestors().find(|elem| elem.has_tag_name("table"))?;
+
+    let own_rows = table.descendants().filter(|elem| {
+        elem.has_tag_name("table-row")
+            && elem
+                .ancestors()
+                .find(|ancestor| ancestor.has_tag_name("table"))
+                == Some(table)
+    });
+    let row_number = own_rows.take_while(|elem| *elem != row).count() as u32 + 1;
+    let col_number = cell
+        .prev_siblings()
+        .skip(1)
+        .filter(|elem| elem.has_tag_name("table-cell") || elem.has_tag_name("covered-table-cell"))
+        .map(|elem| {
+            attribute(elem, "number-columns-repeated")
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(1)
+        })
+        .sum::<u32>()
+        + 1;
+    Some((row_number, col_number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn needles(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(term, metadata)| (term.to_string(), metadata.to_string()))
+            .collect()
+    }
+
+    fn located(blocks: &[TextBlock]) -> Vec<(String, &str)> {
+        blocks
+            .iter()
+            .map(|block| (block.location.to_string(), block.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_paragraphs_are_searched() {
+        let bytes = fixtures::odt(&["Dear Ms Johnson,", "", "Regards, Bob Smith"]);
+
+        let needles = needles(&[
+            ("Alice Johnson", "id=1"),
+            ("Johnson", "id=2"),
+            ("bob smith", "id=3"),
+        ]);
+        let extraction = occurrences_mem(&needles, &bytes, ExtractOptions::default()).unwrap();
+        let found: Vec<(&str, Option<usize>)> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| (occurrence.term.as_str(), occurrence.location.page()))
+            .collect();
+        assert_eq!(found, [("Johnson", Some(1)), ("bob smith", Some(1))]);
+
+        let needle_bytes = b"Bob Smith,id=3\n";
+        let results = parse_from_mem(needle_bytes, &bytes, ExtractOptions::default()).unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.term.as_str())
+                .collect::<Vec<_>>(),
+            ["Bob Smith"]
+        );
+    }
+
+    #[test]
+    fn test_headings_tables_spans_and_notes() {
+        let body = "<text:tracked-changes><text:changed-region><text:deletion><text:p>Deleted Dave</text:p></text:deletion></text:changed-region></text:tracked-changes>\
+            <text:h text:outline-level=\"1\">Contracts</text:h>\
+            <text:p>Signed by <text:span>Alice</text:span><text:s text:c=\"2\"/>Johnson<text:note text:note-class=\"footnote\">\
+            <text:note-citation>1</text:note-citation><text:note-body><text:p>Witness: Carol Diaz</text:p></text:note-body></text:note>\
+            <office:annotation><text:p>Check with Erin</text:p></office:annotation></text:p>\
+            <text:soft-page-break/>\
+            <text:h text:outline-level=\"2\">Termination</text:h>\
+            <table:table><table:table-row><table:table-cell table:number-columns-repeated=\"2\"/>\
+            <table:table-cell><text:p>Bob<text:tab/>Smith</text:p></table:table-cell></table:table-row></table:table>";
+        let master_page = "<style:header><text:p>Acme Confidential</text:p></style:header><style:footer-left><text:p>Page</text:p></style:footer-left>";
+        let bytes = fixtures::odt_from_xml(body, master_page, &[]);
+
+        let blocks = blocks_mem(&bytes, ExtractOptions::default()).unwrap();
+        assert_eq!(
+            located(&blocks),
+            [
+                ("paragraph 1".to_string(), "Contracts"),
+                (
+                    "paragraph 2 under Contracts".to_string(),
+                    "Signed by Alice  Johnson"
+                ),
+                ("paragraph 3 under Contracts".to_string(), "Termination"),
+                (
+                    "paragraph 4 in table row 1, column 3 under Contracts › Termination"
+                        .to_string(),
+                    "Bob Smith"
+                ),
+                ("header paragraph 1".to_string(), "Acme Confidential"),
+                ("footer paragraph 1".to_string(), "Page"),
+                ("footnote 1 paragraph 1".to_string(), "Witness: Carol Diaz"),
+            ]
+        );
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|block| block.location.page())
+                .collect::<Vec<_>>(),
+            [Some(1), Some(1), Some(2), Some(2), None, None, None]
+        );
+        assert_eq!(
+            blocks[3].location.part(),
+            Location::Table { row: 1, col: 3 }
+        );
+    }
+
+    #[test]
+    fn test_properties_are_read_and_searched_when_asked() {
+        let properties = [
+            ("dc:title", "Board minutes"),
+            ("meta:initial-creator", "Alice Johnson"),
+            ("meta:keyword", "board"),
+            ("meta:keyword", "Bob Smith"),
+            ("dc:date", "2024-03-01T09:30:00"),
+        ];
+        let bytes = fixtures::odt_from_xml("<text:p>Present: Carol Diaz</text:p>", "", &properties);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("minutes.odt");
+        std::fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(
+            extract_odt_meta(&path).unwrap(),
+            DocxMeta {
+                title: Some("Board minutes".to_string()),
+                author: Some("Alice Johnson".to_string()),
+                keywords: Some("board, Bob Smith".to_string()),
+                modified: Some("2024-03-01T09:30:00".to_string()),
+                ..DocxMeta::default()
+            }
+        );
+
+        let blocks = blocks_mem(
+            &bytes,
+            ExtractOptions {
+                include_metadata: true,
+                ..ExtractOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            located(&blocks),
+            [
+                ("paragraph 1".to_string(), "Present: Carol Diaz"),
+                ("title property".to_string(), "Board minutes"),
+                ("author property".to_string(), "Alice Johnson"),
+                ("keywords property".to_string(), "board, Bob Smith"),
+            ]
+        );
+    }
+}
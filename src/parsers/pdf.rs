@@ -1,98 +1,847 @@
 use anyhow::{Context, Result};
-use colored::Colorize;
+use pdf_extract::{ConvertToFmt, MediaBox, OutputDev, OutputError, PlainTextOutput, Transform};
 use std::{
-    collections::HashSet,
-    time::Instant,
+    cell::RefCell,
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    rc::Rc,
 };
 
-use crate::utils::read_needles_from_file;
-use crate::types::SearchResult;
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{
+    find_occurrences, merge_results, results_from_occurrences, BlockBudget, BlockLocation,
+    Extraction, Occurrence, TextBlock,
+};
+use crate::types::{ContextKind, ExtractOptions, FileType, SearchResult, SearchResults};
+
+/// [`DocumentParser`] for PDFs
+pub struct PdfParser;
+
+impl DocumentParser for PdfParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Pdf]
+    }
+
+    fn extract_text(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        Ok(extract_paged_text(bytes)
+            .context("Failed to extract text from pdf")?
+            .document(extract))
+    }
+
+    fn extract_within(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        _extract: ExtractOptions,
+        budget: BlockBudget,
+    ) -> Result<Vec<TextBlock>> {
+        blocks_within(bytes, budget)
+    }
+
+    fn each_block(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        extract: ExtractOptions,
+        on_block: &mut dyn FnMut(TextBlock) -> ControlFlow<()>,
+    ) -> Result<()> {
+        let mut document =
+            lopdf::Document::load_mem(bytes).context("Failed to extract text from pdf")?;
+        if extract.include_metadata {
+            let meta = PagedText {
+                meta: PdfMeta::of(&document),
+                ..PagedText::default()
+            }
+            .searched_blocks(extract);
+            if meta.into_iter().try_for_each(&mut *on_block).is_break() {
+                return Ok(());
+            }
+        }
+        let skipped_pages = each_page(&mut document, &mut |page, text| {
+            text.lines().try_for_each(|line| {
+                on_block(TextBlock::new(line, BlockLocation::PdfPage { page }))
+            })
+        });
+        if let Some(warning) = (PagedText {
+            skipped_pages,
+            ..PagedText::default()
+        })
+        .skipped_warning()
+        {
+            tracing::warn!("{}", warning);
+        }
+        Ok(())
+    }
+}
 
+/// Search an in-memory PDF for the needles of an in-memory needles file,
+/// searching as `extract` says
 pub fn parse_from_mem(
     needle_bytes: &[u8],
     haystack_bytes: &[u8],
-) -> Result<HashSet<SearchResult>> {
-    let needles = crate::utils::read_needles_from_mem(needle_bytes)?;
-    println!("Searching across {} contacts", needles.len());
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_mem_for_needles(&PdfParser, needle_bytes, haystack_bytes, extract)
+}
+
+/// Search an in-memory PDF with needles that were already loaded.
+///
+/// Unlike [`parse_from_mem`] this does not re-read the needles for every
+/// document, which makes it suitable for worker threads in batch mode.
+pub fn search_mem(needles: &[(String, String)], haystack_bytes: &[u8]) -> Result<SearchResults> {
+    Ok(results_from_occurrences(
+        &occurrences_mem(needles, haystack_bytes, ExtractOptions::default())?.occurrences,
+    ))
+}
+
+/// Like [`search_mem`], but report every occurrence rather than the
+/// distinct needles, looking them up as `extract` says
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    let haystack = extract_paged_text(haystack_bytes).context("Failed to extract text from pdf")?;
 
-    parse(&needles, haystack_bytes)
+    Ok(haystack.extraction(needles, extract))
 }
 
+/// The blocks [`occurrences_mem`] searches, looking them up as `extract`
+/// says
+pub fn blocks_mem(haystack_bytes: &[u8], extract: ExtractOptions) -> Result<Vec<TextBlock>> {
+    let haystack = extract_paged_text(haystack_bytes).context("Failed to extract text from pdf")?;
+
+    Ok(haystack.searched_blocks(extract))
+}
+
+/// The first non-blank blocks of [`blocks_mem`], as many as `budget`
+/// allows. Pages after the one that spends the budget are not read.
+pub fn blocks_within(haystack_bytes: &[u8], budget: BlockBudget) -> Result<Vec<TextBlock>> {
+    let haystack = extract_paged_text_within(haystack_bytes, Some(budget))
+        .context("Failed to extract text from pdf")?;
+
+    Ok(haystack
+        .blocks()
+        .into_iter()
+        .filter(|block| !block.text.trim().is_empty())
+        .take(budget.blocks)
+        .collect())
+}
+
+/// Search a PDF file on disk for the needles of a needles file, searching
+/// as `extract` says: [`parse_iter`], with the results of each needle
+/// combined
 pub fn parse_from_path(
     needles_path: &str,
     haystack_path: &str,
-) -> Result<HashSet<SearchResult>> {
-    let start = Instant::now();
-    let needles = read_needles_from_file(needles_path)?;
-    println!(
-        "{}",
-        format!(
-            "Read {} contacts in {} ms",
-            needles.len(),
-            start.elapsed().as_millis()
-        )
-        .blue()
-    );
-
-    let start = Instant::now();
-    let text = pdf_extract::extract_text(haystack_path)?;
-    println!(
-        "{}",
-        format!("Extracted text in {} ms", start.elapsed().as_millis()).blue()
-    );
-
-    println!("{}", "Starting search...".blue());
-    let start = Instant::now();
-    let matches = text.lines().fold(HashSet::new(), |mut acc, line| {
-        needles
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    Ok(merge_results(
+        parse_iter(needles_path, haystack_path, extract).collect::<Result<_, _>>()?,
+    ))
+}
+
+/// Search a PDF file on disk for the needles of a needles file, yielding
+/// each needle once for every line it is found on, as the page of that line
+/// is read. Only the text of the page being read is held.
+pub fn parse_iter(
+    needles_path: &str,
+    haystack_path: &str,
+    extract: ExtractOptions,
+) -> impl Iterator<Item = Result<SearchResult, DocSearchError>> + Send {
+    super::search_path_iter(&PdfParser, needles_path, haystack_path, extract)
+}
+
+/// Search a PDF file on disk with needles that were already loaded,
+/// reporting every occurrence
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    haystack_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(
+        &PdfParser,
+        needles,
+        haystack_path,
+        extract,
+        &mut NullReporter,
+    )
+}
+
+/// The document information of a PDF, from the `Info` dictionary its
+/// trailer names; an entry the document does not set is `None`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PdfMeta {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    /// As written, usually separated by commas or semicolons
+    pub keywords: Option<String>,
+    /// The application the document was written in
+    pub creator: Option<String>,
+    /// The application that converted it to PDF
+    pub producer: Option<String>,
+    /// When the document was created, as the PDF date it records, such as
+    /// `D:20240131120000Z`
+    pub creation_date: Option<String>,
+    /// When the document was last changed, as the PDF date it records
+    pub mod_date: Option<String>,
+}
+
+impl PdfMeta {
+    /// The entries that are set, by name, in the order declared
+    pub fn fields(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("title", &self.title),
+            ("author", &self.author),
+            ("subject", &self.subject),
+            ("keywords", &self.keywords),
+            ("creator", &self.creator),
+            ("producer", &self.producer),
+            ("creation date", &self.creation_date),
+            ("modification date", &self.mod_date),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+        .collect()
+    }
+
+    /// The entries searched with `--search-meta`: the dates are left out
+    fn searchable(&self) -> Vec<(&'static str, &str)> {
+        self.fields()
+            .into_iter()
+            .filter(|(name, _)| !name.ends_with("date"))
+            .collect()
+    }
+
+    /// The entries of the `Info` dictionary of `document`
+    fn of(document: &lopdf::Document) -> Self {
+        let info = match document.trailer.get(b"Info") {
+            Ok(lopdf::Object::Reference(id)) => document.get_dictionary(*id).ok(),
+            Ok(lopdf::Object::Dictionary(info)) => Some(info),
+            _ => None,
+        };
+        let Some(info) = info else {
+            return PdfMeta::default();
+        };
+
+        let entry = |key: &[u8]| {
+            let value = match info.get(key).ok()? {
+                lopdf::Object::Reference(id) => document.get_object(*id).ok()?,
+                value => value,
+            };
+            let value = decode_text_string(value.as_str().ok()?);
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+        PdfMeta {
+            title: entry(b"Title"),
+            author: entry(b"Author"),
+            subject: entry(b"Subject"),
+            keywords: entry(b"Keywords"),
+            creator: entry(b"Creator"),
+            producer: entry(b"Producer"),
+            creation_date: entry(b"CreationDate"),
+            mod_date: entry(b"ModDate"),
+        }
+    }
+}
+
+/// A PDF text string: UTF-16BE after a byte order mark, otherwise one byte
+/// per character, which reads PDFDocEncoding right for all but a few
+/// punctuation marks
+fn decode_text_string(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// Read the document information of the PDF file at `path`. A document
+/// without an `Info` dictionary has none set.
+pub fn extract_pdf_meta(path: &Path) -> Result<PdfMeta> {
+    let document = lopdf::Document::load(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(PdfMeta::of(&document))
+}
+
+/// Text extracted from a PDF, with the byte offset at which each page starts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PagedText {
+    pub text: String,
+    pub page_starts: Vec<usize>,
+    /// 1-based pages whose text could not be extracted; they are left empty
+    pub skipped_pages: Vec<usize>,
+    pub meta: PdfMeta,
+}
+
+impl PagedText {
+    /// The 1-based page containing byte `offset` of the text
+    pub fn page_at(&self, offset: usize) -> usize {
+        self.page_starts
+            .partition_point(|&start| start <= offset)
+            .max(1)
+    }
+
+    /// The text of each page, in order
+    pub fn pages(&self) -> Vec<&str> {
+        self.page_starts
             .iter()
-            .filter(|n| line.contains(&n.0))
-            .for_each(|n| {
-                acc.insert((n.0.clone(), n.1.clone()));
+            .enumerate()
+            .map(|(index, &start)| {
+                let end = self
+                    .page_starts
+                    .get(index + 1)
+                    .copied()
+                    .unwrap_or(self.text.len());
+                &self.text[start..end]
+            })
+            .collect()
+    }
+
+    /// Every line of the text, as a block on its page
+    pub fn blocks(&self) -> Vec<TextBlock> {
+        self.text
+            .lines()
+            .map(|line| {
+                let page = self.page_at(line.as_ptr() as usize - self.text.as_ptr() as usize);
+                TextBlock::new(line, BlockLocation::PdfPage { page })
+            })
+            .collect()
+    }
+
+    /// The blocks searched as `extract` says: the searchable document
+    /// information when it asks for metadata, then [`blocks`](Self::blocks)
+    pub fn searched_blocks(&self, extract: ExtractOptions) -> Vec<TextBlock> {
+        let meta = self
+            .meta
+            .searchable()
+            .into_iter()
+            .filter(|_| extract.include_metadata)
+            .map(|(name, value)| TextBlock {
+                concealed: Some(ContextKind::Metadata),
+                ..TextBlock::new(
+                    value,
+                    BlockLocation::PdfProperty {
+                        name: name.to_string(),
+                    },
+                )
             });
-        acc
+        meta.chain(self.blocks()).collect()
+    }
+
+    /// Find every occurrence of `needles`, tagged with its page
+    pub fn occurrences(
+        &self,
+        needles: &[(String, String)],
+        extract: ExtractOptions,
+    ) -> Vec<Occurrence> {
+        find_occurrences(needles, &self.searched_blocks(extract), extract)
+    }
+
+    /// [`occurrences`](Self::occurrences) together with the size of the
+    /// text, warning about any skipped pages
+    pub fn extraction(&self, needles: &[(String, String)], extract: ExtractOptions) -> Extraction {
+        self.document(extract).search(needles, extract)
+    }
+
+    /// The [`searched_blocks`](Self::searched_blocks), with the size of the
+    /// text and a warning about any skipped pages
+    pub fn document(&self, extract: ExtractOptions) -> ExtractedDocument {
+        let warnings: Vec<String> = self.skipped_warning().into_iter().collect();
+        for warning in &warnings {
+            tracing::warn!("{}", warning);
+        }
+        ExtractedDocument {
+            blocks: self.searched_blocks(extract),
+            text_bytes: self.text.len() as u64,
+            warnings,
+        }
+    }
+
+    /// A note of the pages that could not be extracted, if there are any
+    pub fn skipped_warning(&self) -> Option<String> {
+        let (last, rest) = self.skipped_pages.split_last()?;
+        Some(match rest {
+            [] => format!("could not extract the text of page {}", last),
+            _ => format!(
+                "could not extract the text of pages {}",
+                self.skipped_pages
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        })
+    }
+}
+
+/// Extract the text of a PDF like `pdf_extract::extract_text_from_mem`,
+/// recording where each page starts and starting every page on a new line
+pub fn extract_paged_text(bytes: &[u8]) -> Result<PagedText> {
+    extract_paged_text_within(bytes, None)
+}
+
+/// The text of each page of the PDF file at `path`, in order
+pub fn extract_pages(path: &Path) -> Result<Vec<String>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let paged = extract_paged_text(&bytes).context("Failed to extract text from pdf")?;
+    Ok(paged.pages().into_iter().map(str::to_string).collect())
+}
+
+/// Like [`extract_paged_text`], stopping before the first page that starts
+/// once `budget` is spent
+fn extract_paged_text_within(bytes: &[u8], budget: Option<BlockBudget>) -> Result<PagedText> {
+    let mut document = lopdf::Document::load_mem(bytes)?;
+    let mut paged = PagedText {
+        meta: PdfMeta::of(&document),
+        ..PagedText::default()
+    };
+    if budget.is_some_and(|budget| budget.spent(0)) {
+        return Ok(paged);
+    }
+    paged.skipped_pages = each_page(&mut document, &mut |_, page| {
+        if !paged.text.is_empty() && !paged.text.ends_with('\n') {
+            paged.text.push('\n');
+        }
+        paged.page_starts.push(paged.text.len());
+        paged.text.push_str(page);
+        match budget.is_some_and(|budget| {
+            budget.spent(
+                paged
+                    .text
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .count(),
+            )
+        }) {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        }
     });
-    println!(
-        "{}",
-        format!("Search completed in {} ms", start.elapsed().as_millis()).blue()
-    );
+    Ok(paged)
+}
+
+/// Hand `on_page` the 1-based number and text of each page of `document` in
+/// turn, stopping when it breaks, and return the pages whose text could not
+/// be extracted.
+///
+/// A page whose text cannot be extracted, which `pdf_extract` mostly reports
+/// by panicking, is handed over empty; extraction carries on with the pages
+/// after it, which are all that is left of `document` once it is done.
+fn each_page(
+    document: &mut lopdf::Document,
+    on_page: &mut dyn FnMut(usize, &str) -> ControlFlow<()>,
+) -> Vec<usize> {
+    let text = SharedText::default();
+    let mut output = PagedOutput {
+        inner: PlainTextOutput::new(text.clone()),
+        text: text.clone(),
+        ended: 0,
+        on_page,
+        stopped: false,
+    };
+    let mut skipped_pages = Vec::new();
+    loop {
+        let done = output.ended;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pdf_extract::output_doc(document, &mut output)
+        }))
+        .unwrap_or(Err(OutputError::FormatError(std::fmt::Error)));
+        let error = match result {
+            Err(_) if output.stopped => break,
+            Ok(()) => break,
+            Err(error) => error,
+        };
+        // Every page before the failed one was ended; what the failed one
+        // wrote, if it was begun, is dropped
+        let failed = output.ended + 1;
+        text.0.borrow_mut().clear();
+        output.ended = failed;
+        skipped_pages.push(failed);
+        tracing::debug!(page = failed, "skipping pdf page: {}", error);
+        if (output.on_page)(failed, "").is_break() {
+            break;
+        }
+        // Go on from the page after the failed one in what is left of the
+        // document, whose pages are numbered from 1 again
+        let begun: Vec<u32> = (1..=(failed - done) as u32).collect();
+        document.delete_pages(&begun);
+        if document.get_pages().is_empty() {
+            break;
+        }
+    }
+    skipped_pages
+}
+
+/// A text buffer shared between the plain text writer and [`PagedOutput`]
+#[derive(Clone, Default)]
+struct SharedText(Rc<RefCell<String>>);
+
+impl std::fmt::Write for SharedText {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0.borrow_mut().push_str(s);
+        Ok(())
+    }
+}
+
+impl ConvertToFmt for SharedText {
+    type Writer = SharedText;
+
+    fn convert(self) -> Self::Writer {
+        self
+    }
+}
+
+/// Plain text output that hands over the text of each page as it ends
+struct PagedOutput<'a> {
+    inner: PlainTextOutput<SharedText>,
+    /// The text of the current page
+    text: SharedText,
+    /// Pages ended, or skipped, so far
+    ended: usize,
+    on_page: &'a mut dyn FnMut(usize, &str) -> ControlFlow<()>,
+    /// Set when `on_page` ended extraction, which `pdf_extract` only allows
+    /// by failing
+    stopped: bool,
+}
+
+impl OutputDev for PagedOutput<'_> {
+    fn begin_page(
+        &mut self,
+        page_num: u32,
+        media_box: &MediaBox,
+        art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        self.text.0.borrow_mut().clear();
+        self.inner.begin_page(page_num, media_box, art_box)
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        self.inner.end_page()?;
+        self.ended += 1;
+        let page = self.text.0.take();
+        if (self.on_page)(self.ended, &page).is_break() {
+            self.stopped = true;
+            return Err(OutputError::FormatError(std::fmt::Error));
+        }
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> Result<(), OutputError> {
+        self.inner
+            .output_character(trm, width, spacing, font_size, char)
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        self.inner.begin_word()
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        self.inner.end_word()
+    }
 
-    Ok(matches)
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        self.inner.end_line()
+    }
 }
 
-fn parse(needles: &[(String, String)], haystack_bytes: &[u8]) -> Result<HashSet<SearchResult>> {
-    println!("{}", format!("Starting extracting text from pdf...").blue());
-    let start = Instant::now();
-    let haystack = pdf_extract::extract_text_from_mem(&haystack_bytes).with_context(|| {
-        format!(
-            "Failed to extract text from pdf: {}",
-            String::from_utf8_lossy(haystack_bytes)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures,
+        matcher::Strategy,
+        types::{MatchOptions, SearchResult},
+    };
+    use std::time::Instant;
+
+    #[test]
+    fn test_paged_text_keeps_pages_apart() {
+        let bytes = fixtures::pdf(&[
+            &["Alice Johnson", "Page 1"],
+            &["Bob Smith"],
+            &["Alice Johnson again"],
+        ]);
+
+        let paged = extract_paged_text(&bytes).unwrap();
+        assert_eq!(paged.page_starts.len(), 3);
+        let lines: Vec<&str> = paged
+            .text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "Alice Johnson",
+                "Page 1",
+                "Bob Smith",
+                "Alice Johnson again"
+            ]
+        );
+
+        let needles = vec![
+            ("Alice Johnson".to_string(), "id=1".to_string()),
+            ("Bob".to_string(), "id=2".to_string()),
+        ];
+        let occurrences = paged.occurrences(&needles, ExtractOptions::default());
+        let pages: Vec<(&str, Option<usize>)> = occurrences
+            .iter()
+            .map(|occurrence| (occurrence.term.as_str(), occurrence.page()))
+            .collect();
+        assert_eq!(
+            pages,
+            vec![
+                ("Alice Johnson", Some(1)),
+                ("Bob", Some(2)),
+                ("Alice Johnson", Some(3))
+            ]
+        );
+        assert_eq!(occurrences[1].location, BlockLocation::PdfPage { page: 2 });
+        assert_eq!(occurrences[1].line_text.trim(), "Bob Smith");
+        assert_eq!(
+            paged
+                .extraction(
+                    &needles,
+                    ExtractOptions {
+                        strategy: Strategy::Automaton,
+                        ..ExtractOptions::default()
+                    }
+                )
+                .occurrences,
+            occurrences
+        );
+    }
+
+    #[test]
+    fn test_pages_that_fail_to_extract_are_skipped() {
+        // Without a media box pdf_extract gives up on a page; blanking it
+        // keeps the offsets of the cross-reference table valid
+        let mut bytes = fixtures::pdf(&[
+            &["Alice Johnson"],
+            &["Bob Smith"],
+            &["Carol White"],
+            &["Dan Brown"],
+        ]);
+        let media_box = b"/MediaBox [0 0 612 792]";
+        let positions: Vec<usize> = bytes
+            .windows(media_box.len())
+            .enumerate()
+            .filter(|(_, window)| window == media_box)
+            .map(|(at, _)| at)
+            .collect();
+        for &at in [positions[1], positions[3]].iter() {
+            bytes[at..at + media_box.len()].fill(b' ');
+        }
+
+        let paged = extract_paged_text(&bytes).unwrap();
+        assert_eq!(paged.skipped_pages, [2, 4]);
+        let pages: Vec<&str> = paged.pages().into_iter().map(str::trim).collect();
+        assert_eq!(pages, ["Alice Johnson", "", "Carol White", ""]);
+
+        let needles = vec![
+            ("Alice Johnson".to_string(), "id=1".to_string()),
+            ("Carol White".to_string(), "id=3".to_string()),
+        ];
+        let extraction = paged.extraction(&needles, ExtractOptions::default());
+        let pages: Vec<Option<usize>> = extraction
+            .occurrences
+            .iter()
+            .map(Occurrence::page)
+            .collect();
+        assert_eq!(pages, [Some(1), Some(3)]);
+        assert_eq!(
+            extraction.warnings,
+            ["could not extract the text of pages 2, 4"]
+        );
+    }
+
+    #[test]
+    fn test_text_strings_decode_utf16_and_single_bytes() {
+        assert_eq!(
+            decode_text_string(&[0xFE, 0xFF, 0x00, 0x4A, 0x00, 0xF6, 0x00, 0x72, 0x00, 0x67]),
+            "Jörg"
+        );
+        assert_eq!(decode_text_string(b"J\xf6rg"), "Jörg");
+    }
+
+    #[test]
+    fn test_extract_pages_gives_the_text_of_each_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("three.pdf");
+        std::fs::write(
+            &path,
+            fixtures::pdf(&[&["Alice Johnson", "Page 1"], &["Bob Smith"], &[]]),
         )
-    })?;
-    let duration = start.elapsed();
-    println!(
-        "{}",
-        format!("Extracting text from pdf took {} ms", duration.as_millis()).italic()
-    );
-
-    println!("{}", format!("Starting search...").blue());
-    let start = Instant::now();
-    let matches = haystack.lines().filter(|line| line.trim().len() > 0).fold(
-        HashSet::new(),
-        |mut acc, line| {
-            needles.iter().filter(|n| line.contains(&n.0)).for_each(|n| {
-                acc.insert((n.0.clone(), n.1.clone()));
-            });
+        .unwrap();
+
+        let pages = extract_pages(&path).unwrap();
+        let pages: Vec<Vec<&str>> = pages
+            .iter()
+            .map(|page| {
+                page.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            vec![vec!["Alice Johnson", "Page 1"], vec!["Bob Smith"], vec![]]
+        );
+        assert!(extract_pages(&dir.path().join("missing.pdf")).is_err());
+    }
+
+    #[test]
+    fn test_case_sensitivity() {
+        let bytes = fixtures::pdf(&[&["Memo to ALICE JOHNSON"], &["cc: alice johnson"]]);
+        let needles = vec![("Alice Johnson".to_string(), "id=1".to_string())];
+        let found = |case_sensitive| {
+            let extract = ExtractOptions {
+                matching: MatchOptions {
+                    case_sensitive,
+                    ..MatchOptions::default()
+                },
+                ..ExtractOptions::default()
+            };
+            let occurrences = occurrences_mem(&needles, &bytes, extract)
+                .unwrap()
+                .occurrences;
+            occurrences
+                .into_iter()
+                .map(|occurrence| (occurrence.page(), occurrence.term))
+                .collect::<Vec<_>>()
+        };
+
+        // The needle is reported as written, whatever the case it was found in
+        assert_eq!(
+            found(false),
+            vec![
+                (Some(1), "Alice Johnson".to_string()),
+                (Some(2), "Alice Johnson".to_string())
+            ]
+        );
+        assert!(found(true).is_empty());
+
+        let needles = b"alice JOHNSON,id=1\nALICE johnson,id=2\n";
+        let results = parse_from_mem(needles, &bytes, ExtractOptions::default()).unwrap();
+        let on_both_pages = |term, metadata| SearchResult {
+            occurrences: 2,
+            page: Some(1),
+            context: Some("Memo to ALICE JOHNSON".to_string()),
+            ..SearchResult::new(term, metadata)
+        };
+        assert_eq!(
+            results,
+            [
+                on_both_pages("ALICE johnson", "id=2"),
+                on_both_pages("alice JOHNSON", "id=1")
+            ]
+        );
+        assert!(parse_from_mem(
+            needles,
+            &bytes,
+            ExtractOptions {
+                matching: MatchOptions {
+                    case_sensitive: true,
+                    ..MatchOptions::default()
+                },
+                ..ExtractOptions::default()
+            }
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn test_results_name_their_file_page_and_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let (needles, pdf) = (dir.path().join("needles.csv"), dir.path().join("memo.pdf"));
+        std::fs::write(&needles, "Bob Smith,id=2\n").unwrap();
+        std::fs::write(
+            &pdf,
+            fixtures::pdf(&[
+                &["Alice Johnson"],
+                &["Payment approved by Bob Smith on Friday"],
+                &["cc: Bob Smith"],
+            ]),
+        )
+        .unwrap();
+
+        let results = parse_from_path(
+            &needles.to_string_lossy(),
+            &pdf.to_string_lossy(),
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            [SearchResult {
+                occurrences: 2,
+                file: Some(pdf.clone()),
+                page: Some(2),
+                context: Some("Payment approved by Bob Smith on Friday".to_string()),
+                ..SearchResult::new("Bob Smith", "id=2")
+            }]
+        );
+    }
+
+    #[test]
+    fn test_budget_stops_at_the_page_that_spends_it() {
+        let bytes = fixtures::pdf(&[
+            &["Alice Johnson", "Page 1"],
+            &["Bob Smith"],
+            &["Carol Diaz"],
+        ]);
+        let later = Instant::now() + std::time::Duration::from_secs(60);
+        let text = |blocks: usize| -> Vec<String> {
+            blocks_within(
+                &bytes,
+                BlockBudget {
+                    blocks,
+                    deadline: later,
+                },
+            )
+            .unwrap()
+            .into_iter()
+            .map(|block| block.text.trim().to_string())
+            .collect()
+        };
+
+        assert_eq!(text(1), ["Alice Johnson"]);
+        assert_eq!(text(3), ["Alice Johnson", "Page 1", "Bob Smith"]);
+        assert_eq!(text(10).len(), 4);
 
-            acc
-        },
-    );
-    let duration = start.elapsed();
-    println!(
-        "{}",
-        format!("Searching took {} ms", duration.as_millis()).italic()
-    );
-
-    println!("{}", format!("Found {} matches", matches.len()).green());
-    Ok(matches)
+        // Past the deadline, no page is read
+        let passed = BlockBudget {
+            blocks: 10,
+            deadline: Instant::now(),
+        };
+        let paged = extract_paged_text_within(&bytes, Some(passed)).unwrap();
+        assert_eq!(paged.page_starts, Vec::<usize>::new());
+    }
 }
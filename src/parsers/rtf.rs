@@ -0,0 +1,325 @@
+//! Rich Text Format documents (`.rtf`): the text of the document body,
+//! where every paragraph and line break starts a block at its line number.
+//!
+//! The file is split into control words, groups and text by `rtf-grimoire`.
+//! Groups that hold no body text, such as the font table, stylesheet,
+//! pictures, headers, footers and every `\*` destination, are skipped.
+//! `\uN` escapes are decoded and the fallback characters written after them
+//! dropped, as many as `\ucN` says; other 8-bit text is read as
+//! Windows-1252.
+
+use anyhow::{anyhow, bail, Result};
+use rtf_grimoire::tokenizer::{self, Token};
+use std::path::Path;
+
+use super::doc::cp1252_char;
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{Extraction, TextBlock};
+use crate::types::{ExtractOptions, FileType, SearchResults};
+
+/// Leading bytes of every RTF document
+pub const RTF_MAGIC: &[u8] = b"{\\rtf";
+
+/// Destinations whose text is not part of the document body
+const SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "listtable",
+    "listoverridetable",
+    "revtbl",
+    "rsidtbl",
+    "info",
+    "pict",
+    "nonshppict",
+    "object",
+    "fldinst",
+    "footnote",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+];
+
+/// [`DocumentParser`] for RTF documents
+pub struct RtfParser;
+
+impl DocumentParser for RtfParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Rtf]
+    }
+
+    fn extract_text(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        _extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        document(bytes, path)
+    }
+}
+
+/// Whether `bytes` start like an RTF document
+pub fn is_rtf(bytes: &[u8]) -> bool {
+    bytes.starts_with(RTF_MAGIC)
+}
+
+/// Search an in-memory RTF document for the needles of an in-memory needles
+/// file, searching as `extract` says
+pub fn parse_from_mem(
+    needle_bytes: &[u8],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_mem_for_needles(&RtfParser, needle_bytes, haystack_bytes, extract)
+}
+
+/// Report every occurrence of `needles` in an in-memory RTF document
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    Ok(document(haystack_bytes, Path::new(""))?.search(needles, extract))
+}
+
+/// The blocks [`occurrences_mem`] searches: every line of the body text
+pub fn blocks_mem(haystack_bytes: &[u8]) -> Result<Vec<TextBlock>> {
+    Ok(document(haystack_bytes, Path::new(""))?.blocks)
+}
+
+/// Search an RTF file on disk for the needles of a needles file, searching
+/// as `extract` says
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_path(
+        &RtfParser,
+        needle_path,
+        file_path,
+        extract,
+        &mut NullReporter,
+    )
+}
+
+/// Search an RTF file on disk with needles that were already loaded,
+/// reporting every occurrence
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(&RtfParser, needles, file_path, extract, &mut NullReporter)
+}
+
+/// The body text of an in-memory RTF document, line by line; `path` is only
+/// used in errors
+fn document(bytes: &[u8], path: &Path) -> Result<ExtractedDocument> {
+    if !is_rtf(bytes) {
+        bail!("{} is not an RTF document", path.display());
+    }
+    let tokens = tokenizer::parse_finished(bytes)
+        .map_err(|e| anyhow!("Failed to read RTF document {}: {}", path.display(), e))?;
+    let text = plain_text(&tokens);
+
+    Ok(ExtractedDocument {
+        text_bytes: text.len() as u64,
+        ..ExtractedDocument::new(TextBlock::plain_lines(&text))
+    })
+}
+
+/// What applies inside a group; inner groups start from their parent's
+#[derive(Clone, Copy)]
+struct Group {
+    /// Whether the group is a destination that is not body text
+    skipped: bool,
+    /// Fallback characters written after each `\uN`, from `\ucN`
+    fallback_len: usize,
+    /// Whether no token of the group has been read yet
+    at_start: bool,
+}
+
+/// Body text being written out, dropping the fallback characters after
+/// each `\uN`
+#[derive(Default)]
+struct BodyText {
+    text: String,
+    /// Fallback characters still to drop
+    fallback: usize,
+    /// The first half of a character written as a UTF-16 surrogate pair
+    high_surrogate: Option<u16>,
+}
+
+impl BodyText {
+    fn push(&mut self, c: char) {
+        if self.fallback > 0 {
+            self.fallback -= 1;
+        } else {
+            self.text.push(c);
+        }
+    }
+
+    /// Write the UTF-16 unit of a `\uN`, which `fallback` characters follow
+    fn push_unit(&mut self, unit: u16, fallback: usize) {
+        match (self.high_surrogate.take(), unit) {
+            (None, 0xD800..=0xDBFF) => self.high_surrogate = Some(unit),
+            (high, _) => {
+                let units = high.into_iter().chain([unit]);
+                self.text.extend(
+                    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+            }
+        }
+        self.fallback = fallback;
+    }
+}
+
+/// The body text `tokens` render to, with a `\n` at every paragraph, line,
+/// row, section and page break
+fn plain_text(tokens: &[Token]) -> String {
+    let mut body = BodyText::default();
+    let mut groups = vec![Group {
+        skipped: false,
+        fallback_len: 1,
+        at_start: false,
+    }];
+
+    for token in tokens {
+        let group = groups
+            .last_mut()
+            .expect("the outermost group is never closed");
+        let at_start = std::mem::replace(&mut group.at_start, false);
+        match token {
+            Token::StartGroup => {
+                let inner = Group {
+                    at_start: true,
+                    ..*group
+                };
+                groups.push(inner);
+                continue;
+            }
+            Token::EndGroup => {
+                if groups.len() > 1 {
+                    groups.pop();
+                }
+                body.fallback = 0;
+                continue;
+            }
+            Token::ControlSymbol('*') if at_start => group.skipped = true,
+            Token::ControlWord { name, .. }
+                if at_start && SKIPPED_DESTINATIONS.contains(&name.as_str()) =>
+            {
+                group.skipped = true
+            }
+            _ => {}
+        }
+        if group.skipped {
+            continue;
+        }
+
+        match token {
+            Token::ControlWord { name, arg } => match (name.as_str(), *arg) {
+                ("uc", Some(len)) => group.fallback_len = len.max(0) as usize,
+                // Written as a signed 16-bit number, so above U+7FFF it is negative
+                ("u", Some(unit)) => body.push_unit(unit as u16, group.fallback_len),
+                ("'", Some(byte)) => body.push(cp1252_char(byte as u8)),
+                ("par" | "line" | "row" | "sect" | "page", _) => body.text.push('\n'),
+                ("tab" | "cell", _) => body.text.push('\t'),
+                ("emdash", _) => body.push('—'),
+                ("endash", _) => body.push('–'),
+                ("bullet", _) => body.push('•'),
+                ("lquote", _) => body.push('‘'),
+                ("rquote", _) => body.push('’'),
+                ("ldblquote", _) => body.push('“'),
+                ("rdblquote", _) => body.push('”'),
+                ("emspace" | "enspace" | "qmspace", _) => body.push(' '),
+                _ => {}
+            },
+            Token::ControlSymbol(symbol) => match symbol {
+                '~' => body.push('\u{A0}'),
+                '_' => body.push('\u{2011}'),
+                '\\' | '{' | '}' => body.push(*symbol),
+                _ => {}
+            },
+            Token::Text(bytes) => bytes.iter().for_each(|&byte| body.push(cp1252_char(byte))),
+            Token::ControlBin(_) | Token::Newline(_) | Token::StartGroup | Token::EndGroup => {}
+        }
+    }
+    body.text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn text(rtf: &str) -> String {
+        plain_text(&tokenizer::parse_finished(rtf.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_name_in_bold_run_is_found() {
+        let bytes = br"{\rtf1\ansi{\fonttbl\f0\fswiss Helvetica;}\f0\pard Contract with {\b Alice} {\b\i Johnson}.\par
+Signed by {\b Bob Smith}\par}";
+        let needles = vec![
+            ("Alice Johnson".to_string(), "id=1".to_string()),
+            ("Helvetica".to_string(), "id=2".to_string()),
+        ];
+
+        let found: Vec<(String, String)> =
+            occurrences_mem(&needles, bytes, ExtractOptions::default())
+                .unwrap()
+                .occurrences
+                .into_iter()
+                .map(|occurrence| (occurrence.term, occurrence.location.to_string()))
+                .collect();
+        assert_eq!(found, [("Alice Johnson".to_string(), "line 1".to_string())]);
+        assert_eq!(blocks_mem(bytes).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_unicode_escapes_and_their_fallbacks() {
+        assert_eq!(
+            text(r"{\rtf1\ansi\uc1 Jos\u233?, Mu\u241\'f1oz \u-10179?\u-8704? done\par}"),
+            "José, Muñoz 😀 done\n"
+        );
+        assert_eq!(
+            text(r"{\rtf1\ansi{\uc2 \u8364\'80\'80} and \u8364? \'80}"),
+            "€ and € €"
+        );
+        assert_eq!(
+            text(r"{\rtf1\ansi Caf\'e9 \{x\}\~y\tab z\line{\*\generator Writer;}w}"),
+            "Café {x}\u{A0}y\tz\nw"
+        );
+
+        let bytes = fixtures::rtf(&["Zoë → Bob Smith", "Café {bar}"]);
+        assert_eq!(
+            blocks_mem(&bytes)
+                .unwrap()
+                .into_iter()
+                .map(|block| block.text)
+                .collect::<Vec<_>>(),
+            ["Zoë → Bob Smith", "Café {bar}"]
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_documents() {
+        assert!(blocks_mem(b"Alice Johnson").is_err());
+        let results = parse_from_mem(
+            b"Alice Johnson,id=1\n",
+            &fixtures::rtf(&["Alice Johnson"]),
+            ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+}
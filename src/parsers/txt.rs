@@ -0,0 +1,161 @@
+//! Plain text files (`.txt`), such as logs and data exports: every line is
+//! a block, at its line number.
+//!
+//! The text is read as UTF-8, without a byte order mark if it starts with
+//! one, and invalid bytes are replaced rather than failing the search. Lines
+//! may end in `\n`, `\r\n` or a lone `\r`, mixed within one file.
+
+use anyhow::Result;
+use std::path::Path;
+
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{search_blocks, Extraction, TextBlock};
+use crate::types::{ExtractOptions, FileType, SearchResults};
+
+/// [`DocumentParser`] for plain text files
+pub struct TxtParser;
+
+impl DocumentParser for TxtParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Txt]
+    }
+
+    fn extract_text(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        _extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        Ok(ExtractedDocument::new(blocks_mem(bytes)))
+    }
+}
+
+/// Search in-memory text for the needles of an in-memory needles file,
+/// searching as `extract` says
+pub fn parse_from_mem(
+    needle_bytes: &[u8],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_mem_for_needles(&TxtParser, needle_bytes, haystack_bytes, extract)
+}
+
+/// Report every occurrence of `needles` in in-memory text
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Extraction {
+    search_blocks(needles, &blocks_mem(haystack_bytes), extract)
+}
+
+/// The blocks [`occurrences_mem`] searches: every line of the text
+pub fn blocks_mem(haystack_bytes: &[u8]) -> Vec<TextBlock> {
+    TextBlock::plain_lines(&decode(haystack_bytes))
+}
+
+/// Search a text file on disk for the needles of a needles file, searching
+/// as `extract` says
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_path(
+        &TxtParser,
+        needle_path,
+        file_path,
+        extract,
+        &mut NullReporter,
+    )
+}
+
+/// Search a text file on disk with needles that were already loaded,
+/// reporting every occurrence
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(&TxtParser, needles, file_path, extract, &mut NullReporter)
+}
+
+/// The text of a file, without a byte order mark and with every line ending
+/// made `\n`
+fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix("\u{FEFF}".as_bytes()).unwrap_or(bytes);
+    String::from_utf8_lossy(bytes)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::BlockLocation;
+
+    fn lines(bytes: &[u8]) -> Vec<(String, usize)> {
+        blocks_mem(bytes)
+            .into_iter()
+            .map(|block| match block.location {
+                BlockLocation::PlainLine { line } => (block.text, line),
+                location => panic!("not a line: {:?}", location),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_byte_order_mark_and_mixed_line_endings() {
+        let bytes = b"\xEF\xBB\xBFAlice Johnson\r\nBob Smith\nCarol\rDiaz\r\n\nlast";
+        let expected = [
+            ("Alice Johnson", 0),
+            ("Bob Smith", 1),
+            ("Carol", 2),
+            ("Diaz", 3),
+            ("", 4),
+            ("last", 5),
+        ];
+        assert_eq!(
+            lines(bytes),
+            expected.map(|(text, line)| (text.to_string(), line))
+        );
+
+        let needles = vec![
+            ("alice johnson".to_string(), "id=1".to_string()),
+            ("Diaz".to_string(), "id=2".to_string()),
+        ];
+        let found: Vec<(String, String)> =
+            occurrences_mem(&needles, bytes, ExtractOptions::default())
+                .occurrences
+                .into_iter()
+                .map(|occurrence| (occurrence.term, occurrence.location.to_string()))
+                .collect();
+        assert_eq!(
+            found,
+            [
+                ("alice johnson".to_string(), "line 1".to_string()),
+                ("Diaz".to_string(), "line 4".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        assert_eq!(
+            lines(b"caf\xE9 Alice"),
+            [("caf\u{FFFD} Alice".to_string(), 0)]
+        );
+
+        let results =
+            parse_from_mem(b"Alice,id=1\n", b"caf\xE9 Alice", ExtractOptions::default()).unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.term.as_str())
+                .collect::<Vec<_>>(),
+            ["Alice"]
+        );
+    }
+}
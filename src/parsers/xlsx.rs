@@ -0,0 +1,405 @@
+//! Excel workbooks (`.xlsx`): every non-empty cell of every worksheet is a
+//! block, at its sheet and cell reference.
+//!
+//! A workbook is an Office Open XML package like a DOCX document, so it is
+//! read the same way: the worksheets are found through the relationships of
+//! the workbook part, and text cells through its shared string table.
+
+use anyhow::{Context, Result};
+use std::{
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+use zip::ZipArchive;
+
+use super::docx::{attribute, read_part, read_relationships};
+use super::{DocumentParser, ExtractedDocument};
+use crate::error::DocSearchError;
+use crate::progress::NullReporter;
+use crate::search::{search_blocks, BlockBudget, BlockLocation, Extraction, TextBlock};
+use crate::types::{ExtractOptions, FileType, SearchResults};
+
+const OFFICE_DOCUMENT: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument";
+const WORKSHEET: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet";
+const SHARED_STRINGS: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings";
+
+/// [`DocumentParser`] for Excel workbooks
+pub struct XlsxParser;
+
+impl DocumentParser for XlsxParser {
+    fn file_types(&self) -> &'static [FileType] {
+        &[FileType::Xlsx]
+    }
+
+    fn extract_text(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        _extract: ExtractOptions,
+    ) -> Result<ExtractedDocument> {
+        Ok(ExtractedDocument::new(blocks_mem(bytes)?))
+    }
+
+    fn extract_within(
+        &self,
+        _path: &Path,
+        bytes: &[u8],
+        _extract: ExtractOptions,
+        budget: BlockBudget,
+    ) -> Result<Vec<TextBlock>> {
+        blocks_within(bytes, budget)
+    }
+}
+
+/// Report every occurrence of `needles` in an in-memory workbook
+pub fn occurrences_mem(
+    needles: &[(String, String)],
+    haystack_bytes: &[u8],
+    extract: ExtractOptions,
+) -> Result<Extraction> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    let haystack = extract_haystack(&mut archive, None)?;
+
+    Ok(search_blocks(needles, &haystack, extract))
+}
+
+/// The blocks [`occurrences_mem`] searches
+pub fn blocks_mem(haystack_bytes: &[u8]) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, None)
+}
+
+/// The first blocks of [`blocks_mem`], as many as `budget` allows
+pub fn blocks_within(haystack_bytes: &[u8], budget: BlockBudget) -> Result<Vec<TextBlock>> {
+    let mut archive = ZipArchive::new(Cursor::new(haystack_bytes))?;
+    extract_haystack(&mut archive, Some(budget))
+}
+
+/// Search an XLSX file on disk for the needles of a needles file, searching
+/// as `extract` says
+pub fn parse_from_path(
+    needle_path: &str,
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<SearchResults, DocSearchError> {
+    super::search_path(
+        &XlsxParser,
+        needle_path,
+        file_path,
+        extract,
+        &mut NullReporter,
+    )
+}
+
+/// Search an XLSX file on disk with needles that were already loaded,
+/// reporting every occurrence
+pub fn parse_with_needles(
+    needles: &[(String, String)],
+    file_path: &str,
+    extract: ExtractOptions,
+) -> Result<Extraction, DocSearchError> {
+    super::search_path_with_needles(&XlsxParser, needles, file_path, extract, &mut NullReporter)
+}
+
+/// The column letters of a 1-based column number: 1 is `A`, 27 is `AA`
+pub fn column_name(mut col: u32) -> String {
+    let mut letters = Vec::new();
+    while col > 0 {
+        col -= 1;
+        letters.push(b'A' + (col % 26) as u8);
+        col /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+/// The 1-based row and column of a cell reference such as `B7`
+pub fn cell_position(reference: &str) -> Option<(u32, u32)> {
+    let digits = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, row) = reference.split_at(digits);
+    if letters.is_empty() || !letters.bytes().all(|letter| letter.is_ascii_uppercase()) {
+        return None;
+    }
+    let col = letters.bytes().try_fold(0u32, |col, letter| {
+        col.checked_mul(26)?
+            .checked_add(u32::from(letter - b'A') + 1)
+    })?;
+    Some((row.parse().ok()?, col))
+}
+
+/// Every non-empty cell of every worksheet, in workbook order, stopping
+/// after the sheet that spends `budget`
+fn extract_haystack<R>(
+    archive: &mut ZipArchive<R>,
+    budget: Option<BlockBudget>,
+) -> Result<Vec<TextBlock>>
+where
+    R: Seek,
+    R: Read,
+{
+    let workbook_name = read_relationships(archive, "")
+        .into_iter()
+        .find(|(_, kind, _)| kind == OFFICE_DOCUMENT)
+        .map_or_else(
+            || "xl/workbook.xml".to_string(),
+            |(_, _, target)| target.trim_start_matches('/').to_string(),
+        );
+    let workbook = read_part(archive, &workbook_name).context("Workbook part not found")?;
+    let workbook = roxmltree::Document::parse(&workbook).context("Could not parse the workbook")?;
+
+    let relationships = read_relationships(archive, &workbook_name);
+    let dir = workbook_name.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let resolve = |target: &str| match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None if dir.is_empty() => target.to_string(),
+        None => format!("{}/{}", dir, target),
+    };
+    let shared = relationships
+        .iter()
+        .find(|(_, kind, _)| kind == SHARED_STRINGS)
+        .and_then(|(_, _, target)| read_part(archive, &resolve(target)))
+        .map(|strings| shared_strings(&strings))
+        .unwrap_or_default();
+
+    let mut haystack = Vec::new();
+    for sheet in workbook
+        .descendants()
+        .filter(|elem| elem.has_tag_name("sheet"))
+    {
+        if budget.is_some_and(|budget| budget.spent(haystack.len())) {
+            break;
+        }
+        let (Some(name), Some(id)) = (sheet.attribute("name"), attribute(sheet, "id")) else {
+            continue;
+        };
+        let Some((_, _, target)) = relationships
+            .iter()
+            .find(|(rel_id, kind, _)| rel_id == id && kind == WORKSHEET)
+        else {
+            continue;
+        };
+        let Some(xml) = read_part(archive, &resolve(target)) else {
+            continue;
+        };
+        let xml = roxmltree::Document::parse(&xml)
+            .with_context(|| format!("Could not parse worksheet {}", name))?;
+        haystack.extend(sheet_blocks(&xml, name, &shared));
+    }
+
+    if let Some(budget) = budget {
+        haystack.truncate(budget.blocks);
+    }
+    Ok(haystack)
+}
+
+/// The texts of the shared string table, by index
+fn shared_strings(xml: &str) -> Vec<String> {
+    let Ok(xml) = roxmltree::Document::parse(xml) else {
+        return Vec::new();
+    };
+    xml.root_element()
+        .children()
+        .filter(|elem| elem.has_tag_name("si"))
+        .map(string_item_text)
+        .collect()
+}
+
+/// The text of a string item, plain or in formatted runs, leaving out
+/// phonetic guides
+fn string_item_text(item: roxmltree::Node) -> String {
+    item.descendants()
+        .filter(|elem| {
+            elem.has_tag_name("t")
+                && !elem
+                    .ancestors()
+                    .any(|ancestor| ancestor.has_tag_name("rPh"))
+        })
+        .filter_map(|t| t.text())
+        .collect()
+}
+
+/// The non-empty cells of a worksheet, as blocks at their references. Rows
+/// and cells without a reference follow the one before them.
+fn sheet_blocks(xml: &roxmltree::Document, sheet: &str, shared: &[String]) -> Vec<TextBlock> {
+    let mut blocks = Vec::new();
+    let mut row = 0;
+    for row_elem in xml.descendants().filter(|elem| elem.has_tag_name("row")) {
+        row = row_elem
+            .attribute("r")
+            .and_then(|r| r.parse().ok())
+            .unwrap_or(row + 1);
+        let mut col = 0;
+        for cell in row_elem.children().filter(|elem| elem.has_tag_name("c")) {
+            col = cell
+                .attribute("r")
+                .and_then(cell_position)
+                .map_or(col + 1, |(_, col)| col);
+            let Some(text) = cell_text(cell, shared) else {
+                continue;
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            let cell = format!("{}{}", column_name(col), row);
+            blocks.push(TextBlock::new(
+                text,
+                BlockLocation::SheetCell {
+                    sheet: sheet.to_string(),
+                    cell,
+                },
+            ));
+        }
+    }
+    blocks
+}
+
+/// The text of a cell as stored: shared and inline strings, numbers as
+/// written, and booleans as `TRUE` or `FALSE`
+fn cell_text(cell: roxmltree::Node, shared: &[String]) -> Option<String> {
+    let value = || {
+        cell.children()
+            .find(|elem| elem.has_tag_name("v"))
+            .and_then(|v| v.text())
+    };
+    match cell.attribute("t") {
+        Some("s") => shared.get(value()?.trim().parse::<usize>().ok()?).cloned(),
+        Some("inlineStr") => Some(string_item_text(
+            cell.children().find(|elem| elem.has_tag_name("is"))?,
+        )),
+        Some("b") => Some(
+            if value()?.trim() == "1" {
+                "TRUE"
+            } else {
+                "FALSE"
+            }
+            .to_string(),
+        ),
+        _ => value().map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, types::Location};
+
+    fn needles(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(term, metadata)| (term.to_string(), metadata.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_cells_of_every_sheet_are_searched() {
+        let bytes = fixtures::xlsx(&[
+            (
+                "Contacts",
+                &[&["Name", "Phone"], &["Alice Johnson", "5551234"]],
+            ),
+            ("Archive", &[&["", "Bob Smith"]]),
+        ]);
+
+        let blocks = blocks_mem(&bytes).unwrap();
+        let cells: Vec<(String, &str)> = blocks
+            .iter()
+            .map(|block| (block.location.to_string(), block.text.as_str()))
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                ("Contacts!A1".to_string(), "Name"),
+                ("Contacts!B1".to_string(), "Phone"),
+                ("Contacts!A2".to_string(), "Alice Johnson"),
+                ("Contacts!B2".to_string(), "5551234"),
+                ("Archive!B1".to_string(), "Bob Smith"),
+            ]
+        );
+
+        let needles = needles(&[
+            ("Alice Johnson", "id=1"),
+            ("5551234", "phone"),
+            ("Bob Smith", "id=2"),
+        ]);
+        let extraction = occurrences_mem(&needles, &bytes, ExtractOptions::default()).unwrap();
+        let found: Vec<(&str, Location)> = extraction
+            .occurrences
+            .iter()
+            .map(|occurrence| (occurrence.term.as_str(), occurrence.location.part()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Alice Johnson",
+                    Location::Cell {
+                        sheet: "Contacts".to_string(),
+                        row: 2,
+                        col: 1
+                    }
+                ),
+                (
+                    "5551234",
+                    Location::Cell {
+                        sheet: "Contacts".to_string(),
+                        row: 2,
+                        col: 2
+                    }
+                ),
+                (
+                    "Bob Smith",
+                    Location::Cell {
+                        sheet: "Archive".to_string(),
+                        row: 1,
+                        col: 2
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_strings_booleans_and_unreferenced_cells() {
+        let sheet = "<worksheet><sheetData>\
+            <row><c t=\"inlineStr\"><is><r><t>Alice </t></r><r><t>Johnson</t></r><rPh><t>ありす</t></rPh></is></c><c t=\"b\"><v>1</v></c></row>\
+            <row r=\"5\"><c r=\"C5\"><v>42</v></c><c><v>43</v></c></row>\
+            </sheetData></worksheet>";
+        let xml = roxmltree::Document::parse(sheet).unwrap();
+
+        let cells: Vec<(String, String)> = sheet_blocks(&xml, "S", &[])
+            .into_iter()
+            .map(|block| (block.location.to_string(), block.text))
+            .collect();
+        let expected = [
+            ("S!A1", "Alice Johnson"),
+            ("S!B1", "TRUE"),
+            ("S!C5", "42"),
+            ("S!D5", "43"),
+        ];
+        assert_eq!(
+            cells,
+            expected.map(|(cell, text)| (cell.to_string(), text.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_cell_references_convert_both_ways() {
+        for (reference, position) in [
+            ("A1", (1, 1)),
+            ("Z9", (9, 26)),
+            ("AA10", (10, 27)),
+            ("XFD1048576", (1048576, 16384)),
+        ] {
+            assert_eq!(cell_position(reference), Some(position));
+            assert_eq!(
+                format!("{}{}", column_name(position.1), position.0),
+                reference
+            );
+        }
+        assert_eq!(cell_position("7B"), None);
+        assert_eq!(cell_position("b7"), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! Progress of a document search, for callers that show their own.
+//!
+//! The parsers print nothing. The search functions that take a
+//! [`ProgressReporter`], such as
+//! [`parsers::parse_with_progress`](crate::parsers::parse_with_progress),
+//! call it as each step of a search finishes. [`NullReporter`] ignores every
+//! step and [`PrintReporter`] prints them the way interactive mode shows
+//! them.
+
+use std::time::Instant;
+
+use colored::Colorize;
+
+use crate::output::outln;
+
+/// Told about each step of a document search as it finishes; every method
+/// does nothing unless overridden
+pub trait ProgressReporter {
+    /// The needles were read and checked, `total_needles` of them
+    fn on_start(&mut self, _total_needles: usize) {}
+
+    /// The document was opened and read
+    fn on_file_open(&mut self) {}
+
+    /// The document's text was extracted, as `lines` blocks
+    fn on_haystack_built(&mut self, _lines: usize) {}
+
+    /// The text was searched for every needle, with `matches` occurrences
+    /// found
+    fn on_search_complete(&mut self, _matches: usize) {}
+
+    /// The search is over, whether or not it succeeded
+    fn on_done(&mut self) {}
+}
+
+/// Reports nothing, for `--quiet` and callers without a progress display
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullReporter;
+
+impl ProgressReporter for NullReporter {}
+
+/// Prints each step with the time it took
+#[derive(Clone, Copy, Debug)]
+pub struct PrintReporter {
+    /// When the step under way started
+    step: Instant,
+}
+
+impl PrintReporter {
+    pub fn new() -> Self {
+        Self {
+            step: Instant::now(),
+        }
+    }
+
+    /// Milliseconds since the last step finished, starting the next one
+    fn lap(&mut self) -> u128 {
+        let elapsed = self.step.elapsed().as_millis();
+        self.step = Instant::now();
+        elapsed
+    }
+}
+
+impl Default for PrintReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressReporter for PrintReporter {
+    fn on_start(&mut self, total_needles: usize) {
+        let elapsed = self.lap();
+        outln!(
+            "{}",
+            format!("Read {} contacts in {} ms", total_needles, elapsed).blue()
+        );
+    }
+
+    fn on_file_open(&mut self) {
+        self.lap();
+    }
+
+    fn on_haystack_built(&mut self, lines: usize) {
+        let elapsed = self.lap();
+        outln!(
+            "{}",
+            format!("Extracted {} blocks in {} ms", lines, elapsed).blue()
+        );
+        outln!("{}", "Starting search...".blue());
+    }
+
+    fn on_search_complete(&mut self, _matches: usize) {
+        let elapsed = self.lap();
+        outln!("{}", format!("Search completed in {} ms", elapsed).blue());
+    }
+}
@@ -0,0 +1,323 @@
+//! Static HTML reports written by `batch --report-dir`.
+//!
+//! A report is an `index.html` listing every searched document plus one page
+//! per document. Page names come from the document's file name, sanitized
+//! for every platform and cut to a bounded length, followed by a hash of the
+//! full path so same-named documents in different directories don't
+//! collide. On Windows, paths past `MAX_PATH` get the `\\?\` verbatim
+//! prefix. [`prepare_report_dir`] creates the longest page path once as a
+//! probe before any searching, so an unwritable report fails upfront rather
+//! than half way through writing.
+
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    types::{ContextKind, Location, SearchResult},
+    utils::{escape_html, fnv1a, sanitize_file_name},
+};
+
+/// Longest sanitized file stem kept in a page name, in characters
+pub const MAX_STEM_CHARS: usize = 48;
+
+/// Name of the report's entry page
+pub const INDEX_NAME: &str = "index.html";
+
+/// Path length (in UTF-16 units) at which Windows needs a verbatim path
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// One searched document and what was found in it: each match with the
+/// contexts it was found in
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportEntry {
+    pub path: PathBuf,
+    pub matches: Result<Vec<(SearchResult, Vec<ContextKind>)>, String>,
+}
+
+/// The page name for `document`: a bounded sanitized stem plus a hash of
+/// the full path
+pub fn artifact_name(document: &Path) -> String {
+    let stem = document
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let stem: String = stem.chars().take(MAX_STEM_CHARS).collect();
+    let hash = fnv1a(document.to_string_lossy().as_bytes());
+
+    format!("{}-{:016x}.html", sanitize_file_name(&stem), hash)
+}
+
+/// Add the Windows `\\?\` verbatim prefix to absolute paths too long for
+/// `MAX_PATH`; other paths are returned unchanged.
+///
+/// Works on path strings so it behaves the same on every platform.
+pub fn verbatim_path(path: &str) -> Cow<'_, str> {
+    if path.starts_with(r"\\?\") || path.encode_utf16().count() < WINDOWS_MAX_PATH {
+        return Cow::Borrowed(path);
+    }
+
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return Cow::Owned(format!(r"\\?\UNC\{}", unc.replace('/', "\\")));
+    }
+
+    let bytes = path.as_bytes();
+    let drive_absolute = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'\\' | b'/');
+    if drive_absolute {
+        Cow::Owned(format!(r"\\?\{}", path.replace('/', "\\")))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Convert a report path into the form used for file system calls
+fn os_path(path: PathBuf) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(verbatim_path(&path.to_string_lossy()).into_owned())
+    } else {
+        path
+    }
+}
+
+/// Create the report directory and check that the longest page path for
+/// `documents` can be written, before anything is searched.
+///
+pub fn prepare_report_dir(dir: &Path, documents: &[PathBuf]) -> Result<()> {
+    let dir = std::path::absolute(dir)?;
+    fs::create_dir_all(os_path(dir.clone()))
+        .map_err(|e| anyhow!("Cannot create report directory {}: {}", dir.display(), e))?;
+
+    let deepest = documents
+        .iter()
+        .map(|document| dir.join(artifact_name(document)))
+        .chain(std::iter::once(dir.join(INDEX_NAME)))
+        .max_by_key(|path| path.as_os_str().len())
+        .expect("index path is always present");
+
+    let probe = os_path(deepest.clone());
+    let existed = probe.exists();
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&probe)
+        .map_err(|e| {
+            anyhow!(
+                "Cannot write report page {}: {}; no report was written",
+                deepest.display(),
+                e
+            )
+        })?;
+    if !existed {
+        let _ = fs::remove_file(&probe);
+    }
+
+    Ok(())
+}
+
+/// Write the index and one page per entry into `dir`
+pub fn write_report(dir: &Path, entries: &[ReportEntry]) -> Result<()> {
+    let dir = std::path::absolute(dir)?;
+    let write = |name: &str, html: String| {
+        let path = dir.join(name);
+        fs::write(os_path(path.clone()), html)
+            .map_err(|e| anyhow!("Failed to write report page {}: {}", path.display(), e))
+    };
+
+    let mut rows = String::new();
+    for entry in entries {
+        let name = artifact_name(&entry.path);
+        let (summary, body) = match &entry.matches {
+            Ok(matches) => {
+                let body: String = matches
+                    .iter()
+                    .map(
+                        |(
+                            SearchResult {
+                                term,
+                                metadata,
+                                location,
+                                occurrences,
+                                ..
+                            },
+                            contexts,
+                        )| {
+                            let mut found_in: Vec<String> = contexts
+                                .iter()
+                                .map(|kind| match kind.is_concealed() {
+                                    true => format!("<em>{}</em>", kind.as_str()),
+                                    false => kind.as_str().to_string(),
+                                })
+                                .collect();
+                            match location {
+                                Location::Body => {}
+                                Location::Header | Location::Footer => {
+                                    found_in.push(format!("in the {}", location))
+                                }
+                                _ => found_in.push(format!("in {}", location)),
+                            }
+                            format!(
+                                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                                escape_html(term),
+                                escape_html(metadata),
+                                occurrences,
+                                found_in.join(", ")
+                            )
+                        },
+                    )
+                    .collect();
+                (
+                    format!("{} matches", matches.len()),
+                    format!("<table border='1'><tr><th>Term</th><th>Metadata</th><th>Count</th><th>Found in</th></tr>\n{}</table>", body),
+                )
+            }
+            Err(error) => (
+                "error".to_string(),
+                format!("<p>Not searched: {}</p>", escape_html(error)),
+            ),
+        };
+
+        let document = escape_html(&entry.path.to_string_lossy());
+        write(
+            &name,
+            page(&document, &format!("<h1>{}</h1>\n{}", document, body)),
+        )?;
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td></tr>\n",
+            escape_html(&name),
+            document,
+            summary
+        ));
+    }
+
+    write(
+        INDEX_NAME,
+        page(
+            "DocSearcher Batch Report",
+            &format!(
+                "<h1>Batch Search Report</h1>\n<table border='1'><tr><th>Document</th><th>Result</th></tr>\n{}</table>",
+                rows
+            ),
+        ),
+    )
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head><body>\n{}\n</body></html>\n",
+        title, body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_name_is_bounded_for_300_character_names() {
+        let dir = "a/".repeat(100);
+        let first = PathBuf::from(format!("{}{}1.pdf", dir, "x".repeat(300)));
+        let second = PathBuf::from(format!("{}{}2.pdf", dir, "x".repeat(300)));
+
+        let name = artifact_name(&first);
+        assert!(
+            name.len() <= MAX_STEM_CHARS + "-0123456789abcdef.html".len(),
+            "{}",
+            name
+        );
+        assert!(name.starts_with(&"x".repeat(MAX_STEM_CHARS)) && name.ends_with(".html"));
+        assert_eq!(name, artifact_name(&first));
+        assert_ne!(name, artifact_name(&second));
+    }
+
+    #[test]
+    fn test_artifact_name_sanitizes_and_keeps_non_ascii() {
+        let name = artifact_name(Path::new("docs/Q3: \"final\"?.docx"));
+        assert!(name.starts_with("Q3_ _final__-"), "{}", name);
+
+        let long = format!("docs/{}.pdf", "ü".repeat(300));
+        let name = artifact_name(Path::new(&long));
+        assert!(
+            name.starts_with(&"ü".repeat(MAX_STEM_CHARS)) && name.chars().count() < 80,
+            "{}",
+            name
+        );
+    }
+
+    #[test]
+    fn test_verbatim_path() {
+        let long = format!(r"C:\reports\{}\page.html", "deep\\".repeat(60));
+        assert_eq!(verbatim_path(&long), format!(r"\\?\{}", long));
+
+        let mixed = format!("D:/reports/{}", "d/".repeat(150));
+        assert_eq!(
+            verbatim_path(&mixed),
+            format!(r"\\?\{}", mixed.replace('/', "\\"))
+        );
+
+        let unc = format!(r"\\server\share\{}", "x".repeat(300));
+        assert_eq!(
+            verbatim_path(&unc),
+            format!(r"\\?\UNC\server\share\{}", "x".repeat(300))
+        );
+
+        let prefixed = format!(r"\\?\C:\{}", "x".repeat(300));
+        assert_eq!(verbatim_path(&prefixed), prefixed);
+
+        let relative = format!(r"reports\{}", "x".repeat(300));
+        assert_eq!(verbatim_path(&relative), relative);
+        assert_eq!(
+            verbatim_path(r"C:\short\index.html"),
+            r"C:\short\index.html"
+        );
+    }
+
+    #[test]
+    fn test_report_shows_full_document_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = PathBuf::from(format!("{}/{}.pdf", "nested".repeat(20), "r".repeat(250)));
+        let entries = vec![
+            ReportEntry {
+                path: document.clone(),
+                matches: Ok(vec![(
+                    SearchResult::new("Alice <A&B>", "id=1"),
+                    vec![ContextKind::Prose, ContextKind::Hidden],
+                )]),
+            },
+            ReportEntry {
+                path: PathBuf::from("broken.pdf"),
+                matches: Err("Failed to extract text".to_string()),
+            },
+        ];
+
+        let report = dir.path().join("report");
+        prepare_report_dir(&report, std::slice::from_ref(&document)).unwrap();
+        assert_eq!(fs::read_dir(&report).unwrap().count(), 0);
+        write_report(&report, &entries).unwrap();
+
+        let index = fs::read_to_string(report.join(INDEX_NAME)).unwrap();
+        assert!(index.contains(&document.to_string_lossy().into_owned()));
+        assert!(index.contains(&artifact_name(&document)));
+        assert!(index.contains("broken.pdf") && index.contains("error"));
+
+        let page = fs::read_to_string(report.join(artifact_name(&document))).unwrap();
+        assert!(page.contains("Alice &lt;A&amp;B&gt;"));
+        assert!(page.contains("prose, <em>hidden</em>"));
+    }
+
+    #[test]
+    fn test_unwritable_report_dir_fails_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocker = dir.path().join("report");
+        fs::write(&blocker, "not a directory").unwrap();
+
+        assert!(prepare_report_dir(&blocker, &[PathBuf::from("a.pdf")]).is_err());
+        assert_eq!(fs::read_to_string(&blocker).unwrap(), "not a directory");
+    }
+}
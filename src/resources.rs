@@ -0,0 +1,166 @@
+//! Resource usage of a `search` or `batch` run, for capacity planning.
+//!
+//! Memory and CPU time come from `getrusage` on Unix. Other platforms report
+//! them as `None` (`null` in JSON) rather than failing the run. Bytes read
+//! and extracted are counted by the pipeline itself, so they are available
+//! everywhere.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::batch::FileOutcome;
+
+/// What one run cost, collected when it finishes
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    /// Peak resident set size of the process
+    pub peak_rss_bytes: Option<u64>,
+    pub user_cpu_secs: Option<f64>,
+    pub system_cpu_secs: Option<f64>,
+    /// Size of the documents as read from disk
+    pub bytes_read: u64,
+    /// Bytes of text extracted from the documents
+    pub bytes_extracted: u64,
+    pub files: usize,
+    pub elapsed_secs: f64,
+    pub files_per_sec: f64,
+}
+
+impl ResourceUsage {
+    /// Combine the process counters with what the run tracked itself
+    pub fn collect(files: usize, bytes_read: u64, bytes_extracted: u64, elapsed: Duration) -> Self {
+        let process = process_usage();
+        let elapsed_secs = elapsed.as_secs_f64();
+        ResourceUsage {
+            peak_rss_bytes: process.peak_rss_bytes,
+            user_cpu_secs: process.user_cpu_secs,
+            system_cpu_secs: process.system_cpu_secs,
+            bytes_read,
+            bytes_extracted,
+            files,
+            elapsed_secs,
+            files_per_sec: if elapsed_secs > 0.0 {
+                files as f64 / elapsed_secs
+            } else {
+                0.0
+            },
+        }
+    }
+
+    /// [`collect`](Self::collect) with the byte counts of batch outcomes
+    pub fn from_outcomes(outcomes: &[FileOutcome], elapsed: Duration) -> Self {
+        let bytes_read = outcomes.iter().map(|outcome| outcome.bytes_read).sum();
+        let bytes_extracted = outcomes.iter().map(|outcome| outcome.text_bytes).sum();
+        Self::collect(outcomes.len(), bytes_read, bytes_extracted, elapsed)
+    }
+}
+
+/// The counters the operating system keeps for this process
+#[derive(Default)]
+struct ProcessUsage {
+    peak_rss_bytes: Option<u64>,
+    user_cpu_secs: Option<f64>,
+    system_cpu_secs: Option<f64>,
+}
+
+#[cfg(unix)]
+fn process_usage() -> ProcessUsage {
+    // SAFETY: getrusage only writes into the zeroed struct it is given
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return ProcessUsage::default();
+    }
+
+    let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1e6;
+    // ru_maxrss is in bytes on Apple platforms and in KiB everywhere else
+    let unit = if cfg!(target_vendor = "apple") {
+        1
+    } else {
+        1024
+    };
+    ProcessUsage {
+        peak_rss_bytes: u64::try_from(usage.ru_maxrss).ok().map(|rss| rss * unit),
+        user_cpu_secs: Some(seconds(usage.ru_utime)),
+        system_cpu_secs: Some(seconds(usage.ru_stime)),
+    }
+}
+
+#[cfg(not(unix))]
+fn process_usage() -> ProcessUsage {
+    ProcessUsage::default()
+}
+
+/// Peak resident set size of the process, where the platform reports it
+pub fn peak_rss_bytes() -> Option<u64> {
+    process_usage().peak_rss_bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch::{run_pipeline, search_bytes, PipelineOptions},
+        fixtures,
+        types::ExtractOptions,
+    };
+
+    #[test]
+    fn test_bytes_extracted_is_the_sum_of_document_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = vec![("Alice Johnson".to_string(), "alice@company.com".to_string())];
+        let documents = [
+            (
+                "a.docx",
+                fixtures::docx(&["Contact Alice Johnson", "Second line"]),
+            ),
+            ("b.pdf", fixtures::pdf(&[&["Alice Johnson on page one"]])),
+            ("c.docx", fixtures::docx(&["Nobody here"])),
+        ];
+        let mut files = Vec::new();
+        let mut expected_text = 0;
+        let mut expected_read = 0;
+        for (name, bytes) in &documents {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            expected_text += search_bytes(&needles, &path, bytes, ExtractOptions::default())
+                .unwrap()
+                .text_bytes;
+            expected_read += bytes.len() as u64;
+            files.push(path);
+        }
+        files.push(dir.path().join("missing.pdf"));
+
+        let outcomes = run_pipeline(&needles, &files, &PipelineOptions::default(), &|_| {});
+        let usage = ResourceUsage::from_outcomes(&outcomes, Duration::from_millis(500));
+
+        assert!(expected_text > 0);
+        assert_eq!(usage.bytes_extracted, expected_text);
+        assert_eq!(usage.bytes_read, expected_read);
+        assert_eq!(usage.files, 4);
+        assert_eq!(usage.files_per_sec, 8.0);
+    }
+
+    #[test]
+    fn test_json_fields() {
+        let usage = ResourceUsage::collect(0, 0, 0, Duration::ZERO);
+        let json = serde_json::to_value(&usage).unwrap();
+        for field in [
+            "peak_rss_bytes",
+            "user_cpu_secs",
+            "system_cpu_secs",
+            "bytes_read",
+            "bytes_extracted",
+            "files",
+            "elapsed_secs",
+            "files_per_sec",
+        ] {
+            assert!(json.get(field).is_some(), "missing {}", field);
+        }
+        assert_eq!(usage.files_per_sec, 0.0);
+        if cfg!(unix) {
+            assert!(usage.peak_rss_bytes.is_some_and(|rss| rss > 0));
+            assert!(usage.user_cpu_secs.is_some());
+        }
+    }
+}
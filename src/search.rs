@@ -0,0 +1,1659 @@
+//! Occurrence-level matching shared by the document parsers.
+//!
+//! Parsers turn a document into [`TextBlock`]s: runs of text that each know
+//! where they sit in the document, as a [`BlockLocation`]. [`find_occurrences`]
+//! records every place the [`search_matcher`] finds a needle in them, tagged
+//! with the block's location and the byte span within the block, and each
+//! occurrence is classified by the token it sits in, so that mentions inside
+//! email addresses and URLs can be told apart from prose. A new parser only
+//! has to produce blocks; matching, context and snippets are shared.
+//! [`search_text`] runs the same matching on text from anywhere else, such
+//! as a database field.
+//!
+//! A [`BlockBudget`] stops extraction early, for previews that only need
+//! the start of each document.
+//!
+//! [`collapse_repeats`] groups occurrences on lines that only differ in their
+//! numbers, such as running headers and footers, for `--collapse-repeats`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Range,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    matcher::{
+        near_windows, Boundary, CaseFold, CollapseWhitespace, Explanation, Matcher, NeedleLookup,
+        Nfc, StripAccents,
+    },
+    types::{
+        ContextKind, ExtractOptions, Location, MatchOptions, NeedleWeights, SearchResult,
+        SearchResults,
+    },
+    utils::{context_snippet, NeedleQuery},
+};
+
+/// A single place where a needle was found
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Occurrence {
+    /// The needle, or the text a regular-expression needle matched
+    pub term: String,
+    pub metadata: String,
+    /// Index of the block the needle was found in, among the document's blocks
+    pub line: usize,
+    /// Byte range of the match within that block
+    pub span: Range<usize>,
+    pub context_kind: ContextKind,
+    /// The whole text of the block the needle was found in
+    pub line_text: String,
+    /// Where that block sits in the document
+    pub location: BlockLocation,
+    /// Archive entries leading from the searched file to the document this
+    /// was found in; empty when the file is the document
+    pub entries: Vec<String>,
+    /// Edits between the needle and the text at `span`, when `--fuzzy`
+    /// matched it approximately; 0 for an exact match
+    #[serde(default)]
+    pub distance: usize,
+    /// Character range of the match within the [`extracted_text`] of the
+    /// document
+    #[serde(default)]
+    pub offset: Range<usize>,
+}
+
+impl Occurrence {
+    /// 1-based page number, for formats that have pages
+    pub fn page(&self) -> Option<usize> {
+        self.location.page()
+    }
+}
+
+/// Where a [`TextBlock`] sits in its document
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlockLocation {
+    /// A line of a PDF page; pages count from 1
+    PdfPage { page: usize },
+    /// A DOCX or ODT paragraph, counted from 0 in document order, under the
+    /// headings that enclose it, outermost first, starting on `page`. Link
+    /// targets and hidden runs share the location of their paragraph.
+    ///
+    /// Neither format fixes its pagination, so the page is best-effort:
+    /// counted at the page breaks Word or LibreOffice recorded when it last
+    /// laid the document out, or for DOCX at explicit page breaks when there
+    /// are none.
+    ///
+    /// A paragraph in a table also has the row and column, counted from 1,
+    /// of its `cell`, the innermost one for nested tables.
+    DocxParagraph {
+        index: usize,
+        heading_path: Vec<String>,
+        page: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cell: Option<(u32, u32)>,
+    },
+    /// A paragraph of a DOCX or ODT header, footer, footnote or endnote, counted
+    /// from 0 within it. Headers and footers repeat on the pages they apply
+    /// to and notes follow their reference, so none has a page.
+    DocxPart { part: Location, index: usize },
+    /// A property of a DOCX or ODT document, such as its `title`
+    DocxProperty { name: String },
+    /// An entry of the document information of a PDF, such as its `title`
+    PdfProperty { name: String },
+    /// A spreadsheet cell, such as `B7` on sheet `Sheet1`
+    SheetCell { sheet: String, cell: String },
+    /// A line of plain text, counted from 0
+    PlainLine { line: usize },
+    /// A paragraph of an EPUB chapter, counted from 0 within it; the
+    /// chapter is the href of its content document
+    EpubChapter { href: String, index: usize },
+}
+
+impl BlockLocation {
+    /// 1-based page number, for formats that have pages
+    pub fn page(&self) -> Option<usize> {
+        match self {
+            BlockLocation::PdfPage { page } | BlockLocation::DocxParagraph { page, .. } => {
+                Some(*page)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether [`page`](Self::page) is only an estimate, as it is for DOCX
+    pub fn page_is_estimated(&self) -> bool {
+        matches!(self, BlockLocation::DocxParagraph { .. })
+    }
+
+    /// The part of the document the block is in
+    pub fn part(&self) -> Location {
+        match self {
+            BlockLocation::DocxPart { part, .. } => part.clone(),
+            BlockLocation::DocxParagraph {
+                cell: Some((row, col)),
+                ..
+            } => Location::Table {
+                row: *row,
+                col: *col,
+            },
+            BlockLocation::SheetCell { sheet, cell } => {
+                match crate::parsers::xlsx::cell_position(cell) {
+                    Some((row, col)) => Location::Cell {
+                        sheet: sheet.clone(),
+                        row,
+                        col,
+                    },
+                    None => Location::Body,
+                }
+            }
+            BlockLocation::EpubChapter { href, .. } => Location::Chapter { href: href.clone() },
+            _ => Location::Body,
+        }
+    }
+}
+
+impl fmt::Display for BlockLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockLocation::PdfPage { page } => write!(f, "page {}", page),
+            BlockLocation::DocxParagraph {
+                index,
+                heading_path,
+                cell,
+                ..
+            } => {
+                write!(f, "paragraph {}", index + 1)?;
+                if let Some((row, col)) = cell {
+                    write!(f, " in table row {}, column {}", row, col)?;
+                }
+                match heading_path.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, " under {}", heading_path.join(" › ")),
+                }
+            }
+            BlockLocation::DocxPart { part, index } => {
+                write!(f, "{} paragraph {}", part, index + 1)
+            }
+            BlockLocation::DocxProperty { name } | BlockLocation::PdfProperty { name } => {
+                write!(f, "{} property", name)
+            }
+            BlockLocation::SheetCell { sheet, cell } => write!(f, "{}!{}", sheet, cell),
+            BlockLocation::PlainLine { line } => write!(f, "line {}", line + 1),
+            BlockLocation::EpubChapter { href, index } => {
+                write!(f, "paragraph {} of chapter {}", index + 1, href)
+            }
+        }
+    }
+}
+
+/// One block of text as the searcher sees it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextBlock {
+    pub text: String,
+    pub location: BlockLocation,
+    /// The context every match in this block is reported as, for content a
+    /// reader does not see
+    pub concealed: Option<ContextKind>,
+}
+
+impl TextBlock {
+    /// Visible text at `location`
+    pub fn new(text: impl Into<String>, location: BlockLocation) -> Self {
+        Self {
+            text: text.into(),
+            location,
+            concealed: None,
+        }
+    }
+
+    /// Every line of plain `text`, as [`BlockLocation::PlainLine`] blocks
+    pub fn plain_lines(text: &str) -> Vec<TextBlock> {
+        text.lines()
+            .enumerate()
+            .map(|(line, text)| TextBlock::new(text, BlockLocation::PlainLine { line }))
+            .collect()
+    }
+
+    /// The context of the match at `span` in this block
+    pub fn context(&self, span: Range<usize>) -> ContextKind {
+        self.concealed
+            .unwrap_or_else(|| classify_context(&self.text, span))
+    }
+}
+
+/// How much of a document to extract: at most `blocks` blocks, and no more
+/// once `deadline` has passed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockBudget {
+    pub blocks: usize,
+    pub deadline: Instant,
+}
+
+impl BlockBudget {
+    /// Whether extraction should stop, having produced `blocks` blocks
+    pub fn spent(&self, blocks: usize) -> bool {
+        blocks >= self.blocks || Instant::now() >= self.deadline
+    }
+}
+
+/// Everything found in one document, and how much text was extracted from it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Extraction {
+    pub occurrences: Vec<Occurrence>,
+    /// Bytes of text searched
+    pub text_bytes: u64,
+    /// Problems that did not stop the search, such as a document parsed as
+    /// another type than its extension names
+    pub warnings: Vec<String>,
+}
+
+/// The matcher every search runs: substrings, with runs of whitespace
+/// collapsed to one space, composed to NFC unless `options` says not to, in
+/// any case, folded as it says, unless it asks for case-sensitive matching, ignoring accents when it asks to, and only between word
+/// boundaries when it asks for whole words. Needles wrapped in `/…/`, or
+/// every needle with `--regex-needles`, are regular expressions.
+pub fn search_matcher(options: MatchOptions) -> Matcher {
+    let mut matcher = Matcher::default()
+        .with_regex_needles(options.regex())
+        .with_normalizer(CollapseWhitespace);
+    if options.normalize {
+        matcher = matcher.with_normalizer(Nfc);
+    }
+    if !options.case_sensitive {
+        matcher = matcher.with_normalizer(CaseFold(options.case_fold));
+    }
+    if options.ignore_accents {
+        matcher = matcher.with_normalizer(StripAccents);
+    }
+    if options.whole_word {
+        matcher.with_boundary(Boundary::Word)
+    } else {
+        matcher
+    }
+}
+
+/// Where one part of a needle was found in a block, with the edits it took
+type PartSpans = Vec<(Range<usize>, usize)>;
+
+/// Find every occurrence of every needle in `blocks`, looking them up as
+/// `extract` says. Occurrences keep the needle's term as written, whatever
+/// the case it was found in, except that a regular-expression needle reports
+/// the text it matched.
+///
+/// A `Project Falcon && budget overrun` needle only matches where every part
+/// is found: anywhere in the document, or in one block with
+/// [`ExtractOptions::and_same_block`]. Its occurrences are those of its
+/// parts, all reported under the whole needle. A `Smith NEAR/5 invoice`
+/// needle matches where both parts are found in one block at most that many
+/// words apart, and its occurrences span the window from one to the other.
+pub fn find_occurrences(
+    needles: &[(String, String)],
+    blocks: &[TextBlock],
+    extract: ExtractOptions,
+) -> Vec<Occurrence> {
+    find_occurrences_until(needles, blocks, extract, None)
+        .expect("a search without a deadline finishes")
+}
+
+/// [`find_occurrences`], giving up with `None` when `deadline` passes
+/// before the last block is searched
+pub fn find_occurrences_until(
+    needles: &[(String, String)],
+    blocks: &[TextBlock],
+    extract: ExtractOptions,
+    deadline: Option<Instant>,
+) -> Option<Vec<Occurrence>> {
+    let matcher = BlockSearch::matcher(extract);
+    let mut search = BlockSearch::new(&matcher, needles, extract);
+    let mut occurrences = Vec::new();
+    for block in blocks {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return None;
+        }
+        occurrences.extend(search.find(block));
+    }
+    occurrences.extend(search.conjunctions());
+
+    occurrences
+        .sort_by_key(|(needle, occurrence)| (occurrence.line, *needle, occurrence.span.start));
+    Some(
+        occurrences
+            .into_iter()
+            .map(|(_, occurrence)| occurrence)
+            .collect(),
+    )
+}
+
+/// [`find_occurrences`] a block at a time, for documents that are searched
+/// as they are read: each block's occurrences are known once it is searched,
+/// except those of `&&` needles, which are only known at the end
+pub struct BlockSearch<'m, 'n> {
+    matcher: &'m Matcher,
+    needles: &'n [(String, String)],
+    queries: Vec<NeedleQuery<'n>>,
+    /// Each part looked up, with the needle and the part it is
+    parts: Vec<(usize, usize, &'n str)>,
+    needle_lookup: NeedleLookup<'m>,
+    and_same_block: bool,
+    /// Occurrences of the parts of each conjunction, with the part found
+    conjunctions: HashMap<usize, Vec<(usize, Occurrence)>>,
+    /// Index of the next block
+    index: usize,
+    /// Characters of the extracted text before the next block
+    block_start: usize,
+}
+
+impl<'m, 'n> BlockSearch<'m, 'n> {
+    /// The matcher a search as `extract` says needs, which it borrows
+    pub fn matcher(extract: ExtractOptions) -> Matcher {
+        search_matcher(extract.matching).with_max_edits(extract.max_edits)
+    }
+
+    /// A search for `needles` with `matcher`, from
+    /// [`matcher`](Self::matcher), as `extract` says
+    pub fn new(
+        matcher: &'m Matcher,
+        needles: &'n [(String, String)],
+        extract: ExtractOptions,
+    ) -> Self {
+        let queries: Vec<NeedleQuery> = needles
+            .iter()
+            .map(|(term, _)| NeedleQuery::parse(term))
+            .collect();
+        let parts: Vec<(usize, usize, &str)> = queries
+            .iter()
+            .enumerate()
+            .flat_map(|(needle, query)| {
+                query
+                    .parts()
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(part, text)| (needle, part, text))
+            })
+            .collect();
+        let needle_lookup =
+            matcher.needle_lookup(parts.iter().map(|(_, _, text)| *text), extract.strategy);
+        Self {
+            matcher,
+            needles,
+            queries,
+            parts,
+            needle_lookup,
+            and_same_block: extract.and_same_block,
+            conjunctions: HashMap::new(),
+            index: 0,
+            block_start: 0,
+        }
+    }
+
+    /// Every occurrence in the next block of the document, in the order
+    /// [`find_occurrences`] reports them, leaving out `&&` needles
+    pub fn search(&mut self, block: &TextBlock) -> Vec<Occurrence> {
+        self.find(block)
+            .into_iter()
+            .map(|(_, occurrence)| occurrence)
+            .collect()
+    }
+
+    /// The occurrences of `&&` needles in every block searched, once the
+    /// last one has been
+    pub fn finish(self) -> Vec<Occurrence> {
+        let mut occurrences = self.conjunctions();
+        occurrences
+            .sort_by_key(|(needle, occurrence)| (occurrence.line, *needle, occurrence.span.start));
+        occurrences
+            .into_iter()
+            .map(|(_, occurrence)| occurrence)
+            .collect()
+    }
+
+    /// [`search`](Self::search), with the needle of each occurrence
+    fn find(&mut self, block: &TextBlock) -> Vec<(usize, Occurrence)> {
+        let (needles, index, block_start) = (self.needles, self.index, self.block_start);
+        let chars = |bytes: usize| block_start + block.text[..bytes].chars().count();
+        let normalized_block = self.matcher.normalize(&block.text);
+        let mut found: Vec<(usize, Range<usize>, usize)> = self
+            .needle_lookup
+            .find(&block.text, &normalized_block)
+            .into_iter()
+            .map(|(needle, span)| (needle, span, 0))
+            .collect();
+        found.extend(
+            self.needle_lookup
+                .find_approximate(&block.text, &normalized_block),
+        );
+        found.sort_by_key(|(needle, span, _)| (*needle, span.start));
+
+        let occurrence = |needle: usize, span: Range<usize>, distance| Occurrence {
+            term: needles[needle].0.clone(),
+            metadata: needles[needle].1.clone(),
+            line: index,
+            context_kind: block.context(span.clone()),
+            offset: chars(span.start)..chars(span.end),
+            span,
+            line_text: block.text.clone(),
+            location: block.location.clone(),
+            entries: Vec::new(),
+            distance,
+        };
+        let mut occurrences = Vec::new();
+        // Spans of both parts of each proximity needle, with their distance
+        let mut near: HashMap<usize, [PartSpans; 2]> = HashMap::new();
+        for (lookup, span, distance) in found {
+            let (needle, part, _) = self.parts[lookup];
+            match self.queries[needle] {
+                NeedleQuery::Term(_) => {
+                    let mut found = occurrence(needle, span.clone(), distance);
+                    // A regular expression reports the text it matched
+                    if self.needle_lookup.is_regex(lookup) {
+                        found.term = block.text[span].to_string();
+                    }
+                    occurrences.push((needle, found));
+                }
+                NeedleQuery::All(_) => self
+                    .conjunctions
+                    .entry(needle)
+                    .or_default()
+                    .push((part, occurrence(needle, span, distance))),
+                NeedleQuery::Near { .. } => {
+                    near.entry(needle).or_default()[part].push((span, distance))
+                }
+            }
+        }
+        for (needle, [first, second]) in near {
+            let NeedleQuery::Near { within, .. } = self.queries[needle] else {
+                unreachable!("only proximity needles are collected")
+            };
+            let spans = |found: &PartSpans| {
+                found
+                    .iter()
+                    .map(|(span, _)| span.clone())
+                    .collect::<Vec<_>>()
+            };
+            for (i, j, window) in near_windows(&block.text, &spans(&first), &spans(&second), within)
+            {
+                occurrences.push((
+                    needle,
+                    occurrence(needle, window, first[i].1.max(second[j].1)),
+                ));
+            }
+        }
+        occurrences.sort_by_key(|(needle, occurrence)| (*needle, occurrence.span.start));
+        self.index += 1;
+        self.block_start += block.text.chars().count() + 1;
+        occurrences
+    }
+
+    /// The occurrences of the `&&` needles that matched, with their needle
+    fn conjunctions(self) -> Vec<(usize, Occurrence)> {
+        let mut occurrences = Vec::new();
+        for (needle, found) in self.conjunctions {
+            let part_count = self.queries[needle].parts().len();
+            let has_every_part = |found: &[(usize, Occurrence)]| {
+                let mut seen: Vec<usize> = found.iter().map(|(part, _)| *part).collect();
+                seen.sort_unstable();
+                seen.dedup();
+                seen.len() == part_count
+            };
+            if self.and_same_block {
+                for block in found
+                    .chunk_by(|(_, a), (_, b)| a.line == b.line)
+                    .filter(|block| has_every_part(block))
+                {
+                    occurrences.extend(
+                        block
+                            .iter()
+                            .map(|(_, occurrence)| (needle, occurrence.clone())),
+                    );
+                }
+            } else if has_every_part(&found) {
+                occurrences.extend(
+                    found
+                        .into_iter()
+                        .map(|(_, occurrence)| (needle, occurrence)),
+                );
+            }
+        }
+        occurrences
+    }
+}
+
+/// The text of a document as its blocks give it, a line each: what
+/// [`Occurrence::offset`] counts characters in
+pub fn extracted_text(blocks: &[TextBlock]) -> String {
+    blocks
+        .iter()
+        .map(|block| block.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// [`find_occurrences`] together with the size of the text searched
+pub fn search_blocks(
+    needles: &[(String, String)],
+    blocks: &[TextBlock],
+    extract: ExtractOptions,
+) -> Extraction {
+    search_blocks_until(needles, blocks, extract, None)
+        .expect("a search without a deadline finishes")
+}
+
+/// [`search_blocks`], giving up with `None` as [`find_occurrences_until`] does
+pub fn search_blocks_until(
+    needles: &[(String, String)],
+    blocks: &[TextBlock],
+    extract: ExtractOptions,
+    deadline: Option<Instant>,
+) -> Option<Extraction> {
+    Some(Extraction {
+        occurrences: find_occurrences_until(needles, blocks, extract, deadline)?,
+        text_bytes: blocks.iter().map(|block| block.text.len() as u64).sum(),
+        warnings: Vec::new(),
+    })
+}
+
+/// Find every occurrence of `needles` in plain `text`, as in a document of
+/// its lines: each occurrence is located at its [`BlockLocation::PlainLine`]
+pub fn search_text(
+    needles: &[(String, String)],
+    text: &str,
+    extract: ExtractOptions,
+) -> Vec<Occurrence> {
+    find_occurrences(needles, &TextBlock::plain_lines(text), extract)
+}
+
+/// What the [`search_matcher`] did with one needle in one block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineExplanation {
+    /// Index of the block, as in [`Occurrence::line`]
+    pub index: usize,
+    pub location: BlockLocation,
+    pub explanation: Explanation,
+    /// The context of each candidate, in the same order
+    pub contexts: Vec<ContextKind>,
+}
+
+/// Run the search matcher for `term` over `lines`, keeping the blocks with a
+/// candidate match, or only block `only` whatever its result
+pub fn explain_lines(
+    term: &str,
+    lines: &[TextBlock],
+    only: Option<usize>,
+    options: MatchOptions,
+) -> Vec<LineExplanation> {
+    let matcher = search_matcher(options);
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| only.is_none_or(|only| only == *index))
+        .map(|(index, line)| {
+            let explanation = matcher.explain(term, &line.text);
+            let contexts = explanation
+                .candidates
+                .iter()
+                .map(|candidate| line.context(candidate.span.clone()))
+                .collect();
+            LineExplanation {
+                index,
+                location: line.location.clone(),
+                explanation,
+                contexts,
+            }
+        })
+        .filter(|line| only.is_some() || !line.explanation.candidates.is_empty())
+        .collect()
+}
+
+/// Classify the match at `span` by the whitespace-delimited token around it.
+///
+/// The token is an email address when it has a `local@domain.tld` shape
+/// (optionally behind `mailto:`), and a URL when it has a `scheme://`
+/// prefix, starts with `www.`, or is a dotted host followed by a path.
+/// Addresses broken across lines no longer have either shape and are
+/// classified as prose.
+pub fn classify_context(line: &str, span: Range<usize>) -> ContextKind {
+    let start = line[..span.start]
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| {
+            i + line[i..].chars().next().map_or(1, char::len_utf8)
+        });
+    let end = line[span.end..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |i| span.end + i);
+
+    let token = line[start..end].trim_matches(|c: char| "<>()[]{}\"',;:.!?".contains(c));
+
+    if is_email(token) {
+        ContextKind::Email
+    } else if is_url(token) {
+        ContextKind::Url
+    } else {
+        ContextKind::Prose
+    }
+}
+
+fn is_email(token: &str) -> bool {
+    let token = token.strip_prefix("mailto:").unwrap_or(token);
+
+    match token.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && is_host(domain.trim_end_matches('.')),
+        None => false,
+    }
+}
+
+fn is_url(token: &str) -> bool {
+    if let Some((scheme, rest)) = token.split_once("://") {
+        return !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            && !rest.is_empty();
+    }
+
+    if token.starts_with("www.") {
+        return true;
+    }
+
+    match token.split_once('/') {
+        Some((host, _)) => is_host(host),
+        None => false,
+    }
+}
+
+/// Whether `host` looks like a dotted domain name such as `example.com`
+fn is_host(host: &str) -> bool {
+    let labels: Vec<&str> = host.split('.').collect();
+
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+        && labels
+            .last()
+            .is_some_and(|tld| tld.chars().any(char::is_alphabetic))
+}
+
+/// The occurrences in one document that survive `--ignore-context`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchSet {
+    pub occurrences: Vec<Occurrence>,
+    /// Number of occurrences dropped because of their context
+    pub suppressed: usize,
+}
+
+impl MatchSet {
+    /// Keep the occurrences whose context is not in `ignore`
+    pub fn new(occurrences: Vec<Occurrence>, ignore: &[ContextKind]) -> Self {
+        let total = occurrences.len();
+        let occurrences: Vec<Occurrence> = occurrences
+            .into_iter()
+            .filter(|occurrence| !ignore.contains(&occurrence.context_kind))
+            .collect();
+
+        Self {
+            suppressed: total - occurrences.len(),
+            occurrences,
+        }
+    }
+
+    /// The distinct needles that were found, in order
+    pub fn results(&self) -> SearchResults {
+        results_from_occurrences(&self.occurrences)
+    }
+
+    /// The occurrences of each document inside a container, keyed by
+    /// [`Occurrence::entries`] in order; a single set for a plain document
+    pub fn by_document(&self) -> Vec<(Vec<String>, MatchSet)> {
+        let mut documents: Vec<(Vec<String>, MatchSet)> = Vec::new();
+        for occurrence in &self.occurrences {
+            match documents
+                .iter_mut()
+                .find(|(entries, _)| *entries == occurrence.entries)
+            {
+                Some((_, matches)) => matches.occurrences.push(occurrence.clone()),
+                None => documents.push((
+                    occurrence.entries.clone(),
+                    MatchSet {
+                        occurrences: vec![occurrence.clone()],
+                        suppressed: 0,
+                    },
+                )),
+            }
+        }
+        documents.sort_by(|a, b| a.0.cmp(&b.0));
+        documents
+    }
+
+    /// The distinct contexts `result` was found in, in a fixed order
+    pub fn context_kinds(&self, result: &SearchResult) -> Vec<ContextKind> {
+        let mut kinds: Vec<ContextKind> = self
+            .occurrences
+            .iter()
+            .filter(|occurrence| {
+                occurrence.term == result.term
+                    && occurrence.metadata == result.metadata
+                    && occurrence.location.part() == result.location
+            })
+            .map(|occurrence| occurrence.context_kind)
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+        kinds
+    }
+}
+
+/// Occurrences of one needle on lines that are identical once normalized
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepeatGroup {
+    pub term: String,
+    pub metadata: String,
+    /// The shared line, normalized by [`normalize_line`]
+    pub line: String,
+    /// Every occurrence in the group, in document order
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl RepeatGroup {
+    pub fn count(&self) -> usize {
+        self.occurrences.len()
+    }
+
+    /// First and last page the group appears on, when pages are known
+    pub fn page_range(&self) -> Option<(usize, usize)> {
+        let mut pages = self.occurrences.iter().filter_map(Occurrence::page);
+        let first = pages.next()?;
+        Some(pages.fold((first, first), |(low, high), page| {
+            (low.min(page), high.max(page))
+        }))
+    }
+}
+
+/// Normalize a line for repeat detection: trim it, collapse runs of
+/// whitespace to one space, and replace every run of digits with `#`.
+///
+/// Nothing else is changed, so lines that differ in any letter or
+/// punctuation stay distinct.
+pub fn normalize_line(line: &str) -> String {
+    let mut normalized = String::with_capacity(line.len());
+    let mut previous: Option<char> = None;
+
+    for c in line.trim().chars() {
+        let c = if c.is_whitespace() {
+            ' '
+        } else if c.is_ascii_digit() {
+            '#'
+        } else {
+            c
+        };
+        if (c == ' ' || c == '#') && previous == Some(c) {
+            continue;
+        }
+        normalized.push(c);
+        previous = Some(c);
+    }
+
+    normalized
+}
+
+/// Group occurrences of the same needle whose normalized lines are equal.
+///
+/// Groups are ordered by their first occurrence; an occurrence on a line
+/// that repeats nowhere forms a group of one.
+pub fn collapse_repeats(occurrences: &[Occurrence]) -> Vec<RepeatGroup> {
+    let mut groups: Vec<RepeatGroup> = Vec::new();
+    let mut index: HashMap<(&str, &str, String), usize> = HashMap::new();
+
+    for occurrence in occurrences {
+        let line = normalize_line(&occurrence.line_text);
+        let key = (
+            occurrence.term.as_str(),
+            occurrence.metadata.as_str(),
+            line.clone(),
+        );
+
+        match index.get(&key) {
+            Some(&group) => groups[group].occurrences.push(occurrence.clone()),
+            None => {
+                index.insert(key, groups.len());
+                groups.push(RepeatGroup {
+                    term: occurrence.term.clone(),
+                    metadata: occurrence.metadata.clone(),
+                    line,
+                    occurrences: vec![occurrence.clone()],
+                });
+            }
+        }
+    }
+
+    groups
+}
+
+/// Characters of the line a needle was found on kept as a result's context
+const RESULT_CONTEXT_CHARS: usize = 80;
+
+/// Collapse occurrences into the distinct needles they belong to, sorted by
+/// term and metadata, each counting the times it was found, so twice for a
+/// line that holds it twice. A needle
+/// found in several parts of a document, such as its body and a header, has
+/// a result for each part. Each result has the page and context of the
+/// first line its needle was found on, and the distance and text of its
+/// closest occurrence when fuzzy matching found it only approximately.
+pub fn results_from_occurrences(occurrences: &[Occurrence]) -> SearchResults {
+    // The needle, and the document and line it was found on
+    type Hit<'a> = ((&'a str, &'a str, Location), (&'a [String], usize));
+    let mut lines: BTreeMap<Hit, (&Occurrence, u32)> = BTreeMap::new();
+    let mut closest: HashMap<(&str, &str, Location), &Occurrence> = HashMap::new();
+    for occurrence in occurrences {
+        let result = (
+            occurrence.term.as_str(),
+            occurrence.metadata.as_str(),
+            occurrence.location.part(),
+        );
+        lines
+            .entry((
+                result.clone(),
+                (occurrence.entries.as_slice(), occurrence.line),
+            ))
+            .or_insert((occurrence, 0))
+            .1 += 1;
+        let nearest = closest.entry(result).or_insert(occurrence);
+        if occurrence.distance < nearest.distance {
+            *nearest = occurrence;
+        }
+    }
+
+    let mut results = SearchResults::new();
+    for (((term, metadata, location), _), (first, found)) in lines {
+        match results.last_mut() {
+            Some(last)
+                if last.term == term && last.metadata == metadata && last.location == location =>
+            {
+                last.occurrences += found
+            }
+            _ => {
+                let nearest = closest[&(term, metadata, location.clone())];
+                let approximate = (nearest.distance > 0).then_some(nearest);
+                results.push(SearchResult {
+                    location,
+                    occurrences: found,
+                    page: first.page().and_then(|page| u32::try_from(page).ok()),
+                    context: Some(
+                        context_snippet(&first.line_text, &first.span, RESULT_CONTEXT_CHARS).0,
+                    ),
+                    distance_score: approximate.map(|nearest| {
+                        nearest.distance as f32 / term.chars().count().max(1) as f32
+                    }),
+                    matched: approximate
+                        .and_then(|nearest| nearest.line_text.get(nearest.span.clone()))
+                        .map(str::to_string),
+                    ..SearchResult::new(term, metadata)
+                })
+            }
+        }
+    }
+    results
+}
+
+/// Give `results` the weights of their needles and list them heaviest
+/// first; results of equal weight keep their order
+pub fn weigh_results(mut results: SearchResults, weights: &NeedleWeights) -> SearchResults {
+    if weights.is_empty() {
+        return results;
+    }
+    for result in &mut results {
+        result.weight = weights
+            .get(&(result.term.clone(), result.metadata.clone()))
+            .copied()
+            .unwrap_or_default();
+    }
+    results.sort_by_key(|result| std::cmp::Reverse(result.weight));
+    results
+}
+
+/// Combine the results of one document that were found a block at a time,
+/// such as those of [`parse_docx_iter`](crate::parsers::parse_docx_iter):
+/// results of the same needle in the same part add up their occurrences,
+/// and they are listed as [`results_from_occurrences`] and
+/// [`weigh_results`] list them, with the page and context of the result
+/// found first and the distance of the closest
+pub fn merge_results(mut results: SearchResults) -> SearchResults {
+    results.sort_by(|a, b| {
+        (&a.term, &a.metadata, &a.location).cmp(&(&b.term, &b.metadata, &b.location))
+    });
+    results.dedup_by(|later, earlier| {
+        let same = later.term == earlier.term
+            && later.metadata == earlier.metadata
+            && later.location == earlier.location;
+        if same {
+            earlier.occurrences += later.occurrences;
+            // An exact match anywhere makes the needle exact
+            let closer = match (later.distance_score, earlier.distance_score) {
+                (Some(later), Some(earlier)) => later < earlier,
+                (None, Some(_)) => true,
+                _ => false,
+            };
+            if closer {
+                earlier.distance_score = later.distance_score;
+                earlier.matched = later.matched.take();
+            }
+        }
+        same
+    });
+    results.sort_by_key(|result| std::cmp::Reverse(result.weight));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ContextKind::{Email, Prose, Url};
+
+    fn blocks(lines: &[&str]) -> Vec<TextBlock> {
+        TextBlock::plain_lines(&lines.join("\n"))
+    }
+
+    fn classify(line: &str, needle: &str) -> ContextKind {
+        let start = line.find(needle).expect("needle not in line");
+        classify_context(line, start..start + needle.len())
+    }
+
+    #[test]
+    fn test_classify_context() {
+        let cases = [
+            ("Meeting with alice tomorrow", "alice", Prose),
+            ("alice.johnson@company.com", "alice", Email),
+            ("Contact: mailto:alice.johnson@company.com", "alice", Email),
+            ("Write to <alice@company.com>.", "alice", Email),
+            ("Reach bob at bob@mail.example.org, thanks", "bob@", Email),
+            ("https://intranet.example.com/people/alice", "alice", Url),
+            ("see (http://example.com/alice)", "alice", Url),
+            ("www.alice-johnson.com", "alice", Url),
+            ("example.com/team/alice", "alice", Url),
+            ("https://xn--bcher-kva.example/alice", "alice", Url),
+            ("alice@xn--mller-kva.de", "alice", Email),
+            ("Zoë at zoë@bücher.de", "zoë@", Email),
+            ("alice.johnson@exam-", "alice", Prose),
+            ("alice@localhost", "alice", Prose),
+            ("alice.johnson", "alice", Prose),
+            ("and/or alice", "alice", Prose),
+            ("1.5/alice", "alice", Prose),
+        ];
+
+        for (line, needle, expected) in cases {
+            assert_eq!(
+                classify(line, needle),
+                expected,
+                "{:?} in {:?}",
+                needle,
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_occurrences_records_every_span() {
+        let needles = vec![("alice".to_string(), "id=1".to_string())];
+        let lines = ["alice and alice", "mail alice@company.com"];
+
+        let occurrences = find_occurrences(&needles, &blocks(&lines), ExtractOptions::default());
+        let spans: Vec<_> = occurrences
+            .iter()
+            .map(|o| (o.line, o.span.clone(), o.context_kind))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(0, 0..5, Prose), (0, 10..15, Prose), (1, 5..10, Email)]
+        );
+    }
+
+    #[test]
+    fn test_search_text_reports_overlapping_needles() {
+        let needles = vec![
+            ("Alice Johnson".to_string(), "id=1".to_string()),
+            ("Johnson".to_string(), "id=2".to_string()),
+            ("son and".to_string(), "id=3".to_string()),
+            ("aa".to_string(), "id=4".to_string()),
+        ];
+        let text = "Met Alice Johnson and Bob\nbaaa";
+
+        let found: Vec<_> = search_text(&needles, text, ExtractOptions::default())
+            .into_iter()
+            .map(|o| (o.metadata, o.location.to_string(), o.span))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("id=1".to_string(), "line 1".to_string(), 4..17),
+                ("id=2".to_string(), "line 1".to_string(), 10..17),
+                ("id=3".to_string(), "line 1".to_string(), 14..21),
+                ("id=4".to_string(), "line 2".to_string(), 1..3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_text_without_anything_to_find() {
+        let needles = vec![("Alice Johnson".to_string(), "id=1".to_string())];
+        assert!(search_text(&needles, "Alice", ExtractOptions::default()).is_empty());
+        assert!(search_text(&needles, "", ExtractOptions::default()).is_empty());
+        assert!(search_text(&[], "Alice Johnson", ExtractOptions::default()).is_empty());
+
+        // A needle longer than a line is not found across the line break
+        assert!(search_text(&needles, "Alice\nJohnson", ExtractOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_offsets_count_characters_of_the_extracted_text() {
+        let needles = vec![("Zoë".to_string(), "id=1".to_string())];
+        let lines = ["Café Zoë", "naïve Zoë and Zoë"];
+
+        let text: Vec<char> = extracted_text(&blocks(&lines)).chars().collect();
+        let occurrences = find_occurrences(&needles, &blocks(&lines), ExtractOptions::default());
+        let offsets: Vec<_> = occurrences.iter().map(|o| o.offset.clone()).collect();
+        assert_eq!(offsets, vec![5..8, 15..18, 23..26]);
+        for offset in offsets {
+            assert_eq!(text[offset].iter().collect::<String>(), "Zoë");
+        }
+    }
+
+    #[test]
+    fn test_results_count_every_occurrence_in_term_order() {
+        let needles = vec![
+            ("bob".to_string(), "id=2".to_string()),
+            ("alice".to_string(), "id=1".to_string()),
+        ];
+        let lines = ["bob and bob", "alice", "carol", "alice and bob"];
+
+        let occurrences = find_occurrences(&needles, &blocks(&lines), ExtractOptions::default());
+        let results = results_from_occurrences(&occurrences);
+        let counted: Vec<_> = results
+            .iter()
+            .map(|r| (r.term.as_str(), r.occurrences))
+            .collect();
+        // Twice for the line that holds bob twice
+        assert_eq!(counted, vec![("alice", 2), ("bob", 3)]);
+
+        let weights = NeedleWeights::from([(("bob".to_string(), "id=2".to_string()), 3)]);
+        let weighed: Vec<_> = weigh_results(results, &weights)
+            .into_iter()
+            .map(|r| (r.term, r.weight))
+            .collect();
+        assert_eq!(
+            weighed,
+            vec![("bob".to_string(), 3), ("alice".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_automaton_finds_what_naive_finds_among_ten_thousand_needles() {
+        use crate::matcher::Strategy;
+
+        let needles: Vec<(String, String)> = (0..10_000)
+            .map(|i| {
+                (
+                    format!("Customer{:05} Lastname{}", i, i % 97),
+                    format!("id={}", i),
+                )
+            })
+            .collect();
+        let lines = [
+            "Invoice for CUSTOMER00042 lastname42, paid".to_string(),
+            "customer09999 Lastname8 and Customer00001 Lastname1".to_string(),
+            "Customer10000 Lastname9 is not on the list".to_string(),
+            format!("{} then {}", needles[5_000].0, needles[5_000].0),
+            String::new(),
+        ];
+        let found = |strategy| {
+            let extract = ExtractOptions {
+                strategy,
+                ..ExtractOptions::default()
+            };
+            find_occurrences(
+                &needles,
+                &blocks(&lines.iter().map(String::as_str).collect::<Vec<_>>()),
+                extract,
+            )
+            .into_iter()
+            .map(|o| (o.line, o.metadata, o.line_text[o.span].to_string()))
+            .collect::<Vec<_>>()
+        };
+
+        let automaton = found(Strategy::Automaton);
+        assert_eq!(
+            automaton,
+            [
+                (0, "id=42", "CUSTOMER00042 lastname42"),
+                (1, "id=1", "Customer00001 Lastname1"),
+                (1, "id=9999", "customer09999 Lastname8"),
+                (3, "id=5000", "Customer05000 Lastname53"),
+                (3, "id=5000", "Customer05000 Lastname53"),
+            ]
+            .map(|(line, metadata, text)| (
+                line,
+                metadata.to_string(),
+                text.to_string()
+            ))
+        );
+        assert_eq!(found(Strategy::Naive), automaton);
+    }
+
+    #[test]
+    fn test_whole_words() {
+        let whole_words = ExtractOptions {
+            matching: MatchOptions {
+                whole_word: true,
+                ..MatchOptions::default()
+            },
+            ..ExtractOptions::default()
+        };
+        let needles = vec![
+            ("Ann".to_string(), "id=1".to_string()),
+            ("Bob Smith".to_string(), "id=2".to_string()),
+        ];
+        let lines = [
+            "Ann",
+            "Annabelle, the Planner",
+            "Dear Ann, and (Ann). Ann-Marie",
+            "Annual report by Bob Smithers",
+            "Bob Smith.",
+            "cc: bob smith",
+            "Joanne",
+        ];
+
+        let found = |extract| {
+            find_occurrences(&needles, &blocks(&lines), extract)
+                .into_iter()
+                .map(|occurrence| {
+                    (
+                        occurrence.line,
+                        occurrence.line_text[occurrence.span].to_string(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let whole = [
+            (0, "Ann"),
+            (2, "Ann"),
+            (2, "Ann"),
+            (2, "Ann"),
+            (4, "Bob Smith"),
+            (5, "bob smith"),
+        ];
+        assert_eq!(
+            found(whole_words),
+            whole.map(|(line, text)| (line, text.to_string()))
+        );
+
+        // Without whole words, every substring matches
+        assert_eq!(found(ExtractOptions::default()).len(), 11);
+    }
+
+    #[test]
+    fn test_ignore_accents_in_needles_and_text() {
+        let needles: Vec<(String, String)> = [
+            "José García",
+            "Francois Lefevre",
+            "Ana Núñez",
+            "Jiri Dvorak",
+        ]
+        .iter()
+        .map(|term| (term.to_string(), String::new()))
+        .collect();
+        let lines = [
+            "Jose Garcia, JOSÉ GARCÍA",
+            "Signed: François Lefèvre",
+            "ana nunez",
+            "Jiří Dvořák and Jiří Dvořákova",
+        ];
+        let found = |matching| {
+            find_occurrences(
+                &needles,
+                &blocks(&lines),
+                ExtractOptions {
+                    matching,
+                    ..ExtractOptions::default()
+                },
+            )
+            .into_iter()
+            .map(|occurrence| occurrence.line_text[occurrence.span].to_string())
+            .collect::<Vec<_>>()
+        };
+
+        let accents = MatchOptions {
+            ignore_accents: true,
+            whole_word: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            found(accents),
+            [
+                "Jose Garcia",
+                "JOSÉ GARCÍA",
+                "François Lefèvre",
+                "ana nunez",
+                "Jiří Dvořák"
+            ]
+        );
+        assert_eq!(found(MatchOptions::default()), ["JOSÉ GARCÍA"]);
+    }
+
+    #[test]
+    fn test_regex_needles_report_the_matched_text() {
+        let needles = vec![
+            (r"/INV-\d{4}/".to_string(), "kind=invoice".to_string()),
+            (
+                r"^(Alice|Bob) \p{Lu}\w+$".to_string(),
+                "kind=name".to_string(),
+            ),
+        ];
+        let lines = [
+            "Paid inv-0042 and INV-1234.",
+            "Bob Smith",
+            "Bob Smith signed",
+            "Alice Åström",
+        ];
+        let found = |matching| {
+            find_occurrences(
+                &needles,
+                &blocks(&lines),
+                ExtractOptions {
+                    matching,
+                    ..ExtractOptions::default()
+                },
+            )
+            .into_iter()
+            .map(|occurrence| (occurrence.term, occurrence.metadata))
+            .collect::<Vec<_>>()
+        };
+        let found_as = |term: &str, metadata: &str| (term.to_string(), metadata.to_string());
+
+        assert_eq!(
+            found(MatchOptions::default()),
+            [
+                found_as("inv-0042", "kind=invoice"),
+                found_as("INV-1234", "kind=invoice")
+            ]
+        );
+        assert_eq!(
+            found(MatchOptions {
+                case_sensitive: true,
+                regex_needles: true,
+                ..MatchOptions::default()
+            }),
+            [
+                found_as("INV-1234", "kind=invoice"),
+                found_as("Bob Smith", "kind=name"),
+                found_as("Alice Åström", "kind=name")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_matches_report_the_text_and_distance() {
+        let needles = vec![("Alice Johnson".to_string(), "id=1".to_string())];
+        let lines = [
+            "Alice Johnson",
+            "Signed: alice jonson",
+            "Alise Jonson, Esq.",
+            "Alan Johnston",
+        ];
+        let found = |max_edits| {
+            find_occurrences(
+                &needles,
+                &blocks(&lines),
+                ExtractOptions {
+                    max_edits,
+                    ..ExtractOptions::default()
+                },
+            )
+            .into_iter()
+            .map(|occurrence| {
+                (
+                    occurrence.line,
+                    occurrence.line_text[occurrence.span].to_string(),
+                    occurrence.distance,
+                )
+            })
+            .collect::<Vec<_>>()
+        };
+
+        let exact = (0, "Alice Johnson".to_string(), 0);
+        assert_eq!(found(0), vec![exact.clone()]);
+        assert_eq!(
+            found(1),
+            vec![exact.clone(), (1, "alice jonson".to_string(), 1)]
+        );
+        assert_eq!(
+            found(2),
+            vec![
+                exact,
+                (1, "alice jonson".to_string(), 1),
+                (2, "Alise Jonson".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_and_needles_match_only_when_every_part_is_found() {
+        let needles = vec![
+            (
+                "Project Falcon && budget overrun".to_string(),
+                "flagged".to_string(),
+            ),
+            (
+                "Project Falcon && Bob Smith".to_string(),
+                "staffing".to_string(),
+            ),
+            ("R&D".to_string(), "dept".to_string()),
+        ];
+        let document = [
+            "Project Falcon kickoff",
+            "R&D notes",
+            "The budget overrun was approved",
+            "Bob Smith and Project Falcon",
+        ];
+        let found = |lines: &[&str], and_same_block| {
+            find_occurrences(
+                &needles,
+                &blocks(lines),
+                ExtractOptions {
+                    and_same_block,
+                    ..ExtractOptions::default()
+                },
+            )
+            .into_iter()
+            .map(|occurrence| {
+                (
+                    occurrence.line,
+                    occurrence.term,
+                    occurrence.line_text[occurrence.span].to_string(),
+                )
+            })
+            .collect::<Vec<_>>()
+        };
+        let hit = |line, term: &str, text: &str| (line, term.to_string(), text.to_string());
+
+        assert_eq!(
+            found(&document, false),
+            [
+                hit(0, "Project Falcon && budget overrun", "Project Falcon"),
+                hit(0, "Project Falcon && Bob Smith", "Project Falcon"),
+                hit(1, "R&D", "R&D"),
+                hit(2, "Project Falcon && budget overrun", "budget overrun"),
+                hit(3, "Project Falcon && budget overrun", "Project Falcon"),
+                hit(3, "Project Falcon && Bob Smith", "Bob Smith"),
+                hit(3, "Project Falcon && Bob Smith", "Project Falcon"),
+            ]
+        );
+        assert_eq!(
+            found(&document, true),
+            [
+                hit(1, "R&D", "R&D"),
+                hit(3, "Project Falcon && Bob Smith", "Bob Smith"),
+                hit(3, "Project Falcon && Bob Smith", "Project Falcon")
+            ]
+        );
+
+        // Only one part present
+        assert_eq!(
+            found(&["Project Falcon kickoff", "Budget approved"], false),
+            []
+        );
+        assert_eq!(found(&["The budget overrun", "Bob Smith"], false), []);
+    }
+
+    #[test]
+    fn test_near_needles_report_the_window() {
+        let needles = vec![(
+            "Smith NEAR/3 unpaid invoice".to_string(),
+            "id=4".to_string(),
+        )];
+        let lines = [
+            "Bob Smith has an unpaid invoice",
+            "Bob Smith has two more unpaid invoices",
+            "Smith",
+            "unpaid invoice from SMITH",
+        ];
+        let found = find_occurrences(&needles, &blocks(&lines), ExtractOptions::default())
+            .into_iter()
+            .map(|occurrence| {
+                (
+                    occurrence.line,
+                    occurrence.term.clone(),
+                    occurrence.line_text[occurrence.span].to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let term = "Smith NEAR/3 unpaid invoice".to_string();
+
+        assert_eq!(
+            found,
+            [
+                (0, term.clone(), "Smith has an unpaid invoice".to_string()),
+                (3, term, "unpaid invoice from SMITH".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_catches_ocr_substitutions() {
+        let needles = vec![
+            ("Smith".to_string(), String::new()),
+            ("Jane Doe".to_string(), String::new()),
+        ];
+        let lines = ["Signed: J. Sm1th", "Jane D0e and Jane Doe", "Smithson"];
+        let found = |max_edits| {
+            find_occurrences(
+                &needles,
+                &blocks(&lines),
+                ExtractOptions {
+                    max_edits,
+                    ..ExtractOptions::default()
+                },
+            )
+            .into_iter()
+            .map(|occurrence| {
+                (
+                    occurrence.line_text[occurrence.span].to_string(),
+                    occurrence.distance,
+                )
+            })
+            .collect::<Vec<_>>()
+        };
+        let exact = find_occurrences(&needles, &blocks(&lines), ExtractOptions::default());
+
+        assert_eq!(
+            find_occurrences(
+                &needles,
+                &blocks(&lines),
+                ExtractOptions {
+                    max_edits: 0,
+                    ..ExtractOptions::default()
+                }
+            ),
+            exact
+        );
+        assert_eq!(
+            found(0),
+            [("Jane Doe".to_string(), 0), ("Smith".to_string(), 0)]
+        );
+        assert_eq!(
+            found(1),
+            [
+                ("Sm1th".to_string(), 1),
+                ("Jane D0e".to_string(), 1),
+                ("Jane Doe".to_string(), 0),
+                ("Smith".to_string(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_set_suppresses_ignored_contexts() {
+        let needles = vec![
+            ("alice".to_string(), "id=1".to_string()),
+            ("bob".to_string(), "id=2".to_string()),
+        ];
+        let lines = ["alice@company.com", "https://example.com/bob", "bob"];
+
+        let matches = MatchSet::new(
+            find_occurrences(&needles, &blocks(&lines), ExtractOptions::default()),
+            &[Email, Url],
+        );
+        assert_eq!(matches.suppressed, 2);
+        assert_eq!(matches.results().len(), 1);
+        assert_eq!(
+            matches.context_kinds(&SearchResult::new("bob", "id=2")),
+            vec![Prose]
+        );
+
+        let all = MatchSet::new(
+            find_occurrences(&needles, &blocks(&lines), ExtractOptions::default()),
+            &[],
+        );
+        assert_eq!(all.suppressed, 0);
+        assert_eq!(
+            all.context_kinds(&SearchResult::new("bob", "id=2")),
+            vec![Prose, Url]
+        );
+    }
+
+    #[test]
+    fn test_explain_lines_agrees_with_find_occurrences() {
+        let line = |text: &str, concealed| TextBlock {
+            concealed,
+            ..TextBlock::new(text, BlockLocation::PdfPage { page: 2 })
+        };
+        let lines = [
+            line("Dear Alice Johnson,", None),
+            line("nothing here", None),
+            line("mailto:alice@company.com Alice", None),
+            line("Alice", Some(ContextKind::Hidden)),
+        ];
+        let needles = [("Alice".to_string(), String::new())];
+        let exact = MatchOptions {
+            case_sensitive: true,
+            ..MatchOptions::default()
+        };
+
+        let explained = explain_lines("Alice", &lines, None, exact);
+        let found: Vec<(usize, Range<usize>, ContextKind)> = explained
+            .iter()
+            .flat_map(|line| {
+                let matches = line.explanation.matches();
+                matches
+                    .into_iter()
+                    .zip(line.contexts.clone())
+                    .map(move |(span, kind)| (line.index, span, kind))
+            })
+            .collect();
+        let expected: Vec<(usize, Range<usize>, ContextKind)> = find_occurrences(
+            &needles,
+            &lines,
+            ExtractOptions {
+                matching: exact,
+                ..ExtractOptions::default()
+            },
+        )
+        .into_iter()
+        .map(|occurrence| (occurrence.line, occurrence.span, occurrence.context_kind))
+        .collect();
+
+        assert_eq!(
+            explained.iter().map(|line| line.index).collect::<Vec<_>>(),
+            vec![0, 2, 3]
+        );
+        // The same matches; concealed blocks override the classified context
+        assert_eq!(found, expected);
+        assert_eq!(found[2], (3, 0..5, ContextKind::Hidden));
+        assert_eq!(explained[0].location.page(), Some(2));
+
+        let only = explain_lines("Alice", &lines, Some(1), exact);
+        assert_eq!(only.len(), 1);
+        assert!(only[0].explanation.candidates.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_line() {
+        assert_eq!(
+            normalize_line("  Confidential –  Alice\tJohnson – Page 12 "),
+            "Confidential – Alice Johnson – Page #"
+        );
+        assert_eq!(normalize_line("Page 3 of 140"), "Page # of #");
+        assert_eq!(normalize_line("v1.2.10"), "v#.#.#");
+        assert_eq!(normalize_line("Page ½ of ٣"), "Page ½ of ٣");
+    }
+
+    #[test]
+    fn test_collapse_repeated_footers() {
+        let needles = vec![("Alice Johnson".to_string(), "id=1".to_string())];
+        let mut lines = Vec::new();
+        for page in 1..=40 {
+            lines.push(format!("Confidential – Alice Johnson – Page {}", page));
+            lines.push("Quarterly numbers were reviewed.".to_string());
+        }
+        lines.push("Alice Johnson approved the budget.".to_string());
+        lines.push("Alice Johnson approved the budget!".to_string());
+
+        let blocks: Vec<TextBlock> = lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| {
+                TextBlock::new(
+                    line.as_str(),
+                    BlockLocation::PdfPage {
+                        page: index / 2 + 1,
+                    },
+                )
+            })
+            .collect();
+        let occurrences = find_occurrences(&needles, &blocks, ExtractOptions::default());
+
+        let groups = collapse_repeats(&occurrences);
+        let summary: Vec<_> = groups
+            .iter()
+            .map(|group| (group.line.as_str(), group.count(), group.page_range()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Confidential – Alice Johnson – Page #", 40, Some((1, 40))),
+                ("Alice Johnson approved the budget.", 1, Some((41, 41))),
+                ("Alice Johnson approved the budget!", 1, Some((41, 41))),
+            ]
+        );
+        assert_eq!(
+            groups[0].occurrences[39].line_text,
+            "Confidential – Alice Johnson – Page 40"
+        );
+    }
+
+    #[test]
+    fn test_collapse_keeps_needles_apart_on_the_same_line() {
+        let needles = vec![
+            ("Alice".to_string(), "id=1".to_string()),
+            ("Bob".to_string(), "id=2".to_string()),
+        ];
+        let lines = [
+            "Alice and Bob, page 1",
+            "Alice and Bob, page 2",
+            "Alice and Rob, page 3",
+        ];
+
+        let groups = collapse_repeats(&find_occurrences(
+            &needles,
+            &blocks(&lines),
+            ExtractOptions::default(),
+        ));
+        let counts: Vec<(&str, usize)> = groups
+            .iter()
+            .map(|g| (g.term.as_str(), g.count()))
+            .collect();
+        assert_eq!(counts, vec![("Alice", 2), ("Bob", 2), ("Alice", 1)]);
+        assert_eq!(groups[0].page_range(), None);
+    }
+}
@@ -0,0 +1,676 @@
+//! Search settings shared by the command line, the TUI and settings files.
+//!
+//! [`SearchSettings`] is the one definition of every user-facing search
+//! option. clap flattens it into the `search`, `batch` and `tui` commands,
+//! the TUI settings tab edits it through [`SearchSettings::entries`] and
+//! [`SearchSettings::set`], and [`SearchSettings::load`] reads it from JSON.
+//! All three go through [`SearchSettings::validate`].
+//!
+//! Adding an option means adding a field here: `entries` destructures the
+//! struct without `..`, so a new field does not compile until it is listed
+//! there and handled in `set`.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ConfigError,
+    matcher::{CaseFolding, MatcherChoice, Strategy},
+    types::{
+        ContextKind, ExtractOptions, MatchOptions, MetadataFormat, NeedleDelimiter, SearchConfig,
+        StopwordMode,
+    },
+    utils::{parse_where_clause, Date},
+};
+
+/// How results are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Html,
+    /// One self-contained HTML page with excerpts around every match
+    #[serde(rename = "html-full")]
+    HtmlFull,
+    Ndjson,
+}
+
+/// Every option that changes what a search matches or how it is reported
+#[derive(Clone, Debug, Default, PartialEq, Eq, clap::Args, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchSettings {
+    /// Match needles only in the case they are written in
+    #[arg(long)]
+    pub case_sensitive: bool,
+
+    /// Match needles only between word boundaries, not inside longer words
+    #[arg(long)]
+    pub whole_word: bool,
+
+    /// Match needles with or without accents, so José matches Jose and the other way round
+    #[arg(long)]
+    pub ignore_accents: bool,
+
+    /// Compare needles and text as stored, without first composing them to Unicode NFC
+    #[arg(long)]
+    pub no_normalize: bool,
+
+    /// Treat every needle as a regular expression; needles wrapped in /…/ always are
+    #[arg(long)]
+    pub regex_needles: bool,
+
+    /// How to ignore case: full Unicode folding, Unicode with Turkish dotted and dotless I, or ASCII letters only
+    #[arg(long, value_enum, default_value_t = CaseFolding::Unicode)]
+    pub case_fold: CaseFolding,
+
+    /// Also match runs of words up to N inserted, deleted or replaced characters away from a needle
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub fuzzy: u8,
+
+    /// Only match needles joined with && where every part is in the same paragraph (DOCX) or line, not just the same document
+    #[arg(long)]
+    pub and_same_paragraph: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, ignore_case = true, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Write CSV fields verbatim, without neutralizing leading formula characters
+    #[arg(long)]
+    pub csv_unsafe: bool,
+
+    /// Leave out the snippet of text around each match in text, JSON, CSV and HTML output
+    #[arg(long)]
+    pub no_context: bool,
+
+    /// Show N characters either side of each match in its snippet (default: 80 characters in all, centred on the match)
+    #[arg(long, value_name = "N")]
+    pub context: Option<usize>,
+
+    /// Drop matches found inside these contexts (comma-separated: email, url, link-target, hidden, comment-author, metadata)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "KINDS")]
+    pub ignore_context: Vec<ContextKind>,
+
+    /// Report matches on lines that repeat with only numbers changed (headers, footers) once, with a count
+    #[arg(long)]
+    pub collapse_repeats: bool,
+
+    /// Report a needle once for every line it was found on, rather than once per document
+    #[arg(long)]
+    pub no_dedup: bool,
+
+    /// In batch mode, report a needle once, in the first file it was found in, rather than once per file
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// How to interpret the needles metadata column
+    #[arg(long, value_enum, default_value_t = MetadataFormat::Plain)]
+    pub metadata_format: MetadataFormat,
+
+    /// Only search needles whose metadata contains KEY=VALUE (repeatable)
+    #[arg(long = "where", value_name = "KEY=VALUE")]
+    pub filters: Vec<String>,
+
+    /// Also search DOCX hyperlink targets such as mailto: addresses (reported as link-target)
+    #[arg(long)]
+    pub include_link_targets: bool,
+
+    /// Also search DOCX text formatted as hidden (reported as hidden)
+    #[arg(long)]
+    pub include_hidden_text: bool,
+
+    /// Also search the title, author, subject and keywords of DOCX documents, and the creator and producer of PDFs too (reported as metadata)
+    #[arg(long)]
+    pub search_meta: bool,
+
+    /// Only search needles whose valid_from/valid_until metadata includes this date (default: today, UTC)
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    pub as_of: Option<String>,
+
+    /// Drop needles whose term is listed in this file, one term per line
+    #[arg(long, value_name = "FILE")]
+    pub stopwords: Option<PathBuf>,
+
+    /// Which needles --stopwords drops: exact terms, or also terms made up only of stop words
+    #[arg(long, value_enum, default_value_t = StopwordMode::Exact)]
+    pub stopword_mode: StopwordMode,
+
+    /// Character separating term and metadata in needles files, `\t` for a tab (default: detected)
+    #[arg(long, value_name = "CHAR")]
+    pub needle_delimiter: Option<NeedleDelimiter>,
+
+    /// Do not retry a document that fails to parse as the type its magic bytes indicate
+    #[arg(long)]
+    pub no_retry_detect: bool,
+
+    /// How needles are looked up: each on its own, all at once with an automaton, or whichever suits the needles and documents
+    #[arg(long, value_enum, default_value_t = MatcherChoice::Auto)]
+    pub matcher: MatcherChoice,
+}
+
+/// The value of one setting, as shown and edited in the TUI
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettingValue {
+    Flag(bool),
+    /// One of a fixed set of names
+    Choice(String),
+    List(Vec<String>),
+    /// Free text; `None` for the default
+    Text(Option<String>),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Flag(value) => write!(f, "{}", value),
+            SettingValue::Choice(value) => write!(f, "{}", value),
+            SettingValue::List(values) if values.is_empty() => write!(f, "(none)"),
+            SettingValue::List(values) => write!(f, "{}", values.join(", ")),
+            SettingValue::Text(None) => write!(f, "(default)"),
+            SettingValue::Text(Some(value)) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl SearchSettings {
+    /// Check the combination of settings, and that every `--where` clause
+    /// and the `--as-of` date parse
+    pub fn validate(&self) -> Result<()> {
+        self.where_clauses()?;
+        self.as_of_date()?;
+
+        if self.collapse_repeats
+            && matches!(
+                self.format,
+                OutputFormat::Html | OutputFormat::HtmlFull | OutputFormat::Ndjson
+            )
+        {
+            let name = self
+                .format
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default();
+            return Err(anyhow!(
+                "--collapse-repeats is not available with {} output",
+                name
+            ));
+        }
+        if self.dedup && self.no_dedup {
+            return Err(anyhow!("--dedup and --no-dedup cannot be combined"));
+        }
+        // Named by their flags, with the typed error kept as the cause
+        self.search_config().map_err(|e| {
+            let flags = match e {
+                ConfigError::FuzzyRegexNeedles => "--fuzzy cannot be combined with --regex-needles",
+            };
+            anyhow::Error::new(e).context(flags)
+        })?;
+
+        Ok(())
+    }
+
+    /// The parsed `--where` filters
+    pub fn where_clauses(&self) -> Result<Vec<(String, String)>> {
+        self.filters
+            .iter()
+            .map(|clause| parse_where_clause(clause))
+            .collect()
+    }
+
+    /// The date needles have to be valid on
+    pub fn as_of_date(&self) -> Result<Date> {
+        match &self.as_of {
+            Some(date) => date.parse().map_err(|e| anyhow!("Invalid --as-of: {}", e)),
+            None => Ok(Date::today()),
+        }
+    }
+
+    /// The options a compiled needle set has to match
+    pub fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            case_sensitive: self.case_sensitive,
+            whole_word: self.whole_word,
+            ignore_accents: self.ignore_accents,
+            normalize: !self.no_normalize,
+            regex_needles: self.regex_needles,
+            case_fold: self.case_fold,
+        }
+    }
+
+    /// The library's options for the matching flags, built with
+    /// [`SearchConfig::builder`], which refuses `--fuzzy` with
+    /// `--regex-needles`
+    pub fn search_config(&self) -> Result<SearchConfig, ConfigError> {
+        let builder = SearchConfig::builder()
+            .case_sensitive(self.case_sensitive)
+            .whole_word(self.whole_word)
+            .regex_needles(self.regex_needles)
+            .include_context(!self.no_context);
+        match self.fuzzy {
+            0 => builder,
+            edits => builder.fuzzy_distance(edits),
+        }
+        .build()
+    }
+
+    /// The options of [`search_config`](Self::search_config) with the
+    /// document content to search besides the visible text, searched with
+    /// `strategy` (as [`MatcherChoice::resolve`] gives it for `matcher`)
+    pub fn extract_options(&self, strategy: Strategy) -> Result<ExtractOptions, ConfigError> {
+        Ok(ExtractOptions {
+            include_link_targets: self.include_link_targets,
+            include_hidden_text: self.include_hidden_text,
+            include_metadata: self.search_meta,
+            retry_detected_type: !self.no_retry_detect,
+            strategy,
+            matching: self.match_options(),
+            and_same_block: self.and_same_paragraph,
+            ..self.search_config()?.extract_options()
+        })
+    }
+
+    /// Every setting by name, in declaration order
+    pub fn entries(&self) -> Vec<(&'static str, SettingValue)> {
+        // No `..`: a new field has to be listed here before this compiles.
+        let SearchSettings {
+            case_sensitive,
+            whole_word,
+            ignore_accents,
+            no_normalize,
+            regex_needles,
+            case_fold,
+            fuzzy,
+            and_same_paragraph,
+            format,
+            csv_unsafe,
+            no_context,
+            context,
+            ignore_context,
+            collapse_repeats,
+            no_dedup,
+            dedup,
+            metadata_format,
+            filters,
+            include_link_targets,
+            include_hidden_text,
+            search_meta,
+            as_of,
+            stopwords,
+            stopword_mode,
+            needle_delimiter,
+            no_retry_detect,
+            matcher,
+        } = self;
+
+        vec![
+            ("case_sensitive", SettingValue::Flag(*case_sensitive)),
+            ("whole_word", SettingValue::Flag(*whole_word)),
+            ("ignore_accents", SettingValue::Flag(*ignore_accents)),
+            ("no_normalize", SettingValue::Flag(*no_normalize)),
+            ("regex_needles", SettingValue::Flag(*regex_needles)),
+            ("case_fold", choice(case_fold)),
+            (
+                "fuzzy",
+                SettingValue::Text((*fuzzy > 0).then(|| fuzzy.to_string())),
+            ),
+            (
+                "and_same_paragraph",
+                SettingValue::Flag(*and_same_paragraph),
+            ),
+            ("format", choice(format)),
+            ("csv_unsafe", SettingValue::Flag(*csv_unsafe)),
+            ("no_context", SettingValue::Flag(*no_context)),
+            (
+                "context",
+                SettingValue::Text(context.map(|chars| chars.to_string())),
+            ),
+            (
+                "ignore_context",
+                SettingValue::List(
+                    ignore_context
+                        .iter()
+                        .map(|kind| kind.as_str().to_string())
+                        .collect(),
+                ),
+            ),
+            ("collapse_repeats", SettingValue::Flag(*collapse_repeats)),
+            ("no_dedup", SettingValue::Flag(*no_dedup)),
+            ("dedup", SettingValue::Flag(*dedup)),
+            ("metadata_format", choice(metadata_format)),
+            ("filters", SettingValue::List(filters.clone())),
+            (
+                "include_link_targets",
+                SettingValue::Flag(*include_link_targets),
+            ),
+            (
+                "include_hidden_text",
+                SettingValue::Flag(*include_hidden_text),
+            ),
+            ("search_meta", SettingValue::Flag(*search_meta)),
+            ("as_of", SettingValue::Text(as_of.clone())),
+            (
+                "stopwords",
+                SettingValue::Text(
+                    stopwords
+                        .as_ref()
+                        .map(|path| path.to_string_lossy().into_owned()),
+                ),
+            ),
+            ("stopword_mode", choice(stopword_mode)),
+            (
+                "needle_delimiter",
+                SettingValue::Text(needle_delimiter.map(|delimiter| delimiter.to_string())),
+            ),
+            ("no_retry_detect", SettingValue::Flag(*no_retry_detect)),
+            ("matcher", choice(matcher)),
+        ]
+    }
+
+    /// Set the setting called `name`; the result is validated and left
+    /// unchanged when invalid
+    pub fn set(&mut self, name: &str, value: SettingValue) -> Result<()> {
+        let mut updated = self.clone();
+        match (name, value) {
+            ("case_sensitive", SettingValue::Flag(value)) => updated.case_sensitive = value,
+            ("whole_word", SettingValue::Flag(value)) => updated.whole_word = value,
+            ("ignore_accents", SettingValue::Flag(value)) => updated.ignore_accents = value,
+            ("no_normalize", SettingValue::Flag(value)) => updated.no_normalize = value,
+            ("regex_needles", SettingValue::Flag(value)) => updated.regex_needles = value,
+            ("case_fold", SettingValue::Choice(value)) => {
+                updated.case_fold = parse_choice(name, &value)?
+            }
+            ("fuzzy", SettingValue::Text(value)) => {
+                updated.fuzzy = match value {
+                    None => 0,
+                    Some(value) => value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid value for {}: {}", name, value))?,
+                }
+            }
+            ("and_same_paragraph", SettingValue::Flag(value)) => updated.and_same_paragraph = value,
+            ("format", SettingValue::Choice(value)) => updated.format = parse_choice(name, &value)?,
+            ("csv_unsafe", SettingValue::Flag(value)) => updated.csv_unsafe = value,
+            ("no_context", SettingValue::Flag(value)) => updated.no_context = value,
+            ("context", SettingValue::Text(value)) => {
+                updated.context = value
+                    .map(|value| {
+                        value
+                            .parse()
+                            .map_err(|_| anyhow!("Invalid value for {}: {}", name, value))
+                    })
+                    .transpose()?
+            }
+            ("ignore_context", SettingValue::List(values)) => {
+                updated.ignore_context = values
+                    .iter()
+                    .map(|value| parse_choice(name, value))
+                    .collect::<Result<_>>()?
+            }
+            ("collapse_repeats", SettingValue::Flag(value)) => updated.collapse_repeats = value,
+            ("no_dedup", SettingValue::Flag(value)) => updated.no_dedup = value,
+            ("dedup", SettingValue::Flag(value)) => updated.dedup = value,
+            ("metadata_format", SettingValue::Choice(value)) => {
+                updated.metadata_format = parse_choice(name, &value)?
+            }
+            ("filters", SettingValue::List(values)) => updated.filters = values,
+            ("include_link_targets", SettingValue::Flag(value)) => {
+                updated.include_link_targets = value
+            }
+            ("include_hidden_text", SettingValue::Flag(value)) => {
+                updated.include_hidden_text = value
+            }
+            ("search_meta", SettingValue::Flag(value)) => updated.search_meta = value,
+            ("as_of", SettingValue::Text(value)) => updated.as_of = value,
+            ("stopwords", SettingValue::Text(value)) => {
+                updated.stopwords = value.map(PathBuf::from)
+            }
+            ("stopword_mode", SettingValue::Choice(value)) => {
+                updated.stopword_mode = parse_choice(name, &value)?
+            }
+            ("needle_delimiter", SettingValue::Text(value)) => {
+                updated.needle_delimiter = match value.as_deref() {
+                    None | Some("auto") => None,
+                    Some(value) => Some(
+                        value
+                            .parse()
+                            .map_err(|e| anyhow!("Invalid value for {}: {}", name, e))?,
+                    ),
+                }
+            }
+            ("no_retry_detect", SettingValue::Flag(value)) => updated.no_retry_detect = value,
+            ("matcher", SettingValue::Choice(value)) => {
+                updated.matcher = parse_choice(name, &value)?
+            }
+            (name, value) => {
+                return Err(anyhow!(
+                    "Unknown setting or wrong kind of value: {} = {}",
+                    name,
+                    value
+                ))
+            }
+        }
+
+        updated.validate()?;
+        *self = updated;
+        Ok(())
+    }
+
+    /// Rebuild settings from [`entries`](Self::entries)
+    pub fn from_entries(
+        entries: impl IntoIterator<Item = (&'static str, SettingValue)>,
+    ) -> Result<Self> {
+        let mut settings = Self::default();
+        for (name, value) in entries {
+            settings.set(name, value)?;
+        }
+        Ok(settings)
+    }
+
+    /// Flip a flag or move a choice on to its next value. Lists can only be
+    /// given on the command line or in a settings file.
+    pub fn cycle(&mut self, name: &str) -> Result<()> {
+        let value = match self.entries().into_iter().find(|(entry, _)| *entry == name) {
+            Some((_, SettingValue::Flag(value))) => SettingValue::Flag(!value),
+            Some((_, SettingValue::Choice(value))) => SettingValue::Choice(match name {
+                "case_fold" => next_choice::<CaseFolding>(&value),
+                "format" => next_choice::<OutputFormat>(&value),
+                "metadata_format" => next_choice::<MetadataFormat>(&value),
+                "stopword_mode" => next_choice::<StopwordMode>(&value),
+                "matcher" => next_choice::<MatcherChoice>(&value),
+                _ => unreachable!("choice settings are listed above"),
+            }),
+            Some((_, SettingValue::List(_) | SettingValue::Text(_))) => {
+                return Err(anyhow!(
+                    "{} can only be set on the command line or in a settings file",
+                    name
+                ))
+            }
+            None => return Err(anyhow!("Unknown setting: {}", name)),
+        };
+
+        self.set(name, value)
+    }
+
+    /// Parse and validate settings from JSON; missing fields keep their defaults
+    pub fn from_json(json: &str) -> Result<Self> {
+        let settings: Self =
+            serde_json::from_str(json).map_err(|e| anyhow!("Invalid settings: {}", e))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Read settings from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&json).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+fn choice<T: ValueEnum>(value: &T) -> SettingValue {
+    let name = value
+        .to_possible_value()
+        .expect("no skipped variants")
+        .get_name()
+        .to_string();
+    SettingValue::Choice(name)
+}
+
+fn parse_choice<T: ValueEnum>(setting: &str, value: &str) -> Result<T> {
+    T::from_str(value, true).map_err(|_| anyhow!("Invalid value for {}: {}", setting, value))
+}
+
+fn next_choice<T: ValueEnum>(current: &str) -> String {
+    let variants = T::value_variants();
+    let position = variants
+        .iter()
+        .position(
+            |variant| matches!(choice(variant), SettingValue::Choice(name) if name == current),
+        )
+        .unwrap_or(0);
+
+    match choice(&variants[(position + 1) % variants.len()]) {
+        SettingValue::Choice(name) => name,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn customized() -> SearchSettings {
+        SearchSettings {
+            case_sensitive: true,
+            whole_word: true,
+            ignore_accents: true,
+            no_normalize: true,
+            // Cannot be combined with `fuzzy`
+            regex_needles: false,
+            case_fold: CaseFolding::Turkic,
+            fuzzy: 2,
+            and_same_paragraph: true,
+            format: OutputFormat::Csv,
+            csv_unsafe: true,
+            no_context: true,
+            context: Some(40),
+            ignore_context: vec![ContextKind::Email, ContextKind::Url],
+            collapse_repeats: true,
+            no_dedup: true,
+            dedup: false,
+            metadata_format: MetadataFormat::Kv,
+            filters: vec!["dept=legal".to_string(), "note=\"a, b\"".to_string()],
+            include_link_targets: true,
+            include_hidden_text: true,
+            search_meta: true,
+            as_of: Some("2024-06-01".to_string()),
+            stopwords: Some(PathBuf::from("stopwords.txt")),
+            stopword_mode: StopwordMode::Contains,
+            needle_delimiter: Some("\\t".parse().unwrap()),
+            no_retry_detect: true,
+            matcher: MatcherChoice::Automaton,
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip_and_defaults() {
+        let settings = customized();
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(SearchSettings::from_json(&json).unwrap(), settings);
+
+        let partial = SearchSettings::from_json(r#"{"format": "json"}"#).unwrap();
+        assert_eq!(
+            partial,
+            SearchSettings {
+                format: OutputFormat::Json,
+                ..SearchSettings::default()
+            }
+        );
+        assert!(SearchSettings::from_json(r#"{"colour": true}"#).is_err());
+    }
+
+    #[test]
+    fn test_every_path_validates() {
+        let err = SearchSettings::from_json(r#"{"filters": ["dept"]}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("dept"), "{}", err);
+
+        let mut settings = SearchSettings {
+            collapse_repeats: true,
+            ..SearchSettings::default()
+        };
+        assert!(settings
+            .set("format", SettingValue::Choice("html".to_string()))
+            .is_err());
+        assert_eq!(settings.format, OutputFormat::Text);
+        assert!(settings
+            .set("format", SettingValue::Choice("HTML".to_string()))
+            .is_err());
+        assert!(settings.set("format", SettingValue::Flag(true)).is_err());
+        assert!(settings.set("colour", SettingValue::Flag(true)).is_err());
+        assert!(settings
+            .set("as_of", SettingValue::Text(Some("2024-06-31".to_string())))
+            .is_err());
+        assert_eq!(settings.as_of, None);
+        settings.set("dedup", SettingValue::Flag(true)).unwrap();
+        assert!(settings.set("no_dedup", SettingValue::Flag(true)).is_err());
+
+        settings
+            .set("regex_needles", SettingValue::Flag(true))
+            .unwrap();
+        let err = settings
+            .set("fuzzy", SettingValue::Text(Some("1".to_string())))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("--regex-needles"), "{}", err);
+        assert_eq!(settings.fuzzy, 0);
+        assert!(SearchSettings::from_json(r#"{"fuzzy": 2, "regex_needles": true}"#).is_err());
+        let conflicting = SearchSettings {
+            fuzzy: 2,
+            regex_needles: true,
+            ..SearchSettings::default()
+        };
+        assert_eq!(
+            conflicting.search_config(),
+            Err(ConfigError::FuzzyRegexNeedles)
+        );
+        assert_eq!(
+            conflicting
+                .validate()
+                .unwrap_err()
+                .downcast_ref::<ConfigError>(),
+            Some(&ConfigError::FuzzyRegexNeedles)
+        );
+        assert!(conflicting.extract_options(Strategy::default()).is_err());
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut settings = SearchSettings::default();
+        settings.cycle("whole_word").unwrap();
+        assert!(settings.whole_word);
+
+        for expected in [
+            OutputFormat::Json,
+            OutputFormat::Csv,
+            OutputFormat::Html,
+            OutputFormat::HtmlFull,
+            OutputFormat::Ndjson,
+            OutputFormat::Text,
+        ] {
+            settings.cycle("format").unwrap();
+            assert_eq!(settings.format, expected);
+        }
+        assert!(settings.cycle("filters").is_err());
+    }
+}
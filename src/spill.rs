@@ -0,0 +1,156 @@
+//! Occurrences kept on disk while a batch search runs.
+//!
+//! Short needles over a large corpus can find more occurrences than fit in
+//! memory. Once the occurrences held by finished files pass the threshold,
+//! each further file's occurrences are written to a [`Segment`] of their
+//! own in the spill directory, one NDJSON record per occurrence in document
+//! order, and the file keeps only the segment. The formatters read the
+//! segments back a file at a time, in file order, while writing results,
+//! so only one spilled file is resident at once.
+//!
+//! The directory is removed when the [`Spill`] is dropped, whether the run
+//! succeeded or not.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use tempfile::TempDir;
+
+use crate::search::Occurrence;
+
+/// Occurrences held in memory before further files are spilled
+pub const DEFAULT_THRESHOLD: usize = 1_000_000;
+
+/// A spill directory and the count of occurrences kept out of it
+#[derive(Debug)]
+pub struct Spill {
+    dir: TempDir,
+    threshold: usize,
+    resident: AtomicUsize,
+}
+
+/// One file's occurrences, written out by [`Spill::offload`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub path: PathBuf,
+    /// Number of occurrences in the segment
+    pub count: usize,
+}
+
+impl Spill {
+    /// A fresh directory under `parent`, or the system temporary directory,
+    /// spilling once more than `threshold` occurrences are in memory
+    pub fn new(parent: Option<&Path>, threshold: usize) -> Result<Self> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("docsearcher-spill-");
+        let dir = match parent {
+            Some(parent) => builder.tempdir_in(parent),
+            None => builder.tempdir(),
+        }
+        .map_err(|e| {
+            anyhow!(
+                "Failed to create a spill directory in {}: {}",
+                parent.unwrap_or(&std::env::temp_dir()).display(),
+                e
+            )
+        })?;
+
+        Ok(Spill {
+            dir,
+            threshold,
+            resident: AtomicUsize::new(0),
+        })
+    }
+
+    /// Where segments are written
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Keep the `occurrences` of file `index` in memory if they fit under
+    /// the threshold, returning `None`; otherwise write them to a segment
+    pub fn offload(&self, index: usize, occurrences: &[Occurrence]) -> Result<Option<Segment>> {
+        let count = occurrences.len();
+        if self.resident.fetch_add(count, Ordering::Relaxed) + count <= self.threshold {
+            return Ok(None);
+        }
+        self.resident.fetch_sub(count, Ordering::Relaxed);
+
+        let path = self.dir.path().join(format!("{}.ndjson", index));
+        let write = || -> std::io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&path)?);
+            for occurrence in occurrences {
+                serde_json::to_writer(&mut writer, occurrence)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()
+        };
+        write().map_err(|e| anyhow!("Failed to write spill file {}: {}", path.display(), e))?;
+        Ok(Some(Segment { path, count }))
+    }
+}
+
+impl Segment {
+    /// The occurrences written to the segment, in the order they were found
+    pub fn read(&self) -> Result<Vec<Occurrence>> {
+        let failed = |e: &dyn std::fmt::Display| {
+            anyhow!("Failed to read spill file {}: {}", self.path.display(), e)
+        };
+        let file = File::open(&self.path).map_err(|e| failed(&e))?;
+        let mut occurrences = Vec::with_capacity(self.count);
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| failed(&e))?;
+            occurrences.push(serde_json::from_str(&line).map_err(|e| failed(&e))?);
+        }
+        Ok(occurrences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search::BlockLocation, types::ContextKind};
+
+    fn occurrence(term: &str, line: usize) -> Occurrence {
+        Occurrence {
+            term: term.to_string(),
+            metadata: "id=1".to_string(),
+            line,
+            span: 0..term.len(),
+            context_kind: ContextKind::Prose,
+            line_text: format!("{} signed\n\"here\"", term),
+            location: BlockLocation::PdfPage { page: line + 1 },
+            entries: vec!["inner.zip".to_string()],
+            distance: 0,
+            offset: 0..term.chars().count(),
+        }
+    }
+
+    #[test]
+    fn test_files_past_the_threshold_are_spilled_and_read_back() {
+        let parent = tempfile::tempdir().unwrap();
+        let spill = Spill::new(Some(parent.path()), 3).unwrap();
+        let first: Vec<Occurrence> = (0..2).map(|line| occurrence("Alice", line)).collect();
+        let second: Vec<Occurrence> = (0..2).map(|line| occurrence("Bob", line)).collect();
+
+        assert_eq!(spill.offload(0, &first).unwrap(), None);
+        let segment = spill
+            .offload(1, &second)
+            .unwrap()
+            .expect("past the threshold");
+        assert_eq!(segment.count, 2);
+        assert_eq!(segment.read().unwrap(), second);
+        // Spilled files don't count against the threshold
+        assert_eq!(spill.offload(2, &first[..1]).unwrap(), None);
+
+        let dir = spill.path().to_path_buf();
+        assert!(dir.starts_with(parent.path()));
+        drop(spill);
+        assert!(!dir.exists());
+    }
+}
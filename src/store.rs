@@ -0,0 +1,455 @@
+//! Persistent storage of search runs in a local SQLite database.
+//!
+//! Every run appends one row to `runs`, one row per searched file to
+//! `documents` and one row per match to `matches`, so results can be
+//! queried across weekly runs. The schema is versioned through SQLite's
+//! `user_version` pragma and migrated automatically when opened.
+//!
+//! A document inside an archive is stored under the archive's path, with
+//! the entries leading to it in `documents.entries`, joined with
+//! [`SOURCE_SEPARATOR`].
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+use sha2::{Digest, Sha256};
+
+use crate::types::{SearchResult, SOURCE_SEPARATOR};
+
+/// Schema migrations, applied in order; entry `n` upgrades version `n` to `n + 1`
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE runs (
+        id INTEGER PRIMARY KEY,
+        started_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+        invocation TEXT NOT NULL,
+        needles_path TEXT NOT NULL,
+        needle_count INTEGER NOT NULL,
+        document_count INTEGER NOT NULL,
+        match_count INTEGER NOT NULL
+    );
+    CREATE TABLE documents (
+        id INTEGER PRIMARY KEY,
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        path TEXT NOT NULL,
+        sha256 TEXT,
+        error TEXT
+    );
+    CREATE TABLE matches (
+        id INTEGER PRIMARY KEY,
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        document_id INTEGER NOT NULL REFERENCES documents(id),
+        match_id TEXT NOT NULL,
+        term TEXT NOT NULL,
+        metadata TEXT NOT NULL
+    );
+    CREATE INDEX matches_term ON matches(term, metadata);
+    CREATE INDEX documents_path ON documents(path);
+",
+    "
+    ALTER TABLE documents ADD COLUMN entries TEXT;
+",
+];
+
+/// One searched document and what was found in it
+pub struct DocumentRecord<'a> {
+    /// The file on disk
+    pub path: PathBuf,
+    /// Archive entries leading from `path` to the document; empty when the
+    /// file is the document
+    pub entries: Vec<String>,
+    pub matches: Result<&'a [SearchResult], String>,
+}
+
+/// Canned queries over the results database
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CannedQuery {
+    /// The run and document in which each needle was first matched
+    FirstSeen,
+    /// Every match recorded for one file across runs
+    FileHistory,
+    /// One line per run with its counts
+    Runs,
+}
+
+/// Rows returned by a query, with column names
+#[derive(Debug, Default, PartialEq)]
+pub struct QueryRows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+pub struct ResultStore {
+    conn: Connection,
+}
+
+impl ResultStore {
+    /// Open (creating if needed) a results database and migrate it
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open results database: {}", path.display()))?;
+        migrate(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    /// Open an existing results database for queries only
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open results database: {}", path.display()))?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != MIGRATIONS.len() {
+            return Err(anyhow!(
+                "Results database {} is at schema version {}, expected {}; \
+                 record a run with this version to migrate it",
+                path.display(),
+                version,
+                MIGRATIONS.len()
+            ));
+        }
+
+        Ok(Self { conn })
+    }
+
+    /// Append one run and return its id
+    pub fn record_run(
+        &mut self,
+        invocation: &str,
+        needles_path: &Path,
+        needle_count: usize,
+        documents: &[DocumentRecord],
+    ) -> Result<i64> {
+        let match_count: usize = documents
+            .iter()
+            .map(|doc| doc.matches.as_ref().map_or(0, |matches| matches.len()))
+            .sum();
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (invocation, needles_path, needle_count, document_count, match_count)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                invocation,
+                needles_path.to_string_lossy(),
+                needle_count,
+                documents.len(),
+                match_count,
+            ),
+        )?;
+        let run_id = tx.last_insert_rowid();
+
+        // Documents from one archive share its hash
+        let mut hashes: HashMap<&Path, Option<String>> = HashMap::new();
+        for doc in documents {
+            let hash = hashes
+                .entry(&doc.path)
+                .or_insert_with(|| {
+                    std::fs::read(&doc.path)
+                        .ok()
+                        .map(|bytes| sha256_hex(&bytes))
+                })
+                .clone();
+            let entries = (!doc.entries.is_empty()).then(|| doc.entries.join(SOURCE_SEPARATOR));
+            tx.execute(
+                "INSERT INTO documents (run_id, path, sha256, error, entries) VALUES (?1, ?2, ?3, ?4, ?5)",
+                (
+                    run_id,
+                    doc.path.to_string_lossy(),
+                    &hash,
+                    doc.matches.as_ref().err(),
+                    entries,
+                ),
+            )?;
+            let document_id = tx.last_insert_rowid();
+
+            for SearchResult { term, metadata, .. } in
+                doc.matches.as_ref().map_or(&[][..], |matches| *matches)
+            {
+                tx.execute(
+                    "INSERT INTO matches (run_id, document_id, match_id, term, metadata)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    (
+                        run_id,
+                        document_id,
+                        match_id(hash.as_deref().unwrap_or(""), &doc.entries, term, metadata),
+                        term,
+                        metadata,
+                    ),
+                )?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// Run one of the canned queries; `file` is required for `FileHistory`
+    pub fn canned_query(&self, query: CannedQuery, file: Option<&Path>) -> Result<QueryRows> {
+        match query {
+            CannedQuery::FirstSeen => self.raw_query(
+                "SELECT m.term, m.metadata, r.id AS run_id, r.started_at, d.path
+                 FROM matches m
+                 JOIN runs r ON r.id = m.run_id
+                 JOIN documents d ON d.id = m.document_id
+                 WHERE m.id = (SELECT MIN(m2.id) FROM matches m2
+                               WHERE m2.term = m.term AND m2.metadata = m.metadata)
+                 ORDER BY r.id, m.term",
+            ),
+            CannedQuery::FileHistory => {
+                let file = file.ok_or_else(|| anyhow!("The file-history query needs --file"))?;
+                self.query_with_params(
+                    "SELECT r.id AS run_id, r.started_at, d.sha256, m.term, m.metadata, m.match_id
+                     FROM matches m
+                     JOIN runs r ON r.id = m.run_id
+                     JOIN documents d ON d.id = m.document_id
+                     WHERE d.path = ?1
+                     ORDER BY r.id, m.term",
+                    &[&file.to_string_lossy()],
+                )
+            }
+            CannedQuery::Runs => self.raw_query(
+                "SELECT id AS run_id, started_at, invocation, needle_count, document_count, match_count
+                 FROM runs ORDER BY id",
+            ),
+        }
+    }
+
+    /// Run arbitrary SQL, refusing any statement that could modify the database
+    pub fn raw_query(&self, sql: &str) -> Result<QueryRows> {
+        self.query_with_params(sql, &[])
+    }
+
+    fn query_with_params(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<QueryRows> {
+        let mut stmt = self.conn.prepare(sql)?;
+        if !stmt.readonly() {
+            return Err(anyhow!("Only read-only queries are allowed"));
+        }
+
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let column_count = columns.len();
+        let rows = stmt
+            .query_map(params, |row| {
+                (0..column_count)
+                    .map(|i| Ok(value_to_string(row.get_ref(i)?)))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(QueryRows { columns, rows })
+    }
+}
+
+/// Stable identifier of a match: the content of the file, the archive
+/// entries leading to the document inside it, and the needle
+pub fn match_id(document_hash: &str, entries: &[String], term: &str, metadata: &str) -> String {
+    let mut hasher = Sha256::new();
+    let entries = entries.iter().map(String::as_str);
+    for part in std::iter::once(document_hash)
+        .chain(entries)
+        .chain([term, metadata])
+    {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex(&hasher.finalize()[..8])
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn value_to_string(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned(),
+        ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "Results database schema version {} is newer than this build supports ({})",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to migrate results database to version {}", i + 1))?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch::search_bytes, fixtures, search::results_from_occurrences, types::ExtractOptions,
+    };
+
+    fn search(needles: &[(String, String)], path: &Path) -> Vec<SearchResult> {
+        results_from_occurrences(
+            &search_bytes(
+                needles,
+                path,
+                &std::fs::read(path).unwrap(),
+                ExtractOptions::default(),
+            )
+            .unwrap()
+            .occurrences,
+        )
+    }
+
+    #[test]
+    fn test_first_seen_across_two_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("runs.db");
+        let needles = vec![
+            ("Alice Johnson".to_string(), "alice@x.com".to_string()),
+            ("Bob Smith".to_string(), "bob@x.com".to_string()),
+        ];
+        let first = dir.path().join("first.docx");
+        std::fs::write(&first, fixtures::docx(&["Alice Johnson joined"])).unwrap();
+        let second = dir.path().join("second.pdf");
+        std::fs::write(&second, fixtures::pdf(&[&["Alice Johnson", "Bob Smith"]])).unwrap();
+
+        let first_matches = search(&needles, &first);
+        let mut store = ResultStore::open(&db).unwrap();
+        let run1 = store
+            .record_run(
+                "search",
+                Path::new("n.csv"),
+                2,
+                &[DocumentRecord {
+                    path: first.clone(),
+                    entries: Vec::new(),
+                    matches: Ok(&first_matches),
+                }],
+            )
+            .unwrap();
+        drop(store);
+
+        // Reopening must not re-apply migrations
+        let second_matches = search(&needles, &second);
+        let mut store = ResultStore::open(&db).unwrap();
+        let run2 = store
+            .record_run(
+                "search",
+                Path::new("n.csv"),
+                2,
+                &[
+                    DocumentRecord {
+                        path: first.clone(),
+                        entries: Vec::new(),
+                        matches: Ok(&first_matches),
+                    },
+                    DocumentRecord {
+                        path: second.clone(),
+                        entries: Vec::new(),
+                        matches: Ok(&second_matches),
+                    },
+                ],
+            )
+            .unwrap();
+
+        let store = ResultStore::open_read_only(&db).unwrap();
+        let first_seen = store.canned_query(CannedQuery::FirstSeen, None).unwrap();
+        assert_eq!(first_seen.columns[..3], ["term", "metadata", "run_id"]);
+        let seen: Vec<(&str, String, &str)> = first_seen
+            .rows
+            .iter()
+            .map(|row| (row[0].as_str(), row[2].clone(), row[4].as_str()))
+            .collect();
+        assert_eq!(
+            seen,
+            vec![
+                ("Alice Johnson", run1.to_string(), first.to_str().unwrap()),
+                ("Bob Smith", run2.to_string(), second.to_str().unwrap()),
+            ]
+        );
+
+        let history = store
+            .canned_query(CannedQuery::FileHistory, Some(&first))
+            .unwrap();
+        assert_eq!(history.rows.len(), 2);
+        assert_eq!(
+            history.rows[0][5], history.rows[1][5],
+            "unchanged file keeps its match_id"
+        );
+
+        let runs = store.canned_query(CannedQuery::Runs, None).unwrap();
+        assert_eq!(runs.rows.len(), 2);
+        assert_eq!(runs.rows[1][5], "3");
+    }
+
+    #[test]
+    fn test_documents_in_one_archive_get_their_own_match_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("dump.zip");
+        std::fs::write(&archive, b"archive bytes").unwrap();
+        let matches = [SearchResult::new("Alice Johnson", "id=1")];
+        let document = |entry: &str| DocumentRecord {
+            path: archive.clone(),
+            entries: vec![entry.to_string()],
+            matches: Ok(&matches),
+        };
+
+        let db = dir.path().join("runs.db");
+        let mut store = ResultStore::open(&db).unwrap();
+        store
+            .record_run(
+                "batch",
+                Path::new("n.csv"),
+                1,
+                &[document("a/report.pdf"), document("b/report.pdf")],
+            )
+            .unwrap();
+
+        let store = ResultStore::open_read_only(&db).unwrap();
+        let rows = store
+            .raw_query("SELECT d.path, d.entries, m.match_id FROM matches m JOIN documents d ON d.id = m.document_id ORDER BY d.id")
+            .unwrap()
+            .rows;
+        assert_eq!(
+            (rows[0][0].as_str(), rows[0][1].as_str()),
+            (archive.to_str().unwrap(), "a/report.pdf")
+        );
+        assert_eq!(rows[0][0], rows[1][0]);
+        assert_ne!(rows[0][2], rows[1][2]);
+    }
+
+    #[test]
+    fn test_raw_queries_are_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("runs.db");
+        ResultStore::open(&db).unwrap();
+
+        let store = ResultStore::open_read_only(&db).unwrap();
+        assert!(store.raw_query("DELETE FROM runs").is_err());
+        assert!(store.raw_query("DROP TABLE matches").is_err());
+        assert_eq!(
+            store
+                .raw_query("SELECT COUNT(*) AS n FROM runs")
+                .unwrap()
+                .rows,
+            vec![vec!["0".to_string()]]
+        );
+    }
+}
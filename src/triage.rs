@@ -0,0 +1,648 @@
+//! Reviewing matches one at a time (`docsearcher triage`).
+//!
+//! A results file is the JSON report `search` and `batch` write with
+//! `--save-results`: a `results` array of [`MatchRecord`]s next to other
+//! report fields such as `resources`, which are kept as written. Triage adds
+//! a [`Disposition`] and an optional note to each match and rewrites the
+//! annotated file after every decision, so an interrupted session resumes at
+//! the first undecided match.
+//!
+//! Decisions are keyed by [`match_id`], a hash of the match's [`Source`]
+//! (the file, and any archive entries inside it), the needle and the lines
+//! it was found on. Passing an annotated file to a later run with
+//! `--dispositions`, or opening a new results file against an existing
+//! annotated one, carries decisions forward for every match whose id did not
+//! change. A match on an edited line gets a new id and is reviewed again.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    search::{BlockLocation, Occurrence},
+    types::Source,
+    utils::fnv1a,
+};
+
+/// What the reviewer decided about a match
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    Accept,
+    Reject,
+    /// Needs a second look
+    Flag,
+    /// Seen, but left undecided on purpose
+    Skip,
+}
+
+impl Disposition {
+    pub const ALL: [Disposition; 4] = [
+        Disposition::Accept,
+        Disposition::Reject,
+        Disposition::Flag,
+        Disposition::Skip,
+    ];
+
+    /// Name used in structured output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Accept => "accept",
+            Disposition::Reject => "reject",
+            Disposition::Flag => "flag",
+            Disposition::Skip => "skip",
+        }
+    }
+
+    /// The key that records this disposition: the first letter of its name
+    pub fn from_key(key: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|disposition| disposition.as_str().starts_with(key.to_ascii_lowercase()))
+    }
+}
+
+/// A decision and the note that goes with it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub disposition: Disposition,
+    pub note: Option<String>,
+}
+
+/// Where a needle was found
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub line: usize,
+    pub page: Option<usize>,
+    /// Set when `page` is only an estimate, as it is for DOCX
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub page_estimated: bool,
+    /// The header, footer, table cell or note the line is in; absent for the body
+    #[serde(default, skip_serializing_if = "crate::types::Location::is_body")]
+    pub part: crate::types::Location,
+    /// The DOCX body paragraph the line is, counted from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paragraph: Option<usize>,
+    /// The headings the paragraph sits under, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<String>,
+    /// Byte range of the match within `text`
+    pub span: [usize; 2],
+    /// Character range of the match within the extracted text of the
+    /// document, as `--dump-text` writes it
+    #[serde(default)]
+    pub offset: [usize; 2],
+    /// The whole line the needle was found in
+    pub text: String,
+    /// Set when `--fuzzy` matched text that differs from the needle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approximate: Option<Approximate>,
+}
+
+/// Text that `--fuzzy` matched in place of a needle
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Approximate {
+    /// The text at the location's span
+    pub matched: String,
+    /// Characters inserted, deleted or replaced to turn the needle into it
+    pub distance: usize,
+}
+
+impl From<&Occurrence> for Location {
+    fn from(occurrence: &Occurrence) -> Self {
+        let (paragraph, headings) = match &occurrence.location {
+            BlockLocation::DocxParagraph {
+                index,
+                heading_path,
+                ..
+            } => (Some(index + 1), heading_path.clone()),
+            _ => (None, Vec::new()),
+        };
+        Location {
+            line: occurrence.line,
+            page: occurrence.page(),
+            page_estimated: occurrence.location.page_is_estimated(),
+            part: occurrence.location.part(),
+            paragraph,
+            headings,
+            span: [occurrence.span.start, occurrence.span.end],
+            offset: [occurrence.offset.start, occurrence.offset.end],
+            text: occurrence.line_text.clone(),
+            approximate: (occurrence.distance > 0).then(|| Approximate {
+                matched: occurrence.line_text[occurrence.span.clone()].to_string(),
+                distance: occurrence.distance,
+            }),
+        }
+    }
+}
+
+/// One match as written in JSON results
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub match_id: String,
+    pub term: String,
+    pub metadata: String,
+    /// The file on disk, the first segment of `source`
+    pub file: String,
+    /// The file, then each archive entry leading to the document
+    #[serde(default)]
+    pub source: Source,
+    #[serde(default)]
+    pub occurrences: Vec<Location>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disposition: Option<Disposition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// The other fields of the result (`context_kind`, `fields`,
+    /// `needles_list`), kept as written
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MatchRecord {
+    /// The record for `term` found in the document at `source`, at
+    /// `occurrences`
+    pub fn new(source: &Source, term: &str, metadata: &str, occurrences: &[&Occurrence]) -> Self {
+        let occurrences: Vec<Location> = occurrences.iter().copied().map(Location::from).collect();
+        MatchRecord {
+            match_id: match_id(&source.to_string(), term, metadata, &occurrences),
+            term: term.to_string(),
+            metadata: metadata.to_string(),
+            file: source.outermost().to_string_lossy().into_owned(),
+            source: source.clone(),
+            occurrences,
+            disposition: None,
+            note: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    /// Where the match was found, as one string: the file, or the path
+    /// into the container it came from. Results saved before sources were
+    /// recorded fall back to the file.
+    pub fn source_label(&self) -> String {
+        if self.source.segments().is_empty() {
+            self.file.clone()
+        } else {
+            self.source.to_string()
+        }
+    }
+
+    pub fn annotation(&self) -> Option<Annotation> {
+        self.disposition.map(|disposition| Annotation {
+            disposition,
+            note: self.note.clone(),
+        })
+    }
+
+    /// The distinct pages the match was found on, in order; empty for
+    /// formats without pages
+    pub fn pages(&self) -> Vec<usize> {
+        let mut pages: Vec<usize> = self
+            .occurrences
+            .iter()
+            .filter_map(|location| location.page)
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+        pages
+    }
+
+    /// Where in the body of a DOCX document the needle was found:
+    /// `paragraph 87 under Contracts › Termination`, or `paragraphs 3, 87`
+    /// when it was found in several; `None` for other documents
+    pub fn section(&self) -> Option<String> {
+        let mut found: Vec<&Location> = self
+            .occurrences
+            .iter()
+            .filter(|location| location.paragraph.is_some())
+            .collect();
+        found.sort_by_key(|location| location.paragraph);
+        found.dedup_by_key(|location| location.paragraph);
+        match found.as_slice() {
+            [] => None,
+            [location] => {
+                let mut section = format!("paragraph {}", location.paragraph?);
+                if !location.headings.is_empty() {
+                    section.push_str(&format!(" under {}", location.headings.join(" › ")));
+                }
+                Some(section)
+            }
+            found => Some(format!(
+                "paragraphs {}",
+                found
+                    .iter()
+                    .filter_map(|location| location.paragraph)
+                    .map(|paragraph| paragraph.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// The distinct texts `--fuzzy` matched in place of the needle, in the
+    /// order first found
+    pub fn approximations(&self) -> Vec<&Approximate> {
+        let mut approximations: Vec<&Approximate> = Vec::new();
+        for approximate in self
+            .occurrences
+            .iter()
+            .filter_map(|location| location.approximate.as_ref())
+        {
+            if !approximations.contains(&approximate) {
+                approximations.push(approximate);
+            }
+        }
+        approximations
+    }
+
+    /// How many times the needle was found: once per occurrence, so twice
+    /// on a line that holds it twice
+    pub fn count(&self) -> usize {
+        self.occurrences.len()
+    }
+
+    /// The weight the needles file gives the needle; 0 when it gives none
+    pub fn weight(&self) -> i64 {
+        self.extra
+            .get("weight")
+            .and_then(serde_json::Value::as_i64)
+            .unwrap_or_default()
+    }
+
+    /// The edit distance of the closest occurrence, divided by the length
+    /// of the needle, to rank `--fuzzy` matches: 0 for an exact match,
+    /// higher for worse ones. `None` when every occurrence is exact.
+    pub fn distance_score(&self) -> Option<f32> {
+        if self.approximations().is_empty() {
+            return None;
+        }
+        let closest = self
+            .occurrences
+            .iter()
+            .map(|location| {
+                location
+                    .approximate
+                    .as_ref()
+                    .map_or(0, |approximate| approximate.distance)
+            })
+            .min()
+            .unwrap_or(0);
+        Some(closest as f32 / self.term.chars().count().max(1) as f32)
+    }
+
+    /// Record `annotation`, or clear the decision with `None`
+    pub fn annotate(&mut self, annotation: Option<&Annotation>) {
+        self.disposition = annotation.map(|annotation| annotation.disposition);
+        self.note = annotation.and_then(|annotation| annotation.note.clone());
+    }
+}
+
+/// Stable id of a match: the file, the needle and the distinct lines it was
+/// found on, in any order
+pub fn match_id(source: &str, term: &str, metadata: &str, occurrences: &[Location]) -> String {
+    let mut lines: Vec<&str> = occurrences
+        .iter()
+        .map(|location| location.text.as_str())
+        .collect();
+    lines.sort_unstable();
+    lines.dedup();
+
+    let mut key = Vec::new();
+    for part in [source, term, metadata].into_iter().chain(lines) {
+        key.extend_from_slice(part.as_bytes());
+        key.push(0);
+    }
+    format!("{:016x}", fnv1a(&key))
+}
+
+/// Write a results file holding `results` and the `extra` report fields,
+/// as [`ResultsFile::save`] does. `results` is serialized straight to the
+/// file, so it can produce its records as they are written rather than
+/// holding them all.
+pub fn save_results(
+    path: &Path,
+    results: &impl Serialize,
+    extra: &serde_json::Map<String, serde_json::Value>,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Saved<'a, R> {
+        results: R,
+        #[serde(flatten)]
+        extra: &'a serde_json::Map<String, serde_json::Value>,
+    }
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let write = || -> Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(&temporary)?);
+        serde_json::to_writer_pretty(&mut writer, &Saved { results, extra })?;
+        writer.flush()?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    };
+    write().map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// A JSON results file
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResultsFile {
+    pub results: Vec<MatchRecord>,
+    /// The other report fields, such as `resources`
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ResultsFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow!("{} is not a results file: {}", path.display(), e))
+    }
+
+    /// Write the file through a temporary sibling, so an interrupted write
+    /// leaves the previous version in place
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_results(path, &self.results, &self.extra)
+    }
+
+    /// The decisions recorded in this file
+    pub fn dispositions(&self) -> Dispositions {
+        Dispositions(
+            self.results
+                .iter()
+                .filter_map(|record| Some((record.match_id.clone(), record.annotation()?)))
+                .collect(),
+        )
+    }
+
+    /// Take over the decisions in `previous` for matches with the same id;
+    /// returns how many were carried forward
+    pub fn carry_forward(&mut self, previous: &Dispositions) -> usize {
+        let mut carried = 0;
+        for record in &mut self.results {
+            if let Some(annotation) = previous.get(&record.match_id) {
+                record.annotate(Some(annotation));
+                carried += 1;
+            }
+        }
+        carried
+    }
+}
+
+/// Decisions by match id, as read with `--dispositions`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dispositions(HashMap<String, Annotation>);
+
+impl Dispositions {
+    /// The decisions in an annotated results file
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(ResultsFile::load(path)?.dispositions())
+    }
+
+    pub fn get(&self, match_id: &str) -> Option<&Annotation> {
+        self.0.get(match_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// A review in progress: results being annotated, saved to `output` after
+/// every decision
+pub struct TriageSession {
+    pub results: ResultsFile,
+    output: PathBuf,
+}
+
+impl TriageSession {
+    /// Open `results` for review, resuming from `output` when an earlier
+    /// session wrote it
+    pub fn open(results: &Path, output: &Path) -> Result<Self> {
+        let mut file = ResultsFile::load(results)?;
+        if output.exists() {
+            file.carry_forward(&Dispositions::load(output)?);
+        }
+        Ok(TriageSession {
+            results: file,
+            output: output.to_path_buf(),
+        })
+    }
+
+    pub fn output(&self) -> &Path {
+        &self.output
+    }
+
+    /// Index of the first match without a decision
+    pub fn next_pending(&self) -> Option<usize> {
+        self.results
+            .results
+            .iter()
+            .position(|record| record.disposition.is_none())
+    }
+
+    /// Number of matches with a decision
+    pub fn decided(&self) -> usize {
+        self.results
+            .results
+            .iter()
+            .filter(|record| record.disposition.is_some())
+            .count()
+    }
+
+    /// Record a decision and save the annotated file
+    pub fn record(&mut self, index: usize, annotation: Annotation) -> Result<()> {
+        let record = self
+            .results
+            .results
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("No match at index {}", index))?;
+        record.annotate(Some(&annotation));
+        self.results.save(&self.output)
+    }
+
+    /// How many matches got each disposition
+    pub fn counts(&self) -> Vec<(Disposition, usize)> {
+        Disposition::ALL
+            .into_iter()
+            .map(|disposition| {
+                let count = self
+                    .results
+                    .results
+                    .iter()
+                    .filter(|record| record.disposition == Some(disposition))
+                    .count();
+                (disposition, count)
+            })
+            .collect()
+    }
+}
+
+/// Where triage writes annotations by default: `results.json` becomes
+/// `results.triage.json`
+pub fn default_output(results: &Path) -> PathBuf {
+    let stem = results.file_stem().unwrap_or_default().to_string_lossy();
+    results.with_file_name(format!("{}.triage.json", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ContextKind;
+
+    fn occurrence(term: &str, line: usize, text: &str) -> Occurrence {
+        let start = text.find(term).unwrap();
+        Occurrence {
+            term: term.to_string(),
+            metadata: "id=1".to_string(),
+            line,
+            span: start..start + term.len(),
+            context_kind: ContextKind::Prose,
+            line_text: text.to_string(),
+            location: crate::search::BlockLocation::PdfPage { page: 1 },
+            entries: Vec::new(),
+            distance: 0,
+            offset: start..start + term.len(),
+        }
+    }
+
+    fn results(lines: &[&str]) -> ResultsFile {
+        let occurrences: Vec<Occurrence> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, text)| occurrence("Alice", i, text))
+            .collect();
+        let mut records: Vec<MatchRecord> = occurrences
+            .iter()
+            .map(|occurrence| {
+                MatchRecord::new(
+                    &Source::file(Path::new("a.pdf")),
+                    "Alice",
+                    "id=1",
+                    &[occurrence],
+                )
+            })
+            .collect();
+        records[0]
+            .extra
+            .insert("context_kind".to_string(), serde_json::json!(["prose"]));
+        let mut extra = serde_json::Map::new();
+        extra.insert("resources".to_string(), serde_json::json!({ "files": 1 }));
+        ResultsFile {
+            results: records,
+            extra,
+        }
+    }
+
+    #[test]
+    fn test_match_id_depends_on_file_needle_and_lines() {
+        let first = occurrence("Alice", 0, "Alice Johnson");
+        let second = occurrence("Alice", 7, "Dear Alice");
+        let id = |file: &str, occurrences: &[&Occurrence]| {
+            MatchRecord::new(&Source::file(Path::new(file)), "Alice", "id=1", occurrences).match_id
+        };
+
+        assert_eq!(
+            id("a.pdf", &[&first, &second]),
+            id("a.pdf", &[&second, &first])
+        );
+        assert_eq!(id("a.pdf", &[&first]), id("a.pdf", &[&first, &first]));
+        assert_ne!(id("a.pdf", &[&first]), id("b.pdf", &[&first]));
+        assert_ne!(id("a.pdf", &[&first]), id("a.pdf", &[&first, &second]));
+
+        // The same document at different places in one archive
+        let archive = Source::file(Path::new("dump.zip"));
+        let nested =
+            |entry: &str| MatchRecord::new(&archive.nested([entry]), "Alice", "id=1", &[&first]);
+        let (a, b) = (nested("a/report.pdf"), nested("b/report.pdf"));
+        assert_ne!(a.match_id, b.match_id);
+        assert_eq!((a.file.as_str(), b.file.as_str()), ("dump.zip", "dump.zip"));
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(Disposition::from_key('a'), Some(Disposition::Accept));
+        assert_eq!(Disposition::from_key('R'), Some(Disposition::Reject));
+        assert_eq!(Disposition::from_key('f'), Some(Disposition::Flag));
+        assert_eq!(Disposition::from_key('s'), Some(Disposition::Skip));
+        assert_eq!(Disposition::from_key('n'), None);
+    }
+
+    #[test]
+    fn test_session_saves_every_decision_and_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.json");
+        results(&["Alice one", "Alice two", "Alice three"])
+            .save(&path)
+            .unwrap();
+        let output = default_output(&path);
+        assert_eq!(output, dir.path().join("results.triage.json"));
+
+        let mut session = TriageSession::open(&path, &output).unwrap();
+        assert_eq!(session.next_pending(), Some(0));
+        let note = Some("same person".to_string());
+        session
+            .record(
+                0,
+                Annotation {
+                    disposition: Disposition::Accept,
+                    note: note.clone(),
+                },
+            )
+            .unwrap();
+        session
+            .record(
+                1,
+                Annotation {
+                    disposition: Disposition::Skip,
+                    note: None,
+                },
+            )
+            .unwrap();
+        drop(session);
+
+        let resumed = TriageSession::open(&path, &output).unwrap();
+        assert_eq!(resumed.next_pending(), Some(2));
+        assert_eq!(resumed.decided(), 2);
+        assert_eq!(resumed.results.results[0].note, note);
+
+        // Same schema plus the decisions; other fields survive
+        let annotated = ResultsFile::load(&output).unwrap();
+        assert_eq!(annotated.extra["resources"]["files"], 1);
+        assert_eq!(annotated.results[0].extra["context_kind"][0], "prose");
+        assert_eq!(annotated.results[0].disposition, Some(Disposition::Accept));
+        assert!(!dir.path().join("results.triage.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_decisions_carry_forward_to_unchanged_matches() {
+        let mut before = results(&["Alice one", "Alice two"]);
+        before.results[0].annotate(Some(&Annotation {
+            disposition: Disposition::Reject,
+            note: None,
+        }));
+        before.results[1].annotate(Some(&Annotation {
+            disposition: Disposition::Flag,
+            note: None,
+        }));
+
+        let mut after = results(&["Alice one", "Alice 2"]);
+        assert_eq!(after.carry_forward(&before.dispositions()), 1);
+        assert_eq!(after.results[0].disposition, Some(Disposition::Reject));
+        assert_eq!(after.results[1].disposition, None);
+    }
+}
@@ -1,18 +1,215 @@
-use std::collections::HashSet;
+use crate::error::ConfigError;
+use crate::matcher::{CaseFolding, RegexNeedles, Strategy};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 /// Represents a search term with its associated metadata
 pub type Needle<'a> = (&'a str, &'a str);
 
-/// Represents a search result with the found term and metadata
-pub type SearchResult = (String, String);
+/// An owned search term with its metadata, as read from a needles file
+pub type OwnedNeedle = (String, String);
+
+/// The weights given to needles in the optional third column of a needles
+/// file; a needle without one weighs 0
+pub type NeedleWeights = HashMap<OwnedNeedle, i64>;
+
+/// A needle that was found, with its term and metadata as written in the
+/// needles file
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SearchResult {
+    pub term: String,
+    pub metadata: String,
+    /// The part of the document the needle was found in; a needle found in
+    /// several parts has a result for each
+    #[serde(default)]
+    pub location: Location,
+    /// The needle's weight from the needles file; results are listed
+    /// heaviest first
+    #[serde(default)]
+    pub weight: i64,
+    /// Times the needle was found, counting each one on a line that holds
+    /// it more than once
+    #[serde(default = "SearchResult::one")]
+    pub occurrences: u32,
+    /// The document the needle was found in, when it was searched from a
+    /// file on disk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// The 1-based page of the first line the needle was found on, for
+    /// formats that have pages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// The text around the needle on the first line it was found on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// The edit distance of the closest occurrence, divided by the length
+    /// of the needle, when fuzzy matching found the needle only
+    /// approximately: higher for worse matches. `None` when some
+    /// occurrence is exact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_score: Option<f32>,
+    /// The text fuzzy matching found in place of the needle at that
+    /// closest occurrence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<String>,
+}
+
+impl SearchResult {
+    /// A needle found on one line
+    pub fn new(term: impl Into<String>, metadata: impl Into<String>) -> Self {
+        Self {
+            term: term.into(),
+            metadata: metadata.into(),
+            location: Location::Body,
+            weight: 0,
+            occurrences: 1,
+            file: None,
+            page: None,
+            context: None,
+            distance_score: None,
+            matched: None,
+        }
+    }
+
+    /// This result, found in the document at `path`
+    pub fn in_file(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            file: Some(path.into()),
+            ..self
+        }
+    }
+
+    fn one() -> u32 {
+        1
+    }
+}
+
+/// A needle found on one line, from the `(term, metadata)` pair results
+/// used to be
+impl From<OwnedNeedle> for SearchResult {
+    fn from((term, metadata): OwnedNeedle) -> Self {
+        Self::new(term, metadata)
+    }
+}
+
+/// The part of a document a needle was found in
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Location {
+    /// The main text, and the only part of formats without headers and footers
+    #[default]
+    Body,
+    /// A DOCX page header
+    Header,
+    /// A DOCX page footer
+    Footer,
+    /// A cell of a DOCX table in the body, by row and column counted from 1
+    Table { row: u32, col: u32 },
+    /// A DOCX footnote, by its `w:id`, which Word numbers from 1
+    Footnote(u32),
+    /// A DOCX endnote, by its `w:id`
+    Endnote(u32),
+    /// A DOCX review comment, by its author and `w:id`
+    Comment { author: String, id: u32 },
+    /// A cell of a spreadsheet, by its sheet and the row and column counted
+    /// from 1
+    Cell { sheet: String, row: u32, col: u32 },
+    /// A chapter of an EPUB ebook, by the href of its content document in
+    /// the package manifest
+    Chapter { href: String },
+}
+
+impl Location {
+    /// Name used in structured output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Location::Body => "body",
+            Location::Header => "header",
+            Location::Footer => "footer",
+            Location::Table { .. } => "table",
+            Location::Footnote(_) => "footnote",
+            Location::Endnote(_) => "endnote",
+            Location::Comment { .. } => "comment",
+            Location::Cell { .. } => "cell",
+            Location::Chapter { .. } => "chapter",
+        }
+    }
+
+    /// Name of the part, as shown to users
+    pub fn name(&self) -> &'static str {
+        match self {
+            Location::Body => "Body",
+            Location::Header => "Header",
+            Location::Footer => "Footer",
+            Location::Table { .. } => "Table",
+            Location::Footnote(_) => "Footnotes",
+            Location::Endnote(_) => "Endnotes",
+            Location::Comment { .. } => "Comments",
+            Location::Cell { .. } => "Cells",
+            Location::Chapter { .. } => "Chapters",
+        }
+    }
+
+    pub fn is_body(&self) -> bool {
+        *self == Location::Body
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Table { row, col } => write!(f, "table row {}, column {}", row, col),
+            Location::Footnote(id) | Location::Endnote(id) => write!(f, "{} {}", self.as_str(), id),
+            Location::Comment { author, id } if author.is_empty() => write!(f, "comment {}", id),
+            Location::Comment { author, id } => write!(f, "comment {} by {}", id, author),
+            Location::Cell { sheet, row, col } => write!(
+                f,
+                "{}!{}{}",
+                sheet,
+                crate::parsers::xlsx::column_name(*col),
+                row
+            ),
+            Location::Chapter { href } => write!(f, "chapter {}", href),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
 
 /// Supported document file types
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FileType {
     /// Microsoft Word document (.docx)
     Docx,
     /// Portable Document Format (.pdf)
     Pdf,
+    /// Legacy Word 97-2003 document (.doc), searchable only with the `doc` feature
+    Doc,
+    /// Excel workbook (.xlsx)
+    Xlsx,
+    /// OpenDocument text document (.odt)
+    Odt,
+    /// Plain text file (.txt), searched line by line
+    Txt,
+    /// Rich Text Format document (.rtf)
+    Rtf,
+    /// EPUB ebook (.epub)
+    Epub,
 }
 
 impl FileType {
@@ -21,17 +218,629 @@ impl FileType {
         match self {
             FileType::Docx => ".docx",
             FileType::Pdf => ".pdf",
+            FileType::Doc => ".doc",
+            FileType::Xlsx => ".xlsx",
+            FileType::Odt => ".odt",
+            FileType::Txt => ".txt",
+            FileType::Rtf => ".rtf",
+            FileType::Epub => ".epub",
+        }
+    }
+
+    /// Name of the format, as shown to users
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileType::Docx => "DOCX",
+            FileType::Pdf => "PDF",
+            FileType::Doc => "DOC",
+            FileType::Xlsx => "XLSX",
+            FileType::Odt => "ODT",
+            FileType::Txt => "TXT",
+            FileType::Rtf => "RTF",
+            FileType::Epub => "EPUB",
         }
     }
-    
+
     /// Get the MIME type for this file type
     pub fn mime_type(&self) -> &'static str {
         match self {
-            FileType::Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            FileType::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
             FileType::Pdf => "application/pdf",
+            FileType::Doc => "application/msword",
+            FileType::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            FileType::Odt => "application/vnd.oasis.opendocument.text",
+            FileType::Txt => "text/plain",
+            FileType::Rtf => "application/rtf",
+            FileType::Epub => "application/epub+zip",
+        }
+    }
+}
+
+/// Collection of search results, sorted by weight, heaviest first, then by
+/// term and metadata
+pub type SearchResults = Vec<SearchResult>;
+
+/// Structured metadata parsed from `key=value;key2=value2` pairs, in written order
+pub type MetadataFields = Vec<(String, String)>;
+
+/// How the metadata column of a needles file is interpreted
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataFormat {
+    /// Metadata is an opaque string
+    #[default]
+    Plain,
+    /// Metadata is a list of `key=value` pairs separated by `;`
+    Kv,
+}
+
+/// Which needles a stop-word list drops
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum StopwordMode {
+    /// Needles whose whole term is a stop word
+    #[default]
+    Exact,
+    /// Also needles made up only of stop words, such as "the board"
+    Contains,
+}
+
+/// The character separating the term from the metadata in a needles file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NeedleDelimiter(char);
+
+impl NeedleDelimiter {
+    pub const COMMA: NeedleDelimiter = NeedleDelimiter(',');
+
+    /// The delimiters auto-detection chooses between
+    pub const CANDIDATES: [NeedleDelimiter; 4] = [
+        NeedleDelimiter(','),
+        NeedleDelimiter(';'),
+        NeedleDelimiter('\t'),
+        NeedleDelimiter('|'),
+    ];
+
+    pub fn char(self) -> char {
+        self.0
+    }
+}
+
+impl Default for NeedleDelimiter {
+    fn default() -> Self {
+        Self::COMMA
+    }
+}
+
+impl std::str::FromStr for NeedleDelimiter {
+    type Err = String;
+
+    /// A single character, or `\t` or `tab` for a tab. Quotes, letters,
+    /// digits, spaces and line breaks are refused.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let delimiter = match value {
+            "\\t" | "tab" => '\t',
+            _ => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(format!("expected one character or \\t, got {:?}", value)),
+                }
+            }
+        };
+        let refused = delimiter == '"'
+            || delimiter == ' '
+            || delimiter.is_alphanumeric()
+            || (delimiter.is_control() && delimiter != '\t');
+        if refused {
+            return Err(format!("{:?} cannot separate needle fields", delimiter));
+        }
+        Ok(NeedleDelimiter(delimiter))
+    }
+}
+
+impl fmt::Display for NeedleDelimiter {
+    /// As accepted by `--needle-delimiter`: a tab is written `\t`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            '\t' => write!(f, "\\t"),
+            c => write!(f, "{}", c),
+        }
+    }
+}
+
+impl TryFrom<String> for NeedleDelimiter {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<NeedleDelimiter> for String {
+    fn from(delimiter: NeedleDelimiter) -> Self {
+        delimiter.to_string()
+    }
+}
+
+/// Matching behaviour requested on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub ignore_accents: bool,
+    /// Compose needles and text to Unicode NFC before comparing them; on
+    /// unless `--no-normalize` is given
+    pub normalize: bool,
+    /// Treat every needle as a regular expression, not only those wrapped
+    /// in `/…/`
+    pub regex_needles: bool,
+    /// How case is ignored when the search is not case-sensitive
+    pub case_fold: CaseFolding,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            case_sensitive: false,
+            whole_word: false,
+            ignore_accents: false,
+            normalize: true,
+            regex_needles: false,
+            case_fold: CaseFolding::Unicode,
+        }
+    }
+}
+
+impl MatchOptions {
+    /// Which needles these options make regular expressions
+    pub fn regex(&self) -> RegexNeedles {
+        RegexNeedles {
+            all: self.regex_needles,
+            case_insensitive: !self.case_sensitive,
+        }
+    }
+}
+
+impl std::fmt::Display for MatchOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "case_sensitive={}, whole_word={}, ignore_accents={}, normalize={}, regex_needles={}, case_fold={:?}",
+            self.case_sensitive, self.whole_word, self.ignore_accents, self.normalize, self.regex_needles, self.case_fold
+        )
+    }
+}
+
+/// How text is extracted from documents and searched: content searched in
+/// addition to the visible text, how a document that fails to parse is
+/// retried, and how needles are looked up in the text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Search DOCX hyperlink targets (such as `mailto:` addresses)
+    pub include_link_targets: bool,
+    /// Search DOCX runs formatted as hidden text
+    pub include_hidden_text: bool,
+    /// Search the title, author, subject and keywords of a DOCX or ODT
+    /// document, and the creator and producer of a PDF too
+    pub include_metadata: bool,
+    /// When a document fails to parse as the type its extension names,
+    /// parse it once more as the type its magic bytes indicate
+    pub retry_detected_type: bool,
+    /// How the needles are looked up, as resolved from `--matcher`
+    pub strategy: Strategy,
+    /// Whether needles match in any case, from `--case-sensitive`
+    pub matching: MatchOptions,
+    /// Edits a run of words may be away from a needle and still match, from
+    /// `--fuzzy`; 0 for exact matches only
+    pub max_edits: usize,
+    /// Only match a `&&` needle where every part is in the same block, from
+    /// `--and-same-paragraph`, rather than anywhere in the document
+    pub and_same_block: bool,
+    /// Give each result the [`context`](SearchResult::context) it was found in
+    pub include_context: bool,
+    /// When a search of one document stops
+    pub limits: SearchLimits,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            include_link_targets: false,
+            include_hidden_text: false,
+            include_metadata: false,
+            retry_detected_type: true,
+            strategy: Strategy::default(),
+            matching: MatchOptions::default(),
+            max_edits: 0,
+            and_same_block: false,
+            include_context: true,
+            limits: SearchLimits::default(),
+        }
+    }
+}
+
+/// When a search of one document stops short of the whole document; by
+/// default it never does
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Stop once this many results have been found. A search that reads
+    /// the whole document first keeps the heaviest; one that streams it
+    /// stops reading after the result that reaches the limit.
+    pub max_results: Option<usize>,
+    /// Give up on a document whose search takes longer than this, with
+    /// [`DocSearchError::TimedOut`](crate::error::DocSearchError::TimedOut)
+    pub timeout: Option<Duration>,
+}
+
+impl SearchLimits {
+    /// When a search starting now has to give up
+    pub fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Whether `found` results are as many as the search may find
+    pub fn reached(&self, found: usize) -> bool {
+        self.max_results.is_some_and(|max| found >= max)
+    }
+}
+
+/// The options of a search through the library in one place: how needles
+/// match, what each result carries and when the search stops. Every search
+/// takes the [`ExtractOptions`] it [converts to](Self::extract_options).
+///
+/// [`SearchConfig::builder`] checks that the options can be combined:
+///
+/// ```
+/// use docsearcher::SearchConfig;
+///
+/// let config = SearchConfig::builder().case_sensitive(true).whole_word(true).max_results(100).build()?;
+/// assert_eq!(config.max_results, Some(100));
+/// assert!(SearchConfig::builder().fuzzy_distance(1).regex_needles(true).build().is_err());
+/// # Ok::<(), docsearcher::ConfigError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Edits a run of words may be away from a needle and still match;
+    /// `None` or 0 for exact matches only
+    pub fuzzy_distance: Option<u8>,
+    /// Treat every needle as a regular expression, not only those wrapped
+    /// in `/…/`
+    pub regex_needles: bool,
+    /// Give each result the [`context`](SearchResult::context) it was found in
+    pub include_context: bool,
+    /// Stop once this many results have been found
+    pub max_results: Option<usize>,
+    /// Give up on a document whose search takes longer than this
+    pub timeout: Option<Duration>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            case_sensitive: false,
+            whole_word: false,
+            fuzzy_distance: None,
+            regex_needles: false,
+            include_context: true,
+            max_results: None,
+            timeout: None,
+        }
+    }
+}
+
+impl SearchConfig {
+    /// A builder starting from the [default](Self::default) options: case
+    /// insensitive, not whole words, exact and without limits
+    pub fn builder() -> SearchConfigBuilder {
+        SearchConfigBuilder::default()
+    }
+
+    /// The options a search as this says takes, with every other option
+    /// left at its default
+    pub fn extract_options(&self) -> ExtractOptions {
+        ExtractOptions {
+            matching: MatchOptions {
+                case_sensitive: self.case_sensitive,
+                whole_word: self.whole_word,
+                regex_needles: self.regex_needles,
+                ..MatchOptions::default()
+            },
+            max_edits: self.fuzzy_distance.map_or(0, usize::from),
+            include_context: self.include_context,
+            limits: SearchLimits {
+                max_results: self.max_results,
+                timeout: self.timeout,
+            },
+            ..ExtractOptions::default()
+        }
+    }
+}
+
+/// Builds a [`SearchConfig`] an option at a time, from [`SearchConfig::builder`]
+#[derive(Clone, Debug, Default)]
+pub struct SearchConfigBuilder {
+    config: SearchConfig,
+}
+
+impl SearchConfigBuilder {
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.config.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn whole_word(mut self, whole_word: bool) -> Self {
+        self.config.whole_word = whole_word;
+        self
+    }
+
+    pub fn fuzzy_distance(mut self, edits: u8) -> Self {
+        self.config.fuzzy_distance = Some(edits);
+        self
+    }
+
+    pub fn regex_needles(mut self, regex_needles: bool) -> Self {
+        self.config.regex_needles = regex_needles;
+        self
+    }
+
+    pub fn include_context(mut self, include_context: bool) -> Self {
+        self.config.include_context = include_context;
+        self
+    }
+
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.config.max_results = Some(max_results);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// The configuration, unless its options cannot be combined
+    pub fn build(self) -> Result<SearchConfig, ConfigError> {
+        if self.config.fuzzy_distance.is_some_and(|edits| edits > 0) && self.config.regex_needles {
+            return Err(ConfigError::FuzzyRegexNeedles);
         }
+        Ok(self.config)
     }
 }
 
-/// Collection of search results
-pub type SearchResults = HashSet<SearchResult>;
+/// The kind of token a match was found in
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextKind {
+    /// Ordinary running text
+    Prose,
+    /// Part of an email address
+    Email,
+    /// Part of a URL
+    Url,
+    /// The target of a DOCX hyperlink, searched with `--include-link-targets`
+    #[serde(rename = "link-target")]
+    LinkTarget,
+    /// A DOCX run formatted as hidden, searched with `--include-hidden-text`
+    Hidden,
+    /// The author of a DOCX review comment
+    #[serde(rename = "comment-author")]
+    CommentAuthor,
+    /// A DOCX document property such as its title, searched with `--search-meta`
+    Metadata,
+}
+
+impl ContextKind {
+    /// Name used in structured output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContextKind::Prose => "prose",
+            ContextKind::Email => "email",
+            ContextKind::Url => "url",
+            ContextKind::LinkTarget => "link-target",
+            ContextKind::Hidden => "hidden",
+            ContextKind::CommentAuthor => "comment-author",
+            ContextKind::Metadata => "metadata",
+        }
+    }
+
+    /// Whether the match is in text a reader of the document does not see
+    /// as part of it, which includes who wrote a comment and its properties
+    pub fn is_concealed(&self) -> bool {
+        matches!(
+            self,
+            ContextKind::LinkTarget
+                | ContextKind::Hidden
+                | ContextKind::CommentAuthor
+                | ContextKind::Metadata
+        )
+    }
+}
+
+/// Separates the segments of a [`Source`] when it is written as one string,
+/// as in `dump.zip.gz!dump.zip!reports/q1.pdf`
+pub const SOURCE_SEPARATOR: &str = "!";
+
+/// Where a match was found: the file on disk, then the name of each archive
+/// entry leading from it to the searched document.
+///
+/// A document searched directly has a single segment, its path. Serialized
+/// as an array of segments.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Source(Vec<String>);
+
+impl Source {
+    /// A document searched directly from `path`
+    pub fn file(path: &Path) -> Self {
+        Source(vec![path.to_string_lossy().into_owned()])
+    }
+
+    /// The document reached from this one through archive `entries`
+    pub fn nested<I>(&self, entries: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut segments = self.0.clone();
+        segments.extend(entries.into_iter().map(Into::into));
+        Source(segments)
+    }
+
+    /// The on-disk path, then each entry name
+    pub fn segments(&self) -> &[String] {
+        &self.0
+    }
+
+    /// The file on disk the document was read from
+    pub fn outermost(&self) -> &Path {
+        Path::new(self.0.first().map_or("", String::as_str))
+    }
+
+    /// The archive entries below the on-disk file; empty for a document
+    /// searched directly
+    pub fn entries(&self) -> &[String] {
+        self.0.get(1..).unwrap_or_default()
+    }
+}
+
+/// The segments joined with [`SOURCE_SEPARATOR`]
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join(SOURCE_SEPARATOR))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_result_serializes_with_named_fields() {
+        let result = SearchResult::new("Zoë \"Z\" Ångström", "id=9;note=ext, 4410");
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "term": "Zoë \"Z\" Ångström", "metadata": "id=9;note=ext, 4410", "location": "body", "weight": 0, "occurrences": 1 })
+        );
+        assert_eq!(
+            serde_json::from_value::<SearchResult>(json).unwrap(),
+            result
+        );
+
+        let empty = SearchResult::new("Alice", "");
+        assert_eq!(
+            serde_json::from_str::<SearchResult>(&serde_json::to_string(&empty).unwrap()).unwrap(),
+            empty
+        );
+        assert!(serde_json::from_str::<SearchResult>(r#"{ "term": "Alice" }"#).is_err());
+        // Written before results were counted
+        assert_eq!(
+            serde_json::from_str::<SearchResult>(r#"{ "term": "Alice", "metadata": "" }"#).unwrap(),
+            empty
+        );
+        assert_eq!(
+            SearchResult::from(("Alice".to_string(), String::new())),
+            empty
+        );
+
+        let found = SearchResult {
+            page: Some(2),
+            context: Some("cc: Alice".to_string()),
+            ..SearchResult::new("Alice", "")
+        }
+        .in_file("memo.pdf");
+        let json = serde_json::to_value(&found).unwrap();
+        assert_eq!(
+            (&json["file"], &json["page"], &json["context"]),
+            (
+                &serde_json::json!("memo.pdf"),
+                &serde_json::json!(2),
+                &serde_json::json!("cc: Alice")
+            )
+        );
+        assert_eq!(serde_json::from_value::<SearchResult>(json).unwrap(), found);
+    }
+
+    #[test]
+    fn test_search_config_builder() {
+        assert_eq!(SearchConfig::builder().build(), Ok(SearchConfig::default()));
+        let config = SearchConfig::builder()
+            .case_sensitive(true)
+            .whole_word(true)
+            .max_results(100)
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert!(config.case_sensitive && config.whole_word && config.include_context);
+        assert_eq!(
+            (config.max_results, config.timeout, config.fuzzy_distance),
+            (Some(100), Some(Duration::from_secs(5)), None)
+        );
+
+        // Regular expressions are never matched approximately
+        assert_eq!(
+            SearchConfig::builder()
+                .fuzzy_distance(2)
+                .regex_needles(true)
+                .build(),
+            Err(ConfigError::FuzzyRegexNeedles)
+        );
+        // A distance of 0 is exact matching, which regular expressions allow
+        let exact = SearchConfig::builder()
+            .regex_needles(true)
+            .fuzzy_distance(0)
+            .build()
+            .unwrap();
+        assert_eq!(exact.extract_options().max_edits, 0);
+        assert!(SearchConfig::builder().fuzzy_distance(2).build().is_ok());
+        assert!(SearchConfig::builder().regex_needles(true).build().is_ok());
+    }
+}
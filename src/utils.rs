@@ -1,88 +1,1203 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::str::from_utf8;
 
+use nom::branch::alt;
 use nom::bytes::complete::*;
 use nom::character::complete::*;
-use nom::sequence::separated_pair;
+use nom::combinator::map;
 use nom::IResult;
 
-use anyhow::{Result, Context};
+use anyhow::{Context, Result};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::types::{FileType, Needle};
+use crate::error::DocSearchError;
+use crate::matcher::RegexNeedles;
+use crate::output::warnln;
+use crate::parsers::{doc::is_legacy_doc, rtf::is_rtf};
+use crate::types::{
+    FileType, MetadataFields, NeedleDelimiter, NeedleWeights, OwnedNeedle, StopwordMode,
+};
 
-/// Parse a contact line in the format "search_term,metadata"
-pub fn parse_contact(input: &str) -> IResult<&str, Needle> {
-    let (input, _) = nom::character::complete::space0(input)?;
-    let (input, result) = parse_contact_line(input)?;
-    let (input, _) = nom::character::complete::space0(input)?;
-    
-    Ok((input, (result.0.trim(), result.1.trim())))
+/// Parse a needle line in the format `search_term<delimiter>metadata`.
+///
+/// Either field may be wrapped in double quotes, with `""` standing for a
+/// quote inside it, so a quoted term can contain the delimiter. Unquoted
+/// metadata is everything after the first delimiter, less a trailing
+/// weight column (see [`parse_weighted_contact`]).
+pub fn parse_contact(
+    input: &str,
+    delimiter: NeedleDelimiter,
+) -> IResult<&str, (Cow<'_, str>, Cow<'_, str>)> {
+    let (input, (term, metadata, _)) = parse_weighted_contact(input, delimiter)?;
+    Ok((input, (term, metadata)))
 }
 
-fn parse_contact_line(input: &str) -> IResult<&str, Needle> {
-    separated_pair(is_not(","), char(','), is_not("\n"))(input)
+/// Parse a needle line like [`parse_contact`], together with its weight: an
+/// optional last field `<delimiter>weight` holding a whole number, written
+/// right after the delimiter, as in `Alice Johnson,alice@x.com,10`. A line
+/// without one weighs 0.
+pub fn parse_weighted_contact(
+    input: &str,
+    delimiter: NeedleDelimiter,
+) -> IResult<&str, (Cow<'_, str>, Cow<'_, str>, i64)> {
+    let delimiter = delimiter.char();
+    let (input, _) = spaces(input)?;
+    let (input, term) = alt((
+        quoted_field,
+        map(take_till1(|c| c == delimiter), |term: &str| {
+            Cow::Borrowed(term.trim())
+        }),
+    ))(input)?;
+    let (input, _) = spaces(input)?;
+    let (input, _) = char(delimiter)(input)?;
+    let (input, metadata) = is_not("\n")(input)?;
+
+    let (metadata, weight) = split_weight(metadata.trim(), delimiter);
+    let metadata = match quoted_field(metadata) {
+        Ok(("", unquoted)) => unquoted,
+        _ => Cow::Borrowed(metadata),
+    };
+    Ok((input, (term, metadata, weight)))
 }
 
-/// Read search terms from a file
-pub fn read_needles_from_file(path: &str) -> Result<Vec<(String, String)>> {
-    let mut file = File::open(path)
-        .with_context(|| format!("Failed to open needles file: {}", path))?;
-    
-    let mut content = String::new();
-    file.read_to_string(&mut content)
-        .with_context(|| format!("Failed to read needles file: {}", path))?;
-    
-    read_needles_from_string(&content)
+/// Split the weight column off the end of a metadata field. The text after
+/// its last `delimiter` is the weight if it is a whole number with nothing
+/// around it, so `note=ext, 4410` keeps its number.
+fn split_weight(field: &str, delimiter: char) -> (&str, i64) {
+    let Some((metadata, weight)) = field.rsplit_once(delimiter) else {
+        return (field, 0);
+    };
+    let digits = weight.strip_prefix('-').unwrap_or(weight);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return (field, 0);
+    }
+    match weight.parse() {
+        Ok(weight) => (metadata.trim_end(), weight),
+        Err(_) => (field, 0),
+    }
+}
+
+/// Spaces around a field; unlike `space0`, tabs are left for a tab delimiter
+fn spaces(input: &str) -> IResult<&str, &str> {
+    take_while(|c| c == ' ')(input)
+}
+
+/// A double-quoted field, with `""` standing for a quote inside it
+fn quoted_field(input: &str) -> IResult<&str, Cow<'_, str>> {
+    let (mut input, _) = char('"')(input)?;
+    let mut field = String::new();
+    loop {
+        let (rest, chunk) = take_till(|c| c == '"')(input)?;
+        let (rest, _) = char('"')(rest)?;
+        field.push_str(chunk);
+        match rest.strip_prefix('"') {
+            Some(rest) => {
+                field.push('"');
+                input = rest;
+            }
+            None => return Ok((rest, Cow::Owned(field))),
+        }
+    }
+}
+
+/// Needle lines [`detect_delimiter`] looks at
+const DELIMITER_SAMPLE_LINES: usize = 20;
+
+/// Guess the delimiter of a needles file from its first needle lines.
+///
+/// A candidate (`,` `;` tab `|`) has to appear outside quotes on every
+/// sampled line; of those, the one that comes first on the most lines wins,
+/// so the `;` between `key=value` pairs loses to the comma before them
+/// while a comma inside some terms loses to a `;` found on every line.
+/// Files where nothing qualifies are read as comma-separated.
+pub fn detect_delimiter(content: &str) -> NeedleDelimiter {
+    let lines: Vec<Vec<Option<usize>>> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .take(DELIMITER_SAMPLE_LINES)
+        .map(|line| {
+            let mut quoted = false;
+            let mut first = [None; NeedleDelimiter::CANDIDATES.len()];
+            for (i, c) in line.char_indices() {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                if let Some(candidate) = NeedleDelimiter::CANDIDATES
+                    .iter()
+                    .position(|candidate| candidate.char() == c)
+                {
+                    if !quoted && first[candidate].is_none() {
+                        first[candidate] = Some(i);
+                    }
+                }
+            }
+            first.to_vec()
+        })
+        .collect();
+
+    let plausible: Vec<usize> = (0..NeedleDelimiter::CANDIDATES.len())
+        .filter(|&candidate| {
+            !lines.is_empty() && lines.iter().all(|line| line[candidate].is_some())
+        })
+        .collect();
+    let leads = |candidate: usize| {
+        lines
+            .iter()
+            .filter(|line| {
+                plausible
+                    .iter()
+                    .all(|&other| line[candidate] <= line[other])
+            })
+            .count()
+    };
+
+    // `max_by_key` keeps the last maximum; reversing prefers earlier candidates on ties
+    plausible
+        .iter()
+        .rev()
+        .max_by_key(|&&candidate| leads(candidate))
+        .map_or(NeedleDelimiter::COMMA, |&candidate| {
+            NeedleDelimiter::CANDIDATES[candidate]
+        })
+}
+
+/// Line counts from reading a needles file; displays the lines that
+/// held no needle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NeedleFileStats {
+    pub needles: usize,
+    pub comments: usize,
+    pub blank: usize,
+    pub malformed: usize,
+    /// The delimiter the file was read with, given or detected
+    pub delimiter: NeedleDelimiter,
+}
+
+impl std::fmt::Display for NeedleFileStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} comment, {} blank and {} malformed lines",
+            self.comments, self.blank, self.malformed
+        )
+    }
+}
+
+/// Read search terms from a file, splitting lines at `delimiter`, or at the
+/// one [`detect_delimiter`] finds when `None`
+pub fn read_needles_from_file(
+    path: &str,
+    delimiter: Option<NeedleDelimiter>,
+) -> Result<Vec<(String, String)>, DocSearchError> {
+    let (needles, stats) = read_needles_with_stats(path, delimiter)?;
+    require_needles(needles, stats)
+}
+
+/// Read search terms from a file, counting the lines that held none.
+///
+/// Unlike [`read_needles_from_file`], a file without needles is not an error.
+pub fn read_needles_with_stats(
+    path: &str,
+    delimiter: Option<NeedleDelimiter>,
+) -> Result<(Vec<(String, String)>, NeedleFileStats), DocSearchError> {
+    Ok(scan_needles(&read_needles_file(path)?, delimiter))
 }
 
 /// Read search terms from a byte slice
-pub fn read_needles_from_mem(bytes: &[u8]) -> Result<Vec<(String, String)>> {
+pub fn read_needles_from_mem(
+    bytes: &[u8],
+    delimiter: Option<NeedleDelimiter>,
+) -> Result<Vec<(String, String)>, DocSearchError> {
     let content = from_utf8(bytes)
-        .with_context(|| "Failed to parse needles content as UTF-8")?;
-    
-    read_needles_from_string(content)
+        .context("Failed to parse needles content as UTF-8")
+        .map_err(DocSearchError::from)?;
+
+    read_needles_from_string(content, delimiter)
+}
+
+/// The contents of the needles file at `path`
+fn read_needles_file(path: &str) -> Result<String, DocSearchError> {
+    std::fs::read_to_string(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => DocSearchError::FileNotFound(PathBuf::from(path)),
+        kind => DocSearchError::IoError(io::Error::new(
+            kind,
+            format!("Failed to read needles file {}: {}", path, e),
+        )),
+    })
+}
+
+fn read_needles_from_string(
+    content: &str,
+    delimiter: Option<NeedleDelimiter>,
+) -> Result<Vec<(String, String)>, DocSearchError> {
+    let (needles, stats) = scan_needles(content, delimiter);
+    require_needles(needles, stats)
+}
+
+fn require_needles(
+    needles: Vec<(String, String)>,
+    stats: NeedleFileStats,
+) -> Result<Vec<(String, String)>, DocSearchError> {
+    if needles.is_empty() {
+        return Err(DocSearchError::NeedlesEmpty(stats));
+    }
+
+    Ok(needles)
 }
 
-fn read_needles_from_string(content: &str) -> Result<Vec<(String, String)>> {
+fn scan_needles(
+    content: &str,
+    delimiter: Option<NeedleDelimiter>,
+) -> (Vec<(String, String)>, NeedleFileStats) {
+    let delimiter = delimiter.unwrap_or_else(|| detect_delimiter(content));
     let mut needles = Vec::new();
-    
+    let mut stats = NeedleFileStats {
+        delimiter,
+        ..NeedleFileStats::default()
+    };
+
     for (line_num, line) in content.lines().enumerate() {
         let line = line.trim();
-        
-        if line.is_empty() || line.starts_with('#') {
+
+        if line.is_empty() {
+            stats.blank += 1;
             continue;
         }
-        
-        match parse_contact(line) {
-            Ok((_, needle)) => {
-                needles.push((needle.0.to_string(), needle.1.to_string()));
+        if line.starts_with('#') {
+            stats.comments += 1;
+            continue;
+        }
+
+        match parse_contact(line, delimiter) {
+            Ok((_, (term, metadata))) => {
+                needles.push((term.into_owned(), metadata.into_owned()));
             }
             Err(_) => {
-                eprintln!("Warning: Failed to parse line {}: '{}'", line_num + 1, line);
+                stats.malformed += 1;
+                warnln!("Warning: Failed to parse line {}: '{}'", line_num + 1, line);
             }
         }
     }
-    
-    if needles.is_empty() {
-        return Err(anyhow::anyhow!("No valid search terms found in input"));
+
+    stats.needles = needles.len();
+    (needles, stats)
+}
+
+/// Separates the parts of a needle that only matches where all of them do
+pub const CONJUNCTION: &str = "&&";
+
+/// Introduces the word distance of a proximity needle, as in `NEAR/5`
+pub const NEAR: &str = "NEAR/";
+
+/// How the term of a needle is matched
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NeedleQuery<'a> {
+    /// The term as written
+    Term(&'a str),
+    /// `Project Falcon && budget overrun`: every part, trimmed
+    All(Vec<&'a str>),
+    /// `Smith NEAR/5 invoice`: both parts, at most `within` words apart
+    Near { parts: [&'a str; 2], within: usize },
+}
+
+impl<'a> NeedleQuery<'a> {
+    /// Parse `term`. A term with an empty part, such as `Smith &&` or
+    /// `NEAR/5 invoice`, or with `NEAR/0`, is an ordinary term.
+    pub fn parse(term: &'a str) -> Self {
+        let parts: Vec<&str> = term.split(CONJUNCTION).map(str::trim).collect();
+        if parts.len() > 1 && parts.iter().all(|part| !part.is_empty()) {
+            return NeedleQuery::All(parts);
+        }
+        for (start, _) in term.match_indices(NEAR) {
+            let rest = &term[start + NEAR.len()..];
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let (left, right) = (&term[..start], &rest[digits..]);
+            let separated =
+                left.ends_with(char::is_whitespace) && right.starts_with(char::is_whitespace);
+            match rest[..digits].parse() {
+                Ok(within)
+                    if within > 0
+                        && separated
+                        && !left.trim().is_empty()
+                        && !right.trim().is_empty() =>
+                {
+                    return NeedleQuery::Near {
+                        parts: [left.trim(), right.trim()],
+                        within,
+                    };
+                }
+                _ => {}
+            }
+        }
+        NeedleQuery::Term(term)
     }
-    
-    Ok(needles)
+
+    /// The parts looked up on their own
+    pub fn parts(&self) -> Vec<&'a str> {
+        match self {
+            NeedleQuery::Term(term) => vec![term],
+            NeedleQuery::All(parts) => parts.clone(),
+            NeedleQuery::Near { parts, .. } => parts.to_vec(),
+        }
+    }
+}
+
+/// The weights of the needles of a needles file that have one; needles not
+/// in the map weigh 0. A needle listed twice keeps its first weight.
+pub fn needle_weights(content: &str, delimiter: Option<NeedleDelimiter>) -> NeedleWeights {
+    let delimiter = delimiter.unwrap_or_else(|| detect_delimiter(content));
+    let mut weights = NeedleWeights::new();
+    for line in content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        if let Ok((_, (term, metadata, weight))) = parse_weighted_contact(line, delimiter) {
+            if weight != 0 {
+                weights
+                    .entry((term.into_owned(), metadata.into_owned()))
+                    .or_insert(weight);
+            }
+        }
+    }
+    weights
+}
+
+/// [`needle_weights`] for the needles file at `path`
+pub fn read_needle_weights(
+    path: &str,
+    delimiter: Option<NeedleDelimiter>,
+) -> Result<NeedleWeights, DocSearchError> {
+    Ok(needle_weights(&read_needles_file(path)?, delimiter))
+}
+
+/// Check that every needle of a needles file that is a regular expression
+/// compiles, naming the line of the first that doesn't. The error is a
+/// [`DocSearchError::InvalidNeedles`] underneath, which library callers get.
+pub fn check_regex_needles(
+    content: &str,
+    delimiter: Option<NeedleDelimiter>,
+    regex: RegexNeedles,
+) -> Result<()> {
+    let delimiter = delimiter.unwrap_or_else(|| detect_delimiter(content));
+    for (line_num, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Ok((_, (term, _))) = parse_contact(line, delimiter) else {
+            continue;
+        };
+        for part in NeedleQuery::parse(&term).parts() {
+            if let Some(pattern) = regex.pattern(part) {
+                regex.compile(pattern).map_err(|err| {
+                    let invalid = DocSearchError::InvalidNeedles {
+                        line: line_num + 1,
+                        content: part.to_string(),
+                    };
+                    anyhow::Error::new(invalid).context(format!(
+                        "Invalid regular expression on line {} ('{}'):\n{}",
+                        line_num + 1,
+                        part,
+                        err
+                    ))
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// [`check_regex_needles`] for the needles file at `path`, which is only
+/// read again when one of its `needles` is a regular expression
+pub fn check_regex_needles_in_file(
+    path: &str,
+    needles: &[(String, String)],
+    delimiter: Option<NeedleDelimiter>,
+    regex: RegexNeedles,
+) -> Result<()> {
+    if needles.iter().all(|(term, _)| {
+        NeedleQuery::parse(term)
+            .parts()
+            .iter()
+            .all(|part| regex.pattern(part).is_none())
+    }) {
+        return Ok(());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read needles file: {}", path))?;
+    check_regex_needles(&content, delimiter, regex).map_err(|err| {
+        let message = format!("{}: {}", path, err);
+        err.context(message)
+    })
+}
+
+/// Parse file type from a file path: the type its extension names or, for
+/// an extension that names none or no extension at all, the type the magic
+/// bytes at the start of the file name; see [`detect_filetype_from_bytes`]
+pub fn parse_filetype(file_path: impl AsRef<Path>) -> Result<FileType, DocSearchError> {
+    let path = file_path.as_ref();
+    let name = path.to_string_lossy();
+    filetype_from_extension(&name).or_else(|unsupported| match read_file_header(path, SNIFF_LEN) {
+        Ok(header) => detect_filetype_from_bytes(&header, Some(&name)),
+        Err(_) => Err(unsupported),
+    })
+}
+
+/// The file type the extension of `file_path` names, in any case, so
+/// `REPORT.PDF` is a PDF like `report.pdf`
+pub fn filetype_from_extension(file_path: &str) -> Result<FileType, DocSearchError> {
+    let extension = Path::new(file_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("docx") => Ok(FileType::Docx),
+        Some("pdf") => Ok(FileType::Pdf),
+        Some("doc") => Ok(FileType::Doc),
+        Some("xlsx") => Ok(FileType::Xlsx),
+        Some("odt") => Ok(FileType::Odt),
+        Some("txt") => Ok(FileType::Txt),
+        Some("rtf") => Ok(FileType::Rtf),
+        Some("epub") => Ok(FileType::Epub),
+        _ => Err(DocSearchError::UnsupportedFormat(file_path.to_string())),
+    }
+}
+
+/// The file type of a document from its leading bytes, falling back to the
+/// extension of `hint_path` when they carry no magic number.
+///
+/// A ZIP archive is told apart by its entries: `word/` for DOCX, `xl/` for
+/// XLSX and the `mimetype` entry for ODT and EPUB. When `header` ends before
+/// any of those, a ZIP-based extension on `hint_path` settles it; otherwise
+/// the type is ambiguous and an error.
+pub fn detect_filetype_from_bytes(
+    header: &[u8],
+    hint_path: Option<&str>,
+) -> Result<FileType, DocSearchError> {
+    let hinted = hint_path.and_then(|path| filetype_from_extension(path).ok());
+    let unsupported =
+        || DocSearchError::UnsupportedFormat(hint_path.unwrap_or_default().to_string());
+    match sniff_document_type(header) {
+        Some(FileType::Docx) => zip_document_type(header)
+            .or(hinted.filter(|file_type| {
+                matches!(
+                    file_type,
+                    FileType::Docx | FileType::Xlsx | FileType::Odt | FileType::Epub
+                )
+            }))
+            .ok_or_else(unsupported),
+        Some(file_type) => Ok(file_type),
+        None => hinted.ok_or_else(unsupported),
+    }
+}
+
+/// The document type of the ZIP archive starting `header`, from the local
+/// file headers it holds; `None` when none of them gives it away
+fn zip_document_type(header: &[u8]) -> Option<FileType> {
+    let mut rest = header;
+    while let Some(entry) = rest.strip_prefix(b"PK\x03\x04") {
+        let field = |at: usize| {
+            entry
+                .get(at..at + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+        };
+        let (flags, name_len, extra_len) = (field(2)?, field(22)?, field(24)?);
+        let compressed = entry
+            .get(14..18)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)?;
+        let name = entry.get(26..26 + name_len)?;
+        let data = 26 + name_len + extra_len;
+
+        if name == b"mimetype" {
+            let mime = entry.get(data..data + compressed)?;
+            return [FileType::Odt, FileType::Epub]
+                .into_iter()
+                .find(|file_type| file_type.mime_type().as_bytes() == mime);
+        } else if name.starts_with(b"word/") {
+            return Some(FileType::Docx);
+        } else if name.starts_with(b"xl/") {
+            return Some(FileType::Xlsx);
+        }
+        // With a data descriptor the sizes follow the data, so the next
+        // entry cannot be found
+        if flags & 0x8 != 0 {
+            return None;
+        }
+        rest = entry.get(data + compressed..)?;
+    }
+    None
+}
+
+/// Number of leading bytes inspected when sniffing file contents
+const SNIFF_LEN: usize = 4096;
+
+/// Read up to `len` leading bytes of a file
+pub fn read_file_header(path: &Path, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(len);
+    File::open(path)?
+        .take(len as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Identify a document from its leading magic bytes
+pub fn sniff_document_type(header: &[u8]) -> Option<FileType> {
+    if header.starts_with(b"%PDF") {
+        Some(FileType::Pdf)
+    } else if header.starts_with(b"PK\x03\x04") {
+        Some(FileType::Docx)
+    } else if is_legacy_doc(header) {
+        Some(FileType::Doc)
+    } else if is_rtf(header) {
+        Some(FileType::Rtf)
+    } else {
+        None
+    }
+}
+
+/// Whether a byte prefix looks like UTF-8 text rather than binary data.
+///
+/// A multi-byte character cut off at the end of the prefix is tolerated.
+pub fn looks_like_text(header: &[u8]) -> bool {
+    if header.contains(&0) {
+        return false;
+    }
+
+    match from_utf8(header) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Whether a byte prefix looks like a needles file: text whose first
+/// meaningful line is a `term,metadata` pair
+pub fn looks_like_needles(header: &[u8]) -> bool {
+    if !looks_like_text(header) {
+        return false;
+    }
+
+    String::from_utf8_lossy(header)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .is_some_and(|line| parse_contact(line, detect_delimiter(line)).is_ok())
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Detect a needles file and document passed in the wrong order.
+///
+/// The needles path is suspicious when it has a document extension or
+/// document magic bytes; the document path is suspicious when it has a
+/// needles extension or is text shaped like `term,metadata` lines. Either
+/// condition produces an error suggesting the arguments are swapped.
+pub fn check_swapped_arguments(needles: &Path, document: &Path) -> Result<()> {
+    let needles_header = read_file_header(needles, SNIFF_LEN).unwrap_or_default();
+    let document_header = read_file_header(document, SNIFF_LEN).unwrap_or_default();
+
+    // Needles files are text too, so a `.txt` extension says nothing either way
+    let needles_is_document = parse_filetype(needles)
+        .is_ok_and(|file_type| file_type != FileType::Txt)
+        || sniff_document_type(&needles_header).is_some();
+    let document_is_needles = sniff_document_type(&document_header).is_none()
+        && (has_extension(document, &["csv", "nidx"])
+            || (!has_extension(document, &["txt"]) && looks_like_needles(&document_header)));
+
+    let problem = match (needles_is_document, document_is_needles) {
+        (true, true) => format!(
+            "'{}' looks like a document and '{}' looks like a needles file",
+            needles.display(),
+            document.display()
+        ),
+        (true, false) => format!(
+            "The needles file '{}' looks like a document, not a term,metadata list",
+            needles.display()
+        ),
+        (false, true) => format!(
+            "The document '{}' looks like a term,metadata needles file",
+            document.display()
+        ),
+        (false, false) => return Ok(()),
+    };
+
+    Err(anyhow::anyhow!(
+        "{}. The arguments may be swapped: the needles file comes first, e.g. \
+         `docsearcher search {} {}` (pass --swap-ok to skip this check)",
+        problem,
+        document.display(),
+        needles.display()
+    ))
+}
+
+/// Parse metadata of the form `key=value;key2=value2` into ordered fields.
+///
+/// Keys and values may be wrapped in double quotes to contain `;` or `=`,
+/// with `""` standing for a literal quote inside a quoted section.
+pub fn parse_metadata_kv(metadata: &str) -> Result<MetadataFields> {
+    let mut fields = Vec::new();
+    let mut chars = metadata.chars().peekable();
+
+    loop {
+        let key = read_kv_token(&mut chars, &['=', ';'])?;
+        match chars.next() {
+            Some('=') => {}
+            _ if key.trim().is_empty() => {
+                if chars.peek().is_none() {
+                    break;
+                }
+                continue;
+            }
+            _ => return Err(anyhow::anyhow!("Missing '=' after key '{}'", key.trim())),
+        }
+
+        let value = read_kv_token(&mut chars, &[';'])?;
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            return Err(anyhow::anyhow!("Empty key in metadata pair"));
+        }
+        fields.push((key, value.trim().to_string()));
+
+        if chars.next().is_none() {
+            break;
+        }
+    }
+
+    Ok(fields)
 }
 
-/// Parse file type from a file path
-pub fn parse_filetype(file_path: &str) -> Result<FileType> {
-    if file_path.ends_with(".docx") {
-        Ok(FileType::Docx)
-    } else if file_path.ends_with(".pdf") {
-        Ok(FileType::Pdf)
+fn read_kv_token(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    terminators: &[char],
+) -> Result<String> {
+    let mut token = String::new();
+    let mut quoted = false;
+
+    while let Some(&c) = chars.peek() {
+        if quoted {
+            chars.next();
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    token.push('"');
+                } else {
+                    quoted = false;
+                }
+            } else {
+                token.push(c);
+            }
+        } else if terminators.contains(&c) {
+            break;
+        } else {
+            chars.next();
+            if c == '"' {
+                quoted = true;
+            } else {
+                token.push(c);
+            }
+        }
+    }
+
+    if quoted {
+        return Err(anyhow::anyhow!("Unterminated quote in metadata"));
+    }
+
+    Ok(token)
+}
+
+/// Parse a `key=value` filter as given to `--where`
+pub fn parse_where_clause(clause: &str) -> Result<(String, String)> {
+    match parse_metadata_kv(clause)?.as_slice() {
+        [(key, value)] => Ok((key.clone(), value.clone())),
+        _ => Err(anyhow::anyhow!(
+            "Expected a single key=value filter, got: {}",
+            clause
+        )),
+    }
+}
+
+/// Collect the union of metadata keys across needles, in first-seen order.
+///
+/// Needles whose metadata is not valid `key=value` pairs keep their raw
+/// string and are reported with a warning.
+pub fn metadata_keys(needles: &[(String, String)]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+
+    for (term, metadata) in needles {
+        match parse_metadata_kv(metadata) {
+            Ok(fields) => {
+                for (key, _) in fields {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+            Err(e) => {
+                warnln!(
+                    "Warning: Metadata for '{}' is not key=value pairs ({}), keeping raw string",
+                    term,
+                    e
+                );
+            }
+        }
+    }
+
+    keys
+}
+
+/// Terms no one means to search for, read with `--stopwords`.
+///
+/// A stop-word file has one term per line; blank lines and lines starting
+/// with `#` are skipped, as in needles files. Terms compare case-folded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stopwords {
+    words: HashSet<String>,
+    mode: StopwordMode,
+}
+
+impl Stopwords {
+    /// Read a stop-word file
+    pub fn read(path: &Path, mode: StopwordMode) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read stop-word file: {}", path.display()))?;
+        Ok(Self::parse(&content, mode))
+    }
+
+    /// Stop words from the lines of `content`
+    pub fn parse(content: &str, mode: StopwordMode) -> Self {
+        let words = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase)
+            .collect();
+        Self { words, mode }
+    }
+
+    /// Number of distinct stop words
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether the needle `term` is dropped: it is a stop word or, in
+    /// `contains` mode, every word of it is
+    pub fn drops(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        if self.words.contains(term.trim()) {
+            return true;
+        }
+
+        let mut words = term
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .peekable();
+        self.mode == StopwordMode::Contains
+            && words.peek().is_some()
+            && words.all(|word| self.words.contains(word))
+    }
+}
+
+/// Split `needles` into those kept and those `stopwords` drops, each in
+/// their original order
+pub fn filter_stopwords(
+    needles: Vec<OwnedNeedle>,
+    stopwords: &Stopwords,
+) -> (Vec<OwnedNeedle>, Vec<OwnedNeedle>) {
+    needles
+        .into_iter()
+        .partition(|(term, _)| !stopwords.drops(term))
+}
+
+/// Keep only the needles whose metadata contains every `key=value` filter
+pub fn filter_needles_by_metadata(
+    needles: Vec<(String, String)>,
+    filters: &[(String, String)],
+) -> Vec<(String, String)> {
+    if filters.is_empty() {
+        return needles;
+    }
+
+    needles
+        .into_iter()
+        .filter(|(_, metadata)| {
+            parse_metadata_kv(metadata).is_ok_and(|fields| {
+                filters
+                    .iter()
+                    .all(|filter| fields.iter().any(|field| field == filter))
+            })
+        })
+        .collect()
+}
+
+/// A calendar date, `YYYY-MM-DD`, without a time of day or time zone.
+///
+/// Dates compare as calendar days: a needle valid until 2024-06-01 is
+/// active for the whole of that day wherever the search runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: i32,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        let valid = (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day);
+        valid.then_some(Date { year, month, day })
+    }
+
+    /// Today's date in UTC
+    pub fn today() -> Self {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self::from_days_since_epoch((secs / 86_400) as i64)
+    }
+
+    /// The date `days` days after 1970-01-01
+    fn from_days_since_epoch(days: i64) -> Self {
+        // Civil-from-days, counting 400-year eras from 0000-03-01
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        } as u32;
+        let year = (year_of_era + era * 400 + i64::from(month <= 2)) as i32;
+        Date { year, month, day }
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+impl std::str::FromStr for Date {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Not a YYYY-MM-DD date: {}", text);
+        let parts: Vec<&str> = text.trim().split('-').collect();
+        let [year, month, day] = parts.as_slice() else {
+            return Err(invalid());
+        };
+        if year.len() != 4
+            || month.len() != 2
+            || day.len() != 2
+            || !parts
+                .iter()
+                .all(|part| part.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+
+        Date::new(year.parse()?, month.parse()?, day.parse()?).ok_or_else(invalid)
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl serde::Serialize for Date {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Metadata keys holding the first and last day a needle is searched for
+pub const VALID_FROM: &str = "valid_from";
+pub const VALID_UNTIL: &str = "valid_until";
+
+/// The days a needle is active, both ends included; `None` leaves that end open
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Validity {
+    pub from: Option<Date>,
+    pub until: Option<Date>,
+}
+
+impl Validity {
+    pub fn contains(&self, date: Date) -> bool {
+        self.from.is_none_or(|from| from <= date) && self.until.is_none_or(|until| date <= until)
+    }
+}
+
+/// Read a needle's `valid_from` and `valid_until` metadata.
+///
+/// Metadata that is not `key=value` pairs, or lacks both keys, is valid on
+/// every day. A date that does not parse, or a range that ends before it
+/// starts, is an error.
+pub fn needle_validity(metadata: &str) -> Result<Validity> {
+    let Ok(fields) = parse_metadata_kv(metadata) else {
+        return Ok(Validity::default());
+    };
+
+    let date = |key: &str| -> Result<Option<Date>> {
+        match fields.iter().find(|(field, _)| field == key) {
+            Some((_, value)) => value
+                .parse()
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("{}: {}", key, e)),
+            None => Ok(None),
+        }
+    };
+    let validity = Validity {
+        from: date(VALID_FROM)?,
+        until: date(VALID_UNTIL)?,
+    };
+
+    if let (Some(from), Some(until)) = (validity.from, validity.until) {
+        if until < from {
+            return Err(anyhow::anyhow!(
+                "{} {} is after {} {}",
+                VALID_FROM,
+                from,
+                VALID_UNTIL,
+                until
+            ));
+        }
+    }
+
+    Ok(validity)
+}
+
+/// Keep only the needles active on `as_of`, returning them and the number
+/// dropped. Needles with malformed dates are kept; `validate` reports them.
+pub fn filter_needles_by_date(
+    needles: Vec<(String, String)>,
+    as_of: Date,
+) -> (Vec<(String, String)>, usize) {
+    let total = needles.len();
+    let active: Vec<_> = needles
+        .into_iter()
+        .filter(|(_, metadata)| {
+            needle_validity(metadata).map_or(true, |validity| validity.contains(as_of))
+        })
+        .collect();
+    let inactive = total - active.len();
+
+    (active, inactive)
+}
+
+/// Needles whose validity dates are malformed or inverted, with the problem
+pub fn validity_problems(needles: &[(String, String)]) -> Vec<(String, String)> {
+    needles
+        .iter()
+        .filter_map(|(term, metadata)| {
+            needle_validity(metadata)
+                .err()
+                .map(|e| (term.clone(), e.to_string()))
+        })
+        .collect()
+}
+
+/// 64-bit FNV-1a hash, stable across builds and platforms
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Device names Windows reserves regardless of extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Make `name` a valid file name on Windows, macOS and Linux.
+///
+/// Reserved and control characters become `_`, trailing dots and spaces
+/// are dropped, and Windows device names such as `CON` are prefixed with `_`.
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "<>:\"/\\|?*".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+
+    let base = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| base.eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(0, '_');
+    }
+
+    if sanitized.is_empty() {
+        "document".to_string()
+    } else {
+        sanitized
+    }
+}
+
+/// Leading characters that make spreadsheet applications evaluate a cell
+const CSV_FORMULA_PREFIXES: [char; 4] = ['=', '+', '-', '@'];
+
+/// Encode a single CSV field.
+///
+/// Fields containing a comma, quote or line break are wrapped in quotes with
+/// embedded quotes doubled. Unless `raw` is set, a field starting with `=`,
+/// `+`, `-` or `@` is prefixed with `'` so spreadsheets display it as text
+/// instead of running it as a formula; the prefix sits inside the quotes and
+/// is part of the value when the file is read back.
+pub fn csv_field(value: &str, raw: bool) -> String {
+    let value = if !raw && value.starts_with(CSV_FORMULA_PREFIXES) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
     } else {
-        Err(anyhow::anyhow!(
-            "Unsupported file type. Only .docx and .pdf files are supported. Got: {}",
-            file_path
-        ))
+        value
+    }
+}
+
+/// Encode a CSV row, applying [`csv_field`] to every field
+pub fn csv_row<S: AsRef<str>>(fields: &[S], raw: bool) -> String {
+    fields
+        .iter()
+        .map(|field| csv_field(field.as_ref(), raw))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Escape text for an HTML element or quoted attribute. Everything else,
+/// emoji and other non-BMP characters included, is written as is.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Width of `text` in terminal columns; CJK and most emoji take two
+pub fn display_width(text: &str) -> usize {
+    text.width()
+}
+
+/// Cut `text` to at most `width` terminal columns, ending in `…` when
+/// anything was cut. Cuts fall between characters, never inside one.
+pub fn truncate_to_width(text: &str, width: usize) -> Cow<'_, str> {
+    if text.width() <= width {
+        return Cow::Borrowed(text);
+    }
+    if width == 0 {
+        return Cow::Borrowed("");
+    }
+
+    // Leave a column for the ellipsis
+    let mut used = 0;
+    let end = text
+        .char_indices()
+        .find(|(_, c)| {
+            used += c.width().unwrap_or(0);
+            used > width - 1
+        })
+        .map_or(text.len(), |(i, _)| i);
+    Cow::Owned(format!("{}…", &text[..end]))
+}
+
+/// The part of `text` shown around the match at byte `span`: the match and
+/// at most `context` characters either side of it, with every bound on a
+/// character boundary.
+///
+/// Returns the range shown and whether text was cut before and after it.
+pub fn snippet_range(
+    text: &str,
+    span: &Range<usize>,
+    context: usize,
+) -> (Range<usize>, bool, bool) {
+    let mut start = span.start.min(text.len());
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = span.end.clamp(start, text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
     }
+
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .take(context)
+        .last()
+        .map_or(start, |(i, _)| i);
+    let to = text[end..]
+        .char_indices()
+        .nth(context)
+        .map_or(text.len(), |(i, _)| end + i);
+    (from..to, from > 0, to < text.len())
+}
+
+/// Up to `width` characters of `text` centred on the match at byte `span`,
+/// and the span of the match within them. Room one side of the match
+/// doesn't need goes to the other side, whitespace at either end is
+/// dropped, and a match longer than `width` is kept whole.
+pub fn context_snippet(text: &str, span: &Range<usize>, width: usize) -> (String, Range<usize>) {
+    let (matched, ..) = snippet_range(text, span, 0);
+    let room = width.saturating_sub(text[matched.clone()].chars().count());
+    let available_after = text[matched.end..].chars().count();
+    let before = text[..matched.start]
+        .chars()
+        .count()
+        .min((room / 2).max(room.saturating_sub(available_after)));
+    let after = available_after.min(room - before);
+
+    let from = text[..matched.start]
+        .char_indices()
+        .rev()
+        .take(before)
+        .last()
+        .map_or(matched.start, |(i, _)| i);
+    let to = text[matched.end..]
+        .char_indices()
+        .nth(after)
+        .map_or(text.len(), |(i, _)| matched.end + i);
+    let from =
+        from + (text[from..matched.start].len() - text[from..matched.start].trim_start().len());
+    let to = to - (text[matched.end..to].len() - text[matched.end..to].trim_end().len());
+    (
+        text[from..to].to_string(),
+        matched.start - from..matched.end - from,
+    )
 }
 
 #[cfg(test)]
@@ -90,31 +1205,842 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_parse_filetype() {
-        assert_eq!(parse_filetype("document.docx").unwrap(), FileType::Docx);
-        assert_eq!(parse_filetype("report.pdf").unwrap(), FileType::Pdf);
-        assert!(parse_filetype("data.txt").is_err());
-        assert!(parse_filetype("presentation").is_err());
-    }
-
-    #[test]
-    fn test_parse_contact() {
+    fn test_needle_queries() {
         assert_eq!(
-            parse_contact("Alice Johnson,alice.johnson@company.com"),
-            Ok(("", ("Alice Johnson", "alice.johnson@company.com")))
+            NeedleQuery::parse("Project Falcon && budget overrun"),
+            NeedleQuery::All(vec!["Project Falcon", "budget overrun"])
         );
+        assert_eq!(NeedleQuery::parse("a&&b && c").parts(), ["a", "b", "c"]);
         assert_eq!(
-            parse_contact("  Bob Smith  ,  bob.smith@enterprise.org  "),
-            Ok(("", ("Bob Smith", "bob.smith@enterprise.org")))
+            NeedleQuery::parse("Smith NEAR/5 invoice"),
+            NeedleQuery::Near {
+                parts: ["Smith", "invoice"],
+                within: 5
+            }
         );
-    }
+        assert_eq!(
+            NeedleQuery::parse("Bob Smith  NEAR/12 unpaid invoice").parts(),
+            ["Bob Smith", "unpaid invoice"]
+        );
+        for term in [
+            "R&D",
+            "Smith &&",
+            "&& Smith",
+            "Smith NEAR/0 invoice",
+            "NEAR/5 invoice",
+            "Smith NEAR/x invoice",
+            "SmithNEAR/5 invoice",
+            "Smith NEAR/5",
+        ] {
+            assert_eq!(NeedleQuery::parse(term), NeedleQuery::Term(term));
+        }
+    }
+
+    #[test]
+    fn test_invalid_regex_needles_name_their_line() {
+        let content = "# needles\n/[A-Z]{3}-\\d+/,kind=code\n\nplain (text,kind=name\n/(unclosed/,kind=broken\n";
+        let regex = RegexNeedles::default();
+        let err = check_regex_needles(content, None, regex)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Invalid regular expression on line 5 ('/(unclosed/'):"),
+            "{}",
+            err
+        );
+
+        let all = RegexNeedles { all: true, ..regex };
+        let err = check_regex_needles(content, None, all)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Invalid regular expression on line 4 ('plain (text'):"),
+            "{}",
+            err
+        );
+        assert!(check_regex_needles("/a|b/,x\nplain (text,y\n", None, regex).is_ok());
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(
+            sanitize_file_name("a<b>c:d\"e/f\\g|h?i*j"),
+            "a_b_c_d_e_f_g_h_i_j"
+        );
+        assert_eq!(sanitize_file_name("tab\there\u{7}"), "tab_here_");
+        assert_eq!(sanitize_file_name("report. . "), "report");
+        assert_eq!(sanitize_file_name("CON"), "_CON");
+        assert_eq!(sanitize_file_name("lpt1.notes"), "_lpt1.notes");
+        assert_eq!(sanitize_file_name("console"), "console");
+        assert_eq!(sanitize_file_name("..."), "document");
+    }
+
+    #[test]
+    fn test_parse_filetype() {
+        assert_eq!(parse_filetype("document.docx").unwrap(), FileType::Docx);
+        assert_eq!(parse_filetype("report.pdf").unwrap(), FileType::Pdf);
+        assert_eq!(parse_filetype("old.doc").unwrap(), FileType::Doc);
+        assert_eq!(parse_filetype("contacts.xlsx").unwrap(), FileType::Xlsx);
+        assert_eq!(parse_filetype("minutes.odt").unwrap(), FileType::Odt);
+        assert_eq!(parse_filetype("server.log.txt").unwrap(), FileType::Txt);
+        assert_eq!(parse_filetype("letter.rtf").unwrap(), FileType::Rtf);
+        assert_eq!(parse_filetype("memoirs.epub").unwrap(), FileType::Epub);
+        assert!(parse_filetype("data.csv").is_err());
+        assert!(parse_filetype("presentation").is_err());
+
+        // Windows tools often write extensions in capitals
+        assert_eq!(parse_filetype("REPORT.PDF").unwrap(), FileType::Pdf);
+        assert_eq!(parse_filetype("Minutes.Docx").unwrap(), FileType::Docx);
+        assert_eq!(parse_filetype("OLD.DOC").unwrap(), FileType::Doc);
+    }
+
+    fn parse(line: &str, delimiter: char) -> Option<(String, String)> {
+        let delimiter = NeedleDelimiter::CANDIDATES
+            .into_iter()
+            .find(|candidate| candidate.char() == delimiter)
+            .unwrap();
+        parse_contact(line, delimiter)
+            .ok()
+            .map(|(_, (term, metadata))| (term.into_owned(), metadata.into_owned()))
+    }
+
+    fn owned(term: &str, metadata: &str) -> Option<(String, String)> {
+        Some((term.to_string(), metadata.to_string()))
+    }
+
+    #[test]
+    fn test_parse_contact() {
+        assert_eq!(
+            parse("Alice Johnson,alice.johnson@company.com", ','),
+            owned("Alice Johnson", "alice.johnson@company.com")
+        );
+        assert_eq!(
+            parse("  Bob Smith  ,  bob.smith@enterprise.org  ", ','),
+            owned("Bob Smith", "bob.smith@enterprise.org")
+        );
+    }
+
+    #[test]
+    fn test_parse_contact_with_other_delimiters() {
+        assert_eq!(
+            parse("Müller, Hans;id=1;dept=legal", ';'),
+            owned("Müller, Hans", "id=1;dept=legal")
+        );
+        assert_eq!(
+            parse("Bob Smith\tid=2\tnote", '\t'),
+            owned("Bob Smith", "id=2\tnote")
+        );
+        assert_eq!(
+            parse("Carol, Co; Ltd|id=3", '|'),
+            owned("Carol, Co; Ltd", "id=3")
+        );
+        assert_eq!(parse("Bob Smith,id=2", ';'), None);
+    }
+
+    #[test]
+    fn test_quoted_fields_follow_the_active_delimiter() {
+        // Quotes protect the other candidate delimiters, and the active one
+        assert_eq!(
+            parse("\"Smith, Bob; Jr.|\tII\";id=4", ';'),
+            owned("Smith, Bob; Jr.|\tII", "id=4")
+        );
+        assert_eq!(
+            parse("\"Acme; \"\"Widgets\"\"\"\t\"id=5\tnote\"", '\t'),
+            owned("Acme; \"Widgets\"", "id=5\tnote")
+        );
+        // Key=value quoting inside metadata is left alone
+        assert_eq!(
+            parse("Dave,note=\"a, b\";id=6", ','),
+            owned("Dave", "note=\"a, b\";id=6")
+        );
+        // An unterminated quote is part of the term, as before quoting
+        assert_eq!(
+            parse("\"unterminated,id=7", ','),
+            owned("\"unterminated", "id=7")
+        );
+    }
+
+    #[test]
+    fn test_weight_column() {
+        let weighted = |line: &str, delimiter: char| {
+            let delimiter = NeedleDelimiter::CANDIDATES
+                .into_iter()
+                .find(|candidate| candidate.char() == delimiter)
+                .unwrap();
+            parse_weighted_contact(line, delimiter)
+                .ok()
+                .map(|(_, (term, metadata, weight))| {
+                    (term.into_owned(), metadata.into_owned(), weight)
+                })
+        };
+        let expect = |term: &str, metadata: &str, weight| {
+            Some((term.to_string(), metadata.to_string(), weight))
+        };
+
+        assert_eq!(
+            weighted("Alice Johnson,alice@x.com,10", ','),
+            expect("Alice Johnson", "alice@x.com", 10)
+        );
+        assert_eq!(
+            weighted("Bob;id=2;dept=legal;-3", ';'),
+            expect("Bob", "id=2;dept=legal", -3)
+        );
+        assert_eq!(
+            weighted("\"Smith, Bob\",\"id=1, 7\",5", ','),
+            expect("Smith, Bob", "id=1, 7", 5)
+        );
+        // Not a weight: the only field, spaced, or not a whole number
+        assert_eq!(weighted("Carol,10", ','), expect("Carol", "10", 0));
+        assert_eq!(
+            weighted("Dave,note=ext, 4410", ','),
+            expect("Dave", "note=ext, 4410", 0)
+        );
+        assert_eq!(
+            weighted("Erin,id=5,1.5", ','),
+            expect("Erin", "id=5,1.5", 0)
+        );
+        assert_eq!(weighted("Frank,id=6,-", ','), expect("Frank", "id=6,-", 0));
+        assert_eq!(
+            parse("Alice Johnson,alice@x.com,10", ','),
+            owned("Alice Johnson", "alice@x.com")
+        );
+
+        let weights = needle_weights("# weights\nAlice,id=1,10\nBob,id=2\nAlice,id=1,3\n", None);
+        assert_eq!(
+            weights,
+            NeedleWeights::from([(("Alice".to_string(), "id=1".to_string()), 10)])
+        );
+    }
+
+    #[test]
+    fn test_delimiter_argument() {
+        let parse = |value: &str| value.parse::<NeedleDelimiter>().map(NeedleDelimiter::char);
+        assert_eq!(parse(";"), Ok(';'));
+        assert_eq!(parse("\\t"), Ok('\t'));
+        assert_eq!(parse("tab"), Ok('\t'));
+        assert_eq!(parse("\t"), Ok('\t'));
+        for refused in ["", ";;", "\"", " ", "a", "\n"] {
+            assert!(parse(refused).is_err(), "{:?}", refused);
+        }
+        assert_eq!(NeedleDelimiter::CANDIDATES[2].to_string(), "\\t");
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        let detect = |content: &str| detect_delimiter(content).char();
+        assert_eq!(detect("# export\nAlice;id=1\nBob;id=2\n"), ';');
+        assert_eq!(detect("Alice\tid=1\nBob\tid=2"), '\t');
+        assert_eq!(detect("Alice|id=1"), '|');
+        // `;` between key=value pairs comes after the comma
+        assert_eq!(detect("Alice,id=1;dept=legal\nBob,id=2;dept=hr\n"), ',');
+        // A comma in some terms loses to the `;` on every line
+        assert_eq!(
+            detect("Müller, Hans;id=1;dept=legal\nSchmidt;id=2;dept=hr\n"),
+            ';'
+        );
+        // Delimiters inside quotes do not count
+        assert_eq!(detect("\"Smith, Bob|Jr.\";id=3\n"), ';');
+        assert_eq!(detect("\n# only comments\n"), ',');
+        assert_eq!(detect("no delimiter at all"), ',');
+    }
+
+    #[test]
+    fn test_read_needles_with_each_delimiter() {
+        let expected = vec![
+            ("Smith, Bob".to_string(), "id=1".to_string()),
+            ("Alice".to_string(), "id=2;dept=legal".to_string()),
+        ];
+        for content in [
+            "\"Smith, Bob\",id=1\nAlice,id=2;dept=legal\n",
+            "Smith, Bob;id=1\nAlice;id=2;dept=legal\n",
+            "Smith, Bob\tid=1\nAlice\tid=2;dept=legal\n",
+        ] {
+            assert_eq!(
+                read_needles_from_string(content, None).unwrap(),
+                expected,
+                "{:?}",
+                content
+            );
+        }
+
+        let (needles, stats) = scan_needles("Smith, Bob;id=1\n", Some(NeedleDelimiter::COMMA));
+        assert_eq!(needles, vec![("Smith".to_string(), "Bob;id=1".to_string())]);
+        assert_eq!(stats.delimiter, NeedleDelimiter::COMMA);
+        assert_eq!(
+            scan_needles("Smith, Bob;id=1\nAlice;id=2\n", None)
+                .1
+                .delimiter
+                .char(),
+            ';'
+        );
+    }
 
     #[test]
     fn test_read_needles_from_string() {
         let input = "Alice Johnson,alice.johnson@company.com\nBob Smith,bob.smith@enterprise.org\n# Comment line\n\n";
-        let result = read_needles_from_string(input).unwrap();
+        let result = read_needles_from_string(input, None).unwrap();
         assert_eq!(result.len(), 2);
-        assert_eq!(result[0], ("Alice Johnson".to_string(), "alice.johnson@company.com".to_string()));
-        assert_eq!(result[1], ("Bob Smith".to_string(), "bob.smith@enterprise.org".to_string()));
+        assert_eq!(
+            result[0],
+            (
+                "Alice Johnson".to_string(),
+                "alice.johnson@company.com".to_string()
+            )
+        );
+        assert_eq!(
+            result[1],
+            (
+                "Bob Smith".to_string(),
+                "bob.smith@enterprise.org".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_needles_file_without_needles() {
+        let input = "# Contacts\n# generated, none today\n\n   \nnot a needle\n";
+        let (needles, stats) = scan_needles(input, None);
+        assert!(needles.is_empty());
+        assert_eq!(
+            stats,
+            NeedleFileStats {
+                needles: 0,
+                comments: 2,
+                blank: 2,
+                malformed: 1,
+                delimiter: NeedleDelimiter::COMMA
+            }
+        );
+
+        let err = read_needles_from_string(input, None)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("2 comment, 2 blank and 1 malformed lines"),
+            "{}",
+            err
+        );
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> MetadataFields {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_metadata_kv() {
+        assert_eq!(
+            parse_metadata_kv("id=442;dept=legal;tier=2").unwrap(),
+            fields(&[("id", "442"), ("dept", "legal"), ("tier", "2")])
+        );
+        assert_eq!(
+            parse_metadata_kv(" id = 442 ; dept=legal; ").unwrap(),
+            fields(&[("id", "442"), ("dept", "legal")])
+        );
+        assert_eq!(parse_metadata_kv("").unwrap(), fields(&[]));
+        assert_eq!(
+            parse_metadata_kv("empty=").unwrap(),
+            fields(&[("empty", "")])
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_kv_quoting() {
+        assert_eq!(
+            parse_metadata_kv(r#"note="a;b=c";dept=legal"#).unwrap(),
+            fields(&[("note", "a;b=c"), ("dept", "legal")])
+        );
+        assert_eq!(
+            parse_metadata_kv(r#""odd=key"=1"#).unwrap(),
+            fields(&[("odd=key", "1")])
+        );
+        assert_eq!(
+            parse_metadata_kv(r#"quote="say ""hi""""#).unwrap(),
+            fields(&[("quote", r#"say "hi""#)])
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_kv_malformed() {
+        assert!(parse_metadata_kv("alice@company.com").is_err());
+        assert!(parse_metadata_kv("id=1;orphan").is_err());
+        assert!(parse_metadata_kv("=value").is_err());
+        assert!(parse_metadata_kv(r#"note="unterminated"#).is_err());
+    }
+
+    #[test]
+    fn test_metadata_keys_union_in_first_seen_order() {
+        let needles = vec![
+            ("Alice".to_string(), "id=1;dept=legal".to_string()),
+            ("Bob".to_string(), "plain metadata".to_string()),
+            ("Carol".to_string(), "tier=2;id=3".to_string()),
+        ];
+        assert_eq!(metadata_keys(&needles), vec!["id", "dept", "tier"]);
+    }
+
+    #[test]
+    fn test_filter_needles_by_metadata() {
+        let needles = vec![
+            ("Alice".to_string(), "id=1;dept=legal".to_string()),
+            ("Bob".to_string(), "id=2;dept=sales".to_string()),
+            ("Carol".to_string(), "dept=legal".to_string()),
+            ("Dave".to_string(), "not kv".to_string()),
+        ];
+        let filters = vec![parse_where_clause("dept=legal").unwrap()];
+        let filtered = filter_needles_by_metadata(needles.clone(), &filters);
+        assert_eq!(
+            filtered.iter().map(|n| n.0.as_str()).collect::<Vec<_>>(),
+            vec!["Alice", "Carol"]
+        );
+        assert_eq!(filter_needles_by_metadata(needles.clone(), &[]), needles);
+        assert!(parse_where_clause("dept").is_err());
+    }
+
+    fn needles(terms: &[(&str, &str)]) -> Vec<(String, String)> {
+        terms
+            .iter()
+            .map(|(term, metadata)| (term.to_string(), metadata.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_stopwords_exact_mode() {
+        let stopwords = Stopwords::parse(
+            "# junk from the generator\nthe board\n\nAnd Company\nthe\n",
+            StopwordMode::Exact,
+        );
+        assert_eq!(stopwords.len(), 3);
+
+        let (kept, dropped) = filter_stopwords(
+            needles(&[
+                ("The Board", "id=1"),
+                ("and company", "id=2"),
+                ("the company", "id=3"),
+                ("Alice Johnson", "id=4"),
+            ]),
+            &stopwords,
+        );
+        assert_eq!(
+            kept,
+            needles(&[("the company", "id=3"), ("Alice Johnson", "id=4")])
+        );
+        assert_eq!(
+            dropped,
+            needles(&[("The Board", "id=1"), ("and company", "id=2")])
+        );
+    }
+
+    #[test]
+    fn test_stopwords_contains_mode() {
+        let stopwords = Stopwords::parse("the\nand\ncompany\nboard\n", StopwordMode::Contains);
+
+        let (kept, dropped) = filter_stopwords(
+            needles(&[
+                ("The Board", "id=1"),
+                ("and company", "id=2"),
+                ("the Acme company", "id=3"),
+                ("board-and-company", "id=4"),
+                ("--", "id=5"),
+            ]),
+            &stopwords,
+        );
+        assert_eq!(
+            kept,
+            needles(&[("the Acme company", "id=3"), ("--", "id=5")])
+        );
+        assert_eq!(dropped.len(), 3);
+    }
+
+    #[test]
+    fn test_stopwords_only_look_at_the_term() {
+        // Metadata columns such as an alias or group are never matched
+        // against the stop words, and do not save a junk term
+        let stopwords = Stopwords::parse("board\nlegal\n", StopwordMode::Contains);
+        let (kept, dropped) = filter_stopwords(
+            needles(&[
+                ("Board", "group=legal;alias=Acme Board"),
+                ("Acme Legal", "group=board;alias=legal"),
+            ]),
+            &stopwords,
+        );
+        assert_eq!(kept, needles(&[("Acme Legal", "group=board;alias=legal")]));
+        assert_eq!(
+            dropped,
+            needles(&[("Board", "group=legal;alias=Acme Board")])
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            "2024-02-29".parse::<Date>().unwrap(),
+            Date::new(2024, 2, 29).unwrap()
+        );
+        assert_eq!(Date::new(2024, 6, 1).unwrap().to_string(), "2024-06-01");
+        for bad in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-6-1",
+            "24-06-01",
+            "2024/06/01",
+            "2024-06-01T00:00:00Z",
+            "",
+        ] {
+            assert!(bad.parse::<Date>().is_err(), "{}", bad);
+        }
+        assert_eq!(
+            Date::from_days_since_epoch(0),
+            Date::new(1970, 1, 1).unwrap()
+        );
+        assert_eq!(
+            Date::from_days_since_epoch(19_875),
+            Date::new(2024, 6, 1).unwrap()
+        );
+        assert_eq!(
+            Date::from_days_since_epoch(-1),
+            Date::new(1969, 12, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_needle_validity() {
+        let date = |text: &str| text.parse::<Date>().unwrap();
+
+        // Missing columns and metadata that is not key=value pairs: always active
+        assert_eq!(needle_validity("dept=legal").unwrap(), Validity::default());
+        assert_eq!(
+            needle_validity("plain note, no pairs").unwrap(),
+            Validity::default()
+        );
+
+        // Open-ended ranges
+        let from = needle_validity("valid_from=2024-01-01").unwrap();
+        assert!(!from.contains(date("2023-12-31")));
+        assert!(from.contains(date("2099-01-01")));
+        let until = needle_validity("valid_until=2024-06-30;dept=legal").unwrap();
+        assert!(until.contains(date("1999-01-01")));
+        assert!(!until.contains(date("2024-07-01")));
+
+        // Both ends are whole days, so a one-day window is active all that day
+        let day = needle_validity("valid_from=2024-06-01;valid_until=2024-06-01").unwrap();
+        assert!(day.contains(date("2024-06-01")));
+        assert!(!day.contains(date("2024-06-02")));
+
+        assert!(needle_validity("valid_from=2024-06-31").is_err());
+        let inverted = needle_validity("valid_from=2024-06-01;valid_until=2024-01-01")
+            .unwrap_err()
+            .to_string();
+        assert!(inverted.contains("after"), "{}", inverted);
+    }
+
+    #[test]
+    fn test_filter_needles_by_date() {
+        let needles = vec![
+            ("Acme".to_string(), "valid_from=2024-07-01".to_string()),
+            ("Globex".to_string(), "valid_until=2024-05-31".to_string()),
+            (
+                "Initech".to_string(),
+                "valid_from=2024-01-01;valid_until=2024-12-31".to_string(),
+            ),
+            ("Umbrella".to_string(), "valid_from=someday".to_string()),
+            ("Hooli".to_string(), "vendor".to_string()),
+        ];
+        let (active, inactive) =
+            filter_needles_by_date(needles.clone(), "2024-06-01".parse().unwrap());
+        assert_eq!(
+            active.iter().map(|n| n.0.as_str()).collect::<Vec<_>>(),
+            ["Initech", "Umbrella", "Hooli"]
+        );
+        assert_eq!(inactive, 2);
+
+        let problems = validity_problems(&needles);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, "Umbrella");
+    }
+
+    #[test]
+    fn test_sniff_document_type() {
+        assert_eq!(sniff_document_type(b"%PDF-1.4\n"), Some(FileType::Pdf));
+        assert_eq!(sniff_document_type(b"PK\x03\x04rest"), Some(FileType::Docx));
+        assert_eq!(
+            sniff_document_type(&crate::fixtures::ole2_stub()),
+            Some(FileType::Doc)
+        );
+        assert_eq!(
+            sniff_document_type(b"{\\rtf1\\ansi Alice}"),
+            Some(FileType::Rtf)
+        );
+        assert_eq!(sniff_document_type(b"Alice,alice@x.com"), None);
+        assert!(looks_like_text("Jos\u{e9}".as_bytes()));
+        assert!(looks_like_text(&"Jos\u{e9}".as_bytes()[..4]));
+        assert!(!looks_like_text(b"PK\x03\x04\x00\x00"));
+        assert!(looks_like_needles(b"# header\n\nAlice,alice@x.com\n"));
+        assert!(!looks_like_needles(b"Just a sentence without commas\n"));
+    }
+
+    fn write_file(dir: &Path, name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_check_swapped_arguments_detects_both_directions() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = write_file(dir.path(), "contacts.csv", b"Alice Johnson,alice@x.com\n");
+        let pdf = write_file(
+            dir.path(),
+            "report.pdf",
+            &crate::fixtures::pdf(&[&["Alice"]]),
+        );
+        let docx = write_file(
+            dir.path(),
+            "report.docx",
+            &crate::fixtures::docx(&["Alice"]),
+        );
+
+        assert!(check_swapped_arguments(&needles, &pdf).is_ok());
+        assert!(check_swapped_arguments(&needles, &docx).is_ok());
+
+        let err = check_swapped_arguments(&pdf, &needles)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("swapped"), "{}", err);
+        assert!(err.contains("looks like a needles file"), "{}", err);
+
+        // A document renamed without its extension is caught by magic bytes
+        let renamed = write_file(dir.path(), "terms", &crate::fixtures::pdf(&[&["x"]]));
+        let err = check_swapped_arguments(&renamed, &docx)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("looks like a document"), "{}", err);
+
+        // A needles file given as the document, even under a document name
+        let disguised = write_file(dir.path(), "terms.pdf", b"Alice Johnson,alice@x.com\n");
+        assert!(check_swapped_arguments(&needles, &disguised).is_err());
+    }
+
+    #[test]
+    fn test_check_swapped_arguments_allows_unusual_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let needles = write_file(dir.path(), "data.pdf.csv", b"Alice Johnson,alice@x.com\n");
+        let docx = write_file(
+            dir.path(),
+            "notes.csv.docx",
+            &crate::fixtures::docx(&["Alice"]),
+        );
+
+        assert!(check_swapped_arguments(&needles, &docx).is_ok());
+    }
+
+    #[test]
+    fn test_detect_filetype_from_bytes() {
+        use crate::fixtures;
+
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::pdf(&[&["Alice"]]), None).unwrap(),
+            FileType::Pdf
+        );
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::docx(&["Alice"]), None).unwrap(),
+            FileType::Docx
+        );
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::xlsx(&[("Sheet1", &[&["Alice"]])]), None)
+                .unwrap(),
+            FileType::Xlsx
+        );
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::odt(&["Alice"]), None).unwrap(),
+            FileType::Odt
+        );
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::epub(&[&["Alice"]]), None).unwrap(),
+            FileType::Epub
+        );
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::rtf(&["Alice"]), None).unwrap(),
+            FileType::Rtf
+        );
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::ole2_stub(), Some("old.bin")).unwrap(),
+            FileType::Doc
+        );
+
+        // The magic bytes win over the extension
+        assert_eq!(
+            detect_filetype_from_bytes(&fixtures::pdf(&[&["Alice"]]), Some("report.docx")).unwrap(),
+            FileType::Pdf
+        );
+
+        // A ZIP archive whose entries say nothing takes a ZIP-based extension
+        let archive = fixtures::zip_archive(&[("notes.txt", "Alice")]);
+        assert_eq!(
+            detect_filetype_from_bytes(&archive, Some("notes.odt")).unwrap(),
+            FileType::Odt
+        );
+        assert!(detect_filetype_from_bytes(&archive, Some("notes.pdf")).is_err());
+        assert!(detect_filetype_from_bytes(&archive, None).is_err());
+
+        // Without magic bytes only the extension is left
+        assert_eq!(
+            detect_filetype_from_bytes(b"Alice Johnson", Some("notes.txt")).unwrap(),
+            FileType::Txt
+        );
+        assert!(detect_filetype_from_bytes(b"Alice Johnson", Some("notes")).is_err());
+    }
+
+    #[test]
+    fn test_parse_filetype_reads_renamed_and_extensionless_files() {
+        use crate::fixtures;
+
+        let dir = tempfile::tempdir().unwrap();
+        let pdf = write_file(dir.path(), "scan", &fixtures::pdf(&[&["Alice"]]));
+        let docx = write_file(dir.path(), "letter.bak", &fixtures::docx(&["Alice"]));
+        let xlsx = write_file(
+            dir.path(),
+            "ledger.xlsm",
+            &fixtures::xlsx(&[("Sheet1", &[&["Alice"]])]),
+        );
+        let notes = write_file(dir.path(), "notes", b"Alice Johnson\n");
+
+        assert_eq!(parse_filetype(&pdf).unwrap(), FileType::Pdf);
+        assert_eq!(parse_filetype(&docx).unwrap(), FileType::Docx);
+        assert_eq!(parse_filetype(&xlsx).unwrap(), FileType::Xlsx);
+        assert!(matches!(
+            parse_filetype(&notes),
+            Err(DocSearchError::UnsupportedFormat(_))
+        ));
+
+        // A known extension is trusted without opening the file
+        let misnamed = write_file(dir.path(), "scan.docx", &fixtures::pdf(&[&["Alice"]]));
+        assert_eq!(parse_filetype(&misnamed).unwrap(), FileType::Docx);
+    }
+
+    /// Minimal reader for rows written by `csv_row`
+    fn read_csv_row(row: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut chars = row.chars().peekable();
+        let mut quoted = false;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(String::new()),
+                _ => fields.last_mut().unwrap().push(c),
+            }
+        }
+
+        fields
+    }
+
+    #[test]
+    fn test_csv_field_neutralizes_formula_prefixes() {
+        assert_eq!(csv_field("=SUM(A1:A9)", false), "'=SUM(A1:A9)");
+        assert_eq!(csv_field("+1 555 0100", false), "'+1 555 0100");
+        assert_eq!(csv_field("-2+3", false), "'-2+3");
+        assert_eq!(csv_field("@cmd", false), "'@cmd");
+        assert_eq!(csv_field("=", false), "'=");
+        assert_eq!(csv_field("a=b", false), "a=b");
+        assert_eq!(csv_field("", false), "");
+    }
+
+    #[test]
+    fn test_csv_field_prefix_goes_inside_quotes() {
+        assert_eq!(
+            csv_field("=HYPERLINK(\"x\",1)", false),
+            "\"'=HYPERLINK(\"\"x\"\",1)\""
+        );
+        assert_eq!(csv_field("Smith, Bob", false), "\"Smith, Bob\"");
+    }
+
+    #[test]
+    fn test_csv_row_round_trips_when_raw() {
+        let fields = [
+            "=1+1",
+            "+x",
+            "-",
+            "@a,b",
+            "plain \"quoted\"",
+            "line\nbreak",
+            "",
+        ];
+        assert_eq!(read_csv_row(&csv_row(&fields, true)), fields);
+
+        let mitigated = read_csv_row(&csv_row(&fields, false));
+        assert_eq!(mitigated[..4], ["'=1+1", "'+x", "'-", "'@a,b"]);
+        assert_eq!(mitigated[4..], fields[4..]);
+    }
+
+    #[test]
+    fn test_csv_row_keeps_non_bmp_text_byte_identical() {
+        let fields = ["Chat 😀 export", "𠀀𠀁𪚥, ledger", "𝄞 \"Score\" 🎵", "👩‍👩‍👧"];
+        let row = csv_row(&fields, false);
+        assert_eq!(read_csv_row(&row), fields);
+        assert!(row.contains("\"𝄞 \"\"Score\"\" 🎵\""), "{}", row);
+        assert_eq!(escape_html("<👩‍👩‍👧 & 𠀀>"), "&lt;👩‍👩‍👧 &amp; 𠀀&gt;");
+    }
+
+    #[test]
+    fn test_truncate_to_width_counts_columns() {
+        assert_eq!(display_width("𠀀😀a"), 5);
+        assert_eq!(truncate_to_width("𠀀😀a", 5), "𠀀😀a");
+        // Wide characters are never split, even when a column is left over
+        assert_eq!(truncate_to_width("𠀀😀a", 4), "𠀀…");
+        assert_eq!(truncate_to_width("𠀀😀a", 3), "𠀀…");
+        assert_eq!(truncate_to_width("𠀀😀a", 2), "…");
+        assert_eq!(truncate_to_width("𠀀😀a", 0), "");
+        assert!(matches!(truncate_to_width("plain", 10), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_context_snippet_is_centred_on_the_match() {
+        let text = format!("{} Alice Johnson {}", "a".repeat(50), "b".repeat(50));
+        let (snippet, span) = context_snippet(&text, &(51..64), 23);
+        assert_eq!(snippet, "aaaa Alice Johnson bbbb");
+        assert_eq!(&snippet[span], "Alice Johnson");
+
+        // Room not needed before the match goes after it
+        let (snippet, span) =
+            context_snippet("Alice Johnson signed the contract on 3 May", &(0..13), 25);
+        assert_eq!(
+            (snippet.as_str(), span),
+            ("Alice Johnson signed the", 0..13)
+        );
+        assert_eq!(
+            context_snippet(" Alice Johnson\t", &(1..14), 80),
+            ("Alice Johnson".to_string(), 0..13)
+        );
+        let (snippet, span) = context_snippet("née Zoë", &(5..9), 5);
+        assert_eq!((snippet.as_str(), span), ("e Zoë", 2..6));
+        assert_eq!(
+            context_snippet("Alice Johnson", &(0..13), 5).0,
+            "Alice Johnson"
+        );
+    }
+
+    #[test]
+    fn test_snippet_range_stays_on_char_boundaries() {
+        let text = "😀😀😀 Chat 𠀀 export 𪚥𪚥𪚥";
+        let start = text.find("𠀀").unwrap();
+        let span = start..start + "𠀀".len();
+
+        let (shown, cut_before, cut_after) = snippet_range(text, &span, 2);
+        assert_eq!(&text[shown], "t 𠀀 e");
+        assert!(cut_before && cut_after);
+
+        let (shown, cut_before, cut_after) = snippet_range(text, &span, 100);
+        assert_eq!(shown, 0..text.len());
+        assert!(!cut_before && !cut_after);
+
+        // A span inside a character is widened to the whole character
+        let (shown, ..) = snippet_range(text, &(start + 1..start + 2), 0);
+        assert_eq!(&text[shown], "𠀀");
     }
 }
@@ -0,0 +1,79 @@
+//! Batch mode searches for the needles of the file `-n` names, whatever it
+//! is called, against the built binary
+
+use std::process::{Command, Output};
+
+use docsearcher::fixtures;
+
+fn batch(dir: &std::path::Path, needles: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_docsearcher"))
+        .current_dir(dir)
+        .args([
+            "batch",
+            "-n",
+            needles,
+            "-d",
+            "documents",
+            "--format",
+            "csv",
+            "--no-context",
+            "--threads",
+            "1",
+        ])
+        .output()
+        .unwrap()
+}
+
+/// The `term,metadata` of every CSV row of a batch run
+fn rows(output: &Output) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut rows: Vec<String> = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("term,metadata,"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("===="))
+        .filter(|line| !line.is_empty())
+        .map(|line| line.splitn(3, ',').take(2).collect::<Vec<_>>().join(","))
+        .collect();
+    rows.sort();
+    rows
+}
+
+#[test]
+fn test_batch_uses_the_named_needles_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("custom_terms.csv"),
+        "Carol Diaz,id=7\nBob Smith,id=8\n",
+    )
+    .unwrap();
+    let documents = dir.path().join("documents");
+    std::fs::create_dir(&documents).unwrap();
+    std::fs::write(
+        documents.join("a.docx"),
+        fixtures::docx(&["Carol Diaz met Alice Johnson"]),
+    )
+    .unwrap();
+    std::fs::write(
+        documents.join("b.pdf"),
+        fixtures::pdf(&[&["Bob Smith and Alice Johnson"]]),
+    )
+    .unwrap();
+
+    // Without a contacts.csv anywhere the search still runs
+    let output = batch(dir.path(), "custom_terms.csv");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(rows(&output), ["Bob Smith,id=8", "Carol Diaz,id=7"]);
+    let everything = [output.stdout, output.stderr].concat();
+    assert!(!String::from_utf8_lossy(&everything).contains("contacts.csv"));
+
+    // and one next to it is not read
+    std::fs::write(dir.path().join("contacts.csv"), "Alice Johnson,id=1\n").unwrap();
+    let output = batch(dir.path(), "custom_terms.csv");
+    assert!(output.status.success());
+    assert_eq!(rows(&output), ["Bob Smith,id=8", "Carol Diaz,id=7"]);
+}
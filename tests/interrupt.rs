@@ -0,0 +1,103 @@
+//! Ctrl-C during a batch run, against the built binary
+#![cfg(unix)]
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use docsearcher::fixtures;
+
+/// Files in the spill directories under `dir`
+fn spilled(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| std::fs::read_dir(entry.unwrap().path()).map_or(0, Iterator::count))
+        .sum()
+}
+
+#[test]
+fn test_interrupted_batch_reports_what_it_found() {
+    let dir = tempfile::tempdir().unwrap();
+    let needles = dir.path().join("needles.csv");
+    std::fs::write(&needles, "Alice Johnson,id=1\n").unwrap();
+    let documents = dir.path().join("documents");
+    std::fs::create_dir(&documents).unwrap();
+    let document = fixtures::docx(&["Signed by Alice Johnson"; 50]);
+    let total = 2000;
+    for i in 0..total {
+        std::fs::write(documents.join(format!("{:04}.docx", i)), &document).unwrap();
+    }
+    let spill = dir.path().join("spill");
+    std::fs::create_dir(&spill).unwrap();
+
+    let (needles, documents, spill_dir) = (
+        needles.to_string_lossy(),
+        documents.to_string_lossy(),
+        spill.to_string_lossy(),
+    );
+    let args = [
+        "batch",
+        "-n",
+        &needles,
+        "-d",
+        &documents,
+        "--format",
+        "json",
+        "--threads",
+        "1",
+        "--io-threads",
+        "1",
+        "--spill-threshold",
+        "0",
+        "--spill-dir",
+        &spill_dir,
+    ];
+    let child = Command::new(env!("CARGO_BIN_EXE_docsearcher"))
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // A spilled segment shows the search is under way
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while spilled(&spill) == 0 {
+        assert!(Instant::now() < deadline, "the batch never got going");
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+    let output = child.wait_with_output().unwrap();
+
+    let (stdout, stderr) = (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    );
+    assert_eq!(output.status.code(), Some(130), "{}", stderr);
+    assert!(
+        stderr.contains("Interrupted: reporting what was found in "),
+        "{}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Interrupted: partial results"),
+        "{}",
+        stdout
+    );
+
+    let report = &stdout[stdout.find("\n{").unwrap()..stdout.rfind("\n}").unwrap() + 2];
+    let report: serde_json::Value =
+        serde_json::from_str(report).expect("the partial report is valid JSON");
+    assert_eq!(report["interrupted"], true);
+    let results = report["results"].as_array().unwrap().len();
+    assert!(results > 0 && results < total, "{} results", results);
+
+    // Spilled segments are cleaned up on the way out
+    assert_eq!(spilled(&spill), 0);
+}
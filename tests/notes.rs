@@ -0,0 +1,42 @@
+//! Needles that only appear in the footnotes or endnotes of a DOCX file
+
+use docsearcher::{
+    fixtures, parse_docx_from_path,
+    types::{ExtractOptions, Location},
+};
+
+#[test]
+fn test_names_only_in_notes_are_found() {
+    let dir = tempfile::tempdir().unwrap();
+    let needles = dir.path().join("needles.csv");
+    std::fs::write(
+        &needles,
+        "Alice Johnson,id=1\nBob Smith,id=2\nCarol Diaz,id=3\n",
+    )
+    .unwrap();
+    let document = dir.path().join("opinion.docx");
+    let docx = fixtures::docx_with_notes(
+        &["The court finds for the claimant."],
+        &["Per Alice Johnson, 12 May."],
+        &["Counsel: Bob Smith"],
+    );
+    std::fs::write(&document, docx).unwrap();
+
+    let results = parse_docx_from_path(
+        &needles.to_string_lossy(),
+        &document.to_string_lossy(),
+        ExtractOptions::default(),
+    )
+    .unwrap();
+    let found: Vec<(&str, Location)> = results
+        .iter()
+        .map(|result| (result.term.as_str(), result.location.clone()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("Alice Johnson", Location::Footnote(1)),
+            ("Bob Smith", Location::Endnote(1))
+        ]
+    );
+}
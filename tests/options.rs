@@ -0,0 +1,114 @@
+//! Search options built in code reach every parser through
+//! `batch::search_bytes`, and the defaults keep the command line's behaviour
+
+use std::path::Path;
+
+use docsearcher::{
+    batch::search_bytes,
+    fixtures,
+    types::{ExtractOptions, MatchOptions},
+};
+
+fn found(path: &str, bytes: &[u8], extract: ExtractOptions) -> Vec<String> {
+    let needles: Vec<(String, String)> = [
+        ("alice johnson", "id=1"),
+        ("Ann", "id=2"),
+        ("Quarterly", "id=3"),
+    ]
+    .iter()
+    .map(|(term, metadata)| (term.to_string(), metadata.to_string()))
+    .collect();
+    let mut terms: Vec<String> = search_bytes(&needles, Path::new(path), bytes, extract)
+        .unwrap()
+        .occurrences
+        .into_iter()
+        .map(|occurrence| occurrence.term)
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+#[test]
+fn test_options_affect_every_format() {
+    let lines = ["Alice Johnson met Annabelle"];
+    let documents = [
+        (
+            "letter.docx",
+            fixtures::docx_with_properties(&lines, &[("dc:title", "Quarterly review")]),
+        ),
+        (
+            "letter.odt",
+            fixtures::odt_from_xml(
+                "<text:p>Alice Johnson met Annabelle</text:p>",
+                "",
+                &[("dc:title", "Quarterly review")],
+            ),
+        ),
+        (
+            "letter.pdf",
+            fixtures::pdf_with_info(&[&lines], &[("Title", "Quarterly review")]),
+        ),
+        ("letter.xlsx", fixtures::xlsx(&[("Sheet1", &[&lines])])),
+    ];
+
+    for (path, bytes) in &documents {
+        assert_eq!(
+            found(path, bytes, ExtractOptions::default()),
+            ["Ann", "alice johnson"],
+            "{}",
+            path
+        );
+
+        let strict = MatchOptions {
+            case_sensitive: true,
+            whole_word: true,
+            ..MatchOptions::default()
+        };
+        assert!(
+            found(
+                path,
+                bytes,
+                ExtractOptions {
+                    matching: strict,
+                    ..ExtractOptions::default()
+                }
+            )
+            .is_empty(),
+            "{}",
+            path
+        );
+
+        let whole_word = MatchOptions {
+            whole_word: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            found(
+                path,
+                bytes,
+                ExtractOptions {
+                    matching: whole_word,
+                    ..ExtractOptions::default()
+                }
+            ),
+            ["alice johnson"],
+            "{}",
+            path
+        );
+    }
+
+    // Workbooks have no properties to search
+    for (path, bytes) in &documents[..3] {
+        let with_metadata = ExtractOptions {
+            include_metadata: true,
+            ..ExtractOptions::default()
+        };
+        assert_eq!(
+            found(path, bytes, with_metadata),
+            ["Ann", "Quarterly", "alice johnson"],
+            "{}",
+            path
+        );
+    }
+}
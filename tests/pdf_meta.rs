@@ -0,0 +1,90 @@
+//! The document information of a PDF, read for `info` and searched with
+//! `--search-meta`
+
+use docsearcher::{
+    fixtures,
+    parsers::pdf::{extract_pdf_meta, PdfMeta},
+    parsers::pdf_occurrences_mem,
+    search::BlockLocation,
+    types::{ContextKind, ExtractOptions},
+};
+
+#[test]
+fn test_pdf_information_is_read_and_searched_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = dir.path().join("minutes.pdf");
+    let info = [
+        ("Title", "Board minutes"),
+        ("Author", "Alice Johnson"),
+        ("Keywords", "board; Bob Smith"),
+        ("Producer", "Acme PDF 2.1"),
+        ("ModDate", "D:20240301093000Z"),
+    ];
+    let bytes = fixtures::pdf_with_info(&[&["Present: Carol Diaz"]], &info);
+    std::fs::write(&document, &bytes).unwrap();
+
+    assert_eq!(
+        extract_pdf_meta(&document).unwrap(),
+        PdfMeta {
+            title: Some("Board minutes".to_string()),
+            author: Some("Alice Johnson".to_string()),
+            keywords: Some("board; Bob Smith".to_string()),
+            producer: Some("Acme PDF 2.1".to_string()),
+            mod_date: Some("D:20240301093000Z".to_string()),
+            ..PdfMeta::default()
+        }
+    );
+
+    let needles: Vec<(String, String)> = [
+        ("Alice Johnson", "id=1"),
+        ("Bob Smith", "id=2"),
+        ("Carol Diaz", "id=3"),
+    ]
+    .iter()
+    .map(|(term, metadata)| (term.to_string(), metadata.to_string()))
+    .collect();
+    let found = |extract| -> Vec<(String, BlockLocation, ContextKind)> {
+        pdf_occurrences_mem(&needles, &bytes, extract)
+            .unwrap()
+            .occurrences
+            .into_iter()
+            .map(|occurrence| {
+                (
+                    occurrence.term,
+                    occurrence.location,
+                    occurrence.context_kind,
+                )
+            })
+            .collect()
+    };
+
+    let body = (
+        String::from("Carol Diaz"),
+        BlockLocation::PdfPage { page: 1 },
+        ContextKind::Prose,
+    );
+    assert_eq!(
+        found(ExtractOptions::default()),
+        std::slice::from_ref(&body)
+    );
+    let property = |term: &str, name: &str| {
+        (
+            term.to_string(),
+            BlockLocation::PdfProperty {
+                name: name.to_string(),
+            },
+            ContextKind::Metadata,
+        )
+    };
+    assert_eq!(
+        found(ExtractOptions {
+            include_metadata: true,
+            ..ExtractOptions::default()
+        }),
+        [
+            property("Alice Johnson", "author"),
+            property("Bob Smith", "keywords"),
+            body
+        ]
+    );
+}